@@ -3,7 +3,9 @@ use near_primitives::challenge::{ChunkProofs, ChunkState};
 use near_primitives::errors::{EpochError, StorageError};
 use near_primitives::shard_layout::ShardLayoutError;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
-use near_primitives::types::{BlockHeight, EpochId, ShardId};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::version::ProtocolVersion;
 use near_time::Utc;
 use std::io;
 
@@ -135,8 +137,31 @@ pub enum Error {
     InvalidChunkStateWitness(String),
     #[error("Invalid Partial Chunk State Witness: {0}")]
     InvalidPartialChunkStateWitness(String),
+    /// The owner of the partial witness doesn't match the owner of the part_ord in our
+    /// assignment of the parts to the chunk validators.
+    #[error("Invalid Partial Chunk State Witness Owner: {0}")]
+    InvalidPartialChunkStateWitnessOwner(String),
     #[error("Invalid Chunk Endorsement")]
     InvalidChunkEndorsement,
+    /// The partial witness is signed with the valid key of a validator other than the chunk
+    /// producer of the chunk.
+    #[error("Wrong Producer: expected {expected}, signed by {actual}")]
+    WrongProducer { expected: AccountId, actual: AccountId },
+    /// The protocol version signed into the partial witness by the chunk producer is not one of
+    /// the versions allowed for the epoch of the part.
+    #[error(
+        "Invalid Partial Chunk State Witness Protocol Version: {protocol_version}, epoch protocol version {epoch_protocol_version}"
+    )]
+    InvalidPartialWitnessProtocolVersion {
+        protocol_version: ProtocolVersion,
+        epoch_protocol_version: ProtocolVersion,
+    },
+    /// The witness reconstructed from the parts declares an epoch, shard or height other than the
+    /// ones the chunk producer signed the parts for.
+    #[error(
+        "Decoded Witness Key Mismatch: witness of {actual:?} decoded from the parts of {expected:?}"
+    )]
+    DecodedWitnessKeyMismatch { expected: ChunkProductionKey, actual: ChunkProductionKey },
     /// Invalid chunk mask
     #[error("Invalid Chunk Endorsement Bitmap")]
     InvalidChunkEndorsementBitmap(String),
@@ -208,6 +233,23 @@ pub enum Error {
     /// supposed to validate the chunk.
     #[error("Not A Chunk Validator")]
     NotAChunkValidator,
+    /// We are asked to distribute the witness of a chunk we are not the producer of.
+    #[error("Not This Chunk's Producer: expected {expected}, actual {actual}")]
+    NotThisChunksProducer { expected: AccountId, actual: AccountId },
+    /// The chunk has more chunk validators than the Reed Solomon encoding of the witness
+    /// supports parts.
+    #[error("Too Many Witness Parts: {num_parts} exceeds the maximum of {max}")]
+    TooManyWitnessParts { num_parts: usize, max: usize },
+    /// The chunk has no chunk validators, so its witness can't be encoded into parts. Happens
+    /// with a misconfigured genesis or a bug in the chunk validator assignment.
+    #[error(
+        "No Chunk Validators: shard {shard_id} at height {height_created} in epoch {epoch_id:?}"
+    )]
+    NoChunkValidators { epoch_id: EpochId, shard_id: ShardId, height_created: BlockHeight },
+    /// The data of the epoch is not retained by the partial witness actor, which only keeps the
+    /// epochs around the head. `dropped` is set if the epoch was retained before.
+    #[error("Epoch Not Retained: {epoch_id:?}, dropped: {dropped}")]
+    EpochNotRetained { epoch_id: EpochId, dropped: bool },
     /// Validator error.
     #[error("Validator Error: {0}")]
     ValidatorError(String),
@@ -264,6 +306,10 @@ impl Error {
             | Error::IOErr(_)
             | Error::Other(_)
             | Error::ValidatorError(_)
+            | Error::NotThisChunksProducer { .. }
+            | Error::TooManyWitnessParts { .. }
+            | Error::NoChunkValidators { .. }
+            | Error::EpochNotRetained { .. }
             | Error::EpochOutOfBounds(_)
             | Error::ChallengedBlockOnChain
             | Error::CannotBeFinalized
@@ -279,6 +325,10 @@ impl Error {
             | Error::InvalidChunkState(_)
             | Error::InvalidChunkStateWitness(_)
             | Error::InvalidPartialChunkStateWitness(_)
+            | Error::InvalidPartialChunkStateWitnessOwner(_)
+            | Error::WrongProducer { .. }
+            | Error::InvalidPartialWitnessProtocolVersion { .. }
+            | Error::DecodedWitnessKeyMismatch { .. }
             | Error::InvalidChunkEndorsement
             | Error::InvalidChunkEndorsementBitmap(_)
             | Error::InvalidChunkMask
@@ -356,6 +406,14 @@ impl Error {
             Error::InvalidChunkState(_) => "invalid_chunk_state",
             Error::InvalidChunkStateWitness(_) => "invalid_chunk_state_witness",
             Error::InvalidPartialChunkStateWitness(_) => "invalid_partial_chunk_state_witness",
+            Error::InvalidPartialChunkStateWitnessOwner(_) => {
+                "invalid_partial_chunk_state_witness_owner"
+            }
+            Error::WrongProducer { .. } => "wrong_producer",
+            Error::InvalidPartialWitnessProtocolVersion { .. } => {
+                "invalid_partial_witness_protocol_version"
+            }
+            Error::DecodedWitnessKeyMismatch { .. } => "decoded_witness_key_mismatch",
             Error::InvalidChunkEndorsement => "invalid_chunk_endorsement",
             Error::InvalidChunkEndorsementBitmap(_) => "invalid_chunk_endorsement_bitmap",
             Error::InvalidChunkMask => "invalid_chunk_mask",
@@ -391,6 +449,10 @@ impl Error {
             Error::InvalidProtocolVersion => "invalid_protocol_version",
             Error::NotAValidator(_) => "not_a_validator",
             Error::NotAChunkValidator => "not_a_chunk_validator",
+            Error::NotThisChunksProducer { .. } => "not_this_chunks_producer",
+            Error::TooManyWitnessParts { .. } => "too_many_witness_parts",
+            Error::NoChunkValidators { .. } => "no_chunk_validators",
+            Error::EpochNotRetained { .. } => "epoch_not_retained",
             Error::InvalidChallengeRoot => "invalid_challenge_root",
         }
     }
@@ -4711,6 +4711,17 @@ pub struct BlockCatchUpResponse {
 pub struct ChunkStateWitnessMessage {
     pub witness: ChunkStateWitness,
     pub raw_witness_size: ChunkStateWitnessSize,
+    /// The witness was reconstructed for a pre-tracked shard, i.e. we are not a chunk
+    /// validator of this chunk. Such witnesses may be validated, but must not be endorsed.
+    pub pre_tracking: bool,
+    /// Whether the block the chunk is built on was known when the witness was reconstructed.
+    /// The witnesses are often reconstructed ahead of that block, in which case the client
+    /// keeps them in the orphan witness pool until the block arrives.
+    pub prev_block_known: bool,
+    /// Whether the witness was reconstructed after the block at its height had been produced.
+    /// Such witnesses are still validated, but the endorsement would come too late to matter,
+    /// so the client declines to send it.
+    pub decoded_late: bool,
 }
 
 /// Helper to track blocks catch up
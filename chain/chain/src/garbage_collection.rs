@@ -993,6 +993,8 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::FlatStorageStatus
             | DBCol::Misc
             | DBCol::_ReceiptIdToShardId
+            | DBCol::PartialWitnessSpilledParts
+            | DBCol::PartialWitnessEpochStats
             => unreachable!(),
         }
         self.merge(store_update);
@@ -15,6 +15,7 @@ use lru::LruCache;
 use near_async::futures::AsyncComputationSpawnerExt;
 use near_chain_primitives::Error;
 use near_epoch_manager::EpochManagerAdapter;
+use near_o11y::metrics::int_label;
 use near_pool::TransactionGroupIteratorWrapper;
 use near_primitives::apply::ApplyChunkReason;
 use near_primitives::block::Block;
@@ -375,7 +376,7 @@ pub fn validate_chunk_state_witness(
     main_state_transition_cache: &MainStateTransitionCache,
 ) -> Result<(), Error> {
     let _timer = crate::stateless_validation::metrics::CHUNK_STATE_WITNESS_VALIDATION_TIME
-        .with_label_values(&[&state_witness.chunk_header.shard_id().to_string()])
+        .with_label_values(&[&int_label(state_witness.chunk_header.shard_id())])
         .start_timer();
     let span = tracing::debug_span!(target: "client", "validate_chunk_state_witness").entered();
     let block_hash = pre_validation_output.main_transition_params.block_hash();
@@ -528,21 +529,23 @@ impl Chain {
         let parent_span = tracing::debug_span!(
             target: "chain", "shadow_validate", shard_id, height_created);
         let (encoded_witness, raw_witness_size) = {
-            let shard_id_label = shard_id.to_string();
+            let shard_id_label = int_label(shard_id);
             let encode_timer =
                 crate::stateless_validation::metrics::CHUNK_STATE_WITNESS_ENCODE_TIME
-                    .with_label_values(&[shard_id_label.as_str()])
+                    .with_label_values(&[&shard_id_label])
                     .start_timer();
-            let (encoded_witness, raw_witness_size) = EncodedChunkStateWitness::encode(&witness)?;
+            let (encoded_witness, raw_witness_size, section_sizes) =
+                EncodedChunkStateWitness::encode_with_section_sizes(&witness)?;
             encode_timer.observe_duration();
             crate::stateless_validation::metrics::record_witness_size_metrics(
                 raw_witness_size,
-                encoded_witness.size_bytes(),
+                &encoded_witness,
                 &witness,
+                &section_sizes,
             );
             let decode_timer =
                 crate::stateless_validation::metrics::CHUNK_STATE_WITNESS_DECODE_TIME
-                    .with_label_values(&[shard_id_label.as_str()])
+                    .with_label_values(&[&shard_id_label])
                     .start_timer();
             encoded_witness.decode()?;
             decode_timer.observe_duration();
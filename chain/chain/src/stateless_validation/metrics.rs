@@ -1,8 +1,10 @@
 use near_o11y::metrics::{
-    exponential_buckets, linear_buckets, try_create_histogram_vec, try_create_int_counter,
-    try_create_int_gauge, HistogramVec, IntCounter, IntGauge,
+    exponential_buckets, int_label, linear_buckets, try_create_histogram_vec,
+    try_create_int_counter, try_create_int_gauge, HistogramVec, IntCounter, IntGauge,
+};
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, ChunkStateWitnessSectionSizes, EncodedChunkStateWitness,
 };
-use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
 use std::sync::LazyLock;
 
 pub static SAVE_LATEST_WITNESS_GENERATE_UPDATE_TIME: LazyLock<HistogramVec> = LazyLock::new(|| {
@@ -77,6 +79,18 @@ pub(crate) static CHUNK_STATE_WITNESS_TOTAL_SIZE: LazyLock<HistogramVec> = LazyL
     .unwrap()
 });
 
+pub(crate) static CHUNK_STATE_WITNESS_COMPRESSION_RATIO: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_chunk_state_witness_compression_ratio",
+            "Ratio of the raw state witness size to the compressed size, 1.0 for the witnesses \
+            sent uncompressed",
+            &["shard_id"],
+            Some(exponential_buckets(1.0, 1.25, 16).unwrap()),
+        )
+        .unwrap()
+    });
+
 pub(crate) static CHUNK_STATE_WITNESS_RAW_SIZE: LazyLock<HistogramVec> = LazyLock::new(|| {
     try_create_histogram_vec(
         "near_chunk_state_witness_raw_size",
@@ -141,41 +155,66 @@ pub(crate) static CHUNK_STATE_WITNESS_SOURCE_RECEIPT_PROOFS_SIZE: LazyLock<Histo
         .unwrap()
     });
 
+pub(crate) static CHUNK_STATE_WITNESS_TRANSACTIONS_SIZE: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_chunk_state_witness_transactions_size",
+            "Size of ChunkStateWitness::transactions (transactions applied in the chunk)",
+            &["shard_id"],
+            Some(buckets_for_witness_field_size()),
+        )
+        .unwrap()
+    });
+
+pub(crate) static CHUNK_STATE_WITNESS_IMPLICIT_TRANSITIONS_SIZE: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_chunk_state_witness_implicit_transitions_size",
+            "Size of ChunkStateWitness::implicit_transitions (state transitions for missing chunks)",
+            &["shard_id"],
+            Some(buckets_for_witness_field_size()),
+        )
+        .unwrap()
+    });
+
+/// Records the size metrics of the witness. The section sizes are expected to be computed
+/// while encoding the witness, see `EncodedChunkStateWitness::encode_with_section_sizes`.
 pub fn record_witness_size_metrics(
     decoded_size: usize,
-    encoded_size: usize,
+    encoded_witness: &EncodedChunkStateWitness,
     witness: &ChunkStateWitness,
+    section_sizes: &ChunkStateWitnessSectionSizes,
 ) {
-    if let Err(err) = record_witness_size_metrics_fallible(decoded_size, encoded_size, witness) {
-        tracing::warn!(target:"client", "Failed to record witness size metrics!, error: {}", err);
-    }
-}
-
-fn record_witness_size_metrics_fallible(
-    decoded_size: usize,
-    encoded_size: usize,
-    witness: &ChunkStateWitness,
-) -> Result<(), std::io::Error> {
-    let shard_id = witness.chunk_header.shard_id().to_string();
-    CHUNK_STATE_WITNESS_RAW_SIZE
-        .with_label_values(&[shard_id.as_str()])
-        .observe(decoded_size as f64);
-    CHUNK_STATE_WITNESS_TOTAL_SIZE
-        .with_label_values(&[&shard_id.as_str()])
-        .observe(encoded_size as f64);
+    let shard_id = int_label(witness.chunk_header.shard_id());
+    let encoded_size = encoded_witness.size_bytes();
+    CHUNK_STATE_WITNESS_RAW_SIZE.with_label_values(&[&shard_id]).observe(decoded_size as f64);
+    CHUNK_STATE_WITNESS_TOTAL_SIZE.with_label_values(&[&shard_id]).observe(encoded_size as f64);
+    let compression_ratio = if encoded_witness.is_compressed() && encoded_size > 0 {
+        decoded_size as f64 / encoded_size as f64
+    } else {
+        1.0
+    };
+    CHUNK_STATE_WITNESS_COMPRESSION_RATIO
+        .with_label_values(&[&shard_id])
+        .observe(compression_ratio);
     CHUNK_STATE_WITNESS_MAIN_STATE_TRANSISTION_SIZE
-        .with_label_values(&[shard_id.as_str()])
-        .observe(borsh::to_vec(&witness.main_state_transition)?.len() as f64);
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.main_state_transition as f64);
     CHUNK_STATE_WITNESS_NEW_TRANSACTIONS_SIZE
-        .with_label_values(&[&shard_id.as_str()])
-        .observe(borsh::to_vec(&witness.new_transactions)?.len() as f64);
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.new_transactions as f64);
     CHUNK_STATE_WITNESS_NEW_TRANSACTIONS_STATE_SIZE
-        .with_label_values(&[&shard_id.as_str()])
-        .observe(borsh::to_vec(&witness.new_transactions_validation_state)?.len() as f64);
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.new_transactions_validation_state as f64);
     CHUNK_STATE_WITNESS_SOURCE_RECEIPT_PROOFS_SIZE
-        .with_label_values(&[&shard_id.as_str()])
-        .observe(borsh::to_vec(&witness.source_receipt_proofs)?.len() as f64);
-    Ok(())
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.source_receipt_proofs as f64);
+    CHUNK_STATE_WITNESS_TRANSACTIONS_SIZE
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.transactions as f64);
+    CHUNK_STATE_WITNESS_IMPLICIT_TRANSITIONS_SIZE
+        .with_label_values(&[&shard_id])
+        .observe(section_sizes.implicit_transitions as f64);
 }
 
 /// Buckets from 0 to 10MB
@@ -34,7 +34,9 @@ use near_primitives::state_part::PartId;
 use near_primitives::stateless_validation::chunk_endorsement::{
     ChunkEndorsementV1, ChunkEndorsementV2,
 };
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness,
+};
 use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
 use near_primitives::transaction::{
     Action, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus,
@@ -111,6 +113,16 @@ pub struct MockEpochManager {
     /// Maps EpochId to index of `validators_by_valset` to determine validators for an epoch
     hash_to_valset: RwLock<HashMap<EpochId, u64>>,
     epoch_start: RwLock<HashMap<CryptoHash, u64>>,
+    /// Shards for which looking up the chunk validator assignments fails, see
+    /// `fail_chunk_validator_assignments`.
+    failing_assignment_shards: RwLock<HashSet<ShardId>>,
+    /// Protocol version of every epoch, see `set_protocol_version`.
+    protocol_version: RwLock<ProtocolVersion>,
+    /// Whether the chunk validator assignments are returned in the reverse order, see
+    /// `reverse_chunk_validators_order`.
+    reversed_chunk_validators_order: RwLock<bool>,
+    /// Epochs looked up as if they were not known yet, see `hide_epoch`.
+    hidden_epochs: RwLock<HashSet<EpochId>>,
 }
 
 /// Stores the validator information in an epoch.
@@ -217,9 +229,49 @@ impl MockEpochManager {
             hash_to_next_epoch: RwLock::new(map_with_default_hash1),
             hash_to_valset: RwLock::new(map_with_default_hash3),
             epoch_start: RwLock::new(map_with_default_hash2),
+            failing_assignment_shards: RwLock::new(HashSet::new()),
+            protocol_version: RwLock::new(PROTOCOL_VERSION),
+            reversed_chunk_validators_order: RwLock::new(false),
+            hidden_epochs: RwLock::new(HashSet::new()),
         })
     }
 
+    /// Makes `get_chunk_validator_assignments` fail for the shard from now on, to test how the
+    /// failures of a single shard are handled.
+    pub fn fail_chunk_validator_assignments(&self, shard_id: ShardId) {
+        self.failing_assignment_shards.write().unwrap().insert(shard_id);
+    }
+
+    /// Sets the protocol version of every epoch, `PROTOCOL_VERSION` by default. Lets the tests
+    /// cover the protocol features not enabled in `PROTOCOL_VERSION` yet.
+    pub fn set_protocol_version(&self, protocol_version: ProtocolVersion) {
+        *self.protocol_version.write().unwrap() = protocol_version;
+    }
+
+    /// Makes `get_chunk_validator_assignments` return the same chunk validators in the reverse
+    /// order, as a node with a diverging assignment logic would.
+    pub fn reverse_chunk_validators_order(&self, reversed: bool) {
+        *self.reversed_chunk_validators_order.write().unwrap() = reversed;
+    }
+
+    /// Makes the lookups of the epoch info and the validators of the epoch fail with
+    /// `EpochOutOfBounds` while hidden, as an epoch manager lagging behind the chain would.
+    pub fn hide_epoch(&self, epoch_id: EpochId, hidden: bool) {
+        let mut hidden_epochs = self.hidden_epochs.write().unwrap();
+        if hidden {
+            hidden_epochs.insert(epoch_id);
+        } else {
+            hidden_epochs.remove(&epoch_id);
+        }
+    }
+
+    fn check_epoch_not_hidden(&self, epoch_id: &EpochId) -> Result<(), EpochError> {
+        if self.hidden_epochs.read().unwrap().contains(epoch_id) {
+            return Err(EpochError::EpochOutOfBounds(*epoch_id));
+        }
+        Ok(())
+    }
+
     /// Get epoch and index of validator set by the hash of previous block.
     /// Note that it also fills in-memory chain info and there is some
     /// assumption that it is called for all previous blocks.
@@ -294,6 +346,7 @@ impl MockEpochManager {
     }
 
     fn get_valset_for_epoch(&self, epoch_id: &EpochId) -> Result<usize, EpochError> {
+        self.check_epoch_not_hidden(epoch_id)?;
         // conveniently here if the prev_hash is passed mistakenly instead of the epoch_hash,
         // the `unwrap` will trigger
         Ok(*self
@@ -498,7 +551,8 @@ impl EpochManagerAdapter for MockEpochManager {
     /// - block producers
     /// - chunk producers
     /// All the other fields have a hardcoded value or left empty.
-    fn get_epoch_info(&self, _epoch_id: &EpochId) -> Result<Arc<EpochInfo>, EpochError> {
+    fn get_epoch_info(&self, epoch_id: &EpochId) -> Result<Arc<EpochInfo>, EpochError> {
+        self.check_epoch_not_hidden(epoch_id)?;
         let validators = self.validators.iter().map(|(_, stake)| stake.clone()).collect();
         let mut validator_to_index = HashMap::new();
         for (i, (account_id, _)) in self.validators.iter().enumerate() {
@@ -736,15 +790,23 @@ impl EpochManagerAdapter for MockEpochManager {
     fn get_chunk_validator_assignments(
         &self,
         epoch_id: &EpochId,
-        _shard_id: ShardId,
+        shard_id: ShardId,
         _height: BlockHeight,
     ) -> Result<Arc<ChunkValidatorAssignments>, EpochError> {
-        let chunk_validators = self
+        if self.failing_assignment_shards.read().unwrap().contains(&shard_id) {
+            return Err(EpochError::ChunkValidatorSelectionError(format!(
+                "chunk validator assignments of shard {shard_id} set to fail"
+            )));
+        }
+        let mut chunk_validators: Vec<_> = self
             .get_block_producers(self.get_valset_for_epoch(epoch_id)?)
             .into_iter()
             .cloned()
             .map(|validator| validator.account_and_stake())
             .collect();
+        if *self.reversed_chunk_validators_order.read().unwrap() {
+            chunk_validators.reverse();
+        }
         Ok(Arc::new(ChunkValidatorAssignments::new(chunk_validators)))
     }
 
@@ -809,7 +871,7 @@ impl EpochManagerAdapter for MockEpochManager {
         &self,
         _epoch_id: &EpochId,
     ) -> Result<ProtocolVersion, EpochError> {
-        Ok(PROTOCOL_VERSION)
+        Ok(*self.protocol_version.read().unwrap())
     }
 
     fn get_epoch_sync_data(
@@ -967,6 +1029,13 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(true)
     }
 
+    fn verify_full_witness_signature(
+        &self,
+        _full_witness: &FullEncodedStateWitness,
+    ) -> Result<bool, Error> {
+        Ok(true)
+    }
+
     fn cares_about_shard_in_epoch(
         &self,
         epoch_id: EpochId,
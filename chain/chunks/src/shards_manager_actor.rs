@@ -1109,7 +1109,7 @@ impl ShardsManagerActor {
         }
 
         let encoded_length = chunk.encoded_length();
-        if let Err(err) = reed_solomon_decode::<TransactionReceipt>(
+        if let Err(err) = reed_solomon_decode::<TransactionReceipt, _>(
             &self.rs,
             chunk.content_mut().parts.as_mut_slice(),
             encoded_length as usize,
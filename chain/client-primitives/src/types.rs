@@ -609,6 +609,8 @@ pub enum StatusError {
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
+    #[error("Node can't perform its chunk validator duties, failed checks: {failed_checks:?}")]
+    StatelessValidationUnhealthy { failed_checks: Vec<String> },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -0,0 +1,128 @@
+//! Latency of forwarding our own witness part to the other chunk validators, when storing the
+//! part in the partial witness tracker is slow. The tracker is slowed artificially by sleeping
+//! whenever it starts storing a part, see `TRACKER_DELAY`.
+//!
+//! Our part is forwarded before it is stored, so the forward doesn't wait for the tracker. The
+//! time the whole handler takes is printed alongside, which is when the part used to be forwarded
+//! when the store came first.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use near_async::time::{FakeClock, Utc};
+use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+use near_chain_configs::PartialWitnessConfig;
+use near_client::test_utils::PartialWitnessTestDriver;
+use near_client::DistributeStateWitnessRequest;
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::state_witness::PartialEncodedStateWitnessMessage;
+use near_network::types::NetworkRequests;
+use near_o11y::testonly::TracingCapture;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_store::test_utils::create_test_store;
+
+const VALIDATORS: [&str; 4] = ["test0", "test1", "test2", "test3"];
+
+/// Chunk validator whose forward latency is measured.
+const VALIDATOR: &str = "test0";
+
+/// Time the tracker spends storing every part.
+const TRACKER_DELAY: Duration = Duration::from_millis(2);
+
+const NUM_WITNESSES: BlockHeight = 200;
+
+/// The parts owned by `VALIDATOR` of a witness per height, skipping the heights of its chunks.
+fn owned_parts(
+    clock: &FakeClock,
+    epoch_manager: &Arc<MockEpochManager>,
+) -> Vec<PartialEncodedStateWitness> {
+    let validator: AccountId = VALIDATOR.parse().unwrap();
+    let mut parts = vec![];
+    for height in 1..=NUM_WITNESSES {
+        let chunk_producer =
+            epoch_manager.get_chunk_producer(&EpochId::default(), height, 0).unwrap();
+        if chunk_producer == validator {
+            continue;
+        }
+        let mut producer = PartialWitnessTestDriver::new(
+            clock.clone(),
+            chunk_producer,
+            epoch_manager.clone(),
+            PartialWitnessConfig::default(),
+        );
+        let witness = ChunkStateWitness::new_dummy(height, 0, CryptoHash::default());
+        producer.send(DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            clock.now(),
+        ));
+        for request in producer.take_network_requests() {
+            if let NetworkRequests::PartialEncodedStateWitness(sent_parts, _) = request {
+                parts.extend(
+                    sent_parts
+                        .into_iter()
+                        .filter(|(target, _)| target == &validator)
+                        .map(|(_, partial_witness)| partial_witness),
+                );
+            }
+        }
+    }
+    parts
+}
+
+fn median(mut durations: Vec<Duration>) -> Duration {
+    durations.sort();
+    durations[durations.len() / 2]
+}
+
+fn main() {
+    let validators = VALIDATORS.iter().map(|account_id| account_id.parse().unwrap()).collect();
+    let vs = ValidatorSchedule::new().num_shards(1).block_producers_per_epoch(vec![validators]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(create_test_store(), vs, NUM_WITNESSES + 1);
+    let clock = FakeClock::new(Utc::UNIX_EPOCH);
+    let parts = owned_parts(&clock, &epoch_manager);
+    let mut validator = PartialWitnessTestDriver::new(
+        clock.clone(),
+        VALIDATOR.parse().unwrap(),
+        epoch_manager,
+        PartialWitnessConfig::default(),
+    );
+
+    // When the tracker starts storing our part, after the forward if there is one.
+    let mut capture = TracingCapture::enable();
+    let store_started = Arc::new(Mutex::new(None));
+    capture.set_callback({
+        let store_started = store_started.clone();
+        let network_requests = validator.network_requests_counter();
+        move |log| {
+            if log.starts_with("store_partial_encoded_state_witness") {
+                assert_eq!(network_requests(), 1, "the part is stored before it is forwarded");
+                *store_started.lock().unwrap() = Some(Instant::now());
+                std::thread::sleep(TRACKER_DELAY);
+            }
+        }
+    });
+
+    let mut forward_latencies = vec![];
+    let mut handler_latencies = vec![];
+    for partial_witness in parts {
+        let start = Instant::now();
+        validator.send(PartialEncodedStateWitnessMessage(partial_witness));
+        handler_latencies.push(start.elapsed());
+        let store_started = store_started.lock().unwrap().take().unwrap();
+        forward_latencies.push(store_started - start);
+        validator.take_network_requests();
+    }
+    println!(
+        "{} owned parts with a tracker delay of {TRACKER_DELAY:?}: forwarded within {:?}, \
+         handled in {:?} (median)",
+        forward_latencies.len(),
+        median(forward_latencies),
+        median(handler_latencies),
+    );
+}
@@ -351,6 +351,7 @@ impl Client {
             runtime_adapter.clone(),
             config.orphan_state_witness_pool_size,
             async_computation_spawner,
+            partial_witness_adapter.chunk_state_witness_outcome.clone(),
             panic_on_validation_error,
         );
         let chunk_distribution_network = ChunkDistributionNetwork::from_config(&config);
@@ -1820,6 +1821,7 @@ impl Client {
                 Some(signer),
             ) {
                 Ok(Some(result)) => {
+                    let chunk_produced_at = self.clock.now();
                     let shard_chunk = self
                         .persist_and_distribute_encoded_chunk(
                             result.chunk,
@@ -1835,6 +1837,7 @@ impl Client {
                         &shard_chunk,
                         result.transactions_storage_proof,
                         &Some(signer.clone()),
+                        chunk_produced_at,
                     ) {
                         tracing::error!(target: "client", ?err, "Failed to send chunk state witness to chunk validators");
                     }
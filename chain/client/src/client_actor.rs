@@ -11,7 +11,17 @@ use crate::client::{Client, EPOCH_START_INFO_BLOCKS};
 use crate::config_updater::ConfigUpdater;
 use crate::debug::new_network_info_view;
 use crate::info::{display_sync_status, InfoHelper};
-use crate::stateless_validation::partial_witness::partial_witness_actor::PartialWitnessSenderForClient;
+use crate::stateless_validation::partial_witness::partial_witness_actor::{
+    ChainHeadUpdatedMessage, ChunkStateWitnessConsumedMessage, PartialWitnessDebugMessage,
+    PartialWitnessErrorMessage, PartialWitnessSenderForClient, PartialWitnessWarmedUp,
+    StatelessValidationHealthMessage, SyncStatusChangedMessage, WarmUpPartialWitness,
+    WitnessConfigMessage,
+};
+use crate::stateless_validation::partial_witness::{
+    load_epoch_witness_stats, StatelessValidationHealth,
+};
+#[cfg(feature = "test_features")]
+use crate::stateless_validation::partial_witness::{AdvWitnessPartsMessage, AdvWitnessPartsMode};
 use crate::sync::adapter::{SyncMessage, SyncShardInfo};
 use crate::sync::state::{StateSync, StateSyncResult};
 use crate::sync_jobs_actor::{ClientSenderForSyncJobs, SyncJobsActor};
@@ -65,12 +75,16 @@ use near_primitives::block::Tip;
 use near_primitives::block_header::ApprovalType;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
-use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, EpochId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
-use near_primitives::views::{DetailedDebugStatus, ValidatorInfo};
+use near_primitives::views::{
+    DetailedDebugStatus, EpochWitnessStatsView, PartialWitnessDebugView,
+    StatelessValidationHealthCheckView, StatelessValidationHealthView, ValidatorInfo,
+    WitnessConfigView,
+};
 #[cfg(feature = "test_features")]
 use near_store::DBCol;
 use near_store::ShardUId;
@@ -88,6 +102,12 @@ const STATUS_WAIT_TIME_MULTIPLIER: i32 = 10;
 /// `max_block_production_time` times this multiplier is how long we wait before rebroadcasting
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
+/// Age of the last stateless validation health report of the PartialWitnessActor above which the
+/// actor is considered stuck, see `StatelessValidationHealthMessage`.
+const STATELESS_VALIDATION_HEALTH_REPORT_TTL: Duration = Duration::seconds(10);
+/// Number of heights below and above the head searched for the blocks of the other forks, see
+/// `highest_alternative_tip`.
+const ALTERNATIVE_TIP_SEARCH_DISTANCE: BlockHeightDelta = 5;
 
 pub type ClientActor = ActixWrapper<ClientActorInner>;
 
@@ -213,6 +233,11 @@ pub struct SyncJobsSenderForClient {
 #[derive(Clone, MultiSend, MultiSenderFrom)]
 pub struct ClientSenderForPartialWitness {
     pub chunk_state_witness: Sender<ChunkStateWitnessMessage>,
+    pub partial_witness_warmed_up: Sender<PartialWitnessWarmedUp>,
+    pub stateless_validation_health: Sender<StatelessValidationHealthMessage>,
+    pub witness_config: Sender<WitnessConfigMessage>,
+    pub partial_witness_error: Sender<PartialWitnessErrorMessage>,
+    pub partial_witness_debug: Sender<PartialWitnessDebugMessage>,
 }
 
 // A small helper macro to unwrap a result of some state sync operation. If the
@@ -241,6 +266,19 @@ pub struct ClientActorInner {
     node_id: PeerId,
     /// Last time we announced our accounts as validators.
     last_validator_announce_time: Option<Instant>,
+    /// Set while we wait for the PartialWitnessActor to warm up after start. We don't announce
+    /// our account until the warm up is done or this deadline passes.
+    partial_witness_warm_up_deadline: Option<Instant>,
+    /// Whether the PartialWitnessActor was last told that we are syncing, see
+    /// `send_sync_status_to_partial_witness_actor`.
+    partial_witness_syncing: bool,
+    /// Last stateless validation health reported by the PartialWitnessActor, with the time of
+    /// the report.
+    stateless_validation_health: Option<(StatelessValidationHealth, Instant)>,
+    /// Effective witness distribution config last reported by the PartialWitnessActor.
+    witness_config: Option<WitnessConfigView>,
+    /// Recent activity of the witness distribution last reported by the PartialWitnessActor.
+    partial_witness_debug: Option<PartialWitnessDebugView>,
     /// Info helper.
     info_helper: InfoHelper,
 
@@ -376,6 +414,11 @@ impl ClientActorInner {
                 tier1_accounts_data: vec![],
             },
             last_validator_announce_time: None,
+            partial_witness_warm_up_deadline: None,
+            partial_witness_syncing: false,
+            stateless_validation_health: None,
+            witness_config: None,
+            partial_witness_debug: None,
             info_helper,
             block_production_next_attempt: now,
             log_summary_timer_next_attempt: now,
@@ -407,6 +450,7 @@ pub enum AdvProduceChunksMode {
 pub enum NetworkAdversarialMessage {
     AdvProduceBlocks(u64, bool),
     AdvProduceChunks(AdvProduceChunksMode),
+    AdvWitnessParts(AdvWitnessPartsMode),
     AdvSwitchToHeight(u64),
     AdvDisableHeaderSync,
     AdvDisableDoomslug,
@@ -513,6 +557,11 @@ impl Handler<NetworkAdversarialMessage> for ClientActorInner {
                 self.client.adv_produce_chunks = Some(adv_produce_chunks);
                 None
             }
+            NetworkAdversarialMessage::AdvWitnessParts(mode) => {
+                info!(target: "adversary", ?mode, "setting adversary witness parts mode");
+                self.client.partial_witness_adapter.send(AdvWitnessPartsMessage(mode));
+                None
+            }
         }
     }
 }
@@ -723,6 +772,18 @@ impl Handler<Status> for ClientActorInner {
                 return Err(StatusError::NodeIsSyncing);
             }
         }
+        let stateless_validation_health = self.stateless_validation_health_view();
+        if msg.is_health_check && self.client.config.partial_witness.health.fail_health_check {
+            if let Some(health) = stateless_validation_health.as_ref().filter(|h| !h.healthy) {
+                let failed_checks = health
+                    .checks
+                    .iter()
+                    .filter(|check| !check.healthy)
+                    .map(|check| check.name.clone())
+                    .collect();
+                return Err(StatusError::StatelessValidationUnhealthy { failed_checks });
+            }
+        }
         let validators: Vec<ValidatorInfo> = self
             .client
             .epoch_manager
@@ -818,6 +879,10 @@ impl Handler<Status> for ClientActorInner {
             uptime_sec,
             genesis_hash: *self.client.chain.genesis().hash(),
             detailed_debug_status,
+            stateless_validation_health,
+            epoch_witness_stats: self.epoch_witness_stats_views(),
+            witness_config: self.witness_config.clone(),
+            partial_witness_debug: self.partial_witness_debug.clone(),
         })
     }
 }
@@ -922,6 +987,35 @@ impl fmt::Display for SyncRequirement {
 }
 
 impl ClientActorInner {
+    /// Stateless validation health last reported by the PartialWitnessActor, unhealthy if the
+    /// report is too old, as the actor is then likely stuck.
+    fn stateless_validation_health_view(&self) -> Option<StatelessValidationHealthView> {
+        let (health, reported_at) = self.stateless_validation_health.as_ref()?;
+        let mut view = health.to_view();
+        let report_age = self.clock.now() - *reported_at;
+        if report_age > STATELESS_VALIDATION_HEALTH_REPORT_TTL {
+            view.healthy = false;
+            view.checks.push(StatelessValidationHealthCheckView {
+                name: "report_fresh".to_owned(),
+                healthy: false,
+                details: format!("last reported {report_age} ago"),
+            });
+        }
+        Some(view)
+    }
+
+    /// Witness distribution summaries of the recent epochs persisted by the PartialWitnessActor,
+    /// starting from the most recent one.
+    fn epoch_witness_stats_views(&self) -> Vec<EpochWitnessStatsView> {
+        match load_epoch_witness_stats(self.client.chain.chain_store().store()) {
+            Ok(summaries) => summaries.iter().map(|stats| stats.to_view()).collect(),
+            Err(err) => {
+                tracing::warn!(target: "client", ?err, "Failed to load the epoch witness stats");
+                vec![]
+            }
+        }
+    }
+
     pub fn start(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
         self.start_flat_storage_creation(ctx);
 
@@ -937,6 +1031,28 @@ impl ClientActorInner {
         if let Err(err) = self.client.send_network_chain_info() {
             tracing::error!(target: "client", ?err, "Failed to update network chain info");
         }
+
+        self.start_partial_witness_warm_up();
+    }
+
+    /// Asks the PartialWitnessActor to prepare for the witnesses at the heights after the head,
+    /// so that the first witnesses received after the restart aren't delayed. The account isn't
+    /// announced to the peers until the warm up is done, see `check_send_announce_account`.
+    fn start_partial_witness_warm_up(&mut self) {
+        let head = match self.client.chain.head() {
+            Ok(head) => head,
+            Err(err) => {
+                tracing::error!(
+                    target: "client",
+                    ?err,
+                    "Failed to get head to warm up partial witness actor"
+                );
+                return;
+            }
+        };
+        self.partial_witness_warm_up_deadline =
+            Some(self.clock.now() + self.client.config.partial_witness.warm_up_timeout);
+        self.client.partial_witness_adapter.send(WarmUpPartialWitness { head });
     }
 
     /// Check if client Account Id should be sent and send it.
@@ -959,6 +1075,18 @@ impl ClientActorInner {
         };
 
         let now = self.clock.now();
+        if let Some(deadline) = self.partial_witness_warm_up_deadline {
+            if now < deadline {
+                debug!(target: "client", "Partial witness actor is warming up: skip account announce");
+                return;
+            }
+            tracing::warn!(
+                target: "client",
+                "Partial witness actor didn't warm up in time, announcing account anyway"
+            );
+            self.partial_witness_warm_up_deadline = None;
+        }
+
         // Check that we haven't announced it too recently
         if let Some(last_validator_announce_time) = self.last_validator_announce_time {
             // Don't make announcement if have passed less than half of the time in which other peers
@@ -1241,7 +1369,10 @@ impl ClientActorInner {
                 self.sync_wait_period(),
                 self.sync_timer_next_attempt,
                 ctx,
-                |act, _| act.run_sync_step(),
+                |act, _| {
+                    act.run_sync_step();
+                    act.send_sync_status_to_partial_witness_actor();
+                },
                 "sync",
             );
 
@@ -1456,6 +1587,9 @@ impl ClientActorInner {
             "process_accepted_blocks",
             num_blocks = accepted_blocks.len())
         .entered();
+        if !accepted_blocks.is_empty() {
+            self.send_head_to_partial_witness_actor();
+        }
         for accepted_block in accepted_blocks {
             let block = self.client.chain.get_block(&accepted_block).unwrap().clone();
             debug!(target: "client", height=block.header().height(), "process_accepted_block");
@@ -1465,6 +1599,78 @@ impl ClientActorInner {
         }
     }
 
+    /// Tells the PartialWitnessActor about the new head, whose timestamp is the baseline for the
+    /// latency of the witness parts of the next chunks, together with the highest known tip of
+    /// the other forks.
+    fn send_head_to_partial_witness_actor(&self) {
+        let head = match self.client.chain.head() {
+            Ok(head) => head,
+            Err(err) => {
+                tracing::error!(target: "client", ?err, "Failed to get head");
+                return;
+            }
+        };
+        let head_timestamp = match self.client.chain.get_block_header(&head.last_block_hash) {
+            Ok(header) => header.timestamp(),
+            Err(err) => {
+                tracing::error!(target: "client", ?err, "Failed to get head block header");
+                return;
+            }
+        };
+        let alternative_tip = match self.highest_alternative_tip(&head) {
+            Ok(alternative_tip) => alternative_tip,
+            Err(err) => {
+                tracing::debug!(target: "client", ?err, "Failed to find alternative tip");
+                None
+            }
+        };
+        self.client.partial_witness_adapter.send(ChainHeadUpdatedMessage {
+            head,
+            head_timestamp,
+            alternative_tip,
+        });
+    }
+
+    /// The highest block not on the chain of the head, among the blocks above the final head and
+    /// within `ALTERNATIVE_TIP_SEARCH_DISTANCE` of the head. The highest block of a fork has no
+    /// known child on the fork, so it is the tip of the fork.
+    fn highest_alternative_tip(&self, head: &Tip) -> Result<Option<Tip>, near_chain::Error> {
+        let chain_store = self.client.chain.chain_store();
+        let final_height = self.client.chain.final_head()?.height;
+        let lowest_height = std::cmp::max(
+            final_height + 1,
+            head.height.saturating_sub(ALTERNATIVE_TIP_SEARCH_DISTANCE),
+        );
+        let highest_height = head.height + ALTERNATIVE_TIP_SEARCH_DISTANCE;
+        for height in (lowest_height..=highest_height).rev() {
+            let canonical_hash = if height <= head.height {
+                chain_store.get_block_hash_by_height(height).ok()
+            } else {
+                None
+            };
+            let block_hashes = chain_store.get_all_block_hashes_by_height(height)?;
+            let Some(block_hash) =
+                block_hashes.values().flatten().find(|hash| Some(**hash) != canonical_hash)
+            else {
+                continue;
+            };
+            let header = self.client.chain.get_block_header(block_hash)?;
+            return Ok(Some(Tip::from_header(&header)));
+        }
+        Ok(None)
+    }
+
+    /// Tells the PartialWitnessActor when we start or stop syncing, so that it doesn't distribute
+    /// or handle the witnesses relative to a head which the sync is about to replace.
+    fn send_sync_status_to_partial_witness_actor(&mut self) {
+        let syncing = self.client.sync_status.is_syncing();
+        if syncing == self.partial_witness_syncing {
+            return;
+        }
+        self.partial_witness_syncing = syncing;
+        self.client.partial_witness_adapter.send(SyncStatusChangedMessage { syncing });
+    }
+
     fn receive_headers(
         &mut self,
         headers: Vec<BlockHeader>,
@@ -2203,13 +2409,65 @@ impl Handler<SyncMessage> for ClientActorInner {
 impl Handler<ChunkStateWitnessMessage> for ClientActorInner {
     #[perf]
     fn handle(&mut self, msg: ChunkStateWitnessMessage) {
-        let ChunkStateWitnessMessage { witness, raw_witness_size } = msg;
+        let ChunkStateWitnessMessage {
+            witness,
+            raw_witness_size,
+            pre_tracking,
+            prev_block_known,
+            decoded_late,
+        } = msg;
+        let key = witness.chunk_production_key();
         let signer = self.client.validator_signer.get();
-        if let Err(err) =
+        let result = if pre_tracking {
+            self.client.process_pre_tracked_chunk_state_witness(witness, signer)
+        } else if decoded_late {
+            self.client.process_late_chunk_state_witness(witness, signer)
+        } else {
+            if !prev_block_known {
+                // The block may have arrived in the meantime, `process_chunk_state_witness`
+                // checks again and keeps the witness in the orphan pool only if it's still missing.
+                tracing::debug!(
+                    target: "client",
+                    ?key,
+                    "Received chunk state witness reconstructed ahead of its previous block"
+                );
+            }
             self.client.process_chunk_state_witness(witness, raw_witness_size, None, signer)
-        {
-            tracing::error!(target: "client", ?err, "Error processing chunk state witness");
+        };
+        if let Err(err) = result {
+            tracing::error!(target: "client", ?err, pre_tracking, "Error processing chunk state witness");
         }
+        self.client.partial_witness_adapter.send(ChunkStateWitnessConsumedMessage { key });
+    }
+}
+
+impl Handler<PartialWitnessWarmedUp> for ClientActorInner {
+    fn handle(&mut self, _msg: PartialWitnessWarmedUp) {
+        self.partial_witness_warm_up_deadline = None;
+    }
+}
+
+impl Handler<StatelessValidationHealthMessage> for ClientActorInner {
+    fn handle(&mut self, msg: StatelessValidationHealthMessage) {
+        self.stateless_validation_health = Some((msg.0, self.clock.now()));
+    }
+}
+
+impl Handler<WitnessConfigMessage> for ClientActorInner {
+    fn handle(&mut self, msg: WitnessConfigMessage) {
+        self.witness_config = Some(msg.0);
+    }
+}
+
+impl Handler<PartialWitnessDebugMessage> for ClientActorInner {
+    fn handle(&mut self, msg: PartialWitnessDebugMessage) {
+        self.partial_witness_debug = Some(msg.0);
+    }
+}
+
+impl Handler<PartialWitnessErrorMessage> for ClientActorInner {
+    fn handle(&mut self, msg: PartialWitnessErrorMessage) {
+        self.info_helper.partial_witness_error(msg.0);
     }
 }
 
@@ -1,4 +1,5 @@
 use crate::config_updater::ConfigUpdater;
+use crate::stateless_validation::partial_witness::PartialWitnessErrorEvent;
 use crate::{metrics, SyncStatus};
 use itertools::Itertools;
 use lru::LruCache;
@@ -27,7 +28,7 @@ use near_primitives::views::{
 };
 use near_telemetry::TelemetryEvent;
 use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
@@ -37,6 +38,10 @@ use tracing::info;
 
 const TERAGAS: f64 = 1_000_000_000_000_f64;
 
+/// Maximum number of the partial witness errors kept until the next telemetry report, the oldest
+/// ones are dropped first. The errors are already sampled by the partial witness actor.
+const MAX_PARTIAL_WITNESS_ERRORS: usize = 100;
+
 struct ValidatorInfoHelper {
     pub is_validator: bool,
     pub num_validators: usize,
@@ -73,6 +78,8 @@ pub struct InfoHelper {
     prev_sync_requirement: Option<String>,
     /// Number of validators (block + chunk producers) per epoch, cached for a small number of epochs.
     num_validators_per_epoch: LruCache<EpochId, usize>,
+    /// Errors of the partial witness actor since the last telemetry report.
+    partial_witness_errors: VecDeque<PartialWitnessErrorEvent>,
 }
 
 impl InfoHelper {
@@ -99,7 +106,16 @@ impl InfoHelper {
             enable_multiline_logging: client_config.enable_multiline_logging,
             prev_sync_requirement: None,
             num_validators_per_epoch: LruCache::new(NonZeroUsize::new(3).unwrap()),
+            partial_witness_errors: VecDeque::new(),
+        }
+    }
+
+    /// Records the error to include in the next telemetry report.
+    pub fn partial_witness_error(&mut self, event: PartialWitnessErrorEvent) {
+        if self.partial_witness_errors.len() >= MAX_PARTIAL_WITNESS_ERRORS {
+            self.partial_witness_errors.pop_front();
         }
+        self.partial_witness_errors.push_back(event);
     }
 
     pub fn chunk_processed(&mut self, shard_id: ShardId, gas_used: Gas, balance_burnt: Balance) {
@@ -493,6 +509,7 @@ impl InfoHelper {
             ),
         };
         self.telemetry_sender.send(telemetry_event);
+        self.partial_witness_errors.clear();
     }
 
     /// Updates the prometheus metrics to track the block and chunk production and endorsement by validators.
@@ -608,7 +625,11 @@ impl InfoHelper {
                     .as_seconds_f64(),
                 max_block_wait_delay: client_config.max_block_wait_delay.as_seconds_f64(),
             },
-            extra_info: serde_json::to_string(&extra_telemetry_info(client_config)).unwrap(),
+            extra_info: serde_json::to_string(&extra_telemetry_info(
+                client_config,
+                &self.partial_witness_errors,
+            ))
+            .unwrap(),
         };
         // Sign telemetry if there is a signer present.
         if let Some(signer) = signer {
@@ -665,12 +686,16 @@ impl InfoHelper {
     }
 }
 
-fn extra_telemetry_info(client_config: &ClientConfig) -> serde_json::Value {
+fn extra_telemetry_info(
+    client_config: &ClientConfig,
+    partial_witness_errors: &VecDeque<PartialWitnessErrorEvent>,
+) -> serde_json::Value {
     serde_json::json!({
         "block_production_tracking_delay":  client_config.block_production_tracking_delay.as_seconds_f64(),
         "min_block_production_delay":  client_config.min_block_production_delay.as_seconds_f64(),
         "max_block_production_delay": client_config.max_block_production_delay.as_seconds_f64(),
         "max_block_wait_delay": client_config.max_block_wait_delay.as_seconds_f64(),
+        "partial_witness_errors": partial_witness_errors,
     })
 }
 
@@ -979,7 +1004,16 @@ mod tests {
     fn test_telemetry_info() {
         let config = ClientConfig::test(false, 1230, 2340, 50, false, true, true);
         let validator = MutableConfigValue::new(None, "validator_signer");
-        let info_helper = InfoHelper::new(Clock::real(), noop().into_sender(), &config);
+        let mut info_helper = InfoHelper::new(Clock::real(), noop().into_sender(), &config);
+        info_helper.partial_witness_error(PartialWitnessErrorEvent {
+            stage: "owned_part",
+            error: "invalid_partial_chunk_state_witness",
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created: 7,
+            chunk_producer: Some("test".parse().unwrap()),
+            message: "bad part".to_string(),
+        });
 
         let store = near_store::test_utils::create_test_store();
         let mut genesis = Genesis::test(vec!["test".parse::<AccountId>().unwrap()], 1);
@@ -1033,6 +1067,10 @@ mod tests {
             telemetry["extra_info"].as_str().unwrap().find("\"max_block_production_delay\":2.34,"),
             Some(_)
         );
+        let extra_info: serde_json::Value =
+            serde_json::from_str(telemetry["extra_info"].as_str().unwrap()).unwrap();
+        assert_eq!(extra_info["partial_witness_errors"][0]["stage"], "owned_part");
+        assert_eq!(extra_info["partial_witness_errors"][0]["chunk_producer"], "test");
     }
 
     /// Tests that `num_validators` returns the number of all validators including both block and chunk producers.
@@ -23,9 +23,26 @@ pub use near_client_primitives::debug::DebugStatus;
 pub use near_network::client::{
     BlockApproval, BlockResponse, ProcessTxRequest, ProcessTxResponse, SetNetworkInfo,
 };
+pub use stateless_validation::partial_witness::message_recorder::{
+    RecordedEntry, RecordedMessageKind, WitnessMessageRecordingReader,
+};
 pub use stateless_validation::partial_witness::partial_witness_actor::{
-    DistributeStateWitnessRequest, PartialWitnessActor,
+    AnnounceWitnessReceiverUnavailable, ChunkStateWitnessOutcome, DistributeStateWitnessRequest,
+    PartialWitnessActor,
+};
+pub use stateless_validation::partial_witness::stats_export::{
+    WitnessStatsRecord, WitnessStatsSource,
+};
+pub use stateless_validation::partial_witness::{
+    clear_spilled_witness_parts, install_handler_panic_hook, IncompleteWitnessSnapshot,
+    PartialWitnessState, PartialWitnessStateV1, WitnessDecodePath,
 };
+#[cfg(feature = "witness_simulation")]
+pub use stateless_validation::partial_witness::{
+    witness_parts_geometry, ReedSolomonBackend, WitnessEncoderCache,
+};
+#[cfg(feature = "test_features")]
+pub use stateless_validation::partial_witness::{AdvWitnessPartsMode, ForceRedistributeWitness};
 
 pub mod adapter;
 pub mod adversarial;
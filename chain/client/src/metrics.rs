@@ -601,13 +601,39 @@ pub(crate) static CHUNK_STATE_WITNESS_NETWORK_ROUNDTRIP_TIME: LazyLock<Histogram
     LazyLock::new(|| {
         try_create_histogram_vec(
             "near_chunk_state_witness_network_roundtrip_time",
-            "Time in seconds between sending state witness through the network to chunk producer and receiving the corresponding ack message",
+            "Time in seconds between sending the state witness parts to the chunk validators and receiving the ack sent by a chunk validator once it reconstructed the witness, before validating it",
             &["witness_size_bucket"],
             Some(exponential_buckets(0.001, 2.0, 20).unwrap()),
         )
         .unwrap()
     });
 
+pub(crate) static CHUNK_STATE_WITNESS_TRACKER_ENTRIES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_chunk_state_witness_tracker_entries",
+        "Number of state witnesses produced by this node which are waiting for acks from the chunk validators",
+    )
+    .unwrap()
+});
+
+pub(crate) static CHUNK_STATE_WITNESS_TRACKER_EVICTIONS: LazyLock<IntCounter> = LazyLock::new(
+    || {
+        try_create_int_counter(
+            "near_chunk_state_witness_tracker_evictions",
+            "Number of state witnesses produced by this node which were evicted from the tracker before all the chunk validators acked them",
+        )
+        .unwrap()
+    },
+);
+
+pub(crate) static CHUNK_STATE_WITNESS_UNTRACKED_ACKS: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_chunk_state_witness_untracked_acks",
+        "Number of state witness acks received for witnesses which are not tracked anymore, either evicted or already acked by all the chunk validators",
+    )
+    .unwrap()
+});
+
 pub(crate) static ORPHAN_CHUNK_STATE_WITNESS_POOL_SIZE: LazyLock<IntGaugeVec> =
     LazyLock::new(|| {
         try_create_int_gauge_vec(
@@ -677,7 +703,936 @@ pub(crate) static PARTIAL_WITNESS_TIME_TO_LAST_PART: LazyLock<HistogramVec> = La
 pub(crate) static PARTIAL_WITNESS_CACHE_SIZE: LazyLock<Gauge> = LazyLock::new(|| {
     try_create_gauge(
         "near_partial_witness_cache_size",
-        "Total size in bytes of all currently cached witness parts",
+        "Memory in bytes retained by the incomplete witnesses cached by the tracker",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_OWNED_PART_REQUESTS_SERVED: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_owned_part_requests_served",
+            "Number of requests for our own witness part answered directly to the requester",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_OWNED_PART_REBROADCASTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_owned_part_rebroadcasts",
+            "Number of times our own witness part was re-broadcast to all chunk validators \
+            because multiple validators were missing it",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SPILLED_ENTRIES: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_partial_witness_spilled_entries",
+        "Number of incomplete witnesses whose parts were spilled from memory to the database",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_RESTORED_ENTRIES: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_partial_witness_restored_entries",
+        "Number of witnesses whose spilled parts were restored from the database for decoding",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_errors_total",
+        "Number of errors while handling partial witness messages, by handling stage, shard and \
+        error type",
+        &["stage", "shard_id", "error"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_FULL_WITNESS_SENT_BYTES: LazyLock<IntCounterVec> = LazyLock::new(
+    || {
+        try_create_int_counter_vec(
+            "near_partial_witness_full_witness_sent_bytes",
+            "Total size of the full witnesses sent directly to the chunk validators with the highest stake",
+            &["shard_id"],
+        )
+        .unwrap()
+    },
+);
+
+pub(crate) static PARTIAL_WITNESS_FULL_WITNESS_RECEIVED: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_full_witness_received",
+            "Number of full witnesses received directly from the chunk producer. The outcome label \
+            is 'used' if the witness was passed to the client and 'redundant' if it was already \
+            reconstructed from the parts",
+            &["shard_id", "outcome"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_UNCONSUMED_WITNESSES: LazyLock<IntCounterVec> = LazyLock::new(
+    || {
+        try_create_int_counter_vec(
+            "near_partial_witness_unconsumed_witnesses",
+            "Number of witnesses sent to the client for which the client didn't confirm the consumption in time",
+            &["shard_id"],
+        )
+        .unwrap()
+    },
+);
+
+pub(crate) static PARTIAL_WITNESS_REED_SOLOMON_BACKEND: LazyLock<IntGaugeVec> =
+    LazyLock::new(|| {
+        try_create_int_gauge_vec(
+            "near_partial_witness_reed_solomon_backend",
+            "Reed Solomon implementation used for the witness parts, set to 1 for the active one",
+            &["backend"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DECODE_PARITY_PARTS: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_partial_witness_decode_parity_parts",
+            "Number of parity parts among the parts used to reconstruct the state witness. \
+            Zero means that all the data parts arrived and no reconstruction was needed",
+            &["shard_id"],
+            Some(vec![0.0, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0]),
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_NOT_THIS_CHUNKS_PRODUCER: LazyLock<IntCounterVec> = LazyLock::new(
+    || {
+        try_create_int_counter_vec(
+            "near_partial_witness_not_this_chunks_producer",
+            "Number of requests to distribute the witness of a chunk produced by another validator. \
+            The client never sends such requests legitimately, so any value above zero is a bug",
+            &["shard_id"],
+        )
+        .unwrap()
+    },
+);
+
+pub(crate) static PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_BYTES: LazyLock<IntGauge> =
+    LazyLock::new(|| {
+        try_create_int_gauge(
+            "near_partial_witness_distribution_requests_bytes",
+            "Total size of the state witnesses held by the distribution requests sent to the \
+            partial witness actor which were not processed yet",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_STALE_DISTRIBUTION_REQUESTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_stale_distribution_requests",
+            "Number of witness distribution requests dropped because the chunk height was \
+            already below the final head by the time the request was processed",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DISTRIBUTION_REQUEST_DELAY: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_partial_witness_distribution_request_delay",
+            "Time in seconds from the chunk production to the witness distribution request \
+            reaching the partial witness actor, i.e. the time spent on the client side",
+            &["shard_id"],
+            Some(exponential_buckets(0.001, 2.0, 14).unwrap()),
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_RECONSTRUCTED_WITNESSES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_reconstructed_witnesses",
+            "Number of witnesses reconstructed from the parts and sent to the client, by whether \
+            the block the chunk is built on was already known at that time",
+            &["shard_id", "prev_block"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_STALE_SIGNER_DROPS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_stale_signer_drops",
+            "Number of witnesses whose parts were dropped instead of sent because the validator \
+            signer changed while the parts were encoded and signed",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PRODUCED_PART_REQUESTS_SERVED: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_produced_part_requests_served",
+            "Number of requests for a witness part answered by the chunk producer which doesn't \
+            own the requested part",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_MISSING_PART_REQUESTS_SENT: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_missing_part_requests_sent",
+            "Number of requests for the witness parts still missing past \
+            missing_part_request_delay, sent to the owners of the parts",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_WRONG_PRODUCER: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_wrong_producer",
+        "Number of witness parts signed with the valid key of a validator which is not the chunk \
+        producer of the chunk, by the validator which signed them",
+        &["account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_CHECKSUM_MISMATCHES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_checksum_mismatches",
+            "Number of witnesses reconstructed from the parts which don't match the witness hash \
+            signed by the chunk producer",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_MEMORY_PRESSURE_FREED_BYTES: LazyLock<IntCounter> =
+    LazyLock::new(|| {
+        try_create_int_counter(
+            "near_partial_witness_memory_pressure_freed_bytes",
+            "Total size of the witness parts dropped by the partial witness actor when asked to \
+            reduce its memory usage",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_WARMED_UP: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_warmed_up",
+        "Whether the partial witness actor prepared the chunk validator assignments and the Reed \
+        Solomon encoders for the heights after the head since the node started",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_SUPPRESSED_FORWARDS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_suppressed_forwards",
+            "Number of owned witness parts not forwarded to the other chunk validators because \
+            the shard is excluded from forwarding in the config",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_OUTCOMES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_outcomes",
+        "Number of witnesses sent to the client by the partial witness actor, by the outcome \
+        reported by the client: endorsed, validation failed, deadline missed, orphaned, declined \
+        late or pre-tracked",
+        &["outcome"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_SIGNATURE_VERIFICATIONS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_signature_verifications",
+            "Number of signature verifications of the received witness parts, by the kind of \
+            the part: owned or forward",
+            &["kind"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SIGNATURE_VERIFICATIONS_PER_SECOND: LazyLock<Gauge> =
+    LazyLock::new(|| {
+        try_create_gauge(
+            "near_partial_witness_signature_verifications_per_second",
+            "Number of signature verifications of the received witness parts per second, \
+            averaged over the last load window",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SIGNATURE_VERIFICATION_COST: LazyLock<Gauge> =
+    LazyLock::new(|| {
+        try_create_gauge(
+            "near_partial_witness_signature_verification_cost",
+            "Estimated CPU time in seconds of a single signature verification of a witness part, \
+            smoothed over the sampled verifications",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SIGNATURE_VERIFICATION_UTILIZATION: LazyLock<Gauge> =
+    LazyLock::new(|| {
+        try_create_gauge(
+            "near_partial_witness_signature_verification_utilization",
+            "Estimated fraction of one core spent on the signature verifications of the witness \
+            parts over the last load window",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DELIVERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_deliveries",
+        "Number of witness messages sent to the chunk validators as reported by the network, by \
+        the requested routing preference and the path used: direct, routed, or failed if the \
+        message couldn't be sent",
+        &["preference", "path"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_EXPIRED_WITNESSES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_expired_witnesses",
+            "Number of incomplete witnesses abandoned because they were not decoded before \
+            their deadline",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PARTS_AFTER_DEADLINE: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_parts_after_deadline",
+            "Number of witness parts dropped because they arrived after the witness was abandoned \
+            past its deadline",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DISTRIBUTION_EPOCH_MISMATCHES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_distribution_epoch_mismatches",
+            "Number of witness distribution requests whose epoch differs from the epoch resolved \
+            from the prev block of the chunk. Any value above zero is a bug in the client",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_WORST_PRODUCER_DECODE_RATIO: LazyLock<Gauge> =
+    LazyLock::new(|| {
+        try_create_gauge(
+            "near_partial_witness_worst_producer_decode_ratio",
+            "Lowest fraction of the expected witnesses decoded from a single chunk producer over \
+            the last epoch",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_WORST_PRODUCER_FIRST_PART_LATENCY: LazyLock<Gauge> =
+    LazyLock::new(|| {
+        try_create_gauge(
+            "near_partial_witness_worst_producer_first_part_latency",
+            "Highest average latency in seconds of the first witness part of a single chunk \
+            producer relative to the expected production time of the chunk, over the last epoch",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DECODED_LATE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_decoded_late_total",
+        "Number of witnesses decoded after the block at their height was produced, too late to \
+        be endorsed. Every such witness received by the client is counted in \
+        near_chunk_validator_endorsements_declined_late_total",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DECODED_LATENESS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    try_create_histogram_vec(
+        "near_partial_witness_decoded_lateness",
+        "Time in seconds between the production of the block at the height of the witness and \
+        the decoding of the witness, for the witnesses decoded too late to be endorsed",
+        &["shard_id"],
+        Some(exponential_buckets(0.01, 2.0, 12).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static CHUNK_VALIDATOR_ENDORSEMENTS_DECLINED_LATE: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_chunk_validator_endorsements_declined_late_total",
+            "Number of chunks the chunk validator declined to endorse because the witness was \
+            decoded after the block at its height was produced, see \
+            near_partial_witness_decoded_late_total",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_UNAVAILABLE_OWNER_PARTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_unavailable_owner_parts_total",
+            "Number of produced witness parts sent directly to all the chunk validators instead of \
+            their owner, because the owner announced that it can't receive them",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DECODES_PAST_DEADLINE: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_decodes_past_deadline_total",
+            "Number of witnesses which had enough parts to be decoded, but were dropped without \
+            decoding because they reached their deadline while waiting for the decode",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SEND_SKEW: LazyLock<HistogramVec> = LazyLock::new(|| {
+    try_create_histogram_vec(
+        "near_partial_witness_send_skew",
+        "Time between the send timestamp signed by the chunk producer into the witness part sent \
+        to us and the receipt of the part, including the clock skew. Negative values are \
+        clamped to zero",
+        &["shard_id"],
+        Some(exponential_buckets(0.001, 2.0, 14).unwrap()),
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_NEGATIVE_SEND_SKEW: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_negative_send_skew_total",
+            "Number of witness parts received before the send timestamp signed by the chunk \
+            producer, i.e. with the clock of the chunk producer ahead of ours",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_ACTIVE_DECODES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_active_decodes",
+        "Number of witnesses being reconstructed from their parts and decompressed",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_QUEUED_DECODES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_queued_decodes",
+        "Number of witnesses with enough parts waiting for a free slot to be decoded, see \
+        max_concurrent_witness_decodes",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_OWNED_PART_FIRST_DELIVERY: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_owned_part_first_delivery_total",
+            "Number of witnesses in which our own part arrived first in the message sent directly \
+            by the chunk producer (direct) or in a forward (forward)",
+            &["shard_id", "delivery"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_UNTRACKED_VALIDATED_SHARDS: LazyLock<IntGauge> =
+    LazyLock::new(|| {
+        try_create_int_gauge(
+            "near_partial_witness_untracked_validated_shards",
+            "Number of shards this node is a chunk validator of in the epoch of the head but \
+            doesn't track, so it can't validate their witnesses. Should be zero",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_LINK_LOSS_PEERS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    try_create_int_gauge_vec(
+        "near_partial_witness_link_loss_peers",
+        "Number of peers by the estimated loss rate of the forwarded witness parts they deliver, \
+        over the recently settled chunks",
+        &["loss_bucket"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_STATS_EXPORT_DROPPED: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_stats_export_dropped_total",
+            "Number of witness stats records not exported, because the export thread was behind \
+            (queue_full), stopped on an error (export_stopped) or the record didn't fit under the \
+            disk usage cap (disk_usage_cap)",
+            &["reason"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_CONFLICT_EVIDENCE: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_conflict_evidence_total",
+            "Number of witnesses for which the chunk producer signed parts with conflicting \
+            metadata, e.g. encoded length or witness hash. Should be zero",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_CHUNK_VALIDATORS_MISMATCHES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_chunk_validators_mismatches_total",
+            "Number of witnesses whose chunk producer signed a view of the chunk validators, \
+            i.e. their number and order, different from ours. Should be zero",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_CORRUPTED_PARTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_corrupted_parts_total",
+        "Number of witness parts identified as corrupted, because the witness was only \
+            reconstructed once the part was excluded, by the message in which the part arrived",
+        &["shard_id", "delivery"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_VALIDATION_LAG: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    try_create_int_gauge_vec(
+        "near_partial_witness_validation_lag",
+        "Number of the heads above the highest height at which the chunk validator duty of \
+        the node is done, i.e. the witness was sent to the client or the node isn't a chunk \
+        validator of the chunk, at which the node is a chunk validator of the chunk",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_ACKS_SENT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_acks_sent_total",
+        "Number of witness acks sent to the chunk producers, by whether they were sent alone \
+        or batched with other acks to the same chunk producer",
+        &["message"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_DROPPED: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_distribution_requests_dropped_total",
+            "Number of requests to distribute the witnesses of the chunks produced by us which \
+            were dropped without sending any part, by reason",
+            &["reason"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PARTS_DROPPED_DURING_SYNC: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_parts_dropped_during_sync_total",
+            "Number of witness parts received while the node was syncing the chain which were \
+            dropped instead of being handled once the sync is done, by the message in which the \
+            part arrived",
+            &["delivery"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_REDUNDANT_DECODES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_redundant_decodes_total",
+            "Number of witnesses delivered both in full and in parts, by the path which was \
+            redundant because the witness was already decoded from the other one",
+            &["shard_id", "path"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DECODE_CONFLICTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_decode_conflicts_total",
+        "Number of witnesses whose full witness and parts carry different witnesses, by the \
+            path which delivered the witness after the other one was decoded. Should be zero",
+        &["shard_id", "path"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_OVERSIZED_WITNESSES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_oversized_witnesses_total",
+            "Number of witnesses above the size limit which were accepted within the grace band \
+            before the limit increase",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PRIORITIZATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_prioritizations_total",
+        "Number of times a witness prioritized by the client was found in a processing lane of \
+        the actor, by the lane and by whether the prioritization changed the order of the lane",
+        &["lane", "outcome"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_ACK_PARTS_RECEIVED_AT_DECODE: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_partial_witness_ack_parts_received_at_decode",
+            "Number of parts the chunk validators reported in their acks to have received when \
+            they decoded the witnesses produced by us, by the path the witness was decoded from",
+            &["shard_id", "path"],
+            Some(vec![0.0, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0]),
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_ACKS_BELOW_DATA_PARTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_acks_below_data_parts_total",
+            "Number of acks of the witnesses produced by us whose chunk validator had received \
+            fewer parts than the data parts when it decoded the witness, i.e. the parts alone were \
+            not enough and only the full witness made it decode in time",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_HEIGHT_WINDOW_ADMISSIONS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_height_window_admissions_total",
+            "Number of validated witness parts by the tip whose height window admitted them, the \
+            head or the highest known tip of another fork",
+            &["shard_id", "context"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_HEIGHT_WINDOW_REEVALUATIONS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_height_window_reevaluations_total",
+            "Number of witnesses admitted by the alternative tip which were admitted by the head \
+            or dropped after a head update",
+            &["shard_id", "outcome"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DUPLICATE_PARTS_DROPPED: LazyLock<IntCounter> =
+    LazyLock::new(|| {
+        try_create_int_counter(
+            "near_partial_witness_duplicate_parts_dropped_total",
+            "Number of forwarded witness parts dropped before the validation because the exact \
+            same part was already received, or the witness was already decoded from the parts",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_FORWARD_BACKOFF_TRANSITIONS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_forward_backoff_transitions_total",
+            "Number of times the forwards of our parts of a shard started or stopped backing off \
+            because of the failure rate of the sends to their targets",
+            &["shard_id", "transition"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_FORWARD_BACKOFF_ENGAGED: LazyLock<IntGaugeVec> =
+    LazyLock::new(|| {
+        try_create_int_gauge_vec(
+            "near_partial_witness_forward_backoff_engaged",
+            "Whether the forwards of our parts of a shard currently back off",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_LOCAL_DELIVERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_local_deliveries_total",
+        "Number of witnesses produced by us delivered straight to our client, because we are the \
+        only chunk validator of the chunk",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DECODE_ATTEMPTS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    try_create_histogram_vec(
+        "near_partial_witness_decode_attempts",
+        "Number of attempts it took to decode the witnesses reconstructed from the parts. A failed \
+        attempt is retried once another part arrives",
+        &["shard_id"],
+        Some(vec![1.0, 2.0, 3.0, 4.0, 8.0, 16.0]),
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DECODED_AFTER_RETRY: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_decoded_after_retry_total",
+            "Number of witnesses reconstructed from the parts only after more than one decode \
+            attempt, i.e. the first attempt failed and more parts were needed",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_ACKS_DECODED_AFTER_RETRY: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_acks_decoded_after_retry_total",
+            "Number of acks of the witnesses produced by us whose chunk validator needed more than \
+            one attempt to decode the witness",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_RETAINED_EPOCHS: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_retained_epochs",
+        "Number of distinct epochs the partial witness actor holds data for, bounded to the \
+        epochs around the head",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_EPOCH_SCOPED_BYTES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_epoch_scoped_bytes",
+        "Approximate memory used by the data of the partial witness actor scoped to the retained \
+        epochs",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_ACK_THRESHOLD_MISSES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_ack_threshold_misses_total",
+            "Number of witnesses produced by us whose chunk validators holding two thirds of the \
+            stake didn't ack them by the ack threshold deadline",
+            &["shard_id"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_ACKED_STAKE_AT_MISS: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_partial_witness_acked_stake_at_miss",
+            "Fraction of the stake of the chunk validators which acked the witnesses produced by \
+            us that missed the ack threshold deadline",
+            &["shard_id"],
+            Some(vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.67]),
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_MISSING_ACKS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_missing_acks_total",
+        "Number of witnesses produced by us which the chunk validator didn't ack by the ack \
+        threshold deadline, while the acked stake was below two thirds",
+        &["account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_PREVIOUS_FORMAT_PARTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_previous_format_parts_total",
+            "Number of witness parts accepted in the format of a protocol version before the one \
+            of their epoch",
+            &["format"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_DECODED_KEY_MISMATCHES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_decoded_key_mismatches_total",
+            "Number of witnesses decoded from the parts which declare another epoch, shard or \
+            height than the parts were signed for, by mismatched field. Should be zero",
+            &["shard_id", "field"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_HANDLER_PANICS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_handler_panics_total",
+        "Number of panics in the handlers of the partial witness actor, by type of the message \
+        handled. Should be zero",
+        &["message_type"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_FRAGMENTED_PARTS: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_partial_witness_fragmented_parts_total",
+        "Number of witness parts sent split into fragments, as they exceed the max payload of a \
+        routed message",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DROPPED_FRAGMENTED_PARTS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_dropped_fragmented_parts_total",
+            "Number of witness parts dropped before all their fragments were received, by reason",
+            &["reason"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PART_FRAGMENTS_BYTES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_part_fragments_bytes",
+        "Bytes of the fragments of the witness parts held until all the fragments of their part \
+        are received",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_PROCESSING_YIELDS: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_processing_yields_total",
+            "Number of times a loop of the partial witness actor ran out of its time slice and \
+        yielded to the mailbox before finishing, by loop",
+            &["loop"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_REDELIVERIES_SKIPPED: LazyLock<IntCounter> =
+    LazyLock::new(|| {
+        try_create_int_counter(
+            "near_partial_witness_redeliveries_skipped_total",
+            "Number of decoded witnesses not sent to the client, as a previous instance of the \
+        partial witness actor already delivered them",
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_HELD_WITNESSES: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_held_witnesses",
+        "Number of decoded witnesses held by the partial witness actor while the client has no \
+        credits left",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_HELD_WITNESS_RELEASES: LazyLock<IntCounterVec> =
+    LazyLock::new(|| {
+        try_create_int_counter_vec(
+            "near_partial_witness_held_witness_releases_total",
+            "Number of decoded witnesses which left the held ones, by whether they were pushed to \
+        the client, pulled by it, or dropped",
+            &["release"],
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_PART_FRAMING_RATIO: LazyLock<HistogramVec> =
+    LazyLock::new(|| {
+        try_create_histogram_vec(
+            "near_partial_witness_part_framing_ratio",
+            "Serialized size of the witness parts sent to the network divided by the size of \
+            their payload",
+            &["shard_id"],
+            Some(vec![1.0, 1.001, 1.005, 1.01, 1.02, 1.05, 1.1, 1.25, 1.5, 2.0, 4.0]),
+        )
+        .unwrap()
+    });
+
+pub(crate) static PARTIAL_WITNESS_SENT_PART_BYTES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_sent_part_bytes_total",
+        "Bytes of the witness parts sent to the network, split into the payload of the parts and \
+        the framing around it",
+        &["shard_id", "component"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_UNKNOWN_HEAD_EPOCHS: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_partial_witness_unknown_head_epochs",
+        "Number of the epochs of the head, the current and the next one, which the epoch manager \
+        of the partial witness actor doesn't know yet",
+    )
+    .unwrap()
+});
+
+pub(crate) static PARTIAL_WITNESS_DEFERRED_PARTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_partial_witness_deferred_parts_total",
+        "Number of the witness parts deferred while the epoch manager didn't know their epoch, \
+        by whether they were deferred, accepted or rejected once validated again, or dropped",
+        &["outcome"],
     )
     .unwrap()
 });
@@ -1,7 +1,10 @@
 pub mod orphan_witness_handling;
 pub mod orphan_witness_pool;
 
-use crate::Client;
+use crate::stateless_validation::partial_witness::partial_witness_actor::{
+    ChunkStateWitnessOutcome, ChunkStateWitnessOutcomeMessage,
+};
+use crate::{metrics, Client};
 use itertools::Itertools;
 use near_async::futures::{AsyncComputationSpawner, AsyncComputationSpawnerExt};
 use near_async::messaging::{CanSend, Sender};
@@ -14,12 +17,15 @@ use near_chain_primitives::Error;
 use near_epoch_manager::EpochManagerAdapter;
 use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
 use near_o11y::log_assert;
+use near_o11y::metrics::int_label;
 use near_primitives::sharding::ShardChunkHeader;
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsement;
 use near_primitives::stateless_validation::state_witness::{
-    ChunkStateWitness, ChunkStateWitnessAck, ChunkStateWitnessSize,
+    ChunkStateWitness, ChunkStateWitnessSize,
 };
+use near_primitives::stateless_validation::ChunkProductionKey;
 use near_primitives::validator_signer::ValidatorSigner;
+use orphan_witness_handling::HandleOrphanWitnessOutcome;
 use orphan_witness_pool::OrphanStateWitnessPool;
 use std::sync::Arc;
 
@@ -41,6 +47,8 @@ pub struct ChunkValidator {
     runtime_adapter: Arc<dyn RuntimeAdapter>,
     orphan_witness_pool: OrphanStateWitnessPool,
     validation_spawner: Arc<dyn AsyncComputationSpawner>,
+    /// Reports the outcome of the witnesses to the PartialWitnessActor which reconstructed them.
+    witness_outcome_sender: Sender<ChunkStateWitnessOutcomeMessage>,
     main_state_transition_result_cache: chunk_validation::MainStateTransitionCache,
     /// If true, a chunk-witness validation error will lead to a panic.
     /// This is used for non-production environments, eg. mocknet and localnet,
@@ -56,6 +64,7 @@ impl ChunkValidator {
         runtime_adapter: Arc<dyn RuntimeAdapter>,
         orphan_witness_pool_size: usize,
         validation_spawner: Arc<dyn AsyncComputationSpawner>,
+        witness_outcome_sender: Sender<ChunkStateWitnessOutcomeMessage>,
         panic_on_validation_error: bool,
     ) -> Self {
         Self {
@@ -64,6 +73,7 @@ impl ChunkValidator {
             runtime_adapter,
             orphan_witness_pool: OrphanStateWitnessPool::new(orphan_witness_pool_size),
             validation_spawner,
+            witness_outcome_sender,
             main_state_transition_result_cache: chunk_validation::MainStateTransitionCache::default(
             ),
             panic_on_validation_error,
@@ -75,12 +85,14 @@ impl ChunkValidator {
     /// happens in a separate thread.
     /// The chunk is validated asynchronously, if you want to wait for the processing to finish
     /// you can use the `processing_done_tracker` argument (but it's optional, it's safe to pass None there).
+    /// If `send_endorsement` is false, the chunk is only validated and no endorsement is sent.
     fn start_validating_chunk(
         &self,
         state_witness: ChunkStateWitness,
         chain: &Chain,
         processing_done_tracker: Option<ProcessingDoneTracker>,
         signer: &Arc<ValidatorSigner>,
+        send_endorsement: bool,
     ) -> Result<(), Error> {
         let prev_block_hash = state_witness.chunk_header.prev_block_hash();
         let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(prev_block_hash)?;
@@ -98,6 +110,7 @@ impl ChunkValidator {
             self.runtime_adapter.as_ref(),
         )?;
 
+        let key = state_witness.chunk_production_key();
         let chunk_header = state_witness.chunk_header.clone();
         let network_sender = self.network_sender.clone();
         let epoch_manager = self.epoch_manager.clone();
@@ -124,12 +137,15 @@ impl ChunkValidator {
                 &chunk_header,
             ) {
                 Ok(()) => {
-                    send_chunk_endorsement_to_block_producers(
-                        &chunk_header,
-                        epoch_manager.as_ref(),
-                        signer,
-                        &network_sender,
-                    );
+                    if send_endorsement {
+                        send_chunk_endorsement_to_block_producers(
+                            &chunk_header,
+                            epoch_manager.as_ref(),
+                            signer,
+                            &network_sender,
+                        );
+                        self.report_outcome(key, ChunkStateWitnessOutcome::Endorsed);
+                    }
                     return Ok(());
                 }
                 Err(err) => {
@@ -149,6 +165,7 @@ impl ChunkValidator {
         let runtime_adapter = self.runtime_adapter.clone();
         let cache = self.main_state_transition_result_cache.clone();
         let signer = signer.clone();
+        let witness_outcome_sender = self.witness_outcome_sender.clone();
         self.validation_spawner.spawn("stateless_validation", move || {
             // processing_done_tracker must survive until the processing is finished.
             let _processing_done_tracker_capture: Option<ProcessingDoneTracker> =
@@ -162,12 +179,18 @@ impl ChunkValidator {
                 &cache,
             ) {
                 Ok(()) => {
-                    send_chunk_endorsement_to_block_producers(
-                        &chunk_header,
-                        epoch_manager.as_ref(),
-                        signer.as_ref(),
-                        &network_sender,
-                    );
+                    if send_endorsement {
+                        send_chunk_endorsement_to_block_producers(
+                            &chunk_header,
+                            epoch_manager.as_ref(),
+                            signer.as_ref(),
+                            &network_sender,
+                        );
+                        witness_outcome_sender.send(ChunkStateWitnessOutcomeMessage {
+                            key: key.clone(),
+                            outcome: ChunkStateWitnessOutcome::Endorsed,
+                        });
+                    }
                 }
                 Err(err) => {
                     if panic_on_validation_error {
@@ -175,12 +198,27 @@ impl ChunkValidator {
                     } else {
                         tracing::error!("Failed to validate chunk: {:?}", err);
                     }
+                    if send_endorsement {
+                        witness_outcome_sender.send(ChunkStateWitnessOutcomeMessage {
+                            key,
+                            outcome: ChunkStateWitnessOutcome::ValidationFailed,
+                        });
+                    }
                 }
             }
         });
         Ok(())
     }
 
+    /// Reports the outcome of the witness to the PartialWitnessActor.
+    pub(crate) fn report_outcome(
+        &self,
+        key: ChunkProductionKey,
+        outcome: ChunkStateWitnessOutcome,
+    ) {
+        self.witness_outcome_sender.send(ChunkStateWitnessOutcomeMessage { key, outcome });
+    }
+
     /// TESTING ONLY: Used to override the value of panic_on_validation_error, for example,
     /// when the chunks validation errors are expected when testing adversarial behavior and
     /// the test should not panic for the invalid chunks witnesses.
@@ -255,11 +293,6 @@ impl Client {
         );
         let signer = signer.unwrap();
 
-        // Send the acknowledgement for the state witness back to the chunk producer.
-        // This is currently used for network roundtrip time measurement, so we do not need to
-        // wait for validation to finish.
-        self.send_state_witness_ack(&witness, &signer);
-
         if self.config.save_latest_witnesses {
             self.chain.chain_store.save_latest_chunk_state_witness(&witness)?;
         }
@@ -273,34 +306,18 @@ impl Client {
             ),
             Err(Error::DBNotFoundErr(_)) => {
                 // Previous block isn't available at the moment, add this witness to the orphan pool.
-                self.handle_orphan_state_witness(witness, raw_witness_size)?;
+                let key = witness.chunk_production_key();
+                let outcome = self.handle_orphan_state_witness(witness, raw_witness_size)?;
+                tracing::debug!(target: "client", ?outcome, "Handled orphan chunk state witness");
+                if outcome != HandleOrphanWitnessOutcome::SavedToPool {
+                    self.chunk_validator.report_outcome(key, ChunkStateWitnessOutcome::Orphaned);
+                }
                 Ok(())
             }
             Err(err) => Err(err),
         }
     }
 
-    fn send_state_witness_ack(&self, witness: &ChunkStateWitness, signer: &Arc<ValidatorSigner>) {
-        // In production PartialWitnessActor does not forward a state witness to the chunk producer that
-        // produced the witness. However some tests bypass PartialWitnessActor, thus when a chunk producer
-        // receives its own state witness, we log a warning instead of panicking.
-        // TODO: Make sure all tests run with "test_features" and panic for non-test builds.
-        if signer.validator_id() == &witness.chunk_producer {
-            tracing::warn!(
-                "Validator {:?} received state witness from itself. Witness={:?}",
-                signer.validator_id(),
-                witness
-            );
-            return;
-        }
-        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-            NetworkRequests::ChunkStateWitnessAck(
-                witness.chunk_producer.clone(),
-                ChunkStateWitnessAck::new(witness),
-            ),
-        ));
-    }
-
     pub fn process_chunk_state_witness_with_prev_block(
         &mut self,
         witness: ChunkStateWitness,
@@ -316,11 +333,92 @@ impl Client {
             )));
         }
 
-        self.chunk_validator.start_validating_chunk(
+        let key = witness.chunk_production_key();
+        let result = self.chunk_validator.start_validating_chunk(
             witness,
             &self.chain,
             processing_done_tracker,
             signer,
-        )
+            true,
+        );
+        if result.is_err() {
+            self.chunk_validator.report_outcome(key, ChunkStateWitnessOutcome::ValidationFailed);
+        }
+        result
+    }
+
+    /// Validates a `ChunkStateWitness` reconstructed for a pre-tracked shard, i.e. a shard for which
+    /// we are not a chunk validator yet. This only warms up the caches, so we neither send an ack to
+    /// the chunk producer nor endorse the chunk. Orphan witnesses are dropped.
+    pub fn process_pre_tracked_chunk_state_witness(
+        &mut self,
+        witness: ChunkStateWitness,
+        signer: Option<Arc<ValidatorSigner>>,
+    ) -> Result<(), Error> {
+        let key = witness.chunk_production_key();
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?witness.chunk_header.chunk_hash(),
+            shard_id=key.shard_id,
+            "process_pre_tracked_chunk_state_witness",
+        );
+        let Some(signer) = signer else {
+            return Err(Error::NotAValidator(format!("process pre-tracked chunk state witness")));
+        };
+        let result = match self.chain.get_block(witness.chunk_header.prev_block_hash()) {
+            Ok(_) => self.chunk_validator.start_validating_chunk(
+                witness,
+                &self.chain,
+                None,
+                &signer,
+                false,
+            ),
+            Err(Error::DBNotFoundErr(_)) => {
+                self.chunk_validator.report_outcome(key, ChunkStateWitnessOutcome::Orphaned);
+                return Ok(());
+            }
+            Err(err) => Err(err),
+        };
+        let outcome = match result {
+            Ok(()) => ChunkStateWitnessOutcome::PreTracked,
+            Err(_) => ChunkStateWitnessOutcome::ValidationFailed,
+        };
+        self.chunk_validator.report_outcome(key, outcome);
+        result
+    }
+
+    /// Validates a `ChunkStateWitness` decoded after the block at its height was produced. The
+    /// endorsement can't make it into that block anymore, so we decline to send it, but still
+    /// validate the chunk to detect the invalid state transitions. Orphan witnesses are dropped.
+    pub fn process_late_chunk_state_witness(
+        &mut self,
+        witness: ChunkStateWitness,
+        signer: Option<Arc<ValidatorSigner>>,
+    ) -> Result<(), Error> {
+        let key = witness.chunk_production_key();
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?witness.chunk_header.chunk_hash(),
+            shard_id=key.shard_id,
+            "process_late_chunk_state_witness",
+        );
+        metrics::CHUNK_VALIDATOR_ENDORSEMENTS_DECLINED_LATE
+            .with_label_values(&[&int_label(key.shard_id)])
+            .inc();
+        self.chunk_validator.report_outcome(key, ChunkStateWitnessOutcome::DeclinedLate);
+        let Some(signer) = signer else {
+            return Err(Error::NotAValidator(format!("process late chunk state witness")));
+        };
+        match self.chain.get_block(witness.chunk_header.prev_block_hash()) {
+            Ok(_) => self.chunk_validator.start_validating_chunk(
+                witness,
+                &self.chain,
+                None,
+                &signer,
+                false,
+            ),
+            Err(Error::DBNotFoundErr(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
     }
 }
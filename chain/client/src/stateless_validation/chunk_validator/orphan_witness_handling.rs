@@ -5,6 +5,7 @@
 //! and it's kept in the pool until the required block arrives. Once the block
 //! arrives, all witnesses that were waiting for it can be processed.
 
+use crate::stateless_validation::partial_witness::partial_witness_actor::ChunkStateWitnessOutcome;
 use crate::Client;
 use near_chain::Block;
 use near_chain_primitives::Error;
@@ -73,7 +74,11 @@ impl Client {
 
         // Orphan witness is OK, save it to the pool
         tracing::debug!(target: "client", "Saving an orphaned ChunkStateWitness to orphan pool");
-        self.chunk_validator.orphan_witness_pool.add_orphan_state_witness(witness, witness_size);
+        if let Some(ejected_key) =
+            self.chunk_validator.orphan_witness_pool.add_orphan_state_witness(witness, witness_size)
+        {
+            self.chunk_validator.report_outcome(ejected_key, ChunkStateWitnessOutcome::Orphaned);
+        }
         Ok(HandleOrphanWitnessOutcome::SavedToPool)
     }
 
@@ -131,9 +136,13 @@ impl Client {
                 return;
             }
         };
-        self.chunk_validator
+        let removed_keys = self
+            .chunk_validator
             .orphan_witness_pool
             .remove_witnesses_below_final_height(last_final_block.height());
+        for key in removed_keys {
+            self.chunk_validator.report_outcome(key, ChunkStateWitnessOutcome::DeadlineMissed);
+        }
     }
 }
 
@@ -45,23 +45,33 @@ impl OrphanStateWitnessPool {
     /// shard_id, size, epoch_id and distance from the tip. The pool would still work without it, but without
     /// validation it'd be possible to fill the whole cache with spam.
     /// `witness_size` is only used for metrics, it's okay to pass 0 if you don't care about the metrics.
-    pub fn add_orphan_state_witness(&mut self, witness: ChunkStateWitness, witness_size: usize) {
+    /// Returns the key of the witness ejected from the pool to make room for this one, if any.
+    pub fn add_orphan_state_witness(
+        &mut self,
+        witness: ChunkStateWitness,
+        witness_size: usize,
+    ) -> Option<ChunkProductionKey> {
         // Insert the new ChunkStateWitness into the cache
         let cache_key = witness.chunk_production_key();
         let metrics_tracker = OrphanWitnessMetricsTracker::new(&witness, witness_size);
         let cache_entry = CacheEntry { witness, _metrics_tracker: metrics_tracker };
-        if let Some((_, ejected_entry)) = self.witness_cache.push(cache_key, cache_entry) {
-            // Another witness has been ejected from the cache due to capacity limit
-            let header = &ejected_entry.witness.chunk_header;
-            tracing::debug!(
-                target: "client",
-                ejected_witness_height = header.height_created(),
-                ejected_witness_shard = header.shard_id(),
-                ejected_witness_chunk = ?header.chunk_hash(),
-                ejected_witness_prev_block = ?header.prev_block_hash(),
-                "Ejecting an orphaned ChunkStateWitness from the cache due to capacity limit. It will not be processed."
-            );
+        let (ejected_key, ejected_entry) =
+            self.witness_cache.push(cache_key.clone(), cache_entry)?;
+        if ejected_key == cache_key {
+            // The witness replaced an older witness for the same chunk.
+            return None;
         }
+        // Another witness has been ejected from the cache due to capacity limit
+        let header = &ejected_entry.witness.chunk_header;
+        tracing::debug!(
+            target: "client",
+            ejected_witness_height = header.height_created(),
+            ejected_witness_shard = header.shard_id(),
+            ejected_witness_chunk = ?header.chunk_hash(),
+            ejected_witness_prev_block = ?header.prev_block_hash(),
+            "Ejecting an orphaned ChunkStateWitness from the cache due to capacity limit. It will not be processed."
+        );
+        Some(ejected_key)
     }
 
     /// Find all orphaned witnesses that were waiting for this block and remove them from the pool.
@@ -90,7 +100,11 @@ impl OrphanStateWitnessPool {
     /// Remove all witnesses below the given height from the pool.
     /// Orphan witnesses below the final height of the chain won't be needed anymore,
     /// so they can be removed from the pool to free up memory.
-    pub fn remove_witnesses_below_final_height(&mut self, final_height: BlockHeight) {
+    /// Returns the keys of the removed witnesses.
+    pub fn remove_witnesses_below_final_height(
+        &mut self,
+        final_height: BlockHeight,
+    ) -> Vec<ChunkProductionKey> {
         let mut to_remove: Vec<ChunkProductionKey> = Vec::new();
         for (cache_key, cache_entry) in self.witness_cache.iter() {
             let witness_height = cache_key.height_created;
@@ -108,10 +122,11 @@ impl OrphanStateWitnessPool {
                     the final height of the chain. It will not be processed.");
             }
         }
-        for cache_key in to_remove {
-            let popped = self.witness_cache.pop(&cache_key);
+        for cache_key in &to_remove {
+            let popped = self.witness_cache.pop(cache_key);
             debug_assert!(popped.is_some());
         }
+        to_remove
     }
 }
 
@@ -122,6 +137,9 @@ impl Default for OrphanStateWitnessPool {
 }
 
 mod metrics_tracker {
+    use std::borrow::Cow;
+
+    use near_o11y::metrics::int_label;
     use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
 
     use crate::metrics;
@@ -132,7 +150,7 @@ mod metrics_tracker {
     /// removes the witness from metrics.
     /// Using this struct is much less error-prone than adjusting the metrics by hand.
     pub struct OrphanWitnessMetricsTracker {
-        shard_id: String,
+        shard_id: Cow<'static, str>,
         witness_size: usize,
     }
 
@@ -141,15 +159,11 @@ mod metrics_tracker {
             witness: &ChunkStateWitness,
             witness_size: usize,
         ) -> OrphanWitnessMetricsTracker {
-            let shard_id = witness.chunk_header.shard_id().to_string();
-            metrics::ORPHAN_CHUNK_STATE_WITNESSES_TOTAL_COUNT
-                .with_label_values(&[shard_id.as_str()])
-                .inc();
-            metrics::ORPHAN_CHUNK_STATE_WITNESS_POOL_SIZE
-                .with_label_values(&[shard_id.as_str()])
-                .inc();
+            let shard_id = int_label(witness.chunk_header.shard_id());
+            metrics::ORPHAN_CHUNK_STATE_WITNESSES_TOTAL_COUNT.with_label_values(&[&shard_id]).inc();
+            metrics::ORPHAN_CHUNK_STATE_WITNESS_POOL_SIZE.with_label_values(&[&shard_id]).inc();
             metrics::ORPHAN_CHUNK_STATE_WITNESS_POOL_MEMORY_USED
-                .with_label_values(&[shard_id.as_str()])
+                .with_label_values(&[&shard_id])
                 .add(witness_size_to_i64(witness_size));
 
             OrphanWitnessMetricsTracker { shard_id, witness_size }
@@ -159,10 +173,10 @@ mod metrics_tracker {
     impl Drop for OrphanWitnessMetricsTracker {
         fn drop(&mut self) {
             metrics::ORPHAN_CHUNK_STATE_WITNESS_POOL_SIZE
-                .with_label_values(&[self.shard_id.as_str()])
+                .with_label_values(&[&self.shard_id])
                 .dec();
             metrics::ORPHAN_CHUNK_STATE_WITNESS_POOL_MEMORY_USED
-                .with_label_values(&[self.shard_id.as_str()])
+                .with_label_values(&[&self.shard_id])
                 .sub(witness_size_to_i64(self.witness_size));
         }
     }
@@ -315,11 +329,12 @@ mod tests {
         let witness2 = make_witness(101, 1, block(100), 0);
         let witness3 = make_witness(101, 2, block(100), 0);
 
-        pool.add_orphan_state_witness(witness1, 0);
-        pool.add_orphan_state_witness(witness2.clone(), 0);
+        let witness1_key = witness1.chunk_production_key();
+        assert_eq!(pool.add_orphan_state_witness(witness1, 0), None);
+        assert_eq!(pool.add_orphan_state_witness(witness2.clone(), 0), None);
 
         // Inserting the third witness causes the pool to go over capacity, so witness1 should be ejected.
-        pool.add_orphan_state_witness(witness3.clone(), 0);
+        assert_eq!(pool.add_orphan_state_witness(witness3.clone(), 0), Some(witness1_key));
 
         let waiting_for_100 = pool.take_state_witnesses_waiting_for_block(&block(100));
         assert_contents(waiting_for_100, vec![witness2, witness3]);
@@ -356,6 +371,8 @@ mod tests {
         let witness3 = make_witness(102, 1, block(101), 0);
         let witness4 = make_witness(103, 1, block(102), 0);
 
+        let expected_removed_keys =
+            vec![witness1.chunk_production_key(), witness3.chunk_production_key()];
         pool.add_orphan_state_witness(witness1, 0);
         pool.add_orphan_state_witness(witness2.clone(), 0);
         pool.add_orphan_state_witness(witness3, 0);
@@ -364,7 +381,9 @@ mod tests {
         let waiting_for_100 = pool.take_state_witnesses_waiting_for_block(&block(100));
         assert_contents(waiting_for_100, vec![witness2]);
 
-        pool.remove_witnesses_below_final_height(102);
+        let mut removed_keys = pool.remove_witnesses_below_final_height(102);
+        removed_keys.sort_by_key(|key| key.height_created);
+        assert_eq!(removed_keys, expected_removed_keys);
 
         let waiting_for_99 = pool.take_state_witnesses_waiting_for_block(&block(99));
         assert_contents(waiting_for_99, vec![]);
@@ -4,4 +4,4 @@ pub mod partial_witness;
 mod shadow_validate;
 mod state_witness_producer;
 pub mod state_witness_tracker;
-mod validate;
+pub(crate) mod validate;
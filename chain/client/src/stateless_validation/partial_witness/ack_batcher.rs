@@ -0,0 +1,166 @@
+//! Batching of the acks sent to the chunk producers, see `PartialWitnessConfig::ack_batching_delay`.
+//!
+//! The acks are tiny, but each of them pays the framing and queuing of a routed message of its
+//! own. Instead of sending the ack right after the reconstruction, the tracker holds it for up to
+//! the batching delay. All the acks to the same chunk producer pending once the oldest of them was
+//! held for the delay are sent together in a `BatchedChunkStateWitnessAck`, together with the time
+//! each of them was held, which the chunk producer subtracts from the round trip time.
+//!
+//! An ack which finds no other ack to the same chunk producer within the delay is sent alone as a
+//! plain `ChunkStateWitnessAck`. Its held time is not reported, so the round trip time measured by
+//! the chunk producer for it is longer by at most the delay, which is capped at
+//! `MAX_ACK_HELD_TIME`.
+
+use std::collections::HashMap;
+
+use near_async::time::{Duration, Instant};
+use near_primitives::stateless_validation::state_witness::{
+    HeldChunkStateWitnessAckV2, VersionedChunkStateWitnessAck, MAX_ACK_HELD_TIME,
+};
+use near_primitives::types::AccountId;
+
+/// Number of pending acks to a chunk producer at which they are sent without waiting for the
+/// delay, which bounds the size of a batch.
+const MAX_ACKS_PER_BATCH: usize = 64;
+
+pub struct AckBatcher {
+    delay: Duration,
+    /// Pending acks per chunk producer, with the time they became pending, the oldest first.
+    pending: HashMap<AccountId, Vec<(Instant, VersionedChunkStateWitnessAck)>>,
+}
+
+impl AckBatcher {
+    /// The delay is capped at `MAX_ACK_HELD_TIME`, zero disables the batching.
+    pub fn new(delay: Duration) -> Self {
+        Self { delay: delay.clamp(Duration::ZERO, MAX_ACK_HELD_TIME), pending: HashMap::new() }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.delay > Duration::ZERO
+    }
+
+    /// Holds the ack to the chunk producer. Returns the pending acks to the chunk producer if
+    /// they fill a batch, in which case they should be sent right away.
+    pub fn push(
+        &mut self,
+        chunk_producer: AccountId,
+        ack: VersionedChunkStateWitnessAck,
+        now: Instant,
+    ) -> Option<Vec<HeldChunkStateWitnessAckV2>> {
+        let acks = self.pending.entry(chunk_producer.clone()).or_default();
+        acks.push((now, ack));
+        if acks.len() < MAX_ACKS_PER_BATCH {
+            return None;
+        }
+        self.pending.remove(&chunk_producer).map(|acks| held_acks(acks, now))
+    }
+
+    /// Time at which the oldest pending ack reaches the delay.
+    pub fn next_flush_at(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .filter_map(|acks| acks.first())
+            .map(|(since, _)| *since)
+            .min()
+            .map(|since| since + self.delay)
+    }
+
+    /// Removes and returns the pending acks of the chunk producers whose oldest pending ack was
+    /// held for the delay by `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(AccountId, Vec<HeldChunkStateWitnessAckV2>)> {
+        let due: Vec<AccountId> = self
+            .pending
+            .iter()
+            .filter(|(_, acks)| acks.first().is_some_and(|(since, _)| *since + self.delay <= now))
+            .map(|(chunk_producer, _)| chunk_producer.clone())
+            .collect();
+        due.into_iter()
+            .map(|chunk_producer| {
+                let acks = self.pending.remove(&chunk_producer).unwrap();
+                (chunk_producer, held_acks(acks, now))
+            })
+            .collect()
+    }
+}
+
+fn held_acks(
+    acks: Vec<(Instant, VersionedChunkStateWitnessAck)>,
+    now: Instant,
+) -> Vec<HeldChunkStateWitnessAckV2> {
+    acks.into_iter()
+        .map(|(since, ack)| HeldChunkStateWitnessAckV2::new(ack, now.signed_duration_since(since)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::{FakeClock, Utc};
+    use near_primitives::hash::hash;
+    use near_primitives::sharding::ChunkHash;
+    use near_primitives::stateless_validation::state_witness::ChunkStateWitnessAck;
+
+    fn ack(i: u64) -> VersionedChunkStateWitnessAck {
+        ChunkStateWitnessAck { chunk_hash: ChunkHash(hash(&i.to_le_bytes())) }.into()
+    }
+
+    fn producer(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn acks_to_the_same_producer_are_coalesced() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut batcher = AckBatcher::new(Duration::milliseconds(10));
+        assert!(batcher.is_enabled());
+        let start = clock.now();
+        assert!(batcher.push(producer("alice"), ack(1), clock.now()).is_none());
+        clock.advance(Duration::milliseconds(4));
+        assert!(batcher.push(producer("bob"), ack(2), clock.now()).is_none());
+        clock.advance(Duration::milliseconds(3));
+        assert!(batcher.push(producer("alice"), ack(3), clock.now()).is_none());
+        assert_eq!(batcher.next_flush_at(), Some(start + Duration::milliseconds(10)));
+        assert!(batcher.take_due(clock.now()).is_empty());
+
+        // Alice's oldest ack reaches the delay, both of her acks are sent together.
+        clock.advance(Duration::milliseconds(3));
+        let due = batcher.take_due(clock.now());
+        assert_eq!(due.len(), 1);
+        let (chunk_producer, acks) = &due[0];
+        assert_eq!(chunk_producer, &producer("alice"));
+        assert_eq!(acks.iter().map(|held| held.ack.clone()).collect::<Vec<_>>(), [ack(1), ack(3)]);
+        assert_eq!(acks[0].held(), Duration::milliseconds(10));
+        assert_eq!(acks[1].held(), Duration::milliseconds(3));
+
+        // Bob's ack found no other ack within the delay.
+        assert_eq!(batcher.next_flush_at(), Some(start + Duration::milliseconds(14)));
+        clock.advance(Duration::milliseconds(4));
+        let due = batcher.take_due(clock.now());
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].1.len(), 1);
+        assert_eq!(due[0].1[0].held(), Duration::milliseconds(10));
+        assert_eq!(batcher.next_flush_at(), None);
+    }
+
+    #[test]
+    fn full_batch_is_sent_right_away() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut batcher = AckBatcher::new(Duration::milliseconds(10));
+        for i in 1..MAX_ACKS_PER_BATCH as u64 {
+            assert!(batcher.push(producer("alice"), ack(i), clock.now()).is_none());
+        }
+        let acks =
+            batcher.push(producer("alice"), ack(MAX_ACKS_PER_BATCH as u64), clock.now()).unwrap();
+        assert_eq!(acks.len(), MAX_ACKS_PER_BATCH);
+        assert_eq!(batcher.next_flush_at(), None);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        assert!(!AckBatcher::new(Duration::ZERO).is_enabled());
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut batcher = AckBatcher::new(Duration::seconds(1));
+        batcher.push(producer("alice"), ack(1), clock.now());
+        assert_eq!(batcher.next_flush_at(), Some(clock.now() + MAX_ACK_HELD_TIME));
+    }
+}
@@ -0,0 +1,144 @@
+//! Byzantine behaviors of the chunk producer distributing the witness parts, used to test that
+//! the honest chunk validators detect and handle them. Set with the `adv_witness_parts` RPC.
+
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::sharding::ShardChunkHeader;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, EpochId};
+use near_primitives::validator_signer::ValidatorSigner;
+
+/// How the chunk producer alters the parts of the witnesses it distributes. The altered parts
+/// are signed by the chunk producer, so they pass the signature checks of the receivers.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdvWitnessPartsMode {
+    /// Distribute the parts as usual.
+    Honest,
+    /// Flip all the bits of the part with the given part_ord.
+    CorruptPart(usize),
+    /// Neither send nor serve the parts with the given part_ords.
+    Withhold(Vec<usize>),
+    /// Send the honest part with the given part_ord to its owner, and a conflicting part with the
+    /// same part_ord directly to all the other chunk validators.
+    Equivocate(usize),
+    /// Shift the encoded length carried by all the parts by the given number of bytes.
+    WrongLength(i64),
+}
+
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct AdvWitnessPartsMessage(pub AdvWitnessPartsMode);
+
+/// Sends the parts of a witness produced by us again to all its chunk validators, regardless of
+/// when it was distributed. Set with the `adv_force_redistribute_witness` RPC. Returns the number
+/// of the parts sent, or an error if the parts are no longer retained by the chunk producer.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Result<usize, String>")]
+pub struct ForceRedistributeWitness {
+    pub chunk_production_key: ChunkProductionKey,
+}
+
+/// Alters the parts of a witness produced by us, given as (owner, part) ordered by part_ord.
+/// Returns the conflicting parts, as (owner, part), to send directly to the chunk validators which
+/// don't own them, see `AdvWitnessPartsMode::Equivocate`.
+pub(super) fn alter_witness_parts(
+    mode: &AdvWitnessPartsMode,
+    epoch_id: EpochId,
+    chunk_header: &ShardChunkHeader,
+    parts: &mut Vec<(AccountId, PartialEncodedStateWitness)>,
+    signer: &ValidatorSigner,
+) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+    match mode {
+        AdvWitnessPartsMode::Honest => vec![],
+        AdvWitnessPartsMode::CorruptPart(corrupt_ord) => {
+            for (_, partial_witness) in parts.iter_mut() {
+                if partial_witness.part_ord() == *corrupt_ord {
+                    *partial_witness =
+                        resign(partial_witness, epoch_id, chunk_header, signer, |part, _, _| {
+                            flip_bits(part)
+                        });
+                }
+            }
+            vec![]
+        }
+        AdvWitnessPartsMode::Withhold(withheld_ords) => {
+            parts.retain(|(_, partial_witness)| {
+                !withheld_ords.contains(&partial_witness.part_ord())
+            });
+            vec![]
+        }
+        AdvWitnessPartsMode::Equivocate(equivocated_ord) => parts
+            .iter()
+            .filter(|(_, partial_witness)| partial_witness.part_ord() == *equivocated_ord)
+            .map(|(owner, partial_witness)| {
+                let conflicting_part = resign(
+                    partial_witness,
+                    epoch_id,
+                    chunk_header,
+                    signer,
+                    |part, _, witness_hash| {
+                        flip_bits(part);
+                        *witness_hash =
+                            witness_hash.map(|witness_hash| hash(witness_hash.as_ref()));
+                    },
+                );
+                (owner.clone(), conflicting_part)
+            })
+            .collect(),
+        AdvWitnessPartsMode::WrongLength(delta) => {
+            for (_, partial_witness) in parts.iter_mut() {
+                *partial_witness = resign(
+                    partial_witness,
+                    epoch_id,
+                    chunk_header,
+                    signer,
+                    |_, encoded_length, _| {
+                        *encoded_length = encoded_length.saturating_add_signed(*delta as isize);
+                    },
+                );
+            }
+            vec![]
+        }
+    }
+}
+
+/// Signs again the part altered by `alter`, which is given the part, the encoded length and the
+/// witness hash.
+fn resign(
+    partial_witness: &PartialEncodedStateWitness,
+    epoch_id: EpochId,
+    chunk_header: &ShardChunkHeader,
+    signer: &ValidatorSigner,
+    alter: impl FnOnce(&mut Vec<u8>, &mut usize, &mut Option<CryptoHash>),
+) -> PartialEncodedStateWitness {
+    let mut witness_hash = partial_witness.witness_hash().copied();
+    let (part_ord, part, mut encoded_length) = partial_witness.clone().decompose();
+    let mut part = part.to_vec();
+    alter(&mut part, &mut encoded_length, &mut witness_hash);
+    match (partial_witness.owner(), partial_witness.sent_at()) {
+        (Some(owner), Some(sent_at)) => PartialEncodedStateWitness::new_v2(
+            epoch_id,
+            chunk_header.clone(),
+            part_ord,
+            owner.clone(),
+            part,
+            encoded_length,
+            witness_hash,
+            sent_at,
+            signer,
+        ),
+        _ => PartialEncodedStateWitness::new(
+            epoch_id,
+            chunk_header.clone(),
+            part_ord,
+            part,
+            encoded_length,
+            signer,
+        ),
+    }
+}
+
+fn flip_bits(part: &mut [u8]) {
+    part.iter_mut().for_each(|byte| *byte = !*byte);
+}
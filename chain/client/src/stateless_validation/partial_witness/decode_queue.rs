@@ -0,0 +1,88 @@
+//! Bounded parallelism of the witness decodes.
+//!
+//! Reconstructing a witness from its parts and decompressing it takes a sizable share of a core
+//! for the largest witnesses. The witnesses completed together, e.g. by a burst of parts across
+//! many shards after a network blip, are decoded in parallel, but on at most
+//! `PartialWitnessConfig::max_concurrent_witness_decodes` threads, so that the burst doesn't take
+//! all the cores from the chunk application running at the same time. The decodes waiting for a
+//! thread are queued in the order given, which is the newest witness first.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::metrics;
+
+/// Runs `decode` on all the jobs and returns the results in the order of the jobs. The jobs
+/// start in the order given, at most `max_concurrent` of them at once.
+pub fn decode_with_bounded_parallelism<J: Send, R: Send>(
+    max_concurrent: usize,
+    jobs: Vec<J>,
+    decode: impl Fn(J) -> R + Sync,
+) -> Vec<R> {
+    let num_jobs = jobs.len();
+    let queue = Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>());
+    let results = Mutex::new(Vec::with_capacity(num_jobs));
+    metrics::PARTIAL_WITNESS_QUEUED_DECODES.set(num_jobs as i64);
+    let worker = || loop {
+        let next_job = {
+            let mut queue = queue.lock().unwrap();
+            let next_job = queue.pop_front();
+            metrics::PARTIAL_WITNESS_QUEUED_DECODES.set(queue.len() as i64);
+            next_job
+        };
+        let Some((index, job)) = next_job else {
+            break;
+        };
+        metrics::PARTIAL_WITNESS_ACTIVE_DECODES.inc();
+        let result = decode(job);
+        metrics::PARTIAL_WITNESS_ACTIVE_DECODES.dec();
+        results.lock().unwrap().push((index, result));
+    };
+
+    let num_workers = max_concurrent.max(1).min(num_jobs);
+    if num_workers <= 1 {
+        // No need to spawn a thread for a single decode at a time.
+        worker();
+    } else {
+        std::thread::scope(|scope| {
+            for _ in 0..num_workers {
+                scope.spawn(&worker);
+            }
+        });
+    }
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::decode_with_bounded_parallelism;
+
+    #[test]
+    fn decodes_start_in_order_with_bounded_parallelism() {
+        let active = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let started = Mutex::new(vec![]);
+        let jobs = (0..16).collect::<Vec<usize>>();
+        let results = decode_with_bounded_parallelism(2, jobs, |job| {
+            started.lock().unwrap().push(job);
+            peak.fetch_max(active.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            active.fetch_sub(1, Ordering::SeqCst);
+            job * 2
+        });
+
+        assert_eq!(results, (0..16).map(|job| job * 2).collect::<Vec<_>>());
+        assert_eq!(peak.into_inner(), 2);
+        // Each decode starts once one of the two decodes before it finished, at most the two
+        // decodes taken from the queue at the same time may start in the reverse order.
+        for (position, job) in started.into_inner().unwrap().into_iter().enumerate() {
+            assert!(position.abs_diff(job) < 2, "decode {job} started at position {position}");
+        }
+    }
+}
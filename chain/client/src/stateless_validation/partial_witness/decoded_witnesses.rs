@@ -0,0 +1,205 @@
+//! Idempotency of the witness decoding when the same witness reaches a chunk validator both in
+//! full and in parts, see `PartialWitnessConfig::direct_full_witness_targets`.
+//!
+//! The first successful decode of the witness of a chunk wins, whether from the full witness or
+//! from the parts: only that witness is sent to the client and acked. The witness delivered by
+//! the other path afterwards is redundant and dropped, but it is still expected to be the same
+//! witness. If it isn't, or if it fails to decode, the chunk producer sent two different
+//! witnesses for the chunk, which is surfaced as a `WitnessDecodeConflict` instead of being
+//! resolved in favor of the first one without notice.
+
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_chain::Error;
+use near_o11y::metrics::int_label;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::ChunkProductionKey;
+
+use crate::metrics;
+
+/// Number of witnesses for which we keep the conflicts between the two decode paths.
+const DECODE_CONFLICTS_CACHE_SIZE: usize = 10;
+
+/// How the witness reached the chunk validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessDecodePath {
+    /// In full, sent directly by the chunk producer.
+    FullWitness,
+    /// Reconstructed from the parts.
+    Parts,
+}
+
+impl WitnessDecodePath {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WitnessDecodePath::FullWitness => "full_witness",
+            WitnessDecodePath::Parts => "parts",
+        }
+    }
+}
+
+/// The first successful decode of a witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedWitness {
+    pub path: WitnessDecodePath,
+    /// Hash of the borsh-serialized witness.
+    pub witness_hash: CryptoHash,
+}
+
+/// Witness delivered by the redundant path which differs from the witness decoded first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessDecodeConflict {
+    pub decoded: DecodedWitness,
+    pub conflicting_path: WitnessDecodePath,
+    /// Hash of the witness delivered by the redundant path, None if it failed to decode.
+    pub conflicting_witness_hash: Option<CryptoHash>,
+}
+
+struct DecodedEntry {
+    decoded: DecodedWitness,
+    /// Whether the other path was already counted as redundant.
+    redundant: bool,
+}
+
+pub struct DecodedWitnesses {
+    decoded: LruCache<ChunkProductionKey, DecodedEntry>,
+    conflicts: LruCache<ChunkProductionKey, WitnessDecodeConflict>,
+}
+
+impl DecodedWitnesses {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            decoded: LruCache::new(NonZeroUsize::new(capacity).unwrap()),
+            conflicts: LruCache::new(NonZeroUsize::new(DECODE_CONFLICTS_CACHE_SIZE).unwrap()),
+        }
+    }
+
+    pub fn get(&self, key: &ChunkProductionKey) -> Option<DecodedWitness> {
+        self.decoded.peek(key).map(|entry| entry.decoded)
+    }
+
+    /// Records the first successful decode of the witness, the later ones are redundant.
+    pub fn insert(&mut self, key: ChunkProductionKey, decoded: DecodedWitness) {
+        if self.decoded.contains(&key) {
+            return;
+        }
+        self.decoded.put(key, DecodedEntry { decoded, redundant: false });
+    }
+
+    /// Counts `path` as redundant for the witness decoded by the other path, once per witness.
+    pub fn mark_redundant(&mut self, key: &ChunkProductionKey, path: WitnessDecodePath) {
+        let Some(entry) = self.decoded.get_mut(key) else {
+            return;
+        };
+        if entry.decoded.path == path || entry.redundant {
+            return;
+        }
+        entry.redundant = true;
+        metrics::PARTIAL_WITNESS_REDUNDANT_DECODES
+            .with_label_values(&[&int_label(key.shard_id), path.as_str()])
+            .inc();
+    }
+
+    /// Checks the witness delivered by the redundant `path` against the witness decoded first.
+    /// `witness_hash` is the hash of the redundant witness, None if it failed to decode.
+    pub fn check_redundant(
+        &mut self,
+        key: &ChunkProductionKey,
+        path: WitnessDecodePath,
+        witness_hash: Option<CryptoHash>,
+    ) -> Result<(), Error> {
+        self.mark_redundant(key, path);
+        let Some(decoded) = self.get(key) else {
+            return Ok(());
+        };
+        if witness_hash == Some(decoded.witness_hash) {
+            return Ok(());
+        }
+        if !self.conflicts.contains(key) {
+            metrics::PARTIAL_WITNESS_DECODE_CONFLICTS
+                .with_label_values(&[&int_label(key.shard_id), path.as_str()])
+                .inc();
+            tracing::error!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                decoded_path = decoded.path.as_str(),
+                decoded_witness_hash = ?decoded.witness_hash,
+                conflicting_path = path.as_str(),
+                conflicting_witness_hash = ?witness_hash,
+                "Chunk producer sent different witnesses in full and in parts"
+            );
+            self.conflicts.put(
+                key.clone(),
+                WitnessDecodeConflict {
+                    decoded,
+                    conflicting_path: path,
+                    conflicting_witness_hash: witness_hash,
+                },
+            );
+        }
+        Err(Error::InvalidPartialChunkStateWitness(format!(
+            "Witness delivered by {} conflicts with the witness decoded from {}",
+            path.as_str(),
+            decoded.path.as_str(),
+        )))
+    }
+
+    pub fn conflict(&self, key: &ChunkProductionKey) -> Option<&WitnessDecodeConflict> {
+        self.conflicts.peek(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::types::EpochId;
+
+    use super::{DecodedWitness, DecodedWitnesses, WitnessDecodePath};
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id: 0, epoch_id: EpochId::default(), height_created }
+    }
+
+    fn decoded(path: WitnessDecodePath, witness_hash: CryptoHash) -> DecodedWitness {
+        DecodedWitness { path, witness_hash }
+    }
+
+    #[test]
+    fn first_decode_wins() {
+        let mut witnesses = DecodedWitnesses::new(10);
+        let witness_hash = hash(b"witness");
+        witnesses.insert(key(1), decoded(WitnessDecodePath::Parts, witness_hash));
+        witnesses.insert(key(1), decoded(WitnessDecodePath::FullWitness, hash(b"other")));
+        assert_eq!(witnesses.get(&key(1)), Some(decoded(WitnessDecodePath::Parts, witness_hash)));
+
+        // The same witness delivered in full is redundant.
+        assert!(witnesses
+            .check_redundant(&key(1), WitnessDecodePath::FullWitness, Some(witness_hash))
+            .is_ok());
+        assert!(witnesses.conflict(&key(1)).is_none());
+        assert_eq!(witnesses.get(&key(2)), None);
+    }
+
+    #[test]
+    fn different_witness_is_a_conflict() {
+        let mut witnesses = DecodedWitnesses::new(10);
+        let witness_hash = hash(b"witness");
+        witnesses.insert(key(1), decoded(WitnessDecodePath::FullWitness, witness_hash));
+        let other_hash = hash(b"other");
+        assert!(witnesses
+            .check_redundant(&key(1), WitnessDecodePath::Parts, Some(other_hash))
+            .is_err());
+        let conflict = witnesses.conflict(&key(1)).unwrap();
+        assert_eq!(conflict.decoded.path, WitnessDecodePath::FullWitness);
+        assert_eq!(conflict.conflicting_path, WitnessDecodePath::Parts);
+        assert_eq!(conflict.conflicting_witness_hash, Some(other_hash));
+
+        // A redundant witness which fails to decode conflicts as well.
+        witnesses.insert(key(2), decoded(WitnessDecodePath::Parts, witness_hash));
+        assert!(witnesses.check_redundant(&key(2), WitnessDecodePath::FullWitness, None).is_err());
+        assert_eq!(witnesses.conflict(&key(2)).unwrap().conflicting_witness_hash, None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Witnesses sent to the client, shared by the instances of the actor within the process, so that
+//! every witness is sent at most once even if the actor is restarted, e.g. rebuilt after a change
+//! of the signer.
+//!
+//! The caches of the tracker start empty after a restart, so a witness decoded right before it
+//! used to be decoded again from the parts still arriving and sent to the client a second time,
+//! which then validated it and tried to endorse it twice. Whoever builds the actor again hands
+//! the `DeliveredWitnesses` of the previous instance to the new one, see
+//! `PartialWitnessActor::set_delivered_witnesses`. The keys are consulted both when a part
+//! arrives and before a decoded witness is sent. They only matter within the process, as the
+//! client doesn't keep the witnesses across a restart of the node either, so they are kept in
+//! memory. The parts of the witnesses at or below the final head are rejected by the validation,
+//! so their keys are pruned as the heights finalize, and only `MAX_DELIVERED_WITNESSES` keys of
+//! the highest heights are kept.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+
+/// Maximum number of the delivered witnesses kept, the ones of the lowest heights are forgotten
+/// first. Much more than the witnesses of all the shards between the head and the final head.
+const MAX_DELIVERED_WITNESSES: usize = 4096;
+
+/// Ordered by height first, so that the lowest heights are forgotten first.
+type DeliveredKey = (BlockHeight, ShardId, EpochId);
+
+#[derive(Clone, Default)]
+pub struct DeliveredWitnesses {
+    keys: Arc<Mutex<BTreeSet<DeliveredKey>>>,
+}
+
+impl DeliveredWitnesses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, key: &ChunkProductionKey) -> bool {
+        self.keys.lock().unwrap().contains(&delivered_key(key))
+    }
+
+    /// Records that the witness is sent to the client, returns false if it already was.
+    pub fn insert(&self, key: &ChunkProductionKey) -> bool {
+        let mut keys = self.keys.lock().unwrap();
+        if !keys.insert(delivered_key(key)) {
+            return false;
+        }
+        while keys.len() > MAX_DELIVERED_WITNESSES {
+            keys.pop_first();
+        }
+        true
+    }
+
+    /// Forgets the witnesses at or below the final height.
+    pub fn prune_finalized(&self, final_height: BlockHeight) {
+        let mut keys = self.keys.lock().unwrap();
+        *keys = keys.split_off(&(final_height.saturating_add(1), 0, EpochId::default()));
+    }
+}
+
+fn delivered_key(key: &ChunkProductionKey) -> DeliveredKey {
+    (key.height_created, key.shard_id, key.epoch_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::CryptoHash;
+
+    use super::*;
+
+    fn key(height_created: BlockHeight, shard_id: ShardId) -> ChunkProductionKey {
+        ChunkProductionKey {
+            epoch_id: EpochId(CryptoHash::hash_bytes(b"epoch")),
+            shard_id,
+            height_created,
+        }
+    }
+
+    #[test]
+    fn delivered_witnesses_are_shared_until_finalized() {
+        let delivered = DeliveredWitnesses::new();
+        assert!(delivered.insert(&key(5, 0)));
+        assert!(delivered.insert(&key(5, 1)));
+        assert!(delivered.insert(&key(7, 0)));
+        assert!(!delivered.insert(&key(5, 0)));
+
+        let shared = delivered.clone();
+        assert!(shared.contains(&key(5, 1)));
+        assert!(!shared.contains(&key(6, 0)));
+
+        shared.prune_finalized(5);
+        assert!(!delivered.contains(&key(5, 0)));
+        assert!(!delivered.contains(&key(5, 1)));
+        assert!(delivered.contains(&key(7, 0)));
+    }
+
+    #[test]
+    fn lowest_heights_are_forgotten_first() {
+        let delivered = DeliveredWitnesses::new();
+        for height in 0..=MAX_DELIVERED_WITNESSES as BlockHeight {
+            delivered.insert(&key(height, 0));
+        }
+        assert!(!delivered.contains(&key(0, 0)));
+        assert!(delivered.contains(&key(1, 0)));
+        assert!(delivered.contains(&key(MAX_DELIVERED_WITNESSES as BlockHeight, 0)));
+    }
+}
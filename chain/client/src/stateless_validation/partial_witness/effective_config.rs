@@ -0,0 +1,152 @@
+//! Configuration of the witness distribution in effect on the node, exposed in the status RPC.
+//!
+//! The operators used to reconstruct it from config.json, the defaults of the options missing
+//! there and the protocol version of the epoch, which is error prone around a protocol upgrade.
+//! The actor builds `WitnessConfigView` from the config it was started with and the limits of the
+//! protocol version of the epoch of the head, and sends it to the client whenever the protocol
+//! version changes, see `WitnessConfigMessage`. The config holds no secrets, so it is exposed in
+//! full.
+
+use bytesize::ByteSize;
+use near_chain_configs::PartialWitnessConfig;
+use near_primitives::stateless_validation::partial_witness::WitnessSizeLimits;
+use near_primitives::version::{ProtocolFeature, ProtocolVersion};
+use near_primitives::views::{WitnessConfigView, WitnessProtocolLimitsView};
+
+use super::part_format::{PartFormat, PART_FORMAT_COMPATIBILITY_VERSIONS};
+
+pub fn witness_config_view(
+    config: &PartialWitnessConfig,
+    max_routed_message_payload: usize,
+    protocol_version: ProtocolVersion,
+) -> WitnessConfigView {
+    let partial_witness = serde_json::to_value(config).unwrap_or_else(|err| {
+        tracing::warn!(target: "client", ?err, "Failed to serialize the partial witness config");
+        serde_json::Value::Null
+    });
+    WitnessConfigView {
+        partial_witness,
+        max_routed_message_payload,
+        protocol_limits: protocol_limits_view(config, protocol_version),
+    }
+}
+
+fn protocol_limits_view(
+    config: &PartialWitnessConfig,
+    protocol_version: ProtocolVersion,
+) -> WitnessProtocolLimitsView {
+    let size_limits = WitnessSizeLimits::for_protocol_version(protocol_version);
+    let uncompressed_witness_threshold =
+        if ProtocolFeature::UncompressedSmallWitness.enabled(protocol_version) {
+            config.uncompressed_witness_threshold
+        } else {
+            ByteSize::b(0)
+        };
+    let oldest_accepted_version =
+        protocol_version.saturating_sub(PART_FORMAT_COMPATIBILITY_VERSIONS);
+    WitnessProtocolLimitsView {
+        protocol_version,
+        witness_size_limit: size_limits.limit.as_u64(),
+        witness_size_grace_percent: size_limits.grace_percent,
+        max_accepted_witness_size: size_limits.max_accepted_size().as_u64(),
+        uncompressed_witness_threshold: uncompressed_witness_threshold.as_u64(),
+        part_format: PartFormat::for_protocol_version(protocol_version).as_str().to_owned(),
+        oldest_accepted_part_format: PartFormat::for_protocol_version(oldest_accepted_version)
+            .as_str()
+            .to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::stateless_validation::partial_witness::{
+        MAX_COMPRESSED_STATE_WITNESS_SIZE, MAX_COMPRESSED_STATE_WITNESS_SIZE_V2,
+    };
+    use near_primitives::version::PROTOCOL_VERSION;
+
+    use super::*;
+
+    /// Lists every field of `PartialWitnessConfig`: the destructuring doesn't compile once a field
+    /// is added, until it is listed here and shown to appear in the view.
+    macro_rules! assert_every_field_in_view {
+        ($config:expr, $view:expr, [$($field:ident),* $(,)?]) => {{
+            let PartialWitnessConfig { $($field: _),* } = $config;
+            let fields = $view.partial_witness.as_object().expect("config is a JSON object");
+            $(assert!(fields.contains_key(stringify!($field)), stringify!($field));)*
+            assert_eq!(fields.len(), [$(stringify!($field)),*].len());
+        }};
+    }
+
+    #[test]
+    fn every_config_field_is_in_the_view() {
+        let config = PartialWitnessConfig::default();
+        let view = witness_config_view(&config, 1024, PROTOCOL_VERSION);
+        assert_every_field_in_view!(
+            config,
+            view,
+            [
+                pre_tracked_shards,
+                no_forward_shards,
+                spill_to_disk,
+                spill_threshold,
+                direct_full_witness_targets,
+                direct_full_witness_budget_per_height,
+                direct_routing_targets,
+                reed_solomon_backend,
+                memory_pressure_parts_budget,
+                warm_up_heights,
+                warm_up_timeout,
+                incomplete_witness_deadline,
+                record_messages_path,
+                record_messages_max_size,
+                signature_verification_warn_utilization,
+                uncompressed_witness_threshold,
+                announce_unavailability_on_shutdown,
+                decode_batch_window,
+                high_send_skew_threshold,
+                max_concurrent_witness_decodes,
+                export_witness_stats_dir,
+                export_witness_stats_rotation_size,
+                export_witness_stats_max_disk_usage,
+                reject_chunk_validators_mismatch,
+                max_forward_targets,
+                ack_batching_delay,
+                ack_threshold_deadline,
+                max_parts_buffered_during_sync,
+                part_send_window,
+                fork_aware_height_window,
+                health,
+                epoch_witness_stats_retained,
+                forward_backoff,
+                buffered_witness_decode,
+                handler_panic_policy,
+                processing_time_slice,
+                client_witness_credits,
+                missing_part_request_delay,
+            ]
+        );
+    }
+
+    #[test]
+    fn protocol_limits_follow_the_protocol_version() {
+        let config = PartialWitnessConfig::default();
+        let increase = ProtocolFeature::WitnessSizeLimitIncrease.protocol_version();
+        let before = protocol_limits_view(&config, increase - 1);
+        let after = protocol_limits_view(&config, increase);
+        assert!(before.witness_size_grace_percent > 0);
+        assert_eq!(after.witness_size_grace_percent, 0);
+        assert_eq!(before.witness_size_limit, MAX_COMPRESSED_STATE_WITNESS_SIZE.as_u64());
+        assert_eq!(after.witness_size_limit, MAX_COMPRESSED_STATE_WITNESS_SIZE_V2.as_u64());
+        assert_eq!(after.max_accepted_witness_size, after.witness_size_limit);
+
+        let uncompressed = ProtocolFeature::UncompressedSmallWitness.protocol_version();
+        assert_eq!(
+            protocol_limits_view(&config, uncompressed - 1).uncompressed_witness_threshold,
+            0
+        );
+        assert_eq!(
+            protocol_limits_view(&config, uncompressed).uncompressed_witness_threshold,
+            config.uncompressed_witness_threshold.as_u64()
+        );
+    }
+}
@@ -1,34 +1,129 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use near_chain::Error;
+use near_chain_configs::ReedSolomonBackendConfig;
 use near_primitives::reed_solomon::{
-    reed_solomon_decode, reed_solomon_encode, reed_solomon_part_length,
+    reed_solomon_decode, reed_solomon_encode, PortableGalois8Field,
 };
 use near_primitives::stateless_validation::state_witness::EncodedChunkStateWitness;
-use reed_solomon_erasure::galois_8::ReedSolomon;
+use reed_solomon_erasure::{galois_8, ReedSolomon};
 
-/// Ratio of the number of data parts to total parts in the Reed Solomon encoding.
-/// The tradeoff here is having a higher ratio is better for handling missing parts and network errors
-/// but increases the size of the encoded state witness and the total network bandwidth requirements.
-const RATIO_DATA_PARTS: f32 = 0.6;
+use super::witness_parts_geometry;
+use crate::metrics;
+
+/// Maximum number of parts, i.e. chunk validators of a chunk, the witness can be encoded into.
+/// The Reed Solomon encoding over GF(2^8) supports at most 256 data and parity parts in total.
+/// Chunks with more chunk validators are not distributed at all rather than distributed to
+/// a subset of them, so that the problem shows up as missing endorsements right away.
+pub const MAX_WITNESS_PARTS: usize = 256;
 
 /// Type alias around what ReedSolomon represents data part as.
 /// This should help with making the code a bit more understandable.
 pub type WitnessPart = Option<Box<[u8]>>;
 
+/// Reed Solomon implementation used by the `WitnessEncoder`, see `ReedSolomonBackendConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReedSolomonBackend {
+    /// `galois_8` field of reed-solomon-erasure, which uses SIMD kernels when built with the
+    /// `reed_solomon_simd` feature.
+    Galois8,
+    /// `PortableGalois8Field`, which works the same on every CPU.
+    Portable,
+}
+
+impl ReedSolomonBackend {
+    /// Selects the backend according to the config and the capabilities of the CPU, and records
+    /// the selected backend in the log and in the metrics. Without the `reed_solomon_simd`
+    /// feature `galois_8` is plain Rust, so it is used unless the portable backend is requested.
+    /// With the feature its SIMD kernels can't run on every CPU, so the portable backend is used
+    /// on the ones which don't support them.
+    pub fn select(config: ReedSolomonBackendConfig) -> Self {
+        let simd_compiled = cfg!(feature = "reed_solomon_simd");
+        let simd_available = Self::simd_available();
+        let backend = match config {
+            ReedSolomonBackendConfig::Portable => Self::Portable,
+            _ if simd_available => Self::Galois8,
+            ReedSolomonBackendConfig::Auto if !simd_compiled => Self::Galois8,
+            ReedSolomonBackendConfig::Simd if !simd_compiled => {
+                tracing::warn!(
+                    target: "client",
+                    "SIMD Reed Solomon backend is not compiled in, using galois_8 without it"
+                );
+                Self::Galois8
+            }
+            ReedSolomonBackendConfig::Auto => Self::Portable,
+            ReedSolomonBackendConfig::Simd => {
+                tracing::warn!(
+                    target: "client",
+                    "SIMD Reed Solomon backend is not supported by the CPU, using the portable one"
+                );
+                Self::Portable
+            }
+        };
+        tracing::info!(target: "client", ?config, simd_available, ?backend, "Selected Reed Solomon backend for witness parts");
+        for other in [Self::Galois8, Self::Portable] {
+            metrics::PARTIAL_WITNESS_REED_SOLOMON_BACKEND
+                .with_label_values(&[other.as_str()])
+                .set((other == backend) as i64);
+        }
+        backend
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Galois8 if cfg!(feature = "reed_solomon_simd") => "simd",
+            Self::Galois8 => "galois_8",
+            Self::Portable => "portable",
+        }
+    }
+
+    /// Whether the SIMD kernels are compiled in and supported by the CPU we are running on.
+    fn simd_available() -> bool {
+        if !cfg!(feature = "reed_solomon_simd") {
+            return false;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            std::arch::is_x86_feature_detected!("ssse3")
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            std::arch::is_aarch64_feature_detected!("neon")
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            false
+        }
+    }
+}
+
+enum ReedSolomonCodec {
+    Galois8(ReedSolomon<galois_8::Field>),
+    Portable(ReedSolomon<PortableGalois8Field>),
+}
+
 /// Reed Solomon encoder wrapper for encoding and decoding state witness parts.
 pub struct WitnessEncoder {
     /// None corresponds to the case when we are the only validator for the chunk
     /// since ReedSolomon does not support having exactly 1 total part count and
     /// no parity parts.
-    rs: Option<ReedSolomon>,
+    rs: Option<ReedSolomonCodec>,
 }
 
 impl WitnessEncoder {
-    fn new(total_parts: usize) -> WitnessEncoder {
+    fn new(total_parts: usize, backend: ReedSolomonBackend) -> WitnessEncoder {
         let rs = if total_parts > 1 {
-            let data_parts = num_witness_data_parts(total_parts);
-            Some(ReedSolomon::new(data_parts, total_parts - data_parts).unwrap())
+            let data_parts = witness_parts_geometry::data_parts(total_parts);
+            let parity_parts = witness_parts_geometry::parity_parts(total_parts);
+            Some(match backend {
+                ReedSolomonBackend::Galois8 => {
+                    ReedSolomonCodec::Galois8(ReedSolomon::new(data_parts, parity_parts).unwrap())
+                }
+                ReedSolomonBackend::Portable => {
+                    ReedSolomonCodec::Portable(ReedSolomon::new(data_parts, parity_parts).unwrap())
+                }
+            })
         } else {
             None
         };
@@ -37,25 +132,44 @@ impl WitnessEncoder {
 
     pub fn total_parts(&self) -> usize {
         match self.rs {
-            Some(ref rs) => rs.total_shard_count(),
+            Some(ReedSolomonCodec::Galois8(ref rs)) => rs.total_shard_count(),
+            Some(ReedSolomonCodec::Portable(ref rs)) => rs.total_shard_count(),
             None => 1,
         }
     }
 
     pub fn data_parts(&self) -> usize {
         match self.rs {
-            Some(ref rs) => rs.data_shard_count(),
+            Some(ReedSolomonCodec::Galois8(ref rs)) => rs.data_shard_count(),
+            Some(ReedSolomonCodec::Portable(ref rs)) => rs.data_shard_count(),
             None => 1,
         }
     }
 
+    /// Approximate memory used by the encoder, i.e. by its encoding matrix of one byte per data
+    /// part and part. The matrices cached for decoding are not counted.
+    pub fn size_bytes(&self) -> usize {
+        if self.rs.is_none() {
+            return 0;
+        }
+        self.data_parts() * self.total_parts()
+    }
+
     pub fn encode(&self, witness: &EncodedChunkStateWitness) -> (Vec<WitnessPart>, usize) {
-        match self.rs {
-            Some(ref rs) => reed_solomon_encode(rs, witness),
+        let (parts, encoded_length) = match self.rs {
+            Some(ReedSolomonCodec::Galois8(ref rs)) => reed_solomon_encode(rs, witness),
+            Some(ReedSolomonCodec::Portable(ref rs)) => reed_solomon_encode(rs, witness),
             None => {
                 (vec![Some(witness.as_slice().to_vec().into_boxed_slice())], witness.size_bytes())
             }
-        }
+        };
+        // Make sure that the encoder agrees with the geometry used on the receiving side.
+        debug_assert_eq!(parts.len(), self.total_parts());
+        debug_assert!({
+            let part_len = witness_parts_geometry::part_len(encoded_length, self.total_parts());
+            parts.iter().all(|part| part.as_ref().map(|part| part.len()) == Some(part_len))
+        });
+        (parts, encoded_length)
     }
 
     pub fn decode(
@@ -64,7 +178,12 @@ impl WitnessEncoder {
         encoded_length: usize,
     ) -> Result<EncodedChunkStateWitness, std::io::Error> {
         match self.rs {
-            Some(ref rs) => reed_solomon_decode(rs, parts, encoded_length),
+            Some(ReedSolomonCodec::Galois8(ref rs)) => {
+                reed_solomon_decode(rs, parts, encoded_length)
+            }
+            Some(ReedSolomonCodec::Portable(ref rs)) => {
+                reed_solomon_decode(rs, parts, encoded_length)
+            }
             None => {
                 Ok(EncodedChunkStateWitness::from_boxed_slice(parts[0].as_ref().unwrap().clone()))
             }
@@ -75,25 +194,118 @@ impl WitnessEncoder {
 /// We keep one encoder for each length of chunk_validators to avoid re-creating the encoder.
 pub struct WitnessEncoderCache {
     instances: HashMap<usize, Arc<WitnessEncoder>>,
+    backend: ReedSolomonBackend,
 }
 
 impl WitnessEncoderCache {
-    pub fn new() -> Self {
-        Self { instances: HashMap::new() }
+    pub fn new(backend: ReedSolomonBackend) -> Self {
+        Self { instances: HashMap::new(), backend }
     }
 
-    pub fn entry(&mut self, total_parts: usize) -> Arc<WitnessEncoder> {
-        self.instances
+    /// Returns the encoder for `total_parts` parts, or an error if the number of parts
+    /// exceeds `MAX_WITNESS_PARTS`.
+    pub fn entry(&mut self, total_parts: usize) -> Result<Arc<WitnessEncoder>, Error> {
+        if total_parts > MAX_WITNESS_PARTS {
+            return Err(Error::TooManyWitnessParts {
+                num_parts: total_parts,
+                max: MAX_WITNESS_PARTS,
+            });
+        }
+        let backend = self.backend;
+        Ok(self
+            .instances
             .entry(total_parts)
-            .or_insert_with(|| Arc::new(WitnessEncoder::new(total_parts)))
-            .clone()
+            .or_insert_with(|| Arc::new(WitnessEncoder::new(total_parts, backend)))
+            .clone())
+    }
+
+    /// Drops the encoders for the numbers of parts not in `total_parts` and returns how many were
+    /// dropped. The encoders still used by the witnesses being reconstructed are freed once the
+    /// reconstruction is done.
+    pub fn retain(&mut self, total_parts: &HashSet<usize>) -> usize {
+        let num_encoders = self.instances.len();
+        self.instances.retain(|num_parts, _| total_parts.contains(num_parts));
+        num_encoders - self.instances.len()
     }
-}
 
-pub fn witness_part_length(encoded_witness_size: usize, total_parts: usize) -> usize {
-    reed_solomon_part_length(encoded_witness_size, num_witness_data_parts(total_parts))
+    /// Approximate memory used by the encoders, see `WitnessEncoder::size_bytes`.
+    pub fn size_bytes(&self) -> usize {
+        self.instances.values().map(|encoder| encoder.size_bytes()).sum()
+    }
 }
 
-fn num_witness_data_parts(total_parts: usize) -> usize {
-    std::cmp::max((total_parts as f32 * RATIO_DATA_PARTS) as usize, 1)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    /// Test vectors shared by the backends: (number of parts, witness length).
+    const TEST_VECTORS: &[(usize, usize)] =
+        &[(1, 1), (2, 10), (3, 1000), (5, 4096), (10, 12345), (33, 100_000), (68, 500_000)];
+
+    #[test]
+    fn backends_produce_identical_parts() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let mut galois8 = WitnessEncoderCache::new(ReedSolomonBackend::Galois8);
+        let mut portable = WitnessEncoderCache::new(ReedSolomonBackend::Portable);
+        for &(total_parts, len) in TEST_VECTORS {
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let witness = EncodedChunkStateWitness::from_boxed_slice(bytes.into_boxed_slice());
+            let (galois8_parts, galois8_length) =
+                galois8.entry(total_parts).unwrap().encode(&witness);
+            let (portable_parts, portable_length) =
+                portable.entry(total_parts).unwrap().encode(&witness);
+            assert_eq!(galois8_length, portable_length, "total_parts={total_parts}");
+            assert_eq!(galois8_parts, portable_parts, "total_parts={total_parts}");
+
+            // Each backend decodes the parts produced by the other one, with as many parts
+            // missing as there are parity parts.
+            let num_missing = witness_parts_geometry::parity_parts(total_parts);
+            for (mut parts, decoder) in
+                [(galois8_parts, &mut portable), (portable_parts, &mut galois8)]
+            {
+                for part in parts.iter_mut().take(num_missing) {
+                    *part = None;
+                }
+                let decoded =
+                    decoder.entry(total_parts).unwrap().decode(&mut parts, galois8_length).unwrap();
+                assert_eq!(decoded, witness, "total_parts={total_parts}");
+            }
+        }
+    }
+
+    #[test]
+    fn encoder_supports_up_to_max_witness_parts() {
+        let witness =
+            EncodedChunkStateWitness::from_boxed_slice(vec![7; 10_000].into_boxed_slice());
+        for backend in [ReedSolomonBackend::Galois8, ReedSolomonBackend::Portable] {
+            let mut encoders = WitnessEncoderCache::new(backend);
+            let encoder = encoders.entry(MAX_WITNESS_PARTS).unwrap();
+            assert_eq!(encoder.total_parts(), MAX_WITNESS_PARTS);
+            let (mut parts, encoded_length) = encoder.encode(&witness);
+            for part in
+                parts.iter_mut().take(witness_parts_geometry::parity_parts(MAX_WITNESS_PARTS))
+            {
+                *part = None;
+            }
+            assert_eq!(encoder.decode(&mut parts, encoded_length).unwrap(), witness);
+
+            assert!(matches!(
+                encoders.entry(MAX_WITNESS_PARTS + 1),
+                Err(Error::TooManyWitnessParts { num_parts, max: MAX_WITNESS_PARTS })
+                    if num_parts == MAX_WITNESS_PARTS + 1
+            ));
+        }
+    }
+
+    #[test]
+    fn retain_drops_encoders_for_other_numbers_of_parts() {
+        let mut cache = WitnessEncoderCache::new(ReedSolomonBackend::Portable);
+        for total_parts in [3, 5, 10] {
+            cache.entry(total_parts).unwrap();
+        }
+        assert_eq!(cache.retain(&HashSet::from([5, 20])), 2);
+        assert_eq!(cache.instances.keys().copied().collect::<Vec<_>>(), vec![5]);
+        assert_eq!(cache.retain(&HashSet::from([5])), 0);
+    }
 }
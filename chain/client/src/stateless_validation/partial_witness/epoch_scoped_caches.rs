@@ -0,0 +1,202 @@
+//! State of the partial witness actor scoped to an epoch, bounded to the epochs around the head.
+//!
+//! The Reed Solomon encoders are only needed for the numbers of chunk validators of the epochs
+//! the chain is in, yet a node running for months would accrete the encoders of every past epoch
+//! if nothing dropped them. `EpochScopedCaches` owns this data in one place and advances with the
+//! head: it retains the epoch of the head, the epoch the head was in before, for the chunks
+//! produced right before the epoch switch, and the next epoch, warmed up before the head enters
+//! it. The data of the other epochs is dropped, and the lookups for them fail with
+//! `Error::EpochNotRetained` rather than computing the data again. Until the first head nothing
+//! is dropped, so that the actor works before the head is known.
+
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use lru::LruCache;
+use near_chain::Error;
+use near_primitives::types::EpochId;
+
+use super::encoding::{ReedSolomonBackend, WitnessEncoder, WitnessEncoderCache};
+use crate::metrics;
+
+/// Number of the most recently dropped epochs remembered, to tell the lookups for them apart
+/// from the lookups for epochs which were never retained.
+const DROPPED_EPOCHS_CACHE_SIZE: usize = 16;
+
+/// Epochs retained around the head.
+#[derive(Clone, Copy, Debug)]
+struct RetainedEpochs {
+    previous: Option<EpochId>,
+    current: EpochId,
+    next: EpochId,
+}
+
+impl RetainedEpochs {
+    fn contains(&self, epoch_id: &EpochId) -> bool {
+        self.previous.as_ref() == Some(epoch_id)
+            || &self.current == epoch_id
+            || &self.next == epoch_id
+    }
+}
+
+pub struct EpochScopedCaches {
+    /// None until the first head.
+    retained: Option<RetainedEpochs>,
+    /// Numbers of parts of the witnesses of each epoch holding data, whose encoders are kept.
+    total_parts: HashMap<EpochId, HashSet<usize>>,
+    /// Encoders for the numbers of parts used by the epochs in `total_parts`, shared between
+    /// the epochs with the same numbers of chunk validators.
+    encoders: WitnessEncoderCache,
+    dropped_epochs: LruCache<EpochId, ()>,
+}
+
+impl EpochScopedCaches {
+    pub fn new(backend: ReedSolomonBackend) -> Self {
+        Self {
+            retained: None,
+            total_parts: HashMap::new(),
+            encoders: WitnessEncoderCache::new(backend),
+            dropped_epochs: LruCache::new(NonZeroUsize::new(DROPPED_EPOCHS_CACHE_SIZE).unwrap()),
+        }
+    }
+
+    /// Moves the retained epochs to the ones around the new head and drops the data of the
+    /// others. Returns the number of epochs whose data was dropped.
+    pub fn advance(&mut self, epoch_id: EpochId, next_epoch_id: EpochId) -> usize {
+        let previous = match self.retained {
+            Some(retained) if retained.current != epoch_id => Some(retained.current),
+            Some(retained) => retained.previous,
+            None => None,
+        };
+        let retained = RetainedEpochs { previous, current: epoch_id, next: next_epoch_id };
+        self.retained = Some(retained);
+
+        let dropped = self
+            .total_parts
+            .keys()
+            .filter(|epoch_id| !retained.contains(epoch_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for epoch_id in &dropped {
+            self.total_parts.remove(epoch_id);
+            self.dropped_epochs.put(*epoch_id, ());
+        }
+        if !dropped.is_empty() {
+            tracing::debug!(target: "client", ?dropped, ?retained, "Dropped epoch scoped caches");
+            let total_parts = self.total_parts();
+            self.encoders.retain(&total_parts);
+        }
+        self.update_metrics();
+        dropped.len()
+    }
+
+    /// Returns the encoder for `total_parts` parts of the witnesses of the epoch, constructing it
+    /// if needed, or `Error::EpochNotRetained` if the epoch is not around the head.
+    pub fn encoder(
+        &mut self,
+        epoch_id: &EpochId,
+        total_parts: usize,
+    ) -> Result<Arc<WitnessEncoder>, Error> {
+        if let Some(retained) = &self.retained {
+            if !retained.contains(epoch_id) {
+                return Err(Error::EpochNotRetained {
+                    epoch_id: *epoch_id,
+                    dropped: self.dropped_epochs.contains(epoch_id),
+                });
+            }
+        }
+        let encoder = self.encoders.entry(total_parts)?;
+        self.total_parts.entry(*epoch_id).or_default().insert(total_parts);
+        self.update_metrics();
+        Ok(encoder)
+    }
+
+    /// Numbers of parts of the witnesses of all the epochs holding data.
+    pub fn total_parts(&self) -> HashSet<usize> {
+        self.total_parts.values().flatten().copied().collect()
+    }
+
+    /// Drops the encoders for the numbers of parts not in `total_parts`, in all the epochs, and
+    /// returns how many were dropped. The epochs are still retained.
+    pub fn retain_encoders(&mut self, total_parts: &HashSet<usize>) -> usize {
+        for epoch_total_parts in self.total_parts.values_mut() {
+            epoch_total_parts.retain(|num_parts| total_parts.contains(num_parts));
+        }
+        let num_dropped = self.encoders.retain(total_parts);
+        self.update_metrics();
+        num_dropped
+    }
+
+    /// Number of distinct epochs holding data.
+    pub fn num_epochs(&self) -> usize {
+        self.total_parts.len()
+    }
+
+    fn update_metrics(&self) {
+        metrics::PARTIAL_WITNESS_RETAINED_EPOCHS.set(self.num_epochs() as i64);
+        metrics::PARTIAL_WITNESS_EPOCH_SCOPED_BYTES.set(self.encoders.size_bytes() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::CryptoHash;
+
+    use super::*;
+
+    fn epoch(id: u8) -> EpochId {
+        EpochId(CryptoHash([id; 32]))
+    }
+
+    #[test]
+    fn drops_the_epochs_two_behind_the_head() {
+        let mut caches = EpochScopedCaches::new(ReedSolomonBackend::Portable);
+        // Nothing is dropped before the first head.
+        caches.encoder(&epoch(9), 3).unwrap();
+        caches.encoder(&epoch(1), 4).unwrap();
+        assert_eq!(caches.advance(epoch(1), epoch(2)), 1);
+        assert_eq!(caches.num_epochs(), 1);
+        assert!(matches!(
+            caches.encoder(&epoch(9), 3),
+            Err(Error::EpochNotRetained { dropped: true, .. })
+        ));
+
+        // The next epoch is warmed up before the head enters it.
+        caches.encoder(&epoch(2), 5).unwrap();
+        assert_eq!(caches.total_parts(), HashSet::from([4, 5]));
+        assert_eq!(caches.advance(epoch(2), epoch(3)), 0);
+        caches.encoder(&epoch(3), 6).unwrap();
+        // The previous epoch is still retained.
+        caches.encoder(&epoch(1), 4).unwrap();
+        assert_eq!(caches.num_epochs(), 3);
+
+        // The head enters the epoch 3, the epoch 1 is now two behind it.
+        assert_eq!(caches.advance(epoch(3), epoch(4)), 1);
+        assert_eq!(caches.num_epochs(), 2);
+        assert_eq!(caches.total_parts(), HashSet::from([5, 6]));
+        assert!(matches!(
+            caches.encoder(&epoch(1), 4),
+            Err(Error::EpochNotRetained { epoch_id, dropped: true }) if epoch_id == epoch(1)
+        ));
+        // The lookup didn't compute the data again.
+        assert_eq!(caches.num_epochs(), 2);
+        assert!(matches!(
+            caches.encoder(&epoch(7), 4),
+            Err(Error::EpochNotRetained { dropped: false, .. })
+        ));
+        caches.encoder(&epoch(2), 5).unwrap();
+    }
+
+    #[test]
+    fn retain_encoders_keeps_the_epochs() {
+        let mut caches = EpochScopedCaches::new(ReedSolomonBackend::Portable);
+        caches.advance(epoch(1), epoch(2));
+        caches.encoder(&epoch(1), 3).unwrap();
+        caches.encoder(&epoch(2), 5).unwrap();
+        assert_eq!(caches.retain_encoders(&HashSet::from([5])), 1);
+        assert_eq!(caches.num_epochs(), 2);
+        assert_eq!(caches.total_parts(), HashSet::from([5]));
+        caches.encoder(&epoch(1), 3).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! Witness distribution statistics of the recent epochs, persisted in the store so that the
+//! operators can tell after the fact, restarts included, whether their validator missed the
+//! endorsements because of its own downtime or because the witnesses didn't reach it.
+//!
+//! The statistics are collected in memory per epoch. Once the head leaves an epoch, its summary is
+//! written to `DBCol::PartialWitnessEpochStats` keyed by the epoch id, only the
+//! `PartialWitnessConfig::epoch_witness_stats_retained` most recent epochs are kept, and the
+//! summaries are served by the status RPC, see `load_epoch_witness_stats`.
+//!
+//! As a chunk validator, the witnesses expected for a shard are the chunks of the blocks which
+//! became the head for which we are a chunk validator, together with the witnesses we decoded as a
+//! chunk validator, e.g. of the blocks the head skipped over. The expected witnesses not decoded by
+//! the time the head leaves the epoch count as never decoded. As a chunk producer, we count the
+//! witnesses distributed and the time until two thirds of their chunk validators acked them, see
+//! `ChunkStateWitnessTracker`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_async::time::Duration;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+use near_primitives::views::{EpochWitnessStatsView, ShardWitnessStatsView};
+use near_store::{DBCol, Store};
+
+/// Maximum number of the times to the ack threshold kept per shard and epoch for the median, the
+/// later witnesses are still counted as distributed.
+const MAX_ACK_THRESHOLD_SAMPLES: usize = 50_000;
+
+/// Versioned summary of an epoch, as stored in `DBCol::PartialWitnessEpochStats`. A binary which
+/// doesn't know the version of a summary skips it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum VersionedEpochWitnessStats {
+    V1(EpochWitnessStatsV1),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochWitnessStatsV1 {
+    pub epoch_id: EpochId,
+    /// Heights of the first and the last head seen in the epoch.
+    pub first_head_height: BlockHeight,
+    pub last_head_height: BlockHeight,
+    /// Ordered by shard id.
+    pub shards: Vec<ShardWitnessStatsV1>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardWitnessStatsV1 {
+    pub shard_id: ShardId,
+    pub witnesses_expected: u64,
+    pub decoded_on_time: u64,
+    pub decoded_late: u64,
+    pub never_decoded: u64,
+    pub witnesses_distributed: u64,
+    pub median_time_to_ack_threshold_millis: Option<u64>,
+}
+
+impl VersionedEpochWitnessStats {
+    pub fn epoch_id(&self) -> &EpochId {
+        match self {
+            VersionedEpochWitnessStats::V1(stats) => &stats.epoch_id,
+        }
+    }
+
+    pub fn last_head_height(&self) -> BlockHeight {
+        match self {
+            VersionedEpochWitnessStats::V1(stats) => stats.last_head_height,
+        }
+    }
+
+    pub fn to_view(&self) -> EpochWitnessStatsView {
+        let VersionedEpochWitnessStats::V1(stats) = self;
+        EpochWitnessStatsView {
+            epoch_id: stats.epoch_id.0,
+            first_head_height: stats.first_head_height,
+            last_head_height: stats.last_head_height,
+            shards: stats
+                .shards
+                .iter()
+                .map(|shard| ShardWitnessStatsView {
+                    shard_id: shard.shard_id,
+                    witnesses_expected: shard.witnesses_expected,
+                    decoded_on_time: shard.decoded_on_time,
+                    decoded_late: shard.decoded_late,
+                    never_decoded: shard.never_decoded,
+                    witnesses_distributed: shard.witnesses_distributed,
+                    median_time_to_ack_threshold_millis: shard.median_time_to_ack_threshold_millis,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ShardStats {
+    /// Heights of the chunks of the heads we were a chunk validator of.
+    expected_heights: BTreeSet<BlockHeight>,
+    /// Heights of the witnesses decoded as a chunk validator, with whether they were late.
+    decoded: BTreeMap<BlockHeight, bool>,
+    /// Heights of the witnesses distributed by us, a witness sent again is counted once.
+    distributed_heights: BTreeSet<BlockHeight>,
+    times_to_ack_threshold: Vec<Duration>,
+}
+
+impl ShardStats {
+    fn summarize(&self, shard_id: ShardId) -> ShardWitnessStatsV1 {
+        let decoded_late = self.decoded.values().filter(|late| **late).count() as u64;
+        let never_decoded = self
+            .expected_heights
+            .iter()
+            .filter(|height| !self.decoded.contains_key(height))
+            .count() as u64;
+        let mut times = self.times_to_ack_threshold.clone();
+        times.sort();
+        ShardWitnessStatsV1 {
+            shard_id,
+            witnesses_expected: self.decoded.len() as u64 + never_decoded,
+            decoded_on_time: self.decoded.len() as u64 - decoded_late,
+            decoded_late,
+            never_decoded,
+            witnesses_distributed: self.distributed_heights.len() as u64,
+            median_time_to_ack_threshold_millis: times
+                .get(times.len() / 2)
+                .map(|time| time.whole_milliseconds() as u64),
+        }
+    }
+}
+
+#[derive(Default)]
+struct EpochStats {
+    /// Heights of the first and the last head seen in the epoch, None until the head enters it.
+    head_heights: Option<(BlockHeight, BlockHeight)>,
+    shards: BTreeMap<ShardId, ShardStats>,
+}
+
+/// Collects the statistics of the epoch of the head, and of the witnesses of the next epoch
+/// decoded or distributed before the head enters it.
+pub struct EpochWitnessStatsCollector {
+    head_epoch_id: Option<EpochId>,
+    epochs: HashMap<EpochId, EpochStats>,
+}
+
+impl EpochWitnessStatsCollector {
+    pub fn new() -> Self {
+        Self { head_epoch_id: None, epochs: HashMap::new() }
+    }
+
+    fn shard(&mut self, epoch_id: &EpochId, shard_id: ShardId) -> &mut ShardStats {
+        self.epochs.entry(*epoch_id).or_default().shards.entry(shard_id).or_default()
+    }
+
+    /// Records the head, returns the summary of the epoch of the previous head if the head left
+    /// it. The statistics of the other epochs which were never the epoch of the head are dropped.
+    pub fn on_head_updated(
+        &mut self,
+        epoch_id: EpochId,
+        height: BlockHeight,
+    ) -> Option<VersionedEpochWitnessStats> {
+        let mut finished = None;
+        if self.head_epoch_id != Some(epoch_id) {
+            if let Some(previous_epoch_id) = self.head_epoch_id.replace(epoch_id) {
+                finished = self
+                    .epochs
+                    .remove(&previous_epoch_id)
+                    .and_then(|stats| summarize(previous_epoch_id, &stats));
+            }
+            self.epochs.retain(|other_epoch_id, _| *other_epoch_id == epoch_id);
+        }
+        let epoch = self.epochs.entry(epoch_id).or_default();
+        let (first, last) = epoch.head_heights.get_or_insert((height, height));
+        *first = (*first).min(height);
+        *last = (*last).max(height);
+        finished
+    }
+
+    /// Records that the block of the head includes the chunk of the shard at its height, of which
+    /// we are a chunk validator.
+    pub fn record_expected(&mut self, epoch_id: &EpochId, shard_id: ShardId, height: BlockHeight) {
+        self.shard(epoch_id, shard_id).expected_heights.insert(height);
+    }
+
+    /// Records the witness decoded as a chunk validator, the first decode counts.
+    pub fn record_decoded(&mut self, key: &ChunkProductionKey, decoded_late: bool) {
+        self.shard(&key.epoch_id, key.shard_id)
+            .decoded
+            .entry(key.height_created)
+            .or_insert(decoded_late);
+    }
+
+    pub fn record_distributed(&mut self, key: &ChunkProductionKey) {
+        self.shard(&key.epoch_id, key.shard_id).distributed_heights.insert(key.height_created);
+    }
+
+    /// Records the time from distributing the witness to the acks of two thirds of its chunk
+    /// validators.
+    pub fn record_ack_threshold(&mut self, key: &ChunkProductionKey, time: Duration) {
+        let times = &mut self.shard(&key.epoch_id, key.shard_id).times_to_ack_threshold;
+        if times.len() < MAX_ACK_THRESHOLD_SAMPLES {
+            times.push(time);
+        }
+    }
+}
+
+fn summarize(epoch_id: EpochId, stats: &EpochStats) -> Option<VersionedEpochWitnessStats> {
+    let (first_head_height, last_head_height) = stats.head_heights?;
+    Some(VersionedEpochWitnessStats::V1(EpochWitnessStatsV1 {
+        epoch_id,
+        first_head_height,
+        last_head_height,
+        shards: stats.shards.iter().map(|(shard_id, shard)| shard.summarize(*shard_id)).collect(),
+    }))
+}
+
+/// Writes the summary of the epoch and deletes the summaries beyond the `retained` most recent
+/// ones, ordered by the height of the last head seen in them.
+pub fn save_epoch_witness_stats(
+    store: &Store,
+    stats: &VersionedEpochWitnessStats,
+    retained: usize,
+) -> std::io::Result<()> {
+    let mut store_update = store.store_update();
+    store_update.set_ser(DBCol::PartialWitnessEpochStats, stats.epoch_id().0.as_ref(), stats)?;
+    let mut saved = load_epoch_witness_stats(store)?;
+    saved.retain(|saved| saved.epoch_id() != stats.epoch_id());
+    saved.insert(0, stats.clone());
+    saved.sort_by_key(|saved| std::cmp::Reverse(saved.last_head_height()));
+    for pruned in saved.iter().skip(retained) {
+        store_update.delete(DBCol::PartialWitnessEpochStats, pruned.epoch_id().0.as_ref());
+    }
+    store_update.commit()
+}
+
+/// Summaries of the recent epochs, starting from the most recent one. The summaries of unknown
+/// versions are skipped.
+pub fn load_epoch_witness_stats(store: &Store) -> std::io::Result<Vec<VersionedEpochWitnessStats>> {
+    let mut summaries = vec![];
+    for item in store.iter(DBCol::PartialWitnessEpochStats) {
+        let (_, value) = item?;
+        match VersionedEpochWitnessStats::try_from_slice(&value) {
+            Ok(stats) => summaries.push(stats),
+            Err(err) => {
+                tracing::debug!(target: "client", ?err, "Skipping unknown epoch witness stats")
+            }
+        }
+    }
+    summaries.sort_by_key(|stats| std::cmp::Reverse(stats.last_head_height()));
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::Duration;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::types::{BlockHeight, EpochId};
+    use near_store::test_utils::create_test_store;
+
+    use super::{
+        load_epoch_witness_stats, save_epoch_witness_stats, EpochWitnessStatsCollector,
+        ShardWitnessStatsV1, VersionedEpochWitnessStats,
+    };
+
+    fn epoch(index: u8) -> EpochId {
+        EpochId(CryptoHash::hash_bytes(&[index]))
+    }
+
+    fn key(epoch_id: EpochId, height_created: BlockHeight) -> ChunkProductionKey {
+        ChunkProductionKey { epoch_id, shard_id: 0, height_created }
+    }
+
+    fn shard_stats(stats: &VersionedEpochWitnessStats) -> &ShardWitnessStatsV1 {
+        let VersionedEpochWitnessStats::V1(stats) = stats;
+        &stats.shards[0]
+    }
+
+    #[test]
+    fn summary_of_the_epoch_left_by_the_head() {
+        let mut collector = EpochWitnessStatsCollector::new();
+        assert!(collector.on_head_updated(epoch(1), 10).is_none());
+        // Decoded on time, late, never, and decoded though the head skipped its block.
+        for height in [11, 12, 13] {
+            collector.record_expected(&epoch(1), 0, height);
+        }
+        collector.record_decoded(&key(epoch(1), 11), false);
+        collector.record_decoded(&key(epoch(1), 11), true);
+        collector.record_decoded(&key(epoch(1), 12), true);
+        collector.record_decoded(&key(epoch(1), 14), false);
+        // Distributed twice, and another witness not acked by enough chunk validators.
+        for height in [15, 15, 16] {
+            collector.record_distributed(&key(epoch(1), height));
+        }
+        collector.record_ack_threshold(&key(epoch(1), 15), Duration::milliseconds(300));
+        // Decoded before the head enters the next epoch.
+        collector.record_decoded(&key(epoch(2), 17), false);
+        assert!(collector.on_head_updated(epoch(1), 16).is_none());
+
+        let summary = collector.on_head_updated(epoch(2), 17).unwrap();
+        assert_eq!(summary.epoch_id(), &epoch(1));
+        let VersionedEpochWitnessStats::V1(v1) = &summary;
+        assert_eq!((v1.first_head_height, v1.last_head_height), (10, 16));
+        assert_eq!(
+            shard_stats(&summary),
+            &ShardWitnessStatsV1 {
+                shard_id: 0,
+                witnesses_expected: 4,
+                decoded_on_time: 2,
+                decoded_late: 1,
+                never_decoded: 1,
+                witnesses_distributed: 2,
+                median_time_to_ack_threshold_millis: Some(300),
+            }
+        );
+
+        let summary = collector.on_head_updated(epoch(3), 30).unwrap();
+        assert_eq!(summary.epoch_id(), &epoch(2));
+        assert_eq!(shard_stats(&summary).decoded_on_time, 1);
+    }
+
+    #[test]
+    fn only_the_most_recent_epochs_are_retained() {
+        let store = create_test_store();
+        let mut collector = EpochWitnessStatsCollector::new();
+        collector.on_head_updated(epoch(0), 0);
+        for index in 1..=4 {
+            let summary = collector.on_head_updated(epoch(index), index as u64 * 10).unwrap();
+            save_epoch_witness_stats(&store, &summary, 2).unwrap();
+        }
+        let saved = load_epoch_witness_stats(&store).unwrap();
+        let saved_epochs: Vec<_> = saved.iter().map(|stats| *stats.epoch_id()).collect();
+        assert_eq!(saved_epochs, vec![epoch(3), epoch(2)]);
+    }
+}
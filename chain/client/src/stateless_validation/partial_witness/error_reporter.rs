@@ -0,0 +1,270 @@
+//! Reporting of the errors raised while handling the partial witness messages.
+//!
+//! Every error is counted in the `near_partial_witness_errors_total` metric by shard, labeled the
+//! same way as the chain errors in `near_num_invalid_blocks`. The errors are additionally logged
+//! and sent to the client as `PartialWitnessErrorEvent`s along with the chunk production key and
+//! the chunk producer, which the client includes in its telemetry like its own errors. Both only
+//! happen at most `MAX_ERROR_EVENTS_PER_WINDOW` times per `ERROR_EVENTS_WINDOW` for every kind of
+//! error in every shard, so that a flood of identical failures doesn't flood the logs and the
+//! telemetry, nor hide the failures of the other shards.
+
+use std::collections::HashMap;
+
+use near_async::time::{Clock, Duration, Instant};
+use near_chain::Error;
+use near_o11y::metrics::int_label;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+
+use crate::metrics;
+
+/// Maximum number of events reported for a single kind of error within `ERROR_EVENTS_WINDOW`.
+const MAX_ERROR_EVENTS_PER_WINDOW: usize = 10;
+
+const ERROR_EVENTS_WINDOW: Duration = Duration::minutes(1);
+
+/// Stage of the partial witness handling at which the error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PartialWitnessErrorStage {
+    /// Producer side, encoding and distributing the witness parts.
+    DistributeWitness,
+    /// Validator side, handling the part sent by the chunk producer.
+    OwnedPart,
+    /// Validator side, handling the part forwarded by another chunk validator.
+    ForwardedPart,
+    /// Validator side, joining the fragments of a part too large for a single routed message.
+    PartFragment,
+    /// Validator side, handling the request for a part that we own.
+    PartRequest,
+    /// Validator side, handling the full witness sent directly by the chunk producer.
+    FullWitness,
+    /// Validator side, decoding the witness once enough parts arrived.
+    DecodeWitness,
+}
+
+impl PartialWitnessErrorStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartialWitnessErrorStage::DistributeWitness => "distribute_witness",
+            PartialWitnessErrorStage::OwnedPart => "owned_part",
+            PartialWitnessErrorStage::ForwardedPart => "forwarded_part",
+            PartialWitnessErrorStage::PartFragment => "part_fragment",
+            PartialWitnessErrorStage::PartRequest => "part_request",
+            PartialWitnessErrorStage::FullWitness => "full_witness",
+            PartialWitnessErrorStage::DecodeWitness => "decode_witness",
+        }
+    }
+}
+
+/// Error reported to the client, see `PartialWitnessErrorMessage`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PartialWitnessErrorEvent {
+    pub stage: &'static str,
+    /// Label of the error, see `Error::prometheus_label_value`.
+    pub error: &'static str,
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub chunk_producer: Option<AccountId>,
+    pub message: String,
+}
+
+/// Number of events reported for a single kind of error in the current window.
+struct SamplingWindow {
+    started_at: Instant,
+    reported: usize,
+    suppressed: usize,
+}
+
+pub struct PartialWitnessErrorReporter {
+    clock: Clock,
+    windows: HashMap<(PartialWitnessErrorStage, ShardId, &'static str), SamplingWindow>,
+}
+
+impl PartialWitnessErrorReporter {
+    pub fn new(clock: Clock) -> Self {
+        Self { clock, windows: HashMap::new() }
+    }
+
+    /// Records the error in metrics and logs it, unless too many errors of the same kind
+    /// were already logged within the current window. The chunk producer is only looked up for
+    /// the logged errors.
+    /// Returns the event to report to the client if the error was logged.
+    pub fn report(
+        &mut self,
+        stage: PartialWitnessErrorStage,
+        err: &Error,
+        key: &ChunkProductionKey,
+        chunk_producer: impl FnOnce() -> Option<AccountId>,
+    ) -> Option<PartialWitnessErrorEvent> {
+        let error_label = err.prometheus_label_value();
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&[stage.as_str(), &int_label(key.shard_id), error_label])
+            .inc();
+
+        let now = self.clock.now();
+        let window = self
+            .windows
+            .entry((stage, key.shard_id, error_label))
+            .or_insert(SamplingWindow { started_at: now, reported: 0, suppressed: 0 });
+        if now - window.started_at >= ERROR_EVENTS_WINDOW {
+            if window.suppressed > 0 {
+                tracing::error!(
+                    target: "client",
+                    stage = stage.as_str(),
+                    shard_id = key.shard_id,
+                    error = error_label,
+                    suppressed = window.suppressed,
+                    "Suppressed reporting of partial witness errors",
+                );
+            }
+            *window = SamplingWindow { started_at: now, reported: 0, suppressed: 0 };
+        }
+        if window.reported >= MAX_ERROR_EVENTS_PER_WINDOW {
+            window.suppressed += 1;
+            return None;
+        }
+        window.reported += 1;
+        let chunk_producer = chunk_producer();
+        tracing::error!(
+            target: "client",
+            stage = stage.as_str(),
+            error = error_label,
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            ?chunk_producer,
+            ?err,
+            "Failed to handle partial witness message",
+        );
+        Some(PartialWitnessErrorEvent {
+            stage: stage.as_str(),
+            error: error_label,
+            epoch_id: key.epoch_id,
+            shard_id: key.shard_id,
+            height_created: key.height_created,
+            chunk_producer,
+            message: err.to_string(),
+        })
+    }
+
+    /// Number of errors at `stage` within the current windows which started less than
+    /// `ERROR_EVENTS_WINDOW` ago, i.e. roughly within the last minute.
+    pub fn recent_errors(&self, stage: PartialWitnessErrorStage) -> usize {
+        let now = self.clock.now();
+        self.windows
+            .iter()
+            .filter(|((window_stage, _, _), window)| {
+                *window_stage == stage && now - window.started_at < ERROR_EVENTS_WINDOW
+            })
+            .map(|(_, window)| window.reported + window.suppressed)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::{FakeClock, Utc};
+    use near_primitives::types::EpochId;
+
+    fn suppressed(
+        reporter: &PartialWitnessErrorReporter,
+        stage: PartialWitnessErrorStage,
+        err: &Error,
+    ) -> usize {
+        reporter.windows[&(stage, test_key().shard_id, err.prometheus_label_value())].suppressed
+    }
+
+    fn test_key() -> ChunkProductionKey {
+        shard_key(0)
+    }
+
+    fn shard_key(shard_id: ShardId) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id, epoch_id: EpochId::default(), height_created: 1 }
+    }
+
+    #[test]
+    fn burst_of_errors_is_rate_limited() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut reporter = PartialWitnessErrorReporter::new(clock.clock());
+        let stage = PartialWitnessErrorStage::OwnedPart;
+        let err = Error::InvalidPartialChunkStateWitness("bad part".to_string());
+
+        let reported = (0..100)
+            .filter(|_| reporter.report(stage, &err, &test_key(), || None).is_some())
+            .count();
+        assert_eq!(reported, MAX_ERROR_EVENTS_PER_WINDOW);
+        assert_eq!(suppressed(&reporter, stage, &err), 100 - MAX_ERROR_EVENTS_PER_WINDOW);
+
+        // Other kinds of errors are sampled independently.
+        let other_err = Error::NotAValidator("not a validator".to_string());
+        assert!(reporter.report(stage, &other_err, &test_key(), || None).is_some());
+        assert!(reporter
+            .report(PartialWitnessErrorStage::ForwardedPart, &err, &test_key(), || None)
+            .is_some());
+        // So are the errors of the other shards.
+        assert!(reporter.report(stage, &err, &shard_key(1), || None).is_some());
+
+        // Within the window nothing more is reported.
+        clock.advance(ERROR_EVENTS_WINDOW - Duration::seconds(1));
+        assert!(reporter.report(stage, &err, &test_key(), || None).is_none());
+
+        // The budget is restored in the next window.
+        clock.advance(Duration::seconds(1));
+        let reported = (0..100)
+            .filter(|_| reporter.report(stage, &err, &test_key(), || None).is_some())
+            .count();
+        assert_eq!(reported, MAX_ERROR_EVENTS_PER_WINDOW);
+        assert_eq!(suppressed(&reporter, stage, &err), 100 - MAX_ERROR_EVENTS_PER_WINDOW);
+    }
+
+    #[test]
+    fn recent_errors_cover_the_current_windows() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut reporter = PartialWitnessErrorReporter::new(clock.clock());
+        let stage = PartialWitnessErrorStage::DecodeWitness;
+        let err = Error::InvalidPartialChunkStateWitness("bad witness".to_string());
+        for _ in 0..(MAX_ERROR_EVENTS_PER_WINDOW + 5) {
+            reporter.report(stage, &err, &test_key(), || None);
+        }
+        reporter.report(stage, &err, &shard_key(1), || None);
+        reporter.report(PartialWitnessErrorStage::OwnedPart, &err, &test_key(), || None);
+        // The suppressed errors count as well, the other stages don't.
+        assert_eq!(reporter.recent_errors(stage), MAX_ERROR_EVENTS_PER_WINDOW + 6);
+
+        clock.advance(ERROR_EVENTS_WINDOW);
+        assert_eq!(reporter.recent_errors(stage), 0);
+    }
+
+    #[test]
+    fn chunk_producer_is_only_looked_up_for_reported_errors() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut reporter = PartialWitnessErrorReporter::new(clock.clock());
+        let stage = PartialWitnessErrorStage::OwnedPart;
+        let err = Error::InvalidPartialChunkStateWitness("bad part".to_string());
+        let chunk_producer: AccountId = "producer".parse().unwrap();
+
+        let mut lookups = 0;
+        let mut events = vec![];
+        for _ in 0..100 {
+            events.extend(reporter.report(stage, &err, &test_key(), || {
+                lookups += 1;
+                Some(chunk_producer.clone())
+            }));
+        }
+        assert_eq!(lookups, MAX_ERROR_EVENTS_PER_WINDOW);
+        assert_eq!(events.len(), MAX_ERROR_EVENTS_PER_WINDOW);
+        assert_eq!(
+            events[0],
+            PartialWitnessErrorEvent {
+                stage: "owned_part",
+                error: err.prometheus_label_value(),
+                epoch_id: EpochId::default(),
+                shard_id: 0,
+                height_created: 1,
+                chunk_producer: Some(chunk_producer),
+                message: err.to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Backoff of the forwards of our parts while the network fails to send the witness messages.
+//!
+//! Every part we own is forwarded to all the other chunk validators of the chunk, so when the
+//! sends fail or queue up, e.g. because the node lost most of its connections, keeping up the full
+//! fan-out only makes it worse. The network reports the outcome of every witness message it sends,
+//! see `WitnessDeliveryReportMessage`, and the outcomes are kept per chunk validator as counts
+//! decaying exponentially with `ForwardBackoffConfig::half_life`. The destination set of the
+//! forwards of a shard is the set of their targets: once the failure rate of the sends to it rises
+//! above `ForwardBackoffConfig::engage_failure_rate`, the forwards of the shard back off, they go
+//! to the capped subset of the targets, see `ForwardTargets::capped`, and are sent together every
+//! `ForwardBackoffConfig::pacing`. They return to normal once the failure rate decays below
+//! `ForwardBackoffConfig::disengage_failure_rate`.
+//!
+//! The state of a shard is only updated when we forward one of its parts, so a shard we stop
+//! forwarding for, e.g. after the end of the epoch, keeps its last state until we forward again.
+
+use std::collections::{BTreeSet, HashMap};
+
+use near_async::time::{Duration, Instant};
+use near_chain_configs::ForwardBackoffConfig;
+use near_network::state_witness::{WitnessDelivery, WitnessDeliveryPath};
+use near_o11y::metrics::int_label;
+use near_primitives::types::{AccountId, ShardId};
+use time::ext::InstantExt as _;
+
+use crate::metrics;
+
+/// Decayed number of sends to a chunk validator below which its outcomes are forgotten.
+const MIN_RETAINED_SENDS: f64 = 0.01;
+
+/// Outcomes of the sends to a chunk validator, as of `updated_at`.
+#[derive(Clone, Copy, Debug)]
+struct SendOutcomes {
+    sends: f64,
+    failures: f64,
+    updated_at: Instant,
+}
+
+impl SendOutcomes {
+    fn decayed(&self, half_life: Duration, now: Instant) -> Self {
+        let elapsed = now.signed_duration_since(self.updated_at);
+        let factor = if elapsed <= Duration::ZERO {
+            1.0
+        } else if half_life <= Duration::ZERO {
+            0.0
+        } else {
+            0.5f64.powf(elapsed / half_life)
+        };
+        Self {
+            sends: self.sends * factor,
+            failures: self.failures * factor,
+            updated_at: self.updated_at.max(now),
+        }
+    }
+}
+
+pub struct ForwardBackoff {
+    config: ForwardBackoffConfig,
+    /// Outcomes of the recent sends per chunk validator. Only the validators receive the witness
+    /// messages and the decayed outcomes are dropped, so the map is bounded by the validators.
+    outcomes: HashMap<AccountId, SendOutcomes>,
+    /// Shards whose forwards currently back off.
+    engaged_shards: BTreeSet<ShardId>,
+}
+
+impl ForwardBackoff {
+    pub fn new(config: ForwardBackoffConfig) -> Self {
+        Self { config, outcomes: HashMap::new(), engaged_shards: BTreeSet::new() }
+    }
+
+    /// Records the outcomes of the sends reported by the network.
+    pub fn record_deliveries(&mut self, deliveries: &[WitnessDelivery], now: Instant) {
+        if !self.config.enabled {
+            return;
+        }
+        let half_life = self.config.half_life;
+        for delivery in deliveries {
+            let outcomes = self.outcomes.entry(delivery.target.clone()).or_insert(SendOutcomes {
+                sends: 0.0,
+                failures: 0.0,
+                updated_at: now,
+            });
+            *outcomes = outcomes.decayed(half_life, now);
+            outcomes.sends += 1.0;
+            if delivery.path == WitnessDeliveryPath::Failed {
+                outcomes.failures += 1.0;
+            }
+        }
+        self.outcomes
+            .retain(|_, outcomes| outcomes.decayed(half_life, now).sends >= MIN_RETAINED_SENDS);
+    }
+
+    /// Failure rate of the sends to `targets`, together with the decayed number of these sends.
+    fn failure_rate(&self, targets: &[AccountId], now: Instant) -> (f64, f64) {
+        let (sends, failures) = targets
+            .iter()
+            .filter_map(|target| self.outcomes.get(target))
+            .map(|outcomes| outcomes.decayed(self.config.half_life, now))
+            .fold((0.0, 0.0), |(sends, failures), outcomes| {
+                (sends + outcomes.sends, failures + outcomes.failures)
+            });
+        let failure_rate = if sends > 0.0 { failures / sends } else { 0.0 };
+        (failure_rate, sends)
+    }
+
+    /// Updates the state of the forwards of the shard with the current failure rate of the sends
+    /// to their `targets`, and returns whether they back off. Too few recent sends to tell the
+    /// failure rate return the forwards to normal.
+    pub fn update(&mut self, shard_id: ShardId, targets: &[AccountId], now: Instant) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let (failure_rate, sends) = self.failure_rate(targets, now);
+        let trusted = sends >= self.config.min_sends;
+        let engaged = self.engaged_shards.contains(&shard_id);
+        if !engaged && trusted && failure_rate > self.config.engage_failure_rate {
+            tracing::warn!(
+                target: "client",
+                shard_id,
+                failure_rate,
+                sends,
+                max_forward_targets = self.config.max_forward_targets,
+                "Forwards of the witness parts back off, the sends to their targets fail"
+            );
+            self.engaged_shards.insert(shard_id);
+            self.record_transition(shard_id, true);
+        } else if engaged && (!trusted || failure_rate < self.config.disengage_failure_rate) {
+            tracing::info!(
+                target: "client",
+                shard_id,
+                failure_rate,
+                sends,
+                "Forwards of the witness parts return to normal"
+            );
+            self.engaged_shards.remove(&shard_id);
+            self.record_transition(shard_id, false);
+        }
+        self.engaged_shards.contains(&shard_id)
+    }
+
+    fn record_transition(&self, shard_id: ShardId, engaged: bool) {
+        let shard_id = int_label(shard_id);
+        let transition = if engaged { "engaged" } else { "disengaged" };
+        metrics::PARTIAL_WITNESS_FORWARD_BACKOFF_TRANSITIONS
+            .with_label_values(&[&shard_id, transition])
+            .inc();
+        metrics::PARTIAL_WITNESS_FORWARD_BACKOFF_ENGAGED
+            .with_label_values(&[&shard_id])
+            .set(engaged as i64);
+    }
+
+    /// Shards whose forwards currently back off, ordered by shard id.
+    pub fn engaged_shards(&self) -> Vec<ShardId> {
+        self.engaged_shards.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::{FakeClock, Utc};
+    use near_network::state_witness::WitnessRoutingPreference;
+
+    use super::*;
+
+    const SHARD_ID: ShardId = 0;
+
+    fn targets() -> Vec<AccountId> {
+        ["test1", "test2", "test3", "test4"].iter().map(|target| target.parse().unwrap()).collect()
+    }
+
+    /// One send to every target, every `failure_every`-th of them failing if set.
+    fn deliveries(failure_every: Option<usize>) -> Vec<WitnessDelivery> {
+        targets()
+            .into_iter()
+            .enumerate()
+            .map(|(i, target)| WitnessDelivery {
+                target,
+                preference: WitnessRoutingPreference::Default,
+                path: match failure_every {
+                    Some(failure_every) if i % failure_every == 0 => WitnessDeliveryPath::Failed,
+                    _ => WitnessDeliveryPath::Routed,
+                },
+            })
+            .collect()
+    }
+
+    fn config() -> ForwardBackoffConfig {
+        ForwardBackoffConfig {
+            half_life: Duration::seconds(10),
+            engage_failure_rate: 0.3,
+            disengage_failure_rate: 0.1,
+            min_sends: 20.0,
+            ..ForwardBackoffConfig::default()
+        }
+    }
+
+    #[test]
+    fn backs_off_while_half_of_the_sends_fail() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut backoff = ForwardBackoff::new(config());
+
+        // A few failures are not enough to tell the failure rate.
+        backoff.record_deliveries(&deliveries(Some(2)), clock.now());
+        assert!(!backoff.update(SHARD_ID, &targets(), clock.now()));
+
+        for _ in 0..10 {
+            clock.advance(Duration::milliseconds(100));
+            backoff.record_deliveries(&deliveries(Some(2)), clock.now());
+        }
+        assert!(backoff.update(SHARD_ID, &targets(), clock.now()));
+        assert_eq!(backoff.engaged_shards(), vec![SHARD_ID]);
+        // The sends to other chunk validators don't tell anything about the targets.
+        assert!(!backoff.update(SHARD_ID + 1, &["test5".parse().unwrap()], clock.now()));
+
+        // A quarter of the sends failing is between the thresholds, the forwards keep backing off.
+        for _ in 0..20 {
+            clock.advance(Duration::milliseconds(100));
+            backoff.record_deliveries(&deliveries(Some(4)), clock.now());
+        }
+        assert!(backoff.update(SHARD_ID, &targets(), clock.now()));
+
+        // The failures decay once the sends succeed again.
+        for _ in 0..300 {
+            clock.advance(Duration::milliseconds(100));
+            backoff.record_deliveries(&deliveries(None), clock.now());
+        }
+        assert!(!backoff.update(SHARD_ID, &targets(), clock.now()));
+        assert!(backoff.engaged_shards().is_empty());
+    }
+
+    #[test]
+    fn returns_to_normal_once_the_sends_decay() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut backoff = ForwardBackoff::new(config());
+        for _ in 0..10 {
+            backoff.record_deliveries(&deliveries(Some(1)), clock.now());
+        }
+        assert!(backoff.update(SHARD_ID, &targets(), clock.now()));
+
+        // Nothing is sent anymore, the 40 sends decay below `min_sends` after a half-life.
+        clock.advance(Duration::seconds(15));
+        assert!(!backoff.update(SHARD_ID, &targets(), clock.now()));
+
+        let mut disabled = ForwardBackoff::new(ForwardBackoffConfig { enabled: false, ..config() });
+        for _ in 0..10 {
+            disabled.record_deliveries(&deliveries(Some(1)), clock.now());
+        }
+        assert!(!disabled.update(SHARD_ID, &targets(), clock.now()));
+    }
+}
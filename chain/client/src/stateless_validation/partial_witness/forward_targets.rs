@@ -0,0 +1,289 @@
+//! Chunk validators to which we forward our witness parts, memoized per chunk. The targets only
+//! depend on the chunk validator assignments and the chunk producer of the chunk, so they are
+//! computed once per `ChunkProductionKey` however many parts of the chunk we forward.
+
+use std::num::NonZeroUsize;
+
+use itertools::Itertools;
+use lru::LruCache;
+use near_chain::Error;
+use near_network::state_witness::WitnessRoutingHints;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// Where the parts we own for a chunk are forwarded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardTargets {
+    /// Chunk validators of the chunk except for us and the chunk producer.
+    pub targets: Vec<AccountId>,
+    /// Owners of the parts of the chunk ordered by part_ord, see `capped`.
+    pub part_owners: Vec<AccountId>,
+    pub routing_hints: WitnessRoutingHints,
+    /// Validators pre-tracking the shard of the chunk, see `pre_tracking::pre_tracking_targets`.
+    /// They are not chunk validators of the chunk, so the fan-out caps don't apply to them.
+    pub pre_tracking_targets: Vec<AccountId>,
+}
+
+impl ForwardTargets {
+    /// Up to `max_targets` of the targets to which the part with `part_ord` is forwarded when
+    /// the fan-out is capped, see `PartialWitnessConfig::max_forward_targets`.
+    ///
+    /// The part ordinals are arranged in a ring shuffled with the chunk production key as the
+    /// seed, and the part is forwarded to the owners of the ordinals following it in the ring.
+    /// Every owner then gets the parts of the `max_targets` ordinals preceding its own, so the
+    /// subsets differ per ordinal and together cover every chunk validator several times. Shuffling
+    /// the ring independently for every ordinal would leave some chunk validators without any
+    /// forward. The shuffle only has to be deterministic within a node, every owner picks the
+    /// targets of its own parts.
+    pub fn capped(
+        &self,
+        key: &ChunkProductionKey,
+        part_ord: usize,
+        max_targets: usize,
+    ) -> Vec<AccountId> {
+        let seed = CryptoHash::hash_borsh((key.epoch_id, key.shard_id, key.height_created));
+        let mut ring = (0..self.part_owners.len()).collect_vec();
+        ring.shuffle(&mut StdRng::from_seed(seed.0));
+        let Some(position) = ring.iter().position(|ord| *ord == part_ord) else {
+            return vec![];
+        };
+        ring.iter()
+            .cycle()
+            .skip(position + 1)
+            .take(ring.len() - 1)
+            .map(|ord| &self.part_owners[*ord])
+            .filter(|owner| self.targets.contains(owner))
+            .unique()
+            .take(max_targets)
+            .cloned()
+            .collect()
+    }
+}
+
+struct CacheEntry {
+    /// Account for which the targets were computed, as the validator signer may be swapped.
+    my_account_id: AccountId,
+    forward_targets: ForwardTargets,
+}
+
+pub struct ForwardTargetsCache {
+    entries: LruCache<ChunkProductionKey, CacheEntry>,
+}
+
+impl ForwardTargetsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: LruCache::new(NonZeroUsize::new(capacity).unwrap()) }
+    }
+
+    /// Returns the forward targets of the chunk for `my_account_id`, calling `compute` only if
+    /// they are not cached yet.
+    pub fn get_or_try_insert(
+        &mut self,
+        key: &ChunkProductionKey,
+        my_account_id: &AccountId,
+        compute: impl FnOnce() -> Result<ForwardTargets, Error>,
+    ) -> Result<ForwardTargets, Error> {
+        if let Some(entry) = self.entries.get(key) {
+            if &entry.my_account_id == my_account_id {
+                return Ok(entry.forward_targets.clone());
+            }
+        }
+        let forward_targets = compute()?;
+        self.entries.put(
+            key.clone(),
+            CacheEntry {
+                my_account_id: my_account_id.clone(),
+                forward_targets: forward_targets.clone(),
+            },
+        );
+        Ok(forward_targets)
+    }
+
+    /// Drops the targets of the chunks created at `height` or below, returns the number of the
+    /// dropped entries.
+    pub fn drop_up_to(&mut self, height: BlockHeight) -> usize {
+        let keys_to_drop: Vec<ChunkProductionKey> = self
+            .entries
+            .iter()
+            .filter(|(key, _)| key.height_created <= height)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys_to_drop.iter().filter(|key| self.entries.pop(key).is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+    use near_chain::Error;
+    use near_epoch_manager::EpochManagerAdapter;
+    use near_network::state_witness::WitnessRoutingHints;
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::types::{AccountId, EpochId};
+    use near_store::test_utils::create_test_store;
+
+    use itertools::Itertools;
+
+    use super::{ForwardTargets, ForwardTargetsCache};
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 0, height_created }
+    }
+
+    #[test]
+    fn epoch_manager_is_consulted_once_per_key() {
+        let validators = vec![account("test0"), account("test1"), account("test2")];
+        let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![validators]);
+        let epoch_manager = MockEpochManager::new_with_validators(create_test_store(), vs, 100);
+        let consulted = Cell::new(0);
+        let compute = |key: &ChunkProductionKey| -> Result<ForwardTargets, Error> {
+            consulted.set(consulted.get() + 1);
+            let assignments = epoch_manager.get_chunk_validator_assignments(
+                &key.epoch_id,
+                key.shard_id,
+                key.height_created,
+            )?;
+            let chunk_producer = epoch_manager.get_chunk_producer(
+                &key.epoch_id,
+                key.height_created,
+                key.shard_id,
+            )?;
+            let part_owners = assignments.ordered_chunk_validators();
+            let targets = part_owners
+                .iter()
+                .filter(|validator| {
+                    *validator != &account("test0") && *validator != &chunk_producer
+                })
+                .cloned()
+                .collect();
+            Ok(ForwardTargets {
+                targets,
+                part_owners,
+                routing_hints: WitnessRoutingHints::default(),
+                pre_tracking_targets: vec![],
+            })
+        };
+
+        let mut cache = ForwardTargetsCache::new(10);
+        let me = account("test0");
+        let first = cache.get_or_try_insert(&key(5), &me, || compute(&key(5))).unwrap();
+        for _ in 0..5 {
+            let cached = cache.get_or_try_insert(&key(5), &me, || compute(&key(5))).unwrap();
+            assert_eq!(cached, first);
+        }
+        assert_eq!(consulted.get(), 1);
+
+        cache.get_or_try_insert(&key(6), &me, || compute(&key(6))).unwrap();
+        cache.get_or_try_insert(&key(6), &me, || compute(&key(6))).unwrap();
+        assert_eq!(consulted.get(), 2);
+
+        // The targets computed for another account are not reused after a signer swap.
+        cache.get_or_try_insert(&key(5), &account("test1"), || compute(&key(5))).unwrap();
+        assert_eq!(consulted.get(), 3);
+    }
+
+    #[test]
+    fn targets_are_dropped_up_to_height() {
+        let mut cache = ForwardTargetsCache::new(10);
+        let me = account("test0");
+        for height in 5..9 {
+            cache
+                .get_or_try_insert(&key(height), &me, || {
+                    Ok(ForwardTargets {
+                        targets: vec![account("test1")],
+                        part_owners: vec![account("test0"), account("test1")],
+                        routing_hints: WitnessRoutingHints::default(),
+                        pre_tracking_targets: vec![],
+                    })
+                })
+                .unwrap();
+        }
+        assert_eq!(cache.drop_up_to(6), 2);
+        assert_eq!(cache.drop_up_to(6), 0);
+        assert_eq!(cache.drop_up_to(8), 2);
+    }
+
+    /// Forward targets of every part of a chunk with `num_validators` chunk validators, each
+    /// owning one part, when `chunk_producer` produces the chunk.
+    fn capped_targets_per_owner(
+        key: &ChunkProductionKey,
+        num_validators: usize,
+        chunk_producer: usize,
+        max_targets: usize,
+    ) -> Vec<(AccountId, Vec<AccountId>)> {
+        let part_owners = (0..num_validators).map(|i| account(&format!("test{i}"))).collect_vec();
+        let chunk_producer = &part_owners[chunk_producer];
+        (0..num_validators)
+            .map(|part_ord| {
+                let owner = &part_owners[part_ord];
+                let forward_targets = ForwardTargets {
+                    targets: part_owners
+                        .iter()
+                        .filter(|validator| *validator != owner && *validator != chunk_producer)
+                        .cloned()
+                        .collect(),
+                    part_owners: part_owners.clone(),
+                    routing_hints: WitnessRoutingHints::default(),
+                    pre_tracking_targets: vec![],
+                };
+                (owner.clone(), forward_targets.capped(key, part_ord, max_targets))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn capped_targets_cover_every_validator() {
+        for (num_validators, max_targets) in [(4, 1), (10, 3), (50, 4), (100, 8), (300, 10)] {
+            for height_created in 0..50 {
+                let key = key(height_created);
+                let chunk_producer = height_created as usize % num_validators;
+                let targets_per_owner =
+                    capped_targets_per_owner(&key, num_validators, chunk_producer, max_targets);
+                let mut forwards_received = HashMap::<AccountId, usize>::new();
+                for (owner, targets) in &targets_per_owner {
+                    assert_eq!(targets.len(), max_targets);
+                    assert!(targets.iter().all_unique());
+                    assert!(!targets.contains(owner));
+                    assert!(!targets.contains(&targets_per_owner[chunk_producer].0));
+                    for target in targets {
+                        *forwards_received.entry(target.clone()).or_default() += 1;
+                    }
+                }
+                // Every chunk validator but the chunk producer gets the parts of at least
+                // `max_targets` owners. The chunk producer forwards its own part as well.
+                assert_eq!(forwards_received.len(), num_validators - 1);
+                assert!(
+                    forwards_received.values().all(|received| *received >= max_targets),
+                    "{num_validators} validators, {max_targets} targets: {forwards_received:?}"
+                );
+                // Only the owner preceding the chunk producer in the ring can have the same
+                // subset as the chunk producer, which skips itself.
+                let distinct_subsets =
+                    targets_per_owner.iter().map(|(_, targets)| targets).unique().count();
+                assert!(distinct_subsets >= num_validators - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn capped_targets_are_deterministic_per_chunk() {
+        let targets = capped_targets_per_owner(&key(5), 100, 0, 8);
+        assert_eq!(targets, capped_targets_per_owner(&key(5), 100, 0, 8));
+        assert_ne!(targets, capped_targets_per_owner(&key(6), 100, 0, 8));
+        // A cap above the number of the targets forwards to all of them.
+        for (owner, targets) in capped_targets_per_owner(&key(5), 10, 0, 20).into_iter().skip(1) {
+            assert_eq!(targets.len(), 8, "{owner}");
+        }
+    }
+}
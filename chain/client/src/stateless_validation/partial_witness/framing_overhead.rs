@@ -0,0 +1,158 @@
+//! Bytes of the part messages sent to the network beyond the bytes of the parts themselves.
+//!
+//! Besides its payload, the erasure coded bytes of the witness, every part message repeats the
+//! key of the chunk, the owner of the part, the signature of the chunk producer and, depending on
+//! the format, the send time, the inclusion proof of the part and so on. To tell how much of the
+//! distribution bandwidth this framing takes, the serialized size of every part handed to the
+//! network adapter is compared to the size of its payload. The serialized size is the one computed
+//! anyway to tell whether the part is sent in fragments, so the part isn't serialized once more.
+//! The routing envelope added by the network is not covered. The totals per shard are logged once
+//! the head leaves their epoch.
+
+use std::collections::BTreeMap;
+
+use near_o11y::metrics::int_label;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::types::{EpochId, ShardId};
+
+use crate::metrics;
+
+/// Parts sent for the chunks of a shard in an epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FramingTotals {
+    pub num_parts: u64,
+    pub payload_bytes: u64,
+    pub framing_bytes: u64,
+}
+
+impl FramingTotals {
+    /// Percentage of the sent bytes taken by the framing.
+    fn framing_percent(&self) -> f64 {
+        let total = self.payload_bytes + self.framing_bytes;
+        if total == 0 {
+            return 0.0;
+        }
+        self.framing_bytes as f64 * 100.0 / total as f64
+    }
+}
+
+pub struct FramingOverhead {
+    totals: BTreeMap<(EpochId, ShardId), FramingTotals>,
+}
+
+impl FramingOverhead {
+    pub fn new() -> Self {
+        Self { totals: BTreeMap::new() }
+    }
+
+    /// Records the part sent in a message of `serialized_size` bytes, the fragments of a part
+    /// count as one message.
+    pub fn record_part(
+        &mut self,
+        partial_witness: &PartialEncodedStateWitness,
+        serialized_size: usize,
+    ) {
+        let key = partial_witness.chunk_production_key();
+        let payload_bytes = partial_witness.part_size();
+        let framing_bytes = serialized_size.saturating_sub(payload_bytes);
+        let shard_id_label = int_label(key.shard_id);
+        if payload_bytes > 0 {
+            metrics::PARTIAL_WITNESS_PART_FRAMING_RATIO
+                .with_label_values(&[&shard_id_label])
+                .observe(serialized_size as f64 / payload_bytes as f64);
+        }
+        metrics::PARTIAL_WITNESS_SENT_PART_BYTES
+            .with_label_values(&[&shard_id_label, "payload"])
+            .inc_by(payload_bytes as u64);
+        metrics::PARTIAL_WITNESS_SENT_PART_BYTES
+            .with_label_values(&[&shard_id_label, "framing"])
+            .inc_by(framing_bytes as u64);
+        let totals = self.totals.entry((key.epoch_id, key.shard_id)).or_default();
+        totals.num_parts += 1;
+        totals.payload_bytes += payload_bytes as u64;
+        totals.framing_bytes += framing_bytes as u64;
+    }
+
+    /// Logs and forgets the totals of the epochs other than the epoch of the head and the next
+    /// one, in which no more parts are sent. Returns them by epoch and shard.
+    pub fn on_head_updated(
+        &mut self,
+        epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+    ) -> Vec<((EpochId, ShardId), FramingTotals)> {
+        let (finished, kept) =
+            std::mem::take(&mut self.totals).into_iter().partition(|((part_epoch_id, _), _)| {
+                part_epoch_id != epoch_id && part_epoch_id != next_epoch_id
+            });
+        self.totals = kept;
+        let finished: Vec<_> = finished.into_iter().collect();
+        for ((epoch_id, shard_id), totals) in &finished {
+            tracing::info!(
+                target: "client",
+                ?epoch_id,
+                shard_id,
+                num_parts = totals.num_parts,
+                payload_bytes = totals.payload_bytes,
+                framing_bytes = totals.framing_bytes,
+                framing_percent = format!("{:.2}", totals.framing_percent()),
+                "Framing overhead of the witness parts sent in the epoch"
+            );
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+    use near_primitives::test_utils::create_test_signer;
+
+    use super::*;
+
+    fn part(
+        epoch_id: EpochId,
+        shard_id: ShardId,
+        payload_size: usize,
+    ) -> PartialEncodedStateWitness {
+        let signer = create_test_signer("test0");
+        let witness = ChunkStateWitness::new_dummy(5, shard_id, CryptoHash::default());
+        PartialEncodedStateWitness::new(
+            epoch_id,
+            witness.chunk_header,
+            0,
+            vec![7; payload_size],
+            payload_size * 2,
+            &signer,
+        )
+    }
+
+    #[test]
+    fn totals_are_reported_once_the_head_leaves_the_epoch() {
+        let mut framing_overhead = FramingOverhead::new();
+        let epoch = EpochId(CryptoHash::hash_bytes(b"epoch"));
+        let next_epoch = EpochId(CryptoHash::hash_bytes(b"next"));
+        for (epoch_id, shard_id) in [(epoch, 0), (epoch, 0), (epoch, 1), (next_epoch, 0)] {
+            let partial_witness = part(epoch_id, shard_id, 1000);
+            let serialized_size = borsh::object_length(&partial_witness).unwrap();
+            assert!(serialized_size > 1000);
+            framing_overhead.record_part(&partial_witness, serialized_size);
+        }
+        assert!(framing_overhead.on_head_updated(&epoch, &next_epoch).is_empty());
+
+        let after_next = EpochId(CryptoHash::hash_bytes(b"after next"));
+        let finished = framing_overhead.on_head_updated(&next_epoch, &after_next);
+        assert_eq!(finished.len(), 2);
+        let ((_, shard_id), totals) = finished[0];
+        assert_eq!(shard_id, 0);
+        assert_eq!(totals.num_parts, 2);
+        assert_eq!(totals.payload_bytes, 2000);
+        // The framing of the same kind of parts is the same.
+        assert_eq!(totals.framing_bytes, 2 * finished[1].1.framing_bytes);
+        assert!(totals.framing_percent() > 0.0 && totals.framing_percent() < 100.0);
+
+        let finished = framing_overhead.on_head_updated(&after_next, &after_next);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0, (next_epoch, 0));
+    }
+}
@@ -0,0 +1,159 @@
+//! Crash reports of the panics in the handlers of the partial witness actor.
+//!
+//! A panic in a handler, e.g. an index bug in the tracker, used to either abort the node or unwind
+//! through actix without saying which message caused it, and in the latter case the witnesses
+//! silently stopped flowing. `catch_handler_panic` runs every handler, see
+//! `PartialWitnessActor::wrap_handler`, and a panic hook reports the panic with the type of the
+//! message, the chunk it was about if the handler set it, see `set_handler_key`, and the backtrace.
+//! The report is made by the hook, so that it is made even by the builds which abort on panic,
+//! which neard is in the dev and release profiles. The hook is installed once at the startup of
+//! neard, see `install_handler_panic_hook`. Only the builds which unwind get the report back to
+//! apply `HandlerPanicPolicy`.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::sync::Once;
+
+use near_primitives::stateless_validation::ChunkProductionKey;
+
+use crate::metrics;
+
+thread_local! {
+    /// Handler running on this thread, if any.
+    static CURRENT_HANDLER: RefCell<Option<HandlerContext>> = const { RefCell::new(None) };
+    /// Report of the last panic in a handler on this thread, until `catch_handler_panic` takes it.
+    static LAST_REPORT: RefCell<Option<HandlerPanicReport>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug)]
+struct HandlerContext {
+    message_type: &'static str,
+    key: Option<ChunkProductionKey>,
+}
+
+/// Report of a panic in the handler of a message.
+#[derive(Clone, Debug)]
+pub struct HandlerPanicReport {
+    /// Type name of the message being handled.
+    pub message_type: &'static str,
+    /// Chunk the message was about, if the handler got to set it.
+    pub key: Option<ChunkProductionKey>,
+    pub panic_message: String,
+    /// None if the panic hook was replaced after ours, so the backtrace couldn't be captured.
+    pub backtrace: Option<String>,
+}
+
+/// Panic caught in a handler, to be re-raised with `std::panic::resume_unwind(payload)`.
+pub struct HandlerPanic {
+    pub report: HandlerPanicReport,
+    pub payload: Box<dyn Any + Send>,
+}
+
+/// Runs the handler of a message of type `M` and returns the panic in it, if any.
+pub(super) fn catch_handler_panic<M, R>(handler: impl FnOnce() -> R) -> Result<R, HandlerPanic> {
+    LAST_REPORT.with(|last| last.take());
+    let context = HandlerContext { message_type: std::any::type_name::<M>(), key: None };
+    // The handlers of some messages handle other messages, so the context is restored after.
+    let previous = CURRENT_HANDLER.with(|current| current.replace(Some(context.clone())));
+    let result = std::panic::catch_unwind(AssertUnwindSafe(handler));
+    let context = CURRENT_HANDLER.with(|current| current.replace(previous)).unwrap_or(context);
+    result.map_err(|payload| {
+        let report = LAST_REPORT.with(|last| last.take()).unwrap_or_else(|| HandlerPanicReport {
+            message_type: context.message_type,
+            key: context.key,
+            panic_message: panic_message(payload.as_ref()),
+            backtrace: None,
+        });
+        HandlerPanic { report, payload }
+    })
+}
+
+/// Sets the chunk the message being handled is about, reported if the handler panics.
+pub(super) fn set_handler_key(key: &ChunkProductionKey) {
+    CURRENT_HANDLER.with(|current| {
+        if let Some(context) = current.borrow_mut().as_mut() {
+            context.key = Some(key.clone());
+        }
+    });
+}
+
+/// Installs the panic hook reporting the panics in the handlers, in front of the hook installed
+/// so far. Without it the panics are still caught and handled, but reported without a backtrace.
+pub fn install_handler_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            report_handler_panic(info);
+            previous_hook(info);
+        }));
+    });
+}
+
+/// Reports the panic if it happened in a handler, the panics elsewhere are left to the previous
+/// hook.
+fn report_handler_panic(info: &PanicHookInfo) {
+    let Some(context) = CURRENT_HANDLER.with(|current| current.borrow().clone()) else {
+        return;
+    };
+    let report = HandlerPanicReport {
+        message_type: context.message_type,
+        key: context.key,
+        panic_message: panic_message(info.payload()),
+        backtrace: Some(Backtrace::force_capture().to_string()),
+    };
+    metrics::PARTIAL_WITNESS_HANDLER_PANICS.with_label_values(&[report.message_type]).inc();
+    tracing::error!(
+        target: "client",
+        message_type = report.message_type,
+        key = ?report.key,
+        panic_message = %report.panic_message,
+        location = ?info.location(),
+        backtrace = report.backtrace.as_deref().unwrap_or_default(),
+        "Partial witness actor panicked while handling a message"
+    );
+    LAST_REPORT.with(|last| *last.borrow_mut() = Some(report));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::EpochId;
+
+    use super::*;
+
+    struct TestMessage;
+
+    #[test]
+    fn panic_is_reported_with_handler_context() {
+        let key =
+            ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 3, height_created: 7 };
+        install_handler_panic_hook();
+        assert_eq!(catch_handler_panic::<TestMessage, _>(|| 42).ok(), Some(42));
+
+        let panic = catch_handler_panic::<TestMessage, ()>(|| {
+            set_handler_key(&key);
+            panic!("index out of bounds");
+        })
+        .err()
+        .unwrap();
+        assert!(panic.report.message_type.ends_with("TestMessage"));
+        assert_eq!(panic.report.key, Some(key));
+        assert_eq!(panic.report.panic_message, "index out of bounds");
+        assert!(panic.report.backtrace.is_some());
+        assert_eq!(panic_message(panic.payload.as_ref()), "index out of bounds");
+        // The context doesn't outlive the handler.
+        assert!(CURRENT_HANDLER.with(|current| current.borrow().is_none()));
+    }
+}
@@ -0,0 +1,81 @@
+//! Production times of the blocks around the head of the chain, as far as the PartialWitnessActor
+//! can tell from the head updates sent by the client, see `ChainHeadUpdatedMessage`.
+
+use near_async::time::{Duration, Utc};
+use near_primitives::types::BlockHeight;
+
+/// Weight of a new sample in the exponential moving average of the block interval.
+const BLOCK_INTERVAL_SMOOTHING_FACTOR: f64 = 0.1;
+
+pub struct HeadTimeline {
+    /// Height and timestamp of the latest head.
+    head: Option<(BlockHeight, Utc)>,
+    /// Smoothed interval between the blocks, None until two heads were seen.
+    block_interval: Option<Duration>,
+}
+
+impl HeadTimeline {
+    pub fn new() -> Self {
+        Self { head: None, block_interval: None }
+    }
+
+    pub fn on_head_updated(&mut self, height: BlockHeight, timestamp: Utc) {
+        if let Some((last_height, last_timestamp)) = self.head {
+            if height > last_height {
+                let sample = (timestamp - last_timestamp) / (height - last_height) as f64;
+                self.block_interval = Some(match self.block_interval {
+                    None => sample,
+                    Some(interval) => {
+                        interval * (1.0 - BLOCK_INTERVAL_SMOOTHING_FACTOR)
+                            + sample * BLOCK_INTERVAL_SMOOTHING_FACTOR
+                    }
+                });
+            }
+        }
+        self.head = Some((height, timestamp));
+    }
+
+    pub fn head_height(&self) -> Option<BlockHeight> {
+        self.head.map(|(height, _)| height)
+    }
+
+    /// Timestamp of the block at `height` if it's the head, otherwise the time at which it was or
+    /// is expected to be produced, extrapolated from the head with the observed block interval.
+    pub fn block_time(&self, height: BlockHeight) -> Option<Utc> {
+        let (head_height, head_timestamp) = self.head?;
+        if height == head_height {
+            return Some(head_timestamp);
+        }
+        let blocks = height as f64 - head_height as f64;
+        Some(head_timestamp + self.block_interval? * blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64) -> Utc {
+        Utc::UNIX_EPOCH + Duration::milliseconds(millis)
+    }
+
+    #[test]
+    fn block_time_is_extrapolated_from_head() {
+        let mut timeline = HeadTimeline::new();
+        assert_eq!(timeline.block_time(10), None);
+        timeline.on_head_updated(10, at(10_000));
+        assert_eq!(timeline.head_height(), Some(10));
+        assert_eq!(timeline.block_time(10), Some(at(10_000)));
+        // The block interval is unknown until the second head.
+        assert_eq!(timeline.block_time(11), None);
+
+        timeline.on_head_updated(12, at(12_000));
+        assert_eq!(timeline.block_time(12), Some(at(12_000)));
+        assert_eq!(timeline.block_time(13), Some(at(13_000)));
+        assert_eq!(timeline.block_time(11), Some(at(11_000)));
+
+        // The interval follows the new samples slowly.
+        timeline.on_head_updated(13, at(15_000));
+        assert_eq!(timeline.block_time(14), Some(at(16_200)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Whether the node is currently able to perform its chunk validator duties, as a single signal
+//! for the load balancers and the runbooks, see `PartialWitnessActor::stateless_validation_health`.
+//!
+//! The health is computed on demand from the state the actor keeps anyway, as a few independent
+//! checks with the thresholds of `StatelessValidationHealthConfig`. The node is healthy when all
+//! the checks pass. The actor reports it to the client every `HEALTH_REPORT_PERIOD`, which
+//! serves it in the status RPC.
+
+use near_async::time::Duration;
+use near_chain_configs::StatelessValidationHealthConfig;
+use near_primitives::views::{StatelessValidationHealthCheckView, StatelessValidationHealthView};
+
+/// How often the actor reports its health to the client.
+pub const HEALTH_REPORT_PERIOD: Duration = Duration::seconds(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthCheck {
+    /// The node has a validator signer.
+    SignerPresent,
+    /// The block of the head is at most `max_head_age` old.
+    HeadFresh,
+    /// The incomplete witnesses retain at most `max_parts_cache_size`.
+    PartsCacheWithinBudget,
+    /// At most `max_recent_decode_failures` witnesses failed to decode within the last minute.
+    RecentDecodeFailures,
+    /// The chunk validator assignments of all the shards at the head can be looked up.
+    AssignmentsResolvable,
+}
+
+impl HealthCheck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthCheck::SignerPresent => "signer_present",
+            HealthCheck::HeadFresh => "head_fresh",
+            HealthCheck::PartsCacheWithinBudget => "parts_cache_within_budget",
+            HealthCheck::RecentDecodeFailures => "recent_decode_failures",
+            HealthCheck::AssignmentsResolvable => "assignments_resolvable",
+        }
+    }
+}
+
+/// State of the actor the checks are evaluated on.
+#[derive(Debug, Clone)]
+pub struct HealthInputs {
+    pub signer_present: bool,
+    /// Age of the block of the head, None if the head is not known yet.
+    pub head_age: Option<Duration>,
+    /// Memory retained by the incomplete witnesses.
+    pub parts_cache_size: usize,
+    /// Witnesses which failed to decode within the last minute.
+    pub recent_decode_failures: usize,
+    /// Why the chunk validator assignments at the head couldn't be looked up, if they couldn't.
+    pub assignments_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheckResult {
+    pub check: HealthCheck,
+    pub healthy: bool,
+    /// What the check found, e.g. the measured value and the threshold.
+    pub details: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatelessValidationHealth {
+    pub checks: Vec<HealthCheckResult>,
+}
+
+impl StatelessValidationHealth {
+    pub fn evaluate(config: &StatelessValidationHealthConfig, inputs: &HealthInputs) -> Self {
+        let signer_present = HealthCheckResult {
+            check: HealthCheck::SignerPresent,
+            healthy: inputs.signer_present,
+            details: if inputs.signer_present { "present" } else { "missing" }.to_owned(),
+        };
+        let head_fresh = match inputs.head_age {
+            Some(head_age) => HealthCheckResult {
+                check: HealthCheck::HeadFresh,
+                healthy: head_age <= config.max_head_age,
+                details: format!(
+                    "head block is {head_age} old, at most {} expected",
+                    config.max_head_age
+                ),
+            },
+            None => HealthCheckResult {
+                check: HealthCheck::HeadFresh,
+                healthy: false,
+                details: "head unknown".to_owned(),
+            },
+        };
+        let max_parts_cache_size = config.max_parts_cache_size.as_u64() as usize;
+        let parts_cache = HealthCheckResult {
+            check: HealthCheck::PartsCacheWithinBudget,
+            healthy: inputs.parts_cache_size <= max_parts_cache_size,
+            details: format!(
+                "{} bytes retained, at most {max_parts_cache_size} expected",
+                inputs.parts_cache_size
+            ),
+        };
+        let decode_failures = HealthCheckResult {
+            check: HealthCheck::RecentDecodeFailures,
+            healthy: inputs.recent_decode_failures <= config.max_recent_decode_failures,
+            details: format!(
+                "{} decode failures within the last minute, at most {} expected",
+                inputs.recent_decode_failures, config.max_recent_decode_failures
+            ),
+        };
+        let assignments = HealthCheckResult {
+            check: HealthCheck::AssignmentsResolvable,
+            healthy: inputs.assignments_error.is_none(),
+            details: inputs.assignments_error.clone().unwrap_or_else(|| "resolvable".to_owned()),
+        };
+        Self { checks: vec![signer_present, head_fresh, parts_cache, decode_failures, assignments] }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|result| result.healthy)
+    }
+
+    pub fn is_check_healthy(&self, check: HealthCheck) -> bool {
+        self.checks.iter().any(|result| result.check == check && result.healthy)
+    }
+
+    /// Names of the checks which fail.
+    pub fn failed_checks(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter(|result| !result.healthy)
+            .map(|result| result.check.as_str().to_owned())
+            .collect()
+    }
+
+    pub fn to_view(&self) -> StatelessValidationHealthView {
+        StatelessValidationHealthView {
+            healthy: self.is_healthy(),
+            checks: self
+                .checks
+                .iter()
+                .map(|result| StatelessValidationHealthCheckView {
+                    name: result.check.as_str().to_owned(),
+                    healthy: result.healthy,
+                    details: result.details.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+    use near_async::time::Duration;
+    use near_chain_configs::StatelessValidationHealthConfig;
+
+    use super::{HealthCheck, HealthInputs, StatelessValidationHealth};
+
+    fn healthy_inputs() -> HealthInputs {
+        HealthInputs {
+            signer_present: true,
+            head_age: Some(Duration::seconds(1)),
+            parts_cache_size: 1000,
+            recent_decode_failures: 0,
+            assignments_error: None,
+        }
+    }
+
+    /// Evaluates the inputs modified by `modify`, and checks that only `failing` fails.
+    fn check_fails_alone(failing: HealthCheck, modify: impl FnOnce(&mut HealthInputs)) {
+        let config = StatelessValidationHealthConfig {
+            max_head_age: Duration::seconds(10),
+            max_parts_cache_size: ByteSize::b(1000),
+            max_recent_decode_failures: 2,
+            fail_health_check: false,
+        };
+        assert!(StatelessValidationHealth::evaluate(&config, &healthy_inputs()).is_healthy());
+
+        let mut inputs = healthy_inputs();
+        modify(&mut inputs);
+        let health = StatelessValidationHealth::evaluate(&config, &inputs);
+        assert!(!health.is_healthy());
+        assert_eq!(health.failed_checks(), vec![failing.as_str().to_owned()]);
+        assert!(!health.is_check_healthy(failing));
+
+        let view = health.to_view();
+        assert!(!view.healthy);
+        assert_eq!(view.checks.len(), 5);
+    }
+
+    #[test]
+    fn each_check_fails_alone() {
+        check_fails_alone(HealthCheck::SignerPresent, |inputs| inputs.signer_present = false);
+        check_fails_alone(HealthCheck::HeadFresh, |inputs| {
+            inputs.head_age = Some(Duration::seconds(11))
+        });
+        check_fails_alone(HealthCheck::HeadFresh, |inputs| inputs.head_age = None);
+        check_fails_alone(HealthCheck::PartsCacheWithinBudget, |inputs| {
+            inputs.parts_cache_size = 1001
+        });
+        check_fails_alone(HealthCheck::RecentDecodeFailures, |inputs| {
+            inputs.recent_decode_failures = 3
+        });
+        check_fails_alone(HealthCheck::AssignmentsResolvable, |inputs| {
+            inputs.assignments_error = Some("epoch unknown".to_owned())
+        });
+    }
+}
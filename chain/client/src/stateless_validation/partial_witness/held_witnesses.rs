@@ -0,0 +1,176 @@
+//! Decoded witnesses held back from the client, see `PartialWitnessConfig::client_witness_credits`.
+//!
+//! Every witness pushed to the client takes a credit until the client confirms consuming it, see
+//! `ChunkStateWitnessConsumedMessage`. The witnesses decoded while no credit is left are held
+//! here and pushed as the credits are returned, or pulled by the client with
+//! `RequestNextDecodedWitness` once it runs out of work while its credits are still taken, e.g.
+//! by witnesses waiting for their previous block. The pulled witnesses take no credit, as the
+//! client asked for them. Both the pushes and the pulls take the witness the client is blocked on
+//! first, then the witness of the highest height, as the lower heights are the most likely to be
+//! too late to endorse. A witness leaves the held ones when it is delivered, so it is delivered
+//! once whether pushed or pulled.
+
+use std::collections::{BTreeMap, HashSet};
+
+use near_chain::chain::ChunkStateWitnessMessage;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+
+/// Maximum number of the held witnesses, the witness of the lowest height is dropped beyond it.
+/// The client is then far behind, the witnesses of the lowest heights are likely already late.
+const MAX_HELD_WITNESSES: usize = 32;
+
+/// Ordered by height first, so that the last one is the highest.
+type HeldKey = (BlockHeight, ShardId, EpochId);
+
+pub struct HeldWitness {
+    pub key: ChunkProductionKey,
+    pub message: ChunkStateWitnessMessage,
+    pub decode_attempts: usize,
+}
+
+pub struct HeldWitnesses {
+    /// See `PartialWitnessConfig::client_witness_credits`, None if every witness is pushed.
+    credits: Option<usize>,
+    /// Witnesses pushed to the client and not consumed yet, each taking a credit.
+    in_flight: HashSet<ChunkProductionKey>,
+    held: BTreeMap<HeldKey, HeldWitness>,
+}
+
+impl HeldWitnesses {
+    pub fn new(credits: Option<usize>) -> Self {
+        Self { credits, in_flight: HashSet::new(), held: BTreeMap::new() }
+    }
+
+    pub fn has_credit(&self) -> bool {
+        self.credits.map_or(true, |credits| self.in_flight.len() < credits)
+    }
+
+    /// Takes a credit for the witness pushed to the client.
+    pub fn on_pushed(&mut self, key: &ChunkProductionKey) {
+        if self.credits.is_some() {
+            self.in_flight.insert(key.clone());
+        }
+    }
+
+    /// Returns the credit of the witness, once the client consumed it or it is never going to be
+    /// confirmed. Returns false if the witness didn't take a credit, e.g. it was pulled.
+    pub fn release(&mut self, key: &ChunkProductionKey) -> bool {
+        self.in_flight.remove(key)
+    }
+
+    /// Holds the witness until a credit is returned. Returns the witness dropped to make room for
+    /// it, if any, which may be the witness itself.
+    pub fn hold(&mut self, witness: HeldWitness) -> Option<HeldWitness> {
+        self.held.insert(held_key(&witness.key), witness);
+        if self.held.len() > MAX_HELD_WITNESSES {
+            return self.held.pop_first().map(|(_, dropped)| dropped);
+        }
+        None
+    }
+
+    /// Takes the held witness to deliver next among the ones with a raw size up to `max_bytes`:
+    /// the prioritized one of the highest height, otherwise the one of the highest height.
+    pub fn take_next(
+        &mut self,
+        max_bytes: usize,
+        is_prioritized: impl Fn(&ChunkProductionKey) -> bool,
+    ) -> Option<HeldWitness> {
+        let fits = |witness: &&HeldWitness| witness.message.raw_witness_size <= max_bytes;
+        let mut candidates = self.held.values().rev().filter(fits);
+        let next = match candidates.clone().find(|witness| is_prioritized(&witness.key)) {
+            Some(prioritized) => prioritized,
+            None => candidates.next()?,
+        };
+        let key = held_key(&next.key);
+        self.held.remove(&key)
+    }
+
+    /// Drops the held witnesses at or below the final height, their chunks can no longer be
+    /// included.
+    pub fn prune_finalized(&mut self, final_height: BlockHeight) -> Vec<HeldWitness> {
+        let kept = self.held.split_off(&(final_height.saturating_add(1), 0, EpochId::default()));
+        std::mem::replace(&mut self.held, kept).into_values().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+}
+
+fn held_key(key: &ChunkProductionKey) -> HeldKey {
+    (key.height_created, key.shard_id, key.epoch_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+
+    use super::*;
+
+    fn witness(height_created: BlockHeight, shard_id: ShardId, size: usize) -> HeldWitness {
+        let witness = ChunkStateWitness::new_dummy(height_created, shard_id, CryptoHash::default());
+        HeldWitness {
+            key: witness.chunk_production_key(),
+            message: ChunkStateWitnessMessage {
+                witness,
+                raw_witness_size: size,
+                pre_tracking: false,
+                prev_block_known: false,
+                decoded_late: false,
+            },
+            decode_attempts: 1,
+        }
+    }
+
+    fn heights(witnesses: impl IntoIterator<Item = HeldWitness>) -> Vec<BlockHeight> {
+        witnesses.into_iter().map(|witness| witness.key.height_created).collect()
+    }
+
+    #[test]
+    fn credits_are_taken_by_pushes_until_released() {
+        let mut held = HeldWitnesses::new(Some(1));
+        let first = witness(5, 0, 10).key;
+        assert!(held.has_credit());
+        held.on_pushed(&first);
+        assert!(!held.has_credit());
+        assert!(!held.release(&witness(6, 0, 10).key));
+        assert!(held.release(&first));
+        assert!(held.has_credit());
+
+        let mut unlimited = HeldWitnesses::new(None);
+        unlimited.on_pushed(&first);
+        assert!(unlimited.has_credit());
+        assert!(!unlimited.release(&first));
+    }
+
+    #[test]
+    fn next_witness_is_prioritized_then_highest_within_size() {
+        let mut held = HeldWitnesses::new(Some(0));
+        for witness in [witness(5, 0, 10), witness(7, 0, 100), witness(6, 1, 10)] {
+            assert!(held.hold(witness).is_none());
+        }
+        let prioritized = witness(5, 0, 10).key;
+        let next = held.take_next(usize::MAX, |key| key == &prioritized).unwrap();
+        assert_eq!(next.key, prioritized);
+        // The witness at height 7 is over the size cap.
+        let next = held.take_next(50, |_| false).unwrap();
+        assert_eq!(next.key.height_created, 6);
+        assert!(held.take_next(50, |_| false).is_none());
+        assert_eq!(held.len(), 1);
+        assert_eq!(heights(held.take_next(100, |_| false)), vec![7]);
+    }
+
+    #[test]
+    fn lowest_and_finalized_witnesses_are_dropped() {
+        let mut held = HeldWitnesses::new(Some(0));
+        for height in 1..=MAX_HELD_WITNESSES as BlockHeight {
+            assert!(held.hold(witness(height, 0, 10)).is_none());
+        }
+        let dropped = held.hold(witness(100, 0, 10)).unwrap();
+        assert_eq!(dropped.key.height_created, 1);
+        assert_eq!(heights(held.prune_finalized(3)), vec![2, 3]);
+        assert_eq!(held.len(), MAX_HELD_WITNESSES - 2);
+    }
+}
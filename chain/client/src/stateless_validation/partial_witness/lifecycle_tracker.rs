@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_async::time::{Clock, Duration, Instant};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use time::ext::InstantExt as _;
+
+use super::partial_witness_actor::ChunkStateWitnessOutcome;
+
+/// Number of witnesses sent to the client which we keep track of until the client reports their
+/// outcome. The client is expected to confirm the consumption within seconds and to report the
+/// outcome within a few blocks, so only a handful of entries are pending at any time unless the
+/// reports are lost altogether.
+const WITNESS_LIFECYCLE_CACHE_SIZE: usize = 200;
+
+/// Number of the most recent witnesses joined with their outcome kept for debugging.
+const RECENT_OUTCOMES_SIZE: usize = 100;
+
+/// Events in the life of a witness after it was sent to the client.
+struct WitnessLifecycle {
+    sent_at: Instant,
+    prev_block_known: bool,
+    decoded_late: bool,
+    decode_attempts: usize,
+    /// Time it took the client to confirm the consumption, see `ChunkStateWitnessConsumedMessage`.
+    consumed_after: Option<Duration>,
+}
+
+/// Witness reconstructed by us joined with the outcome reported by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessOutcomeRecord {
+    pub key: ChunkProductionKey,
+    pub outcome: ChunkStateWitnessOutcome,
+    /// Whether the previous block of the chunk was known when the witness was sent to the client.
+    pub prev_block_known: bool,
+    /// Whether the witness was decoded after the block at its height was produced, too late to
+    /// be endorsed. The outcome of such witnesses is `ChunkStateWitnessOutcome::DeclinedLate`.
+    pub decoded_late: bool,
+    /// Number of attempts it took to decode the witness, see `CacheEntry::decode_attempts`.
+    pub decode_attempts: usize,
+    /// Time between sending the witness to the client and the client consuming it, None if the
+    /// consumption confirmation didn't arrive before the outcome.
+    pub consumed_after: Option<Duration>,
+    /// Time between sending the witness to the client and the client reporting the outcome.
+    pub outcome_after: Duration,
+}
+
+/// Tracks the witnesses sent to the client from the moment they are sent until the client reports
+/// their outcome. A witness that is never consumed, see `ChunkStateWitnessConsumedMessage`, was
+/// lost on the way to the client, which otherwise goes unnoticed until endorsements are missed.
+/// A consumed witness waits for its outcome, see `ChunkStateWitnessOutcomeMessage`, which tells
+/// whether the reconstruction led to an endorsement.
+pub struct WitnessLifecycleTracker {
+    clock: Clock,
+    /// Witnesses sent to the client for which the client didn't report the outcome yet.
+    witnesses: LruCache<ChunkProductionKey, WitnessLifecycle>,
+    /// The most recent witnesses joined with their outcome, the most recent last.
+    recent_outcomes: VecDeque<WitnessOutcomeRecord>,
+}
+
+impl WitnessLifecycleTracker {
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            clock,
+            witnesses: LruCache::new(NonZeroUsize::new(WITNESS_LIFECYCLE_CACHE_SIZE).unwrap()),
+            recent_outcomes: VecDeque::with_capacity(RECENT_OUTCOMES_SIZE),
+        }
+    }
+
+    /// Records that the witness was sent to the client. Returns the key and the wait time of the
+    /// unconsumed witness evicted to make room for it, which is never going to be confirmed.
+    pub fn expect(
+        &mut self,
+        key: ChunkProductionKey,
+        prev_block_known: bool,
+        decoded_late: bool,
+        decode_attempts: usize,
+    ) -> Option<(ChunkProductionKey, Duration)> {
+        let now = self.clock.now();
+        let lifecycle = WitnessLifecycle {
+            sent_at: now,
+            prev_block_known,
+            decoded_late,
+            decode_attempts,
+            consumed_after: None,
+        };
+        self.witnesses
+            .push(key.clone(), lifecycle)
+            .filter(|(evicted_key, evicted)| {
+                evicted_key != &key && evicted.consumed_after.is_none()
+            })
+            .map(|(evicted_key, evicted)| (evicted_key, now.signed_duration_since(evicted.sent_at)))
+    }
+
+    /// Records that the client consumed the witness. Returns the time it took the client to
+    /// confirm, or None if the witness wasn't pending.
+    pub fn confirm(&mut self, key: &ChunkProductionKey) -> Option<Duration> {
+        let now = self.clock.now();
+        let lifecycle = self.witnesses.peek_mut(key)?;
+        if lifecycle.consumed_after.is_some() {
+            return None;
+        }
+        let consumed_after = now.signed_duration_since(lifecycle.sent_at);
+        lifecycle.consumed_after = Some(consumed_after);
+        Some(consumed_after)
+    }
+
+    /// Removes and returns the witnesses that were not confirmed within the timeout along with
+    /// the time elapsed since they were sent to the client.
+    pub fn take_overdue(&mut self, timeout: Duration) -> Vec<(ChunkProductionKey, Duration)> {
+        let now = self.clock.now();
+        let overdue_keys: Vec<ChunkProductionKey> = self
+            .witnesses
+            .iter()
+            .filter(|(_, lifecycle)| {
+                lifecycle.consumed_after.is_none()
+                    && now.signed_duration_since(lifecycle.sent_at) > timeout
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        overdue_keys
+            .into_iter()
+            .map(|key| {
+                let lifecycle = self.witnesses.pop(&key).unwrap();
+                (key, now.signed_duration_since(lifecycle.sent_at))
+            })
+            .collect()
+    }
+
+    /// Joins the outcome reported by the client with the lifecycle of the witness and keeps the
+    /// result among the recent outcomes. Returns None if the witness wasn't sent to the client by
+    /// us or was already forgotten.
+    pub fn join_outcome(
+        &mut self,
+        key: &ChunkProductionKey,
+        outcome: ChunkStateWitnessOutcome,
+    ) -> Option<&WitnessOutcomeRecord> {
+        let lifecycle = self.witnesses.pop(key)?;
+        if self.recent_outcomes.len() == RECENT_OUTCOMES_SIZE {
+            self.recent_outcomes.pop_front();
+        }
+        self.recent_outcomes.push_back(WitnessOutcomeRecord {
+            key: key.clone(),
+            outcome,
+            prev_block_known: lifecycle.prev_block_known,
+            decoded_late: lifecycle.decoded_late,
+            decode_attempts: lifecycle.decode_attempts,
+            consumed_after: lifecycle.consumed_after,
+            outcome_after: self.clock.now().signed_duration_since(lifecycle.sent_at),
+        });
+        self.recent_outcomes.back()
+    }
+
+    /// Returns the most recent witnesses joined with their outcome, starting from the most
+    /// recent one.
+    pub fn recent_outcomes(&self) -> impl Iterator<Item = &WitnessOutcomeRecord> {
+        self.recent_outcomes.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::FakeClock;
+    use near_primitives::types::EpochId;
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id: 0, epoch_id: EpochId::default(), height_created }
+    }
+
+    #[test]
+    fn unconfirmed_witnesses_become_overdue() {
+        let clock = FakeClock::new(near_async::time::Utc::UNIX_EPOCH);
+        let mut tracker = WitnessLifecycleTracker::new(clock.clock());
+        let timeout = Duration::seconds(10);
+
+        assert!(tracker.expect(key(1), true, false, 1).is_none());
+        clock.advance(Duration::seconds(5));
+        assert!(tracker.expect(key(2), true, false, 1).is_none());
+        assert!(tracker.expect(key(3), true, false, 1).is_none());
+        assert_eq!(tracker.confirm(&key(3)), Some(Duration::ZERO));
+        assert_eq!(tracker.confirm(&key(3)), None);
+        assert!(tracker.take_overdue(timeout).is_empty());
+
+        clock.advance(Duration::seconds(6));
+        assert_eq!(tracker.take_overdue(timeout), vec![(key(1), Duration::seconds(11))]);
+        assert_eq!(tracker.confirm(&key(2)), Some(Duration::seconds(6)));
+        clock.advance(Duration::seconds(60));
+        assert!(tracker.take_overdue(timeout).is_empty());
+    }
+
+    #[test]
+    fn outcomes_are_joined_with_lifecycle() {
+        let clock = FakeClock::new(near_async::time::Utc::UNIX_EPOCH);
+        let mut tracker = WitnessLifecycleTracker::new(clock.clock());
+
+        assert!(tracker.expect(key(1), false, false, 2).is_none());
+        assert!(tracker.expect(key(2), true, false, 1).is_none());
+        clock.advance(Duration::milliseconds(10));
+        tracker.confirm(&key(1));
+        clock.advance(Duration::milliseconds(500));
+        let record = tracker.join_outcome(&key(1), ChunkStateWitnessOutcome::Endorsed).cloned();
+        assert_eq!(
+            record,
+            Some(WitnessOutcomeRecord {
+                key: key(1),
+                outcome: ChunkStateWitnessOutcome::Endorsed,
+                prev_block_known: false,
+                decoded_late: false,
+                decode_attempts: 2,
+                consumed_after: Some(Duration::milliseconds(10)),
+                outcome_after: Duration::milliseconds(510),
+            })
+        );
+        // Each witness is joined at most once.
+        assert!(tracker.join_outcome(&key(1), ChunkStateWitnessOutcome::Endorsed).is_none());
+        assert!(tracker.join_outcome(&key(3), ChunkStateWitnessOutcome::Endorsed).is_none());
+
+        // The outcome may arrive before the consumption confirmation.
+        let record = tracker.join_outcome(&key(2), ChunkStateWitnessOutcome::ValidationFailed);
+        assert_eq!(record.unwrap().consumed_after, None);
+        assert!(tracker.expect(key(4), true, true, 1).is_none());
+        let record = tracker.join_outcome(&key(4), ChunkStateWitnessOutcome::DeclinedLate);
+        assert!(record.unwrap().decoded_late);
+        assert_eq!(tracker.confirm(&key(2)), None);
+
+        let recent_keys: Vec<_> =
+            tracker.recent_outcomes().map(|record| record.key.clone()).collect();
+        assert_eq!(recent_keys, vec![key(4), key(2), key(1)]);
+    }
+
+    #[test]
+    fn consumed_witnesses_are_evicted_silently() {
+        let clock = FakeClock::new(near_async::time::Utc::UNIX_EPOCH);
+        let mut tracker = WitnessLifecycleTracker::new(clock.clock());
+        for height in 0..WITNESS_LIFECYCLE_CACHE_SIZE as u64 {
+            assert!(tracker.expect(key(height), true, false, 1).is_none());
+        }
+        tracker.confirm(&key(0));
+        let evicted_height = WITNESS_LIFECYCLE_CACHE_SIZE as u64;
+        assert!(tracker.expect(key(evicted_height), true, false, 1).is_none());
+        assert_eq!(
+            tracker.expect(key(evicted_height + 1), true, false, 1),
+            Some((key(1), Duration::ZERO))
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Estimation of the witness part loss on the links to our peers. Every chunk validator forwards
+//! the part it owns to all the other chunk validators, so once the parts of a chunk stopped
+//! arriving we know which ordinals each peer should have delivered to us: the ordinals of the
+//! owners whose forwards reach us through the peer. The estimate is purely diagnostic, nothing
+//! is decided based on it.
+
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta};
+
+/// Number of settled chunks the estimate is computed over.
+pub const LINK_LOSS_WINDOW_CHUNKS: usize = 200;
+
+/// Number of heights after the creation of a chunk after which no more forwards are expected
+/// for it, and the deliveries for the chunk are settled. The parts arriving after the witness
+/// was decoded still count as delivered.
+pub const LINK_LOSS_SETTLE_HEIGHTS: BlockHeightDelta = 3;
+
+/// Upper bound of the chunks with deliveries waiting to be settled.
+const MAX_PENDING_CHUNKS: usize = 1000;
+
+/// Upper bound of the owners whose link is remembered, far more than the validators of an epoch.
+const MAX_OWNER_LINKS: usize = 10_000;
+
+/// Coarse buckets of the loss rate, from the best to the worst.
+pub const LINK_LOSS_BUCKETS: [&str; 5] =
+    ["none", "below_1pct", "below_10pct", "below_50pct", "above_50pct"];
+
+/// Forwarded parts delivered by a peer over the settled chunks of the window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkLossEstimate {
+    pub peer_id: PeerId,
+    /// Parts owned by the chunk validators whose forwards reach us through the peer.
+    pub expected_parts: usize,
+    /// Expected parts which the peer actually delivered.
+    pub delivered_parts: usize,
+}
+
+impl LinkLossEstimate {
+    pub fn loss_rate(&self) -> f64 {
+        if self.expected_parts == 0 {
+            return 0.0;
+        }
+        1.0 - self.delivered_parts as f64 / self.expected_parts as f64
+    }
+
+    pub fn loss_bucket(&self) -> &'static str {
+        let loss_rate = self.loss_rate();
+        if self.delivered_parts == self.expected_parts {
+            LINK_LOSS_BUCKETS[0]
+        } else if loss_rate < 0.01 {
+            LINK_LOSS_BUCKETS[1]
+        } else if loss_rate < 0.1 {
+            LINK_LOSS_BUCKETS[2]
+        } else if loss_rate < 0.5 {
+            LINK_LOSS_BUCKETS[3]
+        } else {
+            LINK_LOSS_BUCKETS[4]
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct LinkCounts {
+    expected: usize,
+    delivered: usize,
+}
+
+/// Forwarded part accepted from a peer.
+struct PartProvenance {
+    part_ord: usize,
+    owner: AccountId,
+    peer_id: PeerId,
+}
+
+struct PendingChunk {
+    /// Account of our validator signer when the parts arrived, we don't expect our own part.
+    my_account_id: AccountId,
+    deliveries: Vec<PartProvenance>,
+}
+
+pub struct LinkLossEstimator {
+    /// Peer through which the forwards of each owner arrived last. The owner is usually a direct
+    /// peer, but its forwards may be routed through another one.
+    owner_links: LruCache<AccountId, PeerId>,
+    /// Deliveries of the chunks which are not settled yet.
+    pending: LruCache<ChunkProductionKey, PendingChunk>,
+    /// Per peer counts of the last `LINK_LOSS_WINDOW_CHUNKS` settled chunks, oldest first.
+    window: VecDeque<HashMap<PeerId, LinkCounts>>,
+}
+
+impl LinkLossEstimator {
+    pub fn new() -> Self {
+        Self {
+            owner_links: LruCache::new(NonZeroUsize::new(MAX_OWNER_LINKS).unwrap()),
+            pending: LruCache::new(NonZeroUsize::new(MAX_PENDING_CHUNKS).unwrap()),
+            window: VecDeque::with_capacity(LINK_LOSS_WINDOW_CHUNKS),
+        }
+    }
+
+    /// Records the forwarded part `part_ord` owned by `owner` accepted from `peer_id`.
+    pub fn on_forwarded_part(
+        &mut self,
+        key: &ChunkProductionKey,
+        part_ord: usize,
+        owner: &AccountId,
+        peer_id: &PeerId,
+        my_account_id: &AccountId,
+    ) {
+        let pending = self.pending.get_or_insert_mut(key.clone(), || PendingChunk {
+            my_account_id: my_account_id.clone(),
+            deliveries: vec![],
+        });
+        pending.deliveries.push(PartProvenance {
+            part_ord,
+            owner: owner.clone(),
+            peer_id: peer_id.clone(),
+        });
+    }
+
+    /// Chunks with deliveries which are old enough to be settled at `head_height`,
+    /// see `LINK_LOSS_SETTLE_HEIGHTS`.
+    pub fn settled_keys(&self, head_height: BlockHeight) -> Vec<ChunkProductionKey> {
+        let mut keys: Vec<ChunkProductionKey> = self
+            .pending
+            .iter()
+            .filter(|(key, _)| key.height_created + LINK_LOSS_SETTLE_HEIGHTS <= head_height)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort_by_key(|key| (key.height_created, key.shard_id));
+        keys
+    }
+
+    /// Settles the deliveries of the chunk whose parts are owned by `part_owners`, ordered by
+    /// part_ord. The owners whose forwards never reached us are not attributed to any peer.
+    pub fn settle(&mut self, key: &ChunkProductionKey, part_owners: &[AccountId]) {
+        let Some(pending) = self.pending.pop(key) else {
+            return;
+        };
+        for delivery in &pending.deliveries {
+            self.owner_links.put(delivery.owner.clone(), delivery.peer_id.clone());
+        }
+        let mut counts: HashMap<PeerId, LinkCounts> = HashMap::new();
+        for (part_ord, owner) in part_owners.iter().enumerate() {
+            if owner == &pending.my_account_id {
+                continue;
+            }
+            let Some(peer_id) = self.owner_links.get(owner) else {
+                continue;
+            };
+            let link_counts = counts.entry(peer_id.clone()).or_default();
+            link_counts.expected += 1;
+            if pending
+                .deliveries
+                .iter()
+                .any(|delivery| delivery.part_ord == part_ord && &delivery.peer_id == peer_id)
+            {
+                link_counts.delivered += 1;
+            }
+        }
+        if self.window.len() == LINK_LOSS_WINDOW_CHUNKS {
+            self.window.pop_front();
+        }
+        self.window.push_back(counts);
+    }
+
+    /// Drops the deliveries of a chunk which won't be settled, e.g. because its validators can't
+    /// be looked up anymore.
+    pub fn discard(&mut self, key: &ChunkProductionKey) {
+        self.pending.pop(key);
+    }
+
+    /// Loss estimates of the peers over the window, starting from the highest loss rate.
+    pub fn estimates(&self) -> Vec<LinkLossEstimate> {
+        let mut totals: HashMap<&PeerId, LinkCounts> = HashMap::new();
+        for counts in &self.window {
+            for (peer_id, link_counts) in counts {
+                let total = totals.entry(peer_id).or_default();
+                total.expected += link_counts.expected;
+                total.delivered += link_counts.delivered;
+            }
+        }
+        let mut estimates: Vec<LinkLossEstimate> = totals
+            .into_iter()
+            .map(|(peer_id, total)| LinkLossEstimate {
+                peer_id: peer_id.clone(),
+                expected_parts: total.expected,
+                delivered_parts: total.delivered,
+            })
+            .collect();
+        estimates.sort_by(|a, b| {
+            b.loss_rate().total_cmp(&a.loss_rate()).then_with(|| a.peer_id.cmp(&b.peer_id))
+        });
+        estimates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::network::PeerId;
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::{AccountId, EpochId};
+
+    use super::{LinkLossEstimator, LINK_LOSS_SETTLE_HEIGHTS, LINK_LOSS_WINDOW_CHUNKS};
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    fn peer(account_id: &str) -> PeerId {
+        PeerId::new(create_test_signer(account_id).public_key())
+    }
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 0, height_created }
+    }
+
+    fn owners() -> Vec<AccountId> {
+        ["me", "alice", "bob", "carol"].iter().map(|owner| account(owner)).collect()
+    }
+
+    /// Delivers the forwarded parts of `delivered` at `height` from the peer of their owner.
+    fn deliver(estimator: &mut LinkLossEstimator, height: u64, delivered: &[&str]) {
+        let part_owners = owners();
+        for owner in delivered {
+            let part_ord = part_owners.iter().position(|it| it == &account(owner)).unwrap();
+            estimator.on_forwarded_part(
+                &key(height),
+                part_ord,
+                &account(owner),
+                &peer(owner),
+                &account("me"),
+            );
+        }
+    }
+
+    fn settle_all(estimator: &mut LinkLossEstimator, head_height: u64) {
+        for key in estimator.settled_keys(head_height) {
+            estimator.settle(&key, &owners());
+        }
+    }
+
+    #[test]
+    fn lossy_link_is_reported_first() {
+        let mut estimator = LinkLossEstimator::new();
+        for height in 1..=10 {
+            // The forwards of bob are lost every other chunk, carol's are never lost.
+            if height % 2 == 0 {
+                deliver(&mut estimator, height, &["alice", "bob", "carol"]);
+            } else {
+                deliver(&mut estimator, height, &["alice", "carol"]);
+            }
+        }
+        settle_all(&mut estimator, 10 + LINK_LOSS_SETTLE_HEIGHTS);
+
+        let estimates = estimator.estimates();
+        assert_eq!(estimates.len(), 3);
+        assert_eq!(estimates[0].peer_id, peer("bob"));
+        // Bob's link is learned from the first delivery, at height 2.
+        assert_eq!(estimates[0].expected_parts, 9);
+        assert_eq!(estimates[0].delivered_parts, 5);
+        assert_eq!(estimates[0].loss_bucket(), "below_50pct");
+        for estimate in &estimates[1..] {
+            assert_eq!(estimate.expected_parts, 10);
+            assert_eq!(estimate.delivered_parts, 10);
+            assert_eq!(estimate.loss_bucket(), "none");
+        }
+    }
+
+    #[test]
+    fn chunks_are_settled_after_the_settle_heights() {
+        let mut estimator = LinkLossEstimator::new();
+        deliver(&mut estimator, 5, &["alice"]);
+        deliver(&mut estimator, 6, &["alice"]);
+        assert!(estimator.settled_keys(5 + LINK_LOSS_SETTLE_HEIGHTS - 1).is_empty());
+        assert_eq!(estimator.settled_keys(5 + LINK_LOSS_SETTLE_HEIGHTS), vec![key(5)]);
+        settle_all(&mut estimator, 6 + LINK_LOSS_SETTLE_HEIGHTS);
+        assert!(estimator.settled_keys(100).is_empty());
+        assert_eq!(estimator.estimates()[0].expected_parts, 2);
+    }
+
+    #[test]
+    fn owner_routed_through_another_peer_is_attributed_to_it() {
+        let mut estimator = LinkLossEstimator::new();
+        estimator.on_forwarded_part(&key(1), 1, &account("alice"), &peer("bob"), &account("me"));
+        estimator.settle(&key(1), &owners());
+        let estimates = estimator.estimates();
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(estimates[0].peer_id, peer("bob"));
+        assert_eq!(estimates[0].delivered_parts, 1);
+    }
+
+    #[test]
+    fn window_keeps_the_latest_chunks() {
+        let mut estimator = LinkLossEstimator::new();
+        // A lossy start which falls out of the window.
+        deliver(&mut estimator, 1, &["alice", "carol"]);
+        for height in 2..=10 {
+            deliver(&mut estimator, height, &["carol"]);
+        }
+        let last_height = 10 + LINK_LOSS_WINDOW_CHUNKS as u64;
+        for height in 11..=last_height {
+            deliver(&mut estimator, height, &["alice", "carol"]);
+        }
+        settle_all(&mut estimator, last_height + LINK_LOSS_SETTLE_HEIGHTS);
+        let estimates = estimator.estimates();
+        let alice = estimates.iter().find(|estimate| estimate.peer_id == peer("alice")).unwrap();
+        assert_eq!(alice.expected_parts, LINK_LOSS_WINDOW_CHUNKS);
+        assert_eq!(alice.delivered_parts, LINK_LOSS_WINDOW_CHUNKS);
+    }
+}
@@ -0,0 +1,417 @@
+//! Recording of the witness parts received by the node, to reproduce the incidents offline by
+//! replaying the parts against a `PartialWitnessActor`, see
+//! `neard view-state replay-partial-witnesses`.
+//!
+//! The recording starts with `RECORDING_MAGIC` and the format version, followed by frames each
+//! holding a borsh serialized `RecordedEntry` prefixed by its length as u32 little endian.
+//! The validation of the parts depends on the epoch data of the node, so besides the messages
+//! the recording holds the snapshots of the data read by the validation, each written before the
+//! first message which needs it:
+//! - the `EpochInfo` of the epoch of the chunk and of the epochs around the head, which determine
+//!   the chunk producers, the chunk validators and their keys,
+//! - the head and the final head together with the `BlockInfo`s of the first and the last blocks
+//!   of the current and the previous epoch, which determine the heights accepted for each epoch.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_async::time::{Clock, Instant};
+use near_chain::Error;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::block::Tip;
+use near_primitives::epoch_block_info::BlockInfo;
+use near_primitives::epoch_info::EpochInfo;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::types::{AccountId, EpochId};
+use near_store::{DBCol, Store, FINAL_HEAD_KEY, HEAD_KEY};
+use time::ext::InstantExt as _;
+
+/// Bytes at the start of every recording.
+const RECORDING_MAGIC: &[u8; 8] = b"NEARPWR\0";
+
+/// Version of the recording format. Must be bumped on every change of `RecordedEntry` or of the
+/// borsh representation of the types it holds, the recordings of other versions are rejected.
+pub const RECORDING_FORMAT_VERSION: u32 = 1;
+
+/// How the recorded part reached the node.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedMessageKind {
+    /// Part owned by the node, received from the chunk producer.
+    Owned,
+    /// Part forwarded by its owner.
+    Forwarded,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub enum RecordedEntry {
+    /// Account of the validator signer of the node, recorded whenever it changes.
+    Signer { account_id: AccountId },
+    /// Info of an epoch, recorded once per epoch.
+    Epoch { epoch_id: EpochId, epoch_info: EpochInfo },
+    /// Head of the node, recorded whenever the head or the final head changes.
+    Head { head: Tip, final_head: Option<Tip>, block_infos: Vec<BlockInfo> },
+    /// Part received by the node.
+    Message {
+        /// Nanoseconds between the start of the recording and receiving the part.
+        received_after_nanos: u64,
+        kind: RecordedMessageKind,
+        partial_witness: PartialEncodedStateWitness,
+    },
+}
+
+impl RecordedEntry {
+    /// Saves the epoch data held by the entry to the store, where the `EpochManager` and the
+    /// validation of the parts expect it. Does nothing for the other entries.
+    pub fn save_epoch_data(&self, store: &Store) -> std::io::Result<()> {
+        let mut store_update = store.store_update();
+        match self {
+            RecordedEntry::Epoch { epoch_id, epoch_info } => {
+                store_update.set_ser(DBCol::EpochInfo, epoch_id.as_ref(), epoch_info)?;
+            }
+            RecordedEntry::Head { head, final_head, block_infos } => {
+                store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, head)?;
+                if let Some(final_head) = final_head {
+                    store_update.set_ser(DBCol::BlockMisc, FINAL_HEAD_KEY, final_head)?;
+                }
+                for block_info in block_infos {
+                    if !store.exists(DBCol::BlockInfo, block_info.hash().as_ref())? {
+                        store_update.insert_ser(
+                            DBCol::BlockInfo,
+                            block_info.hash().as_ref(),
+                            block_info,
+                        )?;
+                    }
+                }
+            }
+            RecordedEntry::Signer { .. } | RecordedEntry::Message { .. } => {}
+        }
+        store_update.commit()
+    }
+}
+
+/// Writes the witness parts received by the node to a recording, see the module docs.
+pub struct WitnessMessageRecorder {
+    clock: Clock,
+    writer: BufWriter<File>,
+    started_at: Instant,
+    /// Bytes written to the recording so far.
+    size: u64,
+    max_size: u64,
+    /// Set once the recording reached `max_size`, nothing is written afterwards.
+    full: bool,
+    recorded_account_id: Option<AccountId>,
+    recorded_epochs: HashSet<EpochId>,
+    /// Last block hashes of the head and of the final head recorded last.
+    recorded_head: Option<(CryptoHash, Option<CryptoHash>)>,
+}
+
+impl WitnessMessageRecorder {
+    /// Starts a new recording at `path`, overwriting the existing file.
+    pub fn create(clock: Clock, path: &Path, max_size: u64) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(RECORDING_MAGIC)?;
+        writer.write_all(&RECORDING_FORMAT_VERSION.to_le_bytes())?;
+        writer.flush()?;
+        Ok(Self {
+            started_at: clock.now(),
+            clock,
+            writer,
+            size: (RECORDING_MAGIC.len() + std::mem::size_of::<u32>()) as u64,
+            max_size,
+            full: false,
+            recorded_account_id: None,
+            recorded_epochs: HashSet::new(),
+            recorded_head: None,
+        })
+    }
+
+    /// Records the part received by the node, preceded by the epoch data needed to validate it
+    /// which wasn't recorded yet. The entries are flushed right away, so that the recording is
+    /// usable even if the node crashes.
+    pub fn record(
+        &mut self,
+        kind: RecordedMessageKind,
+        partial_witness: &PartialEncodedStateWitness,
+        account_id: &AccountId,
+        epoch_manager: &dyn EpochManagerAdapter,
+        store: &Store,
+    ) -> Result<(), Error> {
+        if self.full {
+            return Ok(());
+        }
+        let received_after = self.clock.now().signed_duration_since(self.started_at);
+
+        let mut entries = vec![];
+        if self.recorded_account_id.as_ref() != Some(account_id) {
+            entries.push(RecordedEntry::Signer { account_id: account_id.clone() });
+        }
+
+        let mut epoch_ids = vec![partial_witness.chunk_production_key().epoch_id];
+        let mut head_entry = None;
+        let mut head_hashes = None;
+        if let Some(head) = store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? {
+            let final_head = store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?;
+            epoch_ids.push(head.epoch_id);
+            epoch_ids.push(head.next_epoch_id);
+            let hashes = (head.last_block_hash, final_head.as_ref().map(|tip| tip.last_block_hash));
+            if self.recorded_head != Some(hashes) {
+                let block_infos = head_block_infos(epoch_manager, &head);
+                epoch_ids.extend(block_infos.iter().map(|block_info| *block_info.epoch_id()));
+                head_entry = Some(RecordedEntry::Head { head, final_head, block_infos });
+                head_hashes = Some(hashes);
+            }
+        }
+        let mut new_epoch_ids = vec![];
+        for epoch_id in epoch_ids {
+            if self.recorded_epochs.contains(&epoch_id) || new_epoch_ids.contains(&epoch_id) {
+                continue;
+            }
+            // The info of the next epoch isn't known during the first block of the epoch.
+            let Ok(epoch_info) = epoch_manager.get_epoch_info(&epoch_id) else {
+                continue;
+            };
+            entries
+                .push(RecordedEntry::Epoch { epoch_id, epoch_info: epoch_info.as_ref().clone() });
+            new_epoch_ids.push(epoch_id);
+        }
+        entries.extend(head_entry);
+        entries.push(RecordedEntry::Message {
+            received_after_nanos: received_after.whole_nanoseconds().max(0) as u64,
+            kind,
+            partial_witness: partial_witness.clone(),
+        });
+
+        let mut frames = vec![];
+        for entry in &entries {
+            let bytes = borsh::to_vec(entry)?;
+            frames.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            frames.extend(bytes);
+        }
+        if self.size + frames.len() as u64 > self.max_size {
+            self.full = true;
+            tracing::info!(
+                target: "client",
+                size = self.size,
+                "Witness message recording reached its maximum size, no more messages are recorded"
+            );
+            return Ok(());
+        }
+        self.writer.write_all(&frames)?;
+        self.writer.flush()?;
+        self.size += frames.len() as u64;
+
+        self.recorded_account_id = Some(account_id.clone());
+        self.recorded_epochs.extend(new_epoch_ids);
+        if head_hashes.is_some() {
+            self.recorded_head = head_hashes;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the block infos read by `EpochManager::possible_epochs_of_height_around_tip`: the head
+/// and the first block of its epoch, then the last and the first blocks of the previous epoch.
+fn head_block_infos(epoch_manager: &dyn EpochManagerAdapter, head: &Tip) -> Vec<BlockInfo> {
+    let mut block_infos: Vec<BlockInfo> = vec![];
+    let mut last_block_hash = head.last_block_hash;
+    for _ in 0..2 {
+        let Ok(last_block_info) = epoch_manager.get_block_info(&last_block_hash) else {
+            break;
+        };
+        let first_block_hash = *last_block_info.epoch_first_block();
+        block_infos.push(last_block_info.as_ref().clone());
+        let Ok(first_block_info) = epoch_manager.get_block_info(&first_block_hash) else {
+            break;
+        };
+        last_block_hash = *first_block_info.prev_hash();
+        block_infos.push(first_block_info.as_ref().clone());
+    }
+    // The head may be the first block of its epoch.
+    block_infos.dedup_by_key(|block_info| *block_info.hash());
+    block_infos
+}
+
+/// Reads the entries of a recording written by `WitnessMessageRecorder`.
+pub struct WitnessMessageRecordingReader {
+    reader: BufReader<File>,
+}
+
+impl WitnessMessageRecordingReader {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; RECORDING_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != RECORDING_MAGIC {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "not a witness message recording",
+            ));
+        }
+        let mut version = [0; std::mem::size_of::<u32>()];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != RECORDING_FORMAT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported recording format version {version}, expected {RECORDING_FORMAT_VERSION}"
+                ),
+            ));
+        }
+        Ok(Self { reader })
+    }
+}
+
+impl Iterator for WitnessMessageRecordingReader {
+    type Item = std::io::Result<RecordedEntry>;
+
+    /// Returns the next entry. A frame truncated by a crash of the node is returned as an error.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut len = [0; std::mem::size_of::<u32>()];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err)),
+        }
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        Some(
+            self.reader.read_exact(&mut bytes).and_then(|()| RecordedEntry::try_from_slice(&bytes)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::{Duration, FakeClock, Utc};
+    use near_chain::test_utils::MockEpochManager;
+    use near_primitives::test_utils::create_test_signer;
+    use near_store::test_utils::create_test_store;
+
+    use crate::test_utils::TestWitnessBuilder;
+
+    fn partial_witness(height_created: u64) -> PartialEncodedStateWitness {
+        TestWitnessBuilder::new()
+            .height(height_created)
+            .encode_and_split_among(&["test".parse().unwrap()], &create_test_signer("test"))
+            .part(0)
+            .clone()
+    }
+
+    fn read_entries(path: &Path) -> Vec<RecordedEntry> {
+        WitnessMessageRecordingReader::open(path).unwrap().map(|entry| entry.unwrap()).collect()
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording");
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let store = create_test_store();
+        let epoch_manager = MockEpochManager::new(store.clone(), 10);
+        let account_id: AccountId = "test".parse().unwrap();
+
+        let mut recorder = WitnessMessageRecorder::create(clock.clock(), &path, u64::MAX).unwrap();
+        let kinds = [RecordedMessageKind::Owned, RecordedMessageKind::Forwarded];
+        for (height, kind) in kinds.into_iter().enumerate() {
+            clock.advance(Duration::milliseconds(10));
+            recorder
+                .record(
+                    kind,
+                    &partial_witness(height as u64),
+                    &account_id,
+                    epoch_manager.as_ref(),
+                    &store,
+                )
+                .unwrap();
+        }
+
+        // The signer and the epoch are recorded only before the first message.
+        let entries = read_entries(&path);
+        assert_eq!(entries.len(), 4);
+        assert!(
+            matches!(&entries[0], RecordedEntry::Signer { account_id: id } if id == &account_id)
+        );
+        let RecordedEntry::Epoch { epoch_id, .. } = &entries[1] else {
+            panic!("expected epoch, got {:?}", entries[1]);
+        };
+        assert_eq!(epoch_id, &EpochId::default());
+        let messages: Vec<_> = entries[2..]
+            .iter()
+            .map(|entry| match entry {
+                RecordedEntry::Message { received_after_nanos, kind, partial_witness } => {
+                    (*received_after_nanos, *kind, partial_witness.chunk_production_key())
+                }
+                entry => panic!("unexpected entry {entry:?}"),
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (10_000_000, RecordedMessageKind::Owned, partial_witness(0).chunk_production_key()),
+                (
+                    20_000_000,
+                    RecordedMessageKind::Forwarded,
+                    partial_witness(1).chunk_production_key()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn recording_stops_at_max_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording");
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let store = create_test_store();
+        let epoch_manager = MockEpochManager::new(store.clone(), 10);
+        let account_id: AccountId = "test".parse().unwrap();
+
+        let mut recorder = WitnessMessageRecorder::create(clock.clock(), &path, u64::MAX).unwrap();
+        recorder
+            .record(
+                RecordedMessageKind::Owned,
+                &partial_witness(0),
+                &account_id,
+                epoch_manager.as_ref(),
+                &store,
+            )
+            .unwrap();
+        let one_message_size = std::fs::metadata(&path).unwrap().len();
+
+        let mut recorder =
+            WitnessMessageRecorder::create(clock.clock(), &path, one_message_size).unwrap();
+        for height in 0..3 {
+            recorder
+                .record(
+                    RecordedMessageKind::Owned,
+                    &partial_witness(height),
+                    &account_id,
+                    epoch_manager.as_ref(),
+                    &store,
+                )
+                .unwrap();
+        }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), one_message_size);
+        let num_messages = read_entries(&path)
+            .iter()
+            .filter(|entry| matches!(entry, RecordedEntry::Message { .. }))
+            .count();
+        assert_eq!(num_messages, 1);
+    }
+
+    #[test]
+    fn other_format_versions_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording");
+        let mut bytes = RECORDING_MAGIC.to_vec();
+        bytes.extend_from_slice(&(RECORDING_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let err = WitnessMessageRecordingReader::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
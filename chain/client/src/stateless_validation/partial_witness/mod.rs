@@ -1,5 +1,69 @@
+mod ack_batcher;
+#[cfg(feature = "test_features")]
+mod adversarial;
+mod decode_queue;
+mod decoded_witnesses;
+mod delivered_witnesses;
+mod effective_config;
 mod encoding;
+mod epoch_scoped_caches;
+mod epoch_witness_stats;
+mod error_reporter;
+mod forward_backoff;
+mod forward_targets;
+mod framing_overhead;
+mod handler_panic;
+mod head_timeline;
+mod health;
+mod held_witnesses;
+mod lifecycle_tracker;
+mod link_loss;
+pub mod message_recorder;
+mod part_format;
+mod part_fragments;
+mod part_send_queue;
 pub mod partial_witness_actor;
 mod partial_witness_tracker;
+mod pending_epoch_parts;
+mod pre_tracking;
+mod prioritized_witnesses;
+mod producer_health;
+mod shard_tracking_check;
+mod signer_snapshot;
+mod spilled_parts;
+mod state_snapshot;
+pub mod stats_export;
+mod time_slice;
+mod unavailable_receivers;
+mod validation_lag;
+mod verification_load;
+mod witness_deadlines;
+pub mod witness_parts_geometry;
 
-pub use encoding::witness_part_length;
+#[cfg(feature = "test_features")]
+pub use adversarial::{AdvWitnessPartsMessage, AdvWitnessPartsMode, ForceRedistributeWitness};
+pub use decoded_witnesses::{DecodedWitness, WitnessDecodeConflict, WitnessDecodePath};
+pub use delivered_witnesses::DeliveredWitnesses;
+pub use encoding::MAX_WITNESS_PARTS;
+#[cfg(any(test, feature = "test_features", feature = "witness_simulation"))]
+pub use encoding::{ReedSolomonBackend, WitnessEncoderCache};
+pub use epoch_witness_stats::{
+    load_epoch_witness_stats, EpochWitnessStatsV1, ShardWitnessStatsV1, VersionedEpochWitnessStats,
+};
+pub use error_reporter::PartialWitnessErrorEvent;
+pub use handler_panic::{install_handler_panic_hook, HandlerPanicReport};
+pub use health::{HealthCheck, HealthCheckResult, StatelessValidationHealth};
+pub use lifecycle_tracker::WitnessOutcomeRecord;
+pub use link_loss::LinkLossEstimate;
+pub(crate) use part_format::{AcceptedPartFormats, PartFormat, PartialWitnessPart};
+pub use part_fragments::PART_FRAGMENTS_TTL;
+pub use partial_witness_tracker::{
+    CorruptedWitnessPart, PartSource, WitnessConflictEvidence, WitnessKeyMismatchEvidence,
+};
+pub use pending_epoch_parts::EPOCH_INFO_CHECK_PERIOD;
+pub(crate) use pre_tracking::next_epoch_chunk_validators;
+pub use producer_health::{PartDelivery, ProducerDistributionHealth};
+pub use spilled_parts::clear_spilled_witness_parts;
+pub use state_snapshot::{IncompleteWitnessSnapshot, PartialWitnessState, PartialWitnessStateV1};
+
+pub use crate::stateless_validation::validate::HeightWindowContext;
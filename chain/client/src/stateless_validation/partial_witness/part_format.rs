@@ -0,0 +1,287 @@
+//! Wire formats of the partial witness parts, and the normalized view the checks use for all of
+//! them.
+//!
+//! Every protocol feature changing the parts adds a version of
+//! `VersionedPartialEncodedStateWitnessInner`. The chunk producer creates the parts in the format
+//! of the protocol version of the epoch of the chunk, see `PartFormat::for_protocol_version`, but
+//! the chunk validators accept the formats of the `PART_FORMAT_COMPATIBILITY_VERSIONS` protocol
+//! versions before it as well, so that parts of the previous format still in flight around an
+//! upgrade aren't lost. The forwarded parts are sent exactly as received, as the signature of the
+//! chunk producer covers the format. The validation, dedup and conflict checks look at the parts
+//! through `PartialWitnessPart`, so that they treat all the formats the same way.
+
+use near_chain::Error;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::version::{ProtocolFeature, ProtocolVersion};
+
+/// Number of protocol versions before the protocol version of the epoch whose part format is
+/// still accepted, see `AcceptedPartFormats`.
+pub const PART_FORMAT_COMPATIBILITY_VERSIONS: ProtocolVersion = 2;
+
+/// Wire format of a part, ordered from the oldest to the newest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PartFormat {
+    V1,
+    /// `ProtocolFeature::PartialWitnessSendTimestamp`.
+    V2,
+    /// `ProtocolFeature::PartialWitnessMerkleCommitment`.
+    V3,
+    /// `ProtocolFeature::PartialWitnessProtocolVersion`.
+    V4,
+}
+
+impl PartFormat {
+    /// Format in which the chunk producer creates the parts at the protocol version.
+    pub fn for_protocol_version(protocol_version: ProtocolVersion) -> Self {
+        if ProtocolFeature::PartialWitnessProtocolVersion.enabled(protocol_version) {
+            Self::V4
+        } else if ProtocolFeature::PartialWitnessMerkleCommitment.enabled(protocol_version) {
+            Self::V3
+        } else if ProtocolFeature::PartialWitnessSendTimestamp.enabled(protocol_version) {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+            Self::V3 => "v3",
+            Self::V4 => "v4",
+        }
+    }
+}
+
+/// Normalized view of a part in any of the wire formats. The fields introduced by a format are
+/// None for the parts of the formats before it.
+#[derive(Clone, Copy, Debug)]
+pub struct PartialWitnessPart<'a> {
+    pub part_ord: usize,
+    pub format: PartFormat,
+    pub encoded_length: usize,
+    pub witness_hash: Option<&'a CryptoHash>,
+    pub parts_root: Option<&'a CryptoHash>,
+    pub num_parts: Option<usize>,
+    pub protocol_version: Option<ProtocolVersion>,
+}
+
+impl<'a> From<&'a PartialEncodedStateWitness> for PartialWitnessPart<'a> {
+    fn from(partial_witness: &'a PartialEncodedStateWitness) -> Self {
+        let format = if partial_witness.protocol_version().is_some() {
+            PartFormat::V4
+        } else if partial_witness.parts_root().is_some() {
+            PartFormat::V3
+        } else if partial_witness.sent_at().is_some() {
+            PartFormat::V2
+        } else {
+            PartFormat::V1
+        };
+        Self {
+            part_ord: partial_witness.part_ord(),
+            format,
+            encoded_length: partial_witness.encoded_length(),
+            witness_hash: partial_witness.witness_hash(),
+            parts_root: partial_witness.parts_root(),
+            num_parts: partial_witness.num_parts(),
+            protocol_version: partial_witness.protocol_version(),
+        }
+    }
+}
+
+impl PartialWitnessPart<'_> {
+    /// Describes how the metadata of the part conflicts with the one of `other` part of the same
+    /// chunk, if it does. The fields are only compared when both parts carry them, so the parts
+    /// of the same witness in different formats don't conflict.
+    pub fn metadata_conflict(&self, other: &PartialWitnessPart) -> Option<String> {
+        if let (Some(expected), Some(actual)) = (other.witness_hash, self.witness_hash) {
+            if expected != actual {
+                return Some(format!(
+                    "Witness hash {} of part_ord {} conflicts with {}",
+                    actual, self.part_ord, expected
+                ));
+            }
+        }
+        if let (Some(expected), Some(actual)) = (other.parts_root, self.parts_root) {
+            if expected != actual {
+                return Some(format!(
+                    "Parts root {} of part_ord {} conflicts with {}",
+                    actual, self.part_ord, expected
+                ));
+            }
+        }
+        if self.encoded_length != other.encoded_length {
+            return Some(format!(
+                "Encoded length {} of part_ord {} conflicts with {}",
+                self.encoded_length, self.part_ord, other.encoded_length
+            ));
+        }
+        None
+    }
+}
+
+/// Formats of the parts accepted for the chunks of an epoch: the format of the protocol version
+/// of the epoch, and the formats of the `PART_FORMAT_COMPATIBILITY_VERSIONS` versions before it.
+#[derive(Clone, Copy, Debug)]
+pub struct AcceptedPartFormats {
+    epoch_protocol_version: ProtocolVersion,
+    oldest: PartFormat,
+    newest: PartFormat,
+    /// `ProtocolFeature::WitnessChecksum` is enabled at all the versions of the window.
+    witness_hash_required: bool,
+    /// `ProtocolFeature::WitnessChecksum` is enabled at the protocol version of the epoch.
+    witness_hash_allowed: bool,
+}
+
+impl AcceptedPartFormats {
+    pub fn for_protocol_version(epoch_protocol_version: ProtocolVersion) -> Self {
+        let oldest_protocol_version =
+            epoch_protocol_version.saturating_sub(PART_FORMAT_COMPATIBILITY_VERSIONS);
+        Self {
+            epoch_protocol_version,
+            oldest: PartFormat::for_protocol_version(oldest_protocol_version),
+            newest: PartFormat::for_protocol_version(epoch_protocol_version),
+            witness_hash_required: ProtocolFeature::WitnessChecksum
+                .enabled(oldest_protocol_version),
+            witness_hash_allowed: ProtocolFeature::WitnessChecksum.enabled(epoch_protocol_version),
+        }
+    }
+
+    pub fn check(&self, part: &PartialWitnessPart) -> Result<(), Error> {
+        if !(self.oldest..=self.newest).contains(&part.format) {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Part format {:?} is not accepted at protocol version {}, expected {:?} to {:?}",
+                part.format, self.epoch_protocol_version, self.oldest, self.newest
+            )));
+        }
+        let has_witness_hash = part.witness_hash.is_some();
+        if (self.witness_hash_required && !has_witness_hash)
+            || (!self.witness_hash_allowed && has_witness_hash)
+        {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Witness hash present: {}, not accepted at protocol version {}",
+                has_witness_hash, self.epoch_protocol_version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the part is in the format of a protocol version before the one of the epoch.
+    pub fn is_previous_format(&self, part: &PartialWitnessPart) -> bool {
+        part.format < self.newest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use near_async::time::Utc;
+    use near_primitives::stateless_validation::partial_witness::ChunkValidatorsDigest;
+    use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::EpochId;
+
+    use super::*;
+
+    /// Parts of the same witness in every format.
+    fn parts_in_every_format() -> Vec<PartialEncodedStateWitness> {
+        let signer = create_test_signer("test");
+        let chunk_header = ChunkStateWitness::new_dummy(5, 0, CryptoHash::default()).chunk_header;
+        let part: Arc<[u8]> = vec![1; 100].into();
+        let witness_hash = Some(CryptoHash::hash_bytes(&[1]));
+        let v1_part = PartialEncodedStateWitness::new(
+            EpochId::default(),
+            chunk_header.clone(),
+            0,
+            part.clone(),
+            1000,
+            &signer,
+        );
+        let v2_part = PartialEncodedStateWitness::new_v2(
+            EpochId::default(),
+            chunk_header.clone(),
+            0,
+            signer.validator_id().clone(),
+            part.clone(),
+            1000,
+            witness_hash,
+            Utc::UNIX_EPOCH,
+            &signer,
+        );
+        let new_committed_part = |protocol_version| {
+            PartialEncodedStateWitness::new_committed_parts(
+                EpochId::default(),
+                &chunk_header,
+                vec![(signer.validator_id().clone(), part.clone())],
+                1000,
+                witness_hash,
+                ChunkValidatorsDigest::new(&[signer.validator_id().clone()]),
+                Utc::UNIX_EPOCH,
+                protocol_version,
+                &signer,
+            )
+            .pop()
+            .unwrap()
+        };
+        vec![v1_part, v2_part, new_committed_part(None), new_committed_part(Some(7))]
+    }
+
+    #[test]
+    fn parts_are_normalized_from_every_format() {
+        let parts = parts_in_every_format();
+        let parts = parts.iter().map(PartialWitnessPart::from).collect::<Vec<_>>();
+        let formats = parts.iter().map(|part| part.format).collect::<Vec<_>>();
+        assert_eq!(formats, vec![PartFormat::V1, PartFormat::V2, PartFormat::V3, PartFormat::V4]);
+        assert_eq!(parts[3].protocol_version, Some(7));
+        assert_eq!(parts[3].num_parts, Some(1));
+        assert_eq!(parts[2].parts_root, parts[3].parts_root);
+        // The same witness doesn't conflict with itself across the formats.
+        for part in &parts {
+            for other in &parts {
+                assert_eq!(part.metadata_conflict(other), None);
+            }
+        }
+        // The parts of the first format carry no witness hash.
+        assert_eq!(parts[0].witness_hash, None);
+        let other_witness_hash = CryptoHash::hash_bytes(&[2]);
+        let conflicting =
+            PartialWitnessPart { witness_hash: Some(&other_witness_hash), ..parts[1] };
+        assert!(conflicting.metadata_conflict(&parts[3]).is_some());
+        assert_eq!(
+            PartialWitnessPart { witness_hash: None, ..parts[1] }.metadata_conflict(&parts[3]),
+            None
+        );
+    }
+
+    #[test]
+    fn previous_formats_are_accepted_for_two_protocol_versions() {
+        let parts = parts_in_every_format();
+        let parts = parts.iter().map(PartialWitnessPart::from).collect::<Vec<_>>();
+        let version = ProtocolFeature::PartialWitnessProtocolVersion.protocol_version();
+        assert_eq!(PartFormat::for_protocol_version(version), PartFormat::V4);
+        assert_eq!(PartFormat::for_protocol_version(version - 1), PartFormat::V3);
+
+        let accepted = |protocol_version: ProtocolVersion| {
+            let accepted_formats = AcceptedPartFormats::for_protocol_version(protocol_version);
+            parts
+                .iter()
+                .filter(|part| accepted_formats.check(part).is_ok())
+                .map(|part| part.format)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(accepted(version), vec![PartFormat::V2, PartFormat::V3, PartFormat::V4]);
+        assert_eq!(accepted(version + PART_FORMAT_COMPATIBILITY_VERSIONS), vec![PartFormat::V4]);
+        assert_eq!(accepted(version - 1), vec![PartFormat::V1, PartFormat::V2, PartFormat::V3]);
+
+        let accepted_formats = AcceptedPartFormats::for_protocol_version(version);
+        assert!(accepted_formats.is_previous_format(&parts[2]));
+        assert!(!accepted_formats.is_previous_format(&parts[3]));
+        // The witness hash is enabled before all the versions of the window.
+        assert!(accepted_formats
+            .check(&PartialWitnessPart { witness_hash: None, ..parts[3] })
+            .is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Witness parts too large to be sent in one routed message.
+//!
+//! A part is roughly the compressed witness divided by the number of data parts, so with few
+//! chunk validators, as on the testnets, a large witness makes parts above the max payload of a
+//! routed message, see `NetworkConfig::max_routed_message_payload`. Once
+//! `ProtocolFeature::PartialWitnessFragments` is enabled, the sender splits such parts into a
+//! sequence of `PartialEncodedStateWitnessFragment`s, see `split_oversized_part`, and the receiver
+//! joins them back in `PartFragments` before handling the part as if it was received whole. The
+//! fragments aren't signed, so they are only joined with the fragments received from the same
+//! peer, and the joined part must match the hash carried by the fragments.
+//!
+//! The buffer is bounded per peer in the number of parts and in bytes, so that a peer sending
+//! junk fragments can only evict its own parts. The parts of the peer holding the most bytes are
+//! dropped when the buffer is full altogether. The parts whose fragments don't all arrive within
+//! `PART_FRAGMENTS_TTL` are dropped as well. A part losing a fragment is then missing like a part
+//! lost whole: the witness is decoded from the other parts, or the part is requested from its
+//! owner.
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_async::time::{Duration, Instant};
+use near_chain::Error;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::partial_witness::{
+    PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+};
+use near_primitives::version::{ProtocolFeature, ProtocolVersion};
+
+use crate::metrics;
+
+/// Number of parts whose fragments are being received at once from a peer.
+const MAX_PARTS_PER_PEER: usize = 8;
+
+/// Bytes of the fragments held at once for a peer, above which its least recently updated parts
+/// are dropped. Above the largest part, i.e. a whole witness at the size limit.
+const MAX_PART_FRAGMENTS_BYTES_PER_PEER: usize = 96 * 1024 * 1024;
+
+/// Bytes of the fragments held at once for all the peers, above which the least recently updated
+/// parts of the peer holding the most bytes are dropped.
+const MAX_PART_FRAGMENTS_BYTES: usize = 256 * 1024 * 1024;
+
+/// Number of fragments of a part accepted, which bounds the slots allocated for a part.
+const MAX_FRAGMENTS_PER_PART: usize = 1024;
+
+/// Time within which all the fragments of a part must arrive.
+pub const PART_FRAGMENTS_TTL: Duration = Duration::seconds(10);
+
+/// Splits the part into fragments if its `serialized_size` exceeds `max_payload`, returns None if
+/// the part can be sent whole. The parts are always sent whole at the protocol versions before
+/// `ProtocolFeature::PartialWitnessFragments`, as the older nodes can't decode the fragments.
+pub fn split_oversized_part(
+    partial_witness: &PartialEncodedStateWitness,
+    serialized_size: usize,
+    forward: bool,
+    max_payload: usize,
+    protocol_version: ProtocolVersion,
+) -> Option<Vec<PartialEncodedStateWitnessFragment>> {
+    if serialized_size <= max_payload
+        || !ProtocolFeature::PartialWitnessFragments.enabled(protocol_version)
+    {
+        return None;
+    }
+    metrics::PARTIAL_WITNESS_FRAGMENTED_PARTS.inc();
+    Some(PartialEncodedStateWitnessFragment::split(partial_witness, forward, max_payload))
+}
+
+/// Part joined from its fragments.
+#[derive(Debug)]
+pub struct JoinedPart {
+    pub partial_witness: PartialEncodedStateWitness,
+    /// Whether the part is forwarded by its owner rather than sent by the chunk producer.
+    pub forward: bool,
+}
+
+/// Part whose fragments are being received.
+struct PendingPart {
+    /// First fragment received, without its bytes, which the other fragments must agree with.
+    header: PartialEncodedStateWitnessFragment,
+    fragments: Vec<Option<Vec<u8>>>,
+    num_received: usize,
+    size: usize,
+    first_received_at: Instant,
+}
+
+impl PendingPart {
+    fn new(fragment: &PartialEncodedStateWitnessFragment, now: Instant) -> Self {
+        let header = PartialEncodedStateWitnessFragment { bytes: vec![], ..fragment.clone() };
+        Self {
+            header,
+            fragments: vec![None; fragment.num_fragments],
+            num_received: 0,
+            size: 0,
+            first_received_at: now,
+        }
+    }
+
+    fn conflicts_with(&self, fragment: &PartialEncodedStateWitnessFragment) -> bool {
+        let header = &self.header;
+        header.chunk_production_key() != fragment.chunk_production_key()
+            || header.part_ord != fragment.part_ord
+            || header.forward != fragment.forward
+            || header.num_fragments != fragment.num_fragments
+    }
+
+    fn join(self) -> Result<JoinedPart, Error> {
+        let header = self.header;
+        let bytes = self.fragments.into_iter().flatten().collect::<Vec<_>>().concat();
+        if CryptoHash::hash_bytes(&bytes) != header.part_hash {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Fragments of part_ord {} don't join into the part with hash {}",
+                header.part_ord, header.part_hash
+            )));
+        }
+        let partial_witness =
+            borsh::from_slice::<PartialEncodedStateWitness>(&bytes).map_err(|err| {
+                Error::InvalidPartialChunkStateWitness(format!(
+                    "Fragments of part_ord {} don't join into a part: {}",
+                    header.part_ord, err
+                ))
+            })?;
+        if partial_witness.chunk_production_key() != header.chunk_production_key()
+            || partial_witness.part_ord() != header.part_ord
+        {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Fragments of part_ord {} of {:?} join into the part_ord {} of {:?}",
+                header.part_ord,
+                header.chunk_production_key(),
+                partial_witness.part_ord(),
+                partial_witness.chunk_production_key()
+            )));
+        }
+        Ok(JoinedPart { partial_witness, forward: header.forward })
+    }
+}
+
+/// Parts whose fragments are being received from one peer.
+struct PeerParts {
+    /// Keyed by the hash of the part.
+    parts: LruCache<CryptoHash, PendingPart>,
+    /// Bytes of the fragments held in `parts`.
+    size: usize,
+}
+
+impl PeerParts {
+    fn new() -> Self {
+        Self { parts: LruCache::new(NonZeroUsize::new(MAX_PARTS_PER_PEER).unwrap()), size: 0 }
+    }
+
+    /// Drops the least recently updated part, returns its size, None if there is no part.
+    fn drop_lru(&mut self) -> Option<usize> {
+        let (_, evicted) = self.parts.pop_lru()?;
+        self.size -= evicted.size;
+        report_dropped_part(&evicted, "evicted");
+        Some(evicted.size)
+    }
+}
+
+pub struct PartFragments {
+    /// Keyed by the peer which delivered the fragments.
+    peers: HashMap<PeerId, PeerParts>,
+    /// Bytes of the fragments held for all the peers.
+    size: usize,
+}
+
+impl PartFragments {
+    pub fn new() -> Self {
+        Self { peers: HashMap::new(), size: 0 }
+    }
+
+    /// Stores the fragment delivered by `from_peer`, and returns the part once all its fragments
+    /// are received. A fragment received again is ignored.
+    pub fn insert(
+        &mut self,
+        fragment: PartialEncodedStateWitnessFragment,
+        from_peer: PeerId,
+        now: Instant,
+    ) -> Result<Option<JoinedPart>, Error> {
+        self.drop_expired(now);
+        if fragment.num_fragments > MAX_FRAGMENTS_PER_PART
+            || fragment.fragment_ord >= fragment.num_fragments
+            || fragment.bytes.is_empty()
+        {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Invalid fragment {} of {} of part_ord {} with {} bytes",
+                fragment.fragment_ord,
+                fragment.num_fragments,
+                fragment.part_ord,
+                fragment.bytes.len()
+            )));
+        }
+        let part_hash = fragment.part_hash;
+        let peer_parts = self.peers.entry(from_peer.clone()).or_insert_with(PeerParts::new);
+        match peer_parts.parts.peek(&part_hash) {
+            Some(pending) if pending.conflicts_with(&fragment) => {
+                return Err(Error::InvalidPartialChunkStateWitness(format!(
+                    "Fragment {:?} conflicts with the fragments received before",
+                    fragment
+                )));
+            }
+            Some(_) => {}
+            None => {
+                if let Some((_, evicted)) =
+                    peer_parts.parts.push(part_hash, PendingPart::new(&fragment, now))
+                {
+                    peer_parts.size -= evicted.size;
+                    self.size -= evicted.size;
+                    report_dropped_part(&evicted, "evicted");
+                }
+            }
+        }
+
+        let pending = peer_parts.parts.get_mut(&part_hash).unwrap();
+        let slot = &mut pending.fragments[fragment.fragment_ord];
+        if slot.is_some() {
+            return Ok(None);
+        }
+        let fragment_size = fragment.bytes.len();
+        pending.size += fragment_size;
+        pending.num_received += 1;
+        *slot = Some(fragment.bytes);
+        peer_parts.size += fragment_size;
+        self.size += fragment_size;
+        if pending.num_received == pending.fragments.len() {
+            let pending = peer_parts.parts.pop(&part_hash).unwrap();
+            peer_parts.size -= pending.size;
+            self.size -= pending.size;
+            if peer_parts.parts.is_empty() {
+                self.peers.remove(&from_peer);
+            }
+            self.update_metrics();
+            return pending.join().map(Some);
+        }
+
+        // A peer only evicts its own parts, unless the peers together hold too many bytes.
+        while peer_parts.size > MAX_PART_FRAGMENTS_BYTES_PER_PEER {
+            let Some(dropped) = peer_parts.drop_lru() else {
+                break;
+            };
+            self.size -= dropped;
+        }
+        while self.size > MAX_PART_FRAGMENTS_BYTES {
+            let Some(peer_parts) = self.peers.values_mut().max_by_key(|peer_parts| peer_parts.size)
+            else {
+                break;
+            };
+            let Some(dropped) = peer_parts.drop_lru() else {
+                break;
+            };
+            self.size -= dropped;
+        }
+        self.peers.retain(|_, peer_parts| !peer_parts.parts.is_empty());
+        self.update_metrics();
+        Ok(None)
+    }
+
+    /// Number of parts whose fragments are being received.
+    pub fn len(&self) -> usize {
+        self.peers.values().map(|peer_parts| peer_parts.parts.len()).sum()
+    }
+
+    fn drop_expired(&mut self, now: Instant) {
+        for peer_parts in self.peers.values_mut() {
+            let expired = peer_parts
+                .parts
+                .iter()
+                .filter(|(_, pending)| now >= pending.first_received_at + PART_FRAGMENTS_TTL)
+                .map(|(part_hash, _)| *part_hash)
+                .collect::<Vec<_>>();
+            for part_hash in expired {
+                let pending = peer_parts.parts.pop(&part_hash).unwrap();
+                peer_parts.size -= pending.size;
+                self.size -= pending.size;
+                report_dropped_part(&pending, "expired");
+            }
+        }
+        self.peers.retain(|_, peer_parts| !peer_parts.parts.is_empty());
+        self.update_metrics();
+    }
+
+    fn update_metrics(&self) {
+        metrics::PARTIAL_WITNESS_PART_FRAGMENTS_BYTES.set(self.size as i64);
+    }
+}
+
+fn report_dropped_part(pending: &PendingPart, reason: &str) {
+    tracing::debug!(
+        target: "client",
+        key = ?pending.header.chunk_production_key(),
+        part_ord = pending.header.part_ord,
+        num_received = pending.num_received,
+        num_fragments = pending.fragments.len(),
+        reason,
+        "Dropping the fragments of an incomplete witness part"
+    );
+    metrics::PARTIAL_WITNESS_DROPPED_FRAGMENTED_PARTS.with_label_values(&[reason]).inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::{FakeClock, Utc};
+    use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::EpochId;
+
+    use super::*;
+
+    fn part(part_byte: u8) -> PartialEncodedStateWitness {
+        let signer = create_test_signer("test");
+        let chunk_header = ChunkStateWitness::new_dummy(5, 0, CryptoHash::default()).chunk_header;
+        PartialEncodedStateWitness::new(
+            EpochId::default(),
+            chunk_header,
+            1,
+            vec![part_byte; 1000],
+            2000,
+            &signer,
+        )
+    }
+
+    fn split(
+        partial_witness: &PartialEncodedStateWitness,
+        forward: bool,
+        max_payload: usize,
+    ) -> Option<Vec<PartialEncodedStateWitnessFragment>> {
+        let serialized_size = borsh::object_length(partial_witness).unwrap();
+        split_oversized_part(
+            partial_witness,
+            serialized_size,
+            forward,
+            max_payload,
+            ProtocolFeature::PartialWitnessFragments.protocol_version(),
+        )
+    }
+
+    fn peer(seed: &str) -> PeerId {
+        PeerId::new(create_test_signer(seed).public_key())
+    }
+
+    #[test]
+    fn part_is_joined_from_fragments_in_any_order() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut buffer = PartFragments::new();
+        let partial_witness = part(1);
+        assert!(split(&partial_witness, true, 1 << 20).is_none());
+        let mut fragments = split(&partial_witness, true, 300).unwrap();
+        assert!(fragments.len() > 2);
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments.iter().cloned() {
+            assert!(buffer.insert(fragment, peer("a"), clock.now()).unwrap().is_none());
+        }
+        // A fragment received again is ignored.
+        assert!(buffer.insert(fragments[0].clone(), peer("a"), clock.now()).unwrap().is_none());
+        // The fragments of another peer aren't joined with them.
+        assert!(buffer.insert(last.clone(), peer("b"), clock.now()).unwrap().is_none());
+        assert_eq!(buffer.len(), 2);
+
+        let joined = buffer.insert(last, peer("a"), clock.now()).unwrap().unwrap();
+        assert_eq!(joined.partial_witness, partial_witness);
+        assert!(joined.forward);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn part_missing_a_fragment_is_dropped() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut buffer = PartFragments::new();
+        let fragments = split(&part(1), false, 300).unwrap();
+        for fragment in fragments.iter().skip(1).cloned() {
+            assert!(buffer.insert(fragment, peer("a"), clock.now()).unwrap().is_none());
+        }
+        assert_eq!(buffer.len(), 1);
+        let expired = || {
+            metrics::PARTIAL_WITNESS_DROPPED_FRAGMENTED_PARTS.with_label_values(&["expired"]).get()
+        };
+        let expired_before = expired();
+
+        // The lost fragment arrives too late to complete the part.
+        clock.advance(PART_FRAGMENTS_TTL);
+        let late = buffer.insert(fragments[0].clone(), peer("a"), clock.now()).unwrap();
+        assert!(late.is_none());
+        assert!(expired() > expired_before);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.size, fragments[0].bytes.len());
+    }
+
+    #[test]
+    fn invalid_fragments_are_rejected() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut buffer = PartFragments::new();
+        let fragments = split(&part(1), false, 300).unwrap();
+        let insert =
+            |buffer: &mut PartFragments, fragment| buffer.insert(fragment, peer("a"), clock.now());
+
+        let out_of_range = PartialEncodedStateWitnessFragment {
+            fragment_ord: fragments.len(),
+            ..fragments[0].clone()
+        };
+        assert!(insert(&mut buffer, out_of_range).is_err());
+        let too_many = PartialEncodedStateWitnessFragment {
+            num_fragments: MAX_FRAGMENTS_PER_PART + 1,
+            ..fragments[0].clone()
+        };
+        assert!(insert(&mut buffer, too_many).is_err());
+
+        insert(&mut buffer, fragments[0].clone()).unwrap();
+        let conflicting =
+            PartialEncodedStateWitnessFragment { forward: true, ..fragments[1].clone() };
+        assert!(insert(&mut buffer, conflicting).is_err());
+
+        // The bytes of a fragment are replaced, so the joined part doesn't match its hash.
+        let tampered = PartialEncodedStateWitnessFragment {
+            bytes: vec![0; fragments[1].bytes.len()],
+            ..fragments[1].clone()
+        };
+        insert(&mut buffer, tampered).unwrap();
+        let (last, rest) = fragments[2..].split_last().unwrap();
+        for fragment in rest {
+            insert(&mut buffer, fragment.clone()).unwrap();
+        }
+        assert!(insert(&mut buffer, last.clone()).is_err());
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.size, 0);
+    }
+
+    #[test]
+    fn peer_sending_junk_only_evicts_its_own_parts() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut buffer = PartFragments::new();
+        let fragments = split(&part(1), false, 300).unwrap();
+        buffer.insert(fragments[0].clone(), peer("honest"), clock.now()).unwrap();
+
+        // Fragments of made up parts, each evicting the previous ones of the same peer.
+        for junk in 0..2 * MAX_PARTS_PER_PEER {
+            let fragment = PartialEncodedStateWitnessFragment {
+                part_hash: CryptoHash::hash_bytes(&junk.to_le_bytes()),
+                ..fragments[0].clone()
+            };
+            buffer.insert(fragment, peer("junk"), clock.now()).unwrap();
+        }
+        assert_eq!(buffer.len(), MAX_PARTS_PER_PEER + 1);
+
+        for fragment in fragments[1..fragments.len() - 1].iter().cloned() {
+            assert!(buffer.insert(fragment, peer("honest"), clock.now()).unwrap().is_none());
+        }
+        let last = fragments.last().unwrap().clone();
+        let joined = buffer.insert(last, peer("honest"), clock.now()).unwrap().unwrap();
+        assert_eq!(joined.partial_witness, part(1));
+        assert_eq!(buffer.len(), MAX_PARTS_PER_PEER);
+    }
+
+    #[test]
+    fn parts_are_sent_whole_before_the_feature() {
+        let partial_witness = part(1);
+        let serialized_size = borsh::object_length(&partial_witness).unwrap();
+        let version = ProtocolFeature::PartialWitnessFragments.protocol_version();
+        let split_at = |protocol_version| {
+            split_oversized_part(&partial_witness, serialized_size, false, 300, protocol_version)
+        };
+        assert!(split_at(version - 1).is_none());
+        assert!(split_at(version).is_some());
+    }
+}
@@ -0,0 +1,159 @@
+//! Fair sending of the parts of the witnesses we produce for several shards at once.
+//!
+//! A chunk producer of several shards at the same height gets one distribution request per shard,
+//! and used to send all the parts of the first witness before any part of the next one, so the
+//! chunk validators of the shards handled last systematically received their parts later. The
+//! parts of the requests handled within `PartialWitnessConfig::part_send_window` are queued per
+//! shard instead and sent round-robin across the shards, so the first parts of every shard leave
+//! first. The consecutive parts of the same witness are still sent in one message.
+
+use std::collections::VecDeque;
+
+use near_network::state_witness::WitnessRoutingHints;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::types::{AccountId, ShardId};
+
+struct QueuedPart {
+    owner: AccountId,
+    partial_witness: PartialEncodedStateWitness,
+    routing_hints: WitnessRoutingHints,
+}
+
+/// Parts of the same witness sent to their owners in one message.
+#[derive(Debug)]
+pub struct PartSendBatch {
+    pub parts: Vec<(AccountId, PartialEncodedStateWitness)>,
+    pub routing_hints: WitnessRoutingHints,
+}
+
+#[derive(Default)]
+pub struct PartSendQueue {
+    /// Parts waiting to be sent, per shard. The shards are served in the order in which their
+    /// first part was queued, the witnesses of the same shard one after another.
+    shards: VecDeque<(ShardId, VecDeque<QueuedPart>)>,
+}
+
+impl PartSendQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Queues the parts of a witness of `shard_id` for their owners.
+    pub fn push(
+        &mut self,
+        shard_id: ShardId,
+        parts: Vec<(AccountId, PartialEncodedStateWitness)>,
+        routing_hints: WitnessRoutingHints,
+    ) {
+        if parts.is_empty() {
+            return;
+        }
+        let queued_parts = parts.into_iter().map(|(owner, partial_witness)| QueuedPart {
+            owner,
+            partial_witness,
+            routing_hints: routing_hints.clone(),
+        });
+        match self.shards.iter_mut().find(|(queued_shard_id, _)| *queued_shard_id == shard_id) {
+            Some((_, queue)) => queue.extend(queued_parts),
+            None => self.shards.push_back((shard_id, queued_parts.collect())),
+        }
+    }
+
+    /// Takes all the queued parts, taking one part of every shard in turn. The consecutive parts
+    /// of the same witness are batched together, so the parts of a single witness make up one
+    /// batch.
+    pub fn drain(&mut self) -> Vec<PartSendBatch> {
+        let mut batches: Vec<PartSendBatch> = vec![];
+        while !self.shards.is_empty() {
+            self.shards.retain_mut(|(_, queue)| {
+                let Some(part) = queue.pop_front() else {
+                    return false;
+                };
+                match batches.last_mut() {
+                    Some(batch)
+                        if batch.parts[0].1.chunk_production_key()
+                            == part.partial_witness.chunk_production_key() =>
+                    {
+                        batch.parts.push((part.owner, part.partial_witness));
+                    }
+                    _ => batches.push(PartSendBatch {
+                        parts: vec![(part.owner, part.partial_witness)],
+                        routing_hints: part.routing_hints,
+                    }),
+                }
+                !queue.is_empty()
+            });
+        }
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_network::state_witness::WitnessRoutingHints;
+    use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+    use super::PartSendQueue;
+    use crate::test_utils::TestWitnessBuilder;
+
+    fn parts(
+        shard_id: ShardId,
+        height: BlockHeight,
+        num_parts: usize,
+    ) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+        TestWitnessBuilder::new()
+            .shard_id(shard_id)
+            .height(height)
+            .encode_and_split(num_parts, &create_test_signer("producer"))
+            .into_owned_parts()
+    }
+
+    /// Shard, height and part ordinals of the parts of every batch.
+    fn batches(queue: &mut PartSendQueue) -> Vec<(ShardId, BlockHeight, Vec<usize>)> {
+        queue
+            .drain()
+            .into_iter()
+            .map(|batch| {
+                let key = batch.parts[0].1.chunk_production_key();
+                let part_ords = batch.parts.iter().map(|(_, part)| part.part_ord()).collect();
+                (key.shard_id, key.height_created, part_ords)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_witness_is_sent_in_one_batch() {
+        let mut queue = PartSendQueue::new();
+        queue.push(0, parts(0, 10, 3), WitnessRoutingHints::default());
+        assert_eq!(batches(&mut queue), vec![(0, 10, vec![0, 1, 2])]);
+        assert!(queue.is_empty());
+        assert!(queue.drain().is_empty());
+    }
+
+    #[test]
+    fn parts_of_shards_are_interleaved() {
+        let mut queue = PartSendQueue::new();
+        queue.push(1, parts(1, 10, 3), WitnessRoutingHints::default());
+        queue.push(0, parts(0, 10, 2), WitnessRoutingHints::default());
+        // The next witness of shard 1 waits for the parts of the first one.
+        queue.push(1, parts(1, 11, 2), WitnessRoutingHints::default());
+        assert_eq!(
+            batches(&mut queue),
+            vec![
+                (1, 10, vec![0]),
+                (0, 10, vec![0]),
+                (1, 10, vec![1]),
+                (0, 10, vec![1]),
+                (1, 10, vec![2]),
+                (1, 11, vec![0, 1]),
+            ]
+        );
+        assert!(queue.is_empty());
+    }
+}
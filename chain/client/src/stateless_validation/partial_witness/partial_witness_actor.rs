@@ -1,99 +1,879 @@
+use std::collections::{HashSet, VecDeque};
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 
+use bytesize::ByteSize;
 use itertools::Itertools;
+use lru::LruCache;
+use near_async::futures::{DelayedActionRunner, DelayedActionRunnerExt};
 use near_async::messaging::{Actor, CanSend, Handler, Sender};
-use near_async::time::Clock;
+use near_async::time::{Clock, Duration, Instant, Utc};
 use near_async::{MultiSend, MultiSenderFrom};
+use near_chain::chain::ChunkStateWitnessMessage;
 use near_chain::Error;
-use near_chain_configs::MutableValidatorSigner;
+use near_chain_configs::{HandlerPanicPolicy, MutableValidatorSigner, PartialWitnessConfig};
+use near_epoch_manager::shard_tracker::ShardTracker;
 use near_epoch_manager::EpochManagerAdapter;
+use near_network::config::DEFAULT_MAX_ROUTED_MESSAGE_PAYLOAD;
 use near_network::state_witness::{
-    ChunkStateWitnessAckMessage, PartialEncodedStateWitnessForwardMessage,
-    PartialEncodedStateWitnessMessage,
+    BatchedChunkStateWitnessAckMessage, ChunkStateWitnessAckMessage,
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, WitnessDelivery, WitnessDeliveryReportMessage,
+    WitnessReceiverStatusMessage, WitnessRoutingHints,
 };
 use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
+use near_o11y::metrics::{int_label, IntGauge};
 use near_performance_metrics_macros::perf;
-use near_primitives::sharding::ShardChunkHeader;
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::block::{BlockHeader, Tip};
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
+use near_primitives::stateless_validation::estimate_size::{EstimateSize, SizeEstimator};
+use near_primitives::stateless_validation::partial_witness::{
+    ChunkValidatorsDigest, FullEncodedStateWitness, PartialEncodedStateWitness,
+    PartialEncodedStateWitnessFragment, PartialEncodedStateWitnessRequest, WitnessReceiverStatus,
+};
 use near_primitives::stateless_validation::state_witness::{
-    ChunkStateWitness, ChunkStateWitnessAck, EncodedChunkStateWitness,
+    BatchedChunkStateWitnessAckV2, ChunkStateWitness, ChunkStateWitnessSectionSizes,
+    EncodedChunkStateWitness, VersionedChunkStateWitnessAck,
 };
+use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
 use near_primitives::stateless_validation::ChunkProductionKey;
-use near_primitives::types::{AccountId, EpochId};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, EpochId, ShardId};
 use near_primitives::validator_signer::ValidatorSigner;
-use near_store::Store;
+use near_primitives::version::{ProtocolFeature, ProtocolVersion};
+use near_primitives::views::{
+    PartialWitnessDebugView, ProducedWitnessSectionSizesView, ShardValidationLagView,
+    WitnessConfigView,
+};
+use near_store::{DBCol, Store, FINAL_HEAD_KEY, HEAD_KEY};
+use time::ext::InstantExt as _;
+use tokio::sync::broadcast;
 
 use crate::client_actor::ClientSenderForPartialWitness;
 use crate::metrics;
-use crate::stateless_validation::state_witness_tracker::ChunkStateWitnessTracker;
-use crate::stateless_validation::validate::validate_partial_encoded_state_witness;
+use crate::stateless_validation::state_witness_tracker::{
+    ChunkStateWitnessTracker, WitnessDistributionSummary,
+};
+use crate::stateless_validation::validate::{
+    chunk_validators_digest_mismatch, validate_chunk_production_key_height_window,
+    validate_full_encoded_state_witness, validate_partial_encoded_state_witness, validate_shard_id,
+    ChainHeads, HeightWindowContext, ValidationContext,
+};
+
+#[cfg(feature = "test_features")]
+use super::adversarial::{
+    self, AdvWitnessPartsMessage, AdvWitnessPartsMode, ForceRedistributeWitness,
+};
+use super::decoded_witnesses::{WitnessDecodeConflict, WitnessDecodePath};
+use super::delivered_witnesses::DeliveredWitnesses;
+use super::effective_config::witness_config_view;
+use super::encoding::ReedSolomonBackend;
+use super::epoch_scoped_caches::EpochScopedCaches;
+use super::error_reporter::{
+    PartialWitnessErrorEvent, PartialWitnessErrorReporter, PartialWitnessErrorStage,
+};
+use super::forward_backoff::ForwardBackoff;
+use super::forward_targets::{ForwardTargets, ForwardTargetsCache};
+use super::framing_overhead::FramingOverhead;
+use super::handler_panic::{self, HandlerPanic, HandlerPanicReport};
+use super::health::{HealthInputs, StatelessValidationHealth, HEALTH_REPORT_PERIOD};
+use super::lifecycle_tracker::WitnessOutcomeRecord;
+use super::link_loss::LinkLossEstimate;
+use super::message_recorder::{RecordedMessageKind, WitnessMessageRecorder};
+use super::part_format::PartFormat;
+use super::part_fragments::{split_oversized_part, JoinedPart, PartFragments};
+use super::part_send_queue::PartSendQueue;
+use super::partial_witness_tracker::{
+    CorruptedWitnessPart, PartialEncodedStateWitnessTracker, WitnessConflictEvidence,
+    WitnessKeyMismatchEvidence,
+};
+use super::pending_epoch_parts::{PendingEpochParts, EPOCH_INFO_CHECK_PERIOD};
+use super::pre_tracking;
+use super::producer_health::{PartDelivery, ProducerDistributionHealth};
+use super::shard_tracking_check::{
+    untracked_validated_shards, ShardDuties, ShardTrackingCheck, SHARD_TRACKING_CHECK_HEIGHTS,
+};
+use super::signer_snapshot::SignerSnapshot;
+use super::state_snapshot::{PartialWitnessState, PartialWitnessStateV1};
+use super::stats_export::{WitnessStatsExporter, WitnessStatsRecord, WitnessStatsSource};
+use super::time_slice::TimeSlice;
+use super::unavailable_receivers::UnavailableReceivers;
+use super::validation_lag::{ShardDutyAtHead, ValidationLag};
+use super::verification_load::{SignatureVerificationKind, SignatureVerificationLoad};
+use super::witness_parts_geometry;
+
+/// Number of the most recently produced witnesses for which we keep the section sizes breakdown.
+const WITNESS_SECTION_SIZES_CACHE_SIZE: usize = 50;
+
+/// Number of the most recent parts owned by us, i.e. received directly from the chunk producer,
+/// that we keep around to answer requests from the chunk validators missing them.
+const OWNED_PARTS_CACHE_SIZE: usize = 50;
+
+/// Number of the most recent witnesses produced by us for which we keep all the parts around
+/// to answer requests from the chunk validators missing them.
+const PRODUCED_PARTS_CACHE_SIZE: usize = 10;
+
+/// Number of distinct chunk validators requesting our part after which we assume that our initial
+/// forward was lost for many of them and re-broadcast the part to all the chunk validators.
+const OWNED_PART_REBROADCAST_MIN_REQUESTERS: usize = 2;
+
+/// Number of the most recent heights for which we track the bytes of the full witnesses sent,
+/// see `PartialWitnessConfig::direct_full_witness_budget_per_height`.
+const FULL_WITNESS_BUDGET_HEIGHTS: usize = 10;
+
+/// Number of peers for which we count the forwarded parts of nonexistent shards.
+const INVALID_SHARD_ID_PEERS_CACHE_SIZE: usize = 100;
+
+/// Number of the most recent witnesses for which we keep the mismatching views of the chunk
+/// validators, so that every witness is counted and logged once.
+const CHUNK_VALIDATORS_MISMATCHES_CACHE_SIZE: usize = 100;
+
+/// How often we check for the witnesses which the client didn't confirm to have consumed.
+const UNCONSUMED_WITNESSES_CHECK_PERIOD: Duration = Duration::seconds(1);
+
+/// How often we log the distribution summaries of the witnesses with missing acks.
+const DISTRIBUTION_SUMMARIES_CHECK_PERIOD: Duration = Duration::milliseconds(200);
+
+/// How often we look for the witnesses whose parts are missing past
+/// `PartialWitnessConfig::missing_part_request_delay`.
+const MISSING_PARTS_CHECK_PERIOD: Duration = Duration::milliseconds(100);
+
+/// Delay between producing the chunk and receiving the distribution request above which we
+/// warn, since the client is the bottleneck of the witness distribution in such cases.
+const DISTRIBUTION_REQUEST_DELAY_WARN_THRESHOLD: Duration = Duration::milliseconds(500);
+
+/// Time after distributing the witness of a chunk for which another request to distribute it is
+/// dropped as a duplicate, unless forced, see `DistributeStateWitnessRequest::force`. The client
+/// retries within a block time, so it doesn't have to cover more than a few of them.
+const DUPLICATE_DISTRIBUTION_REQUEST_TTL: Duration = Duration::seconds(10);
+
+/// Number of the chunks distributed within `DUPLICATE_DISTRIBUTION_REQUEST_TTL` that we remember,
+/// enough for all the shards we may produce chunks for over the TTL.
+const DISTRIBUTED_CHUNKS_CACHE_SIZE: usize = 100;
+
+/// Witness part held back before handling, while the node is syncing the chain or while the epoch
+/// manager doesn't know the epoch of the part, see `pending_epoch_parts`.
+enum HeldPart {
+    Direct(PartialEncodedStateWitnessMessage),
+    Forward(PartialEncodedStateWitnessForwardMessage),
+}
+
+impl HeldPart {
+    fn key(&self) -> ChunkProductionKey {
+        match self {
+            HeldPart::Direct(msg) => msg.0.chunk_production_key(),
+            HeldPart::Forward(msg) => msg.0.chunk_production_key(),
+        }
+    }
+
+    fn delivery(&self) -> PartDelivery {
+        match self {
+            HeldPart::Direct(_) => PartDelivery::Direct,
+            HeldPart::Forward(_) => PartDelivery::Forward,
+        }
+    }
+}
+
+/// Forward of our part held while the forwards of its shard back off.
+struct PacedForward {
+    targets: Vec<AccountId>,
+    partial_witness: PartialEncodedStateWitness,
+    routing_hints: WitnessRoutingHints,
+}
+
+/// Witness part that we own along with the bookkeeping of the requests received for it.
+struct OwnedPart {
+    partial_witness: PartialEncodedStateWitness,
+    /// Chunk validators that requested the part from us.
+    requesters: HashSet<AccountId>,
+    /// Whether the part was already re-broadcast. We re-broadcast at most once per part.
+    rebroadcast: bool,
+}
 
-use super::encoding::WitnessEncoderCache;
-use super::partial_witness_tracker::PartialEncodedStateWitnessTracker;
+impl EstimateSize for OwnedPart {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        self.partial_witness.add_heap_size(estimator);
+        // The table of the set holds the accounts and one control byte per bucket.
+        estimator.add_bytes(self.requesters.capacity() * (std::mem::size_of::<AccountId>() + 1));
+        for requester in &self.requesters {
+            requester.add_heap_size(estimator);
+        }
+    }
+}
 
 pub struct PartialWitnessActor {
+    clock: Clock,
     /// Adapter to send messages to the network.
     network_adapter: PeerManagerAdapter,
     /// Validator signer to sign the state witness. This field is mutable and optional. Use with caution!
     /// Lock the value of mutable validator signer for the duration of a request to ensure consistency.
     /// Please note that the locked value should not be stored anywhere or passed through the thread boundary.
+    /// When signing takes long, e.g. the witness parts, use `SignerSnapshot` to check that
+    /// the signer is still current before sending the signed data.
     my_signer: MutableValidatorSigner,
     /// Epoch manager to get the set of chunk validators
     epoch_manager: Arc<dyn EpochManagerAdapter>,
+    /// Shard tracking configuration of the node, checked against the chunk validator duties.
+    shard_tracker: ShardTracker,
+    /// Runs the shard tracking check once per epoch, see `check_shard_tracking`.
+    shard_tracking_check: ShardTrackingCheck,
+    /// Lag of the chunk validator duty behind the head, see `update_validation_lag`.
+    validation_lag: ValidationLag,
+    /// Adapter to notify the client, the tracker keeps its own clone to send the witnesses.
+    client_sender: ClientSenderForPartialWitness,
     /// Tracks the parts of the state witness sent from chunk producers to chunk validators.
     partial_witness_tracker: PartialEncodedStateWitnessTracker,
     /// Tracks a collection of state witnesses sent from chunk producers to chunk validators.
     state_witness_tracker: ChunkStateWitnessTracker,
-    /// Reed Solomon encoder for encoding state witness parts.
-    /// We keep one wrapper for each length of chunk_validators to avoid re-creating the encoder.
-    encoders: WitnessEncoderCache,
+    /// Data scoped to the epochs around the head, i.e. the Reed Solomon encoders for encoding
+    /// state witness parts, one for each length of chunk_validators.
+    epoch_caches: EpochScopedCaches,
     /// Currently used to find the chain HEAD when validating partial witnesses,
     /// but should be removed if we implement retrieving this info from the client
     store: Store,
+    /// Configuration of the partial witness distribution.
+    config: PartialWitnessConfig,
+    /// Section sizes breakdown of the most recently produced witnesses, for debugging
+    /// oversized witnesses.
+    witness_section_sizes: LruCache<ChunkProductionKey, ChunkStateWitnessSectionSizes>,
+    /// Parts that we own for the most recent chunks. Only we can re-send these parts
+    /// if our initial forward didn't reach the other chunk validators.
+    owned_parts: LruCache<ChunkProductionKey, Vec<OwnedPart>>,
+    /// All the parts of the most recent witnesses produced by us, ordered by part_ord. The chunk
+    /// producer isn't necessarily a chunk validator of its own chunk, in which case it doesn't
+    /// own any part, but it can still serve every part of the witness.
+    produced_parts: LruCache<ChunkProductionKey, Vec<PartialEncodedStateWitness>>,
+    /// Chunk validators to which we forward our parts, for the same chunks as `owned_parts`.
+    forward_targets: ForwardTargetsCache,
+    /// Reports the errors raised by the message handlers.
+    error_reporter: PartialWitnessErrorReporter,
+    /// Bytes of the full witnesses sent directly to the chunk validators, per height.
+    full_witness_bytes_sent: LruCache<BlockHeight, usize>,
+    /// Time at which the witness of each recent chunk was distributed, to drop the duplicate
+    /// distribution requests, see `DUPLICATE_DISTRIBUTION_REQUEST_TTL`.
+    distributed_chunks: LruCache<ChunkHash, Instant>,
+    /// Number of the forwarded parts of the shards missing from the shard layout of their epoch,
+    /// per peer which delivered them.
+    invalid_shard_id_parts: LruCache<PeerId, u64>,
+    /// Our view and the chunk producer's view of the chunk validators of the recent witnesses
+    /// for which they differ, see `ChunkValidatorsDigest`.
+    chunk_validators_mismatches:
+        LruCache<ChunkProductionKey, (ChunkValidatorsDigest, ChunkValidatorsDigest)>,
+    /// Records the received parts, see `PartialWitnessConfig::record_messages_path`.
+    message_recorder: Option<WitnessMessageRecorder>,
+    /// Exports the sizes of the produced witnesses, see
+    /// `PartialWitnessConfig::export_witness_stats_dir`.
+    stats_exporter: Option<WitnessStatsExporter>,
+    /// Counts the signature verifications of the received parts and estimates their CPU load.
+    verification_load: SignatureVerificationLoad,
+    /// Time at which the pending expiry of the incomplete witnesses is scheduled, if any.
+    /// There is at most one expiry scheduled at a time, see `schedule_witness_expiry`.
+    witness_expiry_scheduled_at: Option<Instant>,
+    /// Chunk validators which announced that they can't receive their parts for a while.
+    unavailable_receivers: UnavailableReceivers,
+    /// Whether the decode of the witnesses with enough parts is scheduled, see
+    /// `PartialWitnessConfig::decode_batch_window`.
+    ready_witnesses_decode_scheduled: bool,
+    /// Whether the decode which ran out of its time slice is scheduled to continue, see
+    /// `PartialWitnessConfig::processing_time_slice`.
+    ready_witnesses_decode_continued: bool,
+    /// Whether handling the parts held during the sync is scheduled to continue, see
+    /// `schedule_held_parts_handling`.
+    held_parts_handling_scheduled: bool,
+    /// Time at which sending the held acks is scheduled, if any, see `schedule_ack_flush`.
+    ack_flush_scheduled_at: Option<Instant>,
+    /// Whether the client is syncing the chain, see `SyncStatusChangedMessage`.
+    syncing: bool,
+    /// Parts received while syncing, the oldest first, see
+    /// `PartialWitnessConfig::max_parts_buffered_during_sync`.
+    parts_received_during_sync: VecDeque<HeldPart>,
+    /// Parts of the epochs of the head which the epoch manager doesn't know yet, see
+    /// `pending_epoch_parts`.
+    pending_epoch_parts: PendingEpochParts<HeldPart>,
+    /// Whether checking the epoch manager again is scheduled, see `schedule_epoch_info_check`.
+    epoch_info_check_scheduled: bool,
+    /// Parts of the witnesses produced by us waiting to be sent, see
+    /// `PartialWitnessConfig::part_send_window`.
+    part_send_queue: PartSendQueue,
+    /// Whether sending the queued parts is scheduled, see `schedule_part_sends`.
+    part_sends_scheduled: bool,
+    /// Reduces the forwards of our parts while the sends fail, see
+    /// `PartialWitnessConfig::forward_backoff`.
+    forward_backoff: ForwardBackoff,
+    /// Forwards of our parts waiting to be sent while backing off, see `schedule_paced_forwards`.
+    paced_forwards: Vec<PacedForward>,
+    /// Whether sending the paced forwards is scheduled.
+    paced_forwards_scheduled: bool,
+    /// Highest known tip of the forks other than the one of the head, as of the last head update,
+    /// see `PartialWitnessConfig::fork_aware_height_window`.
+    alternative_tip: Option<Tip>,
+    /// Asks the node to shut down, see `HandlerPanicPolicy::Shutdown`.
+    shutdown_signal: Option<broadcast::Sender<()>>,
+    /// Report of the last panic in a handler, see `handler_panic`.
+    last_handler_panic: Option<HandlerPanicReport>,
+    /// Max serialized size of a part sent in one routed message, the larger parts are sent as
+    /// fragments, see `NetworkConfig::max_routed_message_payload`.
+    max_routed_message_payload: usize,
+    /// Framing bytes of the parts sent to the network, see `framing_overhead`.
+    framing_overhead: FramingOverhead,
+    /// Fragments of the parts received from the peers, until all the fragments of a part arrive.
+    part_fragments: PartFragments,
+    /// Protocol version of the effective config last sent to the client, see
+    /// `publish_witness_config`.
+    published_witness_config_version: Option<ProtocolVersion>,
+    /// Byzantine behavior simulated when distributing our witnesses, see `AdvWitnessPartsMode`.
+    #[cfg(feature = "test_features")]
+    adv_witness_parts_mode: AdvWitnessPartsMode,
 }
 
-impl Actor for PartialWitnessActor {}
+impl Actor for PartialWitnessActor {
+    fn start_actor(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        self.periodically_check_unconsumed_witnesses(ctx);
+        self.periodically_emit_distribution_summaries(ctx);
+        self.periodically_report_health(ctx);
+        if self.config.missing_part_request_delay.is_some() {
+            self.periodically_request_missing_parts(ctx);
+        }
+        match self.load_head() {
+            Ok(Some((head, _))) => self.publish_witness_config(&head.epoch_id),
+            Ok(None) => {}
+            Err(err) => tracing::debug!(target: "client", ?err, "Failed to load the head"),
+        }
+    }
+
+    /// Decodes the witnesses completed by the message and schedules the expiry of the incomplete
+    /// witnesses after every message, as any message handling the witness parts may complete
+    /// a witness or start the deadline of a new one. A panic in the handler is reported and
+    /// handled as `PartialWitnessConfig::handler_panic_policy` says.
+    fn wrap_handler<M: actix::Message>(
+        &mut self,
+        msg: M,
+        ctx: &mut dyn DelayedActionRunner<Self>,
+        f: impl FnOnce(&mut Self, M, &mut dyn DelayedActionRunner<Self>) -> M::Result,
+    ) -> M::Result {
+        let result = match handler_panic::catch_handler_panic::<M, _>(|| f(self, msg, ctx)) {
+            Ok(result) => result,
+            Err(panic) => self.on_handler_panic(panic),
+        };
+        self.schedule_follow_ups(ctx);
+        result
+    }
+}
 
 #[derive(actix::Message, Debug)]
 #[rtype(result = "()")]
 pub struct DistributeStateWitnessRequest {
     pub epoch_id: EpochId,
     pub chunk_header: ShardChunkHeader,
-    pub state_witness: ChunkStateWitness,
+    /// Shared so that the client can keep the witness around without holding a second copy.
+    pub state_witness: Arc<ChunkStateWitness>,
+    pub in_flight_bytes: InFlightWitnessBytes,
+    /// Time at which the client finished producing the chunk.
+    pub chunk_produced_at: Instant,
+    /// Distributes the witness even if it was distributed within
+    /// `DUPLICATE_DISTRIBUTION_REQUEST_TTL`, e.g. to retransmit it deliberately. Otherwise the
+    /// request is dropped as a duplicate.
+    pub force: bool,
+}
+
+impl DistributeStateWitnessRequest {
+    pub fn new(
+        epoch_id: EpochId,
+        chunk_header: ShardChunkHeader,
+        state_witness: Arc<ChunkStateWitness>,
+        chunk_produced_at: Instant,
+    ) -> Self {
+        let witness_size = state_witness.estimate_size();
+        Self {
+            epoch_id,
+            chunk_header,
+            state_witness,
+            in_flight_bytes: InFlightWitnessBytes::new(witness_size),
+            chunk_produced_at,
+            force: false,
+        }
+    }
+
+    /// Distributes the witness again even if it was distributed recently, see `force`.
+    pub fn forced(mut self) -> Self {
+        self.force = true;
+        self
+    }
+}
+
+/// Accounts the size of the witness held by a `DistributeStateWitnessRequest` in
+/// `PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_BYTES` for as long as the guard is alive, which makes
+/// the memory taken by the requests piling up in the actor mailbox visible.
+#[derive(Debug)]
+pub struct InFlightWitnessBytes {
+    gauge: IntGauge,
+    witness_size: usize,
+}
+
+impl InFlightWitnessBytes {
+    pub fn new(witness_size: usize) -> Self {
+        Self::with_gauge(metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_BYTES.clone(), witness_size)
+    }
+
+    fn with_gauge(gauge: IntGauge, witness_size: usize) -> Self {
+        gauge.add(witness_size as i64);
+        Self { gauge, witness_size }
+    }
+}
+
+impl Drop for InFlightWitnessBytes {
+    fn drop(&mut self) {
+        self.gauge.sub(self.witness_size as i64);
+    }
+}
+
+/// Sent by the client once it consumed the witness received in `ChunkStateWitnessMessage`,
+/// regardless of whether the witness turned out to be valid.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct ChunkStateWitnessConsumedMessage {
+    pub key: ChunkProductionKey,
+}
+
+/// Sent by the client to pull the next decoded witness held back while its credits are taken,
+/// see `PartialWitnessConfig::client_witness_credits`. The actor answers with the witness the
+/// client is blocked on, otherwise the witness of the highest height, among the ones with a raw
+/// size up to `max_bytes`, or None if there is none. A pulled witness is never pushed again.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Option<ChunkStateWitnessMessage>")]
+pub struct RequestNextDecodedWitness {
+    pub max_bytes: usize,
+}
+
+/// What eventually happened to a witness sent to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStateWitnessOutcome {
+    /// The witness was validated and the endorsement sent to the block producers.
+    Endorsed,
+    /// The witness failed the validation, or the validation couldn't be started.
+    ValidationFailed,
+    /// The witness waited in the orphan witness pool for its previous block until the chain
+    /// finalized a height past the chunk, after which the chunk can no longer be included.
+    DeadlineMissed,
+    /// The witness was dropped before its previous block arrived, either because the orphan
+    /// witness pool was full or because the witness couldn't be kept in the pool at all.
+    Orphaned,
+    /// The witness was decoded after the block at its height was produced, so the client didn't
+    /// endorse it, whatever the result of the validation.
+    DeclinedLate,
+    /// The witness of a pre-tracked shard was handed to the validation only to warm up the
+    /// caches, so it is never endorsed.
+    PreTracked,
+}
+
+impl ChunkStateWitnessOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkStateWitnessOutcome::Endorsed => "endorsed",
+            ChunkStateWitnessOutcome::ValidationFailed => "validation_failed",
+            ChunkStateWitnessOutcome::DeadlineMissed => "deadline_missed",
+            ChunkStateWitnessOutcome::Orphaned => "orphaned",
+            ChunkStateWitnessOutcome::DeclinedLate => "declined_late",
+            ChunkStateWitnessOutcome::PreTracked => "pre_tracked",
+        }
+    }
+}
+
+/// Sent by the client once the outcome of the witness received in `ChunkStateWitnessMessage` is
+/// known.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct ChunkStateWitnessOutcomeMessage {
+    pub key: ChunkProductionKey,
+    pub outcome: ChunkStateWitnessOutcome,
+}
+
+/// Sent when the node runs low on memory. The actor drops what it can afford to lose: the oldest
+/// incomplete witnesses, the parts kept for re-sending and the unused Reed Solomon encoders.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct ReduceMemoryPressure;
+
+/// Memory released by the actor on `ReduceMemoryPressure`, as estimated by `EstimateSize`. The
+/// parts still shared with the caches that are kept are not counted, as they stay in memory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FreedWitnessMemory {
+    /// Parts of the incomplete witnesses dropped from the tracker.
+    pub tracker_bytes: usize,
+    /// Parts of the witnesses produced by us, kept to serve the part requests.
+    pub produced_parts_bytes: usize,
+    /// Parts owned by us for the chunks at heights up to the head, kept to serve the part requests.
+    pub owned_parts_bytes: usize,
+    /// Number of Reed Solomon encoders dropped, both for encoding and decoding.
+    pub encoders: usize,
+}
+
+/// Sent by the client once it loaded the head after start. The actor prepares what it needs to
+/// handle the first witnesses without delay: the chunk validator assignments and the chunk
+/// producers at the next `PartialWitnessConfig::warm_up_heights` heights, loaded into the epoch
+/// manager caches, and the Reed Solomon encoders for the numbers of chunk validators at them.
+/// The actor answers with `PartialWitnessWarmedUp`.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct WarmUpPartialWitness {
+    pub head: Tip,
+}
+
+/// Sent by the actor to the client once it handled `WarmUpPartialWitness`, whether the warm up
+/// succeeded or not. A failed warm up only delays the handling of the first witnesses.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct PartialWitnessWarmedUp;
+
+/// Sent by the actor to the client every `HEALTH_REPORT_PERIOD`, see
+/// `PartialWitnessActor::stateless_validation_health`.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct StatelessValidationHealthMessage(pub StatelessValidationHealth);
+
+/// Sent by the actor to the client for every error reported by `PartialWitnessErrorReporter`, so
+/// that the client includes it in its telemetry.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct PartialWitnessErrorMessage(pub PartialWitnessErrorEvent);
+
+/// Sent by the actor to the client every `HEALTH_REPORT_PERIOD`, see
+/// `PartialWitnessActor::debug_view`.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct PartialWitnessDebugMessage(pub PartialWitnessDebugView);
+
+/// Sent by the actor to the client once the head is known, and again whenever the protocol
+/// version of the epoch of the head changes the limits of the witnesses.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct WitnessConfigMessage(pub WitnessConfigView);
+
+/// Sent by the client whenever its head changes. The timestamp of the head block is the baseline
+/// for the latency of the witness parts of the chunks built on top of it, see
+/// `ProducerDistributionHealth`. The alternative tip is the highest known block of the other
+/// forks, see `PartialWitnessConfig::fork_aware_height_window`.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct ChainHeadUpdatedMessage {
+    pub head: Tip,
+    pub head_timestamp: Utc,
+    pub alternative_tip: Option<Tip>,
+}
+
+/// Sent by the client when it starts or stops syncing the chain. While syncing, the head of the
+/// node is behind the chain, so the actor drops the requests to distribute the witnesses and
+/// holds the received parts back until the sync is done, see
+/// `PartialWitnessConfig::max_parts_buffered_during_sync`. Once the sync is done, the actor
+/// reloads the head before handling the held parts.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct SyncStatusChangedMessage {
+    pub syncing: bool,
+}
+
+/// Sent by the client when it is blocked on the witness of the chunk, e.g. when the processing of
+/// a block waits for the endorsement of the chunk. The parts of the witness jump the processing
+/// lanes of the actor for a short while, see `PrioritizedWitnesses`.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct PrioritizeWitness(pub ChunkProductionKey);
+
+/// Sent by neard on graceful shutdown to announce to the chunk producers that the node can't
+/// receive the witness parts for `PartialWitnessConfig::announce_unavailability_on_shutdown`.
+/// Does nothing if the announcements are disabled.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct AnnounceWitnessReceiverUnavailable;
+
+impl FreedWitnessMemory {
+    pub fn total_bytes(&self) -> usize {
+        self.tracker_bytes + self.produced_parts_bytes + self.owned_parts_bytes
+    }
 }
 
 #[derive(Clone, MultiSend, MultiSenderFrom)]
 pub struct PartialWitnessSenderForClient {
     pub distribute_chunk_state_witness: Sender<DistributeStateWitnessRequest>,
+    pub chunk_state_witness_consumed: Sender<ChunkStateWitnessConsumedMessage>,
+    pub chunk_state_witness_outcome: Sender<ChunkStateWitnessOutcomeMessage>,
+    pub reduce_memory_pressure: Sender<ReduceMemoryPressure>,
+    pub warm_up: Sender<WarmUpPartialWitness>,
+    pub chain_head_updated: Sender<ChainHeadUpdatedMessage>,
+    pub sync_status_changed: Sender<SyncStatusChangedMessage>,
+    pub prioritize_witness: Sender<PrioritizeWitness>,
+    #[cfg(feature = "test_features")]
+    pub adv_witness_parts: Sender<AdvWitnessPartsMessage>,
 }
 
 impl Handler<DistributeStateWitnessRequest> for PartialWitnessActor {
     #[perf]
     fn handle(&mut self, msg: DistributeStateWitnessRequest) {
+        let key = msg.state_witness.chunk_production_key();
+        handler_panic::set_handler_key(&key);
+        if self.syncing {
+            // The chunk was produced on top of a head which is about to be replaced by the sync.
+            tracing::debug!(target: "client", ?key, "Dropping the witness distribution while syncing");
+            metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_DROPPED
+                .with_label_values(&["syncing"])
+                .inc();
+            return;
+        }
+        // The producer distributes the witness of every shard separately, so that the failure of
+        // one shard doesn't affect the others. The span tells which shard the logs are about, and
+        // together with its child spans breaks down the latency of the distribution of the chunk
+        // down to the network send.
+        let _span = tracing::debug_span!(
+            target: "client",
+            "distribute_state_witness",
+            chunk_hash = ?msg.chunk_header.chunk_hash(),
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+        )
+        .entered();
         if let Err(err) = self.handle_distribute_state_witness_request(msg) {
-            tracing::error!(target: "client", ?err, "Failed to handle distribute chunk state witness request");
+            self.report_error(PartialWitnessErrorStage::DistributeWitness, &err, &key);
+        }
+    }
+}
+
+impl Handler<ChunkStateWitnessConsumedMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: ChunkStateWitnessConsumedMessage) {
+        self.partial_witness_tracker.on_witness_consumed(&msg.key);
+    }
+}
+
+impl Handler<RequestNextDecodedWitness> for PartialWitnessActor {
+    fn handle(&mut self, msg: RequestNextDecodedWitness) -> Option<ChunkStateWitnessMessage> {
+        self.partial_witness_tracker.take_next_decoded_witness(msg.max_bytes)
+    }
+}
+
+impl Handler<ChunkStateWitnessOutcomeMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: ChunkStateWitnessOutcomeMessage) {
+        self.partial_witness_tracker.on_witness_outcome(&msg.key, msg.outcome);
+    }
+}
+
+impl Handler<ChainHeadUpdatedMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: ChainHeadUpdatedMessage) {
+        self.alternative_tip = msg.alternative_tip;
+        self.on_head_updated(&msg.head, msg.head_timestamp);
+        self.reevaluate_height_windows();
+    }
+}
+
+impl Handler<SyncStatusChangedMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: SyncStatusChangedMessage) {
+        if msg.syncing == self.syncing {
+            return;
+        }
+        self.syncing = msg.syncing;
+        if self.syncing {
+            tracing::info!(target: "client", "Pausing the witness distribution while syncing");
+            return;
+        }
+        // The head updates sent during the sync may be arbitrarily behind the synced chain, so
+        // the head is reloaded before handling the parts held back during the sync.
+        match self.load_head() {
+            Ok(Some((head, head_timestamp))) => self.on_head_updated(&head, head_timestamp),
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!(target: "client", ?err, "Failed to load the head after sync")
+            }
+        }
+        let parts =
+            self.move_prioritized_parts_first(std::mem::take(&mut self.parts_received_during_sync));
+        tracing::info!(
+            target: "client",
+            num_parts = parts.len(),
+            "Resuming the witness distribution after sync"
+        );
+        self.parts_received_during_sync = parts.into();
+        self.handle_parts_held_during_sync();
+    }
+}
+
+impl Handler<PrioritizeWitness> for PartialWitnessActor {
+    fn handle(&mut self, msg: PrioritizeWitness) {
+        tracing::debug!(target: "client", key = ?msg.0, "Prioritizing witness");
+        // A ready witness is decoded right away by `schedule_ready_witnesses_decode`.
+        self.partial_witness_tracker.prioritize_witness(msg.0);
+    }
+}
+
+impl Handler<ReduceMemoryPressure> for PartialWitnessActor {
+    fn handle(&mut self, _msg: ReduceMemoryPressure) {
+        match self.reduce_memory_pressure() {
+            Ok(freed) => {
+                metrics::PARTIAL_WITNESS_MEMORY_PRESSURE_FREED_BYTES
+                    .inc_by(freed.total_bytes() as u64);
+                tracing::info!(
+                    target: "client",
+                    total_bytes = freed.total_bytes(),
+                    ?freed,
+                    "Reduced partial witness memory usage"
+                );
+            }
+            Err(err) => {
+                tracing::warn!(
+                    target: "client",
+                    ?err,
+                    "Failed to reduce partial witness memory usage"
+                );
+            }
+        }
+    }
+}
+
+impl Handler<WarmUpPartialWitness> for PartialWitnessActor {
+    fn handle(&mut self, msg: WarmUpPartialWitness) {
+        let start = std::time::Instant::now();
+        match self.warm_up(&msg.head) {
+            Ok(num_encoders) => {
+                metrics::PARTIAL_WITNESS_WARMED_UP.set(1);
+                tracing::info!(
+                    target: "client",
+                    head_height = msg.head.height,
+                    num_encoders,
+                    elapsed = ?start.elapsed(),
+                    "Warmed up partial witness actor"
+                );
+            }
+            Err(err) => {
+                tracing::warn!(
+                    target: "client",
+                    head_height = msg.head.height,
+                    ?err,
+                    "Failed to warm up partial witness actor"
+                );
+            }
+        }
+        self.client_sender.send(PartialWitnessWarmedUp);
+        // The node is about to announce its account, so it can receive the parts again.
+        if self.config.announce_unavailability_on_shutdown.is_some() {
+            if let Err(err) = self.announce_receiver_status(None) {
+                tracing::warn!(
+                    target: "client",
+                    ?err,
+                    "Failed to clear the witness receiver status"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "test_features")]
+impl Handler<AdvWitnessPartsMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: AdvWitnessPartsMessage) {
+        tracing::info!(target: "adversary", mode = ?msg.0, "Setting adversary witness parts mode");
+        self.adv_witness_parts_mode = msg.0;
+    }
+}
+
+#[cfg(feature = "test_features")]
+impl Handler<ForceRedistributeWitness> for PartialWitnessActor {
+    fn handle(&mut self, msg: ForceRedistributeWitness) -> Result<usize, String> {
+        self.force_redistribute_witness(msg.chunk_production_key).map_err(|err| err.to_string())
+    }
+}
+
+impl Handler<AnnounceWitnessReceiverUnavailable> for PartialWitnessActor {
+    fn handle(&mut self, _msg: AnnounceWitnessReceiverUnavailable) {
+        let Some(unavailability) = self.config.announce_unavailability_on_shutdown else {
+            return;
+        };
+        let unavailable_until = self.clock.now_utc() + unavailability;
+        if let Err(err) = self.announce_receiver_status(Some(unavailable_until)) {
+            tracing::warn!(
+                target: "client",
+                ?err,
+                "Failed to announce the witness receiver unavailability"
+            );
+        }
+    }
+}
+
+impl Handler<WitnessReceiverStatusMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: WitnessReceiverStatusMessage) {
+        if let Err(err) = self.handle_witness_receiver_status(msg.0) {
+            tracing::debug!(target: "client", ?err, "Failed to handle witness receiver status");
         }
     }
 }
 
+impl Handler<WitnessDeliveryReportMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: WitnessDeliveryReportMessage) {
+        record_witness_deliveries(&msg.0);
+        self.forward_backoff.record_deliveries(&msg.0, self.clock.now());
+    }
+}
+
 impl Handler<ChunkStateWitnessAckMessage> for PartialWitnessActor {
     fn handle(&mut self, msg: ChunkStateWitnessAckMessage) {
-        self.handle_chunk_state_witness_ack(msg.0);
+        self.handle_chunk_state_witness_ack(msg.0, msg.1);
+    }
+}
+
+impl Handler<BatchedChunkStateWitnessAckMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: BatchedChunkStateWitnessAckMessage) {
+        self.handle_batched_chunk_state_witness_ack(msg.0, msg.1);
     }
 }
 
 impl Handler<PartialEncodedStateWitnessMessage> for PartialWitnessActor {
     fn handle(&mut self, msg: PartialEncodedStateWitnessMessage) {
-        if let Err(err) = self.handle_partial_encoded_state_witness(msg.0) {
-            tracing::error!(target: "client", ?err, "Failed to handle PartialEncodedStateWitnessMessage");
-        }
+        self.receive_part(HeldPart::Direct(msg));
     }
 }
 
 impl Handler<PartialEncodedStateWitnessForwardMessage> for PartialWitnessActor {
     fn handle(&mut self, msg: PartialEncodedStateWitnessForwardMessage) {
-        if let Err(err) = self.handle_partial_encoded_state_witness_forward(msg.0) {
-            tracing::error!(target: "client", ?err, "Failed to handle PartialEncodedStateWitnessForwardMessage");
+        self.receive_part(HeldPart::Forward(msg));
+    }
+}
+
+impl Handler<PartialEncodedStateWitnessFragmentMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: PartialEncodedStateWitnessFragmentMessage) {
+        let PartialEncodedStateWitnessFragmentMessage(fragment, from_peer) = msg;
+        let key = fragment.chunk_production_key();
+        handler_panic::set_handler_key(&key);
+        if let Err(err) = self.check_part_fragments_enabled(&key) {
+            self.report_error(PartialWitnessErrorStage::PartFragment, &err, &key);
+            return;
+        }
+        match self.part_fragments.insert(fragment, from_peer.clone(), self.clock.now()) {
+            Ok(None) => {}
+            // The joined part is handled as if it was received whole, signature check included.
+            Ok(Some(JoinedPart { partial_witness, forward: false })) => {
+                self.handle(PartialEncodedStateWitnessMessage(partial_witness));
+            }
+            Ok(Some(JoinedPart { partial_witness, forward: true })) => {
+                self.handle(PartialEncodedStateWitnessForwardMessage(partial_witness, from_peer));
+            }
+            Err(err) => self.report_error(PartialWitnessErrorStage::PartFragment, &err, &key),
+        }
+    }
+}
+
+impl Handler<FullEncodedStateWitnessMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: FullEncodedStateWitnessMessage) {
+        let key = msg.0.chunk_production_key();
+        handler_panic::set_handler_key(&key);
+        if let Err(err) = self.handle_full_encoded_state_witness(msg.0) {
+            self.report_error(PartialWitnessErrorStage::FullWitness, &err, &key);
+        }
+    }
+}
+
+impl Handler<PartialEncodedStateWitnessRequestMessage> for PartialWitnessActor {
+    fn handle(&mut self, msg: PartialEncodedStateWitnessRequestMessage) {
+        let key = msg.0.chunk_production_key();
+        handler_panic::set_handler_key(&key);
+        if let Err(err) = self.handle_partial_encoded_state_witness_request(msg.0, msg.1) {
+            self.report_error(PartialWitnessErrorStage::PartRequest, &err, &key);
         }
     }
 }
@@ -105,74 +885,1689 @@ impl PartialWitnessActor {
         client_sender: ClientSenderForPartialWitness,
         my_signer: MutableValidatorSigner,
         epoch_manager: Arc<dyn EpochManagerAdapter>,
+        shard_tracker: ShardTracker,
         store: Store,
+        config: PartialWitnessConfig,
     ) -> Self {
-        let partial_witness_tracker =
-            PartialEncodedStateWitnessTracker::new(client_sender, epoch_manager.clone());
+        if !config.no_forward_shards.is_empty() {
+            tracing::warn!(
+                target: "client",
+                no_forward_shards = ?config.no_forward_shards,
+                "Witness parts owned by this node won't be forwarded for some shards. The other \
+                chunk validators of these shards have to decode the witnesses without these parts, \
+                which endangers the liveness of the shards if many validators do the same."
+            );
+        }
+        let message_recorder = config.record_messages_path.as_ref().and_then(|path| {
+            match WitnessMessageRecorder::create(
+                clock.clone(),
+                path,
+                config.record_messages_max_size.as_u64(),
+            ) {
+                Ok(recorder) => {
+                    tracing::info!(target: "client", ?path, "Recording the received witness parts");
+                    Some(recorder)
+                }
+                Err(err) => {
+                    tracing::error!(
+                        target: "client",
+                        ?path,
+                        ?err,
+                        "Failed to start recording the received witness parts"
+                    );
+                    None
+                }
+            }
+        });
+        let stats_exporter = config.export_witness_stats_dir.as_ref().and_then(|dir| {
+            match WitnessStatsExporter::spawn(
+                dir,
+                config.export_witness_stats_rotation_size.as_u64(),
+                config.export_witness_stats_max_disk_usage.as_u64(),
+            ) {
+                Ok(exporter) => {
+                    tracing::info!(target: "client", ?dir, "Exporting the witness stats");
+                    Some(exporter)
+                }
+                Err(err) => {
+                    tracing::error!(
+                        target: "client",
+                        ?dir,
+                        ?err,
+                        "Failed to start exporting the witness stats"
+                    );
+                    None
+                }
+            }
+        });
+        let reed_solomon_backend = ReedSolomonBackend::select(config.reed_solomon_backend);
+        let verification_load = SignatureVerificationLoad::new(
+            clock.clone(),
+            config.signature_verification_warn_utilization,
+        );
+        let forward_backoff = ForwardBackoff::new(config.forward_backoff.clone());
+        let partial_witness_tracker = PartialEncodedStateWitnessTracker::new(
+            clock.clone(),
+            client_sender.clone(),
+            network_adapter.clone(),
+            epoch_manager.clone(),
+            store.clone(),
+            config.clone(),
+            reed_solomon_backend,
+            stats_exporter.clone(),
+        );
         Self {
+            clock: clock.clone(),
             network_adapter,
             my_signer,
             epoch_manager,
+            shard_tracker,
+            shard_tracking_check: ShardTrackingCheck::new(),
+            validation_lag: ValidationLag::new(),
+            client_sender,
             partial_witness_tracker,
-            state_witness_tracker: ChunkStateWitnessTracker::new(clock),
-            encoders: WitnessEncoderCache::new(),
+            state_witness_tracker: ChunkStateWitnessTracker::new(clock.clone()),
+            epoch_caches: EpochScopedCaches::new(reed_solomon_backend),
             store,
+            config,
+            witness_section_sizes: LruCache::new(
+                NonZeroUsize::new(WITNESS_SECTION_SIZES_CACHE_SIZE).unwrap(),
+            ),
+            owned_parts: LruCache::new(NonZeroUsize::new(OWNED_PARTS_CACHE_SIZE).unwrap()),
+            produced_parts: LruCache::new(NonZeroUsize::new(PRODUCED_PARTS_CACHE_SIZE).unwrap()),
+            forward_targets: ForwardTargetsCache::new(OWNED_PARTS_CACHE_SIZE),
+            error_reporter: PartialWitnessErrorReporter::new(clock),
+            full_witness_bytes_sent: LruCache::new(
+                NonZeroUsize::new(FULL_WITNESS_BUDGET_HEIGHTS).unwrap(),
+            ),
+            distributed_chunks: LruCache::new(
+                NonZeroUsize::new(DISTRIBUTED_CHUNKS_CACHE_SIZE).unwrap(),
+            ),
+            invalid_shard_id_parts: LruCache::new(
+                NonZeroUsize::new(INVALID_SHARD_ID_PEERS_CACHE_SIZE).unwrap(),
+            ),
+            chunk_validators_mismatches: LruCache::new(
+                NonZeroUsize::new(CHUNK_VALIDATORS_MISMATCHES_CACHE_SIZE).unwrap(),
+            ),
+            message_recorder,
+            stats_exporter,
+            verification_load,
+            witness_expiry_scheduled_at: None,
+            unavailable_receivers: UnavailableReceivers::new(),
+            ready_witnesses_decode_scheduled: false,
+            ready_witnesses_decode_continued: false,
+            held_parts_handling_scheduled: false,
+            ack_flush_scheduled_at: None,
+            syncing: false,
+            parts_received_during_sync: VecDeque::new(),
+            pending_epoch_parts: PendingEpochParts::new(),
+            epoch_info_check_scheduled: false,
+            part_send_queue: PartSendQueue::new(),
+            part_sends_scheduled: false,
+            forward_backoff,
+            paced_forwards: vec![],
+            paced_forwards_scheduled: false,
+            alternative_tip: None,
+            shutdown_signal: None,
+            last_handler_panic: None,
+            max_routed_message_payload: DEFAULT_MAX_ROUTED_MESSAGE_PAYLOAD,
+            framing_overhead: FramingOverhead::new(),
+            part_fragments: PartFragments::new(),
+            published_witness_config_version: None,
+            #[cfg(feature = "test_features")]
+            adv_witness_parts_mode: AdvWitnessPartsMode::Honest,
         }
     }
 
-    pub fn handle_distribute_state_witness_request(
-        &mut self,
-        msg: DistributeStateWitnessRequest,
-    ) -> Result<(), Error> {
-        let DistributeStateWitnessRequest { epoch_id, chunk_header, state_witness } = msg;
+    /// Sets the signal on which the node shuts down, see `HandlerPanicPolicy::Shutdown`.
+    pub fn set_shutdown_signal(&mut self, shutdown_signal: broadcast::Sender<()>) {
+        self.shutdown_signal = Some(shutdown_signal);
+    }
 
-        tracing::debug!(
-            target: "client",
-            chunk_hash=?chunk_header.chunk_hash(),
-            "distribute_chunk_state_witness",
-        );
+    /// Shares the witnesses delivered to the client with the other instances of the actor within
+    /// the process, so that an actor built again over the same handle doesn't send them again,
+    /// see `DeliveredWitnesses`.
+    pub fn set_delivered_witnesses(&mut self, delivered_witnesses: DeliveredWitnesses) {
+        self.partial_witness_tracker.set_delivered_witnesses(delivered_witnesses);
+    }
 
-        let signer = match self.my_signer.get() {
-            Some(signer) => signer,
-            None => {
-                return Err(Error::NotAValidator(format!("distribute state witness")));
-            }
-        };
+    /// Sets the max size of a part sent in one routed message, see
+    /// `NetworkConfig::max_routed_message_payload`.
+    pub fn set_max_routed_message_payload(&mut self, max_routed_message_payload: usize) {
+        self.max_routed_message_payload = max_routed_message_payload;
+        // The config sent so far, if any, shows the previous max payload.
+        self.published_witness_config_version = None;
+    }
 
-        let witness_bytes = compress_witness(&state_witness)?;
+    /// Applies `PartialWitnessConfig::handler_panic_policy` to the panic, which was already
+    /// reported by the panic hook, see `handler_panic`. Only reached in the builds which unwind on
+    /// panic. The panic is always re-raised, as the handler may have left the state of the actor
+    /// inconsistent.
+    fn on_handler_panic(&mut self, panic: HandlerPanic) -> ! {
+        let HandlerPanic { report, payload } = panic;
+        let policy = self.config.handler_panic_policy;
+        if policy == HandlerPanicPolicy::Shutdown {
+            match self.shutdown_signal.take() {
+                Some(shutdown_signal) => {
+                    tracing::error!(
+                        target: "client",
+                        message_type = report.message_type,
+                        "Shutting down the node after a panic in the partial witness actor"
+                    );
+                    // The node may be shutting down already, in which case there is nobody
+                    // listening.
+                    let _ = shutdown_signal.send(());
+                }
+                None => tracing::warn!(
+                    target: "client",
+                    "No shutdown signal to shut down the node after a panic in the partial \
+                    witness actor"
+                ),
+            }
+        }
+        self.last_handler_panic = Some(report);
+        std::panic::resume_unwind(payload)
+    }
 
-        self.send_state_witness_parts(epoch_id, chunk_header, witness_bytes, &signer)?;
+    /// Report of the last panic in a handler, if any.
+    pub fn last_handler_panic(&self) -> Option<&HandlerPanicReport> {
+        self.last_handler_panic.as_ref()
+    }
 
-        Ok(())
+    #[cfg(test)]
+    pub(crate) fn panic_on_next_part(&mut self) {
+        self.partial_witness_tracker.panic_on_next_part();
     }
 
-    // Function to generate the parts of the state witness and return them as a tuple of chunk_validator and part.
-    fn generate_state_witness_parts(
+    /// Makes every witness decode take `decode_time` on the fake clock.
+    #[cfg(test)]
+    pub(crate) fn slow_down_decodes(
         &mut self,
-        epoch_id: EpochId,
-        chunk_header: ShardChunkHeader,
-        witness_bytes: EncodedChunkStateWitness,
-        signer: &ValidatorSigner,
-    ) -> Result<Vec<(AccountId, PartialEncodedStateWitness)>, Error> {
-        let chunk_validators = self
-            .epoch_manager
-            .get_chunk_validator_assignments(
-                &epoch_id,
-                chunk_header.shard_id(),
-                chunk_header.height_created(),
-            )?
-            .ordered_chunk_validators();
+        clock: near_async::time::FakeClock,
+        decode_time: Duration,
+    ) {
+        self.partial_witness_tracker.slow_down_decodes(clock, decode_time);
+    }
 
-        tracing::debug!(
-            target: "client",
-            chunk_hash=?chunk_header.chunk_hash(),
-            ?chunk_validators,
-            "generate_state_witness_parts",
-        );
+    fn reduce_memory_pressure(&mut self) -> Result<FreedWitnessMemory, Error> {
+        let tracker_bytes = self.partial_witness_tracker.shrink_parts_cache()?;
+        // The chunk validators which didn't receive the produced parts can still get them from
+        // the owners, so these are the first to go.
+        let produced_parts_bytes = drop_produced_parts(&mut self.produced_parts);
 
-        // Break the state witness into parts using Reed Solomon encoding.
-        let encoder = self.encoders.entry(chunk_validators.len());
-        let (parts, encoded_length) = encoder.encode(&witness_bytes);
+        let Some(head) = self.store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? else {
+            return Ok(FreedWitnessMemory {
+                tracker_bytes,
+                produced_parts_bytes,
+                ..Default::default()
+            });
+        };
+        let owned_parts_bytes = drop_owned_parts_up_to(&mut self.owned_parts, head.height);
+        self.forward_targets.drop_up_to(head.height);
+
+        // Keep the encoders for the numbers of chunk validators at the next height, the rest is
+        // only needed again after the chunk validator assignments change.
+        let mut total_parts = HashSet::new();
+        for shard_id in self.epoch_manager.shard_ids(&head.epoch_id)? {
+            let assignments = self.epoch_manager.get_chunk_validator_assignments(
+                &head.epoch_id,
+                shard_id,
+                head.height + 1,
+            )?;
+            total_parts.insert(assignments.len());
+        }
+        let encoders = self.epoch_caches.retain_encoders(&total_parts)
+            + self.partial_witness_tracker.retain_encoders(&total_parts);
+
+        Ok(FreedWitnessMemory { tracker_bytes, produced_parts_bytes, owned_parts_bytes, encoders })
+    }
+
+    fn on_head_updated(&mut self, head: &Tip, head_timestamp: Utc) {
+        self.publish_witness_config(&head.epoch_id);
+        self.framing_overhead.on_head_updated(&head.epoch_id, &head.next_epoch_id);
+        if self.epoch_caches.advance(head.epoch_id, head.next_epoch_id) > 0 {
+            // The decoders follow the encoders, the ones still needed are constructed again.
+            let total_parts = self.epoch_caches.total_parts();
+            self.partial_witness_tracker.retain_encoders(&total_parts);
+        }
+        if self.shard_tracking_check.is_due(&head.epoch_id) {
+            if let Err(err) = self.check_shard_tracking(head) {
+                tracing::debug!(target: "client", ?err, "Failed to check the shard tracking");
+            }
+        }
+        self.partial_witness_tracker.on_head_updated(head.epoch_id, head.height, head_timestamp);
+        if let Err(err) = self.update_validation_lag(head) {
+            tracing::debug!(target: "client", ?err, "Failed to update the validation lag");
+        }
+        if let Err(err) = self.record_expected_witnesses(head) {
+            tracing::debug!(target: "client", ?err, "Failed to record the expected witnesses");
+        }
+        match self.store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY) {
+            Ok(Some(final_head)) => {
+                self.partial_witness_tracker.on_final_head_updated(final_head.height)
+            }
+            Ok(None) => {}
+            Err(err) => tracing::debug!(target: "client", ?err, "Failed to read the final head"),
+        }
+        self.check_epoch_info(Some(head));
+    }
+
+    /// Sends the effective config to the client unless it was already sent for the protocol
+    /// version of the epoch.
+    fn publish_witness_config(&mut self, epoch_id: &EpochId) {
+        let protocol_version = match self.epoch_manager.get_epoch_protocol_version(epoch_id) {
+            Ok(protocol_version) => protocol_version,
+            Err(err) => {
+                tracing::debug!(target: "client", ?err, "Failed to get the protocol version");
+                return;
+            }
+        };
+        if self.published_witness_config_version == Some(protocol_version) {
+            return;
+        }
+        self.published_witness_config_version = Some(protocol_version);
+        let view =
+            witness_config_view(&self.config, self.max_routed_message_payload, protocol_version);
+        self.client_sender.send(WitnessConfigMessage(view));
+    }
+
+    /// Records the chunks of the block of the head we are a chunk validator of in the epoch
+    /// witness stats. Nothing is recorded until the header of the head is in the store.
+    fn record_expected_witnesses(&mut self, head: &Tip) -> Result<(), Error> {
+        let Some(signer) = self.my_signer.get() else {
+            return Ok(());
+        };
+        let Some(header) =
+            self.store.get_ser::<BlockHeader>(DBCol::BlockHeader, head.last_block_hash.as_ref())?
+        else {
+            return Ok(());
+        };
+        let shard_ids = self.epoch_manager.shard_ids(&head.epoch_id)?;
+        for (shard_id, new_chunk) in shard_ids.into_iter().zip(header.chunk_mask()) {
+            if !*new_chunk {
+                continue;
+            }
+            let assignments = self.epoch_manager.get_chunk_validator_assignments(
+                &head.epoch_id,
+                shard_id,
+                head.height,
+            )?;
+            if assignments.contains(signer.validator_id()) {
+                self.partial_witness_tracker.record_expected_witness(
+                    &head.epoch_id,
+                    shard_id,
+                    head.height,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The head of the chain together with the timestamp of its block, None if the head or its
+    /// block header is not in the store yet.
+    fn load_head(&self) -> Result<Option<(Tip, Utc)>, Error> {
+        let Some(head) = self.store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? else {
+            return Ok(None);
+        };
+        let Some(header) =
+            self.store.get_ser::<BlockHeader>(DBCol::BlockHeader, head.last_block_hash.as_ref())?
+        else {
+            return Ok(None);
+        };
+        let head_timestamp = header.timestamp();
+        Ok(Some((head, head_timestamp)))
+    }
+
+    /// Handles the part received from the network, unless it is held back while syncing or
+    /// deferred while the epoch manager doesn't know its epoch. Returns the result of the
+    /// handling, None if the part is held back.
+    fn receive_part(&mut self, part: HeldPart) -> Option<Result<(), Error>> {
+        if self.syncing {
+            self.hold_part_during_sync(part);
+            return None;
+        }
+        if self.pending_epoch_parts.is_unknown(&part.key().epoch_id) {
+            self.pending_epoch_parts.defer(part);
+            return None;
+        }
+        let result = match part {
+            HeldPart::Direct(msg) => {
+                self.record_message(RecordedMessageKind::Owned, &msg.0);
+                let key = msg.0.chunk_production_key();
+                handler_panic::set_handler_key(&key);
+                let result = self.handle_partial_encoded_state_witness(msg.0);
+                if let Err(err) = &result {
+                    self.report_error(PartialWitnessErrorStage::OwnedPart, err, &key);
+                }
+                result
+            }
+            HeldPart::Forward(msg) => {
+                self.record_message(RecordedMessageKind::Forwarded, &msg.0);
+                let key = msg.0.chunk_production_key();
+                handler_panic::set_handler_key(&key);
+                let from_peer = msg.1;
+                let result = self
+                    .handle_partial_encoded_state_witness_forward(msg.0, Some(from_peer.clone()));
+                if let Err(err) = &result {
+                    if matches!(err, Error::InvalidShardId(_)) {
+                        *self.invalid_shard_id_parts.get_or_insert_mut(from_peer, || 0) += 1;
+                    }
+                    self.report_error(PartialWitnessErrorStage::ForwardedPart, err, &key);
+                }
+                result
+            }
+        };
+        Some(result)
+    }
+
+    /// Checks whether the epoch manager knows the epochs of the head, and validates the deferred
+    /// parts again once the unknown epochs change, see `pending_epoch_parts`.
+    fn check_epoch_info(&mut self, head: Option<&Tip>) {
+        let epoch_manager = self.epoch_manager.as_ref();
+        let parts = match head {
+            Some(head) => self.pending_epoch_parts.on_head_updated(head, epoch_manager),
+            None => self.pending_epoch_parts.check(epoch_manager),
+        };
+        for part in parts {
+            let outcome = match self.receive_part(part) {
+                Some(Ok(())) => "accepted",
+                Some(Err(_)) => "rejected",
+                // Deferred again, the epoch manager still doesn't know the epoch.
+                None => continue,
+            };
+            metrics::PARTIAL_WITNESS_DEFERRED_PARTS.with_label_values(&[outcome]).inc();
+        }
+    }
+
+    /// Keeps the part received while syncing until the sync is done, dropping the oldest part
+    /// held if there are too many of them.
+    fn hold_part_during_sync(&mut self, part: HeldPart) {
+        self.parts_received_during_sync.push_back(part);
+        while self.parts_received_during_sync.len() > self.config.max_parts_buffered_during_sync {
+            let dropped = self.parts_received_during_sync.pop_front().unwrap();
+            metrics::PARTIAL_WITNESS_PARTS_DROPPED_DURING_SYNC
+                .with_label_values(&[dropped.delivery().as_str()])
+                .inc();
+        }
+    }
+
+    /// Orders the parts held during the sync so that the parts of the prioritized witnesses are
+    /// handled first, keeping the order of arrival otherwise.
+    fn move_prioritized_parts_first(&self, parts: VecDeque<HeldPart>) -> Vec<HeldPart> {
+        let is_prioritized =
+            |part: &HeldPart| self.partial_witness_tracker.is_prioritized(&part.key());
+        if !parts.iter().any(is_prioritized) {
+            return parts.into();
+        }
+        // The order changes if a prioritized part is behind one which isn't.
+        let reordered = parts.iter().skip_while(|part| is_prioritized(*part)).any(is_prioritized);
+        metrics::PARTIAL_WITNESS_PRIORITIZATIONS
+            .with_label_values(&["sync_buffer", if reordered { "reordered" } else { "unchanged" }])
+            .inc();
+        let (mut prioritized, others): (Vec<_>, Vec<_>) =
+            parts.into_iter().partition(is_prioritized);
+        prioritized.extend(others);
+        prioritized
+    }
+
+    /// Handles the parts held during the sync in order, until the time slice runs out. The rest
+    /// is handled after the messages received meanwhile, see `schedule_held_parts_handling`.
+    fn handle_parts_held_during_sync(&mut self) {
+        let time_slice = TimeSlice::start(&self.clock, self.config.processing_time_slice);
+        while !self.syncing && !self.parts_received_during_sync.is_empty() {
+            if time_slice.should_yield("handle_parts_held_during_sync") {
+                break;
+            }
+            let part = self.parts_received_during_sync.pop_front().unwrap();
+            self.receive_part(part);
+        }
+    }
+
+    /// Number of the parts received while syncing which are held until the sync is done.
+    pub fn num_parts_held_during_sync(&self) -> usize {
+        self.parts_received_during_sync.len()
+    }
+
+    /// Number of the parts deferred until the epoch manager knows their epoch.
+    pub fn num_deferred_parts(&self) -> usize {
+        self.pending_epoch_parts.len()
+    }
+
+    /// Loads the chunk validator assignments and the chunk producers at the heights after `head`
+    /// and constructs the encoders for them, both for encoding and decoding. Returns the number
+    /// of distinct encoders needed at these heights.
+    fn warm_up(&mut self, head: &Tip) -> Result<usize, Error> {
+        // All the warmed up heights are assumed to be in the epoch of the next block, the caches
+        // for the epoch after it are warmed up as the chain gets there.
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash)?;
+        let mut total_parts = HashSet::new();
+        for shard_id in self.epoch_manager.shard_ids(&epoch_id)? {
+            for height in head.height + 1..=head.height + self.config.warm_up_heights {
+                let assignments = self
+                    .epoch_manager
+                    .get_chunk_validator_assignments(&epoch_id, shard_id, height)?;
+                // Loads the chunk producer, whose key signs the parts.
+                self.epoch_manager.get_chunk_producer(&epoch_id, height, shard_id)?;
+                total_parts.insert(assignments.len());
+            }
+        }
+        for &num_parts in &total_parts {
+            self.epoch_caches.encoder(&epoch_id, num_parts)?;
+            self.partial_witness_tracker.warm_up_encoder(num_parts)?;
+        }
+        Ok(total_parts.len())
+    }
+
+    /// Updates the lag of the chunk validator duty behind the new head, see `ValidationLag`. Only
+    /// the chunks at the head height are checked for the assignment, the heights skipped by the
+    /// head have no chunks to validate.
+    fn update_validation_lag(&mut self, head: &Tip) -> Result<(), Error> {
+        let Some(signer) = self.my_signer.get() else {
+            return Ok(());
+        };
+        let epoch_id = head.epoch_id;
+        if !self.epoch_manager.epoch_exists(&epoch_id) {
+            return Ok(());
+        }
+        let mut duties = vec![];
+        for shard_id in self.epoch_manager.shard_ids(&epoch_id)? {
+            let assigned = self
+                .epoch_manager
+                .get_chunk_validator_assignments(&epoch_id, shard_id, head.height)?
+                .contains(signer.validator_id());
+            let completed_height = self.partial_witness_tracker.completed_height(shard_id);
+            duties.push(ShardDutyAtHead { shard_id, assigned, completed_height });
+        }
+        for shard_id in self.validation_lag.on_head_updated(head.height, &duties) {
+            let _ = metrics::PARTIAL_WITNESS_VALIDATION_LAG
+                .remove_label_values(&[&int_label(shard_id)]);
+        }
+        for (shard_id, lag) in self.validation_lag.lags() {
+            metrics::PARTIAL_WITNESS_VALIDATION_LAG
+                .with_label_values(&[&int_label(shard_id)])
+                .set(lag as i64);
+        }
+        Ok(())
+    }
+
+    /// Checks that the node can validate the shards it is a chunk validator of in the epoch of
+    /// the head, i.e. that it tracks them, either according to the tracked shards configuration
+    /// or as their chunk producer. Reports the shards it can't validate, see
+    /// `ShardTrackingCheck`. The epoch is checked only once a validator signer is set.
+    fn check_shard_tracking(&mut self, head: &Tip) -> Result<(), Error> {
+        let Some(signer) = self.my_signer.get() else {
+            return Ok(());
+        };
+        let epoch_id = head.epoch_id;
+        if !self.epoch_manager.epoch_exists(&epoch_id) {
+            return Ok(());
+        }
+        let my_account_id = signer.validator_id();
+        let mut duties = vec![];
+        for shard_id in self.epoch_manager.shard_ids(&epoch_id)? {
+            let mut chunk_validator = false;
+            for height in head.height + 1..=head.height + SHARD_TRACKING_CHECK_HEIGHTS {
+                chunk_validator |= self
+                    .epoch_manager
+                    .get_chunk_validator_assignments(&epoch_id, shard_id, height)?
+                    .contains(my_account_id);
+            }
+            duties.push(ShardDuties {
+                shard_id,
+                chunk_validator,
+                tracked: self.shard_tracker.tracks_shard_at_epoch(shard_id, &epoch_id)?,
+                chunk_producer: self.epoch_manager.cares_about_shard_in_epoch(
+                    epoch_id,
+                    my_account_id,
+                    shard_id,
+                )?,
+            });
+        }
+        self.shard_tracking_check.on_checked(epoch_id);
+
+        let untracked_shards = untracked_validated_shards(&duties);
+        metrics::PARTIAL_WITNESS_UNTRACKED_VALIDATED_SHARDS.set(untracked_shards.len() as i64);
+        if !untracked_shards.is_empty() {
+            tracing::error!(
+                target: "client",
+                ?epoch_id,
+                %my_account_id,
+                ?untracked_shards,
+                "This node is a chunk validator of shards it doesn't track, the witnesses of \
+                these shards will fail to validate. Add the shards to tracked_shards, or accounts \
+                of the shards to tracked_accounts, in config.json and restart the node."
+            );
+        }
+        Ok(())
+    }
+
+    fn periodically_report_health(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        ctx.run_later(
+            "report_stateless_validation_health",
+            HEALTH_REPORT_PERIOD,
+            move |this, ctx| {
+                let health = this.stateless_validation_health();
+                this.client_sender.send(StatelessValidationHealthMessage(health));
+                this.client_sender.send(PartialWitnessDebugMessage(this.debug_view()));
+                this.periodically_report_health(ctx);
+            },
+        )
+    }
+
+    fn periodically_check_unconsumed_witnesses(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        ctx.run_later(
+            "check_unconsumed_witnesses",
+            UNCONSUMED_WITNESSES_CHECK_PERIOD,
+            move |this, ctx| {
+                this.partial_witness_tracker.check_unconsumed_witnesses();
+                this.periodically_check_unconsumed_witnesses(ctx);
+            },
+        )
+    }
+
+    fn periodically_request_missing_parts(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        ctx.run_later("request_missing_parts", MISSING_PARTS_CHECK_PERIOD, move |this, ctx| {
+            this.request_missing_parts();
+            this.periodically_request_missing_parts(ctx);
+        })
+    }
+
+    /// Requests the parts still missing past `PartialWitnessConfig::missing_part_request_delay`
+    /// from their owners, who answer as in `handle_partial_encoded_state_witness_request`.
+    fn request_missing_parts(&mut self) {
+        let Some(delay) = self.config.missing_part_request_delay else {
+            return;
+        };
+        let Some(signer) = self.my_signer.get() else {
+            return;
+        };
+        let my_account_id = signer.validator_id();
+        for (key, missing_parts) in self.partial_witness_tracker.take_missing_part_requests(delay) {
+            // The owners of the older versions don't answer the requests.
+            let requests_enabled = self
+                .epoch_manager
+                .get_epoch_protocol_version(&key.epoch_id)
+                .is_ok_and(|protocol_version| {
+                    ProtocolFeature::PartialWitnessRequests.enabled(protocol_version)
+                });
+            if !requests_enabled {
+                continue;
+            }
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                ?missing_parts,
+                "Requesting the missing witness parts from their owners"
+            );
+            for (part_ord, owner) in missing_parts {
+                if &owner == my_account_id {
+                    continue;
+                }
+                metrics::PARTIAL_WITNESS_MISSING_PART_REQUESTS_SENT
+                    .with_label_values(&[&int_label(key.shard_id)])
+                    .inc();
+                let request = PartialEncodedStateWitnessRequest {
+                    requester: my_account_id.clone(),
+                    epoch_id: key.epoch_id,
+                    shard_id: key.shard_id,
+                    height_created: key.height_created,
+                    part_ord,
+                };
+                self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::PartialEncodedStateWitnessRequest(owner, request),
+                ));
+            }
+        }
+    }
+
+    /// Schedules the expiry of the incomplete witnesses at the earliest pending deadline, unless
+    /// an expiry is already scheduled for that time or earlier. The deadlines are started in the
+    /// order in which they expire, so a single scheduled expiry covers all of them.
+    fn schedule_witness_expiry(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        let Some(deadline) = self.partial_witness_tracker.next_deadline() else {
+            return;
+        };
+        if self.witness_expiry_scheduled_at.is_some_and(|scheduled_at| scheduled_at <= deadline) {
+            return;
+        }
+        self.witness_expiry_scheduled_at = Some(deadline);
+        let delay = deadline.signed_duration_since(self.clock.now()).max(Duration::ZERO);
+        ctx.run_later("expire_incomplete_witnesses", delay, move |this, ctx| {
+            this.witness_expiry_scheduled_at = None;
+            let time_slice = TimeSlice::start(&this.clock, this.config.processing_time_slice);
+            this.partial_witness_tracker.expire_witnesses(&time_slice);
+            // If the time slice ran out, the next deadline is already due and the expiry
+            // continues after the messages received meanwhile.
+            this.schedule_witness_expiry(ctx);
+        })
+    }
+
+    /// Schedules sending the held acks once the oldest of them reaches
+    /// `PartialWitnessConfig::ack_batching_delay`, unless sending is already scheduled for that
+    /// time or earlier.
+    fn schedule_ack_flush(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        let Some(flush_at) = self.partial_witness_tracker.next_ack_flush_at() else {
+            return;
+        };
+        if self.ack_flush_scheduled_at.is_some_and(|scheduled_at| scheduled_at <= flush_at) {
+            return;
+        }
+        self.ack_flush_scheduled_at = Some(flush_at);
+        let delay = flush_at.signed_duration_since(self.clock.now()).max(Duration::ZERO);
+        ctx.run_later("flush_witness_acks", delay, move |this, ctx| {
+            this.ack_flush_scheduled_at = None;
+            this.partial_witness_tracker.flush_due_acks();
+            this.schedule_ack_flush(ctx);
+        })
+    }
+
+    /// Sends the queued parts of the witnesses produced by us once
+    /// `PartialWitnessConfig::part_send_window` passes, together with the parts of the witnesses
+    /// produced in the meantime. With a zero window the parts are sent by
+    /// `send_state_witness_parts` right away.
+    fn schedule_part_sends(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        if self.part_sends_scheduled || self.part_send_queue.is_empty() {
+            return;
+        }
+        self.part_sends_scheduled = true;
+        ctx.run_later("send_witness_parts", self.config.part_send_window, move |this, _| {
+            this.part_sends_scheduled = false;
+            this.send_queued_parts();
+        })
+    }
+
+    /// Sends the queued parts to their owners, interleaved across the shards, see
+    /// `PartSendQueue::drain`.
+    fn send_queued_parts(&mut self) {
+        for batch in self.part_send_queue.drain() {
+            self.send_owned_parts(batch.parts, batch.routing_hints);
+        }
+    }
+
+    /// Sends the parts to their owners. The parts above the max payload of a routed message are
+    /// sent as fragments, see `part_fragments`.
+    fn send_owned_parts(
+        &mut self,
+        parts: Vec<(AccountId, PartialEncodedStateWitness)>,
+        routing_hints: WitnessRoutingHints,
+    ) {
+        let mut whole_parts = vec![];
+        for (owner, partial_witness) in parts {
+            let serialized_size = borsh::object_length(&partial_witness).unwrap();
+            self.framing_overhead.record_part(&partial_witness, serialized_size);
+            match self.oversized_part_fragments(&partial_witness, serialized_size, false) {
+                Some(fragments) => {
+                    self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                        NetworkRequests::PartialEncodedStateWitnessFragments(
+                            vec![owner],
+                            fragments,
+                            routing_hints.clone(),
+                        ),
+                    ));
+                }
+                None => whole_parts.push((owner, partial_witness)),
+            }
+        }
+        if !whole_parts.is_empty() {
+            self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                NetworkRequests::PartialEncodedStateWitness(whole_parts, routing_hints),
+            ));
+        }
+    }
+
+    /// Sends the part to the targets as a forward, as fragments if the part is above the max
+    /// payload of a routed message, see `part_fragments`.
+    fn send_part_forward(
+        &mut self,
+        targets: Vec<AccountId>,
+        partial_witness: PartialEncodedStateWitness,
+        routing_hints: WitnessRoutingHints,
+    ) {
+        let serialized_size = borsh::object_length(&partial_witness).unwrap();
+        self.framing_overhead.record_part(&partial_witness, serialized_size);
+        let request = match self.oversized_part_fragments(&partial_witness, serialized_size, true) {
+            Some(fragments) => NetworkRequests::PartialEncodedStateWitnessFragments(
+                targets,
+                fragments,
+                routing_hints,
+            ),
+            None => NetworkRequests::PartialEncodedStateWitnessForward(
+                targets,
+                partial_witness,
+                routing_hints,
+            ),
+        };
+        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(request));
+    }
+
+    /// Fragments of the part if it's above the max payload of a routed message and the protocol
+    /// version of its epoch allows the fragments, see `split_oversized_part`. The part of an epoch
+    /// unknown to the epoch manager is sent whole.
+    fn oversized_part_fragments(
+        &self,
+        partial_witness: &PartialEncodedStateWitness,
+        serialized_size: usize,
+        forward: bool,
+    ) -> Option<Vec<PartialEncodedStateWitnessFragment>> {
+        let epoch_id = partial_witness.chunk_production_key().epoch_id;
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id).ok()?;
+        split_oversized_part(
+            partial_witness,
+            serialized_size,
+            forward,
+            self.max_routed_message_payload,
+            protocol_version,
+        )
+    }
+
+    /// Checks that the fragments are allowed at the protocol version of the epoch of the chunk,
+    /// as no honest node sends them before `ProtocolFeature::PartialWitnessFragments`. Checked
+    /// before the fragment takes any room in `PartFragments`.
+    fn check_part_fragments_enabled(&self, key: &ChunkProductionKey) -> Result<(), Error> {
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&key.epoch_id)?;
+        if !ProtocolFeature::PartialWitnessFragments.enabled(protocol_version) {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Received a part fragment at protocol version {} before the fragments are enabled",
+                protocol_version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends the forwards held while backing off once `ForwardBackoffConfig::pacing` passes,
+    /// together with the forwards held in the meantime.
+    fn schedule_paced_forwards(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        if self.paced_forwards_scheduled || self.paced_forwards.is_empty() {
+            return;
+        }
+        self.paced_forwards_scheduled = true;
+        ctx.run_later("send_paced_forwards", self.config.forward_backoff.pacing, move |this, _| {
+            this.paced_forwards_scheduled = false;
+            for forward in std::mem::take(&mut this.paced_forwards) {
+                this.send_part_forward(
+                    forward.targets,
+                    forward.partial_witness,
+                    forward.routing_hints,
+                );
+            }
+        })
+    }
+
+    /// Decodes the witnesses with enough parts, right away or once
+    /// `PartialWitnessConfig::decode_batch_window` passes, so that the witnesses completed within
+    /// the window are decoded together, newest first. A decode which ran out of its time slice
+    /// continues after the messages received meanwhile, together with the witnesses completed
+    /// by them.
+    fn schedule_ready_witnesses_decode(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        if !self.partial_witness_tracker.has_ready_witnesses()
+            || self.ready_witnesses_decode_continued
+        {
+            return;
+        }
+        let window = self.config.decode_batch_window;
+        if window <= Duration::ZERO {
+            self.decode_and_report_ready_witnesses(ctx);
+            return;
+        }
+        if self.partial_witness_tracker.has_prioritized_ready_witness() {
+            // The client is blocked on the witness, it doesn't wait for the window. The decode
+            // scheduled before, if any, still runs for the witnesses completed later.
+            metrics::PARTIAL_WITNESS_PRIORITIZATIONS
+                .with_label_values(&["decode_batch_window", "reordered"])
+                .inc();
+            self.decode_and_report_ready_witnesses(ctx);
+            return;
+        }
+        if self.ready_witnesses_decode_scheduled {
+            return;
+        }
+        self.ready_witnesses_decode_scheduled = true;
+        ctx.run_later("decode_ready_witnesses", window, move |this, ctx| {
+            this.ready_witnesses_decode_scheduled = false;
+            this.decode_and_report_ready_witnesses(ctx);
+        })
+    }
+
+    fn decode_and_report_ready_witnesses(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        let time_slice = TimeSlice::start(&self.clock, self.config.processing_time_slice);
+        for (key, err) in self.partial_witness_tracker.decode_ready_witnesses(&time_slice) {
+            self.report_error(PartialWitnessErrorStage::DecodeWitness, &err, &key);
+        }
+        if !self.partial_witness_tracker.has_ready_witnesses()
+            || self.ready_witnesses_decode_continued
+        {
+            return;
+        }
+        self.ready_witnesses_decode_continued = true;
+        ctx.run_later("continue_decode_ready_witnesses", Duration::ZERO, move |this, ctx| {
+            this.ready_witnesses_decode_continued = false;
+            this.decode_and_report_ready_witnesses(ctx);
+        })
+    }
+
+    /// Schedules the work left by handling a message, or by handling the parts held during the
+    /// sync, which may complete witnesses and start deadlines the same way.
+    fn schedule_follow_ups(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        self.schedule_ready_witnesses_decode(ctx);
+        self.schedule_witness_expiry(ctx);
+        self.schedule_ack_flush(ctx);
+        self.schedule_part_sends(ctx);
+        self.schedule_paced_forwards(ctx);
+        self.schedule_held_parts_handling(ctx);
+        self.schedule_epoch_info_check(ctx);
+    }
+
+    /// Checks the epoch manager again after `EPOCH_INFO_CHECK_PERIOD` while it doesn't know an
+    /// epoch of the head, so that the deferred parts don't wait for the next head update.
+    fn schedule_epoch_info_check(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        if self.epoch_info_check_scheduled || !self.pending_epoch_parts.is_stale() {
+            return;
+        }
+        self.epoch_info_check_scheduled = true;
+        ctx.run_later("check_epoch_info", EPOCH_INFO_CHECK_PERIOD, move |this, ctx| {
+            this.epoch_info_check_scheduled = false;
+            this.check_epoch_info(None);
+            this.schedule_follow_ups(ctx);
+        })
+    }
+
+    /// Continues handling the parts held during the sync after the messages received meanwhile,
+    /// if the time slice ran out, see `handle_parts_held_during_sync`.
+    fn schedule_held_parts_handling(&mut self, ctx: &mut dyn DelayedActionRunner<Self>) {
+        if self.held_parts_handling_scheduled
+            || self.syncing
+            || self.parts_received_during_sync.is_empty()
+        {
+            return;
+        }
+        self.held_parts_handling_scheduled = true;
+        ctx.run_later("handle_parts_held_during_sync", Duration::ZERO, move |this, ctx| {
+            this.held_parts_handling_scheduled = false;
+            this.handle_parts_held_during_sync();
+            this.schedule_follow_ups(ctx);
+        })
+    }
+
+    fn periodically_emit_distribution_summaries(
+        &mut self,
+        ctx: &mut dyn DelayedActionRunner<Self>,
+    ) {
+        ctx.run_later(
+            "emit_witness_distribution_summaries",
+            DISTRIBUTION_SUMMARIES_CHECK_PERIOD,
+            move |this, ctx| {
+                this.state_witness_tracker.emit_overdue_distribution_summaries();
+                this.state_witness_tracker
+                    .check_ack_threshold_deadlines(this.config.ack_threshold_deadline);
+                this.periodically_emit_distribution_summaries(ctx);
+            },
+        )
+    }
+
+    /// Records the received part if `PartialWitnessConfig::record_messages_path` is set.
+    /// The recording is abandoned on the first error.
+    fn record_message(
+        &mut self,
+        kind: RecordedMessageKind,
+        partial_witness: &PartialEncodedStateWitness,
+    ) {
+        let Some(recorder) = &mut self.message_recorder else {
+            return;
+        };
+        let Some(signer) = self.my_signer.get() else {
+            return;
+        };
+        if let Err(err) = recorder.record(
+            kind,
+            partial_witness,
+            signer.validator_id(),
+            self.epoch_manager.as_ref(),
+            &self.store,
+        ) {
+            tracing::error!(
+                target: "client",
+                ?err,
+                "Failed to record the received witness part, stopping the recording"
+            );
+            self.message_recorder = None;
+        }
+    }
+
+    /// Exports the sizes of the witness we produced if
+    /// `PartialWitnessConfig::export_witness_stats_dir` is set.
+    fn export_produced_witness_stats(
+        &self,
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+        section_sizes: &ChunkStateWitnessSectionSizes,
+        compressed_size: usize,
+    ) {
+        let Some(exporter) = &self.stats_exporter else {
+            return;
+        };
+        let num_validators = match self.epoch_manager.get_chunk_validator_assignments(
+            &epoch_id,
+            chunk_header.shard_id(),
+            chunk_header.height_created(),
+        ) {
+            Ok(assignments) => assignments.ordered_chunk_validators().len(),
+            Err(err) => {
+                tracing::debug!(target: "client", ?err, "Failed to export the witness stats");
+                return;
+            }
+        };
+        exporter.export(WitnessStatsRecord {
+            source: WitnessStatsSource::Produced,
+            epoch_id,
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+            raw_size: section_sizes.total(),
+            compressed_size,
+            section_sizes: Some(section_sizes.clone()),
+            num_validators,
+            decode_latency_millis: None,
+        });
+    }
+
+    /// Decodes the witnesses with enough parts and sends them to the client, newest first. This
+    /// is done after handling every message, only the callers of the `handle_*` methods need it.
+    /// Returns the errors of the witnesses which failed to decode.
+    pub fn decode_ready_witnesses(&mut self) -> Vec<(ChunkProductionKey, Error)> {
+        let time_slice = TimeSlice::start(&self.clock, Duration::ZERO);
+        self.partial_witness_tracker.decode_ready_witnesses(&time_slice)
+    }
+
+    /// Returns the distribution summaries of the most recently produced witnesses,
+    /// starting from the most recent one.
+    pub fn recent_witness_distribution_summaries(
+        &self,
+    ) -> impl Iterator<Item = &WitnessDistributionSummary> {
+        self.state_witness_tracker.recent_distribution_summaries()
+    }
+
+    /// Returns how the witness was decoded first, in full or from the parts, None if it wasn't
+    /// decoded or was forgotten.
+    pub fn witness_decode_path(&self, key: &ChunkProductionKey) -> Option<WitnessDecodePath> {
+        self.partial_witness_tracker.decode_path(key)
+    }
+
+    /// Returns the most recently reconstructed witnesses joined with the outcome reported by the
+    /// client, starting from the most recent one.
+    pub fn recent_witness_outcomes(&self) -> impl Iterator<Item = &WitnessOutcomeRecord> {
+        self.partial_witness_tracker.recent_witness_outcomes()
+    }
+
+    /// Returns the witness distribution health of the chunk producers over the last epoch,
+    /// starting from the worst decode ratio.
+    pub fn producer_distribution_health(&self) -> Vec<ProducerDistributionHealth> {
+        self.partial_witness_tracker.producer_distribution_health()
+    }
+
+    /// Returns whether the node is currently able to perform its chunk validator duties, computed
+    /// from the state the actor keeps, see `StatelessValidationHealth`.
+    pub fn stateless_validation_health(&self) -> StatelessValidationHealth {
+        let head = self.load_head().unwrap_or_else(|err| {
+            tracing::debug!(target: "client", ?err, "Failed to load the head for the health check");
+            None
+        });
+        let head_age = head.as_ref().map(|(_, head_timestamp)| {
+            (self.clock.now_utc() - *head_timestamp).max(Duration::ZERO)
+        });
+        let assignments_error = match &head {
+            Some((head, _)) => self.resolve_assignments(head).err().map(|err| err.to_string()),
+            None => Some("head unknown".to_owned()),
+        };
+        let inputs = HealthInputs {
+            signer_present: self.my_signer.get().is_some(),
+            head_age,
+            parts_cache_size: self.partial_witness_tracker.total_parts_cache_size(),
+            recent_decode_failures: self
+                .error_reporter
+                .recent_errors(PartialWitnessErrorStage::DecodeWitness),
+            assignments_error,
+        };
+        StatelessValidationHealth::evaluate(&self.config.health, &inputs)
+    }
+
+    /// Looks up the chunk validator assignments of all the shards at the head.
+    fn resolve_assignments(&self, head: &Tip) -> Result<(), Error> {
+        for shard_id in self.epoch_manager.shard_ids(&head.epoch_id)? {
+            self.epoch_manager.get_chunk_validator_assignments(
+                &head.epoch_id,
+                shard_id,
+                head.height,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns how far the chunk validator duty lags behind the head per shard, see
+    /// `ValidationLag`.
+    pub fn validation_lag(&self) -> Vec<(ShardId, BlockHeightDelta)> {
+        self.validation_lag.lags()
+    }
+
+    /// Returns the loss estimates of the forwarded parts per peer link, starting from the
+    /// highest loss rate, see `LinkLossEstimator`.
+    pub fn link_loss_estimates(&self) -> Vec<LinkLossEstimate> {
+        self.partial_witness_tracker.link_loss_estimates()
+    }
+
+    /// Returns the total size of the produced and owned parts kept by the actor. A part kept in
+    /// both caches, or in flight to the network, shares one buffer and is counted once.
+    pub fn retained_part_bytes(&self) -> usize {
+        let produced = self.produced_parts.iter().flat_map(|(_, parts)| parts);
+        let owned = self
+            .owned_parts
+            .iter()
+            .flat_map(|(_, parts)| parts)
+            .map(|owned_part| &owned_part.partial_witness);
+        let mut seen = HashSet::new();
+        produced
+            .chain(owned)
+            .filter(|partial_witness| seen.insert(partial_witness.part().as_ptr()))
+            .map(|partial_witness| partial_witness.part_size())
+            .sum()
+    }
+
+    /// Returns the number of the forwarded parts of nonexistent shards delivered by the peer.
+    pub fn invalid_shard_id_parts_from(&self, peer_id: &PeerId) -> u64 {
+        self.invalid_shard_id_parts.peek(peer_id).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of the parts whose fragments are being received.
+    pub fn pending_part_fragments(&self) -> usize {
+        self.part_fragments.len()
+    }
+
+    /// Returns our view and the chunk producer's view of the chunk validators of the witness, if
+    /// they differ.
+    pub fn chunk_validators_mismatch(
+        &self,
+        key: &ChunkProductionKey,
+    ) -> Option<&(ChunkValidatorsDigest, ChunkValidatorsDigest)> {
+        self.chunk_validators_mismatches.peek(key)
+    }
+
+    /// Returns the evidence of the chunk producer signing conflicting parts of the witness, if any.
+    pub fn conflict_evidence(&self, key: &ChunkProductionKey) -> Option<&WitnessConflictEvidence> {
+        self.partial_witness_tracker.conflict_evidence(key)
+    }
+
+    /// Returns the conflict between the full witness and the witness reconstructed from the
+    /// parts, if any.
+    pub fn decode_conflict(&self, key: &ChunkProductionKey) -> Option<&WitnessDecodeConflict> {
+        self.partial_witness_tracker.decode_conflict(key)
+    }
+
+    /// Returns the part of the witness identified as corrupted during its reconstruction, if any.
+    pub fn corrupted_part(&self, key: &ChunkProductionKey) -> Option<&CorruptedWitnessPart> {
+        self.partial_witness_tracker.corrupted_part(key)
+    }
+
+    /// Returns the evidence of the chunk producer signing the parts of a witness of another chunk,
+    /// if the witness decoded into one.
+    pub fn key_mismatch(&self, key: &ChunkProductionKey) -> Option<&WitnessKeyMismatchEvidence> {
+        self.partial_witness_tracker.key_mismatch(key)
+    }
+
+    /// Returns the tip whose height window admitted the parts of the incomplete witness, None if
+    /// the witness has no parts waiting to be decoded.
+    pub fn height_window(&self, key: &ChunkProductionKey) -> Option<HeightWindowContext> {
+        self.partial_witness_tracker.height_window(key)
+    }
+
+    /// Returns the shards whose forwards currently back off because the sends to their targets
+    /// fail, see `PartialWitnessConfig::forward_backoff`.
+    pub fn forward_backoff_engaged_shards(&self) -> Vec<ShardId> {
+        self.forward_backoff.engaged_shards()
+    }
+
+    /// Returns the message in which our own part of the witness arrived first, if any.
+    pub fn owned_part_delivery(&self, key: &ChunkProductionKey) -> Option<PartDelivery> {
+        self.partial_witness_tracker.owned_part_delivery(key)
+    }
+
+    /// Recent activity of the witness distribution, reported to the client for its debug
+    /// endpoint every `HEALTH_REPORT_PERIOD`.
+    pub fn debug_view(&self) -> PartialWitnessDebugView {
+        let produced_witness_section_sizes = self
+            .witness_section_sizes
+            .iter()
+            .map(|(key, section_sizes)| ProducedWitnessSectionSizesView {
+                epoch_id: key.epoch_id.0,
+                shard_id: key.shard_id,
+                height_created: key.height_created,
+                section_sizes: section_sizes.clone(),
+            })
+            .collect();
+        let validation_lag = self
+            .validation_lag
+            .lags()
+            .into_iter()
+            .map(|(shard_id, lag)| ShardValidationLagView { shard_id, lag })
+            .collect();
+        PartialWitnessDebugView { produced_witness_section_sizes, validation_lag }
+    }
+
+    /// Takes a snapshot of the witnesses in flight: the parts of the incomplete witnesses and
+    /// the parts of the witnesses produced by us. See `PartialWitnessState` for what is left out.
+    pub fn snapshot(&self) -> PartialWitnessState {
+        PartialWitnessState::V1(PartialWitnessStateV1 {
+            incomplete_witnesses: self.partial_witness_tracker.snapshot_incomplete_witnesses(),
+            produced_parts: self
+                .produced_parts
+                .iter()
+                .rev()
+                .map(|(_, parts)| parts.clone())
+                .collect(),
+        })
+    }
+
+    /// Restores the witnesses in flight from the snapshot taken by `snapshot`, possibly by another
+    /// binary. The incomplete witnesses which can't be restored, e.g. because their epoch is not
+    /// known, are dropped.
+    pub fn restore(&mut self, state: PartialWitnessState) {
+        let PartialWitnessState::V1(state) = state;
+        let num_incomplete_witnesses = state.incomplete_witnesses.len();
+        let mut num_restored = 0;
+        for snapshot in state.incomplete_witnesses {
+            let key = snapshot.chunk_production_key();
+            match self.partial_witness_tracker.restore_incomplete_witness(snapshot) {
+                Ok(()) => num_restored += 1,
+                Err(err) => tracing::warn!(
+                    target: "client",
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    ?err,
+                    "Failed to restore the incomplete witness"
+                ),
+            }
+        }
+        for parts in state.produced_parts {
+            if let Some(partial_witness) = parts.first() {
+                self.produced_parts.put(partial_witness.chunk_production_key(), parts);
+            }
+        }
+        tracing::info!(
+            target: "client",
+            num_restored,
+            num_incomplete_witnesses,
+            num_produced = self.produced_parts.len(),
+            "Restored partial witness state"
+        );
+    }
+
+    pub fn handle_distribute_state_witness_request(
+        &mut self,
+        msg: DistributeStateWitnessRequest,
+    ) -> Result<(), Error> {
+        let DistributeStateWitnessRequest {
+            epoch_id,
+            chunk_header,
+            state_witness,
+            in_flight_bytes,
+            chunk_produced_at,
+            force,
+        } = msg;
+        // The request has left the mailbox, from now on the witness memory is accounted by the
+        // regular processing.
+        drop(in_flight_bytes);
+
+        let request_delay = self.clock.now().signed_duration_since(chunk_produced_at);
+        metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUEST_DELAY
+            .with_label_values(&[&int_label(chunk_header.shard_id())])
+            .observe(request_delay.as_seconds_f64());
+        if request_delay > DISTRIBUTION_REQUEST_DELAY_WARN_THRESHOLD {
+            tracing::warn!(
+                target: "client",
+                chunk_hash=?chunk_header.chunk_hash(),
+                request_delay_ms=request_delay.whole_milliseconds(),
+                "Slow state witness distribution request, the time is spent in the client \
+                between producing the chunk and creating the witness",
+            );
+        }
+
+        // The witness of a chunk below the final head can't be used to endorse anything anymore,
+        // so the requests which waited in the mailbox for that long are not worth processing.
+        let final_head = self.store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?;
+        if final_head.is_some_and(|final_head| chunk_header.height_created() < final_head.height) {
+            tracing::debug!(
+                target: "client",
+                chunk_hash=?chunk_header.chunk_hash(),
+                height_created=chunk_header.height_created(),
+                "Dropping stale state witness distribution request",
+            );
+            metrics::PARTIAL_WITNESS_STALE_DISTRIBUTION_REQUESTS
+                .with_label_values(&[&int_label(chunk_header.shard_id())])
+                .inc();
+            return Ok(());
+        }
+
+        // A retry of the client or a race in the chunk production can request the distribution of
+        // the same witness twice, sending it again would only double the bandwidth.
+        let chunk_hash = chunk_header.chunk_hash();
+        if !force && self.is_recently_distributed(&chunk_hash) {
+            tracing::debug!(
+                target: "client",
+                ?chunk_hash,
+                "Dropping duplicate state witness distribution request",
+            );
+            metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_DROPPED
+                .with_label_values(&["duplicate"])
+                .inc();
+            return Ok(());
+        }
+
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?chunk_header.chunk_hash(),
+            "distribute_chunk_state_witness",
+        );
+
+        // The encoding takes a while, so the signer may change before the parts are sent,
+        // see `send_state_witness_parts`.
+        let signer_snapshot = match SignerSnapshot::take(&self.my_signer) {
+            Some(signer_snapshot) => signer_snapshot,
+            None => {
+                return Err(Error::NotAValidator(format!("distribute state witness")));
+            }
+        };
+        let signer = signer_snapshot.signer();
+        let epoch_id = self.resolve_distribution_epoch_id(epoch_id, &chunk_header);
+
+        // The client only requests the distribution for the chunks it produced, so this indicates
+        // a bug upstream, e.g. around the stake changes at the epoch boundaries.
+        let chunk_producer = self.epoch_manager.get_chunk_producer(
+            &epoch_id,
+            chunk_header.height_created(),
+            chunk_header.shard_id(),
+        )?;
+        if &chunk_producer != signer.validator_id() {
+            metrics::PARTIAL_WITNESS_NOT_THIS_CHUNKS_PRODUCER
+                .with_label_values(&[&int_label(chunk_header.shard_id())])
+                .inc();
+            return Err(Error::NotThisChunksProducer {
+                expected: chunk_producer,
+                actual: signer.validator_id().clone(),
+            });
+        }
+
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+        // Zero compresses every witness, as expected by the nodes of the older versions.
+        let compression_threshold =
+            if ProtocolFeature::UncompressedSmallWitness.enabled(protocol_version) {
+                self.config.uncompressed_witness_threshold
+            } else {
+                ByteSize::b(0)
+            };
+        let (witness_bytes, section_sizes, witness_hash) =
+            compress_witness(&state_witness, compression_threshold)?;
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?chunk_header.chunk_hash(),
+            ?section_sizes,
+            compressed = witness_bytes.is_compressed(),
+            "witness_section_sizes",
+        );
+        let raw_witness_size = section_sizes.total();
+        self.export_produced_witness_stats(
+            epoch_id,
+            &chunk_header,
+            &section_sizes,
+            witness_bytes.size_bytes(),
+        );
+        self.witness_section_sizes.put(state_witness.chunk_production_key(), section_sizes);
+
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &epoch_id,
+            chunk_header.shard_id(),
+            chunk_header.height_created(),
+        )?;
+        if is_sole_chunk_validator(&chunk_validator_assignments, signer.validator_id()) {
+            self.deliver_witness_locally(
+                epoch_id,
+                &chunk_header,
+                &chunk_validator_assignments,
+                signer.validator_id(),
+                state_witness,
+                witness_hash,
+                raw_witness_size,
+                witness_bytes.size_bytes(),
+                request_delay,
+            )?;
+            self.distributed_chunks.put(chunk_hash, self.clock.now());
+            return Ok(());
+        }
+        let witness_hash =
+            ProtocolFeature::WitnessChecksum.enabled(protocol_version).then_some(witness_hash);
+
+        if let Err(err) = self.send_full_witness_to_top_stake_validators(
+            epoch_id,
+            &chunk_header,
+            &witness_bytes,
+            signer,
+        ) {
+            tracing::warn!(
+                target: "client",
+                shard_id = chunk_header.shard_id(),
+                ?err,
+                "Failed to send full state witness"
+            );
+        }
+        self.send_state_witness_parts(
+            epoch_id,
+            chunk_header,
+            witness_bytes,
+            witness_hash,
+            raw_witness_size,
+            request_delay,
+            &signer_snapshot,
+        )?;
+        self.distributed_chunks.put(chunk_hash, self.clock.now());
+
+        Ok(())
+    }
+
+    /// Delivers the witness straight to our client, when we are the only chunk validator of the
+    /// chunk, e.g. on a single node localnet. There is nobody to send the parts to, so the witness
+    /// is not encoded into parts and nothing is sent over the network. It's still serialized by
+    /// the caller, so that its sizes are recorded as for any other witness.
+    fn deliver_witness_locally(
+        &mut self,
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+        chunk_validator_assignments: &ChunkValidatorAssignments,
+        me: &AccountId,
+        state_witness: Arc<ChunkStateWitness>,
+        witness_hash: CryptoHash,
+        raw_witness_size: usize,
+        encoded_witness_size: usize,
+        request_delay: Duration,
+    ) -> Result<(), Error> {
+        let shard_id = chunk_header.shard_id();
+        let height_created = chunk_header.height_created();
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?chunk_header.chunk_hash(),
+            "Sole chunk validator of the chunk, delivering the witness locally",
+        );
+        let shard_id_label = int_label(shard_id);
+        metrics::PARTIAL_WITNESS_ENCODE_TIME.with_label_values(&[&shard_id_label]).observe(0.0);
+        metrics::PARTIAL_WITNESS_LOCAL_DELIVERIES.with_label_values(&[&shard_id_label]).inc();
+        self.state_witness_tracker.record_witness_sent(
+            chunk_header.chunk_hash(),
+            WitnessDistributionSummary::new(
+                epoch_id,
+                height_created,
+                shard_id,
+                raw_witness_size,
+                encoded_witness_size,
+                1,
+                0,
+                request_delay,
+                Duration::ZERO,
+            ),
+            chunk_validator_assignments,
+            me,
+        );
+        self.partial_witness_tracker.record_witness_distributed(&ChunkProductionKey {
+            epoch_id,
+            shard_id,
+            height_created,
+        });
+        self.partial_witness_tracker.deliver_local_witness(
+            Arc::unwrap_or_clone(state_witness),
+            witness_hash,
+            raw_witness_size,
+        )
+    }
+
+    /// Sends the retained parts of a witness produced by us again, bypassing the duplicate check
+    /// like a forced distribution request, see `DistributeStateWitnessRequest::force`. The parts
+    /// are reused as they were signed: every part is sent to its owner, and our own parts are
+    /// forwarded to all the other chunk validators, without capping or pacing the forwards.
+    #[cfg(feature = "test_features")]
+    fn force_redistribute_witness(&mut self, key: ChunkProductionKey) -> Result<usize, Error> {
+        let Some(parts) = self.produced_parts.get(&key).cloned() else {
+            return Err(Error::Other(format!(
+                "cache miss: the parts of the witness {:?} are not retained, either it wasn't \
+                produced by this node or it was evicted from the produced parts",
+                key
+            )));
+        };
+        let Some(signer) = self.my_signer.get() else {
+            return Err(Error::NotAValidator(format!("force redistribute witness")));
+        };
+        tracing::info!(
+            target: "adversary",
+            ?key,
+            num_parts = parts.len(),
+            "Forcing the redistribution of the witness"
+        );
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &key.epoch_id,
+            key.shard_id,
+            key.height_created,
+        )?;
+        let routing_hints = self.routing_hints(&chunk_validator_assignments, signer.validator_id());
+        let num_parts = parts.len();
+        // The retained parts were produced by this node, so every one has an owner.
+        let mut owner_parts = parts
+            .into_iter()
+            .filter_map(|partial_witness| {
+                let owner = witness_parts_geometry::part_owner(
+                    &chunk_validator_assignments,
+                    &partial_witness,
+                )?;
+                Some((owner, partial_witness))
+            })
+            .collect_vec();
+        let own_parts = take_own_parts(&mut owner_parts, signer.validator_id());
+        for partial_witness in own_parts {
+            let targets = forward_targets(
+                &chunk_validator_assignments,
+                signer.validator_id(),
+                signer.validator_id(),
+            );
+            self.send_part_forward(targets, partial_witness, routing_hints.clone());
+        }
+        if !owner_parts.is_empty() {
+            self.send_owned_parts(owner_parts, routing_hints);
+        }
+        Ok(num_parts)
+    }
+
+    fn is_recently_distributed(&self, chunk_hash: &ChunkHash) -> bool {
+        self.distributed_chunks.peek(chunk_hash).is_some_and(|distributed_at| {
+            self.clock.now() < *distributed_at + DUPLICATE_DISTRIBUTION_REQUEST_TTL
+        })
+    }
+
+    /// Resolves the epoch of the chunk from its prev block. The epoch of the request is computed by
+    /// the client, and if it's wrong, e.g. the epoch of the prev block at an epoch boundary, the
+    /// parts are correctly signed but sent to the wrong chunk validators, so nobody endorses the
+    /// chunk. The resolved epoch wins, the request's one is only used if the resolution fails.
+    fn resolve_distribution_epoch_id(
+        &self,
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+    ) -> EpochId {
+        let resolved_epoch_id = match self
+            .epoch_manager
+            .get_epoch_id_from_prev_block(chunk_header.prev_block_hash())
+        {
+            Ok(resolved_epoch_id) => resolved_epoch_id,
+            Err(err) => {
+                tracing::warn!(
+                    target: "client",
+                    chunk_hash=?chunk_header.chunk_hash(),
+                    ?epoch_id,
+                    ?err,
+                    "Failed to resolve the epoch of the witness, using the epoch of the request",
+                );
+                return epoch_id;
+            }
+        };
+        if resolved_epoch_id != epoch_id {
+            tracing::warn!(
+                target: "client",
+                chunk_hash=?chunk_header.chunk_hash(),
+                height_created=chunk_header.height_created(),
+                request_epoch_id=?epoch_id,
+                ?resolved_epoch_id,
+                "Epoch of the state witness distribution request doesn't match the epoch of the \
+                chunk, using the resolved epoch",
+            );
+            metrics::PARTIAL_WITNESS_DISTRIBUTION_EPOCH_MISMATCHES
+                .with_label_values(&[&int_label(chunk_header.shard_id())])
+                .inc();
+        }
+        resolved_epoch_id
+    }
+
+    /// Sends the full encoded witness to `direct_full_witness_targets` chunk validators with the
+    /// highest stake, as long as the bytes sent for the height stay within
+    /// `direct_full_witness_budget_per_height`. These validators also receive their part as usual.
+    fn send_full_witness_to_top_stake_validators(
+        &mut self,
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+        witness_bytes: &EncodedChunkStateWitness,
+        signer: &ValidatorSigner,
+    ) -> Result<(), Error> {
+        let num_targets = self.config.direct_full_witness_targets;
+        if num_targets == 0 {
+            return Ok(());
+        }
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+        if !ProtocolFeature::DirectFullWitness.enabled(protocol_version) {
+            return Ok(());
+        }
+        let height = chunk_header.height_created();
+        let witness_size = witness_bytes.size_bytes().max(1);
+        let budget = self.config.direct_full_witness_budget_per_height.as_u64() as usize;
+        let bytes_sent = self.full_witness_bytes_sent.get(&height).copied().unwrap_or(0);
+        let num_targets = num_targets.min(budget.saturating_sub(bytes_sent) / witness_size);
+        if num_targets == 0 {
+            tracing::debug!(target: "client", height, bytes_sent, "Full witness budget exhausted");
+            return Ok(());
+        }
+
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &epoch_id,
+            chunk_header.shard_id(),
+            height,
+        )?;
+        let targets =
+            top_stake_validators(&chunk_validator_assignments, signer.validator_id(), num_targets);
+        if targets.is_empty() {
+            return Ok(());
+        }
+
+        let total_bytes = targets.len() * witness_size;
+        self.full_witness_bytes_sent.put(height, bytes_sent + total_bytes);
+        metrics::PARTIAL_WITNESS_FULL_WITNESS_SENT_BYTES
+            .with_label_values(&[&int_label(chunk_header.shard_id())])
+            .inc_by(total_bytes as u64);
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?chunk_header.chunk_hash(),
+            ?targets,
+            "send_full_witness_to_top_stake_validators",
+        );
+
+        let routing_hints = self.routing_hints(&chunk_validator_assignments, signer.validator_id());
+        let full_witness =
+            FullEncodedStateWitness::new(epoch_id, chunk_header, witness_bytes.clone(), signer);
+        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::FullEncodedStateWitness(targets, full_witness, routing_hints),
+        ));
+        Ok(())
+    }
+
+    // Function to generate the parts of the state witness and return them as a tuple of chunk_validator and part.
+    fn generate_state_witness_parts(
+        &mut self,
+        epoch_id: EpochId,
+        chunk_header: ShardChunkHeader,
+        witness_bytes: EncodedChunkStateWitness,
+        witness_hash: Option<CryptoHash>,
+        signer: &ValidatorSigner,
+    ) -> Result<Vec<(AccountId, PartialEncodedStateWitness)>, Error> {
+        let key = ChunkProductionKey {
+            epoch_id,
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+        };
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &key.epoch_id,
+            key.shard_id,
+            key.height_created,
+        )?;
+        // The parts are always created in the format of the epoch, the chunk validators accept
+        // the previous formats only for the parts still in flight around an upgrade.
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+        let format = PartFormat::for_protocol_version(protocol_version);
+        let chunk_validators = witness_parts_geometry::non_empty_part_owners(
+            &chunk_validator_assignments,
+            format,
+            &key,
+        )?;
+
+        tracing::debug!(
+            target: "client",
+            chunk_hash=?chunk_header.chunk_hash(),
+            ?chunk_validators,
+            "generate_state_witness_parts",
+        );
+
+        // Break the state witness into parts using Reed Solomon encoding.
+        let encoder = self.epoch_caches.encoder(&key.epoch_id, chunk_validators.len())?;
+        let (parts, encoded_length) = {
+            let _span = tracing::debug_span!(
+                target: "client",
+                "encode_witness_parts",
+                witness_size = witness_bytes.size_bytes(),
+                num_parts = chunk_validators.len(),
+            )
+            .entered();
+            encoder.encode(&witness_bytes)
+        };
+        let _span = tracing::debug_span!(
+            target: "client",
+            "construct_witness_parts",
+            num_parts = parts.len(),
+            encoded_length,
+            part_size = parts.first().and_then(|part| part.as_ref()).map_or(0, |part| part.len()),
+        )
+        .entered();
+        // Taken after the encoding, so that the receivers don't account the encoding time to the
+        // network, see `PartialEncodedStateWitness::sent_at`.
+        let sent_at = (format >= PartFormat::V2).then(|| self.clock.now_utc());
+        let part_protocol_version = (format >= PartFormat::V4).then_some(protocol_version);
+        if let Some(sent_at) = sent_at.filter(|_| format >= PartFormat::V3) {
+            // It's fine to unwrap part here as we just constructed the parts above and we expect
+            // all of them to be present.
+            let parts = chunk_validators
+                .iter()
+                .cloned()
+                .zip_eq(parts.into_iter().map(|part| Arc::from(part.unwrap())))
+                .collect_vec();
+            let partial_witnesses = PartialEncodedStateWitness::new_committed_parts(
+                epoch_id,
+                &chunk_header,
+                parts,
+                encoded_length,
+                witness_hash,
+                ChunkValidatorsDigest::new(&chunk_validator_assignments.ordered_chunk_validators()),
+                sent_at,
+                part_protocol_version,
+                signer,
+            );
+            return Ok(chunk_validators.into_iter().zip_eq(partial_witnesses).collect_vec());
+        }
 
         Ok(chunk_validators
             .iter()
@@ -181,14 +2576,27 @@ impl PartialWitnessActor {
             .map(|(part_ord, (chunk_validator, part))| {
                 // It's fine to unwrap part here as we just constructed the parts above and we expect
                 // all of them to be present.
-                let partial_witness = PartialEncodedStateWitness::new(
-                    epoch_id,
-                    chunk_header.clone(),
-                    part_ord,
-                    part.unwrap().to_vec(),
-                    encoded_length,
-                    signer,
-                );
+                let partial_witness = match sent_at {
+                    Some(sent_at) => PartialEncodedStateWitness::new_v2(
+                        epoch_id,
+                        chunk_header.clone(),
+                        part_ord,
+                        chunk_validator.clone(),
+                        part.unwrap(),
+                        encoded_length,
+                        witness_hash,
+                        sent_at,
+                        signer,
+                    ),
+                    None => PartialEncodedStateWitness::new(
+                        epoch_id,
+                        chunk_header.clone(),
+                        part_ord,
+                        part.unwrap(),
+                        encoded_length,
+                        signer,
+                    ),
+                };
                 (chunk_validator.clone(), partial_witness)
             })
             .collect_vec())
@@ -202,74 +2610,272 @@ impl PartialWitnessActor {
         epoch_id: EpochId,
         chunk_header: ShardChunkHeader,
         witness_bytes: EncodedChunkStateWitness,
-        signer: &ValidatorSigner,
+        witness_hash: Option<CryptoHash>,
+        raw_witness_size: usize,
+        request_delay: Duration,
+        signer_snapshot: &SignerSnapshot,
     ) -> Result<(), Error> {
+        let signer = signer_snapshot.signer();
         // Capture these values first, as the sources are consumed before calling record_witness_sent.
         let chunk_hash = chunk_header.chunk_hash();
+        let height_created = chunk_header.height_created();
+        let shard_id = chunk_header.shard_id();
         let witness_size_in_bytes = witness_bytes.size_bytes();
 
         // Record time taken to encode the state witness parts.
-        let shard_id_label = chunk_header.shard_id().to_string();
+        let shard_id_label = int_label(chunk_header.shard_id());
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &epoch_id,
+            shard_id,
+            height_created,
+        )?;
+        let routing_hints = self.routing_hints(&chunk_validator_assignments, signer.validator_id());
         let encode_timer = metrics::PARTIAL_WITNESS_ENCODE_TIME
-            .with_label_values(&[shard_id_label.as_str()])
+            .with_label_values(&[&shard_id_label])
             .start_timer();
-        let mut validator_witness_tuple =
-            self.generate_state_witness_parts(epoch_id, chunk_header, witness_bytes, signer)?;
-        encode_timer.observe_duration();
+        #[cfg(feature = "test_features")]
+        let adv_chunk_header = chunk_header.clone();
+        let mut validator_witness_tuple = self.generate_state_witness_parts(
+            epoch_id,
+            chunk_header,
+            witness_bytes,
+            witness_hash,
+            signer,
+        )?;
+        let encode_time = Duration::seconds_f64(encode_timer.stop_and_record());
+        let num_parts = validator_witness_tuple.len();
+
+        // The parts are signed with the signer captured before the encoding. If the signer was
+        // swapped in the meantime, e.g. on a key rotation, the parts must not be sent.
+        if !signer_snapshot.is_current(&self.my_signer) {
+            tracing::warn!(
+                target: "client",
+                ?chunk_hash,
+                "Validator signer changed while encoding the witness, dropping the parts"
+            );
+            metrics::PARTIAL_WITNESS_STALE_SIGNER_DROPS.with_label_values(&[&shard_id_label]).inc();
+            return Ok(());
+        }
 
+        #[cfg(feature = "test_features")]
+        let conflicting_parts = adversarial::alter_witness_parts(
+            &self.adv_witness_parts_mode,
+            epoch_id,
+            &adv_chunk_header,
+            &mut validator_witness_tuple,
+            signer,
+        );
+
+        // The parts are all about the same size, so the first one tells whether they fit in one
+        // routed message. The parts which don't are sent as fragments, see `part_fragments`.
+        if let Some((_, partial_witness)) = validator_witness_tuple.first() {
+            let part_size = borsh::object_length(partial_witness).unwrap();
+            if part_size > self.max_routed_message_payload {
+                tracing::debug!(
+                    target: "client",
+                    ?chunk_hash,
+                    part_size,
+                    max_routed_message_payload = self.max_routed_message_payload,
+                    "Witness parts exceed the max payload of a routed message, sending them as \
+                    fragments"
+                );
+            }
+        }
+
+        // The parts are served by part_ord, so we don't serve any of them if some are missing,
+        // which is only the case when simulating `AdvWitnessPartsMode::Withhold`.
+        if validator_witness_tuple.len() == num_parts {
+            if let Some((_, partial_witness)) = validator_witness_tuple.first() {
+                self.produced_parts.put(
+                    partial_witness.chunk_production_key(),
+                    validator_witness_tuple
+                        .iter()
+                        .map(|(_, partial_witness)| partial_witness.clone())
+                        .collect(),
+                );
+            }
+        }
+
+        let _span = tracing::debug_span!(
+            target: "client",
+            "send_witness_parts",
+            num_parts = validator_witness_tuple.len(),
+            parts_size = validator_witness_tuple
+                .iter()
+                .map(|(_, partial_witness)| partial_witness.part_size())
+                .sum::<usize>(),
+        )
+        .entered();
         // Since we can't send network message to ourselves, we need to send the PartialEncodedStateWitnessForward
-        // message for our part.
-        if let Some(index) = validator_witness_tuple
-            .iter()
-            .position(|(validator, _)| validator == signer.validator_id())
-        {
-            // This also removes this validator from the list, since we do not need to send our own witness part to self.
-            let (_, partial_witness) = validator_witness_tuple.swap_remove(index);
+        // message for our parts. We own more than one part if we appear in the assignments more
+        // than once.
+        let own_parts = take_own_parts(&mut validator_witness_tuple, signer.validator_id());
+        if own_parts.is_empty() {
+            // Depending on the assignment, we may not be a chunk validator of our own chunk.
+            // Then all the parts go to the other validators, and we only keep them in
+            // `produced_parts` to serve the requests.
+            tracing::debug!(
+                target: "client",
+                ?chunk_hash,
+                "Chunk producer is not a chunk validator of its own chunk"
+            );
+        }
+        for partial_witness in own_parts {
+            self.record_owned_part(&partial_witness);
             self.forward_state_witness_part(partial_witness, signer)?;
         }
 
+        // The owners which announced that they can't receive their parts wouldn't forward them
+        // either, so we send these parts directly to all the other chunk validators instead.
+        let now = self.clock.now_utc();
+        let unavailable_owner_parts =
+            take_unavailable_owner_parts(&mut validator_witness_tuple, |owner| {
+                self.unavailable_receivers.is_unavailable(owner, now)
+            });
+
         // Record the witness in order to match the incoming acks for measuring round-trip times.
         // See process_chunk_state_witness_ack for the handling of the ack messages.
         self.state_witness_tracker.record_witness_sent(
             chunk_hash,
-            witness_size_in_bytes,
-            validator_witness_tuple.len(),
+            WitnessDistributionSummary::new(
+                epoch_id,
+                height_created,
+                shard_id,
+                raw_witness_size,
+                witness_size_in_bytes,
+                num_parts,
+                validator_witness_tuple.len(),
+                request_delay,
+                encode_time,
+            ),
+            &chunk_validator_assignments,
+            signer.validator_id(),
         );
 
-        // Send the parts to the corresponding chunk validator owners.
-        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-            NetworkRequests::PartialEncodedStateWitness(validator_witness_tuple),
-        ));
+        self.partial_witness_tracker.record_witness_distributed(&ChunkProductionKey {
+            epoch_id,
+            shard_id,
+            height_created,
+        });
+
+        // Send the parts to the corresponding chunk validator owners, interleaved with the parts
+        // of the other shards queued within `PartialWitnessConfig::part_send_window`.
+        self.part_send_queue.push(shard_id, validator_witness_tuple, routing_hints.clone());
+        if self.config.part_send_window <= Duration::ZERO {
+            self.send_queued_parts();
+        }
+
+        for (owner, partial_witness) in unavailable_owner_parts {
+            // Same targets as the ones of the owner's forward, except for the unavailable ones.
+            let targets =
+                forward_targets(&chunk_validator_assignments, &owner, signer.validator_id())
+                    .into_iter()
+                    .filter(|target| !self.unavailable_receivers.is_unavailable(target, now))
+                    .collect_vec();
+            tracing::debug!(
+                target: "client",
+                shard_id,
+                height_created,
+                part_ord = partial_witness.part_ord(),
+                %owner,
+                "Sending the part of an unavailable owner to all the chunk validators"
+            );
+            metrics::PARTIAL_WITNESS_UNAVAILABLE_OWNER_PARTS
+                .with_label_values(&[&shard_id_label])
+                .inc();
+            self.send_part_forward(targets, partial_witness, routing_hints.clone());
+        }
+
+        #[cfg(feature = "test_features")]
+        for (owner, partial_witness) in conflicting_parts {
+            let targets =
+                forward_targets(&chunk_validator_assignments, &owner, signer.validator_id());
+            tracing::info!(
+                target: "adversary",
+                shard_id,
+                height_created,
+                part_ord = partial_witness.part_ord(),
+                "Sending a conflicting part to the chunk validators not owning it"
+            );
+            self.send_part_forward(targets, partial_witness, routing_hints.clone());
+        }
         Ok(())
     }
 
     /// Sends the witness part to the chunk validators, except for the following:
     /// 1) The current validator, 2) Chunk producer that originally generated the witness part.
+    /// The targets are computed once per chunk, see `ForwardTargetsCache`, and capped per part
+    /// with `PartialWitnessConfig::max_forward_targets`. While the sends to the targets fail, the
+    /// forwards are capped further and paced, see `ForwardBackoff`. The part is forwarded exactly
+    /// as received, in the format the chunk producer signed it in, see `part_format`. Towards the
+    /// end of the epoch, the part is also sent to the validators pre-tracking the shard, see
+    /// `pre_tracking`.
     fn forward_state_witness_part(
-        &self,
+        &mut self,
         partial_witness: PartialEncodedStateWitness,
         signer: &ValidatorSigner,
     ) -> Result<(), Error> {
-        let ChunkProductionKey { shard_id, epoch_id, height_created } =
+        let ChunkProductionKey { shard_id, height_created, .. } =
             partial_witness.chunk_production_key();
-        let chunk_producer =
-            self.epoch_manager.get_chunk_producer(&epoch_id, height_created, shard_id)?;
-        let ordered_chunk_validators = self
-            .epoch_manager
-            .get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?
-            .ordered_chunk_validators();
-        // Forward witness part to chunk validators except for the following:
-        // (1) the current validator and (2) validator that produced the chunk and witness.
-        let target_chunk_validators = ordered_chunk_validators
-            .into_iter()
-            .filter(|validator| validator != signer.validator_id() && *validator != chunk_producer)
-            .collect();
-        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-            NetworkRequests::PartialEncodedStateWitnessForward(
-                target_chunk_validators,
-                partial_witness,
-            ),
-        ));
+        if self.config.no_forward_shards.contains(&shard_id) {
+            tracing::debug!(
+                target: "client",
+                shard_id,
+                height_created,
+                part_ord = partial_witness.part_ord(),
+                "Not forwarding witness part for shard excluded from forwarding"
+            );
+            metrics::PARTIAL_WITNESS_SUPPRESSED_FORWARDS
+                .with_label_values(&[&int_label(shard_id)])
+                .inc();
+            return Ok(());
+        }
+        let key = partial_witness.chunk_production_key();
+        let epoch_manager = self.epoch_manager.as_ref();
+        let store = &self.store;
+        let direct_routing_targets = self.config.direct_routing_targets;
+        let forward_targets =
+            self.forward_targets.get_or_try_insert(&key, signer.validator_id(), || {
+                compute_forward_targets(
+                    epoch_manager,
+                    ChainHeads::load(store)?.head.as_ref(),
+                    &key,
+                    signer.validator_id(),
+                    direct_routing_targets,
+                )
+            })?;
+        if !forward_targets.pre_tracking_targets.is_empty() {
+            self.send_part_forward(
+                forward_targets.pre_tracking_targets.clone(),
+                partial_witness.clone(),
+                WitnessRoutingHints::default(),
+            );
+        }
+        let backing_off =
+            self.forward_backoff.update(shard_id, &forward_targets.targets, self.clock.now());
+        let max_targets = match self.config.max_forward_targets {
+            Some(max_targets) if backing_off => {
+                Some(max_targets.min(self.config.forward_backoff.max_forward_targets))
+            }
+            None if backing_off => Some(self.config.forward_backoff.max_forward_targets),
+            max_targets => max_targets,
+        };
+        let targets = match max_targets {
+            Some(max_targets) => {
+                forward_targets.capped(&key, partial_witness.part_ord(), max_targets)
+            }
+            None => forward_targets.targets,
+        };
+        // We are the only other chunk validator, e.g. when the chunk producer isn't a validator.
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let routing_hints = forward_targets.routing_hints;
+        if backing_off && self.config.forward_backoff.pacing > Duration::ZERO {
+            self.paced_forwards.push(PacedForward { targets, partial_witness, routing_hints });
+            return Ok(());
+        }
+        self.send_part_forward(targets, partial_witness, routing_hints);
         Ok(())
     }
 
@@ -278,7 +2884,14 @@ impl PartialWitnessActor {
         &mut self,
         partial_witness: PartialEncodedStateWitness,
     ) -> Result<(), Error> {
-        tracing::debug!(target: "client", ?partial_witness, "Receive PartialEncodedStateWitnessMessage");
+        let key = partial_witness.chunk_production_key();
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            part_ord = partial_witness.part_ord(),
+            "Receive PartialEncodedStateWitnessMessage"
+        );
 
         let signer = match self.my_signer.get() {
             Some(signer) => signer,
@@ -287,28 +2900,82 @@ impl PartialWitnessActor {
             }
         };
 
+        // Nothing can be looked up for a nonexistent shard, not even whether we pre-track it.
+        validate_shard_id(self.epoch_manager.as_ref(), &key)?;
+        let pre_tracking = self.is_pre_tracked_part(&partial_witness, &signer)?;
+        // The chunk producer sends us directly only the part we own, unless we pre-track the shard.
+        if !pre_tracking {
+            let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+                &key.epoch_id,
+                key.shard_id,
+                key.height_created,
+            )?;
+            // A part_ord out of range has no owner, the validation below rejects it.
+            let owner =
+                witness_parts_geometry::part_owner(&chunk_validator_assignments, &partial_witness);
+            if owner.as_ref().is_some_and(|owner| owner != signer.validator_id()) {
+                return Err(Error::InvalidPartialChunkStateWitnessOwner(format!(
+                    "Received part_ord {} owned by {:?} directly from the chunk producer",
+                    partial_witness.part_ord(),
+                    owner
+                )));
+            }
+        }
+
         // Validate the partial encoded state witness.
-        if validate_partial_encoded_state_witness(
-            self.epoch_manager.as_ref(),
+        if let Some(height_window) = self.validate_partial_encoded_state_witness(
             &partial_witness,
             &signer,
-            &self.store,
+            pre_tracking,
+            SignatureVerificationKind::Owned,
         )? {
-            // Store the partial encoded state witness for self.
-            self.partial_witness_tracker
-                .store_partial_encoded_state_witness(partial_witness.clone())?;
-            // Forward the part to all the chunk validators.
-            self.forward_state_witness_part(partial_witness, &signer)?;
+            // Only the parts sent directly by the chunk producer tell how late its parts arrive,
+            // the forwarded ones also include the delay of the owner.
+            self.partial_witness_tracker.record_send_skew(&partial_witness);
+            // Forward the part to all the chunk validators before storing it, the other chunk
+            // validators wait for it while we only set up the entry and maybe complete the
+            // witness, which is decoded after this handler returns anyway. If our part conflicts
+            // with the forwards received before it, it is not forwarded, so the conflict doesn't
+            // spread to the other chunk validators, and storing it reports the conflict. We are
+            // not the owner of any part of a pre-tracked shard, so there is nothing to forward in
+            // that case. Our part is stored even if the forward targets can't be computed.
+            let mut forwarded = Ok(());
+            if !pre_tracking
+                && !self.partial_witness_tracker.conflicts_with_received_parts(&partial_witness)
+            {
+                self.record_owned_part(&partial_witness);
+                forwarded = self.forward_state_witness_part(partial_witness.clone(), &signer);
+            }
+            self.partial_witness_tracker.store_partial_encoded_state_witness(
+                partial_witness,
+                pre_tracking,
+                PartDelivery::Direct,
+                height_window,
+                None,
+                signer.validator_id(),
+            )?;
+            forwarded?;
         }
 
         Ok(())
     }
 
     /// Function to handle receiving partial_encoded_state_witness_forward message from chunk producer.
+    /// `from_peer` is the peer which delivered the forward, unknown for the replayed messages.
     pub fn handle_partial_encoded_state_witness_forward(
         &mut self,
         partial_witness: PartialEncodedStateWitness,
+        from_peer: Option<PeerId>,
     ) -> Result<(), Error> {
+        // Dropped before the validation, which would only find the part we already validated.
+        if self.partial_witness_tracker.is_duplicate_part(
+            &partial_witness.chunk_production_key(),
+            partial_witness.part_ord(),
+            partial_witness.part(),
+        ) {
+            metrics::PARTIAL_WITNESS_DUPLICATE_PARTS_DROPPED.inc();
+            return Ok(());
+        }
         let signer = match self.my_signer.get() {
             Some(signer) => signer,
             None => {
@@ -318,17 +2985,365 @@ impl PartialWitnessActor {
             }
         };
 
+        // Nothing can be looked up for a nonexistent shard, not even whether we pre-track it.
+        validate_shard_id(self.epoch_manager.as_ref(), &partial_witness.chunk_production_key())?;
+        let pre_tracking = self.is_pre_tracked_part(&partial_witness, &signer)?;
+
         // Validate the partial encoded state witness.
-        if validate_partial_encoded_state_witness(
-            self.epoch_manager.as_ref(),
+        if let Some(height_window) = self.validate_partial_encoded_state_witness(
             &partial_witness,
             &signer,
-            &self.store,
+            pre_tracking,
+            SignatureVerificationKind::Forward,
         )? {
+            if let Some(from_peer) = from_peer.as_ref().filter(|_| !pre_tracking) {
+                self.partial_witness_tracker.record_forwarded_part(
+                    &partial_witness,
+                    from_peer,
+                    signer.validator_id(),
+                );
+            }
             // Store the partial encoded state witness for self.
-            self.partial_witness_tracker.store_partial_encoded_state_witness(partial_witness)?;
+            self.partial_witness_tracker.store_partial_encoded_state_witness(
+                partial_witness,
+                pre_tracking,
+                PartDelivery::Forward,
+                height_window,
+                from_peer.as_ref(),
+                signer.validator_id(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Function to handle receiving the full encoded state witness sent directly by the chunk producer.
+    pub fn handle_full_encoded_state_witness(
+        &mut self,
+        full_witness: FullEncodedStateWitness,
+    ) -> Result<(), Error> {
+        tracing::debug!(target: "client", ?full_witness, "Receive FullEncodedStateWitnessMessage");
+
+        let signer = match self.my_signer.get() {
+            Some(signer) => signer,
+            None => {
+                return Err(Error::NotAValidator(format!("handle full encoded state witness")));
+            }
+        };
+
+        validate_shard_id(self.epoch_manager.as_ref(), &full_witness.chunk_production_key())?;
+        if validate_full_encoded_state_witness(
+            self.epoch_manager.as_ref(),
+            &full_witness,
+            &signer,
+            &self.store,
+        )? {
+            self.partial_witness_tracker.store_full_encoded_state_witness(full_witness)?;
+        }
+
+        Ok(())
+    }
+
+    /// Function to handle a request for a witness part from a chunk validator that didn't
+    /// receive our forward. We answer directly to the requester, unless enough chunk validators
+    /// are missing our part, in which case we re-broadcast it to all of them once. The requester
+    /// must be the account announced by the author of the message, `author_account`, so that
+    /// nobody can make us send the part to the chunk validators or trigger the re-broadcast on
+    /// their behalf.
+    pub fn handle_partial_encoded_state_witness_request(
+        &mut self,
+        request: PartialEncodedStateWitnessRequest,
+        author_account: Option<AccountId>,
+    ) -> Result<(), Error> {
+        tracing::debug!(
+            target: "client",
+            shard_id = request.shard_id,
+            height_created = request.height_created,
+            part_ord = request.part_ord,
+            requester = %request.requester,
+            "Receive PartialEncodedStateWitnessRequest"
+        );
+
+        let signer = match self.my_signer.get() {
+            Some(signer) => signer,
+            None => {
+                return Err(Error::NotAValidator(format!(
+                    "handle partial encoded state witness request"
+                )));
+            }
+        };
+        if author_account.as_ref() != Some(&request.requester) {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Witness part requested on behalf of {} by {:?}",
+                request.requester, author_account,
+            )));
+        }
+
+        let key = request.chunk_production_key();
+        // No honest node sends the requests before `ProtocolFeature::PartialWitnessRequests`.
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&key.epoch_id)?;
+        if !ProtocolFeature::PartialWitnessRequests.enabled(protocol_version) {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Received a part request at protocol version {} before the requests are enabled",
+                protocol_version
+            )));
+        }
+        let is_owned_part = self.owned_parts.peek(&key).is_some_and(|owned_parts| {
+            owned_parts
+                .iter()
+                .any(|owned_part| owned_part.partial_witness.part_ord() == request.part_ord)
+        });
+        // As the chunk producer we can serve any part of the witness, but the owner of the part
+        // is responsible for re-broadcasting it, so we prefer the owned part when we have it.
+        let produced_part = if is_owned_part {
+            None
+        } else {
+            self.produced_parts.peek(&key).and_then(|parts| parts.get(request.part_ord)).cloned()
+        };
+        if !is_owned_part && produced_part.is_none() {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                requested_part_ord = request.part_ord,
+                "Requested witness part is not owned or produced by us, or already evicted",
+            );
+            return Ok(());
+        }
+
+        // Only the chunk validators of the chunk are allowed to request the parts.
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &key.epoch_id,
+            key.shard_id,
+            key.height_created,
+        )?;
+        if !chunk_validator_assignments.contains(&request.requester) {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Witness part requested by {} which is not a chunk validator for {:?}",
+                request.requester, key,
+            )));
+        }
+
+        let shard_id_label = int_label(key.shard_id);
+        if let Some(partial_witness) = produced_part {
+            metrics::PARTIAL_WITNESS_PRODUCED_PART_REQUESTS_SERVED
+                .with_label_values(&[&shard_id_label])
+                .inc();
+            self.send_part_forward(
+                vec![request.requester],
+                partial_witness,
+                WitnessRoutingHints::default(),
+            );
+            return Ok(());
+        }
+
+        let owned_part = self
+            .owned_parts
+            .get_mut(&key)
+            .and_then(|owned_parts| {
+                owned_parts
+                    .iter_mut()
+                    .find(|owned_part| owned_part.partial_witness.part_ord() == request.part_ord)
+            })
+            .unwrap();
+        owned_part.requesters.insert(request.requester.clone());
+        // With the capped fan-out the chunk validators outside of our subset are expected to
+        // request the part, and re-broadcasting it would only send it to the same subset again.
+        let rebroadcast = self.config.max_forward_targets.is_none()
+            && !owned_part.rebroadcast
+            && owned_part.requesters.len() >= OWNED_PART_REBROADCAST_MIN_REQUESTERS;
+        owned_part.rebroadcast |= rebroadcast;
+        let partial_witness = owned_part.partial_witness.clone();
+
+        if rebroadcast {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                "Re-broadcasting our witness part"
+            );
+            metrics::PARTIAL_WITNESS_OWNED_PART_REBROADCASTS
+                .with_label_values(&[&shard_id_label])
+                .inc();
+            self.forward_state_witness_part(partial_witness, &signer)?;
+        } else {
+            metrics::PARTIAL_WITNESS_OWNED_PART_REQUESTS_SERVED
+                .with_label_values(&[&shard_id_label])
+                .inc();
+            self.send_part_forward(
+                vec![request.requester],
+                partial_witness,
+                WitnessRoutingHints::default(),
+            );
+        }
+        Ok(())
+    }
+
+    fn report_error(
+        &mut self,
+        stage: PartialWitnessErrorStage,
+        err: &Error,
+        key: &ChunkProductionKey,
+    ) {
+        let epoch_manager = &self.epoch_manager;
+        let event = self.error_reporter.report(stage, err, key, || {
+            // The chunk producer of a nonexistent shard can't be looked up.
+            if matches!(err, Error::InvalidShardId(_)) {
+                return None;
+            }
+            epoch_manager.get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id).ok()
+        });
+        if let Some(event) = event {
+            self.client_sender.send(PartialWitnessErrorMessage(event));
+        }
+    }
+
+    /// Keeps the part that we own so that we can re-send it later on request.
+    fn record_owned_part(&mut self, partial_witness: &PartialEncodedStateWitness) {
+        insert_owned_part(&mut self.owned_parts, partial_witness);
+    }
+
+    /// Returns true if the part belongs to a shard from `pre_tracked_shards` for which we are
+    /// not a chunk validator of the chunk.
+    fn is_pre_tracked_part(
+        &self,
+        partial_witness: &PartialEncodedStateWitness,
+        signer: &ValidatorSigner,
+    ) -> Result<bool, Error> {
+        let ChunkProductionKey { shard_id, epoch_id, height_created } =
+            partial_witness.chunk_production_key();
+        if !self.config.pre_tracked_shards.contains(&shard_id) {
+            return Ok(false);
+        }
+        let chunk_validator_assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &epoch_id,
+            shard_id,
+            height_created,
+        )?;
+        Ok(!chunk_validator_assignments.contains(signer.validator_id()))
+    }
+
+    /// Routing hints for the witness messages of a chunk: the chunk validators with the highest
+    /// stake are preferably reached over a direct connection.
+    fn routing_hints(
+        &self,
+        chunk_validator_assignments: &ChunkValidatorAssignments,
+        my_account_id: &AccountId,
+    ) -> WitnessRoutingHints {
+        WitnessRoutingHints {
+            direct: top_stake_validators(
+                chunk_validator_assignments,
+                my_account_id,
+                self.config.direct_routing_targets,
+            ),
+        }
+    }
+
+    /// Validates the part, returning the tip whose height window admitted it, None if the part
+    /// should not be processed at this point.
+    fn validate_partial_encoded_state_witness(
+        &mut self,
+        partial_witness: &PartialEncodedStateWitness,
+        signer: &ValidatorSigner,
+        pre_tracking: bool,
+        kind: SignatureVerificationKind,
+    ) -> Result<Option<HeightWindowContext>, Error> {
+        let epoch_manager = self.epoch_manager.as_ref();
+        let key = partial_witness.chunk_production_key();
+        let context = ValidationContext {
+            epoch_manager,
+            account_id: signer.validator_id(),
+            pre_tracking,
+            heads: ChainHeads::load(&self.store)?,
+            // The window of the alternative tip is only considered with
+            // `PartialWitnessConfig::fork_aware_height_window`.
+            alternative_tip: self
+                .alternative_tip
+                .as_ref()
+                .filter(|_| self.config.fork_aware_height_window),
+            deadline: self.partial_witness_tracker.deadline_of(&key),
+            expired: self.partial_witness_tracker.is_expired(&key),
+        };
+        let verify_signature = || {
+            self.verification_load
+                .verify(kind, || epoch_manager.verify_partial_witness_signature(partial_witness))
+        };
+        let height_window = validate_partial_encoded_state_witness(
+            partial_witness,
+            &context,
+            &self.clock,
+            verify_signature,
+        )?;
+        // Only after the signature check, so that nobody but the chunk producer can trigger it.
+        if let Some(height_window) = height_window {
+            self.check_chunk_validators_digest(partial_witness)?;
+            metrics::PARTIAL_WITNESS_HEIGHT_WINDOW_ADMISSIONS
+                .with_label_values(&[
+                    &int_label(partial_witness.chunk_production_key().shard_id),
+                    height_window.as_str(),
+                ])
+                .inc();
         }
+        Ok(height_window)
+    }
+
+    /// Checks the witnesses admitted by the alternative tip against the height windows of the new
+    /// head and alternative tip. After a reorg to their fork the head admits them and they are
+    /// decoded, and they are dropped once neither tip admits them anymore.
+    fn reevaluate_height_windows(&mut self) {
+        let heads = match ChainHeads::load(&self.store) {
+            Ok(heads) => heads,
+            Err(err) => {
+                tracing::debug!(
+                    target: "client",
+                    ?err,
+                    "Failed to load the heads to reevaluate the height windows"
+                );
+                return;
+            }
+        };
+        let epoch_manager = self.epoch_manager.as_ref();
+        let alternative_tip =
+            self.alternative_tip.as_ref().filter(|_| self.config.fork_aware_height_window);
+        self.partial_witness_tracker.reevaluate_height_windows(|key| {
+            validate_chunk_production_key_height_window(epoch_manager, key, &heads, alternative_tip)
+        });
+    }
 
+    /// Counts and logs once per witness the parts whose chunk producer has a view of the chunk
+    /// validators different from ours, which means that the chunk validator assignment differs
+    /// between the nodes, e.g. because of a bug in one of the versions. The part is rejected
+    /// only with `PartialWitnessConfig::reject_chunk_validators_mismatch`.
+    fn check_chunk_validators_digest(
+        &mut self,
+        partial_witness: &PartialEncodedStateWitness,
+    ) -> Result<(), Error> {
+        let Some(expected) =
+            chunk_validators_digest_mismatch(self.epoch_manager.as_ref(), partial_witness)?
+        else {
+            return Ok(());
+        };
+        // Present in every part which carries the view of the chunk producer.
+        let actual = *partial_witness.chunk_validators_digest().unwrap();
+        let key = partial_witness.chunk_production_key();
+        if self.chunk_validators_mismatches.put(key.clone(), (expected, actual)).is_none() {
+            metrics::PARTIAL_WITNESS_CHUNK_VALIDATORS_MISMATCHES
+                .with_label_values(&[&int_label(key.shard_id)])
+                .inc();
+            tracing::warn!(
+                target: "client",
+                ?key,
+                %expected,
+                %actual,
+                "Chunk producer's view of the chunk validators differs from ours",
+            );
+        }
+        if self.config.reject_chunk_validators_mismatch {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Chunk validators signed by the chunk producer ({}) differ from ours ({})",
+                actual, expected
+            )));
+        }
         Ok(())
     }
 
@@ -337,23 +3352,580 @@ impl PartialWitnessActor {
     /// the ack message and updates the corresponding metric with it.
     /// Currently we do not raise an error for handling of witness-ack messages,
     /// as it is used only for tracking some networking metrics.
-    pub fn handle_chunk_state_witness_ack(&mut self, witness_ack: ChunkStateWitnessAck) {
-        self.state_witness_tracker.on_witness_ack_received(witness_ack);
+    pub fn handle_chunk_state_witness_ack(
+        &mut self,
+        witness_ack: VersionedChunkStateWitnessAck,
+        validator: Option<AccountId>,
+    ) {
+        if let Some((key, time)) =
+            self.state_witness_tracker.on_witness_ack_received(witness_ack, validator.as_ref())
+        {
+            self.partial_witness_tracker.record_ack_threshold(&key, time);
+        }
+    }
+
+    /// Handles the acks held by the chunk validator and sent together, see
+    /// `PartialWitnessConfig::ack_batching_delay`.
+    pub fn handle_batched_chunk_state_witness_ack(
+        &mut self,
+        batch: BatchedChunkStateWitnessAckV2,
+        validator: Option<AccountId>,
+    ) {
+        for held_ack in batch.acks {
+            let held = held_ack.held();
+            if let Some((key, time)) = self.state_witness_tracker.on_held_witness_ack_received(
+                held_ack.ack,
+                held,
+                validator.as_ref(),
+            ) {
+                self.partial_witness_tracker.record_ack_threshold(&key, time);
+            }
+        }
+    }
+
+    /// Announces to the chunk producers of the epoch of the head until when we can't receive the
+    /// witness parts, None if we can receive them again.
+    fn announce_receiver_status(&self, unavailable_until: Option<Utc>) -> Result<(), Error> {
+        let signer = match self.my_signer.get() {
+            Some(signer) => signer,
+            None => {
+                return Err(Error::NotAValidator(format!("announce witness receiver status")));
+            }
+        };
+        let Some(head) = self.store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? else {
+            return Ok(());
+        };
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&head.epoch_id)?;
+        if !ProtocolFeature::WitnessReceiverStatus.enabled(protocol_version) {
+            tracing::debug!(
+                target: "client",
+                protocol_version,
+                "Witness receiver status not enabled yet, not announcing it"
+            );
+            return Ok(());
+        }
+        let chunk_producers = self
+            .epoch_manager
+            .get_epoch_chunk_producers(&head.epoch_id)?
+            .into_iter()
+            .map(|chunk_producer| chunk_producer.take_account_id())
+            .filter(|chunk_producer| chunk_producer != signer.validator_id())
+            .collect_vec();
+        tracing::info!(
+            target: "client",
+            ?unavailable_until,
+            num_chunk_producers = chunk_producers.len(),
+            "Announcing witness receiver status"
+        );
+        let status = WitnessReceiverStatus::new(
+            signer.validator_id().clone(),
+            self.clock.now_utc(),
+            unavailable_until,
+            &signer,
+        );
+        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::WitnessReceiverStatus(chunk_producers, status),
+        ));
+        Ok(())
+    }
+
+    /// Records the status announced by a chunk validator. The status must be signed by the key
+    /// of the validator in the epoch of the head.
+    pub fn handle_witness_receiver_status(
+        &mut self,
+        status: WitnessReceiverStatus,
+    ) -> Result<(), Error> {
+        tracing::debug!(target: "client", ?status, "Receive WitnessReceiverStatusMessage");
+        let Some(head) = self.store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)? else {
+            return Ok(());
+        };
+        // No honest node announces its status before `ProtocolFeature::WitnessReceiverStatus`.
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&head.epoch_id)?;
+        if !ProtocolFeature::WitnessReceiverStatus.enabled(protocol_version) {
+            return Err(Error::Other(format!(
+                "Received a witness receiver status at protocol version {} before it is enabled",
+                protocol_version
+            )));
+        }
+        let (validator, _) = self.epoch_manager.get_validator_by_account_id(
+            &head.epoch_id,
+            &head.last_block_hash,
+            status.account_id(),
+        )?;
+        if !status.verify(validator.public_key()) {
+            return Err(Error::InvalidSignature);
+        }
+        if self.unavailable_receivers.update(
+            status.account_id(),
+            status.issued_at(),
+            status.unavailable_until(),
+            self.clock.now_utc(),
+        ) {
+            tracing::info!(
+                target: "client",
+                account_id = %status.account_id(),
+                unavailable_until = ?status.unavailable_until(),
+                "Updated witness receiver status"
+            );
+        }
+        Ok(())
     }
 }
 
-fn compress_witness(witness: &ChunkStateWitness) -> Result<EncodedChunkStateWitness, Error> {
-    let shard_id_label = witness.chunk_header.shard_id().to_string();
+/// Encodes the witness and hashes it in the same pass, see
+/// `EncodedChunkStateWitness::encode_and_hash`.
+fn compress_witness(
+    witness: &ChunkStateWitness,
+    compression_threshold: ByteSize,
+) -> Result<(EncodedChunkStateWitness, ChunkStateWitnessSectionSizes, CryptoHash), Error> {
+    let span = tracing::debug_span!(
+        target: "client",
+        "compress_witness",
+        raw_size = tracing::field::Empty,
+        compressed_size = tracing::field::Empty,
+    )
+    .entered();
+    let shard_id_label = int_label(witness.chunk_header.shard_id());
     let encode_timer = near_chain::stateless_validation::metrics::CHUNK_STATE_WITNESS_ENCODE_TIME
-        .with_label_values(&[shard_id_label.as_str()])
+        .with_label_values(&[&shard_id_label])
         .start_timer();
-    let (witness_bytes, raw_witness_size) = EncodedChunkStateWitness::encode(&witness)?;
+    let (witness_bytes, raw_witness_size, section_sizes, witness_hash) =
+        EncodedChunkStateWitness::encode_and_hash(&witness, compression_threshold)?;
     encode_timer.observe_duration();
+    span.record("raw_size", raw_witness_size);
+    span.record("compressed_size", witness_bytes.size_bytes());
 
     near_chain::stateless_validation::metrics::record_witness_size_metrics(
         raw_witness_size,
-        witness_bytes.size_bytes(),
+        &witness_bytes,
         witness,
+        &section_sizes,
     );
-    Ok(witness_bytes)
+    Ok((witness_bytes, section_sizes, witness_hash))
+}
+
+/// Drops all the parts of the witnesses produced by us, returns the memory freed. The parts we
+/// own are shared with `owned_parts` and stay in memory until dropped there.
+fn drop_produced_parts(
+    produced_parts: &mut LruCache<ChunkProductionKey, Vec<PartialEncodedStateWitness>>,
+) -> usize {
+    let mut estimator = SizeEstimator::new();
+    for (_, parts) in produced_parts.iter() {
+        for partial_witness in parts {
+            if Arc::strong_count(partial_witness.shared_part()) > 1 {
+                estimator.skip_shared(partial_witness.shared_part());
+            }
+        }
+        estimator.add(parts);
+    }
+    produced_parts.clear();
+    estimator.total()
+}
+
+/// Drops the parts owned by us for the chunks created at `height` or below, returns the memory
+/// freed.
+fn drop_owned_parts_up_to(
+    owned_parts: &mut LruCache<ChunkProductionKey, Vec<OwnedPart>>,
+    height: BlockHeight,
+) -> usize {
+    let keys_to_drop: Vec<ChunkProductionKey> = owned_parts
+        .iter()
+        .filter(|(key, _)| key.height_created <= height)
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut estimator = SizeEstimator::new();
+    for parts in keys_to_drop.iter().filter_map(|key| owned_parts.pop(key)) {
+        estimator.add(&parts);
+    }
+    estimator.total()
+}
+
+/// Adds the part to the parts we own for the chunk. We own more than one part of the chunk if we
+/// appear in the assignments more than once. A part we already have is kept as is, together
+/// with its requesters.
+fn insert_owned_part(
+    owned_parts: &mut LruCache<ChunkProductionKey, Vec<OwnedPart>>,
+    partial_witness: &PartialEncodedStateWitness,
+) {
+    let owned_part = OwnedPart {
+        partial_witness: partial_witness.clone(),
+        requesters: HashSet::new(),
+        rebroadcast: false,
+    };
+    let key = partial_witness.chunk_production_key();
+    let Some(parts) = owned_parts.get_mut(&key) else {
+        owned_parts.put(key, vec![owned_part]);
+        return;
+    };
+    if parts.iter().all(|part| part.partial_witness.part_ord() != partial_witness.part_ord()) {
+        parts.push(owned_part);
+    }
+}
+
+/// Chunk validators to which we forward our part: all of them except for us and the chunk
+/// producer, which has all the parts. An account appearing in the assignments more than once
+/// gets the part only once.
+fn forward_targets(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    my_account_id: &AccountId,
+    chunk_producer: &AccountId,
+) -> Vec<AccountId> {
+    chunk_validator_assignments
+        .ordered_chunk_validators()
+        .into_iter()
+        .filter(|validator| validator != my_account_id && validator != chunk_producer)
+        .unique()
+        .collect()
+}
+
+/// Computes where we forward our parts of the chunk, see `ForwardTargetsCache`.
+fn compute_forward_targets(
+    epoch_manager: &dyn EpochManagerAdapter,
+    head: Option<&Tip>,
+    key: &ChunkProductionKey,
+    my_account_id: &AccountId,
+    direct_routing_targets: usize,
+) -> Result<ForwardTargets, Error> {
+    let chunk_producer =
+        epoch_manager.get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)?;
+    let chunk_validator_assignments = epoch_manager.get_chunk_validator_assignments(
+        &key.epoch_id,
+        key.shard_id,
+        key.height_created,
+    )?;
+    let format =
+        PartFormat::for_protocol_version(epoch_manager.get_epoch_protocol_version(&key.epoch_id)?);
+    let pre_tracking_targets = match head {
+        Some(head) => pre_tracking::pre_tracking_targets(
+            epoch_manager,
+            head,
+            key,
+            &chunk_validator_assignments,
+            my_account_id,
+            &chunk_producer,
+        )?,
+        None => vec![],
+    };
+    Ok(ForwardTargets {
+        targets: forward_targets(&chunk_validator_assignments, my_account_id, &chunk_producer),
+        part_owners: witness_parts_geometry::part_owners(&chunk_validator_assignments, format),
+        routing_hints: WitnessRoutingHints {
+            direct: top_stake_validators(
+                &chunk_validator_assignments,
+                my_account_id,
+                direct_routing_targets,
+            ),
+        },
+        pre_tracking_targets,
+    })
+}
+
+/// Whether we are the only chunk validator of the chunk, possibly assigned more than once.
+fn is_sole_chunk_validator(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    my_account_id: &AccountId,
+) -> bool {
+    let assignments = chunk_validator_assignments.assignments();
+    !assignments.is_empty() && assignments.iter().all(|(validator, _)| validator == my_account_id)
+}
+
+/// Up to `num_validators` chunk validators with the highest stake other than us, ordered by stake
+/// and then by account id. An account appearing in the assignments more than once is listed once.
+fn top_stake_validators(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    my_account_id: &AccountId,
+    num_validators: usize,
+) -> Vec<AccountId> {
+    chunk_validator_assignments
+        .assignments()
+        .iter()
+        .filter(|(validator, _)| validator != my_account_id)
+        .sorted_by(|(a, a_stake), (b, b_stake)| b_stake.cmp(a_stake).then_with(|| a.cmp(b)))
+        .map(|(validator, _)| validator.clone())
+        .unique()
+        .take(num_validators)
+        .collect()
+}
+
+/// Counts the delivered witness messages by the requested routing preference and the path
+/// actually used, which gives the share of the messages delivered directly.
+fn record_witness_deliveries(deliveries: &[WitnessDelivery]) {
+    for delivery in deliveries {
+        metrics::PARTIAL_WITNESS_DELIVERIES
+            .with_label_values(&[delivery.preference.as_str(), delivery.path.as_str()])
+            .inc();
+    }
+}
+
+/// Removes and returns our own parts from the parts of the witness produced by us, empty if we
+/// are not a chunk validator of the chunk. We own every part at an ordinal assigned to us, which
+/// is more than one if we appear in the assignments more than once. We can't send network
+/// messages to ourselves, so our parts are forwarded to the other chunk validators instead of
+/// being sent to us.
+fn take_own_parts(
+    validator_witness_tuple: &mut Vec<(AccountId, PartialEncodedStateWitness)>,
+    my_account_id: &AccountId,
+) -> Vec<PartialEncodedStateWitness> {
+    let (own_parts, other_parts): (Vec<_>, Vec<_>) = std::mem::take(validator_witness_tuple)
+        .into_iter()
+        .partition(|(validator, _)| validator == my_account_id);
+    *validator_witness_tuple = other_parts;
+    own_parts.into_iter().map(|(_, partial_witness)| partial_witness).collect()
+}
+
+/// Removes and returns the parts owned by the chunk validators which announced that they can't
+/// receive them, along with their owners, see `UnavailableReceivers`.
+fn take_unavailable_owner_parts(
+    validator_witness_tuple: &mut Vec<(AccountId, PartialEncodedStateWitness)>,
+    is_unavailable: impl Fn(&AccountId) -> bool,
+) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+    let (unavailable_owner_parts, other_parts): (Vec<_>, Vec<_>) =
+        std::mem::take(validator_witness_tuple)
+            .into_iter()
+            .partition(|(validator, _)| is_unavailable(validator));
+    *validator_witness_tuple = other_parts;
+    unavailable_owner_parts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::num::NonZeroUsize;
+
+    use lru::LruCache;
+    use near_network::state_witness::{
+        WitnessDelivery, WitnessDeliveryPath, WitnessRoutingHints, WitnessRoutingPreference,
+    };
+    use near_o11y::metrics::IntGauge;
+    use near_primitives::stateless_validation::estimate_size::EstimateSize;
+    use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+    use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::AccountId;
+
+    use super::{
+        drop_owned_parts_up_to, drop_produced_parts, forward_targets, insert_owned_part,
+        record_witness_deliveries, take_own_parts, take_unavailable_owner_parts,
+        top_stake_validators, InFlightWitnessBytes, OwnedPart, PartFormat,
+    };
+    use crate::metrics;
+    use crate::stateless_validation::partial_witness::witness_parts_geometry;
+    use crate::test_utils::TestWitnessBuilder;
+
+    #[test]
+    fn in_flight_witness_bytes_are_released_on_drop() {
+        let gauge = IntGauge::new("in_flight_bytes", "test").unwrap();
+        let first = InFlightWitnessBytes::with_gauge(gauge.clone(), 100);
+        let second = InFlightWitnessBytes::with_gauge(gauge.clone(), 50);
+        assert_eq!(gauge.get(), 150);
+
+        // The request is processed by another thread than the one which sent it.
+        std::thread::spawn(move || drop(first)).join().unwrap();
+        assert_eq!(gauge.get(), 50);
+        drop(second);
+        assert_eq!(gauge.get(), 0);
+    }
+
+    fn produced_parts(owners: &[&str]) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+        let owners = owners.iter().map(|owner| owner.parse().unwrap()).collect::<Vec<AccountId>>();
+        TestWitnessBuilder::new()
+            .encode_and_split_among(&owners, &create_test_signer("producer"))
+            .into_owned_parts()
+    }
+
+    fn part_ords<'a>(
+        parts: impl IntoIterator<Item = &'a PartialEncodedStateWitness>,
+    ) -> Vec<usize> {
+        parts.into_iter().map(|partial_witness| partial_witness.part_ord()).collect()
+    }
+
+    /// Assignments in which an account appears more than once.
+    fn duplicated_assignments() -> ChunkValidatorAssignments {
+        ChunkValidatorAssignments::new(
+            ["test0", "producer", "test1", "producer", "test1"]
+                .into_iter()
+                .map(|account_id| (account_id.parse().unwrap(), 1))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parts_of_unavailable_owner_are_taken() {
+        let mut parts = produced_parts(&["test0", "test1", "test2", "test1"]);
+        let unavailable_owner_parts =
+            take_unavailable_owner_parts(&mut parts, |owner| owner.as_str() == "test1");
+        assert_eq!(
+            part_ords(unavailable_owner_parts.iter().map(|(_, partial_witness)| partial_witness)),
+            vec![1, 3]
+        );
+        assert!(unavailable_owner_parts.iter().all(|(owner, _)| owner.as_str() == "test1"));
+        assert_eq!(part_ords(parts.iter().map(|(_, partial_witness)| partial_witness)), vec![0, 2]);
+    }
+
+    #[test]
+    fn own_part_of_chunk_validator_producer() {
+        let mut parts = produced_parts(&["test0", "producer", "test1"]);
+        let own_parts = take_own_parts(&mut parts, &"producer".parse().unwrap());
+        assert_eq!(part_ords(&own_parts), vec![1]);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|(owner, _)| owner.as_str() != "producer"));
+    }
+
+    #[test]
+    fn all_own_parts_of_duplicated_producer() {
+        let owners = witness_parts_geometry::part_owners(&duplicated_assignments(), PartFormat::V2);
+        let owners = owners.iter().map(|owner| owner.as_str()).collect::<Vec<_>>();
+        assert_eq!(owners, vec!["producer", "producer", "test0", "test1", "test1"]);
+        let mut parts = produced_parts(&owners);
+        let own_parts = take_own_parts(&mut parts, &"producer".parse().unwrap());
+        assert_eq!(part_ords(&own_parts), vec![0, 1]);
+        // None of our parts is sent to us over the network.
+        assert_eq!(
+            part_ords(parts.iter().map(|(_, partial_witness)| partial_witness)),
+            vec![2, 3, 4]
+        );
+        assert!(parts.iter().all(|(owner, _)| owner.as_str() != "producer"));
+    }
+
+    #[test]
+    fn forward_targets_of_duplicated_assignments() {
+        let assignments = duplicated_assignments();
+        let producer: AccountId = "producer".parse().unwrap();
+        let targets = forward_targets(&assignments, &"test1".parse().unwrap(), &producer);
+        assert_eq!(targets, vec!["test0".parse::<AccountId>().unwrap()]);
+        let targets = forward_targets(&assignments, &"test0".parse().unwrap(), &producer);
+        assert_eq!(targets, vec!["test1".parse::<AccountId>().unwrap()]);
+    }
+
+    #[test]
+    fn top_stake_validators_are_preferred_for_direct_routing() {
+        let assignments = ChunkValidatorAssignments::new(
+            [("carol", 10), ("me", 50), ("alice", 30), ("bob", 30), ("alice", 30), ("dave", 5)]
+                .into_iter()
+                .map(|(account_id, stake)| (account_id.parse().unwrap(), stake))
+                .collect(),
+        );
+        let me: AccountId = "me".parse().unwrap();
+        let direct = top_stake_validators(&assignments, &me, 3);
+        let direct_names = direct.iter().map(|account_id| account_id.as_str()).collect::<Vec<_>>();
+        assert_eq!(direct_names, vec!["alice", "bob", "carol"]);
+        assert!(top_stake_validators(&assignments, &me, 0).is_empty());
+        assert_eq!(top_stake_validators(&assignments, &me, 10).len(), 4);
+
+        let hints = WitnessRoutingHints { direct };
+        assert_eq!(hints.preference(&"bob".parse().unwrap()), WitnessRoutingPreference::Direct);
+        assert_eq!(hints.preference(&"dave".parse().unwrap()), WitnessRoutingPreference::Default);
+        assert_eq!(
+            WitnessRoutingHints::default().preference(&"bob".parse().unwrap()),
+            WitnessRoutingPreference::Default
+        );
+    }
+
+    #[test]
+    fn witness_deliveries_are_counted_by_preference_and_path() {
+        let count = |preference: WitnessRoutingPreference, path: WitnessDeliveryPath| {
+            metrics::PARTIAL_WITNESS_DELIVERIES
+                .with_label_values(&[preference.as_str(), path.as_str()])
+                .get()
+        };
+        let direct_routed = count(WitnessRoutingPreference::Direct, WitnessDeliveryPath::Routed);
+        let default_routed = count(WitnessRoutingPreference::Default, WitnessDeliveryPath::Routed);
+        let direct_direct = count(WitnessRoutingPreference::Direct, WitnessDeliveryPath::Direct);
+
+        let delivery = |target: &str, preference| WitnessDelivery {
+            target: target.parse().unwrap(),
+            preference,
+            path: WitnessDeliveryPath::Routed,
+        };
+        record_witness_deliveries(&[
+            delivery("test0", WitnessRoutingPreference::Direct),
+            delivery("test1", WitnessRoutingPreference::Direct),
+            delivery("test2", WitnessRoutingPreference::Default),
+        ]);
+        assert_eq!(
+            count(WitnessRoutingPreference::Direct, WitnessDeliveryPath::Routed),
+            direct_routed + 2
+        );
+        assert_eq!(
+            count(WitnessRoutingPreference::Default, WitnessDeliveryPath::Routed),
+            default_routed + 1
+        );
+        assert_eq!(
+            count(WitnessRoutingPreference::Direct, WitnessDeliveryPath::Direct),
+            direct_direct
+        );
+    }
+
+    #[test]
+    fn all_owned_parts_of_duplicated_owner_are_kept() {
+        let mut owned_parts = LruCache::new(NonZeroUsize::new(10).unwrap());
+        let parts = produced_parts(&["test0", "test1", "test1"]);
+        let key = parts[0].1.chunk_production_key();
+        insert_owned_part(&mut owned_parts, &parts[1].1);
+        insert_owned_part(&mut owned_parts, &parts[2].1);
+        // Receiving an owned part again doesn't reset its requesters.
+        owned_parts.get_mut(&key).unwrap()[0].requesters.insert("test0".parse().unwrap());
+        insert_owned_part(&mut owned_parts, &parts[1].1);
+
+        let kept = owned_parts.peek(&key).unwrap();
+        assert_eq!(
+            part_ords(kept.iter().map(|owned_part| &owned_part.partial_witness)),
+            vec![1, 2]
+        );
+        assert_eq!(kept[0].requesters.len(), 1);
+        let kept_size = kept.estimate_size();
+        assert_eq!(drop_owned_parts_up_to(&mut owned_parts, key.height_created), kept_size);
+    }
+
+    #[test]
+    fn no_own_part_of_producer_outside_chunk_validators() {
+        let mut parts = produced_parts(&["test0", "test1", "test2"]);
+        assert!(take_own_parts(&mut parts, &"producer".parse().unwrap()).is_empty());
+        // All the parts still go to their owners.
+        assert_eq!(
+            parts.iter().map(|(_, partial_witness)| partial_witness.part_ord()).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn dropped_produced_parts_size_is_reported() {
+        let mut cache = LruCache::new(NonZeroUsize::new(10).unwrap());
+        let parts = produced_parts(&["test0", "test1", "test2"]);
+        let key = parts[0].1.chunk_production_key();
+        let parts: Vec<_> = parts.into_iter().map(|(_, part)| part).collect();
+        let parts_size = parts.estimate_size();
+        cache.put(key, parts);
+
+        assert_eq!(drop_produced_parts(&mut cache), parts_size);
+        assert!(cache.is_empty());
+        assert_eq!(drop_produced_parts(&mut cache), 0);
+    }
+
+    #[test]
+    fn owned_parts_above_height_are_kept() {
+        let signer = create_test_signer("producer");
+        let mut owned_parts = LruCache::new(NonZeroUsize::new(10).unwrap());
+        let mut dropped_size = 0;
+        for height in 10..15 {
+            let partial_witness = TestWitnessBuilder::new()
+                .height(height)
+                .encode_and_split(2, &signer)
+                .part(0)
+                .clone();
+            let key = partial_witness.chunk_production_key();
+            let parts =
+                vec![OwnedPart { partial_witness, requesters: HashSet::new(), rebroadcast: false }];
+            if height <= 12 {
+                dropped_size += parts.estimate_size();
+            }
+            owned_parts.put(key, parts);
+        }
+
+        assert_eq!(drop_owned_parts_up_to(&mut owned_parts, 12), dropped_size);
+        let mut kept_heights =
+            owned_parts.iter().map(|(key, _)| key.height_created).collect::<Vec<_>>();
+        kept_heights.sort();
+        assert_eq!(kept_heights, vec![13, 14]);
+    }
 }
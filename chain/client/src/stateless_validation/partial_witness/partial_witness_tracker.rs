@@ -1,29 +1,68 @@
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
 use std::sync::Arc;
 
 use lru::LruCache;
 use near_async::messaging::CanSend;
-use near_async::time::Instant;
+use near_async::time::{Clock, Duration, Instant, Utc};
 use near_chain::chain::ChunkStateWitnessMessage;
 use near_chain::Error;
+use near_chain_configs::PartialWitnessConfig;
 use near_epoch_manager::EpochManagerAdapter;
+use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
 use near_o11y::log_assert_fail;
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_o11y::metrics::int_label;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::estimate_size::{EstimateSize, SizeEstimator};
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness, WitnessSizeBand, WitnessSizeLimits,
+};
 use near_primitives::stateless_validation::state_witness::{
-    ChunkStateWitness, ChunkStateWitnessSize, EncodedChunkStateWitness,
+    BatchedChunkStateWitnessAckV2, ChunkStateWitness, ChunkStateWitnessAck, ChunkStateWitnessSize,
+    EncodedChunkStateWitness, HeldChunkStateWitnessAckV2, VersionedChunkStateWitnessAck,
+    WitnessDecodeMode,
 };
 use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::version::ProtocolFeature;
+use near_store::{DBCol, Store};
 use time::ext::InstantExt as _;
 
 use crate::client_actor::ClientSenderForPartialWitness;
 use crate::metrics;
+use crate::stateless_validation::validate::HeightWindowContext;
 
-use super::encoding::{WitnessEncoder, WitnessEncoderCache, WitnessPart};
+use super::ack_batcher::AckBatcher;
+use super::decode_queue::decode_with_bounded_parallelism;
+use super::decoded_witnesses::{
+    DecodedWitness, DecodedWitnesses, WitnessDecodeConflict, WitnessDecodePath,
+};
+use super::delivered_witnesses::DeliveredWitnesses;
+use super::encoding::{ReedSolomonBackend, WitnessEncoder, WitnessEncoderCache, WitnessPart};
+use super::epoch_witness_stats::{
+    save_epoch_witness_stats, EpochWitnessStatsCollector, VersionedEpochWitnessStats,
+};
+use super::head_timeline::HeadTimeline;
+use super::held_witnesses::{HeldWitness, HeldWitnesses};
+use super::lifecycle_tracker::{WitnessLifecycleTracker, WitnessOutcomeRecord};
+use super::link_loss::{LinkLossEstimate, LinkLossEstimator, LINK_LOSS_BUCKETS};
+use super::part_format::{PartFormat, PartialWitnessPart};
+use super::partial_witness_actor::ChunkStateWitnessOutcome;
+use super::prioritized_witnesses::PrioritizedWitnesses;
+use super::producer_health::{PartDelivery, ProducerDistributionHealth, ProducerHealthTracker};
+use super::spilled_parts::SpilledParts;
+use super::state_snapshot::IncompleteWitnessSnapshot;
+use super::stats_export::{WitnessStatsExporter, WitnessStatsRecord, WitnessStatsSource};
+use super::time_slice::TimeSlice;
+use super::witness_deadlines::WitnessDeadlines;
+use super::witness_parts_geometry;
 
 /// Max number of chunks to keep in the witness tracker cache. We reach here only after validation
 /// of the partial_witness so the LRU cache size need not be too large.
 /// This effectively limits memory usage to the size of the cache multiplied by
-/// MAX_COMPRESSED_STATE_WITNESS_SIZE, currently 40 * 48MiB = 1920MiB.
+/// max accepted size of the compressed witness, see `WitnessSizeLimits`, currently
+/// 40 * 60MiB = 2400MiB.
 const WITNESS_PARTS_CACHE_SIZE: usize = 40;
 
 /// Number of entries to keep in LRU cache of the processed state witnesses
@@ -31,67 +70,632 @@ const WITNESS_PARTS_CACHE_SIZE: usize = 40;
 /// so we don't have to worry much about memory usage here.
 const PROCESSED_WITNESSES_CACHE_SIZE: usize = 200;
 
+/// Maximum number of pending deadlines of the incomplete witnesses. Only the witnesses in the
+/// parts cache have a pending deadline, except for the evicted ones whose deadline is yet to pass.
+const MAX_PENDING_DEADLINES: usize = 2 * WITNESS_PARTS_CACHE_SIZE;
+
+/// Time within which the client is expected to confirm that it consumed the witness sent to it.
+/// The client only needs to put the witness into the validation queue, so not hearing back
+/// within this time means that the witness was lost on the way to the client.
+const WITNESS_CONSUMPTION_TIMEOUT: Duration = Duration::seconds(10);
+
+/// Number of witnesses for which we keep the evidence of conflicting parts. Each evidence holds
+/// two parts, so it is kept small.
+const CONFLICT_EVIDENCE_CACHE_SIZE: usize = 10;
+
+/// Maximum number of decode retries of a witness, each with a different part excluded, after
+/// the reconstruction from all the received parts failed, see `decode_witness`. Every retry is
+/// a full decode and decompression of the witness, so only a few of them are tried.
+const MAX_DECODE_RETRIES: usize = 8;
+
+/// Number of witnesses for which we keep the part identified as corrupted.
+const CORRUPTED_PARTS_CACHE_SIZE: usize = 100;
+
+/// Number of witnesses for which we keep the evidence of a decoded witness of another chunk.
+/// Each evidence holds a part, so it is kept small.
+const KEY_MISMATCH_EVIDENCE_CACHE_SIZE: usize = 10;
+
+/// Two parts of the same chunk with conflicting metadata, both signed by the chunk producer, which
+/// prove that the producer signed parts of two different witnesses for the chunk.
+#[derive(Debug, Clone)]
+pub struct WitnessConflictEvidence {
+    /// Part received first, whose metadata the parts received later were checked against.
+    pub reference_part: PartialEncodedStateWitness,
+    /// Part rejected because its metadata conflicts with `reference_part`.
+    pub conflicting_part: PartialEncodedStateWitness,
+}
+
+/// Part signed by the chunk producer for a chunk, whose witness decoded into the witness of
+/// another chunk, see `Error::DecodedWitnessKeyMismatch`.
+#[derive(Debug, Clone)]
+pub struct WitnessKeyMismatchEvidence {
+    /// Part received first for the chunk, signed by the chunk producer.
+    pub reference_part: PartialEncodedStateWitness,
+    /// Key declared by the decoded witness.
+    pub decoded_key: ChunkProductionKey,
+    /// Witness hash signed in the parts, which the decoded witness matched, if they carry one.
+    pub witness_hash: Option<CryptoHash>,
+}
+
+/// Message in which a part held in the cache entry reached us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartSource {
+    pub delivery: PartDelivery,
+    /// Peer which delivered the forward, unknown for the direct messages and the replayed ones.
+    pub from_peer: Option<PeerId>,
+}
+
+impl EstimateSize for PartSource {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        self.from_peer.add_heap_size(estimator);
+    }
+}
+
+impl PartSource {
+    /// Rank of the trust in the part, the parts with the lowest rank are suspected first when the
+    /// witness fails to reconstruct. A forward passed through more hands than the message sent
+    /// directly by the chunk producer, and a forward of an unknown peer can't even be attributed.
+    fn trust_rank(&self) -> u8 {
+        match (self.delivery, &self.from_peer) {
+            (PartDelivery::Forward, None) => 0,
+            (PartDelivery::Forward, Some(_)) => 1,
+            (PartDelivery::Direct, _) => 2,
+        }
+    }
+}
+
+/// Part whose exclusion made the witness reconstruct after the reconstruction from all the
+/// received parts failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptedWitnessPart {
+    pub part_ord: usize,
+    pub source: PartSource,
+    /// Number of decode retries it took to identify the part.
+    pub retries: usize,
+}
+
 struct CacheEntry {
     pub created_at: Instant,
     pub data_parts_present: usize,
     pub parts: Vec<WitnessPart>,
     pub encoder: Arc<WitnessEncoder>,
-    pub total_parts_size: usize,
+    /// The parts are collected for a pre-tracked shard, see `PartialWitnessConfig::pre_tracked_shards`.
+    pub pre_tracking: bool,
+    /// Ordinals of the parts spilled to `DBCol::PartialWitnessSpilledParts`, see
+    /// `PartialWitnessConfig::spill_to_disk`. These parts are not present in `parts`
+    /// and don't count towards the size of the entry until restored.
+    pub spilled_part_ords: Vec<usize>,
+    /// Number of parity parts among the parts fed to the decoder, set once we try to decode.
+    pub parity_parts_used: usize,
+    /// Ordinals of the parts fed to the decoder, set once we try to decode.
+    pub used_part_ords: Vec<usize>,
+    /// Witness hash signed by the chunk producer in the first part carrying one, all the other
+    /// parts carrying one must carry the same hash.
+    pub witness_hash: Option<CryptoHash>,
+    /// Length of the encoded witness carried by the parts, known once a part is inserted.
+    pub encoded_length: usize,
+    /// Merkle root signed by the chunk producer in the first V3 part received, all the other
+    /// parts must be committed to by the same root.
+    pub parts_root: Option<CryptoHash>,
+    /// First part received, no matter whether directly or in a forward. The metadata of all the
+    /// other parts must match it, see `metadata_conflict`, and on a mismatch it is kept with the
+    /// conflicting part as the evidence of the chunk producer signing both.
+    pub reference_part: Option<PartialEncodedStateWitness>,
+    /// Message in which each received part arrived, indexed by part_ord. Kept for the spilled
+    /// parts as well, so it also tells which parts were received rather than reconstructed.
+    pub part_sources: Vec<Option<PartSource>>,
+    /// Number of attempts to decode the witness. A failed attempt keeps the entry while parts
+    /// are missing, and the next attempt is made once another part arrives, see
+    /// `is_ready_to_decode`.
+    pub decode_attempts: usize,
+    /// Number of parts received at the last decode attempt.
+    pub parts_at_last_attempt: usize,
+    /// Number of decode retries with a part excluded, see `decode_witness`.
+    pub decode_retries: usize,
+    /// Part whose exclusion made the witness reconstruct, see `decode_witness`.
+    pub corrupted_part_ord: Option<usize>,
+    /// Tip whose height window admitted the parts. The witnesses admitted only by the alternative
+    /// tip are not decoded until the head admits them, see `reevaluate_height_windows`.
+    pub height_window: HeightWindowContext,
+    /// The missing parts were requested from their owners, see
+    /// `PartialWitnessConfig::missing_part_request_delay`.
+    pub parts_requested: bool,
 }
 
 impl CacheEntry {
-    pub fn new(encoder: Arc<WitnessEncoder>) -> Self {
+    pub fn new(
+        encoder: Arc<WitnessEncoder>,
+        pre_tracking: bool,
+        witness_hash: Option<CryptoHash>,
+        created_at: Instant,
+    ) -> Self {
         Self {
-            created_at: Instant::now(),
+            created_at,
             data_parts_present: 0,
             parts: vec![None; encoder.total_parts()],
-            total_parts_size: 0,
+            pre_tracking,
+            spilled_part_ords: vec![],
+            parity_parts_used: 0,
+            used_part_ords: vec![],
+            witness_hash,
+            encoded_length: 0,
+            parts_root: None,
+            reference_part: None,
+            part_sources: vec![None; encoder.total_parts()],
+            decode_attempts: 0,
+            parts_at_last_attempt: 0,
+            decode_retries: 0,
+            corrupted_part_ord: None,
+            height_window: HeightWindowContext::Head,
+            parts_requested: false,
             encoder,
         }
     }
 
+    fn is_spilled(&self) -> bool {
+        !self.spilled_part_ords.is_empty()
+    }
+
     fn data_parts_required(&self) -> usize {
         self.encoder.data_parts()
     }
 
-    // Function to insert a part into the cache entry for the chunk hash. Additionally, it tries to
-    // decode and return the state witness if all parts are present.
-    pub fn insert_in_cache_entry(
+    /// Whether there are enough parts to decode the witness, and if an attempt already failed,
+    /// whether another part arrived since then.
+    fn is_ready_to_decode(&self) -> bool {
+        self.data_parts_present >= self.data_parts_required()
+            && self.data_parts_present > self.parts_at_last_attempt
+    }
+
+    /// Whether a failed decode can be retried, i.e. some parts may still arrive. The parts
+    /// spilled to the database are missing only if restoring them failed.
+    fn can_retry_decode(&self) -> bool {
+        !self.is_spilled() && self.part_sources.iter().any(Option::is_none)
+    }
+
+    /// Drops the parts reconstructed by the decoder, keeping the received ones.
+    fn drop_reconstructed_parts(&mut self) {
+        for (part, source) in self.parts.iter_mut().zip(&self.part_sources) {
+            if source.is_none() {
+                *part = None;
+            }
+        }
+    }
+
+    /// Describes how the metadata of the part conflicts with the parts received before, if it
+    /// does. The parts are signed by the chunk producer, so a conflict means that the producer
+    /// signed parts of two different witnesses for the same chunk.
+    fn metadata_conflict(&self, partial_witness: &PartialEncodedStateWitness) -> Option<String> {
+        let part = PartialWitnessPart::from(partial_witness);
+        // The metadata of the parts received before, taken from the first part carrying each
+        // field, as the parts may be in different formats, see `part_format`.
+        let received = PartialWitnessPart {
+            witness_hash: self.witness_hash.as_ref(),
+            parts_root: self.parts_root.as_ref(),
+            encoded_length: self
+                .reference_part
+                .as_ref()
+                .map_or(part.encoded_length, |reference_part| reference_part.encoded_length()),
+            ..part
+        };
+        part.metadata_conflict(&received)
+    }
+
+    /// Whether we hold a different part with the same part_ord, which means that the chunk
+    /// producer signed two conflicting parts. The spilled parts are not compared.
+    fn has_conflicting_part(&self, partial_witness: &PartialEncodedStateWitness) -> bool {
+        self.parts[partial_witness.part_ord()]
+            .as_deref()
+            .is_some_and(|part| part != partial_witness.part())
+    }
+
+    /// Ordinals of the parts not received yet, neither held in memory nor spilled.
+    fn missing_part_ords(&self) -> Vec<usize> {
+        (0..self.parts.len())
+            .filter(|part_ord| {
+                self.parts[*part_ord].is_none() && !self.spilled_part_ords.contains(part_ord)
+            })
+            .collect()
+    }
+
+    // Function to insert a part into the cache entry for the chunk hash. Returns whether the
+    // state witness is ready to decode, see `is_ready_to_decode`.
+    pub fn insert_part(
         &mut self,
         partial_witness: PartialEncodedStateWitness,
-    ) -> Option<std::io::Result<EncodedChunkStateWitness>> {
+        source: PartSource,
+    ) -> bool {
         let ChunkProductionKey { shard_id, height_created, .. } =
             partial_witness.chunk_production_key();
         let (part_ord, part, encoded_length) = partial_witness.decompose();
 
         // Check if the part is already present.
-        if self.parts[part_ord].is_some() {
+        if self.parts[part_ord].is_some() || self.spilled_part_ords.contains(&part_ord) {
             log_assert_fail!("Received duplicate or redundant partial state witness part. shard_id={shard_id:?}, height_created={height_created:?}, part_ord={part_ord:?}");
-            return None;
+            return false;
         }
 
         // Increment the count of data parts present even if the part has been decoded before.
         // We use this in metrics to track the number of parts received. Insert the part into the cache entry.
         self.data_parts_present += 1;
-        self.total_parts_size += part.len();
-        self.parts[part_ord] = Some(part);
+        // The decode reconstructs the missing parts in place, so the entry keeps its own copy of
+        // the part rather than sharing it with the caches of the actor.
+        self.parts[part_ord] = Some(Box::from(&*part));
+        self.part_sources[part_ord] = Some(source);
+        self.encoded_length = encoded_length;
+
+        self.is_ready_to_decode()
+    }
+
+    // Function to decode the state witness once enough parts are present, see `insert_part`.
+    // If some of the parts were spilled to the database, they are restored before decoding.
+    // The received part `excluded_part_ord`, if any, is left out of the decode.
+    pub fn decode(
+        &mut self,
+        spilled_parts: &SpilledParts,
+        key: &ChunkProductionKey,
+        excluded_part_ord: Option<usize>,
+    ) -> std::io::Result<EncodedChunkStateWitness> {
+        if self.is_spilled() {
+            self.restore(spilled_parts, key)?;
+        }
+        // The decoder fills in the missing parts, so the parts reconstructed by a previous
+        // decode are dropped to decode from the received parts only.
+        self.drop_reconstructed_parts();
+        let excluded_part = excluded_part_ord.and_then(|part_ord| self.parts[part_ord].take());
+        let total_parts = self.parts.len();
+        self.used_part_ords = self
+            .parts
+            .iter()
+            .enumerate()
+            .filter_map(|(part_ord, part)| part.as_ref().map(|_| part_ord))
+            .collect();
+        self.parity_parts_used = self
+            .used_part_ords
+            .iter()
+            .filter(|part_ord| witness_parts_geometry::is_parity_part(**part_ord, total_parts))
+            .count();
+        let result = self.encoder.decode(&mut self.parts, self.encoded_length);
+        if let (Some(part_ord), Some(part)) = (excluded_part_ord, excluded_part) {
+            self.parts[part_ord] = Some(part);
+        }
+        result
+    }
+
+    /// Received parts which can be excluded from the decode, the least trusted first, see
+    /// `PartSource::trust_rank`. Empty if there are no more parts than the decode requires.
+    fn suspect_part_ords(&self) -> Vec<usize> {
+        let mut suspects: Vec<(u8, usize)> = self
+            .part_sources
+            .iter()
+            .enumerate()
+            .filter_map(|(part_ord, source)| {
+                source.as_ref().map(|source| (source.trust_rank(), part_ord))
+            })
+            .collect();
+        if suspects.len() <= self.data_parts_required() {
+            return vec![];
+        }
+        suspects.sort();
+        suspects.into_iter().map(|(_, part_ord)| part_ord).collect()
+    }
 
-        // If we have enough parts, try to decode the state witness.
-        if self.data_parts_present < self.data_parts_required() {
+    /// Hands the parts held in memory over to be written to the database, keeping only the
+    /// metadata in memory. Returns the number of bytes freed, None if too many spills wait to be
+    /// written, see `SpilledParts::spill`.
+    fn spill(&mut self, spilled_parts: &SpilledParts, key: &ChunkProductionKey) -> Option<usize> {
+        let size_before = self.estimate_size();
+        let parts: Vec<(usize, Box<[u8]>)> = self
+            .parts
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(part_ord, part)| Some((part_ord, part.take()?)))
+            .collect();
+        let part_ords: Vec<usize> = parts.iter().map(|(part_ord, _)| *part_ord).collect();
+        if let Err(parts) = spilled_parts.spill(key, parts) {
+            for (part_ord, part) in parts {
+                self.parts[part_ord] = Some(part);
+            }
             return None;
         }
+        self.spilled_part_ords.extend(part_ords);
+        Some(size_before - self.estimate_size())
+    }
+
+    /// Loads the spilled parts back into memory and removes them from the database.
+    fn restore(
+        &mut self,
+        spilled_parts: &SpilledParts,
+        key: &ChunkProductionKey,
+    ) -> std::io::Result<()> {
+        for (part_ord, part) in spilled_parts.restore(key)? {
+            self.parts[part_ord] = Some(part);
+        }
+        self.spilled_part_ords.clear();
+        metrics::PARTIAL_WITNESS_RESTORED_ENTRIES.inc();
+        Ok(())
+    }
+}
+
+/// The parts held in memory, including the parts reconstructed by the decode, and the metadata
+/// kept per part. The encoder is shared by all the entries with the same number of parts and
+/// outlives them, so it isn't counted.
+impl EstimateSize for CacheEntry {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        self.parts.add_heap_size(estimator);
+        self.part_sources.add_heap_size(estimator);
+        self.spilled_part_ords.add_heap_size(estimator);
+        self.used_part_ords.add_heap_size(estimator);
+        self.reference_part.add_heap_size(estimator);
+    }
+}
+
+/// Outcome of the decode of a witness with enough parts, see `decode_witness`.
+enum DecodeOutcome {
+    /// The witness reached its deadline while waiting to be decoded, so it wasn't decoded.
+    PastDeadline,
+    /// Restoring the spilled parts or the Reed Solomon decoding failed.
+    ReedSolomonFailure(std::io::Error),
+    /// Result of the decompression and of the checks of the decoded witness.
+    Decoded(Result<(ChunkStateWitness, ChunkStateWitnessSize, CryptoHash), Error>),
+}
+
+impl DecodeOutcome {
+    /// Whether the decode failed in a way other parts may fix, i.e. a part may be corrupted or
+    /// missing. A witness declaring another chunk than the key decompressed into a valid witness,
+    /// so it is what the chunk producer encoded and other parts wouldn't change it.
+    fn is_retriable_failure(&self) -> bool {
+        match self {
+            Self::PastDeadline => false,
+            Self::ReedSolomonFailure(_) => true,
+            Self::Decoded(Err(Error::DecodedWitnessKeyMismatch { .. })) => false,
+            Self::Decoded(result) => result.is_err(),
+        }
+    }
+}
+
+/// Reconstructs the witness from the parts of the entry and decompresses it. Runs on the threads
+/// of `decode_with_bounded_parallelism`, so only the entry is modified and the deadline is
+/// checked once the decode starts, as the witness may have waited for a free thread.
+fn decode_witness(
+    key: &ChunkProductionKey,
+    entry: &mut CacheEntry,
+    spilled_parts: &SpilledParts,
+    clock: &Clock,
+    deadline: Duration,
+    decode_mode: WitnessDecodeMode,
+) -> DecodeOutcome {
+    if clock.now().signed_duration_since(entry.created_at) >= deadline {
+        return DecodeOutcome::PastDeadline;
+    }
+    entry.decode_attempts += 1;
+    entry.parts_at_last_attempt = entry.data_parts_present;
+    let outcome = match entry.decode(spilled_parts, key, None) {
+        Ok(encoded_witness) => {
+            let expected_hash = entry.witness_hash.map(|witness_hash| ExpectedWitnessHash {
+                witness_hash,
+                used_part_ords: std::mem::take(&mut entry.used_part_ords),
+            });
+            DecodeOutcome::Decoded(decode_state_witness(
+                key,
+                &encoded_witness,
+                expected_hash,
+                decode_mode,
+            ))
+        }
+        // Restoring the spilled parts failed, so there are no parts to retry the decode with.
+        Err(err) if entry.is_spilled() => return DecodeOutcome::ReedSolomonFailure(err),
+        Err(err) => DecodeOutcome::ReedSolomonFailure(err),
+    };
+    if !outcome.is_retriable_failure() {
+        return outcome;
+    }
+    // Reed Solomon assumes that the parts it gets are intact, so a single corrupted part breaks
+    // the reconstruction even with plenty of parts. With more parts than needed, the witness can
+    // still be reconstructed without the corrupted part, which also identifies it.
+    for part_ord in entry.suspect_part_ords().into_iter().take(MAX_DECODE_RETRIES) {
+        entry.decode_retries += 1;
+        let decoded = decode_without_part(key, entry, spilled_parts, part_ord, decode_mode);
+        if let Some(decoded) = decoded {
+            entry.corrupted_part_ord = Some(part_ord);
+            return DecodeOutcome::Decoded(Ok(decoded));
+        }
+    }
+    outcome
+}
+
+/// Decodes the witness with the part `part_ord` excluded, returns the witness only if it passes
+/// all the checks of `decode_state_witness`. The failures are expected while looking for the
+/// corrupted part, so they are not reported.
+fn decode_without_part(
+    key: &ChunkProductionKey,
+    entry: &mut CacheEntry,
+    spilled_parts: &SpilledParts,
+    part_ord: usize,
+    decode_mode: WitnessDecodeMode,
+) -> Option<(ChunkStateWitness, ChunkStateWitnessSize, CryptoHash)> {
+    let encoded_witness = entry.decode(spilled_parts, key, Some(part_ord)).ok()?;
+    let decoded = decode_state_witness(key, &encoded_witness, None, decode_mode).ok()?;
+    if entry.witness_hash.is_some_and(|witness_hash| decoded.2 != witness_hash) {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// Decompresses the witness and checks that it is the witness of the chunk it was sent for.
+/// Returns the witness along with its raw size and its hash, computed from the decompressed bytes.
+fn decode_state_witness(
+    key: &ChunkProductionKey,
+    encoded_witness: &EncodedChunkStateWitness,
+    expected_hash: Option<ExpectedWitnessHash>,
+    decode_mode: WitnessDecodeMode,
+) -> Result<(ChunkStateWitness, ChunkStateWitnessSize, CryptoHash), Error> {
+    let decode_start = std::time::Instant::now();
+    let (witness, raw_witness_size, witness_hash) = encoded_witness.decode_and_hash(decode_mode)?;
+    let decode_elapsed_seconds = decode_start.elapsed().as_secs_f64();
+
+    // The hash is checked first, so that a witness reconstructed from a corrupted part fails the
+    // hash check and is retried, while a key mismatch of a witness matching the signed hash can
+    // only come from the chunk producer.
+    if let Some(expected_hash) = expected_hash {
+        check_witness_hash(key, &witness_hash, &expected_hash)?;
+    }
+    check_decoded_witness_key(key, &witness)?;
+
+    // Record metrics after validating the witness, the shard of the witness is the one of the key.
+    near_chain::stateless_validation::metrics::CHUNK_STATE_WITNESS_DECODE_TIME
+        .with_label_values(&[&int_label(key.shard_id)])
+        .observe(decode_elapsed_seconds);
+    Ok((witness, raw_witness_size, witness_hash))
+}
+
+/// Checks that the decoded witness declares the epoch, shard and height the chunk producer signed
+/// the parts for. A witness of another chunk must not reach the client under the key of the parts.
+fn check_decoded_witness_key(
+    key: &ChunkProductionKey,
+    witness: &ChunkStateWitness,
+) -> Result<(), Error> {
+    let actual = witness.chunk_production_key();
+    if &actual != key {
+        return Err(Error::DecodedWitnessKeyMismatch { expected: key.clone(), actual });
+    }
+    Ok(())
+}
+
+/// Witness hash signed by the chunk producer in the parts, together with the ordinals of the parts
+/// the witness was reconstructed from, see `ProtocolFeature::WitnessChecksum`.
+struct ExpectedWitnessHash {
+    witness_hash: CryptoHash,
+    used_part_ords: Vec<usize>,
+}
+
+/// Checks the hash of the decompressed bytes of the reconstructed witness against the hash signed
+/// by the chunk producer.
+fn check_witness_hash(
+    key: &ChunkProductionKey,
+    witness_hash: &CryptoHash,
+    expected_hash: &ExpectedWitnessHash,
+) -> Result<(), Error> {
+    if witness_hash == &expected_hash.witness_hash {
+        return Ok(());
+    }
+    metrics::PARTIAL_WITNESS_CHECKSUM_MISMATCHES
+        .with_label_values(&[&int_label(key.shard_id)])
+        .inc();
+    // All the parts are signed by the chunk producer with the same hash, so either the producer
+    // signed a hash of a different witness or the reconstruction went wrong locally. The used
+    // parts tell whether other validators reconstructing from different parts are affected.
+    tracing::error!(
+        target: "client",
+        shard_id = key.shard_id,
+        height_created = key.height_created,
+        expected = ?expected_hash.witness_hash,
+        actual = ?witness_hash,
+        used_part_ords = ?expected_hash.used_part_ords,
+        "Reconstructed witness doesn't match the witness hash signed by the chunk producer"
+    );
+    Err(Error::InvalidPartialChunkStateWitness(format!(
+        "Reconstructed witness hash {} doesn't match the signed witness hash {}",
+        witness_hash, expected_hash.witness_hash
+    )))
+}
+
+/// Drops the least recently used entries until the total size of the entries held in memory is at
+/// most `budget`. Returns the size of the entries dropped from memory, the parts of the dropped
+/// entries spilled to the database are deleted as well.
+fn drop_oldest_entries(
+    parts_cache: &mut LruCache<ChunkProductionKey, CacheEntry>,
+    spilled_parts: &SpilledParts,
+    budget: usize,
+) -> usize {
+    let mut total_size = parts_cache_size(parts_cache);
+    let mut freed = 0;
+    while total_size > budget {
+        let Some((key, entry)) = parts_cache.pop_lru() else {
+            break;
+        };
+        let entry_size = entry.estimate_size();
+        total_size = total_size.saturating_sub(entry_size);
+        freed += entry_size;
+        if entry.is_spilled() {
+            spilled_parts.delete(&key);
+        }
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            freed = entry_size,
+            "Dropped incomplete witness under memory pressure"
+        );
+    }
+    freed
+}
+
+/// Memory retained by the entries of the cache, the parts shared between the entries counted once.
+fn parts_cache_size(parts_cache: &LruCache<ChunkProductionKey, CacheEntry>) -> usize {
+    let mut estimator = SizeEstimator::new();
+    for (_, entry) in parts_cache.iter() {
+        estimator.add(entry);
+    }
+    estimator.total()
+}
 
-        let decode_result = self.encoder.decode(&mut self.parts, encoded_length);
-        Some(decode_result)
+/// Reports the witness which had enough parts, but reached its deadline before it was decoded.
+/// Records the number of attempts it took to decode the witness from the parts.
+fn record_decode_attempts(key: &ChunkProductionKey, decode_attempts: usize) {
+    let shard_id_label = int_label(key.shard_id);
+    metrics::PARTIAL_WITNESS_DECODE_ATTEMPTS
+        .with_label_values(&[&shard_id_label])
+        .observe(decode_attempts as f64);
+    if decode_attempts > 1 {
+        metrics::PARTIAL_WITNESS_DECODED_AFTER_RETRY.with_label_values(&[&shard_id_label]).inc();
     }
 }
 
+fn report_skipped_decode(key: &ChunkProductionKey) {
+    metrics::PARTIAL_WITNESS_DECODES_PAST_DEADLINE
+        .with_label_values(&[&int_label(key.shard_id)])
+        .inc();
+    tracing::debug!(
+        target: "client",
+        shard_id = key.shard_id,
+        height_created = key.height_created,
+        "Dropped witness ready to decode after its deadline"
+    );
+}
+
+fn report_skipped_redelivery(key: &ChunkProductionKey) {
+    metrics::PARTIAL_WITNESS_REDELIVERIES_SKIPPED.inc();
+    tracing::debug!(
+        target: "client",
+        shard_id = key.shard_id,
+        height_created = key.height_created,
+        "Not sending the witness delivered to the client before the restart"
+    );
+}
+
+fn report_unconsumed_witness(key: &ChunkProductionKey, waited: Duration) {
+    metrics::PARTIAL_WITNESS_UNCONSUMED_WITNESSES
+        .with_label_values(&[&int_label(key.shard_id)])
+        .inc();
+    tracing::error!(
+        target: "client",
+        shard_id = key.shard_id,
+        height_created = key.height_created,
+        ?waited,
+        "Witness sent to the client was never consumed, it will not be validated"
+    );
+}
+
 /// Track the Reed Solomon erasure encoded parts of the `EncodedChunkStateWitness`. These are created
 /// by the chunk producer and distributed to validators. Note that we do not need all the parts of to
 /// recreate the full state witness.
 pub struct PartialEncodedStateWitnessTracker {
+    clock: Clock,
     /// Sender to send the encoded state witness to the client actor.
     client_sender: ClientSenderForPartialWitness,
+    /// Adapter to send the acks for the reconstructed witnesses to the chunk producers.
+    network_adapter: PeerManagerAdapter,
     /// Epoch manager to get the set of chunk validators
     epoch_manager: Arc<dyn EpochManagerAdapter>,
     /// Keeps track of state witness parts received from chunk producers.
@@ -100,144 +704,2137 @@ pub struct PartialEncodedStateWitnessTracker {
     /// to protect chunk validator from processing the same witness multiple
     /// times.
     processed_witnesses: LruCache<ChunkProductionKey, ()>,
+    /// First successful decode of the processed witnesses, from the full witness or from the
+    /// parts, see `DecodedWitnesses`.
+    decoded_witnesses: DecodedWitnesses,
+    /// Witnesses sent to the client, by this or a previous instance of the actor, see
+    /// `DeliveredWitnesses`.
+    delivered_witnesses: DeliveredWitnesses,
+    /// Decoded witnesses held back while the client has no credits left, see `HeldWitnesses`.
+    held_witnesses: HeldWitnesses,
+    /// Witnesses for which we already sent the ack to the chunk producer. We send exactly one
+    /// ack per witness, no matter how many times the witness is reconstructed.
+    acked_witnesses: LruCache<ChunkProductionKey, ()>,
+    /// Acks held to be sent together, see `PartialWitnessConfig::ack_batching_delay`.
+    ack_batcher: AckBatcher,
     /// Reed Solomon encoder for decoding state witness parts.
     encoders: WitnessEncoderCache,
+    /// Parts of the incomplete witnesses spilled to the store under memory pressure.
+    spilled_parts: SpilledParts,
+    /// Store of the node, to check for the blocks and to save the epoch witness stats.
+    store: Store,
+    /// Configuration of the partial witness distribution.
+    config: PartialWitnessConfig,
+    /// Witnesses sent to the client, until the client reports their outcome.
+    lifecycle_tracker: WitnessLifecycleTracker,
+    /// Deadlines of the incomplete witnesses in the parts cache.
+    deadlines: WitnessDeadlines,
+    /// Witnesses abandoned because they were not decoded before their deadline. The parts
+    /// arriving for them later are dropped.
+    expired_witnesses: LruCache<ChunkProductionKey, ()>,
+    /// Witness distribution health of the chunk producers.
+    producer_health: ProducerHealthTracker,
+    /// Witness distribution statistics of the recent epochs, persisted once the head leaves the
+    /// epoch, see `EpochWitnessStatsCollector`.
+    epoch_witness_stats: EpochWitnessStatsCollector,
+    /// Production times of the blocks around the head, used to tell how late the witnesses are.
+    head_timeline: HeadTimeline,
+    /// Witnesses in the parts cache with enough parts to be decoded, see `decode_ready_witnesses`.
+    ready_witnesses: HashSet<ChunkProductionKey>,
+    /// Witnesses the client is blocked on, decoded before the others.
+    prioritized_witnesses: PrioritizedWitnesses,
+    /// Message in which our own part of the witness arrived first, see
+    /// `record_owned_part_delivery`.
+    owned_part_deliveries: LruCache<ChunkProductionKey, PartDelivery>,
+    /// Peers which delivered the forwarded parts, see `record_forwarded_part`.
+    link_loss: LinkLossEstimator,
+    /// First evidence of conflicting parts per witness, see `record_conflict_evidence`.
+    conflict_evidence: LruCache<ChunkProductionKey, WitnessConflictEvidence>,
+    /// Part identified as corrupted per witness, see `record_corrupted_part`.
+    corrupted_parts: LruCache<ChunkProductionKey, CorruptedWitnessPart>,
+    /// Witnesses decoded into the witness of another chunk, see `record_key_mismatch`.
+    key_mismatches: LruCache<ChunkProductionKey, WitnessKeyMismatchEvidence>,
+    /// Highest height of the witnesses sent to the client as a chunk validator, per shard.
+    completed_heights: HashMap<ShardId, BlockHeight>,
+    /// Exports the sizes of the reconstructed witnesses, see
+    /// `PartialWitnessConfig::export_witness_stats_dir`.
+    stats_exporter: Option<WitnessStatsExporter>,
+    /// Makes the next stored part panic, to test the handling of the panics in the actor.
+    #[cfg(test)]
+    panic_on_next_part: bool,
+    /// Advances the fake clock by this much per decoded witness, to test the time slicing.
+    #[cfg(test)]
+    slow_decodes: Option<(near_async::time::FakeClock, Duration)>,
 }
 
 impl PartialEncodedStateWitnessTracker {
     pub fn new(
+        clock: Clock,
         client_sender: ClientSenderForPartialWitness,
+        network_adapter: PeerManagerAdapter,
         epoch_manager: Arc<dyn EpochManagerAdapter>,
+        store: Store,
+        config: PartialWitnessConfig,
+        reed_solomon_backend: ReedSolomonBackend,
+        stats_exporter: Option<WitnessStatsExporter>,
     ) -> Self {
+        let deadlines =
+            WitnessDeadlines::new(config.incomplete_witness_deadline, MAX_PENDING_DEADLINES);
+        let producer_health = ProducerHealthTracker::new(config.high_send_skew_threshold);
         Self {
+            clock: clock.clone(),
             client_sender,
+            network_adapter,
             epoch_manager,
             parts_cache: LruCache::new(NonZeroUsize::new(WITNESS_PARTS_CACHE_SIZE).unwrap()),
             processed_witnesses: LruCache::new(
                 NonZeroUsize::new(PROCESSED_WITNESSES_CACHE_SIZE).unwrap(),
             ),
-            encoders: WitnessEncoderCache::new(),
+            decoded_witnesses: DecodedWitnesses::new(PROCESSED_WITNESSES_CACHE_SIZE),
+            delivered_witnesses: DeliveredWitnesses::new(),
+            held_witnesses: HeldWitnesses::new(config.client_witness_credits),
+            acked_witnesses: LruCache::new(
+                NonZeroUsize::new(PROCESSED_WITNESSES_CACHE_SIZE).unwrap(),
+            ),
+            ack_batcher: AckBatcher::new(config.ack_batching_delay),
+            encoders: WitnessEncoderCache::new(reed_solomon_backend),
+            spilled_parts: SpilledParts::spawn(store.clone()),
+            store,
+            config,
+            lifecycle_tracker: WitnessLifecycleTracker::new(clock),
+            deadlines,
+            expired_witnesses: LruCache::new(
+                NonZeroUsize::new(PROCESSED_WITNESSES_CACHE_SIZE).unwrap(),
+            ),
+            producer_health,
+            epoch_witness_stats: EpochWitnessStatsCollector::new(),
+            head_timeline: HeadTimeline::new(),
+            ready_witnesses: HashSet::new(),
+            prioritized_witnesses: PrioritizedWitnesses::new(),
+            owned_part_deliveries: LruCache::new(
+                NonZeroUsize::new(PROCESSED_WITNESSES_CACHE_SIZE).unwrap(),
+            ),
+            link_loss: LinkLossEstimator::new(),
+            conflict_evidence: LruCache::new(
+                NonZeroUsize::new(CONFLICT_EVIDENCE_CACHE_SIZE).unwrap(),
+            ),
+            corrupted_parts: LruCache::new(NonZeroUsize::new(CORRUPTED_PARTS_CACHE_SIZE).unwrap()),
+            key_mismatches: LruCache::new(
+                NonZeroUsize::new(KEY_MISMATCH_EVIDENCE_CACHE_SIZE).unwrap(),
+            ),
+            completed_heights: HashMap::new(),
+            stats_exporter,
+            #[cfg(test)]
+            panic_on_next_part: false,
+            #[cfg(test)]
+            slow_decodes: None,
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn panic_on_next_part(&mut self) {
+        self.panic_on_next_part = true;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn slow_down_decodes(
+        &mut self,
+        clock: near_async::time::FakeClock,
+        decode_time: Duration,
+    ) {
+        self.slow_decodes = Some((clock, decode_time));
+    }
+
+    /// Stores the validated part which reached us in `delivery`, admitted by the height window
+    /// of `height_window`, forwarded by `from_peer` if known. `my_account_id` is the account of
+    /// our validator signer, which tells the parts we own.
     pub fn store_partial_encoded_state_witness(
         &mut self,
         partial_witness: PartialEncodedStateWitness,
+        pre_tracking: bool,
+        delivery: PartDelivery,
+        height_window: HeightWindowContext,
+        from_peer: Option<&PeerId>,
+        my_account_id: &AccountId,
     ) -> Result<(), Error> {
-        tracing::debug!(target: "client", ?partial_witness, "store_partial_encoded_state_witness");
-
         let key = partial_witness.chunk_production_key();
+        let _span = tracing::debug_span!(
+            target: "client",
+            "store_partial_encoded_state_witness",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            part_ord = partial_witness.part_ord(),
+        )
+        .entered();
+        #[cfg(test)]
+        if std::mem::take(&mut self.panic_on_next_part) {
+            panic!("injected tracker panic");
+        }
+        // Recorded even for the processed witnesses, our part may arrive after enough other
+        // parts did.
+        if !pre_tracking && self.part_owner(&partial_witness).as_ref() == Some(my_account_id) {
+            self.record_owned_part_delivery(&key, delivery);
+        }
+
         if self.processed_witnesses.contains(&key) {
             tracing::debug!(
                 target: "client",
-                ?partial_witness,
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                part_ord = partial_witness.part_ord(),
                 "Received redundant part for already processed witness"
             );
+            let decoded_path = self.decoded_witnesses.get(&key).map(|decoded| decoded.path);
+            if decoded_path != Some(WitnessDecodePath::FullWitness) {
+                return Ok(());
+            }
+            // The parts of a witness decoded from the full witness must carry the same witness.
+            return match partial_witness.witness_hash() {
+                Some(witness_hash) => self.decoded_witnesses.check_redundant(
+                    &key,
+                    WitnessDecodePath::Parts,
+                    Some(*witness_hash),
+                ),
+                None => {
+                    self.decoded_witnesses.mark_redundant(&key, WitnessDecodePath::Parts);
+                    Ok(())
+                }
+            };
+        }
+        if self.delivered_witnesses.contains(&key) {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                part_ord = partial_witness.part_ord(),
+                "Received part of a witness delivered to the client before the restart"
+            );
+            self.processed_witnesses.push(key, ());
             return Ok(());
         }
 
-        self.maybe_insert_new_entry_in_parts_cache(&partial_witness)?;
+        let is_new_entry = !self.parts_cache.contains(&key);
+        self.maybe_insert_new_entry_in_parts_cache(&partial_witness, pre_tracking)?;
+        if is_new_entry {
+            self.parts_cache.peek_mut(&key).unwrap().height_window = height_window;
+        } else if height_window == HeightWindowContext::Head {
+            self.admit_by_head(&key);
+        }
         let entry = self.parts_cache.get_mut(&key).unwrap();
+        if let Some(conflict) = entry.metadata_conflict(&partial_witness) {
+            tracing::warn!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                part_ord = partial_witness.part_ord(),
+                conflict,
+                "Witness part metadata conflicts with the previous parts"
+            );
+            if let Some(reference_part) = entry.reference_part.clone() {
+                self.record_conflict_evidence(&key, reference_part, partial_witness);
+            }
+            return Err(Error::InvalidPartialChunkStateWitness(conflict));
+        }
+        if entry.witness_hash.is_none() {
+            entry.witness_hash = partial_witness.witness_hash().copied();
+        }
+        if entry.parts_root.is_none() {
+            entry.parts_root = partial_witness.parts_root().copied();
+        }
+        if entry.reference_part.is_none() {
+            entry.reference_part = Some(partial_witness.clone());
+        }
+        if entry.has_conflicting_part(&partial_witness) {
+            tracing::warn!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                part_ord = partial_witness.part_ord(),
+                "Witness part conflicts with the part received before with the same part_ord"
+            );
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Part with part_ord {} conflicts with the part received before",
+                partial_witness.part_ord()
+            )));
+        }
 
-        if let Some(decode_result) = entry.insert_in_cache_entry(partial_witness) {
-            // Record the time taken from receiving first part to decoding partial witness.
-            let time_to_last_part = Instant::now().signed_duration_since(entry.created_at);
-            metrics::PARTIAL_WITNESS_TIME_TO_LAST_PART
-                .with_label_values(&[key.shard_id.to_string().as_str()])
-                .observe(time_to_last_part.as_seconds_f64());
+        let source = PartSource { delivery, from_peer: from_peer.cloned() };
+        if entry.insert_part(partial_witness, source) {
+            // The witness waits for the head to admit it, see `reevaluate_height_windows`.
+            if entry.height_window == HeightWindowContext::Head
+                && self.ready_witnesses.insert(key.clone())
+                && entry.decode_attempts == 0
+            {
+                // Record the time taken from receiving first part to having enough parts to decode.
+                let time_to_last_part = self.clock.now().signed_duration_since(entry.created_at);
+                metrics::PARTIAL_WITNESS_TIME_TO_LAST_PART
+                    .with_label_values(&[&int_label(key.shard_id)])
+                    .observe(time_to_last_part.as_seconds_f64());
+            }
+        } else if self.config.spill_to_disk {
+            self.maybe_spill_parts(&key);
+        }
+        self.record_total_parts_cache_size_metric();
+        Ok(())
+    }
 
-            self.parts_cache.pop(&key);
-            self.processed_witnesses.push(key.clone(), ());
+    /// Whether `store_partial_encoded_state_witness` would reject the part as conflicting with
+    /// the parts received before it, or with the witness decoded from the full witness. Doesn't
+    /// change anything, so that our part can be checked before it is forwarded and stored.
+    pub fn conflicts_with_received_parts(
+        &self,
+        partial_witness: &PartialEncodedStateWitness,
+    ) -> bool {
+        let key = partial_witness.chunk_production_key();
+        if self.processed_witnesses.contains(&key) {
+            return self.decoded_witnesses.get(&key).is_some_and(|decoded| {
+                decoded.path == WitnessDecodePath::FullWitness
+                    && partial_witness
+                        .witness_hash()
+                        .is_some_and(|witness_hash| *witness_hash != decoded.witness_hash)
+            });
+        }
+        self.parts_cache.peek(&key).is_some_and(|entry| {
+            entry.metadata_conflict(partial_witness).is_some()
+                || entry.has_conflicting_part(partial_witness)
+        })
+    }
 
-            let encoded_witness = match decode_result {
-                Ok(encoded_chunk_state_witness) => encoded_chunk_state_witness,
-                Err(err) => {
-                    // We ideally never expect the decoding to fail. In case it does, we received a bad part
-                    // from the chunk producer.
-                    tracing::error!(
-                        target: "client",
-                        ?err,
-                        shard_id = key.shard_id,
-                        height_created = key.height_created,
-                        "Failed to reed solomon decode witness parts. Maybe malicious or corrupt data."
+    /// Whether we already hold the exact same part, or already decoded the witness from the parts.
+    /// Such a part changes nothing and can be dropped before any validation. The check doesn't
+    /// allocate, it runs for every forwarded part and the duplicates make up most of them on a
+    /// busy validator. A different part with the same part_ord is not a duplicate, it goes through
+    /// the validation and is reported as a conflict, see `store_partial_encoded_state_witness`.
+    pub fn is_duplicate_part(
+        &self,
+        key: &ChunkProductionKey,
+        part_ord: usize,
+        part: &[u8],
+    ) -> bool {
+        if self.processed_witnesses.contains(key) {
+            // The parts of a witness decoded from the full witness are still checked against it.
+            return self
+                .decoded_witnesses
+                .get(key)
+                .is_some_and(|decoded| decoded.path == WitnessDecodePath::Parts);
+        }
+        self.parts_cache.peek(key).is_some_and(|entry| {
+            entry.parts.get(part_ord).is_some_and(|stored| stored.as_deref() == Some(part))
+        })
+    }
+
+    /// Whether some witnesses have enough parts and wait for `decode_ready_witnesses`.
+    pub fn has_ready_witnesses(&self) -> bool {
+        !self.ready_witnesses.is_empty()
+    }
+
+    /// Prioritizes the witness the client is blocked on, see `PrioritizedWitnesses`.
+    pub fn prioritize_witness(&mut self, key: ChunkProductionKey) {
+        self.prioritized_witnesses.prioritize(key, self.clock.now());
+    }
+
+    pub fn is_prioritized(&self, key: &ChunkProductionKey) -> bool {
+        self.prioritized_witnesses.is_prioritized(key, self.clock.now())
+    }
+
+    /// Whether a prioritized witness has enough parts and waits for `decode_ready_witnesses`.
+    pub fn has_prioritized_ready_witness(&self) -> bool {
+        self.ready_witnesses.iter().any(|key| self.is_prioritized(key))
+    }
+
+    /// Decodes the witnesses which have enough parts and sends them to the client, starting from
+    /// the prioritized ones and then from the highest height. After a burst of parts completes
+    /// several witnesses at once, the newest one is the most likely to still be endorsed in time,
+    /// so it shouldn't wait behind the stale ones. The witnesses are decoded in parallel, see
+    /// `decode_with_bounded_parallelism`, and the ones past their deadline by the time their
+    /// decode starts are dropped without decoding. Once the time slice runs out, the witnesses
+    /// not decoded yet stay ready for the next call, see `TimeSlice`. Returns the errors of the
+    /// witnesses which failed to decode.
+    pub fn decode_ready_witnesses(
+        &mut self,
+        time_slice: &TimeSlice,
+    ) -> Vec<(ChunkProductionKey, Error)> {
+        let mut keys: Vec<ChunkProductionKey> = self.ready_witnesses.drain().collect();
+        keys.sort_by_key(|key| (std::cmp::Reverse(key.height_created), key.shard_id));
+        if keys.iter().any(|key| self.is_prioritized(key)) {
+            // The order changes if a prioritized witness is behind one which isn't.
+            let reordered = keys
+                .iter()
+                .skip_while(|key| self.is_prioritized(key))
+                .any(|key| self.is_prioritized(key));
+            keys.sort_by_key(|key| !self.is_prioritized(key));
+            metrics::PARTIAL_WITNESS_PRIORITIZATIONS
+                .with_label_values(&[
+                    "decode_order",
+                    if reordered { "reordered" } else { "unchanged" },
+                ])
+                .inc();
+        }
+        if keys.len() > 1 {
+            tracing::debug!(
+                target: "client",
+                heights = ?keys.iter().map(|key| key.height_created).collect::<Vec<_>>(),
+                "Decoding several witnesses at once, newest first"
+            );
+        }
+
+        let mut errors = vec![];
+        // The witnesses are decoded in batches of the parallel decodes, the time slice is checked
+        // between the batches.
+        let batch_size = self.config.max_concurrent_witness_decodes.max(1);
+        let mut keys = keys.into_iter().peekable();
+        while keys.peek().is_some() {
+            if time_slice.should_yield("decode_ready_witnesses") {
+                self.ready_witnesses.extend(keys);
+                break;
+            }
+            // The entry may be gone since it became ready, e.g. when the full witness arrived.
+            let jobs: Vec<(ChunkProductionKey, CacheEntry)> = keys
+                .by_ref()
+                .take(batch_size)
+                .filter_map(|key| self.parts_cache.pop(&key).map(|entry| (key, entry)))
+                .collect();
+            let spilled_parts = &self.spilled_parts;
+            let clock = &self.clock;
+            let deadline = self.deadlines.deadline();
+            let decode_mode = self.decode_mode();
+            let decoded = decode_with_bounded_parallelism(
+                self.config.max_concurrent_witness_decodes,
+                jobs,
+                |(key, mut entry)| {
+                    let outcome = decode_witness(
+                        &key,
+                        &mut entry,
+                        spilled_parts,
+                        clock,
+                        deadline,
+                        decode_mode,
                     );
-                    return Err(Error::InvalidPartialChunkStateWitness(format!(
-                        "Failed to reed solomon decode witness parts: {err}",
-                    )));
+                    (key, entry, outcome)
+                },
+            );
+            #[cfg(test)]
+            if let Some((clock, decode_time)) = &self.slow_decodes {
+                clock.advance(*decode_time * decoded.len() as i32);
+            }
+
+            for (key, entry, outcome) in decoded {
+                if let Err(err) = self.on_witness_decoded(&key, entry, outcome) {
+                    errors.push((key, err));
                 }
-            };
+            }
+        }
+        self.record_total_parts_cache_size_metric();
+        errors
+    }
+
+    fn on_witness_decoded(
+        &mut self,
+        key: &ChunkProductionKey,
+        mut entry: CacheEntry,
+        outcome: DecodeOutcome,
+    ) -> Result<(), Error> {
+        if outcome.is_retriable_failure() && entry.can_retry_decode() {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                decode_attempts = entry.decode_attempts,
+                data_parts_present = entry.data_parts_present,
+                "Failed to decode witness, retrying once another part arrives"
+            );
+            entry.drop_reconstructed_parts();
+            // The entry was popped for the decode, so putting it back doesn't evict anything.
+            self.parts_cache.put(key.clone(), entry);
+            return Ok(());
+        }
+        // Restoring the spilled parts may have failed or been skipped, make sure they don't stay
+        // in the database.
+        if entry.is_spilled() {
+            self.spilled_parts.delete(key);
+        }
+        self.deadlines.cancel(key);
 
-            let (witness, raw_witness_size) = self.decode_state_witness(&encoded_witness)?;
-            if witness.chunk_production_key() != key {
+        let decode_result = match outcome {
+            DecodeOutcome::PastDeadline => {
+                self.expired_witnesses.put(key.clone(), ());
+                report_skipped_decode(key);
+                return Ok(());
+            }
+            DecodeOutcome::Decoded(decode_result) => decode_result,
+            DecodeOutcome::ReedSolomonFailure(err) => {
+                self.processed_witnesses.push(key.clone(), ());
+                // We ideally never expect the decoding to fail. In case it does, we received a bad
+                // part from the chunk producer.
+                self.producer_health.on_decode_failure(key);
+                tracing::error!(
+                    target: "client",
+                    ?err,
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    decode_retries = entry.decode_retries,
+                    "Failed to reed solomon decode witness parts. Maybe malicious or corrupt data."
+                );
                 return Err(Error::InvalidPartialChunkStateWitness(format!(
-                    "Decoded witness key {:?} doesn't match partial witness {:?}",
-                    witness.chunk_production_key(),
-                    key,
+                    "Failed to reed solomon decode witness parts: {err}",
                 )));
             }
+        };
+        self.processed_witnesses.push(key.clone(), ());
+        if let Some(part_ord) = entry.corrupted_part_ord {
+            self.record_corrupted_part(key, &entry, part_ord);
+        }
+        if let Err(Error::DecodedWitnessKeyMismatch { actual, .. }) = &decode_result {
+            self.record_key_mismatch(key, &entry, actual.clone());
+        }
+        if decode_result.is_ok() {
+            record_decode_attempts(key, entry.decode_attempts);
+        }
 
-            tracing::debug!(target: "client", ?key, "Sending encoded witness to client.");
-            self.client_sender.send(ChunkStateWitnessMessage { witness, raw_witness_size });
+        let parity_parts_used = entry.parity_parts_used;
+        let data_parts_used = entry.data_parts_present - parity_parts_used;
+        metrics::PARTIAL_WITNESS_DECODE_PARITY_PARTS
+            .with_label_values(&[&int_label(key.shard_id)])
+            .observe(parity_parts_used as f64);
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            data_parts_used,
+            parity_parts_used,
+            decode_attempts = entry.decode_attempts,
+            "Decoded witness from parts"
+        );
+        if let (Some(exporter), Ok((_, raw_witness_size, _))) =
+            (&self.stats_exporter, &decode_result)
+        {
+            let decode_latency = self.clock.now().signed_duration_since(entry.created_at);
+            exporter.export(WitnessStatsRecord {
+                source: WitnessStatsSource::Reconstructed,
+                epoch_id: key.epoch_id,
+                shard_id: key.shard_id,
+                height_created: key.height_created,
+                raw_size: *raw_witness_size,
+                compressed_size: entry.encoded_length,
+                section_sizes: None,
+                num_validators: entry.encoder.total_parts(),
+                decode_latency_millis: Some(decode_latency.whole_milliseconds().max(0) as u64),
+            });
         }
-        self.record_total_parts_cache_size_metric();
-        Ok(())
+
+        let result = decode_result.and_then(|(witness, raw_witness_size, witness_hash)| {
+            self.decoded_witnesses.insert(
+                key.clone(),
+                DecodedWitness { path: WitnessDecodePath::Parts, witness_hash },
+            );
+            self.record_witness_size(key, entry.encoded_length);
+            self.send_witness_to_client(
+                key,
+                witness,
+                raw_witness_size,
+                entry.pre_tracking,
+                entry.data_parts_present,
+                entry.decode_attempts,
+            )
+        });
+        self.record_decode_result(key, &result);
+        result
     }
 
-    fn get_num_parts(&self, partial_witness: &PartialEncodedStateWitness) -> Result<usize, Error> {
-        // The expected number of parts for the Reed Solomon encoding is the number of chunk validators.
-        let ChunkProductionKey { shard_id, epoch_id, height_created } =
-            partial_witness.chunk_production_key();
-        Ok(self
-            .epoch_manager
-            .get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?
-            .len())
+    /// Handles the full witness sent directly by the chunk producer. Once the full witness is
+    /// decoded, the parts received so far for the same chunk are dropped and the parts received
+    /// later are ignored, so that the client receives the witness only once, no matter whether it
+    /// arrived in full or in parts. A full witness which fails to decode doesn't stop the
+    /// reconstruction from the parts.
+    pub fn store_full_encoded_state_witness(
+        &mut self,
+        full_witness: FullEncodedStateWitness,
+    ) -> Result<(), Error> {
+        tracing::debug!(target: "client", ?full_witness, "store_full_encoded_state_witness");
+
+        let key = full_witness.chunk_production_key();
+        let shard_id_label = int_label(key.shard_id);
+        if let Some(decoded) = self.decoded_witnesses.get(&key) {
+            tracing::debug!(
+                target: "client",
+                ?full_witness,
+                decoded_path = decoded.path.as_str(),
+                "Received redundant full witness for already decoded witness"
+            );
+            metrics::PARTIAL_WITNESS_FULL_WITNESS_RECEIVED
+                .with_label_values(&[&shard_id_label, "redundant"])
+                .inc();
+            // Decoding it again is only paid for by the validators receiving both the parts and
+            // the full witness, and it catches a chunk producer sending different witnesses.
+            let witness_hash = decode_state_witness(
+                &key,
+                full_witness.encoded_witness(),
+                None,
+                self.decode_mode(),
+            )
+            .ok()
+            .map(|(_, _, witness_hash)| witness_hash);
+            return self.decoded_witnesses.check_redundant(
+                &key,
+                WitnessDecodePath::FullWitness,
+                witness_hash,
+            );
+        }
+        // Counts the witness as expected unless one of its parts was already received.
+        self.record_first_part(&key);
+
+        let (witness, raw_witness_size, witness_hash) = match decode_state_witness(
+            &key,
+            full_witness.encoded_witness(),
+            None,
+            self.decode_mode(),
+        ) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                self.producer_health.on_decode_failure(&key);
+                return Err(err);
+            }
+        };
+        metrics::PARTIAL_WITNESS_FULL_WITNESS_RECEIVED
+            .with_label_values(&[&shard_id_label, "used"])
+            .inc();
+        self.record_witness_size(&key, full_witness.size_bytes());
+        self.decoded_witnesses.insert(
+            key.clone(),
+            DecodedWitness { path: WitnessDecodePath::FullWitness, witness_hash },
+        );
+        let mut parts_received = 0;
+        if let Some(entry) = self.parts_cache.pop(&key) {
+            self.decoded_witnesses.mark_redundant(&key, WitnessDecodePath::Parts);
+            if entry.is_spilled() {
+                self.spilled_parts.delete(&key);
+            }
+            parts_received = entry.data_parts_present;
+        }
+        self.ready_witnesses.remove(&key);
+        self.deadlines.cancel(&key);
+        self.processed_witnesses.push(key.clone(), ());
+        self.record_total_parts_cache_size_metric();
+
+        let result =
+            self.send_witness_to_client(&key, witness, raw_witness_size, false, parts_received, 1);
+        self.record_decode_result(&key, &result);
+        result
     }
 
-    // Function to insert a new entry into the cache for the chunk hash if it does not already exist
-    // We additionally check if an evicted entry has been fully decoded and processed.
-    fn maybe_insert_new_entry_in_parts_cache(
+    /// Delivers the witness produced by us straight to the client, when we are the only chunk
+    /// validator of the chunk, so there is nobody to send the parts to and nothing to decode.
+    /// The witness is handled as if it arrived in full at once, except that it's not acked, as
+    /// we would only ack it to ourselves.
+    pub fn deliver_local_witness(
         &mut self,
-        partial_witness: &PartialEncodedStateWitness,
+        witness: ChunkStateWitness,
+        witness_hash: CryptoHash,
+        raw_witness_size: ChunkStateWitnessSize,
     ) -> Result<(), Error> {
-        // Insert a new entry into the cache for the chunk hash.
-        let key = partial_witness.chunk_production_key();
-        if self.parts_cache.contains(&key) {
+        let key = witness.chunk_production_key();
+        // A forced distribution of the same witness, the client already has it.
+        if self.decoded_witnesses.get(&key).is_some() {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                "Local witness already delivered"
+            );
             return Ok(());
         }
-        let num_parts = self.get_num_parts(&partial_witness)?;
-        let new_entry = CacheEntry::new(self.encoders.entry(num_parts));
-        if let Some((evicted_key, evicted_entry)) = self.parts_cache.push(key, new_entry) {
-            tracing::warn!(
+        self.record_first_part(&key);
+        self.decoded_witnesses.insert(
+            key.clone(),
+            DecodedWitness { path: WitnessDecodePath::FullWitness, witness_hash },
+        );
+        self.processed_witnesses.push(key.clone(), ());
+        self.acked_witnesses.put(key.clone(), ());
+        metrics::PARTIAL_WITNESS_TIME_TO_LAST_PART
+            .with_label_values(&[&int_label(key.shard_id)])
+            .observe(0.0);
+
+        let result = self.send_witness_to_client(&key, witness, raw_witness_size, false, 1, 1);
+        self.record_decode_result(&key, &result);
+        result
+    }
+
+    /// Reports the decoded witnesses which were only accepted within the grace band of the size
+    /// limit, see `WitnessSizeLimits`. Their chunk producers already apply the increased limit.
+    fn record_witness_size(&self, key: &ChunkProductionKey, encoded_size: usize) {
+        let Ok(protocol_version) = self.epoch_manager.get_epoch_protocol_version(&key.epoch_id)
+        else {
+            return;
+        };
+        let limits = WitnessSizeLimits::for_protocol_version(protocol_version);
+        if limits.band(encoded_size) != WitnessSizeBand::GraceBand {
+            return;
+        }
+        metrics::PARTIAL_WITNESS_OVERSIZED_WITNESSES
+            .with_label_values(&[&int_label(key.shard_id)])
+            .inc();
+        tracing::warn!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            encoded_size,
+            limit = %limits.limit,
+            "Accepted witness above the size limit within the grace band"
+        );
+    }
+
+    /// Records the arrival of the first part, or of the full witness, in the distribution health
+    /// of the chunk producer.
+    fn record_first_part(&mut self, key: &ChunkProductionKey) {
+        match self.epoch_manager.get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)
+        {
+            Ok(producer) => {
+                // The chunk is expected to be produced once its previous block is.
+                let latency = key
+                    .height_created
+                    .checked_sub(1)
+                    .and_then(|prev_height| self.head_timeline.block_time(prev_height))
+                    .map(|expected_at| self.clock.now_utc() - expected_at);
+                self.producer_health.on_first_part(key, producer, latency);
+            }
+            Err(err) => tracing::debug!(
                 target: "client",
-                ?evicted_key,
-                data_parts_present = ?evicted_entry.data_parts_present,
-                data_parts_required = ?evicted_entry.data_parts_required(),
-                "Evicted unprocessed partial state witness."
-            );
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                ?err,
+                "Failed to get the chunk producer of the witness"
+            ),
         }
-        Ok(())
     }
 
-    fn record_total_parts_cache_size_metric(&self) {
-        let total_size: usize =
-            self.parts_cache.iter().map(|(_, entry)| entry.total_parts_size).sum();
-        metrics::PARTIAL_WITNESS_CACHE_SIZE.set(total_size as f64);
+    /// Records the send skew of a part sent to us directly by the chunk producer, see
+    /// `PartialEncodedStateWitness::sent_at`. The skew is only recorded in the metrics and in the
+    /// distribution health, it doesn't affect the handling of the part in any way.
+    pub fn record_send_skew(&mut self, partial_witness: &PartialEncodedStateWitness) {
+        let Some(sent_at) = partial_witness.sent_at() else {
+            return;
+        };
+        let key = partial_witness.chunk_production_key();
+        let shard_id_label = int_label(key.shard_id);
+        let send_skew = self.clock.now_utc() - sent_at;
+        if send_skew < Duration::ZERO {
+            metrics::PARTIAL_WITNESS_NEGATIVE_SEND_SKEW.with_label_values(&[&shard_id_label]).inc();
+        }
+        metrics::PARTIAL_WITNESS_SEND_SKEW
+            .with_label_values(&[&shard_id_label])
+            .observe(send_skew.max(Duration::ZERO).as_seconds_f64());
+        match self.epoch_manager.get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)
+        {
+            Ok(producer) => self.producer_health.on_send_skew(producer, send_skew),
+            Err(err) => tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                ?err,
+                "Failed to get the chunk producer of the witness"
+            ),
+        }
     }
 
-    fn decode_state_witness(
-        &self,
-        encoded_witness: &EncodedChunkStateWitness,
-    ) -> Result<(ChunkStateWitness, ChunkStateWitnessSize), Error> {
-        let decode_start = std::time::Instant::now();
-        let (witness, raw_witness_size) = encoded_witness.decode()?;
-        let decode_elapsed_seconds = decode_start.elapsed().as_secs_f64();
-        let witness_shard = witness.chunk_header.shard_id();
-
-        // Record metrics after validating the witness
-        near_chain::stateless_validation::metrics::CHUNK_STATE_WITNESS_DECODE_TIME
-            .with_label_values(&[&witness_shard.to_string()])
-            .observe(decode_elapsed_seconds);
-
-        Ok((witness, raw_witness_size))
+    /// Records the message in which our own part of the witness arrived first, once per witness.
+    /// The owner is expected to receive its part directly from the chunk producer, receiving it
+    /// in a forward first means that the direct message was lost or delayed. The delivery is
+    /// only recorded in the metrics and in the distribution health.
+    fn record_owned_part_delivery(&mut self, key: &ChunkProductionKey, delivery: PartDelivery) {
+        if self.owned_part_deliveries.contains(key) {
+            return;
+        }
+        self.owned_part_deliveries.put(key.clone(), delivery);
+        metrics::PARTIAL_WITNESS_OWNED_PART_FIRST_DELIVERY
+            .with_label_values(&[&int_label(key.shard_id), delivery.as_str()])
+            .inc();
+        match self.epoch_manager.get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)
+        {
+            Ok(producer) => {
+                if delivery == PartDelivery::Forward {
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        %producer,
+                        "Our witness part arrived in a forward before the chunk producer's message"
+                    );
+                }
+                self.producer_health.on_owned_part_delivery(producer, delivery);
+            }
+            Err(err) => tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                ?err,
+                "Failed to get the chunk producer of the witness"
+            ),
+        }
+    }
+
+    /// Keeps the two conflicting parts, once per witness, and alerts about the chunk producer
+    /// signing them. The parts are not forwarded, so the conflict doesn't spread any further.
+    fn record_conflict_evidence(
+        &mut self,
+        key: &ChunkProductionKey,
+        reference_part: PartialEncodedStateWitness,
+        conflicting_part: PartialEncodedStateWitness,
+    ) {
+        if self.conflict_evidence.contains(key) {
+            return;
+        }
+        metrics::PARTIAL_WITNESS_CONFLICT_EVIDENCE
+            .with_label_values(&[&int_label(key.shard_id)])
+            .inc();
+        tracing::error!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            chunk_producer = ?self
+                .epoch_manager
+                .get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)
+                .ok(),
+            ?reference_part,
+            ?conflicting_part,
+            "Chunk producer signed conflicting witness parts"
+        );
+        self.conflict_evidence
+            .put(key.clone(), WitnessConflictEvidence { reference_part, conflicting_part });
+    }
+
+    /// Evidence of the chunk producer signing conflicting parts of the witness, if we received
+    /// any.
+    pub fn conflict_evidence(&self, key: &ChunkProductionKey) -> Option<&WitnessConflictEvidence> {
+        self.conflict_evidence.peek(key)
+    }
+
+    /// Keeps the evidence of the chunk producer encoding a witness of another chunk into the parts
+    /// of the key, once per witness. The witness is not sent to the client.
+    fn record_key_mismatch(
+        &mut self,
+        key: &ChunkProductionKey,
+        entry: &CacheEntry,
+        decoded_key: ChunkProductionKey,
+    ) {
+        let Some(reference_part) = entry.reference_part.clone() else {
+            return;
+        };
+        if self.key_mismatches.contains(key) {
+            return;
+        }
+        for (field, mismatched) in [
+            ("epoch_id", decoded_key.epoch_id != key.epoch_id),
+            ("shard_id", decoded_key.shard_id != key.shard_id),
+            ("height_created", decoded_key.height_created != key.height_created),
+        ] {
+            if mismatched {
+                metrics::PARTIAL_WITNESS_DECODED_KEY_MISMATCHES
+                    .with_label_values(&[&int_label(key.shard_id), field])
+                    .inc();
+            }
+        }
+        tracing::error!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            chunk_producer = ?self
+                .epoch_manager
+                .get_chunk_producer(&key.epoch_id, key.height_created, key.shard_id)
+                .ok(),
+            ?decoded_key,
+            "Chunk producer signed the parts of a witness of another chunk"
+        );
+        self.key_mismatches.put(
+            key.clone(),
+            WitnessKeyMismatchEvidence {
+                reference_part,
+                decoded_key,
+                witness_hash: entry.witness_hash,
+            },
+        );
+    }
+
+    /// Evidence of the chunk producer signing the parts of a witness of another chunk, if the
+    /// witness of the key decoded into one.
+    pub fn key_mismatch(&self, key: &ChunkProductionKey) -> Option<&WitnessKeyMismatchEvidence> {
+        self.key_mismatches.peek(key)
+    }
+
+    /// Records the part whose exclusion made the witness reconstruct, together with the message
+    /// in which it arrived. A part sent directly by the chunk producer was corrupted by the
+    /// producer or on the link to it, a forwarded one by the peer which delivered it or on the
+    /// way to that peer.
+    fn record_corrupted_part(
+        &mut self,
+        key: &ChunkProductionKey,
+        entry: &CacheEntry,
+        part_ord: usize,
+    ) {
+        let Some(source) = entry.part_sources[part_ord].clone() else {
+            return;
+        };
+        metrics::PARTIAL_WITNESS_CORRUPTED_PARTS
+            .with_label_values(&[&int_label(key.shard_id), source.delivery.as_str()])
+            .inc();
+        tracing::warn!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            part_ord,
+            delivery = source.delivery.as_str(),
+            from_peer = ?source.from_peer,
+            retries = entry.decode_retries,
+            "Reconstructed witness after excluding a corrupted part"
+        );
+        self.corrupted_parts.put(
+            key.clone(),
+            CorruptedWitnessPart { part_ord, source, retries: entry.decode_retries },
+        );
+    }
+
+    /// Part of the witness identified as corrupted during its reconstruction, if any.
+    /// Conflict between the full witness and the parts of the witness, see `DecodedWitnesses`.
+    pub fn decode_conflict(&self, key: &ChunkProductionKey) -> Option<&WitnessDecodeConflict> {
+        self.decoded_witnesses.conflict(key)
+    }
+
+    pub fn corrupted_part(&self, key: &ChunkProductionKey) -> Option<&CorruptedWitnessPart> {
+        self.corrupted_parts.peek(key)
+    }
+
+    /// Highest height of the witnesses of the shard sent to the client as a chunk validator, see
+    /// `ValidationLag`.
+    pub fn completed_height(&self, shard_id: ShardId) -> Option<BlockHeight> {
+        self.completed_heights.get(&shard_id).copied()
+    }
+
+    /// Message in which our own part of the witness arrived first, None if none of our parts
+    /// arrived yet or the witness is too old.
+    pub fn owned_part_delivery(&self, key: &ChunkProductionKey) -> Option<PartDelivery> {
+        self.owned_part_deliveries.peek(key).copied()
+    }
+
+    /// See `PartialWitnessConfig::buffered_witness_decode`.
+    fn decode_mode(&self) -> WitnessDecodeMode {
+        if self.config.buffered_witness_decode {
+            WitnessDecodeMode::Buffered
+        } else {
+            WitnessDecodeMode::Streamed
+        }
+    }
+
+    fn record_decode_result(&mut self, key: &ChunkProductionKey, result: &Result<(), Error>) {
+        match result {
+            Ok(()) => self.producer_health.on_decoded(key),
+            Err(_) => self.producer_health.on_decode_failure(key),
+        }
+    }
+
+    /// Shares the witnesses delivered by a previous instance of the actor, see
+    /// `DeliveredWitnesses`.
+    pub fn set_delivered_witnesses(&mut self, delivered_witnesses: DeliveredWitnesses) {
+        self.delivered_witnesses = delivered_witnesses;
+    }
+
+    /// Forgets the delivered witnesses at or below the final head, see `DeliveredWitnesses`.
+    pub fn on_final_head_updated(&mut self, final_height: BlockHeight) {
+        self.delivered_witnesses.prune_finalized(final_height);
+        let finalized = self.held_witnesses.prune_finalized(final_height);
+        if !finalized.is_empty() {
+            metrics::PARTIAL_WITNESS_HELD_WITNESS_RELEASES
+                .with_label_values(&["finalized"])
+                .inc_by(finalized.len() as u64);
+            metrics::PARTIAL_WITNESS_HELD_WITNESSES.set(self.held_witnesses.len() as i64);
+            tracing::debug!(
+                target: "client",
+                final_height,
+                num_witnesses = finalized.len(),
+                "Dropped the held witnesses at or below the final height"
+            );
+        }
+    }
+
+    /// Records the new head of the chain, see `ProducerHealthTracker::on_head_updated`.
+    pub fn on_head_updated(&mut self, epoch_id: EpochId, height: BlockHeight, timestamp: Utc) {
+        self.head_timeline.on_head_updated(height, timestamp);
+        self.producer_health.on_head_updated(epoch_id);
+        self.settle_link_deliveries(height);
+        if let Some(stats) = self.epoch_witness_stats.on_head_updated(epoch_id, height) {
+            self.persist_epoch_witness_stats(&stats);
+        }
+    }
+
+    fn persist_epoch_witness_stats(&self, stats: &VersionedEpochWitnessStats) {
+        let retained = self.config.epoch_witness_stats_retained;
+        if retained == 0 {
+            return;
+        }
+        if let Err(err) = save_epoch_witness_stats(&self.store, stats, retained) {
+            tracing::warn!(
+                target: "client",
+                ?err,
+                epoch_id = ?stats.epoch_id(),
+                "Failed to persist the epoch witness stats"
+            );
+        }
+    }
+
+    /// Records that the block of the head includes the chunk of the shard at its height, of which
+    /// we are a chunk validator, see `EpochWitnessStatsCollector::record_expected`.
+    pub fn record_expected_witness(
+        &mut self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        height: BlockHeight,
+    ) {
+        self.epoch_witness_stats.record_expected(epoch_id, shard_id, height);
+    }
+
+    /// Records the witness distributed by us as the chunk producer in the epoch witness stats.
+    pub fn record_witness_distributed(&mut self, key: &ChunkProductionKey) {
+        self.epoch_witness_stats.record_distributed(key);
+    }
+
+    /// Records the time it took for the witness distributed by us to be acked by two thirds of
+    /// its chunk validators in the epoch witness stats.
+    pub fn record_ack_threshold(&mut self, key: &ChunkProductionKey, time: Duration) {
+        self.epoch_witness_stats.record_ack_threshold(key, time);
+    }
+
+    /// Checks the witnesses admitted only by the alternative tip against the height windows of
+    /// the current head and alternative tip with `height_window`, which returns the tip admitting
+    /// the witness, if any. The witnesses admitted by the head, e.g. after a reorg to the fork of
+    /// the alternative tip, are decoded once they have enough parts. The ones admitted by neither
+    /// tip are dropped, the ones still admitted by the alternative tip keep waiting.
+    pub fn reevaluate_height_windows<F>(&mut self, mut height_window: F)
+    where
+        F: FnMut(&ChunkProductionKey) -> Result<Option<HeightWindowContext>, Error>,
+    {
+        let keys: Vec<ChunkProductionKey> = self
+            .parts_cache
+            .iter()
+            .filter(|(_, entry)| entry.height_window == HeightWindowContext::AlternativeTip)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            let outcome = match height_window(&key) {
+                Ok(Some(HeightWindowContext::Head)) => {
+                    self.admit_by_head(&key);
+                    "admitted_by_head"
+                }
+                Ok(Some(HeightWindowContext::AlternativeTip)) => continue,
+                Ok(None) => {
+                    self.drop_outside_height_windows(&key);
+                    "dropped"
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        ?err,
+                        "Failed to reevaluate the height window of the witness"
+                    );
+                    continue;
+                }
+            };
+            metrics::PARTIAL_WITNESS_HEIGHT_WINDOW_REEVALUATIONS
+                .with_label_values(&[&int_label(key.shard_id), outcome])
+                .inc();
+        }
+        self.record_total_parts_cache_size_metric();
+    }
+
+    /// Tags the witness as admitted by the head, making it ready to decode if it already has
+    /// enough parts.
+    fn admit_by_head(&mut self, key: &ChunkProductionKey) {
+        let Some(entry) = self.parts_cache.peek_mut(key) else {
+            return;
+        };
+        if entry.height_window == HeightWindowContext::Head {
+            return;
+        }
+        entry.height_window = HeightWindowContext::Head;
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            data_parts_present = entry.data_parts_present,
+            "Witness admitted by the alternative tip is now admitted by the head"
+        );
+        if entry.is_ready_to_decode() {
+            self.ready_witnesses.insert(key.clone());
+        }
+    }
+
+    /// Drops the parts of the witness admitted by neither the head nor the alternative tip.
+    fn drop_outside_height_windows(&mut self, key: &ChunkProductionKey) {
+        let Some(entry) = self.parts_cache.pop(key) else {
+            return;
+        };
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            data_parts_present = entry.data_parts_present,
+            "Dropping witness outside of the height windows of the head and alternative tip"
+        );
+        if entry.is_spilled() {
+            self.spilled_parts.delete(key);
+        }
+    }
+
+    /// Tip whose height window admitted the parts of the incomplete witness, None if the
+    /// witness is not in the parts cache.
+    pub fn height_window(&self, key: &ChunkProductionKey) -> Option<HeightWindowContext> {
+        self.parts_cache.peek(key).map(|entry| entry.height_window)
+    }
+
+    /// Records that `peer_id` delivered the validated forwarded part. Recorded even for the
+    /// processed witnesses, the forwards arriving after the decode still tell that the link works.
+    pub fn record_forwarded_part(
+        &mut self,
+        partial_witness: &PartialEncodedStateWitness,
+        peer_id: &PeerId,
+        my_account_id: &AccountId,
+    ) {
+        let Some(owner) = self.part_owner(partial_witness) else {
+            return;
+        };
+        self.link_loss.on_forwarded_part(
+            &partial_witness.chunk_production_key(),
+            partial_witness.part_ord(),
+            &owner,
+            peer_id,
+            my_account_id,
+        );
+    }
+
+    /// Owner of the validated part, see `witness_parts_geometry::part_owner`.
+    fn part_owner(&self, partial_witness: &PartialEncodedStateWitness) -> Option<AccountId> {
+        let key = partial_witness.chunk_production_key();
+        let assignments = self
+            .epoch_manager
+            .get_chunk_validator_assignments(&key.epoch_id, key.shard_id, key.height_created)
+            .ok()?;
+        witness_parts_geometry::part_owner(&assignments, partial_witness)
+    }
+
+    /// Owners of the parts of the chunk in the format of the protocol version of its epoch, see
+    /// `witness_parts_geometry::part_owners`.
+    fn part_owners(&self, key: &ChunkProductionKey) -> Result<Vec<AccountId>, Error> {
+        let assignments = self.epoch_manager.get_chunk_validator_assignments(
+            &key.epoch_id,
+            key.shard_id,
+            key.height_created,
+        )?;
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&key.epoch_id)?;
+        witness_parts_geometry::non_empty_part_owners(
+            &assignments,
+            PartFormat::for_protocol_version(protocol_version),
+            key,
+        )
+    }
+
+    /// Settles the forwarded part deliveries of the chunks old enough at `head_height`, see
+    /// `LinkLossEstimator`.
+    fn settle_link_deliveries(&mut self, head_height: BlockHeight) {
+        let keys = self.link_loss.settled_keys(head_height);
+        if keys.is_empty() {
+            return;
+        }
+        for key in keys {
+            match self.part_owners(&key) {
+                Ok(part_owners) => self.link_loss.settle(&key, &part_owners),
+                Err(err) => {
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        ?err,
+                        "Failed to get the part owners to settle the forwarded part deliveries"
+                    );
+                    self.link_loss.discard(&key);
+                }
+            }
+        }
+        let estimates = self.link_loss.estimates();
+        for bucket in LINK_LOSS_BUCKETS {
+            let peers =
+                estimates.iter().filter(|estimate| estimate.loss_bucket() == bucket).count();
+            metrics::PARTIAL_WITNESS_LINK_LOSS_PEERS.with_label_values(&[bucket]).set(peers as i64);
+        }
+    }
+
+    pub fn link_loss_estimates(&self) -> Vec<LinkLossEstimate> {
+        self.link_loss.estimates()
+    }
+
+    /// Returns how late the witness is if the block at its height is already on the chain, in
+    /// which case the block was produced without our endorsement of the chunk.
+    fn decode_lateness(&self, key: &ChunkProductionKey) -> Option<Duration> {
+        let head_height = self.head_timeline.head_height()?;
+        if head_height < key.height_created {
+            return None;
+        }
+        let lateness = self
+            .head_timeline
+            .block_time(key.height_created)
+            .map(|produced_at| self.clock.now_utc() - produced_at)
+            .unwrap_or(Duration::ZERO);
+        Some(lateness.max(Duration::ZERO))
+    }
+
+    /// Witness distribution health of the chunk producers over the last epoch, starting from the
+    /// worst decode ratio.
+    pub fn producer_distribution_health(&self) -> Vec<ProducerDistributionHealth> {
+        self.producer_health.health()
+    }
+
+    fn send_witness_to_client(
+        &mut self,
+        key: &ChunkProductionKey,
+        witness: ChunkStateWitness,
+        raw_witness_size: ChunkStateWitnessSize,
+        pre_tracking: bool,
+        parts_received: usize,
+        decode_attempts: usize,
+    ) -> Result<(), Error> {
+        if self.delivered_witnesses.contains(key) {
+            // Delivered by a previous instance of the actor, which also acked it.
+            report_skipped_redelivery(key);
+            return Ok(());
+        }
+        // Acknowledge the witness right after the reconstruction, so that the round trip time
+        // measured by the chunk producer doesn't include the validation time. Pre-tracking nodes
+        // are not chunk validators of the chunk, so the producer doesn't expect an ack from them.
+        if !pre_tracking {
+            self.send_state_witness_ack(key, &witness, parts_received, decode_attempts);
+            let completed_height = self.completed_heights.entry(key.shard_id).or_default();
+            *completed_height = (*completed_height).max(key.height_created);
+        }
+
+        // Reconstructing the witness before its previous block arrives is expected, the client
+        // handles both cases, but we keep track of how often each of them happens.
+        let prev_block_known =
+            self.store.exists(DBCol::Block, witness.chunk_header.prev_block_hash().as_ref())?;
+        metrics::PARTIAL_WITNESS_RECONSTRUCTED_WITNESSES
+            .with_label_values(&[
+                &int_label(key.shard_id),
+                if prev_block_known { "known" } else { "unknown" },
+            ])
+            .inc();
+
+        // The pre-tracked witnesses are never endorsed, so they can't be late.
+        let lateness = if pre_tracking { None } else { self.decode_lateness(key) };
+        let decoded_late = lateness.is_some();
+        if !pre_tracking {
+            self.epoch_witness_stats.record_decoded(key, decoded_late);
+        }
+        if let Some(lateness) = lateness {
+            let shard_id_label = int_label(key.shard_id);
+            metrics::PARTIAL_WITNESS_DECODED_LATE.with_label_values(&[&shard_id_label]).inc();
+            metrics::PARTIAL_WITNESS_DECODED_LATENESS
+                .with_label_values(&[&shard_id_label])
+                .observe(lateness.as_seconds_f64());
+            tracing::warn!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                ?lateness,
+                "Decoded witness after the block at its height, too late to endorse"
+            );
+        }
+
+        let message = ChunkStateWitnessMessage {
+            witness,
+            raw_witness_size,
+            pre_tracking,
+            prev_block_known,
+            decoded_late,
+        };
+        let witness = HeldWitness { key: key.clone(), message, decode_attempts };
+        if self.held_witnesses.has_credit() {
+            self.push_to_client(witness);
+            return Ok(());
+        }
+        tracing::debug!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            "Holding the decoded witness until the client has a credit"
+        );
+        if let Some(dropped) = self.held_witnesses.hold(witness) {
+            metrics::PARTIAL_WITNESS_HELD_WITNESS_RELEASES.with_label_values(&["dropped"]).inc();
+            tracing::warn!(
+                target: "client",
+                shard_id = dropped.key.shard_id,
+                height_created = dropped.key.height_created,
+                "Dropping the held witness of the lowest height, the client is too far behind"
+            );
+        }
+        metrics::PARTIAL_WITNESS_HELD_WITNESSES.set(self.held_witnesses.len() as i64);
+        Ok(())
+    }
+
+    /// Sends the witness to the client, taking a credit.
+    fn push_to_client(&mut self, witness: HeldWitness) {
+        if !self.record_delivery(&witness) {
+            return;
+        }
+        tracing::debug!(
+            target: "client",
+            shard_id = witness.key.shard_id,
+            height_created = witness.key.height_created,
+            pre_tracking = witness.message.pre_tracking,
+            prev_block_known = witness.message.prev_block_known,
+            decoded_late = witness.message.decoded_late,
+            "Sending encoded witness to client."
+        );
+        self.held_witnesses.on_pushed(&witness.key);
+        self.client_sender.send(witness.message);
+    }
+
+    /// Records that the witness is delivered to the client, returns false if it already was.
+    fn record_delivery(&mut self, witness: &HeldWitness) -> bool {
+        let key = &witness.key;
+        if !self.delivered_witnesses.insert(key) {
+            report_skipped_redelivery(key);
+            return false;
+        }
+        if let Some((evicted_key, waited)) = self.lifecycle_tracker.expect(
+            key.clone(),
+            witness.message.prev_block_known,
+            witness.message.decoded_late,
+            witness.decode_attempts,
+        ) {
+            report_unconsumed_witness(&evicted_key, waited);
+            self.held_witnesses.release(&evicted_key);
+        }
+        true
+    }
+
+    /// Pushes the held witnesses to the client while it has credits.
+    fn push_held_witnesses(&mut self) {
+        while self.held_witnesses.has_credit() {
+            let now = self.clock.now();
+            let prioritized_witnesses = &self.prioritized_witnesses;
+            let Some(witness) = self
+                .held_witnesses
+                .take_next(usize::MAX, |key| prioritized_witnesses.is_prioritized(key, now))
+            else {
+                break;
+            };
+            metrics::PARTIAL_WITNESS_HELD_WITNESS_RELEASES.with_label_values(&["pushed"]).inc();
+            self.push_to_client(witness);
+        }
+        metrics::PARTIAL_WITNESS_HELD_WITNESSES.set(self.held_witnesses.len() as i64);
+    }
+
+    /// Hands the next held witness with a raw size up to `max_bytes` to the client asking for it,
+    /// see `RequestNextDecodedWitness`. The witness takes no credit.
+    pub fn take_next_decoded_witness(
+        &mut self,
+        max_bytes: usize,
+    ) -> Option<ChunkStateWitnessMessage> {
+        let now = self.clock.now();
+        let prioritized_witnesses = &self.prioritized_witnesses;
+        let witness = self
+            .held_witnesses
+            .take_next(max_bytes, |key| prioritized_witnesses.is_prioritized(key, now))?;
+        metrics::PARTIAL_WITNESS_HELD_WITNESSES.set(self.held_witnesses.len() as i64);
+        if !self.record_delivery(&witness) {
+            return None;
+        }
+        metrics::PARTIAL_WITNESS_HELD_WITNESS_RELEASES.with_label_values(&["pulled"]).inc();
+        tracing::debug!(
+            target: "client",
+            shard_id = witness.key.shard_id,
+            height_created = witness.key.height_created,
+            "Client pulled the held witness"
+        );
+        Some(witness.message)
+    }
+
+    /// Acks the witness to its chunk producer, together with the number of parts received when it
+    /// was decoded, see `ChunkStateWitnessAckV2`.
+    fn send_state_witness_ack(
+        &mut self,
+        key: &ChunkProductionKey,
+        witness: &ChunkStateWitness,
+        parts_received: usize,
+        decode_attempts: usize,
+    ) {
+        if self.acked_witnesses.put(key.clone(), ()).is_some() {
+            tracing::debug!(
+                target: "client",
+                shard_id = key.shard_id,
+                height_created = key.height_created,
+                "Witness already acked"
+            );
+            return;
+        }
+        let via_full_witness = self
+            .decoded_witnesses
+            .get(key)
+            .is_some_and(|decoded| decoded.path == WitnessDecodePath::FullWitness);
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&key.epoch_id).ok();
+        let ack = match protocol_version {
+            Some(protocol_version) => VersionedChunkStateWitnessAck::new(
+                witness,
+                parts_received,
+                via_full_witness,
+                decode_attempts,
+                protocol_version,
+            ),
+            // The V1 ack is understood by the chunk producer in any epoch.
+            None => ChunkStateWitnessAck::new(witness).into(),
+        };
+        // The older chunk producers don't handle the batches, so the acks are sent one by one
+        // until the batching is enabled.
+        let batching_enabled = protocol_version.is_some_and(|protocol_version| {
+            ProtocolFeature::BatchedWitnessAcks.enabled(protocol_version)
+        });
+        if !self.ack_batcher.is_enabled() || !batching_enabled {
+            self.send_acks(
+                witness.chunk_producer.clone(),
+                vec![HeldChunkStateWitnessAckV2::new(ack, Duration::ZERO)],
+            );
+            return;
+        }
+        let now = self.clock.now();
+        if let Some(acks) = self.ack_batcher.push(witness.chunk_producer.clone(), ack, now) {
+            self.send_acks(witness.chunk_producer.clone(), acks);
+        }
+    }
+
+    /// Sends the acks to the chunk producer, in a batch if there are several of them. A single
+    /// ack is sent as a plain `ChunkStateWitnessAck`, see the `ack_batcher` module docs.
+    fn send_acks(&mut self, chunk_producer: AccountId, mut acks: Vec<HeldChunkStateWitnessAckV2>) {
+        let request = if acks.len() == 1 {
+            metrics::PARTIAL_WITNESS_ACKS_SENT.with_label_values(&["standalone"]).inc();
+            NetworkRequests::ChunkStateWitnessAck(chunk_producer, acks.pop().unwrap().ack)
+        } else {
+            metrics::PARTIAL_WITNESS_ACKS_SENT
+                .with_label_values(&["batched"])
+                .inc_by(acks.len() as u64);
+            NetworkRequests::BatchedChunkStateWitnessAck(
+                chunk_producer,
+                BatchedChunkStateWitnessAckV2 { acks },
+            )
+        };
+        self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(request));
+    }
+
+    /// Time at which the oldest held ack should be sent, see `flush_due_acks`.
+    pub fn next_ack_flush_at(&self) -> Option<Instant> {
+        self.ack_batcher.next_flush_at()
+    }
+
+    /// Sends the held acks to the chunk producers whose oldest held ack reached
+    /// `PartialWitnessConfig::ack_batching_delay`.
+    pub fn flush_due_acks(&mut self) {
+        let now = self.clock.now();
+        for (chunk_producer, acks) in self.ack_batcher.take_due(now) {
+            self.send_acks(chunk_producer, acks);
+        }
+    }
+
+    /// Handles the confirmation from the client that it consumed the witness sent to it.
+    pub fn on_witness_consumed(&mut self, key: &ChunkProductionKey) {
+        if self.held_witnesses.release(key) {
+            self.push_held_witnesses();
+        }
+        match self.lifecycle_tracker.confirm(key) {
+            Some(waited) => {
+                tracing::trace!(
+                    target: "client",
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    ?waited,
+                    "Witness consumed by client"
+                );
+            }
+            None => {
+                tracing::debug!(
+                    target: "client",
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    "Received consumption confirmation for a witness that is not pending"
+                );
+            }
+        }
+    }
+
+    /// Handles the outcome of the witness reported by the client, joining it with the lifecycle
+    /// of the witness if we reconstructed it.
+    pub fn on_witness_outcome(
+        &mut self,
+        key: &ChunkProductionKey,
+        outcome: ChunkStateWitnessOutcome,
+    ) {
+        metrics::PARTIAL_WITNESS_OUTCOMES.with_label_values(&[outcome.as_str()]).inc();
+        match self.lifecycle_tracker.join_outcome(key, outcome) {
+            Some(record) => {
+                tracing::debug!(
+                    target: "client",
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    outcome = outcome.as_str(),
+                    prev_block_known = record.prev_block_known,
+                    decode_attempts = record.decode_attempts,
+                    consumed_after = ?record.consumed_after,
+                    outcome_after = ?record.outcome_after,
+                    "Witness outcome reported by client"
+                );
+            }
+            None => {
+                tracing::debug!(
+                    target: "client",
+                    shard_id = key.shard_id,
+                    height_created = key.height_created,
+                    outcome = outcome.as_str(),
+                    "Received outcome of a witness that is not tracked"
+                );
+            }
+        }
+    }
+
+    /// Returns how the witness was decoded first, None if it wasn't decoded or was forgotten.
+    pub fn decode_path(&self, key: &ChunkProductionKey) -> Option<WitnessDecodePath> {
+        self.decoded_witnesses.get(key).map(|decoded| decoded.path)
+    }
+
+    /// Returns the most recently reconstructed witnesses joined with their outcome, starting
+    /// from the most recent one.
+    pub fn recent_witness_outcomes(&self) -> impl Iterator<Item = &WitnessOutcomeRecord> {
+        self.lifecycle_tracker.recent_outcomes()
+    }
+
+    /// Reports the witnesses sent to the client which the client didn't confirm to have
+    /// consumed within `WITNESS_CONSUMPTION_TIMEOUT`.
+    pub fn check_unconsumed_witnesses(&mut self) {
+        for (key, waited) in self.lifecycle_tracker.take_overdue(WITNESS_CONSUMPTION_TIMEOUT) {
+            report_unconsumed_witness(&key, waited);
+            self.held_witnesses.release(&key);
+        }
+        self.push_held_witnesses();
+    }
+
+    /// Returns whether the witness was abandoned because it wasn't decoded before its deadline.
+    pub fn is_expired(&self, key: &ChunkProductionKey) -> bool {
+        self.expired_witnesses.contains(key)
+    }
+
+    /// The deadline of the incomplete witness, None if none of its parts arrived yet or if it's
+    /// no longer pending.
+    pub fn deadline_of(&self, key: &ChunkProductionKey) -> Option<Instant> {
+        self.deadlines.deadline_of(key)
+    }
+
+    /// The earliest deadline of the incomplete witnesses, see `expire_witnesses`.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.next_deadline()
+    }
+
+    /// Abandons the incomplete witnesses whose first part arrived more than
+    /// `PartialWitnessConfig::incomplete_witness_deadline` ago. Their parts are freed, and the
+    /// parts arriving for them later are dropped, see `is_expired`. Once the time slice runs
+    /// out, the rest is left for the next call, which is due right away, see `next_deadline`.
+    pub fn expire_witnesses(&mut self, time_slice: &TimeSlice) {
+        let now = self.clock.now();
+        while self.deadlines.next_deadline().is_some_and(|deadline| deadline <= now) {
+            if time_slice.should_yield("expire_witnesses") {
+                break;
+            }
+            let key = self.deadlines.pop_expired(now).unwrap();
+            // The entry may have been evicted and created again by a later part, in which case
+            // the deadline of the new entry is still pending.
+            let Some(entry) = self.parts_cache.peek(&key) else {
+                continue;
+            };
+            if now.signed_duration_since(entry.created_at) < self.deadlines.deadline() {
+                continue;
+            }
+            let entry = self.parts_cache.pop(&key).unwrap();
+            if entry.is_spilled() {
+                self.spilled_parts.delete(&key);
+            }
+            self.expired_witnesses.put(key.clone(), ());
+            if self.ready_witnesses.remove(&key) {
+                report_skipped_decode(&key);
+            } else {
+                self.report_expired_witness(&key, &entry);
+            }
+        }
+        self.record_total_parts_cache_size_metric();
+    }
+
+    /// Parts still missing `delay` after the first part of their witness arrived, together with
+    /// their owners, by witness. The witnesses of the pre-tracked shards are skipped, and every
+    /// witness is returned once, see `PartialWitnessConfig::missing_part_request_delay`.
+    pub fn take_missing_part_requests(
+        &mut self,
+        delay: Duration,
+    ) -> Vec<(ChunkProductionKey, Vec<(usize, AccountId)>)> {
+        let now = self.clock.now();
+        let due_keys: Vec<ChunkProductionKey> = self
+            .parts_cache
+            .iter()
+            .filter(|(_, entry)| {
+                !entry.pre_tracking
+                    && !entry.parts_requested
+                    && entry.data_parts_present < entry.data_parts_required()
+                    && now.signed_duration_since(entry.created_at) >= delay
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut requests = vec![];
+        for key in due_keys {
+            let entry = self.parts_cache.peek_mut(&key).unwrap();
+            entry.parts_requested = true;
+            let missing_part_ords = entry.missing_part_ords();
+            // The owners follow the format of the parts received, see `part_format`.
+            let format = entry
+                .reference_part
+                .as_ref()
+                .map(|reference_part| PartialWitnessPart::from(reference_part).format);
+            let owners = match format {
+                Some(format) => self
+                    .epoch_manager
+                    .get_chunk_validator_assignments(
+                        &key.epoch_id,
+                        key.shard_id,
+                        key.height_created,
+                    )
+                    .map(|assignments| witness_parts_geometry::part_owners(&assignments, format))
+                    .map_err(Error::from),
+                None => self.part_owners(&key),
+            };
+            let owners = match owners {
+                Ok(owners) => owners,
+                Err(err) => {
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        ?err,
+                        "Failed to get the owners of the missing witness parts"
+                    );
+                    continue;
+                }
+            };
+            let missing_parts = missing_part_ords
+                .into_iter()
+                .filter_map(|part_ord| Some((part_ord, owners.get(part_ord)?.clone())))
+                .collect();
+            requests.push((key, missing_parts));
+        }
+        requests
+    }
+
+    /// Reports which parts of the expired witness never arrived, and which chunk validators own
+    /// them, to tell the missing owners from a missing chunk producer.
+    fn report_expired_witness(&self, key: &ChunkProductionKey, entry: &CacheEntry) {
+        metrics::PARTIAL_WITNESS_EXPIRED_WITNESSES
+            .with_label_values(&[&int_label(key.shard_id)])
+            .inc();
+        let missing_part_ords = entry.missing_part_ords();
+        let missing_owners = self
+            .part_owners(key)
+            .map(|owners| {
+                missing_part_ords
+                    .iter()
+                    .filter_map(|part_ord| owners.get(*part_ord).cloned())
+                    .collect::<Vec<_>>()
+            })
+            .ok();
+        tracing::warn!(
+            target: "client",
+            shard_id = key.shard_id,
+            height_created = key.height_created,
+            data_parts_present = entry.data_parts_present,
+            data_parts_required = entry.data_parts_required(),
+            ?missing_part_ords,
+            ?missing_owners,
+            "Abandoned incomplete witness past its deadline"
+        );
+    }
+
+    /// Spills the parts of the least recently used incomplete witnesses to the database until the
+    /// total size of the parts held in memory is below `PartialWitnessConfig::spill_threshold`.
+    /// The entry we just inserted a part into is never spilled.
+    fn maybe_spill_parts(&mut self, current_key: &ChunkProductionKey) {
+        let threshold = self.config.spill_threshold.as_u64() as usize;
+        let mut total_size = self.total_parts_cache_size();
+        if total_size <= threshold {
+            return;
+        }
+        // `iter()` goes from the most to the least recently used entry.
+        let keys_to_spill: Vec<ChunkProductionKey> = self
+            .parts_cache
+            .iter()
+            .rev()
+            .filter(|(key, entry)| *key != current_key && entry.parts.iter().any(Option::is_some))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys_to_spill {
+            if total_size <= threshold {
+                break;
+            }
+            let entry = self.parts_cache.peek_mut(&key).unwrap();
+            match entry.spill(&self.spilled_parts, &key) {
+                Some(freed) => {
+                    total_size = total_size.saturating_sub(freed);
+                    metrics::PARTIAL_WITNESS_SPILLED_ENTRIES.inc();
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        freed,
+                        "Spilled witness parts to disk"
+                    );
+                }
+                None => {
+                    tracing::debug!(
+                        target: "client",
+                        shard_id = key.shard_id,
+                        height_created = key.height_created,
+                        "Too many witness parts waiting to be spilled to disk"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    fn get_num_parts(&self, key: &ChunkProductionKey) -> Result<usize, Error> {
+        // The expected number of parts for the Reed Solomon encoding is the number of chunk validators.
+        Ok(self.part_owners(key)?.len())
+    }
+
+    // Function to insert a new entry into the cache for the chunk hash if it does not already exist
+    // We additionally check if an evicted entry has been fully decoded and processed.
+    fn maybe_insert_new_entry_in_parts_cache(
+        &mut self,
+        partial_witness: &PartialEncodedStateWitness,
+        pre_tracking: bool,
+    ) -> Result<(), Error> {
+        // Insert a new entry into the cache for the chunk hash.
+        let key = partial_witness.chunk_production_key();
+        if self.parts_cache.contains(&key) {
+            return Ok(());
+        }
+        let num_parts = self.get_num_parts(&key)?;
+        self.record_first_part(&key);
+        let now = self.clock.now();
+        let new_entry = CacheEntry::new(
+            self.encoders.entry(num_parts)?,
+            pre_tracking,
+            partial_witness.witness_hash().copied(),
+            now,
+        );
+        if let Some(dropped_key) = self.deadlines.start(key.clone(), now) {
+            tracing::debug!(
+                target: "client",
+                shard_id = dropped_key.shard_id,
+                height_created = dropped_key.height_created,
+                "Too many pending witness deadlines, dropped the oldest one"
+            );
+        }
+        if let Some((evicted_key, evicted_entry)) = self.parts_cache.push(key, new_entry) {
+            tracing::warn!(
+                target: "client",
+                shard_id = evicted_key.shard_id,
+                height_created = evicted_key.height_created,
+                data_parts_present = ?evicted_entry.data_parts_present,
+                data_parts_required = ?evicted_entry.data_parts_required(),
+                "Evicted unprocessed partial state witness."
+            );
+            if evicted_entry.is_spilled() {
+                self.spilled_parts.delete(&evicted_key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the raw parts of the incomplete witnesses, from the least to the most recently used,
+    /// see `PartialWitnessState`. The parts spilled to the database are read back without
+    /// restoring them; if that fails, the witness is snapshotted with the parts in memory only.
+    pub fn snapshot_incomplete_witnesses(&self) -> Vec<IncompleteWitnessSnapshot> {
+        self.parts_cache
+            .iter()
+            .rev()
+            .map(|(key, entry)| {
+                let mut parts: Vec<(usize, Box<[u8]>)> = entry
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(part_ord, part)| Some((part_ord, part.clone()?)))
+                    .collect();
+                if entry.is_spilled() {
+                    match self.spilled_parts.read(key) {
+                        Ok(spilled_parts) => parts.extend(spilled_parts),
+                        Err(err) => tracing::warn!(
+                            target: "client",
+                            shard_id = key.shard_id,
+                            height_created = key.height_created,
+                            ?err,
+                            "Failed to read spilled witness parts for the snapshot"
+                        ),
+                    }
+                }
+                parts.sort_by_key(|(part_ord, _)| *part_ord);
+                IncompleteWitnessSnapshot {
+                    epoch_id: key.epoch_id,
+                    shard_id: key.shard_id,
+                    height_created: key.height_created,
+                    pre_tracking: entry.pre_tracking,
+                    witness_hash: entry.witness_hash,
+                    parts,
+                }
+            })
+            .collect()
+    }
+
+    /// Puts the snapshotted incomplete witness back into the parts cache, with a fresh deadline.
+    /// The witness is decoded once the remaining parts arrive.
+    pub fn restore_incomplete_witness(
+        &mut self,
+        snapshot: IncompleteWitnessSnapshot,
+    ) -> Result<(), Error> {
+        let key = snapshot.chunk_production_key();
+        if self.parts_cache.contains(&key) || self.processed_witnesses.contains(&key) {
+            return Ok(());
+        }
+        let num_parts = self.get_num_parts(&key)?;
+        let now = self.clock.now();
+        let mut entry = CacheEntry::new(
+            self.encoders.entry(num_parts)?,
+            snapshot.pre_tracking,
+            snapshot.witness_hash,
+            now,
+        );
+        for (part_ord, part) in snapshot.parts {
+            if part_ord >= num_parts || entry.parts[part_ord].is_some() {
+                return Err(Error::InvalidPartialChunkStateWitness(format!(
+                    "Invalid part_ord {} in the snapshot of {:?}, expected {} parts",
+                    part_ord, key, num_parts
+                )));
+            }
+            entry.data_parts_present += 1;
+            entry.parts[part_ord] = Some(part);
+        }
+        self.deadlines.start(key.clone(), now);
+        if let Some((evicted_key, evicted_entry)) = self.parts_cache.push(key, entry) {
+            if evicted_entry.is_spilled() {
+                self.spilled_parts.delete(&evicted_key);
+            }
+        }
+        self.record_total_parts_cache_size_metric();
+        Ok(())
+    }
+
+    /// Drops the least recently used incomplete witnesses until the total size of the parts held
+    /// in memory fits into `PartialWitnessConfig::memory_pressure_parts_budget`. Returns the size
+    /// of the parts dropped from memory.
+    pub fn shrink_parts_cache(&mut self) -> Result<usize, Error> {
+        let budget = self.config.memory_pressure_parts_budget.as_u64() as usize;
+        let freed = drop_oldest_entries(&mut self.parts_cache, &self.spilled_parts, budget);
+        self.record_total_parts_cache_size_metric();
+        Ok(freed)
+    }
+
+    /// Constructs the decoder for `total_parts` parts ahead of the first witness using it.
+    pub fn warm_up_encoder(&mut self, total_parts: usize) -> Result<(), Error> {
+        self.encoders.entry(total_parts)?;
+        Ok(())
+    }
+
+    /// Drops the decoders for the numbers of parts not in `total_parts`, see
+    /// `WitnessEncoderCache::retain`.
+    pub fn retain_encoders(&mut self, total_parts: &HashSet<usize>) -> usize {
+        self.encoders.retain(total_parts)
+    }
+
+    /// Memory retained by the incomplete witnesses, without the spilled parts.
+    pub fn total_parts_cache_size(&self) -> usize {
+        parts_cache_size(&self.parts_cache)
+    }
+
+    fn record_total_parts_cache_size_metric(&self) {
+        metrics::PARTIAL_WITNESS_CACHE_SIZE.set(self.total_parts_cache_size() as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::EpochId;
+    use near_store::test_utils::create_test_store;
+
+    use crate::test_utils::{TestPayload, TestWitnessBuilder};
+
+    fn direct() -> PartSource {
+        PartSource { delivery: PartDelivery::Direct, from_peer: None }
+    }
+
+    /// Cache entry of the witness encoded into `total_parts` parts, with the parts `corrupted`
+    /// flipped and forwarded by a peer, and the other parts sent directly.
+    fn entry_with_corrupted_parts(
+        total_parts: usize,
+        corrupted: &[usize],
+    ) -> (ChunkStateWitness, ChunkProductionKey, CacheEntry) {
+        let mut parts =
+            TestWitnessBuilder::new().encode_and_split(total_parts, &create_test_signer("test"));
+        for part_ord in corrupted {
+            parts = parts.corrupt_part(*part_ord);
+        }
+        let encoder =
+            WitnessEncoderCache::new(ReedSolomonBackend::Portable).entry(total_parts).unwrap();
+        let witness_hash = CryptoHash::hash_borsh(&parts.witness);
+        let mut entry = CacheEntry::new(encoder, false, Some(witness_hash), Instant::now());
+        for partial_witness in parts.parts() {
+            let source = if corrupted.contains(&partial_witness.part_ord()) {
+                PartSource {
+                    delivery: PartDelivery::Forward,
+                    from_peer: Some(PeerId::new(PublicKey::empty(KeyType::ED25519))),
+                }
+            } else {
+                direct()
+            };
+            entry.insert_part(partial_witness.clone(), source);
+        }
+        (parts.witness.clone(), parts.key(), entry)
+    }
+
+    #[test]
+    fn spilled_parts_are_restored_for_decoding() {
+        let store = create_test_store();
+        let spilled_parts = SpilledParts::spawn(store.clone());
+        let parts = TestWitnessBuilder::new()
+            .payload(100_000, TestPayload::Incompressible)
+            .encode_and_split(10, &create_test_signer("test"));
+        let (witness, _) = EncodedChunkStateWitness::encode(&parts.witness).unwrap();
+        let key = parts.key();
+        let partial_witnesses = parts.into_parts();
+        let encoder = WitnessEncoderCache::new(ReedSolomonBackend::Portable).entry(10).unwrap();
+        let data_parts = encoder.data_parts();
+        let mut entry = CacheEntry::new(encoder, false, None, Instant::now());
+
+        for partial_witness in &partial_witnesses[..data_parts - 1] {
+            assert!(!entry.insert_part(partial_witness.clone(), direct()));
+        }
+        let size_before = entry.estimate_size();
+        let freed = entry.spill(&spilled_parts, &key).unwrap();
+        assert_eq!(entry.estimate_size(), size_before - freed);
+        let spilled_bytes: usize =
+            partial_witnesses[..data_parts - 1].iter().map(|part| part.part_size()).sum();
+        assert!(freed >= spilled_bytes);
+        assert!(entry.parts.iter().all(|part| part.is_none()));
+        // The spilled parts are not missing.
+        assert_eq!(entry.missing_part_ords(), (data_parts - 1..10).collect::<Vec<_>>());
+
+        assert!(entry.insert_part(partial_witnesses[data_parts - 1].clone(), direct()));
+        let decoded = entry.decode(&spilled_parts, &key, None).unwrap();
+        assert_eq!(decoded, witness);
+        assert_eq!(entry.parity_parts_used, 0);
+        assert!(!entry.is_spilled());
+        spilled_parts.flush();
+        assert!(store.iter(DBCol::PartialWitnessSpilledParts).next().is_none());
+    }
+
+    #[test]
+    fn reconstructed_witness_is_checked_against_signed_hash() {
+        let witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let key = witness.chunk_production_key();
+        let expected_hash = ExpectedWitnessHash {
+            witness_hash: CryptoHash::hash_borsh(&witness),
+            used_part_ords: vec![0, 2],
+        };
+        check_witness_hash(&key, &expected_hash.witness_hash, &expected_hash).unwrap();
+
+        let other_witness = ChunkStateWitness::new_dummy(43, 0, CryptoHash::default());
+        assert!(matches!(
+            check_witness_hash(&key, &CryptoHash::hash_borsh(&other_witness), &expected_hash),
+            Err(Error::InvalidPartialChunkStateWitness(_))
+        ));
+    }
+
+    #[test]
+    fn witness_is_reconstructed_without_corrupted_part() {
+        let spilled_parts = SpilledParts::spawn(create_test_store());
+        let (witness, key, mut entry) = entry_with_corrupted_parts(10, &[1]);
+        let outcome = decode_witness(
+            &key,
+            &mut entry,
+            &spilled_parts,
+            &Clock::real(),
+            Duration::hours(1),
+            WitnessDecodeMode::Streamed,
+        );
+        let DecodeOutcome::Decoded(Ok((decoded, _, decoded_hash))) = outcome else {
+            panic!("witness with a single corrupted part should be reconstructed");
+        };
+        assert_eq!(decoded, witness);
+        assert_eq!(decoded_hash, CryptoHash::hash_borsh(&witness));
+        // The forwarded part is the least trusted one, so it is excluded first.
+        assert_eq!(entry.corrupted_part_ord, Some(1));
+        assert_eq!(entry.decode_retries, 1);
+        assert_eq!(entry.part_sources[1].as_ref().unwrap().delivery, PartDelivery::Forward);
+    }
+
+    #[test]
+    fn decode_retries_are_capped() {
+        let spilled_parts = SpilledParts::spawn(create_test_store());
+
+        // Excluding one part at a time leaves the other corrupted part in.
+        let (_, key, mut entry) = entry_with_corrupted_parts(20, &[1, 2]);
+        let outcome = decode_witness(
+            &key,
+            &mut entry,
+            &spilled_parts,
+            &Clock::real(),
+            Duration::hours(1),
+            WitnessDecodeMode::Streamed,
+        );
+        assert!(matches!(outcome, DecodeOutcome::Decoded(Err(_))));
+        assert_eq!(entry.corrupted_part_ord, None);
+        assert_eq!(entry.decode_retries, MAX_DECODE_RETRIES);
+
+        // Without spare parts there is nothing to exclude.
+        let (_, key, mut entry) = entry_with_corrupted_parts(10, &[1]);
+        let data_parts = entry.data_parts_required();
+        for part_ord in data_parts..10 {
+            entry.parts[part_ord] = None;
+            entry.part_sources[part_ord] = None;
+        }
+        let outcome = decode_witness(
+            &key,
+            &mut entry,
+            &spilled_parts,
+            &Clock::real(),
+            Duration::hours(1),
+            WitnessDecodeMode::Streamed,
+        );
+        assert!(matches!(outcome, DecodeOutcome::Decoded(Err(_))));
+        assert_eq!(entry.decode_retries, 0);
+    }
+
+    #[test]
+    fn witness_of_another_chunk_is_rejected_without_retries() {
+        let spilled_parts = SpilledParts::spawn(create_test_store());
+        let signed_for = TestWitnessBuilder::new().height(43).build();
+        let parts = TestWitnessBuilder::new()
+            .shard_id(1)
+            .encode_and_split(10, &create_test_signer("test"))
+            .sign_for(&signed_for);
+        let key = signed_for.chunk_production_key();
+        let encoder = WitnessEncoderCache::new(ReedSolomonBackend::Portable).entry(10).unwrap();
+        let witness_hash = CryptoHash::hash_borsh(&parts.witness);
+        let mut entry = CacheEntry::new(encoder, false, Some(witness_hash), Instant::now());
+        for partial_witness in parts.parts() {
+            assert_eq!(partial_witness.chunk_production_key(), key);
+            entry.insert_part(partial_witness.clone(), direct());
+        }
+
+        let outcome = decode_witness(
+            &key,
+            &mut entry,
+            &spilled_parts,
+            &Clock::real(),
+            Duration::hours(1),
+            WitnessDecodeMode::Streamed,
+        );
+        assert!(!outcome.is_retriable_failure());
+        let DecodeOutcome::Decoded(Err(Error::DecodedWitnessKeyMismatch { expected, actual })) =
+            outcome
+        else {
+            panic!("witness of another chunk should be rejected");
+        };
+        assert_eq!(expected, key);
+        assert_eq!(actual, parts.key());
+        // The witness decoded intact, so no part is suspected.
+        assert_eq!(entry.decode_retries, 0);
+        assert_eq!(entry.corrupted_part_ord, None);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped_down_to_budget() {
+        let store = create_test_store();
+        let spilled_parts = SpilledParts::spawn(store.clone());
+        let signer = create_test_signer("test");
+        let encoder = WitnessEncoderCache::new(ReedSolomonBackend::Portable).entry(4).unwrap();
+        let mut parts_cache = LruCache::new(NonZeroUsize::new(10).unwrap());
+        for height in 1..=3 {
+            let chunk_header =
+                ChunkStateWitness::new_dummy(height, 0, CryptoHash::default()).chunk_header;
+            let partial_witness = PartialEncodedStateWitness::new(
+                EpochId::default(),
+                chunk_header,
+                0,
+                vec![1; 100 * height as usize],
+                1000,
+                &signer,
+            );
+            let key = partial_witness.chunk_production_key();
+            let mut entry = CacheEntry::new(encoder.clone(), false, None, Instant::now());
+            assert!(!entry.insert_part(partial_witness, direct()));
+            parts_cache.put(key, entry);
+        }
+        let oldest_key = parts_cache.peek_lru().unwrap().0.clone();
+        parts_cache.peek_mut(&oldest_key).unwrap().spill(&spilled_parts, &oldest_key).unwrap();
+        let sizes =
+            parts_cache.iter().rev().map(|(_, entry)| entry.estimate_size()).collect::<Vec<_>>();
+        assert_eq!(parts_cache_size(&parts_cache), sizes.iter().sum::<usize>());
+
+        // The spilled entry only frees its metadata, then the entry of height 2 brings the size
+        // under the budget.
+        let budget = sizes[2];
+        assert_eq!(
+            drop_oldest_entries(&mut parts_cache, &spilled_parts, budget),
+            sizes[0] + sizes[1]
+        );
+        assert_eq!(parts_cache.len(), 1);
+        assert_eq!(parts_cache.peek_lru().unwrap().1.estimate_size(), budget);
+        spilled_parts.flush();
+        assert!(store.iter(DBCol::PartialWitnessSpilledParts).next().is_none());
+        assert_eq!(drop_oldest_entries(&mut parts_cache, &spilled_parts, budget), 0);
+    }
+
+    #[test]
+    fn first_part_after_warm_up_reuses_decoder() {
+        const NUM_PARTS: usize = 68;
+        let store = create_test_store();
+        let signer = create_test_signer("test");
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        let partial_witness = PartialEncodedStateWitness::new(
+            EpochId::default(),
+            chunk_header,
+            0,
+            vec![1; 1000],
+            NUM_PARTS * 1000,
+            &signer,
+        );
+        // Mirrors `maybe_insert_new_entry_in_parts_cache` followed by the insertion of the part.
+        let handle_first_part = |encoders: &mut WitnessEncoderCache| {
+            let start = std::time::Instant::now();
+            let mut entry =
+                CacheEntry::new(encoders.entry(NUM_PARTS).unwrap(), false, None, Instant::now());
+            assert!(!entry.insert_part(partial_witness.clone(), direct()));
+            (entry, start.elapsed())
+        };
+
+        let mut cold_encoders = WitnessEncoderCache::new(ReedSolomonBackend::Portable);
+        let (_, cold_latency) = handle_first_part(&mut cold_encoders);
+
+        let mut warm_encoders = WitnessEncoderCache::new(ReedSolomonBackend::Portable);
+        let warmed_up_encoder = warm_encoders.entry(NUM_PARTS).unwrap();
+        let (entry, warm_latency) = handle_first_part(&mut warm_encoders);
+        assert!(Arc::ptr_eq(&entry.encoder, &warmed_up_encoder));
+        tracing::info!(?cold_latency, ?warm_latency, "Latency of handling the first part");
     }
 }
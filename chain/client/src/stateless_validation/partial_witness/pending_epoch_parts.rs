@@ -0,0 +1,161 @@
+//! Parts of the epochs the epoch manager doesn't know yet, validated once it catches up.
+//!
+//! Some deployments back the epoch manager of the actor with a view client, whose epoch info may
+//! be slightly behind the head reported by the client. Around an epoch switch, the lookups of the
+//! chunk validator assignments of the new epoch then fail, and its parts would be rejected while
+//! the other chunk validators accept them. On every head update the actor checks whether the epoch
+//! manager knows the epoch of the head and the next one. The parts of an unknown epoch are
+//! deferred here instead of validated, and validated again once the epoch manager knows it,
+//! checked on the head updates and every `EPOCH_INFO_CHECK_PERIOD` in the meantime.
+
+use std::collections::VecDeque;
+
+use near_async::time::Duration;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::block::Tip;
+use near_primitives::errors::EpochError;
+use near_primitives::types::EpochId;
+
+use crate::metrics;
+
+/// How often the epoch manager is checked again while it doesn't know an epoch of the head.
+pub const EPOCH_INFO_CHECK_PERIOD: Duration = Duration::milliseconds(200);
+
+/// Maximum number of the deferred parts, the oldest part is dropped beyond it. The epoch manager
+/// is expected to catch up within a few blocks, the parts of a handful of witnesses are enough.
+const MAX_DEFERRED_PARTS: usize = 256;
+
+pub struct PendingEpochParts<T> {
+    /// Epoch of the head and the next one, as of the last head update.
+    head_epochs: Vec<EpochId>,
+    /// The epochs of `head_epochs` the epoch manager didn't know at the last check.
+    unknown_epochs: Vec<EpochId>,
+    /// Parts of the unknown epochs, the oldest first.
+    parts: VecDeque<T>,
+}
+
+impl<T> PendingEpochParts<T> {
+    pub fn new() -> Self {
+        Self { head_epochs: vec![], unknown_epochs: vec![], parts: VecDeque::new() }
+    }
+
+    /// Checks the epochs of the new head, see `check`.
+    pub fn on_head_updated(
+        &mut self,
+        head: &Tip,
+        epoch_manager: &dyn EpochManagerAdapter,
+    ) -> Vec<T> {
+        self.head_epochs = vec![head.epoch_id, head.next_epoch_id];
+        self.head_epochs.dedup();
+        self.check(epoch_manager)
+    }
+
+    /// Checks which epochs of the head the epoch manager doesn't know yet. Once they change, e.g.
+    /// the epoch manager caught up, returns the deferred parts to validate again.
+    pub fn check(&mut self, epoch_manager: &dyn EpochManagerAdapter) -> Vec<T> {
+        let unknown_epochs: Vec<_> = self
+            .head_epochs
+            .iter()
+            .filter(|epoch_id| {
+                matches!(
+                    epoch_manager.get_epoch_info(epoch_id),
+                    Err(EpochError::EpochOutOfBounds(_))
+                )
+            })
+            .copied()
+            .collect();
+        if unknown_epochs == self.unknown_epochs {
+            return vec![];
+        }
+        if unknown_epochs.is_empty() {
+            tracing::info!(
+                target: "client",
+                num_parts = self.parts.len(),
+                "Epoch manager caught up with the head, validating the deferred parts"
+            );
+        } else {
+            tracing::warn!(
+                target: "client",
+                ?unknown_epochs,
+                "Epoch manager doesn't know the epoch of the head yet, deferring its parts"
+            );
+        }
+        metrics::PARTIAL_WITNESS_UNKNOWN_HEAD_EPOCHS.set(unknown_epochs.len() as i64);
+        self.unknown_epochs = unknown_epochs;
+        self.parts.drain(..).collect()
+    }
+
+    /// Whether the epoch manager didn't know some epoch of the head at the last check.
+    pub fn is_stale(&self) -> bool {
+        !self.unknown_epochs.is_empty()
+    }
+
+    /// Whether the parts of the epoch should be deferred.
+    pub fn is_unknown(&self, epoch_id: &EpochId) -> bool {
+        self.unknown_epochs.contains(epoch_id)
+    }
+
+    /// Defers the part of an unknown epoch, dropping the oldest part if there are too many.
+    pub fn defer(&mut self, part: T) {
+        metrics::PARTIAL_WITNESS_DEFERRED_PARTS.with_label_values(&["deferred"]).inc();
+        self.parts.push_back(part);
+        if self.parts.len() > MAX_DEFERRED_PARTS {
+            self.parts.pop_front();
+            metrics::PARTIAL_WITNESS_DEFERRED_PARTS.with_label_values(&["dropped"]).inc();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+    use near_primitives::hash::CryptoHash;
+    use near_store::test_utils::create_test_store;
+
+    use super::*;
+
+    fn head(epoch_id: EpochId, next_epoch_id: EpochId) -> Tip {
+        Tip {
+            height: 10,
+            last_block_hash: CryptoHash::hash_bytes(b"head"),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id,
+            next_epoch_id,
+        }
+    }
+
+    #[test]
+    fn parts_are_released_once_the_epoch_manager_catches_up() {
+        let vs = ValidatorSchedule::new()
+            .block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+        let epoch_manager = MockEpochManager::new_with_validators(create_test_store(), vs, 10);
+        let epoch = EpochId(CryptoHash::hash_bytes(b"epoch"));
+        let next_epoch = EpochId(CryptoHash::hash_bytes(b"next"));
+        let mut pending = PendingEpochParts::new();
+        assert!(pending
+            .on_head_updated(&head(epoch, next_epoch), epoch_manager.as_ref())
+            .is_empty());
+        assert!(!pending.is_stale());
+
+        epoch_manager.hide_epoch(next_epoch, true);
+        assert!(pending.check(epoch_manager.as_ref()).is_empty());
+        assert!(pending.is_unknown(&next_epoch));
+        assert!(!pending.is_unknown(&epoch));
+        for part in 0..MAX_DEFERRED_PARTS + 2 {
+            pending.defer(part);
+        }
+        assert_eq!(pending.len(), MAX_DEFERRED_PARTS);
+        // Nothing changed, the parts stay deferred.
+        assert!(pending.check(epoch_manager.as_ref()).is_empty());
+
+        epoch_manager.hide_epoch(next_epoch, false);
+        let released = pending.check(epoch_manager.as_ref());
+        assert_eq!(released, (2..MAX_DEFERRED_PARTS + 2).collect::<Vec<_>>());
+        assert!(!pending.is_stale());
+        assert_eq!(pending.len(), 0);
+    }
+}
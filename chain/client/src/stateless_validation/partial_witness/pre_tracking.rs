@@ -0,0 +1,83 @@
+//! Distribution of the witness parts to the validators pre-tracking a shard, see
+//! `PartialWitnessConfig::pre_tracked_shards`. A validator pre-tracks a shard it is going to
+//! validate in the next epoch, but it isn't a chunk validator of the chunks of the current epoch,
+//! so none of the chunk validators would send it the parts otherwise. Towards the end of the
+//! epoch, the owners of the parts also forward them to the chunk validators of the shard in the
+//! next epoch. The chunk validators are sampled for every height, and both the owners and the
+//! receivers sample them at the height of the chunk, so they agree on who pre-tracks the chunk.
+
+use near_chain::Error;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::block::Tip;
+use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeightDelta};
+
+/// Number of the last heights of the epoch for whose chunks the owners forward their parts to
+/// the validators pre-tracking the shard.
+pub const PRE_TRACKING_HEIGHTS: BlockHeightDelta = 5;
+
+/// Chunk validators of the shard of the chunk in the epoch after the one of the head, or of its
+/// children if the shard layout changes, sampled at the height of the chunk. Empty if the chunk
+/// isn't in the epoch of the head.
+pub fn next_epoch_chunk_validators(
+    epoch_manager: &dyn EpochManagerAdapter,
+    head: &Tip,
+    key: &ChunkProductionKey,
+) -> Result<Vec<AccountId>, Error> {
+    if key.epoch_id != head.epoch_id {
+        return Ok(vec![]);
+    }
+    let next_epoch_id = epoch_manager.get_next_epoch_id(&head.last_block_hash)?;
+    let shard_layout = epoch_manager.get_shard_layout(&key.epoch_id)?;
+    let next_shard_layout = epoch_manager.get_shard_layout(&next_epoch_id)?;
+    let next_shard_ids = if shard_layout == next_shard_layout {
+        vec![key.shard_id]
+    } else {
+        next_shard_layout.get_children_shards_ids(key.shard_id).unwrap_or_default()
+    };
+    let mut chunk_validators = vec![];
+    for shard_id in next_shard_ids {
+        let assignments = epoch_manager.get_chunk_validator_assignments(
+            &next_epoch_id,
+            shard_id,
+            key.height_created,
+        )?;
+        for (account_id, _) in assignments.assignments() {
+            if !chunk_validators.contains(account_id) {
+                chunk_validators.push(account_id.clone());
+            }
+        }
+    }
+    Ok(chunk_validators)
+}
+
+/// Validators to which the owners forward the parts of the chunk for pre-tracking: the
+/// `next_epoch_chunk_validators` which are not chunk validators of the chunk, other than us and
+/// the chunk producer. Empty unless the chunk is within the last `PRE_TRACKING_HEIGHTS` heights
+/// of the epoch of the head.
+pub fn pre_tracking_targets(
+    epoch_manager: &dyn EpochManagerAdapter,
+    head: &Tip,
+    key: &ChunkProductionKey,
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    my_account_id: &AccountId,
+    chunk_producer: &AccountId,
+) -> Result<Vec<AccountId>, Error> {
+    if key.epoch_id != head.epoch_id {
+        return Ok(vec![]);
+    }
+    let epoch_length = epoch_manager.get_epoch_config(&head.epoch_id)?.epoch_length;
+    let epoch_end = epoch_manager.get_epoch_start_height(&head.last_block_hash)? + epoch_length;
+    if key.height_created + PRE_TRACKING_HEIGHTS < epoch_end {
+        return Ok(vec![]);
+    }
+    Ok(next_epoch_chunk_validators(epoch_manager, head, key)?
+        .into_iter()
+        .filter(|account_id| {
+            account_id != my_account_id
+                && account_id != chunk_producer
+                && !chunk_validator_assignments.contains(account_id)
+        })
+        .collect())
+}
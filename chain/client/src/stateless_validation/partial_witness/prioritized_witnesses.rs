@@ -0,0 +1,77 @@
+//! Witnesses the client is blocked on, see `PrioritizeWitness`.
+//!
+//! The parts of a prioritized witness jump the processing lanes of the actor: the parts held
+//! during the sync are handled before the others once the sync is done, the witness is decoded
+//! as soon as it has enough parts instead of waiting for
+//! `PartialWitnessConfig::decode_batch_window`, and it is decoded before the other witnesses
+//! ready at the same time. The prioritization expires after `PRIORITIZATION_TTL`, as by then the
+//! client has either received the witness or stopped waiting for it, and a stale boost would only
+//! delay the other witnesses.
+
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_async::time::{Duration, Instant};
+use near_primitives::stateless_validation::ChunkProductionKey;
+
+/// How long a witness stays prioritized after the client asked for it.
+pub const PRIORITIZATION_TTL: Duration = Duration::seconds(1);
+
+/// Number of witnesses that can be prioritized at once. The client is blocked on a handful of
+/// chunks at most, the least recently prioritized ones are dropped beyond that.
+const PRIORITIZED_WITNESSES_CACHE_SIZE: usize = 20;
+
+pub struct PrioritizedWitnesses {
+    /// Time at which each witness was prioritized.
+    prioritized_at: LruCache<ChunkProductionKey, Instant>,
+}
+
+impl PrioritizedWitnesses {
+    pub fn new() -> Self {
+        Self {
+            prioritized_at: LruCache::new(
+                NonZeroUsize::new(PRIORITIZED_WITNESSES_CACHE_SIZE).unwrap(),
+            ),
+        }
+    }
+
+    /// Prioritizes the witness, or extends its prioritization if it is already prioritized.
+    pub fn prioritize(&mut self, key: ChunkProductionKey, now: Instant) {
+        self.prioritized_at.put(key, now);
+    }
+
+    pub fn is_prioritized(&self, key: &ChunkProductionKey, now: Instant) -> bool {
+        self.prioritized_at.peek(key).is_some_and(|since| now < *since + PRIORITIZATION_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::{Duration, FakeClock, Utc};
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::types::EpochId;
+
+    use super::{PrioritizedWitnesses, PRIORITIZATION_TTL};
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id: 0, epoch_id: EpochId::default(), height_created }
+    }
+
+    #[test]
+    fn prioritization_expires() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut prioritized = PrioritizedWitnesses::new();
+        prioritized.prioritize(key(1), clock.now());
+        assert!(prioritized.is_prioritized(&key(1), clock.now()));
+        assert!(!prioritized.is_prioritized(&key(2), clock.now()));
+
+        clock.advance(PRIORITIZATION_TTL - Duration::milliseconds(1));
+        assert!(prioritized.is_prioritized(&key(1), clock.now()));
+        clock.advance(Duration::milliseconds(1));
+        assert!(!prioritized.is_prioritized(&key(1), clock.now()));
+
+        // Prioritizing the witness again starts a new prioritization.
+        prioritized.prioritize(key(1), clock.now());
+        assert!(prioritized.is_prioritized(&key(1), clock.now()));
+    }
+}
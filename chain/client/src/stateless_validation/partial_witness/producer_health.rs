@@ -0,0 +1,426 @@
+//! Witness distribution health of the chunk producers, as seen by this chunk validator.
+//!
+//! For every chunk producer we count the witnesses we expected from it, i.e. the chunks for which
+//! at least one part or the full witness arrived, how many of them were decoded and how many
+//! failed to decode, and the latency of the first part relative to the expected production time
+//! of the chunk. A chunk at height `h` is expected to be produced once the block at `h - 1`
+//! exists, so the baseline is the timestamp of that block, see `HeadTimeline::block_time`.
+//!
+//! For the parts sent to us directly by the chunk producer in the V2 format, we also record the
+//! send skew, i.e. the time between the send timestamp signed by the producer and the receipt
+//! of the part, which includes both the network latency and the difference between the clocks.
+//! The producers with a high median send skew are flagged, as their witnesses arrive late or
+//! their clock is off. The skew is only used for debugging, see
+//! `PartialEncodedStateWitness::sent_at`.
+//!
+//! For the witnesses in which we own a part, we also count whether our part arrived first in the
+//! message sent to us directly by the producer, or in a forward from a peer. Our parts arriving
+//! mostly in forwards suggest that the direct messages from the producer don't reach us, which
+//! delays our own forwards by one hop for all the other chunk validators.
+//!
+//! The statistics are kept in two buckets, one for the epoch of the head and one for the previous
+//! epoch, and the reported values are the sum of both, so they always cover at least the last
+//! full epoch.
+
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use near_async::time::Duration;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, EpochId};
+
+use crate::metrics;
+
+/// Number of the most recent witnesses for which we remember the producer, so that every witness
+/// is counted as expected and as decoded at most once.
+const SEEN_WITNESSES_CACHE_SIZE: usize = 1000;
+
+/// Minimum number of witnesses expected from a chunk producer for it to be considered in the
+/// worst producer metrics, so that a producer with a single unlucky chunk doesn't stand out.
+const MIN_WITNESSES_FOR_WORST_PRODUCER: u64 = 10;
+
+/// Number of the most recent send skews kept per chunk producer and epoch bucket to compute the
+/// median send skew.
+const SEND_SKEW_SAMPLES: usize = 100;
+
+/// Message in which a witness part reached us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartDelivery {
+    /// The message sent to us directly by the chunk producer.
+    Direct,
+    /// The forward of another chunk validator, or of the chunk producer.
+    Forward,
+}
+
+impl PartDelivery {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartDelivery::Direct => "direct",
+            PartDelivery::Forward => "forward",
+        }
+    }
+}
+
+/// Distribution health of a single chunk producer, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProducerDistributionHealth {
+    pub account_id: AccountId,
+    pub witnesses_expected: u64,
+    pub witnesses_decoded: u64,
+    pub decode_failures: u64,
+    /// Average latency of the first part relative to the expected production time of the chunk,
+    /// None if the production time couldn't be estimated for any of the witnesses.
+    pub avg_first_part_latency: Option<Duration>,
+    /// Median send skew of the parts sent to us directly, None if the producer didn't send any
+    /// part in the V2 format.
+    pub median_send_skew: Option<Duration>,
+    /// Whether the median send skew exceeds `PartialWitnessConfig::high_send_skew_threshold`.
+    pub high_send_skew: bool,
+    /// Witnesses in which our own part arrived first in the message sent directly by the producer.
+    pub owned_parts_direct_first: u64,
+    /// Witnesses in which our own part arrived first in a forward.
+    pub owned_parts_forward_first: u64,
+}
+
+impl ProducerDistributionHealth {
+    /// Fraction of the expected witnesses which were decoded.
+    pub fn decode_ratio(&self) -> f64 {
+        if self.witnesses_expected == 0 {
+            return 1.0;
+        }
+        self.witnesses_decoded as f64 / self.witnesses_expected as f64
+    }
+}
+
+#[derive(Default, Clone)]
+struct ProducerStats {
+    witnesses_expected: u64,
+    witnesses_decoded: u64,
+    decode_failures: u64,
+    first_part_latency_sum: Duration,
+    first_part_latency_samples: u64,
+    /// Most recent send skews, clamped to zero.
+    send_skews: VecDeque<Duration>,
+    owned_parts_direct_first: u64,
+    owned_parts_forward_first: u64,
+}
+
+impl ProducerStats {
+    fn add(&mut self, other: &ProducerStats) {
+        self.witnesses_expected += other.witnesses_expected;
+        self.witnesses_decoded += other.witnesses_decoded;
+        self.decode_failures += other.decode_failures;
+        self.first_part_latency_sum += other.first_part_latency_sum;
+        self.first_part_latency_samples += other.first_part_latency_samples;
+        self.send_skews.extend(other.send_skews.iter().copied());
+        self.owned_parts_direct_first += other.owned_parts_direct_first;
+        self.owned_parts_forward_first += other.owned_parts_forward_first;
+    }
+
+    fn median_send_skew(&self) -> Option<Duration> {
+        let mut send_skews = self.send_skews.iter().copied().collect::<Vec<_>>();
+        send_skews.sort();
+        send_skews.get(send_skews.len() / 2).copied()
+    }
+}
+
+/// Witness counted in the statistics.
+struct SeenWitness {
+    producer: AccountId,
+    /// Epoch bucket in which the witness was counted as expected.
+    generation: u64,
+    /// Whether the witness was already counted as decoded or failed.
+    done: bool,
+}
+
+pub struct ProducerHealthTracker {
+    /// Epoch of the head, the statistics of the witnesses seen since the head entered it are in
+    /// `current`, the ones of the previous epoch in `previous`.
+    head_epoch_id: Option<EpochId>,
+    /// Incremented on every epoch change, identifies the `current` bucket.
+    generation: u64,
+    current: HashMap<AccountId, ProducerStats>,
+    previous: HashMap<AccountId, ProducerStats>,
+    seen: LruCache<ChunkProductionKey, SeenWitness>,
+    /// See `PartialWitnessConfig::high_send_skew_threshold`.
+    high_send_skew_threshold: Duration,
+}
+
+impl ProducerHealthTracker {
+    pub fn new(high_send_skew_threshold: Duration) -> Self {
+        Self {
+            head_epoch_id: None,
+            generation: 0,
+            current: HashMap::new(),
+            previous: HashMap::new(),
+            seen: LruCache::new(NonZeroUsize::new(SEEN_WITNESSES_CACHE_SIZE).unwrap()),
+            high_send_skew_threshold,
+        }
+    }
+
+    /// Records the epoch of the new head of the chain, rotating the statistics when the head
+    /// enters a new epoch, and exports the worst producer metrics.
+    pub fn on_head_updated(&mut self, epoch_id: EpochId) {
+        if self.head_epoch_id != Some(epoch_id) {
+            if self.head_epoch_id.is_some() {
+                self.previous = std::mem::take(&mut self.current);
+                self.generation += 1;
+            }
+            self.head_epoch_id = Some(epoch_id);
+        }
+        self.export_worst_producer_metrics();
+    }
+
+    /// Records the first part, or the full witness, received for the witness produced by
+    /// `producer`, with its latency relative to the expected production time of the chunk if
+    /// known. Does nothing if the witness was already seen.
+    pub fn on_first_part(
+        &mut self,
+        key: &ChunkProductionKey,
+        producer: AccountId,
+        latency: Option<Duration>,
+    ) {
+        if self.seen.contains(key) {
+            return;
+        }
+        let stats = self.current.entry(producer.clone()).or_default();
+        stats.witnesses_expected += 1;
+        if let Some(latency) = latency {
+            stats.first_part_latency_sum += latency;
+            stats.first_part_latency_samples += 1;
+        }
+        self.seen
+            .put(key.clone(), SeenWitness { producer, generation: self.generation, done: false });
+    }
+
+    /// Records the send skew of a part sent to us directly by `producer`, clamped to zero.
+    pub fn on_send_skew(&mut self, producer: AccountId, send_skew: Duration) {
+        let send_skews = &mut self.current.entry(producer).or_default().send_skews;
+        if send_skews.len() == SEND_SKEW_SAMPLES {
+            send_skews.pop_front();
+        }
+        send_skews.push_back(send_skew.max(Duration::ZERO));
+    }
+
+    /// Records the message in which our own part of a witness produced by `producer` arrived
+    /// first. Called at most once per witness.
+    pub fn on_owned_part_delivery(&mut self, producer: AccountId, delivery: PartDelivery) {
+        let stats = self.current.entry(producer).or_default();
+        match delivery {
+            PartDelivery::Direct => stats.owned_parts_direct_first += 1,
+            PartDelivery::Forward => stats.owned_parts_forward_first += 1,
+        }
+    }
+
+    /// Records that the witness was decoded and sent to the client.
+    pub fn on_decoded(&mut self, key: &ChunkProductionKey) {
+        if let Some(stats) = self.take_unfinished_stats(key) {
+            stats.witnesses_decoded += 1;
+        }
+    }
+
+    /// Records that decoding the witness failed, or that the decoded witness was invalid.
+    pub fn on_decode_failure(&mut self, key: &ChunkProductionKey) {
+        if let Some(stats) = self.take_unfinished_stats(key) {
+            stats.decode_failures += 1;
+        }
+    }
+
+    /// Returns the statistics of the producer of the witness, unless the witness was already
+    /// decoded or failed, or its bucket was dropped in the meantime.
+    fn take_unfinished_stats(&mut self, key: &ChunkProductionKey) -> Option<&mut ProducerStats> {
+        let seen = self.seen.get_mut(key)?;
+        if seen.done {
+            return None;
+        }
+        seen.done = true;
+        let bucket = if seen.generation == self.generation {
+            &mut self.current
+        } else if seen.generation + 1 == self.generation {
+            &mut self.previous
+        } else {
+            return None;
+        };
+        bucket.get_mut(&seen.producer)
+    }
+
+    /// Statistics of all the chunk producers, starting from the worst decode ratio.
+    pub fn health(&self) -> Vec<ProducerDistributionHealth> {
+        let mut stats: HashMap<&AccountId, ProducerStats> = HashMap::new();
+        for (account_id, producer_stats) in self.previous.iter().chain(self.current.iter()) {
+            stats.entry(account_id).or_default().add(producer_stats);
+        }
+        let mut health: Vec<ProducerDistributionHealth> = stats
+            .into_iter()
+            .map(|(account_id, stats)| {
+                let median_send_skew = stats.median_send_skew();
+                ProducerDistributionHealth {
+                    account_id: account_id.clone(),
+                    witnesses_expected: stats.witnesses_expected,
+                    witnesses_decoded: stats.witnesses_decoded,
+                    decode_failures: stats.decode_failures,
+                    avg_first_part_latency: (stats.first_part_latency_samples > 0).then(|| {
+                        stats.first_part_latency_sum / stats.first_part_latency_samples as f64
+                    }),
+                    median_send_skew,
+                    high_send_skew: median_send_skew
+                        .is_some_and(|median| median > self.high_send_skew_threshold),
+                    owned_parts_direct_first: stats.owned_parts_direct_first,
+                    owned_parts_forward_first: stats.owned_parts_forward_first,
+                }
+            })
+            .collect();
+        health.sort_by(|a, b| {
+            a.decode_ratio()
+                .total_cmp(&b.decode_ratio())
+                .then_with(|| b.avg_first_part_latency.cmp(&a.avg_first_part_latency))
+                .then_with(|| a.account_id.cmp(&b.account_id))
+        });
+        health
+    }
+
+    fn export_worst_producer_metrics(&self) {
+        let health = self.health();
+        let candidates = health
+            .iter()
+            .filter(|health| health.witnesses_expected >= MIN_WITNESSES_FOR_WORST_PRODUCER);
+        let mut worst_decode_ratio = 1.0f64;
+        let mut worst_latency = Duration::ZERO;
+        for health in candidates {
+            worst_decode_ratio = worst_decode_ratio.min(health.decode_ratio());
+            if let Some(latency) = health.avg_first_part_latency {
+                worst_latency = worst_latency.max(latency);
+            }
+        }
+        metrics::PARTIAL_WITNESS_WORST_PRODUCER_DECODE_RATIO.set(worst_decode_ratio);
+        metrics::PARTIAL_WITNESS_WORST_PRODUCER_FIRST_PART_LATENCY
+            .set(worst_latency.as_seconds_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::BlockHeight;
+
+    fn key(height_created: BlockHeight) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id: 0, epoch_id: EpochId::default(), height_created }
+    }
+
+    fn epoch(id: u8) -> EpochId {
+        EpochId(CryptoHash([id; 32]))
+    }
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    fn millis(millis: i64) -> Option<Duration> {
+        Some(Duration::milliseconds(millis))
+    }
+
+    #[test]
+    fn witnesses_are_counted_once_per_producer() {
+        let mut tracker = ProducerHealthTracker::new(Duration::seconds(1));
+        tracker.on_head_updated(epoch(1));
+        tracker.on_first_part(&key(11), account("alice"), millis(100));
+        tracker.on_first_part(&key(11), account("alice"), millis(500));
+        tracker.on_decoded(&key(11));
+        tracker.on_decoded(&key(11));
+        tracker.on_first_part(&key(12), account("bob"), None);
+        tracker.on_decode_failure(&key(12));
+        tracker.on_first_part(&key(13), account("bob"), millis(300));
+
+        let health = tracker.health();
+        assert_eq!(health.len(), 2);
+        // Bob delivered only one of his two witnesses, and that one didn't decode.
+        assert_eq!(health[0].account_id, account("bob"));
+        assert_eq!(health[0].witnesses_expected, 2);
+        assert_eq!(health[0].witnesses_decoded, 0);
+        assert_eq!(health[0].decode_failures, 1);
+        // Only the witnesses with a known latency count towards the average.
+        assert_eq!(health[0].avg_first_part_latency, millis(300));
+        assert_eq!(health[1].account_id, account("alice"));
+        assert_eq!(health[1].witnesses_expected, 1);
+        assert_eq!(health[1].witnesses_decoded, 1);
+        assert_eq!(health[1].decode_ratio(), 1.0);
+        assert_eq!(health[1].avg_first_part_latency, millis(100));
+    }
+
+    #[test]
+    fn equal_decode_ratios_are_ordered_by_latency() {
+        let mut tracker = ProducerHealthTracker::new(Duration::seconds(1));
+        tracker.on_head_updated(epoch(1));
+        tracker.on_first_part(&key(11), account("alice"), millis(100));
+        tracker.on_first_part(&key(12), account("bob"), millis(400));
+        tracker.on_first_part(&key(13), account("bob"), millis(200));
+        let health = tracker.health();
+        assert_eq!(health[0].account_id, account("bob"));
+        assert_eq!(health[0].avg_first_part_latency, millis(300));
+        assert_eq!(health[1].account_id, account("alice"));
+    }
+
+    #[test]
+    fn producers_with_high_median_send_skew_are_flagged() {
+        let mut tracker = ProducerHealthTracker::new(Duration::milliseconds(500));
+        tracker.on_head_updated(epoch(1));
+        for send_skew in [100, 200, 2000] {
+            tracker.on_send_skew(account("alice"), Duration::milliseconds(send_skew));
+        }
+        for send_skew in [-300, 700, 900] {
+            tracker.on_send_skew(account("bob"), Duration::milliseconds(send_skew));
+        }
+        let health = tracker.health();
+        let alice = health.iter().find(|health| health.account_id == account("alice")).unwrap();
+        assert_eq!(alice.median_send_skew, millis(200));
+        assert!(!alice.high_send_skew);
+        let bob = health.iter().find(|health| health.account_id == account("bob")).unwrap();
+        assert_eq!(bob.median_send_skew, millis(700));
+        assert!(bob.high_send_skew);
+
+        tracker.on_first_part(&key(11), account("carol"), None);
+        let carol =
+            tracker.health().into_iter().find(|health| health.account_id == account("carol"));
+        assert_eq!(carol.unwrap().median_send_skew, None);
+    }
+
+    #[test]
+    fn owned_part_deliveries_are_counted_per_producer() {
+        let mut tracker = ProducerHealthTracker::new(Duration::seconds(1));
+        tracker.on_head_updated(epoch(1));
+        tracker.on_owned_part_delivery(account("alice"), PartDelivery::Direct);
+        tracker.on_owned_part_delivery(account("alice"), PartDelivery::Direct);
+        tracker.on_owned_part_delivery(account("bob"), PartDelivery::Forward);
+        tracker.on_head_updated(epoch(2));
+        tracker.on_owned_part_delivery(account("bob"), PartDelivery::Direct);
+
+        let health = tracker.health();
+        let alice = health.iter().find(|health| health.account_id == account("alice")).unwrap();
+        assert_eq!((alice.owned_parts_direct_first, alice.owned_parts_forward_first), (2, 0));
+        let bob = health.iter().find(|health| health.account_id == account("bob")).unwrap();
+        assert_eq!((bob.owned_parts_direct_first, bob.owned_parts_forward_first), (1, 1));
+    }
+
+    #[test]
+    fn statistics_cover_the_last_epoch() {
+        let mut tracker = ProducerHealthTracker::new(Duration::seconds(1));
+        tracker.on_head_updated(epoch(1));
+        tracker.on_first_part(&key(11), account("alice"), None);
+        tracker.on_head_updated(epoch(2));
+        // The witness of the previous epoch is still accounted to the previous epoch.
+        tracker.on_decoded(&key(11));
+        tracker.on_first_part(&key(12), account("alice"), None);
+        let health = tracker.health();
+        assert_eq!(health[0].witnesses_expected, 2);
+        assert_eq!(health[0].witnesses_decoded, 1);
+
+        // Once the head moves to the next epoch, the statistics of epoch 1 are dropped.
+        tracker.on_head_updated(epoch(3));
+        tracker.on_decoded(&key(12));
+        let health = tracker.health();
+        assert_eq!(health[0].witnesses_expected, 1);
+        assert_eq!(health[0].witnesses_decoded, 1);
+    }
+}
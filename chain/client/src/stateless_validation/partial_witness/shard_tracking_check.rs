@@ -0,0 +1,122 @@
+//! Consistency check between the shard tracking configuration of the node and its chunk validator
+//! duties. A node which is a chunk validator of a shard it neither tracks nor produces chunks for
+//! receives the witnesses of the shard, but fails to validate them deep in the client, long after
+//! the parts were received and forwarded. The check runs once per epoch, on the first head of the
+//! epoch, and only reports the misconfiguration, the handling of the witnesses is unchanged.
+
+use near_primitives::types::{EpochId, ShardId};
+
+/// Number of heights after the head at which the chunk validator assignments are checked. The
+/// chunk validators are sampled for every height, so a few heights are enough to tell the shards
+/// the node validates without loading the assignments of the whole epoch.
+pub const SHARD_TRACKING_CHECK_HEIGHTS: u64 = 5;
+
+/// Relation of the node to a shard in the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardDuties {
+    pub shard_id: ShardId,
+    /// The node is a chunk validator of the shard at one of the checked heights.
+    pub chunk_validator: bool,
+    /// The shard is tracked according to the tracked shards configuration.
+    pub tracked: bool,
+    /// The node is a chunk producer of the shard, which makes the node track it.
+    pub chunk_producer: bool,
+}
+
+impl ShardDuties {
+    /// Whether the node receives the witnesses of the shard without tracking the shard.
+    pub fn is_untracked_validated(&self) -> bool {
+        self.chunk_validator && !self.tracked && !self.chunk_producer
+    }
+}
+
+/// Shards the node receives the witnesses for but can't validate them, see `ShardDuties`.
+pub fn untracked_validated_shards(duties: &[ShardDuties]) -> Vec<ShardId> {
+    duties
+        .iter()
+        .filter(|duties| duties.is_untracked_validated())
+        .map(|duties| duties.shard_id)
+        .collect()
+}
+
+/// Remembers the epoch checked last, so that the check runs once per epoch.
+pub struct ShardTrackingCheck {
+    checked_epoch_id: Option<EpochId>,
+}
+
+impl ShardTrackingCheck {
+    pub fn new() -> Self {
+        Self { checked_epoch_id: None }
+    }
+
+    /// Whether the epoch of the new head wasn't checked yet.
+    pub fn is_due(&self, epoch_id: &EpochId) -> bool {
+        self.checked_epoch_id.as_ref() != Some(epoch_id)
+    }
+
+    pub fn on_checked(&mut self, epoch_id: EpochId) {
+        self.checked_epoch_id = Some(epoch_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::EpochId;
+
+    use super::{untracked_validated_shards, ShardDuties, ShardTrackingCheck};
+
+    #[test]
+    fn mismatch_matrix() {
+        for chunk_validator in [false, true] {
+            for tracked in [false, true] {
+                for chunk_producer in [false, true] {
+                    let duties =
+                        ShardDuties { shard_id: 0, chunk_validator, tracked, chunk_producer };
+                    // Only a chunk validator can't validate a shard, and only if the shard isn't
+                    // tracked either through the configuration or as a chunk producer.
+                    let expected = chunk_validator && !tracked && !chunk_producer;
+                    assert_eq!(duties.is_untracked_validated(), expected, "{duties:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn untracked_validated_shards_are_listed() {
+        let duties = |shard_id, chunk_validator, tracked| ShardDuties {
+            shard_id,
+            chunk_validator,
+            tracked,
+            chunk_producer: false,
+        };
+        // Tracking no shards while being a chunk validator.
+        assert_eq!(
+            untracked_validated_shards(&[duties(0, true, false), duties(1, true, false)]),
+            vec![0, 1]
+        );
+        // Tracking some of the shards validated.
+        assert_eq!(
+            untracked_validated_shards(&[
+                duties(0, true, true),
+                duties(1, true, false),
+                duties(2, false, false)
+            ]),
+            vec![1]
+        );
+        assert!(untracked_validated_shards(&[duties(0, false, false), duties(1, true, true)])
+            .is_empty());
+    }
+
+    #[test]
+    fn check_is_due_once_per_epoch() {
+        let epoch = |id: u8| EpochId(CryptoHash([id; 32]));
+        let mut check = ShardTrackingCheck::new();
+        assert!(check.is_due(&epoch(1)));
+        check.on_checked(epoch(1));
+        assert!(!check.is_due(&epoch(1)));
+        assert!(check.is_due(&epoch(2)));
+        check.on_checked(epoch(2));
+        assert!(!check.is_due(&epoch(2)));
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use near_chain_configs::MutableValidatorSigner;
+use near_crypto::PublicKey;
+use near_primitives::types::AccountId;
+use near_primitives::validator_signer::ValidatorSigner;
+
+/// Snapshot of the validator signer taken when we start producing the witness parts.
+///
+/// The signer may be swapped, e.g. on a key rotation, while the witness is being encoded and
+/// the parts signed. The parts signed with the snapshot must then be dropped instead of sent,
+/// so check `is_current` right before sending them. The snapshot must not outlive the request.
+pub struct SignerSnapshot {
+    signer: Arc<ValidatorSigner>,
+    account_id: AccountId,
+    public_key: PublicKey,
+}
+
+impl SignerSnapshot {
+    /// Takes the snapshot of the current signer, None if we are not a validator.
+    pub fn take(my_signer: &MutableValidatorSigner) -> Option<Self> {
+        let signer = my_signer.get()?;
+        let account_id = signer.validator_id().clone();
+        let public_key = signer.public_key();
+        Some(Self { signer, account_id, public_key })
+    }
+
+    pub fn signer(&self) -> &ValidatorSigner {
+        &self.signer
+    }
+
+    /// Whether the current signer still has the account id and the key of the snapshot.
+    pub fn is_current(&self, my_signer: &MutableValidatorSigner) -> bool {
+        my_signer.get().is_some_and(|signer| {
+            signer.validator_id() == &self.account_id && signer.public_key() == self.public_key
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use near_chain_configs::MutableConfigValue;
+    use near_crypto::KeyType;
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::validator_signer::InMemoryValidatorSigner;
+
+    use super::*;
+
+    fn mutable_signer(signer: ValidatorSigner) -> MutableValidatorSigner {
+        MutableConfigValue::new(Some(Arc::new(signer)), "validator_signer")
+    }
+
+    #[test]
+    fn snapshot_is_stale_after_signer_swap_during_encode() {
+        let my_signer = mutable_signer(create_test_signer("test0"));
+        let snapshot = SignerSnapshot::take(&my_signer).unwrap();
+        assert!(snapshot.is_current(&my_signer));
+
+        // Rotate the key of the same account while a slow encode is running on another thread.
+        let (encode_started_sender, encode_started) = mpsc::channel();
+        let (key_rotated_sender, key_rotated) = mpsc::channel();
+        let encode = std::thread::spawn(move || {
+            encode_started_sender.send(()).unwrap();
+            key_rotated.recv().unwrap();
+        });
+        encode_started.recv().unwrap();
+        let rotated_signer = InMemoryValidatorSigner::from_seed(
+            "test0".parse().unwrap(),
+            KeyType::ED25519,
+            "rotated",
+        );
+        my_signer.update(Some(Arc::new(rotated_signer.into())));
+        key_rotated_sender.send(()).unwrap();
+        encode.join().unwrap();
+
+        assert!(!snapshot.is_current(&my_signer));
+        assert!(SignerSnapshot::take(&my_signer).unwrap().is_current(&my_signer));
+    }
+
+    #[test]
+    fn snapshot_is_stale_after_signer_removal() {
+        let my_signer = mutable_signer(create_test_signer("test0"));
+        let snapshot = SignerSnapshot::take(&my_signer).unwrap();
+        my_signer.update(None);
+        assert!(!snapshot.is_current(&my_signer));
+        assert!(SignerSnapshot::take(&my_signer).is_none());
+    }
+}
@@ -0,0 +1,242 @@
+//! Witness parts spilled to `DBCol::PartialWitnessSpilledParts` under memory pressure, see
+//! `PartialWitnessConfig::spill_to_disk`.
+//!
+//! The parts are written and deleted by a dedicated thread, so that the actor never waits for the
+//! database. A spilled part stays in memory until the thread commits it, so it can be restored
+//! right away, and nothing more is spilled while too many spills wait for the thread. The spilled
+//! parts are only meaningful together with the entries of the tracker, so the column is cleared at
+//! the startup of the node, see `clear_spilled_witness_parts`.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_store::{DBCol, Store};
+
+/// Number of the witnesses whose spilled parts wait for the thread above which no more parts are
+/// spilled, so that the parts waiting in memory stay bounded.
+const MAX_PENDING_SPILLS: usize = 16;
+
+type SpillKey = Vec<u8>;
+
+/// Parts spilled at once, shared by the queue of the thread and the pending parts.
+type SpillBatch = Arc<Vec<(usize, Box<[u8]>)>>;
+
+enum SpillOp {
+    Write(SpillKey, SpillBatch),
+    Delete(SpillKey),
+    /// Answers once all the operations queued before are done.
+    #[cfg(test)]
+    Flush(Sender<()>),
+}
+
+pub(super) struct SpilledParts {
+    store: Store,
+    /// Batches queued for the thread and not committed yet, per witness.
+    pending: Arc<Mutex<HashMap<SpillKey, Vec<SpillBatch>>>>,
+    sender: Sender<SpillOp>,
+}
+
+impl SpilledParts {
+    /// Starts the thread writing to the store. The thread stops once `SpilledParts` is dropped.
+    pub fn spawn(store: Store) -> Self {
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let writer_store = store.clone();
+        let writer_pending = pending.clone();
+        std::thread::Builder::new()
+            .name("witness_parts_spill".to_string())
+            .spawn(move || run_writer(writer_store, writer_pending, receiver))
+            .expect("failed to spawn the thread spilling the witness parts");
+        Self { store, pending, sender }
+    }
+
+    /// Queues the parts for writing. Returns them back if too many spills wait for the thread.
+    pub fn spill(
+        &self,
+        key: &ChunkProductionKey,
+        parts: Vec<(usize, Box<[u8]>)>,
+    ) -> Result<(), Vec<(usize, Box<[u8]>)>> {
+        let key = spill_key(key);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING_SPILLS && !pending.contains_key(&key) {
+            return Err(parts);
+        }
+        let batch = Arc::new(parts);
+        pending.entry(key.clone()).or_default().push(batch.clone());
+        // The thread only stops once we are dropped.
+        self.sender.send(SpillOp::Write(key, batch)).unwrap();
+        Ok(())
+    }
+
+    /// Reads the spilled parts of the witness, both the committed ones and the ones waiting for
+    /// the thread, without removing them.
+    pub fn read(&self, key: &ChunkProductionKey) -> std::io::Result<Vec<(usize, Box<[u8]>)>> {
+        let key = spill_key(key);
+        let mut parts: Vec<(usize, Box<[u8]>)> = self
+            .pending
+            .lock()
+            .unwrap()
+            .get(&key)
+            .into_iter()
+            .flatten()
+            .flat_map(|batch| batch.iter().cloned())
+            .collect();
+        parts.extend(self.read_committed(&key)?);
+        Ok(dedup_parts(parts))
+    }
+
+    /// Takes the spilled parts of the witness and queues their deletion.
+    pub fn restore(&self, key: &ChunkProductionKey) -> std::io::Result<Vec<(usize, Box<[u8]>)>> {
+        let spill_key = spill_key(key);
+        let batches = self.pending.lock().unwrap().remove(&spill_key).unwrap_or_default();
+        let mut parts: Vec<(usize, Box<[u8]>)> =
+            batches.into_iter().flat_map(Arc::unwrap_or_clone).collect();
+        parts.extend(self.read_committed(&spill_key)?);
+        if parts.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("spilled witness parts not found for {key:?}"),
+            ));
+        }
+        self.delete(key);
+        Ok(dedup_parts(parts))
+    }
+
+    /// Forgets the spilled parts of the witness and queues their deletion.
+    pub fn delete(&self, key: &ChunkProductionKey) {
+        let key = spill_key(key);
+        self.pending.lock().unwrap().remove(&key);
+        self.sender.send(SpillOp::Delete(key)).unwrap();
+    }
+
+    /// Waits for the thread to finish the operations queued so far.
+    #[cfg(test)]
+    pub fn flush(&self) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.sender.send(SpillOp::Flush(sender)).unwrap();
+        receiver.recv().unwrap();
+    }
+
+    fn read_committed(&self, key: &[u8]) -> std::io::Result<Vec<(usize, Box<[u8]>)>> {
+        Ok(self.store.get_ser(DBCol::PartialWitnessSpilledParts, key)?.unwrap_or_default())
+    }
+}
+
+/// Deletes the parts spilled by a previous run of the node, e.g. before a crash.
+pub fn clear_spilled_witness_parts(store: &Store) -> std::io::Result<()> {
+    let mut store_update = store.store_update();
+    store_update.delete_all(DBCol::PartialWitnessSpilledParts);
+    store_update.commit()
+}
+
+fn run_writer(
+    store: Store,
+    pending: Arc<Mutex<HashMap<SpillKey, Vec<SpillBatch>>>>,
+    receiver: Receiver<SpillOp>,
+) {
+    for op in receiver {
+        match op {
+            SpillOp::Write(key, batch) => {
+                if let Err(err) = write_batch(&store, &key, &batch) {
+                    // The parts stay pending in memory until the witness is restored or dropped.
+                    tracing::warn!(target: "client", ?err, "Failed to spill witness parts");
+                    continue;
+                }
+                let mut pending = pending.lock().unwrap();
+                if let Some(batches) = pending.get_mut(&key) {
+                    batches.retain(|pending_batch| !Arc::ptr_eq(pending_batch, &batch));
+                    if batches.is_empty() {
+                        pending.remove(&key);
+                    }
+                }
+            }
+            SpillOp::Delete(key) => {
+                let mut store_update = store.store_update();
+                store_update.delete(DBCol::PartialWitnessSpilledParts, &key);
+                if let Err(err) = store_update.commit() {
+                    tracing::warn!(
+                        target: "client",
+                        ?err,
+                        "Failed to delete spilled witness parts"
+                    );
+                }
+            }
+            #[cfg(test)]
+            SpillOp::Flush(sender) => {
+                let _ = sender.send(());
+            }
+        }
+    }
+}
+
+/// Appends the parts of the batch to the parts of the witness spilled before.
+fn write_batch(store: &Store, key: &[u8], batch: &[(usize, Box<[u8]>)]) -> std::io::Result<()> {
+    let mut parts: Vec<(usize, Box<[u8]>)> =
+        store.get_ser(DBCol::PartialWitnessSpilledParts, key)?.unwrap_or_default();
+    parts.extend(batch.iter().cloned());
+    let mut store_update = store.store_update();
+    store_update.set_ser(DBCol::PartialWitnessSpilledParts, key, &parts)?;
+    store_update.commit()
+}
+
+/// Sorts the parts by part_ord. A part committed by the thread while it is read is both pending
+/// and committed, so it is only kept once.
+fn dedup_parts(mut parts: Vec<(usize, Box<[u8]>)>) -> Vec<(usize, Box<[u8]>)> {
+    parts.sort_by_key(|(part_ord, _)| *part_ord);
+    parts.dedup_by_key(|(part_ord, _)| *part_ord);
+    parts
+}
+
+fn spill_key(key: &ChunkProductionKey) -> SpillKey {
+    let mut res = Vec::with_capacity(48);
+    res.extend_from_slice(key.epoch_id.0.as_ref());
+    res.extend_from_slice(&key.shard_id.to_be_bytes());
+    res.extend_from_slice(&key.height_created.to_be_bytes());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::EpochId;
+    use near_store::test_utils::create_test_store;
+
+    use super::*;
+
+    fn parts(part_ords: &[usize]) -> Vec<(usize, Box<[u8]>)> {
+        part_ords.iter().map(|&part_ord| (part_ord, vec![part_ord as u8; 10].into())).collect()
+    }
+
+    #[test]
+    fn spilled_parts_are_restored_whether_written_or_not() {
+        let store = create_test_store();
+        let spilled_parts = SpilledParts::spawn(store.clone());
+        let key =
+            ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 0, height_created: 5 };
+
+        spilled_parts.spill(&key, parts(&[3, 1])).unwrap();
+        spilled_parts.flush();
+        spilled_parts.spill(&key, parts(&[2])).unwrap();
+        assert_eq!(spilled_parts.read(&key).unwrap(), parts(&[1, 2, 3]));
+        assert_eq!(spilled_parts.restore(&key).unwrap(), parts(&[1, 2, 3]));
+
+        spilled_parts.flush();
+        assert!(store.iter(DBCol::PartialWitnessSpilledParts).next().is_none());
+        assert!(spilled_parts.restore(&key).is_err());
+    }
+
+    #[test]
+    fn parts_left_by_previous_run_are_cleared() {
+        let store = create_test_store();
+        let spilled_parts = SpilledParts::spawn(store.clone());
+        let key =
+            ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 1, height_created: 7 };
+        spilled_parts.spill(&key, parts(&[0])).unwrap();
+        spilled_parts.flush();
+        drop(spilled_parts);
+
+        clear_spilled_witness_parts(&store).unwrap();
+        assert!(store.iter(DBCol::PartialWitnessSpilledParts).next().is_none());
+    }
+}
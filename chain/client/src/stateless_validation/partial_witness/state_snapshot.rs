@@ -0,0 +1,65 @@
+//! Snapshot of the transient state of the `PartialWitnessActor`, see
+//! `PartialWitnessActor::snapshot`. The snapshot lets a node restarted on a new binary pick up the
+//! witnesses which were in flight, e.g. when testing the hot code reload.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+
+/// Versioned snapshot of the `PartialWitnessActor` state. A binary which doesn't know the version
+/// of the snapshot fails to deserialize it, and must discard it.
+///
+/// The following is deliberately left out of the snapshot:
+/// - the deadlines and the creation times of the incomplete witnesses, `Instant` has no meaning
+///   outside of the process; the restored witnesses get a fresh deadline instead,
+/// - the Reed Solomon encoders, rebuilt from the number of parts,
+/// - the processed, acked and expired witnesses; the client of the new binary starts without
+///   the witnesses sent to the old one, so they must be decodable again,
+/// - the parts we own and their requesters; we forward our parts as soon as we get them, and
+///   the chunk validators missing them can get them from the chunk producer,
+/// - the statistics: witness lifecycles and outcomes, producer health, section sizes, summaries,
+///   the head timeline and the metrics,
+/// - the announced unavailability of the receivers, which is announced again on restart,
+/// - whether a witness is ready to be decoded, see `PartialWitnessConfig::decode_batch_window`;
+///   the encoded length is only carried by the parts, so the restored witness is decoded once
+///   another of its parts arrives.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub enum PartialWitnessState {
+    V1(PartialWitnessStateV1),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct PartialWitnessStateV1 {
+    /// Witnesses whose parts we were collecting, from the least to the most recently used.
+    pub incomplete_witnesses: Vec<IncompleteWitnessSnapshot>,
+    /// All the parts of the witnesses produced by us, ordered by part_ord, from the least to the
+    /// most recently produced witness. The parts are kept signed, so they can be re-sent as is.
+    pub produced_parts: Vec<Vec<PartialEncodedStateWitness>>,
+}
+
+/// Raw parts of a witness not decoded yet. The parts are already validated, so only the part
+/// bytes are kept; the encoded length is carried by the parts still to arrive.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct IncompleteWitnessSnapshot {
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    /// See `PartialWitnessConfig::pre_tracked_shards`.
+    pub pre_tracking: bool,
+    /// Witness hash signed by the chunk producer, see `ProtocolFeature::WitnessChecksum`.
+    pub witness_hash: Option<CryptoHash>,
+    /// Parts received so far as (part_ord, part), including the parts spilled to the database.
+    pub parts: Vec<(usize, Box<[u8]>)>,
+}
+
+impl IncompleteWitnessSnapshot {
+    pub fn chunk_production_key(&self) -> ChunkProductionKey {
+        ChunkProductionKey {
+            epoch_id: self.epoch_id,
+            shard_id: self.shard_id,
+            height_created: self.height_created,
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Export of the sizes of the witnesses produced and reconstructed by the node, for analyzing the
+//! growth of the witnesses offline, see `PartialWitnessConfig::export_witness_stats_dir`.
+//!
+//! Every witness is exported as a `WitnessStatsRecord` serialized as a JSON line, the witnesses
+//! themselves are not exported. The records are appended to `witness_stats.<index>.jsonl` files in
+//! the export directory, a new file is started once the current one reaches the rotation size and
+//! the oldest files are deleted to keep the total size below the disk usage cap.
+//!
+//! The files are written by a dedicated thread. The actor only pushes the records into a bounded
+//! queue and drops them when the queue is full, so it never waits for the disk.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+
+use near_primitives::stateless_validation::state_witness::ChunkStateWitnessSectionSizes;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+
+use crate::metrics;
+
+/// Number of records waiting to be written above which the new records are dropped.
+const EXPORT_QUEUE_SIZE: usize = 1000;
+
+const EXPORT_FILE_PREFIX: &str = "witness_stats.";
+const EXPORT_FILE_SUFFIX: &str = ".jsonl";
+
+/// How the node came by the exported witness.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WitnessStatsSource {
+    /// Produced by the node as the chunk producer.
+    Produced,
+    /// Decoded by the node from the parts.
+    Reconstructed,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WitnessStatsRecord {
+    pub source: WitnessStatsSource,
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    /// Size of the borsh-serialized witness.
+    pub raw_size: usize,
+    /// Size of the encoded witness distributed in the parts.
+    pub compressed_size: usize,
+    /// Sizes of the sections of the witness, only known for the produced witnesses, the
+    /// reconstructed ones are not serialized again to measure them.
+    pub section_sizes: Option<ChunkStateWitnessSectionSizes>,
+    /// Number of the chunk validators of the chunk, which is also the number of the parts.
+    pub num_validators: usize,
+    /// Time between the first part of the witness arriving and the witness being decoded, only
+    /// known for the reconstructed witnesses.
+    pub decode_latency_millis: Option<u64>,
+}
+
+/// Queues the records for the export thread, cheap to clone.
+#[derive(Clone)]
+pub struct WitnessStatsExporter {
+    sender: SyncSender<WitnessStatsRecord>,
+}
+
+impl WitnessStatsExporter {
+    /// Starts the export thread writing to `dir`, which is created if needed. The thread stops
+    /// once all the clones of the exporter are dropped.
+    pub fn spawn(dir: &Path, rotation_size: u64, max_disk_usage: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let writer = RotatingWriter::open(dir, rotation_size, max_disk_usage)?;
+        let (sender, receiver) = std::sync::mpsc::sync_channel(EXPORT_QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("witness_stats_export".to_string())
+            .spawn(move || run_export(writer, receiver))?;
+        Ok(Self { sender })
+    }
+
+    /// Queues the record for the export, drops it if the export thread is behind.
+    pub fn export(&self, record: WitnessStatsRecord) {
+        match self.sender.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => metrics::PARTIAL_WITNESS_STATS_EXPORT_DROPPED
+                .with_label_values(&["queue_full"])
+                .inc(),
+            Err(TrySendError::Disconnected(_)) => metrics::PARTIAL_WITNESS_STATS_EXPORT_DROPPED
+                .with_label_values(&["export_stopped"])
+                .inc(),
+        }
+    }
+}
+
+/// Writes the queued records, flushing whenever the queue is drained so that the files are
+/// complete while the node is idle.
+fn run_export(mut writer: RotatingWriter, receiver: Receiver<WitnessStatsRecord>) {
+    while let Ok(record) = receiver.recv() {
+        if let Err(err) = write_queued(&mut writer, record, &receiver) {
+            tracing::error!(target: "client", ?err, "Failed to export witness stats, stopping");
+            return;
+        }
+    }
+}
+
+fn write_queued(
+    writer: &mut RotatingWriter,
+    record: WitnessStatsRecord,
+    receiver: &Receiver<WitnessStatsRecord>,
+) -> std::io::Result<()> {
+    writer.write(&record)?;
+    while let Ok(record) = receiver.try_recv() {
+        writer.write(&record)?;
+    }
+    writer.flush()
+}
+
+/// Appends the records to the export files, see the module docs.
+struct RotatingWriter {
+    dir: PathBuf,
+    rotation_size: u64,
+    max_disk_usage: u64,
+    /// Index and size of the export files, the oldest first. The last one is being written.
+    files: Vec<(u64, u64)>,
+    current: BufWriter<File>,
+}
+
+impl RotatingWriter {
+    /// Continues the export in a new file after the existing ones.
+    fn open(dir: &Path, rotation_size: u64, max_disk_usage: u64) -> std::io::Result<Self> {
+        let mut files = export_files(dir)?
+            .into_iter()
+            .map(|(index, path)| Ok((index, std::fs::metadata(path)?.len())))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let index = files.last().map_or(0, |(index, _)| index + 1);
+        let current = create_export_file(dir, index)?;
+        files.push((index, 0));
+        Ok(Self { dir: dir.to_path_buf(), rotation_size, max_disk_usage, files, current })
+    }
+
+    fn write(&mut self, record: &WitnessStatsRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let line_len = line.len() as u64;
+        if self.files.last().unwrap().1 + line_len > self.rotation_size {
+            self.rotate()?;
+        }
+        while self.total_size() + line_len > self.max_disk_usage {
+            if self.files.len() == 1 {
+                metrics::PARTIAL_WITNESS_STATS_EXPORT_DROPPED
+                    .with_label_values(&["disk_usage_cap"])
+                    .inc();
+                return Ok(());
+            }
+            let (index, _) = self.files.remove(0);
+            std::fs::remove_file(export_file_path(&self.dir, index))?;
+        }
+        self.current.write_all(&line)?;
+        self.files.last_mut().unwrap().1 += line_len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.current.flush()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.current.flush()?;
+        let index = self.files.last().unwrap().0 + 1;
+        self.current = create_export_file(&self.dir, index)?;
+        self.files.push((index, 0));
+        Ok(())
+    }
+
+    fn total_size(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+fn export_file_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("{EXPORT_FILE_PREFIX}{index:06}{EXPORT_FILE_SUFFIX}"))
+}
+
+fn create_export_file(dir: &Path, index: u64) -> std::io::Result<BufWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(export_file_path(dir, index))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Index and path of the export files in `dir`, the oldest first.
+pub(crate) fn export_files(dir: &Path) -> std::io::Result<Vec<(u64, PathBuf)>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(EXPORT_FILE_PREFIX))
+            .and_then(|name| name.strip_suffix(EXPORT_FILE_SUFFIX))
+            .and_then(|index| index.parse::<u64>().ok());
+        if let Some(index) = index {
+            files.push((index, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::EpochId;
+
+    use super::{export_files, RotatingWriter, WitnessStatsRecord, WitnessStatsSource};
+
+    fn record(height_created: u64) -> WitnessStatsRecord {
+        WitnessStatsRecord {
+            source: WitnessStatsSource::Reconstructed,
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created,
+            raw_size: 1000,
+            compressed_size: 300,
+            section_sizes: None,
+            num_validators: 4,
+            decode_latency_millis: Some(12),
+        }
+    }
+
+    fn record_len() -> u64 {
+        serde_json::to_vec(&record(100)).unwrap().len() as u64 + 1
+    }
+
+    fn read(dir: &std::path::Path) -> Vec<u64> {
+        export_files(dir)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(_, path)| {
+                std::fs::read_to_string(path)
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str::<WitnessStatsRecord>(line).unwrap())
+                    .map(|record| record.height_created)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn files_are_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = RotatingWriter::open(dir.path(), 2 * record_len(), u64::MAX).unwrap();
+        for height in 100..105 {
+            writer.write(&record(height)).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(export_files(dir.path()).unwrap().len(), 3);
+        assert_eq!(read(dir.path()), vec![100, 101, 102, 103, 104]);
+
+        // A restarted export continues in a new file.
+        drop(writer);
+        let mut writer = RotatingWriter::open(dir.path(), 2 * record_len(), u64::MAX).unwrap();
+        writer.write(&record(105)).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(export_files(dir.path()).unwrap().len(), 4);
+        assert_eq!(read(dir.path()), vec![100, 101, 102, 103, 104, 105]);
+    }
+
+    #[test]
+    fn oldest_files_are_deleted_at_the_disk_usage_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer =
+            RotatingWriter::open(dir.path(), 2 * record_len(), 4 * record_len()).unwrap();
+        for height in 100..107 {
+            writer.write(&record(height)).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(read(dir.path()), vec![104, 105, 106]);
+        let total_size: u64 = export_files(dir.path())
+            .unwrap()
+            .iter()
+            .map(|(_, path)| std::fs::metadata(path).unwrap().len())
+            .sum();
+        assert!(total_size <= 4 * record_len());
+
+        // A single file can't exceed the cap either.
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer =
+            RotatingWriter::open(dir.path(), 10 * record_len(), 2 * record_len()).unwrap();
+        for height in 100..105 {
+            writer.write(&record(height)).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(read(dir.path()), vec![100, 101]);
+    }
+}
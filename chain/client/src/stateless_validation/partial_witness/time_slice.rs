@@ -0,0 +1,60 @@
+//! Cooperative time slicing of the loops of the actor.
+//!
+//! The actor handles the messages one at a time, so a loop doing a lot of work in one go, e.g.
+//! decoding a burst of large witnesses, delays the forwards of the parts of all the other shards
+//! queued in the mailbox meanwhile. The loops over the ready witnesses, the parts held during the
+//! sync and the expired witnesses check their `TimeSlice` after every step, and once it is used
+//! up they stop and schedule their continuation, which runs after the messages queued meanwhile.
+//! The step in progress always completes, so a single slow step still takes as long as it takes.
+
+use near_async::time::{Clock, Duration, Instant};
+
+use crate::metrics;
+
+/// Time the loop may run for before yielding to the mailbox, see
+/// `PartialWitnessConfig::processing_time_slice`.
+pub struct TimeSlice {
+    clock: Clock,
+    /// None if the loop doesn't yield.
+    ends_at: Option<Instant>,
+}
+
+impl TimeSlice {
+    /// Starts the slice of the loop. A zero budget never runs out.
+    pub fn start(clock: &Clock, budget: Duration) -> Self {
+        let ends_at = (budget > Duration::ZERO).then(|| clock.now() + budget);
+        Self { clock: clock.clone(), ends_at }
+    }
+
+    /// Whether the loop named `loop_name` ran out of its slice and must yield. Counts the yields
+    /// of the loop, so it's meant to be called once the loop has more work to do.
+    pub fn should_yield(&self, loop_name: &'static str) -> bool {
+        let should_yield = self.ends_at.is_some_and(|ends_at| self.clock.now() >= ends_at);
+        if should_yield {
+            metrics::PARTIAL_WITNESS_PROCESSING_YIELDS.with_label_values(&[loop_name]).inc();
+        }
+        should_yield
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::{FakeClock, Utc};
+
+    use super::*;
+
+    #[test]
+    fn slice_runs_out_after_its_budget() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let time_slice = TimeSlice::start(&clock.clock(), Duration::milliseconds(5));
+        assert!(!time_slice.should_yield("test"));
+        clock.advance(Duration::milliseconds(4));
+        assert!(!time_slice.should_yield("test"));
+        clock.advance(Duration::milliseconds(1));
+        assert!(time_slice.should_yield("test"));
+
+        let unbounded = TimeSlice::start(&clock.clock(), Duration::ZERO);
+        clock.advance(Duration::seconds(10));
+        assert!(!unbounded.should_yield("test"));
+    }
+}
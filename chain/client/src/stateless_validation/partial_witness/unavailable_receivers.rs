@@ -0,0 +1,101 @@
+//! Chunk validators which announced that they can't receive the witness parts for a while, see
+//! `WitnessReceiverStatus`. The chunk producer doesn't send these validators their parts, and sends
+//! the parts to all the other chunk validators directly instead.
+
+use std::collections::HashMap;
+
+use near_async::time::{Duration, Utc};
+use near_primitives::types::AccountId;
+
+/// Longest unavailability we accept from a single announcement. A validator stuck with a long
+/// announcement, e.g. because it crashed before clearing it, would otherwise miss the witnesses for
+/// that long.
+pub const MAX_RECEIVER_UNAVAILABILITY: Duration = Duration::minutes(10);
+
+struct ReceiverStatus {
+    issued_at: Utc,
+    unavailable_until: Option<Utc>,
+}
+
+pub struct UnavailableReceivers {
+    /// Latest status announced by each chunk validator. Only validators can announce, so the map
+    /// is bounded by the number of validators, and the expired statuses are dropped on update.
+    statuses: HashMap<AccountId, ReceiverStatus>,
+}
+
+impl UnavailableReceivers {
+    pub fn new() -> Self {
+        Self { statuses: HashMap::new() }
+    }
+
+    /// Records the status announced by the chunk validator, unless a status issued later was
+    /// already recorded. Returns whether the status was recorded.
+    pub fn update(
+        &mut self,
+        account_id: &AccountId,
+        issued_at: Utc,
+        unavailable_until: Option<Utc>,
+        now: Utc,
+    ) -> bool {
+        self.statuses.retain(|_, status| {
+            status.unavailable_until.is_some_and(|unavailable_until| unavailable_until > now)
+        });
+        if self.statuses.get(account_id).is_some_and(|status| status.issued_at >= issued_at) {
+            return false;
+        }
+        let unavailable_until = unavailable_until
+            .map(|unavailable_until| unavailable_until.min(now + MAX_RECEIVER_UNAVAILABILITY));
+        self.statuses.insert(account_id.clone(), ReceiverStatus { issued_at, unavailable_until });
+        true
+    }
+
+    pub fn is_unavailable(&self, account_id: &AccountId, now: Utc) -> bool {
+        self.statuses.get(account_id).is_some_and(|status| {
+            status.unavailable_until.is_some_and(|unavailable_until| unavailable_until > now)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> Utc {
+        Utc::UNIX_EPOCH + Duration::seconds(seconds)
+    }
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    #[test]
+    fn receiver_is_unavailable_until_the_announced_time() {
+        let mut receivers = UnavailableReceivers::new();
+        assert!(receivers.update(&account("alice"), at(100), Some(at(160)), at(100)));
+        assert!(receivers.is_unavailable(&account("alice"), at(159)));
+        assert!(!receivers.is_unavailable(&account("alice"), at(160)));
+        assert!(!receivers.is_unavailable(&account("bob"), at(120)));
+    }
+
+    #[test]
+    fn only_the_latest_status_counts() {
+        let mut receivers = UnavailableReceivers::new();
+        assert!(receivers.update(&account("alice"), at(100), Some(at(160)), at(100)));
+        // Cleared on start.
+        assert!(receivers.update(&account("alice"), at(110), None, at(110)));
+        assert!(!receivers.is_unavailable(&account("alice"), at(120)));
+        // The announcement arriving late, or replayed, doesn't override the newer status.
+        assert!(!receivers.update(&account("alice"), at(100), Some(at(160)), at(120)));
+        assert!(!receivers.is_unavailable(&account("alice"), at(120)));
+    }
+
+    #[test]
+    fn unavailability_is_capped() {
+        let mut receivers = UnavailableReceivers::new();
+        let now = at(100);
+        assert!(receivers.update(&account("alice"), now, Some(now + Duration::hours(5)), now));
+        let cap = now + MAX_RECEIVER_UNAVAILABILITY;
+        assert!(receivers.is_unavailable(&account("alice"), cap - Duration::seconds(1)));
+        assert!(!receivers.is_unavailable(&account("alice"), cap));
+    }
+}
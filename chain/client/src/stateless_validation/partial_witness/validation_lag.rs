@@ -0,0 +1,161 @@
+//! How far the chunk validator duty of the node lags behind the head of the chain, per shard.
+//!
+//! The duty for the chunk of a shard at a height is done once the witness of the chunk was
+//! reconstructed and sent to the client, see `PartialEncodedStateWitnessTracker::completed_height`.
+//! The heights at which the node is not a chunk validator of the shard are done by definition, so
+//! a node which validates a shard only at some heights doesn't appear to fall behind in between.
+//! The lag is the number of the heads above the highest done height at which the node was a chunk
+//! validator of the shard, so the heights skipped by the head, which have no chunks to validate,
+//! don't count. It is counted from the first head seen for the shard, as the witnesses before it
+//! can't be told apart from the ones the node never received. A chunk missing from the block also
+//! counts as lag until the next height the duty is done at, as the actor can't tell the missing
+//! chunks.
+
+use std::collections::BTreeMap;
+
+use near_primitives::types::{BlockHeight, BlockHeightDelta, ShardId};
+
+/// Duty of the node for the chunk of a shard at the head height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardDutyAtHead {
+    pub shard_id: ShardId,
+    /// The node is a chunk validator of the chunk at the head height.
+    pub assigned: bool,
+    /// Highest height of the witnesses of the shard sent to the client, if any.
+    pub completed_height: Option<BlockHeight>,
+}
+
+/// Duty of the node for a shard since the highest done height.
+struct ShardLag {
+    /// Highest done height, see the module docs.
+    done_height: BlockHeight,
+    /// Heights of the heads above `done_height` at which the node was a chunk validator of the
+    /// shard, in increasing order.
+    unfinished_heights: Vec<BlockHeight>,
+}
+
+pub struct ValidationLag {
+    /// Duty per shard of the epoch of the head.
+    shards: BTreeMap<ShardId, ShardLag>,
+}
+
+impl ValidationLag {
+    pub fn new() -> Self {
+        Self { shards: BTreeMap::new() }
+    }
+
+    /// Updates the lag of the shards of the epoch of the new head at `head_height`. Returns the
+    /// shards which are not in the epoch of the head anymore, whose lag is dropped.
+    pub fn on_head_updated(
+        &mut self,
+        head_height: BlockHeight,
+        duties: &[ShardDutyAtHead],
+    ) -> Vec<ShardId> {
+        let removed_shards: Vec<ShardId> = self
+            .shards
+            .keys()
+            .filter(|shard_id| duties.iter().all(|duty| duty.shard_id != **shard_id))
+            .copied()
+            .collect();
+        for shard_id in &removed_shards {
+            self.shards.remove(shard_id);
+        }
+        for duty in duties {
+            let shard = self
+                .shards
+                .entry(duty.shard_id)
+                .or_insert(ShardLag { done_height: head_height, unfinished_heights: vec![] });
+            if !duty.assigned {
+                shard.done_height = shard.done_height.max(head_height);
+            }
+            if let Some(completed_height) = duty.completed_height {
+                shard.done_height = shard.done_height.max(completed_height);
+            }
+            if duty.assigned
+                && head_height > shard.done_height
+                && shard.unfinished_heights.last() < Some(&head_height)
+            {
+                shard.unfinished_heights.push(head_height);
+            }
+            let done_height = shard.done_height;
+            shard.unfinished_heights.retain(|height| *height > done_height);
+        }
+        removed_shards
+    }
+
+    /// Lag per shard as of the latest head, ordered by shard id.
+    pub fn lags(&self) -> Vec<(ShardId, BlockHeightDelta)> {
+        self.shards
+            .iter()
+            .map(|(shard_id, shard)| {
+                (*shard_id, shard.unfinished_heights.len() as BlockHeightDelta)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShardDutyAtHead, ValidationLag};
+
+    fn assigned(shard_id: u64, completed_height: Option<u64>) -> ShardDutyAtHead {
+        ShardDutyAtHead { shard_id, assigned: true, completed_height }
+    }
+
+    fn unassigned(shard_id: u64, completed_height: Option<u64>) -> ShardDutyAtHead {
+        ShardDutyAtHead { shard_id, assigned: false, completed_height }
+    }
+
+    #[test]
+    fn lag_moves_across_assigned_and_unassigned_heights() {
+        let mut lag = ValidationLag::new();
+        // The lag is counted from the first head.
+        assert!(lag.on_head_updated(10, &[assigned(0, None)]).is_empty());
+        assert_eq!(lag.lags(), vec![(0, 0)]);
+
+        // The witnesses stop arriving.
+        lag.on_head_updated(11, &[assigned(0, None)]);
+        assert_eq!(lag.lags(), vec![(0, 1)]);
+        // The height skipped by the head has no chunk to validate.
+        lag.on_head_updated(13, &[assigned(0, None)]);
+        assert_eq!(lag.lags(), vec![(0, 2)]);
+        // The same head again doesn't add to the lag.
+        lag.on_head_updated(13, &[assigned(0, None)]);
+        assert_eq!(lag.lags(), vec![(0, 2)]);
+
+        // The heights we don't validate are done.
+        lag.on_head_updated(14, &[unassigned(0, None)]);
+        assert_eq!(lag.lags(), vec![(0, 0)]);
+        lag.on_head_updated(15, &[assigned(0, None)]);
+        assert_eq!(lag.lags(), vec![(0, 1)]);
+
+        // The witness of the next chunk may be completed before its block becomes the head.
+        lag.on_head_updated(16, &[assigned(0, Some(17))]);
+        assert_eq!(lag.lags(), vec![(0, 0)]);
+        lag.on_head_updated(17, &[assigned(0, Some(17))]);
+        assert_eq!(lag.lags(), vec![(0, 0)]);
+
+        // An old witness completed late doesn't move the done height back.
+        lag.on_head_updated(18, &[assigned(0, Some(12))]);
+        lag.on_head_updated(20, &[assigned(0, Some(12))]);
+        assert_eq!(lag.lags(), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn lag_follows_the_shards_across_epoch_changes() {
+        let mut lag = ValidationLag::new();
+        lag.on_head_updated(10, &[assigned(0, None), unassigned(1, None)]);
+        lag.on_head_updated(11, &[assigned(0, None), unassigned(1, None)]);
+        lag.on_head_updated(12, &[assigned(0, None), unassigned(1, None)]);
+        assert_eq!(lag.lags(), vec![(0, 2), (1, 0)]);
+
+        // The new epoch splits shard 1, its children start without lag.
+        let removed =
+            lag.on_head_updated(13, &[assigned(0, Some(13)), assigned(2, None), assigned(3, None)]);
+        assert_eq!(removed, vec![1]);
+        assert_eq!(lag.lags(), vec![(0, 0), (2, 0), (3, 0)]);
+
+        lag.on_head_updated(14, &[assigned(0, Some(13)), assigned(2, Some(14)), assigned(3, None)]);
+        assert_eq!(lag.lags(), vec![(0, 1), (2, 0), (3, 1)]);
+    }
+}
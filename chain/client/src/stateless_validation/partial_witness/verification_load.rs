@@ -0,0 +1,253 @@
+//! Estimation of the CPU load caused by the signature verifications of the witness parts.
+//!
+//! Signature verification is the dominant CPU cost of receiving the witness parts. Every
+//! verification is counted in `near_partial_witness_signature_verifications`, and every
+//! `COST_SAMPLE_INTERVAL`-th verification is timed to estimate the cost of a single verification.
+//! The samples are smoothed with an exponential moving average, so that a single slow sample
+//! (e.g. the thread being preempted) doesn't skew the estimate. At the end of every
+//! `LOAD_WINDOW` the number of verifications in the window times the estimated cost gives the
+//! fraction of one core spent on the verifications, exported in
+//! `near_partial_witness_signature_verification_utilization`. A warning is logged when the
+//! utilization stays above the configured threshold for `SUSTAINED_LOAD_WINDOWS` windows in a row.
+
+use near_async::time::{Clock, Duration, Instant};
+use time::ext::InstantExt as _;
+
+use crate::metrics;
+
+/// Every `COST_SAMPLE_INTERVAL`-th verification is timed to sample the verification cost.
+/// The first verification is always timed, so that there is an estimate right after the start.
+const COST_SAMPLE_INTERVAL: u64 = 100;
+
+/// Weight of a new sample in the exponential moving average of the verification cost.
+const COST_SMOOTHING_FACTOR: f64 = 0.2;
+
+/// Window over which the verification rate and the utilization are computed.
+const LOAD_WINDOW: Duration = Duration::seconds(10);
+
+/// Number of consecutive windows with the utilization above the threshold after which the load
+/// is considered sustained and a warning is logged.
+const SUSTAINED_LOAD_WINDOWS: u32 = 3;
+
+/// Why the signature of the part is verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureVerificationKind {
+    /// The part sent to us by the chunk producer.
+    Owned,
+    /// The part forwarded to us by another chunk validator.
+    Forward,
+}
+
+impl SignatureVerificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignatureVerificationKind::Owned => "owned",
+            SignatureVerificationKind::Forward => "forward",
+        }
+    }
+}
+
+pub struct SignatureVerificationLoad {
+    clock: Clock,
+    /// Fraction of one core above which the sustained load is reported.
+    warn_utilization: f64,
+    /// Number of verifications since the last timed one.
+    verifications_since_sample: u64,
+    /// Smoothed cost of a single verification, None until the first sample.
+    cost: Option<Duration>,
+    window_started_at: Instant,
+    window_verifications: u64,
+    /// Fraction of one core spent on the verifications in the last finished window.
+    utilization: f64,
+    /// Number of consecutive windows with the utilization above `warn_utilization`.
+    overloaded_windows: u32,
+}
+
+impl SignatureVerificationLoad {
+    pub fn new(clock: Clock, warn_utilization: f64) -> Self {
+        let window_started_at = clock.now();
+        Self {
+            clock,
+            warn_utilization,
+            verifications_since_sample: 0,
+            cost: None,
+            window_started_at,
+            window_verifications: 0,
+            utilization: 0.0,
+            overloaded_windows: 0,
+        }
+    }
+
+    /// Runs the signature verification, counting it and timing it if it's due for a cost sample.
+    pub fn verify<T>(&mut self, kind: SignatureVerificationKind, verify: impl FnOnce() -> T) -> T {
+        metrics::PARTIAL_WITNESS_SIGNATURE_VERIFICATIONS.with_label_values(&[kind.as_str()]).inc();
+        let result =
+            if self.cost.is_none() || self.verifications_since_sample >= COST_SAMPLE_INTERVAL {
+                let started_at = self.clock.now();
+                let result = verify();
+                self.record_cost_sample(self.clock.now().signed_duration_since(started_at));
+                result
+            } else {
+                self.verifications_since_sample += 1;
+                verify()
+            };
+        self.window_verifications += 1;
+        self.maybe_finish_window();
+        result
+    }
+
+    /// Smoothed cost of a single verification, None until the first verification.
+    pub fn cost(&self) -> Option<Duration> {
+        self.cost
+    }
+
+    /// Fraction of one core spent on the verifications in the last finished window.
+    pub fn utilization(&self) -> f64 {
+        self.utilization
+    }
+
+    fn record_cost_sample(&mut self, sample: Duration) {
+        self.verifications_since_sample = 0;
+        let cost = match self.cost {
+            None => sample,
+            Some(cost) => cost * (1.0 - COST_SMOOTHING_FACTOR) + sample * COST_SMOOTHING_FACTOR,
+        };
+        self.cost = Some(cost);
+        metrics::PARTIAL_WITNESS_SIGNATURE_VERIFICATION_COST.set(cost.as_seconds_f64());
+    }
+
+    /// Exports the rate and the utilization of the current window and starts a new one,
+    /// if the current window is over.
+    fn maybe_finish_window(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.signed_duration_since(self.window_started_at);
+        if elapsed < LOAD_WINDOW {
+            return;
+        }
+        let rate = self.window_verifications as f64 / elapsed.as_seconds_f64();
+        let utilization = rate * self.cost.unwrap_or(Duration::ZERO).as_seconds_f64();
+        metrics::PARTIAL_WITNESS_SIGNATURE_VERIFICATIONS_PER_SECOND.set(rate);
+        metrics::PARTIAL_WITNESS_SIGNATURE_VERIFICATION_UTILIZATION.set(utilization);
+        self.window_started_at = now;
+        self.window_verifications = 0;
+        self.utilization = utilization;
+
+        if utilization <= self.warn_utilization {
+            self.overloaded_windows = 0;
+            return;
+        }
+        self.overloaded_windows += 1;
+        if self.overloaded_windows % SUSTAINED_LOAD_WINDOWS == 0 {
+            tracing::warn!(
+                target: "client",
+                utilization,
+                warn_utilization = self.warn_utilization,
+                verifications_per_second = rate,
+                cost = ?self.cost,
+                overloaded_for = ?LOAD_WINDOW * self.overloaded_windows,
+                "Sustained high load of witness part signature verifications, \
+                chunk endorsements may be delayed",
+            );
+        }
+    }
+
+    /// Whether the utilization was above the threshold long enough to be reported.
+    pub fn is_overloaded(&self) -> bool {
+        self.overloaded_windows >= SUSTAINED_LOAD_WINDOWS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::{FakeClock, Utc};
+
+    /// Verifier with a known cost, advancing the fake clock by the cost on every verification.
+    fn mock_verify(
+        load: &mut SignatureVerificationLoad,
+        clock: &FakeClock,
+        cost: Duration,
+    ) -> bool {
+        load.verify(SignatureVerificationKind::Owned, || {
+            clock.advance(cost);
+            true
+        })
+    }
+
+    /// Verifies the signatures at the given rate for the given time.
+    fn verify_at_rate(
+        load: &mut SignatureVerificationLoad,
+        clock: &FakeClock,
+        cost: Duration,
+        per_second: u32,
+        seconds: u32,
+    ) {
+        let interval = Duration::seconds(1) / per_second;
+        for _ in 0..per_second * seconds {
+            let started_at = clock.now();
+            assert!(mock_verify(load, clock, cost));
+            clock.advance_until(started_at + interval);
+        }
+    }
+
+    fn assert_cost_near(load: &SignatureVerificationLoad, expected: Duration) {
+        let cost = load.cost().unwrap();
+        assert!((cost - expected).abs() < Duration::microseconds(1), "{cost} != {expected}");
+    }
+
+    #[test]
+    fn first_verification_is_sampled() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut load = SignatureVerificationLoad::new(clock.clock(), 0.5);
+        assert_eq!(load.cost(), None);
+        mock_verify(&mut load, &clock, Duration::microseconds(50));
+        assert_eq!(load.cost(), Some(Duration::microseconds(50)));
+    }
+
+    #[test]
+    fn only_every_interval_verification_is_sampled() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut load = SignatureVerificationLoad::new(clock.clock(), 0.5);
+        mock_verify(&mut load, &clock, Duration::microseconds(50));
+        // The verifications between the samples don't change the estimate.
+        for _ in 0..COST_SAMPLE_INTERVAL {
+            mock_verify(&mut load, &clock, Duration::milliseconds(10));
+        }
+        assert_eq!(load.cost(), Some(Duration::microseconds(50)));
+        mock_verify(&mut load, &clock, Duration::microseconds(150));
+        assert_cost_near(&load, Duration::microseconds(70));
+    }
+
+    #[test]
+    fn cost_converges_to_measured_cost() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut load = SignatureVerificationLoad::new(clock.clock(), 0.5);
+        mock_verify(&mut load, &clock, Duration::milliseconds(1));
+        for _ in 0..COST_SAMPLE_INTERVAL * 50 {
+            mock_verify(&mut load, &clock, Duration::microseconds(100));
+        }
+        assert_cost_near(&load, Duration::microseconds(100));
+    }
+
+    #[test]
+    fn sustained_load_above_threshold() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut load = SignatureVerificationLoad::new(clock.clock(), 0.5);
+        // 1000 verifications per second costing 0.2 ms each use 20% of a core.
+        verify_at_rate(&mut load, &clock, Duration::microseconds(200), 1000, 60);
+        assert!(!load.is_overloaded());
+        let utilization = load.utilization();
+        assert!((utilization - 0.2).abs() < 0.01, "{utilization}");
+
+        // 1000 verifications per second costing 0.8 ms each use 80% of a core, but the load
+        // is reported only once it lasts for several windows.
+        verify_at_rate(&mut load, &clock, Duration::microseconds(800), 1000, 25);
+        assert!(!load.is_overloaded());
+        verify_at_rate(&mut load, &clock, Duration::microseconds(800), 1000, 10);
+        assert!(load.is_overloaded());
+
+        // The load is no longer reported after a window below the threshold.
+        verify_at_rate(&mut load, &clock, Duration::microseconds(200), 1000, 20);
+        assert!(!load.is_overloaded());
+    }
+}
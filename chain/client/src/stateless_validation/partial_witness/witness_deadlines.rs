@@ -0,0 +1,136 @@
+//! Deadlines of the incomplete witnesses.
+//!
+//! A witness whose parts keep arriving past the time by which its chunk had to be endorsed is
+//! useless, so instead of holding its parts until the entry is evicted from the parts cache, the
+//! tracker abandons it once `PartialWitnessConfig::incomplete_witness_deadline` passes since its
+//! first part arrived. All the witnesses share the same deadline, so the deadlines expire in the
+//! order in which the first parts arrived and a queue is enough to keep them sorted.
+
+use std::collections::VecDeque;
+
+use near_async::time::{Duration, Instant};
+use near_primitives::stateless_validation::ChunkProductionKey;
+
+pub struct WitnessDeadlines {
+    deadline: Duration,
+    /// Maximum number of pending deadlines, the oldest ones are dropped above it.
+    max_pending: usize,
+    /// Pending deadlines together with the witness, ordered by the deadline.
+    pending: VecDeque<(Instant, ChunkProductionKey)>,
+}
+
+impl WitnessDeadlines {
+    pub fn new(deadline: Duration, max_pending: usize) -> Self {
+        Self { deadline, max_pending, pending: VecDeque::new() }
+    }
+
+    /// Starts the deadline of the witness whose first part arrived at `first_part_at`.
+    /// Returns the witness whose deadline was dropped to keep the number of pending deadlines
+    /// within `max_pending`. That witness is abandoned only once evicted from the parts cache.
+    pub fn start(
+        &mut self,
+        key: ChunkProductionKey,
+        first_part_at: Instant,
+    ) -> Option<ChunkProductionKey> {
+        let dropped = if self.pending.len() >= self.max_pending {
+            self.pending.pop_front().map(|(_, key)| key)
+        } else {
+            None
+        };
+        self.pending.push_back((first_part_at + self.deadline, key));
+        dropped
+    }
+
+    /// Cancels the deadline of the witness, e.g. once it was decoded.
+    pub fn cancel(&mut self, key: &ChunkProductionKey) {
+        self.pending.retain(|(_, pending_key)| pending_key != key);
+    }
+
+    /// The pending deadline of the witness.
+    pub fn deadline_of(&self, key: &ChunkProductionKey) -> Option<Instant> {
+        self.pending
+            .iter()
+            .find(|(_, pending_key)| pending_key == key)
+            .map(|(deadline, _)| *deadline)
+    }
+
+    /// The earliest pending deadline.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.front().map(|(deadline, _)| *deadline)
+    }
+
+    /// Removes and returns the witness with the earliest deadline if it's at or before `now`.
+    pub fn pop_expired(&mut self, now: Instant) -> Option<ChunkProductionKey> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        self.pending.pop_front().map(|(_, key)| key)
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_async::time::{FakeClock, Utc};
+    use near_primitives::types::EpochId;
+
+    fn key(height_created: u64) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id: 0, epoch_id: EpochId::default(), height_created }
+    }
+
+    fn take_expired(deadlines: &mut WitnessDeadlines, now: Instant) -> Vec<ChunkProductionKey> {
+        std::iter::from_fn(|| deadlines.pop_expired(now)).collect()
+    }
+
+    #[test]
+    fn deadlines_expire_in_order_of_first_parts() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut deadlines = WitnessDeadlines::new(Duration::milliseconds(1500), 10);
+        assert_eq!(deadlines.next_deadline(), None);
+
+        let started_at = clock.now();
+        assert_eq!(deadlines.start(key(1), clock.now()), None);
+        clock.advance(Duration::milliseconds(500));
+        assert_eq!(deadlines.start(key(2), clock.now()), None);
+        assert_eq!(deadlines.next_deadline(), Some(started_at + Duration::milliseconds(1500)));
+
+        clock.advance(Duration::milliseconds(999));
+        assert!(take_expired(&mut deadlines, clock.now()).is_empty());
+        clock.advance(Duration::milliseconds(1));
+        assert_eq!(take_expired(&mut deadlines, clock.now()), vec![key(1)]);
+        assert_eq!(deadlines.next_deadline(), Some(started_at + Duration::milliseconds(2000)));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(take_expired(&mut deadlines, clock.now()), vec![key(2)]);
+        assert_eq!(deadlines.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancelled_deadline_does_not_expire() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut deadlines = WitnessDeadlines::new(Duration::seconds(1), 10);
+        deadlines.start(key(1), clock.now());
+        deadlines.start(key(2), clock.now());
+        deadlines.start(key(3), clock.now());
+        deadlines.cancel(&key(2));
+        assert_eq!(deadlines.deadline_of(&key(1)), Some(clock.now() + Duration::seconds(1)));
+        assert_eq!(deadlines.deadline_of(&key(2)), None);
+        clock.advance(Duration::seconds(1));
+        assert_eq!(take_expired(&mut deadlines, clock.now()), vec![key(1), key(3)]);
+    }
+
+    #[test]
+    fn number_of_pending_deadlines_is_bounded() {
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let mut deadlines = WitnessDeadlines::new(Duration::seconds(1), 2);
+        assert_eq!(deadlines.start(key(1), clock.now()), None);
+        assert_eq!(deadlines.start(key(2), clock.now()), None);
+        clock.advance(Duration::milliseconds(100));
+        assert_eq!(deadlines.start(key(3), clock.now()), Some(key(1)));
+        clock.advance(Duration::seconds(1));
+        assert_eq!(take_expired(&mut deadlines, clock.now()), vec![key(2), key(3)]);
+    }
+}
@@ -0,0 +1,228 @@
+//! Geometry of the Reed Solomon encoded state witness parts.
+//!
+//! The chunk producer, the chunk validators forwarding parts and the validation logic all
+//! need to agree on how many parts there are, how many of them carry data, how long
+//! each part is and which chunk validator owns which part. Keep all of that here so that
+//! it is derived in exactly one place.
+
+use itertools::Itertools;
+use near_chain::Error;
+use near_primitives::reed_solomon::reed_solomon_part_length;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::AccountId;
+
+use super::part_format::{PartFormat, PartialWitnessPart};
+
+/// Ratio of the number of data parts to total parts in the Reed Solomon encoding.
+/// The tradeoff here is having a higher ratio is better for handling missing parts and network errors
+/// but increases the size of the encoded state witness and the total network bandwidth requirements.
+const RATIO_DATA_PARTS: f32 = 0.6;
+
+/// Number of data parts for a witness encoded into `total_parts` parts.
+/// There is always at least one data part.
+pub fn data_parts(total_parts: usize) -> usize {
+    std::cmp::max((total_parts as f32 * RATIO_DATA_PARTS) as usize, 1)
+}
+
+/// Number of parity parts for a witness encoded into `total_parts` parts.
+pub fn parity_parts(total_parts: usize) -> usize {
+    total_parts.saturating_sub(data_parts(total_parts))
+}
+
+/// Length in bytes of every part of a witness with `encoded_length` bytes encoded into
+/// `total_parts` parts. The last data part is padded with zeroes up to this length.
+pub fn part_len(encoded_length: usize, total_parts: usize) -> usize {
+    reed_solomon_part_length(encoded_length, data_parts(total_parts))
+}
+
+/// Whether the part at `part_ord` is a parity part, i.e. it doesn't carry the witness bytes
+/// directly and is only useful for reconstructing the missing data parts.
+pub fn is_parity_part(part_ord: usize, total_parts: usize) -> bool {
+    part_ord >= data_parts(total_parts)
+}
+
+/// Minimum number of distinct parts a chunk validator needs to reconstruct the witness.
+pub fn min_parts_to_decode(total_parts: usize) -> usize {
+    data_parts(total_parts)
+}
+
+/// Owners of the parts of the `format`, i.e. the chunk validator at index `part_ord` receives the
+/// part directly from the chunk producer and forwards it to the other chunk validators.
+/// The order is part of the protocol. Since the V2 format, see
+/// `ProtocolFeature::PartialWitnessSendTimestamp`, it is pinned to the account ids instead of
+/// depending on the order in which the epoch manager returns the chunk validators, and the owner
+/// is included in the signed part, so a divergence is detected rather than silently breaking the
+/// forwarding. The V1 parts are owned in the order of the assignment, as they always were.
+pub fn part_owners(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    format: PartFormat,
+) -> Vec<AccountId> {
+    let chunk_validators = chunk_validator_assignments.ordered_chunk_validators();
+    if format >= PartFormat::V2 {
+        chunk_validators.into_iter().sorted().collect()
+    } else {
+        chunk_validators
+    }
+}
+
+/// Same as `part_owners`, but fails for a chunk without any chunk validator. There are no parts
+/// to encode the witness into then, and the Reed Solomon encoding doesn't support zero parts.
+pub fn non_empty_part_owners(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    format: PartFormat,
+    key: &ChunkProductionKey,
+) -> Result<Vec<AccountId>, Error> {
+    let part_owners = part_owners(chunk_validator_assignments, format);
+    if part_owners.is_empty() {
+        return Err(Error::NoChunkValidators {
+            epoch_id: key.epoch_id,
+            shard_id: key.shard_id,
+            height_created: key.height_created,
+        });
+    }
+    Ok(part_owners)
+}
+
+/// Owner of the part in the order of its format, see `part_owners`, None if `part_ord` is out of
+/// range. Unlike `PartialEncodedStateWitness::owner`, it is known for the V1 parts as well.
+pub fn part_owner(
+    chunk_validator_assignments: &ChunkValidatorAssignments,
+    partial_witness: &PartialEncodedStateWitness,
+) -> Option<AccountId> {
+    let format = PartialWitnessPart::from(partial_witness).format;
+    part_owners(chunk_validator_assignments, format).into_iter().nth(partial_witness.part_ord())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stateless_validation::partial_witness::encoding::{
+        ReedSolomonBackend, WitnessEncoderCache,
+    };
+    use near_primitives::stateless_validation::state_witness::EncodedChunkStateWitness;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    const MAX_TOTAL_PARTS: usize = 200;
+
+    #[test]
+    fn geometry_is_consistent() {
+        for total_parts in 1..=MAX_TOTAL_PARTS {
+            let data = data_parts(total_parts);
+            assert!(data >= 1);
+            assert!(data <= total_parts);
+            assert_eq!(data + parity_parts(total_parts), total_parts);
+            assert_eq!(min_parts_to_decode(total_parts), data);
+            assert_eq!(
+                (0..total_parts).filter(|ord| is_parity_part(*ord, total_parts)).count(),
+                parity_parts(total_parts)
+            );
+            if total_parts > 1 {
+                assert!(parity_parts(total_parts) >= 1, "total_parts={total_parts}");
+            }
+        }
+    }
+
+    #[test]
+    fn part_len_covers_encoded_length() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        for total_parts in 1..=MAX_TOTAL_PARTS {
+            for _ in 0..10 {
+                let encoded_length = rng.gen_range(0..10_000_000);
+                let len = part_len(encoded_length, total_parts);
+                let data = data_parts(total_parts);
+                assert!(len * data >= encoded_length);
+                // Padding never exceeds one byte per data part.
+                assert!(len * data < encoded_length + data);
+            }
+        }
+    }
+
+    #[test]
+    fn encoder_output_matches_geometry() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut encoders = WitnessEncoderCache::new(ReedSolomonBackend::Portable);
+        for total_parts in [1, 2, 3, 5, 10, 33, 68, 100, 150, MAX_TOTAL_PARTS] {
+            let len = rng.gen_range(1..100_000);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let witness = EncodedChunkStateWitness::from_boxed_slice(bytes.into_boxed_slice());
+            let encoder = encoders.entry(total_parts).unwrap();
+            assert_eq!(encoder.total_parts(), total_parts);
+            assert_eq!(encoder.data_parts(), data_parts(total_parts));
+
+            let (parts, encoded_length) = encoder.encode(&witness);
+            assert_eq!(parts.len(), total_parts);
+            let expected_part_len = part_len(encoded_length, total_parts);
+            for part in &parts {
+                assert_eq!(part.as_ref().unwrap().len(), expected_part_len);
+            }
+
+            // Losing as many parts as there are parity parts must still allow decoding.
+            let mut parts = parts;
+            for part in parts.iter_mut().take(parity_parts(total_parts)) {
+                *part = None;
+            }
+            assert_eq!(
+                parts.iter().filter(|part| part.is_some()).count(),
+                min_parts_to_decode(total_parts)
+            );
+            let decoded = encoder.decode(&mut parts, encoded_length).unwrap();
+            assert_eq!(decoded, witness);
+        }
+    }
+
+    #[test]
+    fn part_owners_do_not_depend_on_assignments_order() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut assignments: Vec<(AccountId, u128)> = (0..20)
+            .map(|i| (format!("validator{i}").parse().unwrap(), rng.gen_range(1..1000)))
+            .collect();
+        let owners_of = |assignments: &Vec<(AccountId, u128)>| {
+            part_owners(&ChunkValidatorAssignments::new(assignments.clone()), PartFormat::V2)
+        };
+        let expected = owners_of(&assignments);
+        for _ in 0..10 {
+            assignments.shuffle(&mut rng);
+            assert_eq!(owners_of(&assignments), expected);
+        }
+    }
+
+    /// Pins the assignment of the parts to the owners. If this test fails, the order changed in a
+    /// way that is incompatible with the nodes running the previous version.
+    #[test]
+    fn part_owners_order_is_stable() {
+        let assignments = ChunkValidatorAssignments::new(
+            [("carol", 10), ("alice", 5), ("dave", 30), ("bob", 30), ("alice1", 1)]
+                .into_iter()
+                .map(|(account_id, stake)| (account_id.parse().unwrap(), stake))
+                .collect(),
+        );
+        let owners = |format| {
+            part_owners(&assignments, format)
+                .iter()
+                .map(|owner| owner.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(owners(PartFormat::V1), vec!["carol", "alice", "dave", "bob", "alice1"]);
+        for format in [PartFormat::V2, PartFormat::V3, PartFormat::V4] {
+            assert_eq!(owners(format), vec!["alice", "alice1", "bob", "carol", "dave"]);
+        }
+    }
+
+    #[test]
+    fn chunk_without_chunk_validators_is_rejected() {
+        let key =
+            ChunkProductionKey { epoch_id: Default::default(), shard_id: 3, height_created: 42 };
+        let err =
+            non_empty_part_owners(&ChunkValidatorAssignments::new(vec![]), PartFormat::V2, &key)
+                .unwrap_err();
+        assert!(
+            matches!(err, Error::NoChunkValidators { shard_id: 3, height_created: 42, .. }),
+            "{err:?}"
+        );
+        let assignments = ChunkValidatorAssignments::new(vec![("alice".parse().unwrap(), 1)]);
+        assert_eq!(non_empty_part_owners(&assignments, PartFormat::V2, &key).unwrap().len(), 1);
+    }
+}
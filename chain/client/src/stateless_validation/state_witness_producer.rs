@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use near_async::messaging::{CanSend, IntoSender};
+use near_async::time::Instant;
 use near_chain::{BlockHeader, Chain, ChainStoreAccess};
 use near_chain_primitives::Error;
 use near_o11y::log_assert_fail;
@@ -33,6 +34,7 @@ impl Client {
         chunk: &ShardChunk,
         transactions_storage_proof: Option<PartialState>,
         validator_signer: &Option<Arc<ValidatorSigner>>,
+        chunk_produced_at: Instant,
     ) -> Result<(), Error> {
         let protocol_version = self.epoch_manager.get_epoch_protocol_version(epoch_id)?;
         if !checked_feature!("stable", StatelessValidation, protocol_version) {
@@ -72,11 +74,12 @@ impl Client {
             );
         }
 
-        self.partial_witness_adapter.send(DistributeStateWitnessRequest {
-            epoch_id: *epoch_id,
+        self.partial_witness_adapter.send(DistributeStateWitnessRequest::new(
+            *epoch_id,
             chunk_header,
-            state_witness,
-        });
+            Arc::new(state_witness),
+            chunk_produced_at,
+        ));
         Ok(())
     }
 
@@ -1,18 +1,37 @@
+use super::partial_witness::{witness_parts_geometry, WitnessDecodePath};
 use crate::metrics;
 use bytesize::ByteSize;
 use lru::LruCache;
-use near_async::time::Clock;
+use near_async::time::{Clock, Duration, Instant};
+use near_o11y::metrics::int_label;
 use near_primitives::sharding::ChunkHash;
-use near_primitives::stateless_validation::state_witness::ChunkStateWitnessAck;
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitnessAckV2, VersionedChunkStateWitnessAck, MAX_ACK_HELD_TIME,
+};
+use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochId, ShardId};
 use s3::creds::time::ext::InstantExt as _;
+use std::collections::HashSet;
 use std::hash::Hash;
 use std::num::NonZeroUsize;
 
 /// Limit to the number of witnesses tracked.
 ///
-/// Other witnesses past this number are discarded (perhaps add a blurb on how.)
+/// Once the limit is reached, the oldest witness is discarded to make room for the newly sent one,
+/// regardless of how many acks are still missing for it. Without the limit the records would pile
+/// up whenever some of the validators never ack, e.g. during a network partition or when they run
+/// an old binary.
 const CHUNK_STATE_WITNESS_MAX_RECORD_COUNT: usize = 50;
 
+/// Acks received within this time after sending the witness count as timely in the
+/// distribution summary.
+const TIMELY_ACK_WINDOW: Duration = Duration::seconds(1);
+
+/// The distribution summary is logged once all the validators acked the witness, or after
+/// this timeout if some of the acks are missing.
+const DISTRIBUTION_SUMMARY_TIMEOUT: Duration = Duration::seconds(2);
+
 /// Refers to a state witness sent from a chunk producer to a chunk validator.
 ///
 /// Used to map the incoming acknowledgement messages back to the timing information of
@@ -38,14 +57,177 @@ struct ChunkStateWitnessRecord {
     sent_timestamp: near_async::time::Instant,
 }
 
+/// Summary of the distribution of a witness produced by us. It is logged once per chunk at
+/// info level and kept around for debugging the recent witnesses.
+#[derive(Debug, Clone)]
+pub struct WitnessDistributionSummary {
+    pub epoch_id: EpochId,
+    pub height_created: BlockHeight,
+    pub shard_id: ShardId,
+    /// Size of the witness before compression.
+    pub raw_witness_size: usize,
+    /// Size of the compressed witness.
+    pub encoded_witness_size: usize,
+    /// Number of Reed Solomon parts the witness was encoded into.
+    pub num_parts: usize,
+    /// Number of validators the parts were sent to, i.e. the validators we expect an ack from.
+    pub num_validators: usize,
+    /// Time from the chunk production to the distribution request reaching the partial
+    /// witness actor, i.e. the time spent on the client side.
+    pub request_delay: Duration,
+    /// Time taken to encode the witness into parts.
+    pub encode_time: Duration,
+    /// Time from sending the witness to receiving the first ack.
+    pub time_to_first_ack: Option<Duration>,
+    /// Time from sending the witness to receiving the acks of two thirds of the validators, see
+    /// `reached_ack_threshold`.
+    pub time_to_ack_threshold: Option<Duration>,
+    pub acks_received: usize,
+    /// Number of acks received within `TIMELY_ACK_WINDOW` after sending the witness.
+    pub timely_acks_received: usize,
+    /// Fewest parts a chunk validator reported to have received when it decoded the witness,
+    /// see `ChunkStateWitnessAckV2`.
+    pub min_parts_received_at_decode: Option<usize>,
+    /// Whether the summary was already logged.
+    pub emitted: bool,
+    /// Set if the chunk validators holding two thirds of the stake didn't ack the witness by
+    /// `PartialWitnessConfig::ack_threshold_deadline`.
+    pub ack_threshold_miss: Option<AckThresholdMiss>,
+}
+
+/// Chunk validators which didn't ack a witness produced by us by the ack threshold deadline, see
+/// `ChunkStateWitnessTracker::check_ack_threshold_deadlines`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AckThresholdMiss {
+    /// Fraction of the stake of the chunk validators which acked the witness, counting our own
+    /// stake if we are a chunk validator of the chunk.
+    pub acked_stake_fraction: f64,
+    /// Chunk validators which didn't ack the witness, in the order of the assignment.
+    pub missing_acks: Vec<AccountId>,
+    /// Acks received from chunk validators the network couldn't tell, e.g. whose account
+    /// announcement it doesn't know. Their stake is missing from `acked_stake_fraction`.
+    pub unattributed_acks: usize,
+}
+
+impl WitnessDistributionSummary {
+    pub fn new(
+        epoch_id: EpochId,
+        height_created: BlockHeight,
+        shard_id: ShardId,
+        raw_witness_size: usize,
+        encoded_witness_size: usize,
+        num_parts: usize,
+        num_validators: usize,
+        request_delay: Duration,
+        encode_time: Duration,
+    ) -> Self {
+        Self {
+            epoch_id,
+            height_created,
+            shard_id,
+            raw_witness_size,
+            encoded_witness_size,
+            num_parts,
+            num_validators,
+            request_delay,
+            encode_time,
+            time_to_first_ack: None,
+            time_to_ack_threshold: None,
+            acks_received: 0,
+            timely_acks_received: 0,
+            min_parts_received_at_decode: None,
+            emitted: false,
+            ack_threshold_miss: None,
+        }
+    }
+
+    /// Fraction of the validators that acked the witness within `TIMELY_ACK_WINDOW`.
+    pub fn timely_acked_fraction(&self) -> f64 {
+        if self.num_validators == 0 {
+            return 1.0;
+        }
+        self.timely_acks_received as f64 / self.num_validators as f64
+    }
+
+    fn emit(&mut self) {
+        tracing::info!(
+            target: "client",
+            height = self.height_created,
+            shard_id = self.shard_id,
+            raw_size = self.raw_witness_size,
+            compressed_size = self.encoded_witness_size,
+            parts = self.num_parts,
+            validators = self.num_validators,
+            request_delay_ms = self.request_delay.whole_milliseconds(),
+            encode_time_ms = self.encode_time.whole_milliseconds(),
+            time_to_first_ack_ms = ?self.time_to_first_ack.map(|time| time.whole_milliseconds()),
+            acked_within_1s = self.timely_acked_fraction(),
+            min_parts_at_decode = ?self.min_parts_received_at_decode,
+            "Witness distribution summary",
+        );
+        self.emitted = true;
+    }
+}
+
+struct WitnessDistributionRecord {
+    summary: WitnessDistributionSummary,
+    /// Timestamp of when the chunk producer sends the state witness.
+    sent_timestamp: near_async::time::Instant,
+    /// Chunk validators of the chunk with their stakes.
+    chunk_validators: Vec<(AccountId, Balance)>,
+    /// Chunk validators known to have the witness, i.e. the ones which acked it and us.
+    acked_by: HashSet<AccountId>,
+    /// Number of the acks received from unknown chunk validators, see
+    /// `AckThresholdMiss::unattributed_acks`.
+    unattributed_acks: usize,
+    /// Whether the acked stake was already checked at the ack threshold deadline.
+    ack_deadline_checked: bool,
+}
+
+impl WitnessDistributionRecord {
+    /// Checks whether the chunk validators holding two thirds of the stake acked the witness.
+    /// Returns the miss otherwise.
+    fn check_acked_stake(&self) -> Option<AckThresholdMiss> {
+        let assignments = ChunkValidatorAssignments::new(self.chunk_validators.clone());
+        let stats = assignments.compute_endorsement_stats(&self.acked_by.iter().collect());
+        if stats.has_enough_stake() {
+            return None;
+        }
+        let acked_stake_fraction = if stats.total_stake == 0 {
+            0.0
+        } else {
+            stats.endorsed_stake as f64 / stats.total_stake as f64
+        };
+        let missing_acks = self
+            .chunk_validators
+            .iter()
+            .map(|(account_id, _)| account_id)
+            .filter(|account_id| !self.acked_by.contains(*account_id))
+            .cloned()
+            .collect();
+        Some(AckThresholdMiss {
+            acked_stake_fraction,
+            missing_acks,
+            unattributed_acks: self.unattributed_acks,
+        })
+    }
+}
+
 /// Tracks a collection of state witnesses sent from chunk producers to validators.
 ///
 /// This is currently used to calculate the round-trip time of sending the witness and
-/// getting the ack message back, where the ack message is used as a proxy for the endorsement
-/// message from validators to block producers to make an estimate of network time for sending
-/// witness and receiving the endorsement.
+/// getting the ack message back. The chunk validators send the ack as soon as they reconstruct
+/// the witness from the parts, before validating it, so the round-trip time reflects the network
+/// time of distributing the witness and not the validation time.
+///
+/// It also aggregates the distribution summary of each witness, see `WitnessDistributionSummary`.
 pub struct ChunkStateWitnessTracker {
+    /// Witnesses waiting for acks. The acks don't refresh the records, so the oldest sent
+    /// witness is evicted first once `CHUNK_STATE_WITNESS_MAX_RECORD_COUNT` is reached.
     witnesses: LruCache<ChunkStateWitnessKey, ChunkStateWitnessRecord>,
+    /// Distribution summaries of the recent witnesses. Unlike `witnesses`, these are kept after
+    /// all the acks are received so that they can be inspected for debugging.
+    summaries: LruCache<ChunkStateWitnessKey, WitnessDistributionRecord>,
     clock: Clock,
 }
 
@@ -55,55 +237,250 @@ impl ChunkStateWitnessTracker {
             witnesses: LruCache::new(
                 NonZeroUsize::new(CHUNK_STATE_WITNESS_MAX_RECORD_COUNT).unwrap(),
             ),
+            summaries: LruCache::new(
+                NonZeroUsize::new(CHUNK_STATE_WITNESS_MAX_RECORD_COUNT).unwrap(),
+            ),
             clock,
         }
     }
 
-    /// Adds a new witness message to track.
+    /// Adds a new witness message to track. A witness sent again for the same chunk, see
+    /// `DistributeStateWitnessRequest::force`, updates the distribution summary of the witness
+    /// instead. The acks received so far are kept and the round trip times are still measured
+    /// from the first send, as the validators may be acking either of them.
+    ///
+    /// The acks are expected from the `chunk_validators` other than `producer`, i.e. us.
     pub fn record_witness_sent(
         &mut self,
         chunk_hash: ChunkHash,
-        witness_size_in_bytes: usize,
-        num_validators: usize,
+        mut summary: WitnessDistributionSummary,
+        chunk_validators: &ChunkValidatorAssignments,
+        producer: &AccountId,
     ) -> () {
         let key = ChunkStateWitnessKey::new(chunk_hash);
+        if let Some(distribution) = self.summaries.peek_mut(&key) {
+            tracing::debug!(target: "state_witness_tracker", witness_key=?key,
+                "Updating the record of the state witness sent again.");
+            let previous = &distribution.summary;
+            summary.time_to_first_ack = previous.time_to_first_ack;
+            summary.time_to_ack_threshold = previous.time_to_ack_threshold;
+            summary.acks_received = previous.acks_received;
+            summary.timely_acks_received = previous.timely_acks_received;
+            summary.min_parts_received_at_decode = previous.min_parts_received_at_decode;
+            summary.emitted = previous.emitted;
+            summary.ack_threshold_miss = previous.ack_threshold_miss.clone();
+            distribution.summary = summary;
+            return;
+        }
         tracing::trace!(target: "state_witness_tracker", witness_key=?key,
-            size=witness_size_in_bytes, "Recording state witness sent.");
-        self.witnesses.put(
-            key,
+            size=summary.encoded_witness_size, "Recording state witness sent.");
+        let sent_timestamp = self.clock.now();
+        let evicted = self.witnesses.push(
+            key.clone(),
             ChunkStateWitnessRecord {
-                num_validators,
-                witness_size: witness_size_in_bytes,
-                sent_timestamp: self.clock.now(),
+                num_validators: summary.num_validators,
+                witness_size: summary.encoded_witness_size,
+                sent_timestamp,
+            },
+        );
+        if let Some((evicted_key, evicted_record)) =
+            evicted.filter(|(evicted_key, _)| evicted_key != &key)
+        {
+            metrics::CHUNK_STATE_WITNESS_TRACKER_EVICTIONS.inc();
+            tracing::debug!(target: "state_witness_tracker", witness_key=?evicted_key,
+                missing_acks=evicted_record.num_validators, "Evicted state witness waiting for acks.");
+        }
+        self.record_num_witnesses_metric();
+        // There are no acks to wait for if we are the only validator.
+        if summary.num_validators == 0 {
+            summary.emit();
+        }
+        let mut acked_by = HashSet::new();
+        if chunk_validators.contains(producer) {
+            acked_by.insert(producer.clone());
+        }
+        self.summaries.put(
+            key,
+            WitnessDistributionRecord {
+                summary,
+                sent_timestamp,
+                chunk_validators: chunk_validators.assignments().clone(),
+                acked_by,
+                unattributed_acks: 0,
+                ack_deadline_checked: false,
             },
         );
     }
 
-    /// Handles an ack message for the witness. Calculates the round-trip duration and
-    /// records it in the corresponding metric.
-    pub fn on_witness_ack_received(&mut self, ack: ChunkStateWitnessAck) -> () {
-        let key = ChunkStateWitnessKey { chunk_hash: ack.chunk_hash };
-        tracing::trace!(target: "state_witness_tracker", witness_key=?key,
+    /// Handles an ack message for the witness sent by `validator`, None if the network couldn't
+    /// tell the chunk validator. Calculates the round-trip duration and records it in the
+    /// corresponding metric. Returns the time to the ack threshold if the ack made the witness
+    /// reach it, see `reached_ack_threshold`.
+    pub fn on_witness_ack_received(
+        &mut self,
+        ack: VersionedChunkStateWitnessAck,
+        validator: Option<&AccountId>,
+    ) -> Option<(ChunkProductionKey, Duration)> {
+        self.on_held_witness_ack_received(ack, Duration::ZERO, validator)
+    }
+
+    /// Handles an ack which the chunk validator held for `held` before sending it in a batch,
+    /// see `HeldChunkStateWitnessAck`. The held time is subtracted from the round-trip time, up
+    /// to `MAX_ACK_HELD_TIME` so that a misbehaving validator can't make the acks look older.
+    pub fn on_held_witness_ack_received(
+        &mut self,
+        ack: VersionedChunkStateWitnessAck,
+        held: Duration,
+        validator: Option<&AccountId>,
+    ) -> Option<(ChunkProductionKey, Duration)> {
+        let key = ChunkStateWitnessKey { chunk_hash: ack.chunk_hash().clone() };
+        tracing::trace!(target: "state_witness_tracker", witness_key=?key, ?held,
             "Received ack for state witness");
-        if let Some(record) = self.witnesses.get_mut(&key) {
-            debug_assert!(record.num_validators > 0);
-
-            Self::update_roundtrip_time_metric(record, &self.clock);
-
-            // Cleanup the record if we received the acks from all the validators, otherwise update
-            // the number of validators from which we are expecting an ack message.
-            let remaining = record.num_validators.saturating_sub(1);
-            if remaining > 0 {
-                record.num_validators = remaining;
-            } else {
-                self.witnesses.pop(&key);
+        let received_time = self.clock.now() - held.clamp(Duration::ZERO, MAX_ACK_HELD_TIME);
+        let ack_threshold = self.update_distribution_summary(&key, &ack, received_time, validator);
+        let Some(record) = self.witnesses.peek_mut(&key) else {
+            // The witness was evicted or all its acks were already received, which is expected
+            // for the acks arriving late or sent multiple times.
+            metrics::CHUNK_STATE_WITNESS_UNTRACKED_ACKS.inc();
+            tracing::trace!(target: "state_witness_tracker", witness_key=?key,
+                "Received ack for state witness which is not tracked");
+            return ack_threshold;
+        };
+        debug_assert!(record.num_validators > 0);
+
+        Self::update_roundtrip_time_metric(record, received_time);
+
+        // Cleanup the record if we received the acks from all the validators, otherwise update
+        // the number of validators from which we are expecting an ack message.
+        let remaining = record.num_validators.saturating_sub(1);
+        if remaining > 0 {
+            record.num_validators = remaining;
+        } else {
+            self.witnesses.pop(&key);
+            self.record_num_witnesses_metric();
+        }
+        ack_threshold
+    }
+
+    fn record_num_witnesses_metric(&self) {
+        metrics::CHUNK_STATE_WITNESS_TRACKER_ENTRIES.set(self.witnesses.len() as i64);
+    }
+
+    /// Records the ack in the distribution summary and logs the summary once all the validators
+    /// acked the witness. Returns the time to the ack threshold if the ack made the witness
+    /// reach it.
+    fn update_distribution_summary(
+        &mut self,
+        key: &ChunkStateWitnessKey,
+        ack: &VersionedChunkStateWitnessAck,
+        received_time: Instant,
+        validator: Option<&AccountId>,
+    ) -> Option<(ChunkProductionKey, Duration)> {
+        let record = self.summaries.get_mut(key)?;
+        match validator {
+            Some(validator) => {
+                record.acked_by.insert(validator.clone());
+            }
+            None => record.unattributed_acks += 1,
+        }
+        if let VersionedChunkStateWitnessAck::V2(ack) = ack {
+            record_decode_stats(&mut record.summary, ack);
+        }
+        let elapsed = received_time.signed_duration_since(record.sent_timestamp);
+        let summary = &mut record.summary;
+        summary.acks_received += 1;
+        summary.time_to_first_ack.get_or_insert(elapsed);
+        if elapsed <= TIMELY_ACK_WINDOW {
+            summary.timely_acks_received += 1;
+        }
+        if !summary.emitted && summary.acks_received >= summary.num_validators {
+            summary.emit();
+        }
+        if summary.time_to_ack_threshold.is_some()
+            || !reached_ack_threshold(summary.acks_received, summary.num_validators)
+        {
+            return None;
+        }
+        summary.time_to_ack_threshold = Some(elapsed);
+        let key = ChunkProductionKey {
+            epoch_id: summary.epoch_id,
+            shard_id: summary.shard_id,
+            height_created: summary.height_created,
+        };
+        Some((key, elapsed))
+    }
+
+    /// Logs the distribution summaries of the witnesses for which some of the acks didn't arrive
+    /// within `DISTRIBUTION_SUMMARY_TIMEOUT`. Returns the number of summaries logged.
+    pub fn emit_overdue_distribution_summaries(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut num_emitted = 0;
+        for (_, record) in self.summaries.iter_mut() {
+            if !record.summary.emitted
+                && now.signed_duration_since(record.sent_timestamp) >= DISTRIBUTION_SUMMARY_TIMEOUT
+            {
+                record.summary.emit();
+                num_emitted += 1;
+            }
+        }
+        num_emitted
+    }
+
+    /// Checks the acked stake of the witnesses sent at least `deadline` ago, once per witness,
+    /// and reports the ones whose chunk validators holding two thirds of the stake didn't ack
+    /// them, see `AckThresholdMiss`. Returns the number of witnesses reported.
+    pub fn check_ack_threshold_deadlines(&mut self, deadline: Duration) -> usize {
+        let now = self.clock.now();
+        let mut num_missed = 0;
+        for (_, record) in self.summaries.iter_mut() {
+            if record.ack_deadline_checked
+                || now.signed_duration_since(record.sent_timestamp) < deadline
+            {
+                continue;
             }
+            record.ack_deadline_checked = true;
+            let Some(miss) = record.check_acked_stake() else {
+                continue;
+            };
+            let summary = &record.summary;
+            tracing::warn!(
+                target: "client",
+                height = summary.height_created,
+                shard_id = summary.shard_id,
+                acked_stake_fraction = miss.acked_stake_fraction,
+                missing_acks = ?miss.missing_acks,
+                unattributed_acks = miss.unattributed_acks,
+                ?deadline,
+                "Chunk validators holding two thirds of the stake didn't ack the witness in time",
+            );
+            let shard_id_label = int_label(summary.shard_id);
+            metrics::PARTIAL_WITNESS_ACK_THRESHOLD_MISSES
+                .with_label_values(&[&shard_id_label])
+                .inc();
+            metrics::PARTIAL_WITNESS_ACKED_STAKE_AT_MISS
+                .with_label_values(&[&shard_id_label])
+                .observe(miss.acked_stake_fraction);
+            for account_id in &miss.missing_acks {
+                metrics::PARTIAL_WITNESS_MISSING_ACKS
+                    .with_label_values(&[account_id.as_str()])
+                    .inc();
+            }
+            record.summary.ack_threshold_miss = Some(miss);
+            num_missed += 1;
         }
+        num_missed
+    }
+
+    /// Returns the distribution summaries of the recent witnesses, starting from the most
+    /// recent one.
+    pub fn recent_distribution_summaries(
+        &self,
+    ) -> impl Iterator<Item = &WitnessDistributionSummary> {
+        self.summaries.iter().map(|(_, record)| &record.summary)
     }
 
     /// Records the roundtrip time in metrics.
-    fn update_roundtrip_time_metric(record: &ChunkStateWitnessRecord, clock: &Clock) -> () {
-        let received_time = clock.now();
+    fn update_roundtrip_time_metric(record: &ChunkStateWitnessRecord, received_time: Instant) {
         if received_time > record.sent_timestamp {
             metrics::CHUNK_STATE_WITNESS_NETWORK_ROUNDTRIP_TIME
                 .with_label_values(&[witness_size_bucket(record.witness_size)])
@@ -123,6 +500,41 @@ impl ChunkStateWitnessTracker {
     }
 }
 
+/// Whether the acks of two thirds of the validators were received, which mirrors the two thirds of
+/// the stake needed to endorse the chunk. The acks are counted per validator, as the stakes of the
+/// validators are not known here.
+fn reached_ack_threshold(acks_received: usize, num_validators: usize) -> bool {
+    acks_received * 3 >= num_validators * 2
+}
+
+/// Aggregates the number of parts the chunk validator had received when it decoded the witness.
+/// A validator which decoded it with fewer parts than the data parts could only decode it thanks
+/// to the full witness, the parts alone weren't enough yet. A validator which needed more than one
+/// attempt got at least one bad part, which the extra parts only masked.
+fn record_decode_stats(summary: &mut WitnessDistributionSummary, ack: &ChunkStateWitnessAckV2) {
+    let parts_received = ack.parts_received_at_decode as usize;
+    let path = if ack.via_full_witness {
+        WitnessDecodePath::FullWitness
+    } else {
+        WitnessDecodePath::Parts
+    };
+    let shard_id_label = int_label(summary.shard_id);
+    metrics::PARTIAL_WITNESS_ACK_PARTS_RECEIVED_AT_DECODE
+        .with_label_values(&[&shard_id_label, path.as_str()])
+        .observe(parts_received as f64);
+    if parts_received < witness_parts_geometry::data_parts(summary.num_parts) {
+        metrics::PARTIAL_WITNESS_ACKS_BELOW_DATA_PARTS.with_label_values(&[&shard_id_label]).inc();
+    }
+    if ack.decode_attempts > 1 {
+        metrics::PARTIAL_WITNESS_ACKS_DECODED_AFTER_RETRY
+            .with_label_values(&[&shard_id_label])
+            .inc();
+    }
+    summary.min_parts_received_at_decode = Some(
+        summary.min_parts_received_at_decode.map_or(parts_received, |min| min.min(parts_received)),
+    );
+}
+
 /// Buckets for state-witness size.
 static SIZE_IN_BYTES_TO_BUCKET: &'static [(ByteSize, &str)] = &[
     (ByteSize::kb(1), "<1KB"),
@@ -152,10 +564,14 @@ mod state_witness_tracker_tests {
     use super::*;
     use near_async::time::{Duration, FakeClock, Utc};
     use near_primitives::hash::hash;
-    use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+    use near_primitives::sharding::ChunkHash;
+    use near_primitives::stateless_validation::state_witness::{
+        ChunkStateWitness, ChunkStateWitnessAck,
+    };
     use near_primitives::types::ShardId;
 
     const NUM_VALIDATORS: usize = 3;
+    const ACK_THRESHOLD_DEADLINE: Duration = Duration::milliseconds(800);
 
     #[test]
     fn record_and_receive_ack_num_validators_decreased() {
@@ -163,12 +579,17 @@ mod state_witness_tracker_tests {
         let clock = dummy_clock();
         let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
 
-        tracker.record_witness_sent(witness.chunk_header.compute_hash(), 4321, NUM_VALIDATORS);
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
         clock.advance(Duration::milliseconds(3444));
 
         // Ack received from all "except for one".
         for _ in 1..NUM_VALIDATORS {
-            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness));
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
         }
 
         let record = tracker.get_record_for_witness(&witness);
@@ -182,12 +603,17 @@ mod state_witness_tracker_tests {
         let clock = dummy_clock();
         let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
 
-        tracker.record_witness_sent(witness.chunk_header.compute_hash(), 4321, NUM_VALIDATORS);
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
         clock.advance(Duration::milliseconds(3444));
 
         // Ack received from all.
         for _ in 1..=NUM_VALIDATORS {
-            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness));
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
         }
 
         let record = tracker.get_record_for_witness(&witness);
@@ -204,6 +630,386 @@ mod state_witness_tracker_tests {
         assert_eq!(witness_size_bucket(25_000_000), ">20MB");
     }
 
+    #[test]
+    fn distribution_summary_emitted_when_all_acks_received() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(300));
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        clock.advance(Duration::milliseconds(900));
+        for _ in 1..NUM_VALIDATORS {
+            assert!(!tracker.recent_distribution_summaries().next().unwrap().emitted);
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        }
+
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert!(summary.emitted);
+        assert_eq!(summary.acks_received, NUM_VALIDATORS);
+        assert_eq!(summary.timely_acks_received, 1);
+        assert_eq!(summary.time_to_first_ack, Some(Duration::milliseconds(300)));
+        assert_eq!(tracker.emit_overdue_distribution_summaries(), 0);
+    }
+
+    #[test]
+    fn ack_threshold_reached_once() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(100));
+        assert_eq!(
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None),
+            None
+        );
+        clock.advance(Duration::milliseconds(200));
+        let (key, time) = tracker
+            .on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None)
+            .unwrap();
+        assert_eq!((key.shard_id, key.height_created), (2, 100));
+        assert_eq!(time, Duration::milliseconds(300));
+        assert_eq!(
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None),
+            None
+        );
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.time_to_ack_threshold, Some(Duration::milliseconds(300)));
+    }
+
+    #[test]
+    fn distribution_summary_emitted_after_timeout() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(500));
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        assert_eq!(tracker.emit_overdue_distribution_summaries(), 0);
+
+        clock.advance(DISTRIBUTION_SUMMARY_TIMEOUT);
+        assert_eq!(tracker.emit_overdue_distribution_summaries(), 1);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert!(summary.emitted);
+        assert_eq!(summary.acks_received, 1);
+        assert_eq!(summary.timely_acked_fraction(), 1.0 / NUM_VALIDATORS as f64);
+
+        // The summary is logged only once, even if the missing acks arrive later.
+        clock.advance(DISTRIBUTION_SUMMARY_TIMEOUT);
+        assert_eq!(tracker.emit_overdue_distribution_summaries(), 0);
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        assert_eq!(tracker.recent_distribution_summaries().next().unwrap().acks_received, 3);
+    }
+
+    #[test]
+    fn held_time_is_subtracted_from_roundtrip_time() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(1010));
+        tracker.on_held_witness_ack_received(
+            ChunkStateWitnessAck::new(&witness).into(),
+            Duration::milliseconds(15),
+            None,
+        );
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.time_to_first_ack, Some(Duration::milliseconds(995)));
+        assert_eq!(summary.timely_acks_received, 1);
+
+        // The held time reported above the cap is only subtracted up to the cap.
+        clock.advance(Duration::milliseconds(15));
+        tracker.on_held_witness_ack_received(
+            ChunkStateWitnessAck::new(&witness).into(),
+            Duration::seconds(1),
+            None,
+        );
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.acks_received, 2);
+        assert_eq!(summary.timely_acks_received, 1);
+    }
+
+    #[test]
+    fn witnesses_without_acks_are_evicted() {
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        let num_witnesses = 10_000;
+        for i in 0..num_witnesses {
+            let chunk_hash = ChunkHash(hash(&(i as u64).to_le_bytes()));
+            tracker.record_witness_sent(
+                chunk_hash,
+                dummy_summary(NUM_VALIDATORS),
+                &dummy_assignments(NUM_VALIDATORS),
+                &producer(),
+            );
+            clock.advance(Duration::milliseconds(100));
+            assert!(tracker.witnesses.len() <= CHUNK_STATE_WITNESS_MAX_RECORD_COUNT);
+            assert!(tracker.summaries.len() <= CHUNK_STATE_WITNESS_MAX_RECORD_COUNT);
+        }
+        assert_eq!(tracker.witnesses.len(), CHUNK_STATE_WITNESS_MAX_RECORD_COUNT);
+
+        // The oldest witnesses are evicted first, even if acks arrive for the remaining ones.
+        let oldest_tracked = num_witnesses - CHUNK_STATE_WITNESS_MAX_RECORD_COUNT;
+        let oldest_key =
+            ChunkStateWitnessKey::new(ChunkHash(hash(&(oldest_tracked as u64).to_le_bytes())));
+        assert!(tracker.witnesses.peek_lru().is_some_and(|(key, _)| key == &oldest_key));
+
+        // Acks for the evicted witnesses are ignored.
+        let evicted_key = ChunkStateWitnessKey::new(ChunkHash(hash(&0u64.to_le_bytes())));
+        tracker.on_witness_ack_received(
+            ChunkStateWitnessAck { chunk_hash: evicted_key.chunk_hash.clone() }.into(),
+            None,
+        );
+        assert_eq!(tracker.witnesses.len(), CHUNK_STATE_WITNESS_MAX_RECORD_COUNT);
+        assert!(tracker.witnesses.peek(&evicted_key).is_none());
+    }
+
+    #[test]
+    fn witness_sent_again_updates_the_record() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(300));
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        let mut resent_summary = dummy_summary(NUM_VALIDATORS);
+        resent_summary.encode_time = Duration::milliseconds(7);
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            resent_summary,
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+
+        assert_eq!(tracker.witnesses.len(), 1);
+        assert_eq!(tracker.summaries.len(), 1);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.encode_time, Duration::milliseconds(7));
+        assert_eq!(summary.acks_received, 1);
+        assert_eq!(summary.time_to_first_ack, Some(Duration::milliseconds(300)));
+
+        // The acks of the remaining validators complete the witness, whichever send they ack.
+        for _ in 1..NUM_VALIDATORS {
+            tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        }
+        assert!(tracker.get_record_for_witness(&witness).is_none());
+        assert!(tracker.recent_distribution_summaries().next().unwrap().emitted);
+    }
+
+    #[test]
+    fn parts_received_at_decode_are_aggregated() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+        let below_data_parts =
+            || metrics::PARTIAL_WITNESS_ACKS_BELOW_DATA_PARTS.with_label_values(&["2"]).get();
+        let decoded_after_retry =
+            || metrics::PARTIAL_WITNESS_ACKS_DECODED_AFTER_RETRY.with_label_values(&["2"]).get();
+        let ack = |parts_received_at_decode, via_full_witness, decode_attempts| {
+            VersionedChunkStateWitnessAck::V2(ChunkStateWitnessAckV2 {
+                chunk_hash: witness.chunk_header.chunk_hash(),
+                parts_received_at_decode,
+                via_full_witness,
+                decode_attempts,
+            })
+        };
+
+        // The witness is encoded into 4 parts, 2 of which are data parts.
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        let below_data_parts_before = below_data_parts();
+        let decoded_after_retry_before = decoded_after_retry();
+        tracker.on_witness_ack_received(ack(3, false, 2), None);
+        tracker.on_witness_ack_received(ack(2, false, 1), None);
+        assert_eq!(below_data_parts(), below_data_parts_before);
+        assert_eq!(decoded_after_retry(), decoded_after_retry_before + 1);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.min_parts_received_at_decode, Some(2));
+
+        // The validator decoded the full witness before the parts were enough.
+        tracker.on_witness_ack_received(ack(1, true, 1), None);
+        assert_eq!(below_data_parts(), below_data_parts_before + 1);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.min_parts_received_at_decode, Some(1));
+        assert_eq!(summary.acks_received, NUM_VALIDATORS);
+
+        // The V1 acks don't report the parts received.
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.min_parts_received_at_decode, None);
+    }
+
+    #[test]
+    fn ack_threshold_reached_before_deadline() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(300));
+        for validator in [validator(0), validator(2)] {
+            tracker.on_witness_ack_received(
+                ChunkStateWitnessAck::new(&witness).into(),
+                Some(&validator),
+            );
+        }
+
+        clock.advance(ACK_THRESHOLD_DEADLINE);
+        assert_eq!(tracker.check_ack_threshold_deadlines(ACK_THRESHOLD_DEADLINE), 0);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(summary.ack_threshold_miss, None);
+    }
+
+    #[test]
+    fn ack_threshold_missed_with_partial_acks() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &dummy_assignments(NUM_VALIDATORS),
+            &producer(),
+        );
+        clock.advance(Duration::milliseconds(300));
+        tracker.on_witness_ack_received(
+            ChunkStateWitnessAck::new(&witness).into(),
+            Some(&validator(1)),
+        );
+        // The ack of an unknown chunk validator doesn't count towards the acked stake.
+        tracker.on_witness_ack_received(ChunkStateWitnessAck::new(&witness).into(), None);
+        assert_eq!(tracker.check_ack_threshold_deadlines(ACK_THRESHOLD_DEADLINE), 0);
+
+        clock.advance(ACK_THRESHOLD_DEADLINE);
+        assert_eq!(tracker.check_ack_threshold_deadlines(ACK_THRESHOLD_DEADLINE), 1);
+        let summary = tracker.recent_distribution_summaries().next().unwrap();
+        assert_eq!(
+            summary.ack_threshold_miss,
+            Some(AckThresholdMiss {
+                acked_stake_fraction: 0.5,
+                missing_acks: vec![validator(0), validator(2)],
+                unattributed_acks: 1,
+            })
+        );
+
+        // The witness is reported once, even if the missing acks arrive later.
+        tracker.on_witness_ack_received(
+            ChunkStateWitnessAck::new(&witness).into(),
+            Some(&validator(0)),
+        );
+        clock.advance(ACK_THRESHOLD_DEADLINE);
+        assert_eq!(tracker.check_ack_threshold_deadlines(ACK_THRESHOLD_DEADLINE), 0);
+    }
+
+    #[test]
+    fn ack_threshold_missed_without_acks() {
+        let witness = dummy_witness();
+        let clock = dummy_clock();
+        let mut tracker = ChunkStateWitnessTracker::new(clock.clock());
+        // We are not a chunk validator of the chunk, so none of the stake has the witness.
+        let chunk_validators = ChunkValidatorAssignments::new(
+            (0..NUM_VALIDATORS).map(|i| (validator(i), 100 + i as Balance)).collect(),
+        );
+        tracker.record_witness_sent(
+            witness.chunk_header.compute_hash(),
+            dummy_summary(NUM_VALIDATORS),
+            &chunk_validators,
+            &producer(),
+        );
+
+        clock.advance(ACK_THRESHOLD_DEADLINE);
+        assert_eq!(tracker.check_ack_threshold_deadlines(ACK_THRESHOLD_DEADLINE), 1);
+        let miss =
+            tracker.recent_distribution_summaries().next().unwrap().ack_threshold_miss.clone();
+        assert_eq!(
+            miss,
+            Some(AckThresholdMiss {
+                acked_stake_fraction: 0.0,
+                missing_acks: chunk_validators.ordered_chunk_validators(),
+                unattributed_acks: 0,
+            })
+        );
+    }
+
+    /// Us as the chunk producer and chunk validator, followed by `num_validators` other chunk
+    /// validators, all with the same stake.
+    fn dummy_assignments(num_validators: usize) -> ChunkValidatorAssignments {
+        let chunk_validators = std::iter::once(producer())
+            .chain((0..num_validators).map(validator))
+            .map(|account_id| (account_id, 100))
+            .collect();
+        ChunkValidatorAssignments::new(chunk_validators)
+    }
+
+    fn producer() -> AccountId {
+        "producer".parse().unwrap()
+    }
+
+    fn validator(index: usize) -> AccountId {
+        format!("validator{index}").parse().unwrap()
+    }
+
+    fn dummy_summary(num_validators: usize) -> WitnessDistributionSummary {
+        WitnessDistributionSummary::new(
+            EpochId::default(),
+            100,
+            2,
+            10000,
+            4321,
+            num_validators + 1,
+            num_validators,
+            Duration::milliseconds(20),
+            Duration::milliseconds(5),
+        )
+    }
+
     fn dummy_witness() -> ChunkStateWitness {
         ChunkStateWitness::new_dummy(100, 2 as ShardId, hash("fake hash".as_bytes()))
     }
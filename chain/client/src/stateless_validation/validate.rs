@@ -1,45 +1,404 @@
-use super::partial_witness::witness_part_length;
+use super::partial_witness::{
+    next_epoch_chunk_validators, witness_parts_geometry, AcceptedPartFormats, PartialWitnessPart,
+    MAX_WITNESS_PARTS,
+};
+use crate::metrics;
 use itertools::Itertools;
+use near_async::time::{Clock, Instant};
 use near_chain::types::Tip;
 use near_chain_primitives::Error;
 use near_epoch_manager::EpochManagerAdapter;
+use near_o11y::metrics::int_label;
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsementV2;
 use near_primitives::stateless_validation::partial_witness::{
-    PartialEncodedStateWitness, MAX_COMPRESSED_STATE_WITNESS_SIZE,
+    ChunkValidatorsDigest, FullEncodedStateWitness, PartialEncodedStateWitness, WitnessSizeBand,
+    WitnessSizeLimits,
 };
 use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, BlockHeightDelta};
 use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::version::{ProtocolFeature, ProtocolVersion};
 use near_store::{DBCol, Store, FINAL_HEAD_KEY, HEAD_KEY};
 
 /// This is taken to be the same value as near_chunks::chunk_cache::MAX_HEIGHTS_AHEAD, and we
 /// reject partial witnesses with height more than this value above the height of our current HEAD
 const MAX_HEIGHTS_AHEAD: BlockHeightDelta = 5;
 
+/// Number of protocol versions before the protocol version of the epoch of a partial witness
+/// under which the chunk producer may have created the part, see `validate_part_protocol_version`.
+const MAX_PART_PROTOCOL_VERSIONS_BEHIND: ProtocolVersion = 1;
+
+/// Chain tip whose height window admitted a witness part, see
+/// `validate_chunk_production_key_height_window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeightWindowContext {
+    /// The head of the chain.
+    Head,
+    /// The highest known tip of a fork other than the one of the head, see
+    /// `PartialWitnessConfig::fork_aware_height_window`.
+    AlternativeTip,
+}
+
+impl HeightWindowContext {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeightWindowContext::Head => "head",
+            HeightWindowContext::AlternativeTip => "alternative_tip",
+        }
+    }
+}
+
+/// Head and final head of the chain, against which the heights of the chunks are checked, see
+/// `validate_chunk_production_key_height_window`.
+#[derive(Debug, Clone, Default)]
+pub struct ChainHeads {
+    pub head: Option<Tip>,
+    pub final_head: Option<Tip>,
+}
+
+impl ChainHeads {
+    pub fn load(store: &Store) -> Result<Self, Error> {
+        // TODO(https://github.com/near/nearcore/issues/11301): replace these direct DB accesses with messages
+        // sent to the client actor. for a draft, see https://github.com/near/nearcore/commit/e186dc7c0b467294034c60758fe555c78a31ef2d
+        Ok(Self {
+            head: store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)?,
+            final_head: store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?,
+        })
+    }
+}
+
+/// Everything besides the part itself and the current time that the acceptance of a partial
+/// witness depends on, gathered by the caller for every part. The decision of
+/// `validate_partial_encoded_state_witness` only depends on the part, the context and the time.
+pub struct ValidationContext<'a> {
+    pub epoch_manager: &'a dyn EpochManagerAdapter,
+    /// Our account, a chunk validator of the chunk, or a chunk validator of the shard in the next
+    /// epoch if `pre_tracking`.
+    pub account_id: &'a AccountId,
+    /// Whether we reconstruct the witness of a shard we are going to validate soon, without being
+    /// a chunk validator of the chunk, see `PartialWitnessConfig::pre_tracked_shards`.
+    pub pre_tracking: bool,
+    pub heads: ChainHeads,
+    /// The highest known tip of a fork other than the one of the head, only given with
+    /// `PartialWitnessConfig::fork_aware_height_window`.
+    pub alternative_tip: Option<&'a Tip>,
+    /// Time at which the witness of the part is abandoned, if its first part already arrived,
+    /// see `PartialWitnessConfig::incomplete_witness_deadline`.
+    pub deadline: Option<Instant>,
+    /// Whether the witness of the part was already abandoned past its deadline.
+    pub expired: bool,
+}
+
 /// Function to validate the partial encoded state witness. In addition of ChunkProductionKey, we check the following:
-/// - part_ord is valid and within range of the number of expected parts for this chunk
-/// - partial_witness signature is valid and from the expected chunk_producer
+/// - the witness of the part wasn't abandoned, nor is its deadline reached at `clock.now()`,
+///   checked before spending any time on the validation
+/// - shard_id is in the shard layout of the epoch, which the caller checks before anything is
+///   looked up for the shard, see `validate_shard_id`
+/// - part_ord is below `MAX_WITNESS_PARTS` and within range of the number of expected parts for this chunk
+/// - the chunk has at least one chunk validator
+/// - owner, when the part carries it, is the chunk validator assigned to part_ord, see
+///   `witness_parts_geometry::part_owners`
+/// - the part is in one of the formats accepted at the protocol version of the epoch, see
+///   `AcceptedPartFormats`; the send timestamp itself is only used for metrics and never checked
+/// - a part in the V3 format or later commits to the expected number of parts
+/// - a part in the V4 format was created under a protocol version allowed for the epoch, see
+///   `validate_part_protocol_version`
+/// - we are a chunk validator of the chunk or, when pre-tracking the shard, a chunk validator of
+///   the shard in the next epoch, see `next_epoch_chunk_validators`
+/// - partial_witness signature is valid and from the expected chunk_producer, see
+///   `verify_partial_witness_signature`. The signature is checked by `verify_signature`, which
+///   lets the caller account for the cost of the check.
+///
+/// The height of the part is checked against the window of the head, or of the alternative tip if
+/// given, see `validate_chunk_production_key_height_window`. Returns the tip which admitted the
+/// part, None if the part should not be processed at this point.
 /// TODO(stateless_validation): Include checks from handle_orphan_state_witness in orphan_witness_handling.rs
 /// These include checks based on epoch_id validity, witness size, height_created, distance from chain head, etc.
 pub fn validate_partial_encoded_state_witness(
-    epoch_manager: &dyn EpochManagerAdapter,
     partial_witness: &PartialEncodedStateWitness,
+    context: &ValidationContext,
+    clock: &Clock,
+    verify_signature: impl FnOnce() -> Result<bool, Error>,
+) -> Result<Option<HeightWindowContext>, Error> {
+    if is_past_deadline(partial_witness, context, clock.now()) {
+        return Ok(None);
+    }
+    let epoch_manager = context.epoch_manager;
+    validate_partial_encoded_state_witness_part(epoch_manager, partial_witness)?;
+
+    let chunk_production_key = partial_witness.chunk_production_key();
+    if context.pre_tracking {
+        if !is_next_epoch_chunk_validator(context, &chunk_production_key)? {
+            return Ok(None);
+        }
+    } else {
+        validate_chunk_production_key_validator(
+            epoch_manager,
+            &chunk_production_key,
+            context.account_id,
+        )?;
+    }
+    let Some(height_window) = validate_chunk_production_key_height_window(
+        epoch_manager,
+        &chunk_production_key,
+        &context.heads,
+        context.alternative_tip,
+    )?
+    else {
+        return Ok(None);
+    };
+
+    verify_partial_witness_signature(epoch_manager, partial_witness, verify_signature)?;
+
+    Ok(Some(height_window))
+}
+
+/// Returns whether the part belongs to a witness abandoned past its deadline, in which case the
+/// part is dropped. The deadline is checked against `now` as well, so that the part is dropped
+/// even if the witness wasn't abandoned yet when the part arrived right at the deadline.
+fn is_past_deadline(
+    partial_witness: &PartialEncodedStateWitness,
+    context: &ValidationContext,
+    now: Instant,
+) -> bool {
+    if !context.expired && context.deadline.map_or(true, |deadline| now < deadline) {
+        return false;
+    }
+    let chunk_production_key = partial_witness.chunk_production_key();
+    tracing::debug!(
+        target: "stateless_validation",
+        ?chunk_production_key,
+        part_ord = partial_witness.part_ord(),
+        "Dropping witness part arriving after the deadline"
+    );
+    metrics::PARTIAL_WITNESS_PARTS_AFTER_DEADLINE
+        .with_label_values(&[&int_label(chunk_production_key.shard_id)])
+        .inc();
+    true
+}
+
+/// Checks that we are a chunk validator of the shard in the epoch after the one of the head, as
+/// required to reconstruct the witness of a pre-tracked shard, for which we are not a chunk
+/// validator of the chunk but are going to validate the shard soon. The owners of the parts
+/// forward them to the same validators, see `pre_tracking`.
+fn is_next_epoch_chunk_validator(
+    context: &ValidationContext,
+    chunk_production_key: &ChunkProductionKey,
+) -> Result<bool, Error> {
+    let Some(head) = &context.heads.head else {
+        return Ok(false);
+    };
+    let next_epoch_chunk_validators =
+        next_epoch_chunk_validators(context.epoch_manager, head, chunk_production_key)?;
+    if !next_epoch_chunk_validators.contains(context.account_id) {
+        tracing::debug!(
+            target: "stateless_validation",
+            ?chunk_production_key,
+            "Skipping pre-tracked part because we are not its chunk validator in the next epoch",
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Function to validate the full encoded state witness sent directly by the chunk producer.
+/// The caller checks that shard_id is valid before, see `validate_shard_id`. In addition of
+/// ChunkProductionKey, we check the following:
+/// - the full witnesses are enabled at the protocol version of the epoch, as no honest node
+///   sends them before `ProtocolFeature::DirectFullWitness`
+/// - the size of the witness doesn't exceed the limit, see `WitnessSizeLimits`
+/// - full_witness signature is valid and from the expected chunk_producer
+pub fn validate_full_encoded_state_witness(
+    epoch_manager: &dyn EpochManagerAdapter,
+    full_witness: &FullEncodedStateWitness,
     signer: &ValidatorSigner,
     store: &Store,
 ) -> Result<bool, Error> {
+    if !validate_chunk_production_key(
+        epoch_manager,
+        full_witness.chunk_production_key(),
+        signer.validator_id(),
+        store,
+    )? {
+        return Ok(false);
+    }
+
+    let epoch_id = full_witness.chunk_production_key().epoch_id;
+    let protocol_version = epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+    if !ProtocolFeature::DirectFullWitness.enabled(protocol_version) {
+        return Err(Error::InvalidChunkStateWitness(format!(
+            "Received a full witness at protocol version {} before the full witnesses are enabled",
+            protocol_version
+        )));
+    }
+
+    // The witnesses within the grace band are accepted, see `WitnessSizeLimits`.
+    let limits = WitnessSizeLimits::for_protocol_version(protocol_version);
+    if limits.band(full_witness.size_bytes()) == WitnessSizeBand::TooLarge {
+        return Err(Error::InvalidChunkStateWitness(format!(
+            "Full witness size {} exceeds limit of {}",
+            full_witness.size_bytes(),
+            limits.max_accepted_size()
+        )));
+    }
+
+    if !epoch_manager.verify_full_witness_signature(&full_witness)? {
+        return Err(Error::InvalidChunkStateWitness("Invalid signature".to_string()));
+    }
+
+    Ok(true)
+}
+
+/// Compares the chunk validators signed into the V3 part by the chunk producer with the chunk
+/// validators of the chunk according to our epoch manager. Returns our view if the two differ,
+/// None if they match or if the part doesn't carry the view of the chunk producer.
+///
+/// The owners of the parts are already checked in `validate_partial_encoded_state_witness_part`,
+/// but they are sorted by account id, so a different order of the assignment is only caught here.
+pub fn chunk_validators_digest_mismatch(
+    epoch_manager: &dyn EpochManagerAdapter,
+    partial_witness: &PartialEncodedStateWitness,
+) -> Result<Option<ChunkValidatorsDigest>, Error> {
+    let Some(signed_digest) = partial_witness.chunk_validators_digest() else {
+        return Ok(None);
+    };
     let ChunkProductionKey { shard_id, epoch_id, height_created } =
         partial_witness.chunk_production_key();
-    let num_parts =
-        epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?.len();
-    if partial_witness.part_ord() >= num_parts {
+    let chunk_validator_assignments =
+        epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?;
+    let digest =
+        ChunkValidatorsDigest::new(&chunk_validator_assignments.ordered_chunk_validators());
+    Ok((&digest != signed_digest).then_some(digest))
+}
+
+/// Checks that the partial witness is signed by the chunk producer of the chunk. A part signed
+/// with the valid key of another validator of the epoch is rejected with `Error::WrongProducer`,
+/// so that the misbehavior is attributed to the validator which signed it. Only the keys of the
+/// few validators returned by `suspected_signers` are checked.
+fn verify_partial_witness_signature(
+    epoch_manager: &dyn EpochManagerAdapter,
+    partial_witness: &PartialEncodedStateWitness,
+    verify_signature: impl FnOnce() -> Result<bool, Error>,
+) -> Result<(), Error> {
+    if verify_signature()? {
+        return Ok(());
+    }
+    let ChunkProductionKey { shard_id, epoch_id, height_created } =
+        partial_witness.chunk_production_key();
+    let chunk_producer = epoch_manager.get_chunk_producer(&epoch_id, height_created, shard_id)?;
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id)?;
+    let suspected_signers = suspected_signers(epoch_manager, partial_witness)?;
+    Err(invalid_partial_witness_signature_error(
+        partial_witness,
+        chunk_producer,
+        suspected_signers
+            .iter()
+            .filter_map(|account_id| epoch_info.get_validator_by_account(account_id)),
+    ))
+}
+
+/// Accounts which may have signed the partial witness failing the check against the chunk
+/// producer's key. The V4 parts claim their signer, see
+/// `PartialEncodedStateWitness::claimed_signer`. For the older parts, the suspects are the owner
+/// of the part and the chunk producers of the adjacent heights of the shard, which are the ones
+/// to hold the part or to produce a chunk of the shard around the same time. A part signed by
+/// anyone else is only rejected as invalid, as checking every validator of the epoch would cost
+/// a signature verification per validator.
+fn suspected_signers(
+    epoch_manager: &dyn EpochManagerAdapter,
+    partial_witness: &PartialEncodedStateWitness,
+) -> Result<Vec<AccountId>, Error> {
+    if let Some(claimed_signer) = partial_witness.claimed_signer() {
+        return Ok(vec![claimed_signer.clone()]);
+    }
+    let ChunkProductionKey { shard_id, epoch_id, height_created } =
+        partial_witness.chunk_production_key();
+    let chunk_validator_assignments =
+        epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?;
+    let owner = witness_parts_geometry::part_owner(&chunk_validator_assignments, partial_witness);
+    let mut adjacent_chunk_producers = vec![];
+    for height in [height_created.saturating_sub(1), height_created.saturating_add(1)] {
+        adjacent_chunk_producers
+            .push(epoch_manager.get_chunk_producer(&epoch_id, height, shard_id)?);
+    }
+    Ok(owner.into_iter().chain(adjacent_chunk_producers).unique().collect())
+}
+
+/// Attributes the partial witness failing the check against the chunk producer's key to the
+/// first of the suspected signers whose key verifies the signature, see `suspected_signers`.
+fn invalid_partial_witness_signature_error(
+    partial_witness: &PartialEncodedStateWitness,
+    chunk_producer: AccountId,
+    suspected_signers: impl Iterator<Item = ValidatorStake>,
+) -> Error {
+    let signer = suspected_signers
+        .filter(|validator| validator.account_id() != &chunk_producer)
+        .find(|validator| partial_witness.verify(validator.public_key()));
+    let Some(signer) = signer else {
+        return Error::InvalidPartialChunkStateWitness("Invalid signature".to_string());
+    };
+    metrics::PARTIAL_WITNESS_WRONG_PRODUCER
+        .with_label_values(&[signer.account_id().as_str()])
+        .inc();
+    Error::WrongProducer { expected: chunk_producer, actual: signer.take_account_id() }
+}
+
+/// Checks that part_ord, owner and part size of the partial witness are consistent with
+/// the chunk validators of the chunk.
+fn validate_partial_encoded_state_witness_part(
+    epoch_manager: &dyn EpochManagerAdapter,
+    partial_witness: &PartialEncodedStateWitness,
+) -> Result<(), Error> {
+    // Cheap check before looking up the chunk validators, no chunk can have more parts.
+    if partial_witness.part_ord() >= MAX_WITNESS_PARTS {
+        return Err(Error::InvalidPartialChunkStateWitness(format!(
+            "part_ord {} exceeds the maximum number of parts {}",
+            partial_witness.part_ord(),
+            MAX_WITNESS_PARTS
+        )));
+    }
+    let ChunkProductionKey { shard_id, epoch_id, height_created } =
+        partial_witness.chunk_production_key();
+    let part = PartialWitnessPart::from(partial_witness);
+    let part_owners = witness_parts_geometry::non_empty_part_owners(
+        &epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?,
+        part.format,
+        &partial_witness.chunk_production_key(),
+    )?;
+    let num_parts = part_owners.len();
+    let Some(expected_owner) = part_owners.get(partial_witness.part_ord()) else {
         return Err(Error::InvalidPartialChunkStateWitness(format!(
             "Invalid part_ord in PartialEncodedStateWitness: {}",
             partial_witness.part_ord()
         )));
+    };
+    // The V1 parts don't carry their owner, it follows from part_ord.
+    if let Some(owner) = partial_witness.owner() {
+        if expected_owner != owner {
+            return Err(Error::InvalidPartialChunkStateWitnessOwner(format!(
+                "part_ord {} is owned by {} but the part claims owner {}",
+                partial_witness.part_ord(),
+                expected_owner,
+                owner
+            )));
+        }
     }
 
-    let max_part_len =
-        witness_part_length(MAX_COMPRESSED_STATE_WITNESS_SIZE.as_u64() as usize, num_parts);
+    let protocol_version = epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+    // The witnesses within the grace band are accepted, see `WitnessSizeLimits`.
+    let max_witness_size = WitnessSizeLimits::for_protocol_version(protocol_version)
+        .max_accepted_size()
+        .as_u64() as usize;
+    if partial_witness.encoded_length() > max_witness_size {
+        return Err(Error::InvalidPartialChunkStateWitness(format!(
+            "Encoded length {} exceeds limit of {}",
+            partial_witness.encoded_length(),
+            max_witness_size
+        )));
+    }
+    let max_part_len = witness_parts_geometry::part_len(max_witness_size, num_parts);
     if partial_witness.part_size() > max_part_len {
         return Err(Error::InvalidPartialChunkStateWitness(format!(
             "Part size {} exceed limit of {} (total parts: {})",
@@ -49,29 +408,56 @@ pub fn validate_partial_encoded_state_witness(
         )));
     }
 
-    if !validate_chunk_production_key(
-        epoch_manager,
-        partial_witness.chunk_production_key(),
-        signer.validator_id(),
-        store,
-    )? {
-        return Ok(false);
+    let accepted_formats = AcceptedPartFormats::for_protocol_version(protocol_version);
+    accepted_formats.check(&part)?;
+    if accepted_formats.is_previous_format(&part) {
+        metrics::PARTIAL_WITNESS_PREVIOUS_FORMAT_PARTS
+            .with_label_values(&[part.format.as_str()])
+            .inc();
     }
-
-    if !epoch_manager.verify_partial_witness_signature(&partial_witness)? {
-        return Err(Error::InvalidPartialChunkStateWitness("Invalid signature".to_string()));
+    if let Some(committed_num_parts) = part.num_parts {
+        if committed_num_parts != num_parts {
+            return Err(Error::InvalidPartialChunkStateWitness(format!(
+                "Parts root commits to {} parts, expected {}",
+                committed_num_parts, num_parts
+            )));
+        }
+    }
+    if let Some(part_protocol_version) = part.protocol_version {
+        validate_part_protocol_version(part_protocol_version, protocol_version)?;
     }
 
-    Ok(true)
+    Ok(())
+}
+
+/// Checks that the chunk producer created the part under the protocol version of the epoch of the
+/// part, or under one of the `MAX_PART_PROTOCOL_VERSIONS_BEHIND` versions before it, e.g. when the
+/// part was created right at the version switch and delivered late. A part created under a
+/// version above the one of the epoch follows rules we can't assume to know.
+fn validate_part_protocol_version(
+    part_protocol_version: ProtocolVersion,
+    epoch_protocol_version: ProtocolVersion,
+) -> Result<(), Error> {
+    let min_protocol_version =
+        epoch_protocol_version.saturating_sub(MAX_PART_PROTOCOL_VERSIONS_BEHIND);
+    if !(min_protocol_version..=epoch_protocol_version).contains(&part_protocol_version) {
+        return Err(Error::InvalidPartialWitnessProtocolVersion {
+            protocol_version: part_protocol_version,
+            epoch_protocol_version,
+        });
+    }
+    Ok(())
 }
 
 /// Function to validate the chunk endorsement. In addition of ChunkProductionKey, we check the following:
+/// - shard_id is in the shard layout of the epoch, see `validate_shard_id`
 /// - signature of endorsement and metadata is valid
 pub fn validate_chunk_endorsement(
     epoch_manager: &dyn EpochManagerAdapter,
     endorsement: &ChunkEndorsementV2,
     store: &Store,
 ) -> Result<bool, Error> {
+    validate_shard_id(epoch_manager, &endorsement.chunk_production_key())?;
     if !validate_chunk_production_key(
         epoch_manager,
         endorsement.chunk_production_key(),
@@ -88,8 +474,8 @@ pub fn validate_chunk_endorsement(
     Ok(true)
 }
 
-/// Function to validate ChunkProductionKey. We check the following:
-/// - shard_id is valid
+/// Function to validate ChunkProductionKey. The caller checks that shard_id is valid before, see
+/// `validate_shard_id`. We check the following:
 /// - account_id is one of the validators for the chunk
 /// - height_created is in (last_final_height..chain_head_height + MAX_HEIGHTS_AHEAD] range
 /// - epoch_id is within epoch_manager's possible_epochs_of_height_around_tip
@@ -104,11 +490,48 @@ fn validate_chunk_production_key(
     account_id: &AccountId,
     store: &Store,
 ) -> Result<bool, Error> {
+    validate_chunk_production_key_validator(epoch_manager, &chunk_production_key, account_id)?;
+    validate_chunk_production_key_height(epoch_manager, &chunk_production_key, store)
+}
+
+/// Checks that account_id is one of the chunk validators of the chunk, see
+/// `validate_chunk_production_key`.
+fn validate_chunk_production_key_validator(
+    epoch_manager: &dyn EpochManagerAdapter,
+    chunk_production_key: &ChunkProductionKey,
+    account_id: &AccountId,
+) -> Result<(), Error> {
     let shard_id = chunk_production_key.shard_id;
     let epoch_id = chunk_production_key.epoch_id;
     let height_created = chunk_production_key.height_created;
 
-    if !epoch_manager.get_shard_layout(&epoch_id)?.shard_ids().contains(&shard_id) {
+    // Reject witnesses/endorsements for chunks for which the account_id isn't a validator.
+    // It's an error, as chunk producer shouldn't send the witness/endorsement to/from a non-validator node.
+    let chunk_validator_assignments =
+        epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?;
+    if !chunk_validator_assignments.contains(account_id) {
+        return Err(Error::NotAChunkValidator);
+    }
+    Ok(())
+}
+
+/// Checks that shard_id of the ChunkProductionKey is in the shard layout of its epoch. It has to
+/// be checked before anything else is looked up for the shard, e.g. the chunk validator
+/// assignments, as the epoch manager doesn't necessarily reject the nonexistent shards.
+pub fn validate_shard_id(
+    epoch_manager: &dyn EpochManagerAdapter,
+    chunk_production_key: &ChunkProductionKey,
+) -> Result<(), Error> {
+    let shard_layout = epoch_manager.get_shard_layout(&chunk_production_key.epoch_id)?;
+    validate_shard_id_in_layout(&shard_layout, chunk_production_key)
+}
+
+fn validate_shard_id_in_layout(
+    shard_layout: &ShardLayout,
+    chunk_production_key: &ChunkProductionKey,
+) -> Result<(), Error> {
+    let shard_id = chunk_production_key.shard_id;
+    if !shard_layout.shard_ids().contains(&shard_id) {
         tracing::error!(
             target: "stateless_validation",
             ?chunk_production_key,
@@ -116,26 +539,46 @@ fn validate_chunk_production_key(
         );
         return Err(Error::InvalidShardId(shard_id));
     }
+    Ok(())
+}
 
-    // Reject witnesses/endorsements for chunks for which the account_id isn't a validator.
-    // It's an error, as chunk producer shouldn't send the witness/endorsement to/from a non-validator node.
-    let chunk_validator_assignments =
-        epoch_manager.get_chunk_validator_assignments(&epoch_id, shard_id, height_created)?;
-    if !chunk_validator_assignments.contains(account_id) {
-        return Err(Error::NotAChunkValidator);
-    }
+/// Checks that height_created and epoch_id of the ChunkProductionKey are consistent with
+/// the current chain head. See `validate_chunk_production_key` for the return values.
+fn validate_chunk_production_key_height(
+    epoch_manager: &dyn EpochManagerAdapter,
+    chunk_production_key: &ChunkProductionKey,
+    store: &Store,
+) -> Result<bool, Error> {
+    Ok(validate_chunk_production_key_height_window(
+        epoch_manager,
+        chunk_production_key,
+        &ChainHeads::load(store)?,
+        None,
+    )?
+    .is_some())
+}
 
-    // TODO(https://github.com/near/nearcore/issues/11301): replace these direct DB accesses with messages
-    // sent to the client actor. for a draft, see https://github.com/near/nearcore/commit/e186dc7c0b467294034c60758fe555c78a31ef2d
-    let head = store.get_ser::<Tip>(DBCol::BlockMisc, HEAD_KEY)?;
-    let final_head = store.get_ser::<Tip>(DBCol::BlockMisc, FINAL_HEAD_KEY)?;
+/// Checks that height_created and epoch_id of the ChunkProductionKey are consistent with the
+/// head of the chain or, failing that, with `alternative_tip`, the highest known tip of another
+/// fork. While the head is on a different fork than the one the chunk producer built on, the part
+/// may only be within the window of the other fork, and becomes relevant if the chain reorgs to
+/// it. Returns the tip which admitted the chunk, the head if there is no head yet, or None if the
+/// chunk should not be processed at this point.
+pub fn validate_chunk_production_key_height_window(
+    epoch_manager: &dyn EpochManagerAdapter,
+    chunk_production_key: &ChunkProductionKey,
+    heads: &ChainHeads,
+    alternative_tip: Option<&Tip>,
+) -> Result<Option<HeightWindowContext>, Error> {
+    let height_created = chunk_production_key.height_created;
 
     // Avoid processing state witness for old chunks.
     // In particular it is impossible for a chunk created at a height
     // that doesn't exceed the height of the current final block to be
     // included in the chain. This addresses both network-delayed messages
-    // as well as malicious behavior of a chunk producer.
-    if let Some(final_head) = final_head {
+    // as well as malicious behavior of a chunk producer. The final block
+    // is on every fork, so this holds for the alternative tip as well.
+    if let Some(final_head) = &heads.final_head {
         if height_created <= final_head.height {
             tracing::debug!(
                 target: "stateless_validation",
@@ -143,38 +586,247 @@ fn validate_chunk_production_key(
                 final_head_height = final_head.height,
                 "Skipping because height created is not greater than final head height",
             );
-            return Ok(false);
+            return Ok(None);
         }
     }
-    if let Some(head) = head {
-        if height_created > head.height + MAX_HEIGHTS_AHEAD {
+    let Some(head) = &heads.head else {
+        return Ok(Some(HeightWindowContext::Head));
+    };
+    if is_within_tip_window(epoch_manager, chunk_production_key, head)? {
+        return Ok(Some(HeightWindowContext::Head));
+    }
+    if let Some(alternative_tip) = alternative_tip {
+        if is_within_tip_window(epoch_manager, chunk_production_key, alternative_tip)? {
             tracing::debug!(
                 target: "stateless_validation",
                 ?chunk_production_key,
                 head_height = head.height,
-                "Skipping because height created is more than {} blocks ahead of head height",
-                MAX_HEIGHTS_AHEAD
+                alternative_tip_height = alternative_tip.height,
+                "Admitting outside of the head window within the window of the alternative tip",
             );
-            return Ok(false);
+            return Ok(Some(HeightWindowContext::AlternativeTip));
         }
+    }
+    Ok(None)
+}
 
-        // Try to find the EpochId to which this witness will belong based on its height.
-        // It's not always possible to determine the exact epoch_id because the exact
-        // starting height of the next epoch isn't known until it actually starts,
-        // so things can get unclear around epoch boundaries.
-        // Let's collect the epoch_ids in which the witness might possibly be.
-        let possible_epochs =
-            epoch_manager.possible_epochs_of_height_around_tip(&head, height_created)?;
-        if !possible_epochs.contains(&epoch_id) {
-            tracing::debug!(
-                target: "stateless_validation",
-                ?chunk_production_key,
-                ?possible_epochs,
-                "Skipping because EpochId is not in the possible list of epochs",
+/// Checks that height_created is at most `MAX_HEIGHTS_AHEAD` above the tip and that epoch_id is
+/// one of the epochs the height may belong to after the tip.
+fn is_within_tip_window(
+    epoch_manager: &dyn EpochManagerAdapter,
+    chunk_production_key: &ChunkProductionKey,
+    tip: &Tip,
+) -> Result<bool, Error> {
+    let epoch_id = chunk_production_key.epoch_id;
+    let height_created = chunk_production_key.height_created;
+
+    if height_created > tip.height + MAX_HEIGHTS_AHEAD {
+        tracing::debug!(
+            target: "stateless_validation",
+            ?chunk_production_key,
+            tip_height = tip.height,
+            "Skipping because height created is more than {} blocks ahead of tip height",
+            MAX_HEIGHTS_AHEAD
+        );
+        return Ok(false);
+    }
+
+    // Try to find the EpochId to which this witness will belong based on its height.
+    // It's not always possible to determine the exact epoch_id because the exact
+    // starting height of the next epoch isn't known until it actually starts,
+    // so things can get unclear around epoch boundaries.
+    // Let's collect the epoch_ids in which the witness might possibly be.
+    let possible_epochs =
+        epoch_manager.possible_epochs_of_height_around_tip(tip, height_created)?;
+    if !possible_epochs.contains(&epoch_id) {
+        tracing::debug!(
+            target: "stateless_validation",
+            ?chunk_production_key,
+            ?possible_epochs,
+            tip_height = tip.height,
+            "Skipping because EpochId is not in the possible list of epochs",
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_async::time::Utc;
+    use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+    use near_primitives::test_utils::create_test_signer;
+    use near_primitives::types::{EpochId, ShardId};
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_store::test_utils::create_test_store;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_utils::TestWitnessBuilder;
+
+    /// The V1 part owned by test2.
+    fn partial_witness_signed_by(account_id: &str) -> PartialEncodedStateWitness {
+        TestWitnessBuilder::new()
+            .encode_and_split(3, &create_test_signer(account_id))
+            .part(2)
+            .clone()
+    }
+
+    /// A V4 part, which claims its signer, signed by `account_id`.
+    fn v4_partial_witness_signed_by(account_id: &str) -> PartialEncodedStateWitness {
+        let signer = create_test_signer(account_id);
+        let chunk_header = TestWitnessBuilder::new().build().chunk_header;
+        let owner: AccountId = "test2".parse().unwrap();
+        PartialEncodedStateWitness::new_committed_parts(
+            EpochId::default(),
+            &chunk_header,
+            vec![(owner.clone(), vec![1; 10].into())],
+            10,
+            None,
+            ChunkValidatorsDigest::new(&[owner]),
+            Utc::UNIX_EPOCH,
+            Some(PROTOCOL_VERSION),
+            &signer,
+        )
+        .pop()
+        .unwrap()
+    }
+
+    fn validator(account_id: &str) -> ValidatorStake {
+        let signer = create_test_signer(account_id);
+        ValidatorStake::new(signer.validator_id().clone(), signer.public_key(), 1)
+    }
+
+    #[test]
+    fn part_signed_by_another_validator_is_attributed_to_it() {
+        let partial_witness = v4_partial_witness_signed_by("test1");
+        assert_eq!(partial_witness.claimed_signer().map(AccountId::as_str), Some("test1"));
+        let err = invalid_partial_witness_signature_error(
+            &partial_witness,
+            "test0".parse().unwrap(),
+            Some(validator("test1")).into_iter(),
+        );
+        match err {
+            Error::WrongProducer { expected, actual } => {
+                assert_eq!(expected.as_str(), "test0");
+                assert_eq!(actual.as_str(), "test1");
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[test]
+    fn part_not_signed_by_its_claimed_signer_is_invalid() {
+        // The claimed signer isn't a validator of the epoch.
+        let partial_witness = v4_partial_witness_signed_by("outsider");
+        let err = invalid_partial_witness_signature_error(
+            &partial_witness,
+            "test0".parse().unwrap(),
+            None.into_iter(),
+        );
+        assert!(matches!(err, Error::InvalidPartialChunkStateWitness(_)), "{err:?}");
+        // The key of the claimed signer doesn't verify the signature.
+        let err = invalid_partial_witness_signature_error(
+            &partial_witness,
+            "test0".parse().unwrap(),
+            Some(validator("test1")).into_iter(),
+        );
+        assert!(matches!(err, Error::InvalidPartialChunkStateWitness(_)), "{err:?}");
+    }
+
+    /// Epoch manager in which the chunk producers of shard 0 at the heights 41, 42 and 43 are
+    /// test2, test3 and test4, and the V1 part 2 is owned by test2.
+    fn epoch_manager_with_five_validators() -> Arc<MockEpochManager> {
+        let accounts = (0..5).map(|i| format!("test{i}").parse().unwrap()).collect();
+        let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![accounts]);
+        MockEpochManager::new_with_validators(create_test_store(), vs, 10)
+    }
+
+    #[test]
+    fn part_without_claimed_signer_is_checked_against_suspected_signers() {
+        let epoch_manager = epoch_manager_with_five_validators();
+        let partial_witness = partial_witness_signed_by("test4");
+        assert_eq!(partial_witness.claimed_signer(), None);
+        let suspected_signers =
+            suspected_signers(epoch_manager.as_ref(), &partial_witness).unwrap();
+        assert_eq!(
+            suspected_signers.iter().map(AccountId::as_str).collect::<Vec<_>>(),
+            vec!["test2", "test4"]
+        );
+
+        // The chunk producer of the next height signed the part.
+        let err =
+            verify_partial_witness_signature(epoch_manager.as_ref(), &partial_witness, || {
+                Ok(false)
+            })
+            .unwrap_err();
+        match err {
+            Error::WrongProducer { expected, actual } => {
+                assert_eq!(expected.as_str(), "test3");
+                assert_eq!(actual.as_str(), "test4");
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[test]
+    fn part_without_claimed_signer_signed_by_unsuspected_validator_is_invalid() {
+        let epoch_manager = epoch_manager_with_five_validators();
+        // test1 is a validator of the epoch, but neither owns the part nor produces a chunk of
+        // the shard at an adjacent height.
+        let partial_witness = partial_witness_signed_by("test1");
+        let err =
+            verify_partial_witness_signature(epoch_manager.as_ref(), &partial_witness, || {
+                Ok(false)
+            })
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidPartialChunkStateWitness(_)), "{err:?}");
+    }
+
+    fn key(shard_id: ShardId) -> ChunkProductionKey {
+        ChunkProductionKey { shard_id, epoch_id: EpochId::default(), height_created: 42 }
+    }
+
+    #[test]
+    fn part_protocol_version_is_checked_against_the_epoch() {
+        validate_part_protocol_version(151, 151).unwrap();
+        validate_part_protocol_version(150, 151).unwrap();
+        for part_protocol_version in [149, 152] {
+            let err = validate_part_protocol_version(part_protocol_version, 151).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    Error::InvalidPartialWitnessProtocolVersion {
+                        protocol_version,
+                        epoch_protocol_version: 151,
+                    } if protocol_version == part_protocol_version
+                ),
+                "{err:?}"
             );
-            return Ok(false);
         }
     }
 
-    Ok(true)
+    #[test]
+    fn shard_id_past_the_last_shard_is_invalid() {
+        let shard_layout = ShardLayout::v0(6, 0);
+        validate_shard_id_in_layout(&shard_layout, &key(0)).unwrap();
+        validate_shard_id_in_layout(&shard_layout, &key(5)).unwrap();
+        let err = validate_shard_id_in_layout(&shard_layout, &key(6)).unwrap_err();
+        assert!(matches!(err, Error::InvalidShardId(6)), "{err:?}");
+        let err = validate_shard_id_in_layout(&shard_layout, &key(u64::MAX)).unwrap_err();
+        assert!(matches!(err, Error::InvalidShardId(u64::MAX)), "{err:?}");
+    }
+
+    #[test]
+    fn shard_id_of_another_layout_is_invalid() {
+        // The resharding from v2 to v3 splits shard 2 into the shards 2 and 3, so the last shard
+        // of v3 doesn't exist in the epochs of v2.
+        let previous_layout = ShardLayout::get_simple_nightshade_layout_v2();
+        let layout = ShardLayout::get_simple_nightshade_layout_v3();
+        let last_shard_id = layout.shard_ids().last().unwrap();
+        validate_shard_id_in_layout(&layout, &key(last_shard_id)).unwrap();
+        let err = validate_shard_id_in_layout(&previous_layout, &key(last_shard_id)).unwrap_err();
+        assert!(matches!(err, Error::InvalidShardId(shard_id) if shard_id == last_shard_id));
+    }
 }
@@ -130,6 +130,7 @@ impl Client {
             &shard_chunk,
             transactions_storage_proof,
             &signer,
+            self.clock.now(),
         )
         .unwrap();
         shard_chunk
@@ -3,7 +3,13 @@ use std::sync::{Arc, RwLock};
 
 use near_async::messaging::CanSend;
 
-use crate::stateless_validation::partial_witness::partial_witness_actor::DistributeStateWitnessRequest;
+use crate::stateless_validation::partial_witness::partial_witness_actor::{
+    ChainHeadUpdatedMessage, ChunkStateWitnessConsumedMessage, ChunkStateWitnessOutcomeMessage,
+    DistributeStateWitnessRequest, PrioritizeWitness, ReduceMemoryPressure,
+    SyncStatusChangedMessage, WarmUpPartialWitness,
+};
+#[cfg(feature = "test_features")]
+use crate::stateless_validation::partial_witness::AdvWitnessPartsMessage;
 
 #[derive(Clone, Default)]
 pub struct MockPartialWitnessAdapter {
@@ -16,6 +22,39 @@ impl CanSend<DistributeStateWitnessRequest> for MockPartialWitnessAdapter {
     }
 }
 
+impl CanSend<ChunkStateWitnessConsumedMessage> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: ChunkStateWitnessConsumedMessage) {}
+}
+
+impl CanSend<ChunkStateWitnessOutcomeMessage> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: ChunkStateWitnessOutcomeMessage) {}
+}
+
+impl CanSend<ReduceMemoryPressure> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: ReduceMemoryPressure) {}
+}
+
+impl CanSend<WarmUpPartialWitness> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: WarmUpPartialWitness) {}
+}
+
+impl CanSend<ChainHeadUpdatedMessage> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: ChainHeadUpdatedMessage) {}
+}
+
+impl CanSend<SyncStatusChangedMessage> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: SyncStatusChangedMessage) {}
+}
+
+impl CanSend<PrioritizeWitness> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: PrioritizeWitness) {}
+}
+
+#[cfg(feature = "test_features")]
+impl CanSend<AdvWitnessPartsMessage> for MockPartialWitnessAdapter {
+    fn send(&self, _msg: AdvWitnessPartsMessage) {}
+}
+
 impl MockPartialWitnessAdapter {
     pub fn pop_distribution_request(&self) -> Option<DistributeStateWitnessRequest> {
         self.distribution_request.write().unwrap().pop_front()
@@ -1,15 +1,22 @@
 pub mod block_stats;
 pub mod client;
 mod mock_partial_witness_adapter;
+pub mod partial_witness_driver;
 pub mod peer_manager_mock;
 pub mod setup;
 pub mod test_env;
 pub mod test_env_builder;
 pub mod test_loop;
+#[cfg(any(test, feature = "test_features", feature = "witness_simulation"))]
+pub mod witness_builder;
+pub mod witness_stats;
 
 pub use block_stats::*;
 pub use client::*;
+pub use partial_witness_driver::*;
 pub use peer_manager_mock::*;
 pub use setup::*;
 pub use test_env::*;
 pub use test_env_builder::*;
+#[cfg(any(test, feature = "test_features", feature = "witness_simulation"))]
+pub use witness_builder::*;
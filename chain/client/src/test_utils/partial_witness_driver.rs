@@ -0,0 +1,257 @@
+//! Synchronous driver of a single `PartialWitnessActor` for tests.
+//!
+//! The driver owns the actor, so the tests can deliver its messages one by one in any order
+//! without actix or the test loop. The delayed actions scheduled by the actor are queued and only
+//! run when the test advances the fake clock past their deadline, and everything the actor sends
+//! to the network and to the client is collected for the assertions.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use near_async::futures::DelayedActionRunner;
+use near_async::messaging::{noop, Actor, Handler, HandlerWithContext, IntoSender, Sender};
+use near_async::time::{Clock, Duration, FakeClock, Instant};
+use near_chain::chain::ChunkStateWitnessMessage;
+use near_chain_configs::{MutableConfigValue, PartialWitnessConfig};
+use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
+use near_primitives::test_utils::create_test_signer;
+use near_primitives::types::AccountId;
+use near_primitives::views::WitnessConfigView;
+use near_store::test_utils::create_test_store;
+use near_store::Store;
+
+use crate::client_actor::ClientSenderForPartialWitness;
+use crate::stateless_validation::partial_witness::partial_witness_actor::{
+    PartialWitnessActor, WitnessConfigMessage,
+};
+
+type DelayedActionFn = Box<
+    dyn FnOnce(&mut PartialWitnessActor, &mut dyn DelayedActionRunner<PartialWitnessActor>)
+        + Send
+        + 'static,
+>;
+
+struct DelayedAction {
+    deadline: Instant,
+    name: String,
+    f: DelayedActionFn,
+}
+
+/// Queue of the delayed actions scheduled by the actor, run by `PartialWitnessTestDriver::advance`.
+struct DelayedActions {
+    clock: Clock,
+    /// Ordered by scheduling time, the actions with the same deadline run in this order.
+    pending: Vec<DelayedAction>,
+}
+
+impl DelayedActionRunner<PartialWitnessActor> for DelayedActions {
+    fn run_later_boxed(&mut self, name: &str, dur: Duration, f: DelayedActionFn) {
+        self.pending.push(DelayedAction {
+            deadline: self.clock.now() + dur,
+            name: name.to_string(),
+            f,
+        });
+    }
+}
+
+impl DelayedActions {
+    /// Removes the earliest action due at or before `until`.
+    fn pop_due(&mut self, until: Instant) -> Option<DelayedAction> {
+        let (index, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| action.deadline <= until)
+            .min_by_key(|(index, action)| (action.deadline, *index))?;
+        Some(self.pending.remove(index))
+    }
+}
+
+pub struct PartialWitnessTestDriver {
+    account_id: AccountId,
+    clock: FakeClock,
+    actor: PartialWitnessActor,
+    delayed_actions: DelayedActions,
+    store: Store,
+    network_requests: Arc<Mutex<VecDeque<NetworkRequests>>>,
+    client_witnesses: Arc<Mutex<VecDeque<ChunkStateWitnessMessage>>>,
+    num_warmed_up: Arc<Mutex<usize>>,
+    witness_configs: Arc<Mutex<Vec<WitnessConfigView>>>,
+}
+
+impl PartialWitnessTestDriver {
+    /// Creates the actor of the validator `account_id` with an empty store, and starts it.
+    /// The drivers of several validators may share the clock and the epoch manager.
+    pub fn new(
+        clock: FakeClock,
+        account_id: AccountId,
+        epoch_manager: Arc<dyn EpochManagerAdapter>,
+        config: PartialWitnessConfig,
+    ) -> Self {
+        Self::new_with_store(clock, account_id, epoch_manager, config, create_test_store())
+    }
+
+    /// Same as `new`, but over the given store, e.g. the store of the driver of an actor which is
+    /// restarted.
+    pub fn new_with_store(
+        clock: FakeClock,
+        account_id: AccountId,
+        epoch_manager: Arc<dyn EpochManagerAdapter>,
+        config: PartialWitnessConfig,
+        store: Store,
+    ) -> Self {
+        let network_requests = Arc::new(Mutex::new(VecDeque::new()));
+        let network_adapter = PeerManagerAdapter {
+            async_request_sender: noop().into_sender(),
+            request_sender: Sender::from_fn({
+                let network_requests = network_requests.clone();
+                move |request: PeerManagerMessageRequest| {
+                    let PeerManagerMessageRequest::NetworkRequests(request) = request else {
+                        panic!("Unexpected request from PartialWitnessActor: {request:?}");
+                    };
+                    network_requests.lock().unwrap().push_back(request);
+                }
+            }),
+            set_chain_info_sender: noop().into_sender(),
+        };
+        let client_witnesses = Arc::new(Mutex::new(VecDeque::new()));
+        let num_warmed_up = Arc::new(Mutex::new(0));
+        let witness_configs = Arc::new(Mutex::new(vec![]));
+        let client_sender = ClientSenderForPartialWitness {
+            chunk_state_witness: Sender::from_fn({
+                let client_witnesses = client_witnesses.clone();
+                move |msg: ChunkStateWitnessMessage| {
+                    client_witnesses.lock().unwrap().push_back(msg);
+                }
+            }),
+            partial_witness_warmed_up: Sender::from_fn({
+                let num_warmed_up = num_warmed_up.clone();
+                move |_| *num_warmed_up.lock().unwrap() += 1
+            }),
+            stateless_validation_health: noop().into_sender(),
+            witness_config: Sender::from_fn({
+                let witness_configs = witness_configs.clone();
+                move |msg: WitnessConfigMessage| witness_configs.lock().unwrap().push(msg.0)
+            }),
+            partial_witness_error: noop().into_sender(),
+            partial_witness_debug: noop().into_sender(),
+        };
+
+        let signer = MutableConfigValue::new(
+            Some(Arc::new(create_test_signer(account_id.as_str()))),
+            "validator_signer",
+        );
+        let shard_tracker = ShardTracker::new(TrackedConfig::AllShards, epoch_manager.clone());
+        let mut actor = PartialWitnessActor::new(
+            clock.clock(),
+            network_adapter,
+            client_sender,
+            signer,
+            epoch_manager,
+            shard_tracker,
+            store.clone(),
+            config,
+        );
+        let mut delayed_actions = DelayedActions { clock: clock.clock(), pending: vec![] };
+        actor.start_actor(&mut delayed_actions);
+        Self {
+            account_id,
+            clock,
+            actor,
+            delayed_actions,
+            store,
+            network_requests,
+            client_witnesses,
+            num_warmed_up,
+            witness_configs,
+        }
+    }
+
+    pub fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    pub fn clock(&self) -> &FakeClock {
+        &self.clock
+    }
+
+    /// The store of the actor, e.g. to set the chain head used by the validation.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    pub fn actor(&self) -> &PartialWitnessActor {
+        &self.actor
+    }
+
+    pub fn actor_mut(&mut self) -> &mut PartialWitnessActor {
+        &mut self.actor
+    }
+
+    /// Handles the message as the actor would when receiving it from its mailbox. The delayed
+    /// actions scheduled by the handler run once the clock is advanced, see `advance`.
+    pub fn send<M>(&mut self, msg: M) -> M::Result
+    where
+        M: actix::Message,
+        PartialWitnessActor: Handler<M>,
+    {
+        HandlerWithContext::handle(&mut self.actor, msg, &mut self.delayed_actions)
+    }
+
+    /// Advances the clock by `duration`, running the delayed actions due in the meantime in the
+    /// order of their deadlines, with the clock set to the deadline of each action.
+    pub fn advance(&mut self, duration: Duration) {
+        let until = self.clock.now() + duration;
+        while let Some(action) = self.delayed_actions.pop_due(until) {
+            if action.deadline > self.clock.now() {
+                self.clock.advance_until(action.deadline);
+            }
+            tracing::debug!(target: "test", action = %action.name, "Running delayed action");
+            (action.f)(&mut self.actor, &mut self.delayed_actions);
+        }
+        // An action may have advanced the clock past `until` itself, e.g. a slowed down decode.
+        if until > self.clock.now() {
+            self.clock.advance_until(until);
+        }
+    }
+
+    /// Names of the delayed actions that didn't run yet, in the order of scheduling.
+    pub fn pending_delayed_actions(&self) -> Vec<&str> {
+        self.delayed_actions.pending.iter().map(|action| action.name.as_str()).collect()
+    }
+
+    /// Deadline of the earliest delayed action that didn't run yet, if any.
+    pub fn next_delayed_action_at(&self) -> Option<Instant> {
+        self.delayed_actions.pending.iter().map(|action| action.deadline).min()
+    }
+
+    /// Takes the requests sent to the network so far, in the order of sending.
+    pub fn take_network_requests(&self) -> Vec<NetworkRequests> {
+        self.network_requests.lock().unwrap().drain(..).collect()
+    }
+
+    /// Counts the requests sent to the network and not taken yet. Unlike the driver it can be
+    /// called while the actor handles a message, e.g. from a `TracingCapture` callback, to tell
+    /// what the actor sent before some point of the handling.
+    pub fn network_requests_counter(&self) -> impl Fn() -> usize + Send + Sync + 'static {
+        let network_requests = self.network_requests.clone();
+        move || network_requests.lock().unwrap().len()
+    }
+
+    /// Takes the witnesses sent to the client so far, in the order of sending.
+    pub fn take_client_witnesses(&self) -> Vec<ChunkStateWitnessMessage> {
+        self.client_witnesses.lock().unwrap().drain(..).collect()
+    }
+
+    /// Number of `PartialWitnessWarmedUp` notifications sent to the client.
+    pub fn num_warmed_up(&self) -> usize {
+        *self.num_warmed_up.lock().unwrap()
+    }
+
+    /// Takes the effective configs sent to the client so far, in the order of sending.
+    pub fn take_witness_configs(&self) -> Vec<WitnessConfigView> {
+        std::mem::take(&mut *self.witness_configs.lock().unwrap())
+    }
+}
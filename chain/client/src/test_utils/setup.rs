@@ -41,8 +41,10 @@ use near_network::client::{
 };
 use near_network::shards_manager::ShardsManagerRequestFromNetwork;
 use near_network::state_witness::{
-    PartialEncodedStateWitnessForwardMessage, PartialEncodedStateWitnessMessage,
-    PartialWitnessSenderForNetwork,
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, PartialWitnessSenderForNetwork,
+    WitnessReceiverStatusMessage,
 };
 use near_network::types::{BlockInfo, PeerChainInfo};
 use near_network::types::{
@@ -162,7 +164,9 @@ pub fn setup(
         client_adapter_for_partial_witness_actor.as_multi_sender(),
         signer.clone(),
         epoch_manager.clone(),
+        shard_tracker.clone(),
         store.clone(),
+        config.partial_witness.clone(),
     ));
     let partial_witness_adapter = partial_witness_addr.with_auto_span_context();
 
@@ -735,7 +739,7 @@ fn process_peer_manager_message_default(
                 }
             }
         }
-        NetworkRequests::PartialEncodedStateWitness(partial_witnesses) => {
+        NetworkRequests::PartialEncodedStateWitness(partial_witnesses, _) => {
             for (account, partial_witness) in partial_witnesses {
                 for (i, name) in validators.iter().enumerate() {
                     if name == account {
@@ -746,23 +750,77 @@ fn process_peer_manager_message_default(
                 }
             }
         }
-        NetworkRequests::PartialEncodedStateWitnessForward(accounts, partial_witness) => {
+        NetworkRequests::PartialEncodedStateWitnessForward(accounts, partial_witness, _) => {
             for account in accounts {
                 for (i, name) in validators.iter().enumerate() {
                     if name == account {
                         connectors[i].partial_witness_sender.send(
-                            PartialEncodedStateWitnessForwardMessage(partial_witness.clone()),
+                            PartialEncodedStateWitnessForwardMessage(
+                                partial_witness.clone(),
+                                my_key_pair.id.clone(),
+                            ),
                         );
                     }
                 }
             }
         }
+        NetworkRequests::PartialEncodedStateWitnessFragments(accounts, fragments, _) => {
+            for account in accounts {
+                for (i, name) in validators.iter().enumerate() {
+                    if name == account {
+                        for fragment in fragments {
+                            connectors[i].partial_witness_sender.send(
+                                PartialEncodedStateWitnessFragmentMessage(
+                                    fragment.clone(),
+                                    my_key_pair.id.clone(),
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        NetworkRequests::PartialEncodedStateWitnessRequest(account, request) => {
+            for (i, name) in validators.iter().enumerate() {
+                if name == account {
+                    connectors[i].partial_witness_sender.send(
+                        PartialEncodedStateWitnessRequestMessage(
+                            request.clone(),
+                            Some(validators[my_ord].clone()),
+                        ),
+                    );
+                }
+            }
+        }
+        NetworkRequests::FullEncodedStateWitness(accounts, full_witness, _) => {
+            for account in accounts {
+                for (i, name) in validators.iter().enumerate() {
+                    if name == account {
+                        connectors[i]
+                            .partial_witness_sender
+                            .send(FullEncodedStateWitnessMessage(full_witness.clone()));
+                    }
+                }
+            }
+        }
+        NetworkRequests::WitnessReceiverStatus(accounts, status) => {
+            for account in accounts {
+                for (i, name) in validators.iter().enumerate() {
+                    if name == account {
+                        connectors[i]
+                            .partial_witness_sender
+                            .send(WitnessReceiverStatusMessage(status.clone()));
+                    }
+                }
+            }
+        }
         NetworkRequests::ForwardTx(_, _)
         | NetworkRequests::BanPeer { .. }
         | NetworkRequests::TxStatus(_, _, _)
         | NetworkRequests::SnapshotHostInfo { .. }
         | NetworkRequests::Challenge(_)
         | NetworkRequests::ChunkStateWitnessAck(_, _)
+        | NetworkRequests::BatchedChunkStateWitnessAck(_, _)
         | NetworkRequests::EpochSyncRequest { .. }
         | NetworkRequests::EpochSyncResponse { .. } => {}
     }
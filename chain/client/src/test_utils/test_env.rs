@@ -359,8 +359,25 @@ impl TestEnv {
         let partial_witness_adapters = self.partial_witness_adapters.clone();
         for (client_idx, partial_witness_adapter) in partial_witness_adapters.iter().enumerate() {
             while let Some(request) = partial_witness_adapter.pop_distribution_request() {
-                let DistributeStateWitnessRequest { epoch_id, chunk_header, state_witness } =
+                let DistributeStateWitnessRequest { epoch_id, chunk_header, state_witness, .. } =
                     request;
+                let state_witness = Arc::unwrap_or_clone(state_witness);
+
+                // The partial witness actor rejects the requests for chunks produced by someone
+                // else with `Error::NotThisChunksProducer`, which must never happen legitimately.
+                let chunk_producer = self.clients[client_idx]
+                    .epoch_manager
+                    .get_chunk_producer(
+                        &epoch_id,
+                        chunk_header.height_created(),
+                        chunk_header.shard_id(),
+                    )
+                    .unwrap();
+                assert_eq!(
+                    &chunk_producer,
+                    self.clients[client_idx].validator_signer.get().unwrap().validator_id(),
+                    "DistributeStateWitnessRequest sent for a chunk produced by someone else"
+                );
 
                 let raw_witness_size = borsh::to_vec(&state_witness).unwrap().len();
                 let chunk_validators = self.clients[client_idx]
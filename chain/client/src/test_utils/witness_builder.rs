@@ -0,0 +1,227 @@
+//! Fixtures of the state witnesses and their parts for the tests of the witness distribution.
+//!
+//! `TestWitnessBuilder` builds a `ChunkStateWitness` with default values for everything the test
+//! doesn't care about, and encodes it into correctly signed `PartialEncodedStateWitness` parts the
+//! way the chunk producer does. The parts can then be corrupted or dropped to simulate the faults
+//! of the network or of the chunk producer.
+
+use near_primitives::challenge::PartialState;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, EncodedChunkStateWitness,
+};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::validator_signer::ValidatorSigner;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use crate::stateless_validation::partial_witness::{ReedSolomonBackend, WitnessEncoderCache};
+
+/// Data added to the witness to make it larger, see `TestWitnessBuilder::payload`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPayload {
+    /// Repeated bytes, which the compression of the witness shrinks to almost nothing.
+    Compressible,
+    /// Random bytes, which keep the encoded witness about as large as the payload.
+    Incompressible,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestWitnessBuilder {
+    epoch_id: EpochId,
+    shard_id: ShardId,
+    height_created: BlockHeight,
+    prev_block_hash: CryptoHash,
+    payload: Option<(usize, TestPayload)>,
+}
+
+impl TestWitnessBuilder {
+    /// Witness of the chunk of shard 0 at height 42 of the default epoch, without payload.
+    pub fn new() -> Self {
+        Self {
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created: 42,
+            prev_block_hash: CryptoHash::default(),
+            payload: None,
+        }
+    }
+
+    pub fn epoch_id(mut self, epoch_id: EpochId) -> Self {
+        self.epoch_id = epoch_id;
+        self
+    }
+
+    pub fn shard_id(mut self, shard_id: ShardId) -> Self {
+        self.shard_id = shard_id;
+        self
+    }
+
+    pub fn height(mut self, height_created: BlockHeight) -> Self {
+        self.height_created = height_created;
+        self
+    }
+
+    /// The chunks built on different prev blocks have different witnesses, as if the chunk
+    /// producer equivocated.
+    pub fn prev_block_hash(mut self, prev_block_hash: CryptoHash) -> Self {
+        self.prev_block_hash = prev_block_hash;
+        self
+    }
+
+    /// Adds `size` bytes of `payload` to the base state of the witness.
+    pub fn payload(mut self, size: usize, payload: TestPayload) -> Self {
+        self.payload = Some((size, payload));
+        self
+    }
+
+    pub fn build(&self) -> ChunkStateWitness {
+        let mut witness =
+            ChunkStateWitness::new_dummy(self.height_created, self.shard_id, self.prev_block_hash);
+        witness.epoch_id = self.epoch_id;
+        if let Some((size, payload)) = self.payload {
+            let mut bytes = vec![0x5a; size];
+            if payload == TestPayload::Incompressible {
+                StdRng::seed_from_u64(self.height_created).fill_bytes(&mut bytes);
+            }
+            witness.main_state_transition.base_state = PartialState::TrieValues(vec![bytes.into()]);
+        }
+        witness
+    }
+
+    /// Encodes the witness into `n_validators` V1 parts signed by `signer`, owned by the accounts
+    /// `test0`, `test1` and so on in the order of the parts.
+    pub fn encode_and_split(
+        &self,
+        n_validators: usize,
+        signer: &ValidatorSigner,
+    ) -> TestWitnessParts {
+        let owners = (0..n_validators)
+            .map(|part_ord| format!("test{part_ord}").parse().unwrap())
+            .collect::<Vec<_>>();
+        self.encode_and_split_among(&owners, signer)
+    }
+
+    /// Encodes the witness into one V1 part per owner, signed by `signer`. The V1 parts don't carry
+    /// their owner, see `TestWitnessParts::into_owned_parts`.
+    pub fn encode_and_split_among(
+        &self,
+        owners: &[AccountId],
+        signer: &ValidatorSigner,
+    ) -> TestWitnessParts {
+        let witness = self.build();
+        let (encoded_witness, _) = EncodedChunkStateWitness::encode(&witness).unwrap();
+        let encoder =
+            WitnessEncoderCache::new(ReedSolomonBackend::Portable).entry(owners.len()).unwrap();
+        let (parts, encoded_length) = encoder.encode(&encoded_witness);
+        let parts = parts
+            .into_iter()
+            .enumerate()
+            .map(|(part_ord, part)| {
+                Some(sign_part(&witness, part_ord, part.unwrap().to_vec(), encoded_length, signer))
+            })
+            .collect();
+        TestWitnessParts {
+            witness,
+            encoded_length,
+            owners: owners.to_vec(),
+            parts,
+            signer: signer.clone(),
+        }
+    }
+}
+
+/// Parts of the witness built by `TestWitnessBuilder::encode_and_split`.
+pub struct TestWitnessParts {
+    pub witness: ChunkStateWitness,
+    /// Length of the encoded witness, before it was split into the parts.
+    pub encoded_length: usize,
+    /// Owners of the parts ordered by part_ord.
+    owners: Vec<AccountId>,
+    /// The parts ordered by part_ord, None for the dropped ones.
+    parts: Vec<Option<PartialEncodedStateWitness>>,
+    signer: ValidatorSigner,
+}
+
+impl TestWitnessParts {
+    /// Flips all the bits of the part. The part is signed again, so that it passes the validation
+    /// and is only caught when the witness is decoded.
+    pub fn corrupt_part(mut self, part_ord: usize) -> Self {
+        let part = self.parts[part_ord].take().expect("the part is dropped");
+        let bytes = part.part().iter().map(|byte| byte ^ 0xff).collect();
+        self.parts[part_ord] =
+            Some(sign_part(&self.witness, part_ord, bytes, self.encoded_length, &self.signer));
+        self
+    }
+
+    /// Signs the parts again for the chunk of `witness`, as if the chunk producer encoded the
+    /// witness of another chunk into the parts of that chunk. `key` is still the key of the
+    /// encoded witness.
+    pub fn sign_for(mut self, witness: &ChunkStateWitness) -> Self {
+        for part in self.parts.iter_mut().flatten() {
+            let signed = sign_part(
+                witness,
+                part.part_ord(),
+                part.part().to_vec(),
+                self.encoded_length,
+                &self.signer,
+            );
+            *part = signed;
+        }
+        self
+    }
+
+    /// Drops the parts, as if they were lost on the way.
+    pub fn drop_parts(mut self, part_ords: &[usize]) -> Self {
+        for part_ord in part_ords {
+            self.parts[*part_ord] = None;
+        }
+        self
+    }
+
+    pub fn key(&self) -> ChunkProductionKey {
+        self.witness.chunk_production_key()
+    }
+
+    /// The part, which must not be dropped.
+    pub fn part(&self, part_ord: usize) -> &PartialEncodedStateWitness {
+        self.parts[part_ord].as_ref().expect("the part is dropped")
+    }
+
+    /// The parts which are not dropped, ordered by part_ord.
+    pub fn parts(&self) -> impl Iterator<Item = &PartialEncodedStateWitness> {
+        self.parts.iter().flatten()
+    }
+
+    pub fn into_parts(self) -> Vec<PartialEncodedStateWitness> {
+        self.parts.into_iter().flatten().collect()
+    }
+
+    /// The parts which are not dropped together with their owners, ordered by part_ord.
+    pub fn into_owned_parts(self) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+        self.owners
+            .into_iter()
+            .zip(self.parts)
+            .filter_map(|(owner, part)| part.map(|part| (owner, part)))
+            .collect()
+    }
+}
+
+fn sign_part(
+    witness: &ChunkStateWitness,
+    part_ord: usize,
+    part: Vec<u8>,
+    encoded_length: usize,
+    signer: &ValidatorSigner,
+) -> PartialEncodedStateWitness {
+    PartialEncodedStateWitness::new(
+        witness.epoch_id,
+        witness.chunk_header.clone(),
+        part_ord,
+        part,
+        encoded_length,
+        signer,
+    )
+}
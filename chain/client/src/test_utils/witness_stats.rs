@@ -0,0 +1,20 @@
+use std::path::Path;
+
+use crate::stateless_validation::partial_witness::stats_export::export_files;
+use crate::WitnessStatsRecord;
+
+/// Reads the witness stats exported to `dir`, see `PartialWitnessConfig::export_witness_stats_dir`,
+/// the oldest first. A record being written while reading is skipped.
+pub fn read_witness_stats(dir: &Path) -> std::io::Result<Vec<WitnessStatsRecord>> {
+    let mut records = vec![];
+    for (_, path) in export_files(dir)? {
+        let content = std::fs::read_to_string(path)?;
+        for line in content.split_inclusive('\n') {
+            let Some(line) = line.strip_suffix('\n') else {
+                continue;
+            };
+            records.push(serde_json::from_str(line)?);
+        }
+    }
+    Ok(records)
+}
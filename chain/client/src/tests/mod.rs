@@ -5,5 +5,6 @@ mod consensus;
 mod cross_shard_tx;
 mod doomslug;
 mod maintenance_windows;
+mod partial_witness;
 mod process_blocks;
 mod query_client;
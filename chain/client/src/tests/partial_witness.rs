@@ -0,0 +1,2991 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+use bytesize::ByteSize;
+use near_async::time::{Duration, FakeClock, Instant, Utc};
+use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+use near_chain_configs::{
+    HandlerPanicPolicy, PartialWitnessConfig, StatelessValidationHealthConfig,
+};
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::state_witness::{
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, WitnessRoutingHints,
+};
+use near_network::types::NetworkRequests;
+use near_o11y::testonly::{init_test_logger, TracingCapture};
+use near_primitives::block::{Block, Tip};
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+    PartialEncodedStateWitnessRequest,
+};
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, EncodedChunkStateWitness, VersionedChunkStateWitnessAck,
+};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::test_utils::{create_test_signer, TestBlockBuilder};
+use near_primitives::types::{AccountId, BlockHeight, EpochId, NumShards, ShardId};
+use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+use near_primitives::views::ShardValidationLagView;
+use near_store::test_utils::create_test_store;
+use near_store::{DBCol, Store, HEAD_KEY};
+
+use crate::metrics;
+use crate::stateless_validation::partial_witness::partial_witness_actor::{
+    ChainHeadUpdatedMessage, ChunkStateWitnessConsumedMessage, PrioritizeWitness,
+    RequestNextDecodedWitness, SyncStatusChangedMessage,
+};
+use crate::stateless_validation::partial_witness::{
+    install_handler_panic_hook, load_epoch_witness_stats, witness_parts_geometry,
+    DeliveredWitnesses, HealthCheck, HeightWindowContext, PartDelivery, PartialWitnessState,
+    VersionedEpochWitnessStats, WitnessDecodePath, EPOCH_INFO_CHECK_PERIOD, PART_FRAGMENTS_TTL,
+};
+use crate::stateless_validation::validate::{
+    validate_partial_encoded_state_witness, ChainHeads, ValidationContext,
+};
+use crate::test_utils::{PartialWitnessTestDriver, TestPayload, TestWitnessBuilder};
+use crate::{AnnounceWitnessReceiverUnavailable, DistributeStateWitnessRequest};
+
+/// The chunk validators of every chunk, which own the parts in this order.
+const VALIDATORS: [&str; 4] = ["test0", "test1", "test2", "test3"];
+
+const HEIGHT: BlockHeight = 5;
+
+struct Setup {
+    clock: FakeClock,
+    store: Store,
+    epoch_manager: Arc<MockEpochManager>,
+}
+
+impl Setup {
+    fn new() -> Self {
+        Self::with_epoch_length(100)
+    }
+
+    fn with_epoch_length(epoch_length: u64) -> Self {
+        Self::with_shards(epoch_length, 1)
+    }
+
+    /// All the validators are chunk validators of every shard.
+    fn with_shards(epoch_length: u64, num_shards: NumShards) -> Self {
+        Self::with_validators(epoch_length, num_shards, &VALIDATORS)
+    }
+
+    fn with_validators(epoch_length: u64, num_shards: NumShards, validators: &[&str]) -> Self {
+        init_test_logger();
+        let validators = validators.iter().map(|account_id| account_id.parse().unwrap()).collect();
+        let vs = ValidatorSchedule::new()
+            .num_shards(num_shards)
+            .block_producers_per_epoch(vec![validators]);
+        let store = create_test_store();
+        let epoch_manager = MockEpochManager::new_with_validators(store.clone(), vs, epoch_length);
+        Self { clock: FakeClock::new(Utc::UNIX_EPOCH), store, epoch_manager }
+    }
+
+    fn driver(
+        &self,
+        account_id: &AccountId,
+        config: PartialWitnessConfig,
+    ) -> PartialWitnessTestDriver {
+        PartialWitnessTestDriver::new(
+            self.clock.clone(),
+            account_id.clone(),
+            self.epoch_manager.clone(),
+            config,
+        )
+    }
+
+    fn chunk_producer(&self) -> AccountId {
+        self.chunk_producer_at(HEIGHT)
+    }
+
+    fn chunk_producer_at(&self, height: BlockHeight) -> AccountId {
+        self.epoch_manager.get_chunk_producer(&EpochId::default(), height, 0).unwrap()
+    }
+
+    /// A chunk validator which is not the chunk producer.
+    fn validator(&self, index: usize) -> AccountId {
+        let chunk_producer = self.chunk_producer();
+        VALIDATORS
+            .iter()
+            .map(|account_id| account_id.parse::<AccountId>().unwrap())
+            .filter(|account_id| account_id != &chunk_producer)
+            .nth(index)
+            .unwrap()
+    }
+
+    fn distribute_witness(&self, producer: &mut PartialWitnessTestDriver) {
+        self.distribute_witness_on(producer, EpochId::default(), HEIGHT, CryptoHash::default());
+    }
+
+    fn distribute_witness_on(
+        &self,
+        producer: &mut PartialWitnessTestDriver,
+        epoch_id: EpochId,
+        height: BlockHeight,
+        prev_block_hash: CryptoHash,
+    ) {
+        let witness = ChunkStateWitness::new_dummy(height, 0, prev_block_hash);
+        producer.send(DistributeStateWitnessRequest::new(
+            epoch_id,
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            self.clock.now(),
+        ));
+    }
+
+    /// Saves the genesis block and the block on top of it to the store of the epoch manager.
+    fn save_blocks(&self) -> (Block, Block) {
+        let genesis = Block::genesis(
+            PROTOCOL_VERSION,
+            vec![],
+            self.clock.now_utc(),
+            0,
+            1000,
+            1000,
+            CryptoHash::default(),
+        );
+        let signer = Arc::new(create_test_signer(VALIDATORS[0]));
+        let block = TestBlockBuilder::new(self.clock.clock(), &genesis, signer).build();
+        let mut store_update = self.store.store_update();
+        for block in [&genesis, &block] {
+            store_update
+                .set_ser(DBCol::BlockHeader, block.hash().as_ref(), block.header())
+                .unwrap();
+        }
+        store_update.commit().unwrap();
+        (genesis, block)
+    }
+
+    /// Distributes the witness from the chunk producer and returns all of its parts ordered by
+    /// part_ord, both the ones sent to their owners and the producer's own forwarded part.
+    fn produce_parts(&self) -> Vec<PartialEncodedStateWitness> {
+        self.produce_parts_at(HEIGHT)
+    }
+
+    fn produce_parts_at(&self, height: BlockHeight) -> Vec<PartialEncodedStateWitness> {
+        self.produce_parts_on(height, CryptoHash::default())
+    }
+
+    /// Parts of the witness of the chunk built on top of `prev_block_hash`, a different witness
+    /// for every prev block, as if the chunk producer equivocated.
+    fn produce_parts_on(
+        &self,
+        height: BlockHeight,
+        prev_block_hash: CryptoHash,
+    ) -> Vec<PartialEncodedStateWitness> {
+        self.produce_owned_parts_on(height, prev_block_hash)
+            .into_iter()
+            .map(|(_, partial_witness)| partial_witness)
+            .collect()
+    }
+
+    /// Same as `produce_parts_on`, along with the owners the chunk producer sent the parts to.
+    fn produce_owned_parts_on(
+        &self,
+        height: BlockHeight,
+        prev_block_hash: CryptoHash,
+    ) -> Vec<(AccountId, PartialEncodedStateWitness)> {
+        let chunk_producer = self.chunk_producer_at(height);
+        let mut producer = self.driver(&chunk_producer, PartialWitnessConfig::default());
+        self.distribute_witness_on(&mut producer, EpochId::default(), height, prev_block_hash);
+        let mut parts = vec![];
+        for request in producer.take_network_requests() {
+            match request {
+                NetworkRequests::PartialEncodedStateWitness(owned_parts, _) => {
+                    parts.extend(owned_parts)
+                }
+                NetworkRequests::PartialEncodedStateWitnessForward(_, partial_witness, _) => {
+                    parts.push((chunk_producer.clone(), partial_witness))
+                }
+                _ => {}
+            }
+        }
+        parts.sort_by_key(|(_, partial_witness)| partial_witness.part_ord());
+        assert_eq!(parts.len(), VALIDATORS.len());
+        parts
+    }
+
+    /// Enables `ProtocolFeature::DirectFullWitness`, before the parts are produced so that they
+    /// are of the same protocol version as the full witness.
+    fn enable_full_witness(&self) {
+        self.epoch_manager
+            .set_protocol_version(ProtocolFeature::DirectFullWitness.protocol_version());
+    }
+
+    /// Distributes the witness from the chunk producer sending the full witness to one chunk
+    /// validator, returns the chunk validator and the full witness. The full witness needs
+    /// `enable_full_witness`.
+    fn produce_full_witness(&self) -> (AccountId, FullEncodedStateWitness) {
+        let config = PartialWitnessConfig {
+            direct_full_witness_targets: 1,
+            ..PartialWitnessConfig::default()
+        };
+        let mut producer = self.driver(&self.chunk_producer(), config);
+        self.distribute_witness(&mut producer);
+        let (mut targets, full_witness) = producer
+            .take_network_requests()
+            .into_iter()
+            .find_map(|request| match request {
+                NetworkRequests::FullEncodedStateWitness(targets, full_witness, _) => {
+                    Some((targets, full_witness))
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(targets.len(), 1);
+        (targets.pop().unwrap(), full_witness)
+    }
+}
+
+fn part_of<'a>(
+    parts: &'a [PartialEncodedStateWitness],
+    owner: &AccountId,
+) -> &'a PartialEncodedStateWitness {
+    parts.iter().find(|partial_witness| &owner_of(partial_witness) == owner).unwrap()
+}
+
+/// Chunk validator owning the part. The parts of the first format don't carry their owner, they
+/// are owned in the order of `VALIDATORS`.
+fn owner_of(partial_witness: &PartialEncodedStateWitness) -> AccountId {
+    partial_witness
+        .owner()
+        .cloned()
+        .unwrap_or_else(|| VALIDATORS[partial_witness.part_ord()].parse().unwrap())
+}
+
+fn peer_id_of(account_id: &AccountId) -> PeerId {
+    PeerId::new(create_test_signer(account_id.as_str()).public_key())
+}
+
+/// The forward of the part as delivered by its owner.
+fn forward_from_owner(
+    partial_witness: PartialEncodedStateWitness,
+) -> PartialEncodedStateWitnessForwardMessage {
+    let peer_id = peer_id_of(&owner_of(&partial_witness));
+    PartialEncodedStateWitnessForwardMessage(partial_witness, peer_id)
+}
+
+/// Targets and part_ord of the forwards among the requests.
+fn forwards(requests: &[NetworkRequests]) -> Vec<(Vec<AccountId>, usize)> {
+    requests
+        .iter()
+        .filter_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, _) => {
+                Some((targets.clone(), partial_witness.part_ord()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn sorted(mut account_ids: Vec<AccountId>) -> Vec<AccountId> {
+    account_ids.sort();
+    account_ids
+}
+
+#[test]
+fn chunk_producer_forwards_own_part() {
+    let setup = Setup::new();
+    let chunk_producer = setup.chunk_producer();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    setup.distribute_witness(&mut producer);
+
+    let requests = producer.take_network_requests();
+    // We can't send the part to ourselves, so our part is forwarded right away.
+    let own_part_ord = VALIDATORS.iter().position(|v| *v == chunk_producer.as_str()).unwrap();
+    let expected_targets = sorted(vec![setup.validator(0), setup.validator(1), setup.validator(2)]);
+    let forwards = forwards(&requests);
+    assert_eq!(forwards.len(), 1);
+    assert_eq!(sorted(forwards[0].0.clone()), expected_targets);
+    assert_eq!(forwards[0].1, own_part_ord);
+
+    let owners = requests
+        .iter()
+        .find_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitness(parts, _) => {
+                Some(parts.iter().map(|(owner, _)| owner.clone()).collect::<Vec<_>>())
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(sorted(owners), expected_targets);
+}
+
+#[test]
+fn produced_witness_section_sizes_are_in_debug_view() {
+    let setup = Setup::new();
+    let chunk_producer = setup.chunk_producer();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    assert!(producer.actor().debug_view().produced_witness_section_sizes.is_empty());
+    setup.distribute_witness(&mut producer);
+
+    let debug_view = producer.actor().debug_view();
+    assert_eq!(debug_view.produced_witness_section_sizes.len(), 1);
+    let produced = &debug_view.produced_witness_section_sizes[0];
+    assert_eq!((produced.shard_id, produced.height_created), (0, HEIGHT));
+    let witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default());
+    assert_eq!(produced.section_sizes.total(), borsh::to_vec(&witness).unwrap().len());
+}
+
+#[test]
+fn chunk_producer_keeps_one_copy_of_own_part() {
+    let setup = Setup::new();
+    let chunk_producer = setup.chunk_producer();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    let witness = TestWitnessBuilder::new()
+        .height(HEIGHT)
+        .payload(300_000, TestPayload::Incompressible)
+        .build();
+    producer.send(DistributeStateWitnessRequest::new(
+        EpochId::default(),
+        witness.chunk_header.clone(),
+        Arc::new(witness),
+        setup.clock.now(),
+    ));
+
+    let requests = producer.take_network_requests();
+    let forwarded = requests
+        .iter()
+        .find_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitnessForward(_, partial_witness, _) => {
+                Some(partial_witness)
+            }
+            _ => None,
+        })
+        .unwrap();
+    let sent_size: usize = requests
+        .iter()
+        .filter_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitness(parts, _) => {
+                Some(parts.iter().map(|(_, partial_witness)| partial_witness.part_size()).sum())
+            }
+            _ => None,
+        })
+        .sum();
+    let total_size = sent_size + forwarded.part_size();
+    assert!(forwarded.part_size() > 50_000);
+
+    // Our own part is kept both in the produced parts and in the owned parts, and is in flight
+    // in the forward, all of which share the same buffer.
+    assert_eq!(producer.actor().retained_part_bytes(), total_size);
+    assert_eq!(Arc::strong_count(forwarded.shared_part()), 3);
+}
+
+#[test]
+fn distribution_is_traced_under_one_span() {
+    let setup = Setup::new();
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    let capture = TracingCapture::enable();
+    setup.distribute_witness(&mut producer);
+    let spans = capture.spans();
+    let span = |name: &str| spans.iter().find(|span| span.name == name).unwrap();
+
+    let root = span("distribute_state_witness");
+    for field in ["chunk_hash", "shard_id", "height_created"] {
+        assert!(root.fields.contains_key(field), "{field} missing in {root:?}");
+    }
+    assert_eq!(root.fields["height_created"], HEIGHT.to_string());
+    let children = [
+        ("compress_witness", &["raw_size", "compressed_size"][..]),
+        ("encode_witness_parts", &["witness_size", "num_parts"][..]),
+        ("construct_witness_parts", &["num_parts", "encoded_length", "part_size"][..]),
+        ("send_witness_parts", &["num_parts", "parts_size"][..]),
+    ];
+    for (name, fields) in children {
+        let child = span(name);
+        assert_eq!(child.parent.as_deref(), Some("distribute_state_witness"), "{child:?}");
+        for field in fields {
+            assert!(child.fields.contains_key(*field), "{field} missing in {child:?}");
+        }
+    }
+    assert_eq!(span("send_witness_parts").fields["num_parts"], VALIDATORS.len().to_string());
+}
+
+#[test]
+fn forward_fan_out_is_capped() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let config = PartialWitnessConfig { max_forward_targets: Some(1), ..Default::default() };
+    for i in 0..3 {
+        let validator_id = setup.validator(i);
+        let mut validator = setup.driver(&validator_id, config.clone());
+        let own_part = part_of(&parts, &validator_id);
+        validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+
+        let forwards = forwards(&validator.take_network_requests());
+        assert_eq!(forwards.len(), 1);
+        let (targets, part_ord) = &forwards[0];
+        assert_eq!(*part_ord, own_part.part_ord());
+        assert_eq!(targets.len(), 1);
+        assert_ne!(targets[0], validator_id);
+        assert_ne!(targets[0], setup.chunk_producer());
+    }
+}
+
+#[test]
+fn witness_is_decoded_once_from_forwarded_parts() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    let forwards = forwards(&validator.take_network_requests());
+    assert_eq!(forwards.len(), 1);
+    assert_eq!(sorted(forwards[0].0.clone()), sorted(vec![setup.validator(1), setup.validator(2)]));
+
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    // The parts arriving after the witness was decoded, as well as the duplicates, are ignored.
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let witnesses = validator.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert_eq!(witnesses[0].witness.chunk_production_key().height_created, HEIGHT);
+    assert!(!witnesses[0].pre_tracking);
+    let acks = validator
+        .take_network_requests()
+        .into_iter()
+        .filter(|request| matches!(request, NetworkRequests::ChunkStateWitnessAck(..)))
+        .count();
+    assert_eq!(acks, 1);
+}
+
+#[test]
+fn duplicate_forward_is_dropped_before_validation() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let other_parts = setup.produce_parts_on(HEIGHT, CryptoHash::hash_bytes(b"other"));
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let dropped_before = metrics::PARTIAL_WITNESS_DUPLICATE_PARTS_DROPPED.get();
+
+    let forwarded_part = parts.iter().find(|part| owner_of(part) != validator_id).unwrap();
+    validator.send(forward_from_owner(forwarded_part.clone()));
+    // The copy is dropped without even checking its signature, which belongs to another witness.
+    let mut copy = forwarded_part.clone();
+    copy.signature = other_parts[forwarded_part.part_ord()].signature.clone();
+    validator.send(forward_from_owner(copy));
+    assert!(metrics::PARTIAL_WITNESS_DUPLICATE_PARTS_DROPPED.get() > dropped_before);
+
+    // A different part with the same part_ord is not a duplicate and is still rejected.
+    let key = forwarded_part.chunk_production_key();
+    validator.send(forward_from_owner(other_parts[forwarded_part.part_ord()].clone()));
+    assert!(validator.actor().conflict_evidence(&key).is_some());
+}
+
+#[test]
+fn acks_to_the_same_chunk_producer_are_batched() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::BatchedWitnessAcks.protocol_version());
+    let chunk_producer = setup.chunk_producer();
+    let other_height = (HEIGHT + 1..HEIGHT + 10)
+        .find(|height| setup.chunk_producer_at(*height) == chunk_producer)
+        .unwrap();
+    let config = PartialWitnessConfig {
+        ack_batching_delay: Duration::milliseconds(10),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    let is_ack = |request: &NetworkRequests| {
+        matches!(
+            request,
+            NetworkRequests::ChunkStateWitnessAck(..)
+                | NetworkRequests::BatchedChunkStateWitnessAck(..)
+        )
+    };
+
+    for partial_witness in setup.produce_parts() {
+        validator.send(forward_from_owner(partial_witness));
+    }
+    validator.advance(Duration::milliseconds(4));
+    for partial_witness in setup.produce_parts_at(other_height) {
+        validator.send(forward_from_owner(partial_witness));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 2);
+    assert!(!validator.take_network_requests().iter().any(is_ack));
+
+    // Both acks are sent together once the first one was held for the delay.
+    validator.advance(Duration::milliseconds(6));
+    let acks = validator.take_network_requests().into_iter().filter(is_ack).collect::<Vec<_>>();
+    assert_eq!(acks.len(), 1);
+    let NetworkRequests::BatchedChunkStateWitnessAck(target, batch) = &acks[0] else {
+        panic!("expected batched acks, got {:?}", acks[0]);
+    };
+    assert_eq!(target, &chunk_producer);
+    let held = batch.acks.iter().map(|held_ack| held_ack.held()).collect::<Vec<_>>();
+    assert_eq!(held, vec![Duration::milliseconds(10), Duration::milliseconds(6)]);
+}
+
+#[test]
+fn acks_are_not_batched_before_the_feature() {
+    let setup = Setup::new();
+    let version = ProtocolFeature::BatchedWitnessAcks.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+    let chunk_producer = setup.chunk_producer();
+    let other_height = (HEIGHT + 1..HEIGHT + 10)
+        .find(|height| setup.chunk_producer_at(*height) == chunk_producer)
+        .unwrap();
+    let config = PartialWitnessConfig {
+        ack_batching_delay: Duration::milliseconds(10),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+
+    // Each ack is sent right after the reconstruction, without waiting for the delay.
+    for height in [HEIGHT, other_height] {
+        for partial_witness in setup.produce_parts_at(height) {
+            validator.send(forward_from_owner(partial_witness));
+        }
+        let requests = validator.take_network_requests();
+        assert!(requests
+            .iter()
+            .all(|request| !matches!(request, NetworkRequests::BatchedChunkStateWitnessAck(..))));
+        assert_eq!(num_acks(&requests), 1);
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 2);
+    validator.advance(Duration::milliseconds(10));
+    assert_eq!(num_acks(&validator.take_network_requests()), 0);
+}
+
+#[test]
+fn decoded_witness_is_accounted_to_chunk_producer() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+
+    // The head is the prev block of the chunk, so its timestamp is the expected production time.
+    let head = Tip {
+        height: HEIGHT - 1,
+        last_block_hash: CryptoHash::hash_bytes(b"head"),
+        prev_block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        next_epoch_id: EpochId::default(),
+    };
+    validator.send(ChainHeadUpdatedMessage {
+        head,
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip: None,
+    });
+    validator.advance(Duration::milliseconds(300));
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    validator.advance(Duration::milliseconds(100));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let witnesses = validator.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert!(!witnesses[0].decoded_late);
+
+    let health = validator.actor().producer_distribution_health();
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].account_id, setup.chunk_producer());
+    assert_eq!(health[0].witnesses_expected, 1);
+    assert_eq!(health[0].witnesses_decoded, 1);
+    assert_eq!(health[0].decode_failures, 0);
+    assert_eq!(health[0].avg_first_part_latency, Some(Duration::milliseconds(300)));
+}
+
+#[test]
+fn validation_lag_follows_the_head() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let non_validator_id: AccountId = "test4".parse().unwrap();
+    let mut non_validator = setup.driver(&non_validator_id, PartialWitnessConfig::default());
+    let head_at = |height| ChainHeadUpdatedMessage {
+        head: Tip {
+            height,
+            last_block_hash: CryptoHash::hash_bytes(&height.to_le_bytes()),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        },
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip: None,
+    };
+
+    // The witness of the chunk is decoded before its block becomes the head.
+    validator.send(head_at(HEIGHT - 1));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    validator.send(head_at(HEIGHT));
+    assert_eq!(validator.actor().validation_lag(), vec![(0, 0)]);
+
+    // No witness arrives for the next chunk, the height skipped by the head has none.
+    validator.send(head_at(HEIGHT + 2));
+    assert_eq!(validator.actor().validation_lag(), vec![(0, 1)]);
+    let debug_view = validator.actor().debug_view();
+    assert_eq!(debug_view.validation_lag, vec![ShardValidationLagView { shard_id: 0, lag: 1 }]);
+
+    // The node isn't a chunk validator of any chunk, so it never lags.
+    non_validator.send(head_at(HEIGHT - 1));
+    non_validator.send(head_at(HEIGHT + 2));
+    assert_eq!(non_validator.actor().validation_lag(), vec![(0, 0)]);
+}
+
+#[test]
+fn witness_decoded_after_its_block_is_late() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let decoded_late = || metrics::PARTIAL_WITNESS_DECODED_LATE.with_label_values(&["0"]).get();
+    let decoded_late_before = decoded_late();
+
+    // The block at the height of the chunk is already the head.
+    let head = Tip {
+        height: HEIGHT,
+        last_block_hash: CryptoHash::hash_bytes(b"head"),
+        prev_block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        next_epoch_id: EpochId::default(),
+    };
+    validator.send(ChainHeadUpdatedMessage {
+        head,
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip: None,
+    });
+    validator.advance(Duration::milliseconds(250));
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    let witnesses = validator.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert!(witnesses[0].decoded_late);
+    assert_eq!(decoded_late(), decoded_late_before + 1);
+}
+
+#[test]
+fn distribution_is_dropped_while_syncing() {
+    let setup = Setup::new();
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    let dropped = || {
+        metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_DROPPED.with_label_values(&["syncing"]).get()
+    };
+    let dropped_before = dropped();
+
+    producer.send(SyncStatusChangedMessage { syncing: true });
+    setup.distribute_witness(&mut producer);
+    assert!(producer.take_network_requests().is_empty());
+    assert_eq!(dropped(), dropped_before + 1);
+
+    producer.send(SyncStatusChangedMessage { syncing: false });
+    setup.distribute_witness(&mut producer);
+    assert!(!producer.take_network_requests().is_empty());
+    assert_eq!(dropped(), dropped_before + 1);
+}
+
+#[test]
+fn duplicate_distribution_request_is_dropped() {
+    let setup = Setup::new();
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    let dropped = || {
+        metrics::PARTIAL_WITNESS_DISTRIBUTION_REQUESTS_DROPPED
+            .with_label_values(&["duplicate"])
+            .get()
+    };
+    let dropped_before = dropped();
+
+    setup.distribute_witness(&mut producer);
+    let num_requests = producer.take_network_requests().len();
+    assert!(num_requests > 0);
+    setup.distribute_witness(&mut producer);
+    assert!(producer.take_network_requests().is_empty());
+    assert_eq!(dropped(), dropped_before + 1);
+    assert_eq!(producer.actor().recent_witness_distribution_summaries().count(), 1);
+
+    // A forced request distributes the witness again, which updates the tracked witness.
+    let witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default());
+    producer.send(
+        DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            setup.clock.now(),
+        )
+        .forced(),
+    );
+    assert_eq!(producer.take_network_requests().len(), num_requests);
+    assert_eq!(producer.actor().recent_witness_distribution_summaries().count(), 1);
+
+    // The request is no longer a duplicate once the chunk was distributed long enough ago.
+    producer.advance(Duration::seconds(10));
+    setup.distribute_witness(&mut producer);
+    assert_eq!(producer.take_network_requests().len(), num_requests);
+    assert_eq!(dropped(), dropped_before + 1);
+}
+
+#[test]
+fn witness_of_sole_chunk_validator_is_delivered_locally() {
+    let setup = Setup::with_validators(100, 1, &["test0"]);
+    let chunk_producer = setup.chunk_producer();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    let local_deliveries =
+        || metrics::PARTIAL_WITNESS_LOCAL_DELIVERIES.with_label_values(&["0"]).get();
+    let local_deliveries_before = local_deliveries();
+
+    setup.distribute_witness(&mut producer);
+    // Nothing is sent over the network, not even the acks to ourselves.
+    assert!(producer.take_network_requests().is_empty());
+    let witnesses = producer.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert_eq!(witnesses[0].witness.chunk_header.height_created(), HEIGHT);
+    assert!(!witnesses[0].pre_tracking);
+    assert_eq!(local_deliveries(), local_deliveries_before + 1);
+    let summaries = producer.actor().recent_witness_distribution_summaries().collect::<Vec<_>>();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].num_validators, 0);
+
+    // A forced distribution of the same witness doesn't deliver it to the client again.
+    let witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default());
+    producer.send(
+        DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            setup.clock.now(),
+        )
+        .forced(),
+    );
+    assert!(producer.take_network_requests().is_empty());
+    assert!(producer.take_client_witnesses().is_empty());
+}
+
+#[test]
+fn parts_received_while_syncing_are_handled_with_the_synced_head() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let config = PartialWitnessConfig { max_parts_buffered_during_sync: 10, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    // The head known before the sync is the prev block of the chunk.
+    let stale_head = Tip {
+        height: HEIGHT - 1,
+        last_block_hash: CryptoHash::hash_bytes(b"head"),
+        prev_block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        next_epoch_id: EpochId::default(),
+    };
+    validator.send(ChainHeadUpdatedMessage {
+        head: stale_head,
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip: None,
+    });
+    validator.send(SyncStatusChangedMessage { syncing: true });
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+    assert_eq!(validator.actor().num_parts_held_during_sync(), parts.len());
+
+    // The sync brings the head to the height of the chunk, so the witness is only handled once
+    // the actor knows that its block is already in the chain.
+    let (genesis, _) = setup.save_blocks();
+    let signer = Arc::new(create_test_signer(VALIDATORS[0]));
+    let block = TestBlockBuilder::new(setup.clock.clock(), &genesis, signer).height(HEIGHT).build();
+    let mut store_update = validator.store().store_update();
+    store_update.set_ser(DBCol::BlockHeader, block.hash().as_ref(), block.header()).unwrap();
+    store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, &Tip::from_header(block.header())).unwrap();
+    store_update.commit().unwrap();
+    validator.send(SyncStatusChangedMessage { syncing: false });
+
+    assert_eq!(validator.actor().num_parts_held_during_sync(), 0);
+    let witnesses = validator.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert!(witnesses[0].decoded_late);
+}
+
+/// Tip of the chain at `height`, the fork is told by the hash.
+fn tip_at(height: BlockHeight, fork: &[u8]) -> Tip {
+    Tip {
+        height,
+        last_block_hash: CryptoHash::hash_bytes(&[fork, &height.to_le_bytes()].concat()),
+        prev_block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        next_epoch_id: EpochId::default(),
+    }
+}
+
+/// Makes `head` the head in the store of the validator and tells the actor about it.
+fn update_head(
+    setup: &Setup,
+    validator: &mut PartialWitnessTestDriver,
+    head: Tip,
+    alternative_tip: Option<Tip>,
+) {
+    let mut store_update = validator.store().store_update();
+    store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, &head).unwrap();
+    store_update.commit().unwrap();
+    validator.send(ChainHeadUpdatedMessage {
+        head,
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip,
+    });
+}
+
+/// The unavailability is only announced from `ProtocolFeature::WitnessReceiverStatus` on.
+#[test]
+fn receiver_status_is_announced_from_the_feature_on() {
+    let setup = Setup::new();
+    let config = PartialWitnessConfig {
+        announce_unavailability_on_shutdown: Some(Duration::minutes(5)),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    let mut store_update = validator.store().store_update();
+    store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, &tip_at(HEIGHT, b"")).unwrap();
+    store_update.commit().unwrap();
+    let announces_status = |validator: &mut PartialWitnessTestDriver| {
+        validator.send(AnnounceWitnessReceiverUnavailable);
+        validator
+            .take_network_requests()
+            .iter()
+            .any(|request| matches!(request, NetworkRequests::WitnessReceiverStatus(..)))
+    };
+
+    let version = ProtocolFeature::WitnessReceiverStatus.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+    assert!(!announces_status(&mut validator));
+    setup.epoch_manager.set_protocol_version(version);
+    assert!(announces_status(&mut validator));
+}
+
+/// Height of a chunk too far ahead of the head at `HEIGHT` for the height window of the head.
+const FORK_WITNESS_HEIGHT: BlockHeight = HEIGHT + 7;
+
+/// A chunk validator of the chunk at `FORK_WITNESS_HEIGHT` which is not its chunk producer.
+fn fork_witness_validator(setup: &Setup) -> AccountId {
+    let chunk_producer = setup.chunk_producer_at(FORK_WITNESS_HEIGHT);
+    VALIDATORS
+        .iter()
+        .map(|account_id| account_id.parse().unwrap())
+        .find(|account_id| *account_id != chunk_producer)
+        .unwrap()
+}
+
+#[test]
+fn witness_admitted_by_alternative_tip_is_decoded_after_reorg() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts_at(FORK_WITNESS_HEIGHT);
+    let key = parts[0].chunk_production_key();
+    let validator_id = fork_witness_validator(&setup);
+    let config = PartialWitnessConfig { fork_aware_height_window: true, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+    let admitted_by_alternative_tip = || {
+        metrics::PARTIAL_WITNESS_HEIGHT_WINDOW_ADMISSIONS
+            .with_label_values(&["0", "alternative_tip"])
+            .get()
+    };
+    let admitted_before = admitted_by_alternative_tip();
+
+    // The chunk is built on the other fork, whose tip is higher than our head.
+    update_head(&setup, &mut validator, tip_at(HEIGHT, b"a"), Some(tip_at(HEIGHT + 3, b"b")));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(admitted_by_alternative_tip() > admitted_before);
+    assert_eq!(validator.actor().height_window(&key), Some(HeightWindowContext::AlternativeTip));
+    assert!(validator.take_client_witnesses().is_empty());
+
+    // A head update on the same fork keeps the witness waiting.
+    update_head(&setup, &mut validator, tip_at(HEIGHT, b"a"), Some(tip_at(HEIGHT + 3, b"b")));
+    assert!(validator.take_client_witnesses().is_empty());
+
+    // The chain reorgs to the other fork, which admits the witness in the window of the head.
+    update_head(&setup, &mut validator, tip_at(HEIGHT + 3, b"b"), Some(tip_at(HEIGHT, b"a")));
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert_eq!(validator.actor().height_window(&key), None);
+}
+
+#[test]
+fn witness_admitted_by_alternative_tip_is_dropped_with_it() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts_at(FORK_WITNESS_HEIGHT);
+    let key = parts[0].chunk_production_key();
+    let validator_id = fork_witness_validator(&setup);
+    let config = PartialWitnessConfig { fork_aware_height_window: true, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    update_head(&setup, &mut validator, tip_at(HEIGHT, b"a"), Some(tip_at(HEIGHT + 3, b"b")));
+    // Not enough parts to decode yet.
+    validator.send(forward_from_owner(parts[0].clone()));
+    assert_eq!(validator.actor().height_window(&key), Some(HeightWindowContext::AlternativeTip));
+
+    // The other fork is abandoned, so neither window admits the witness anymore.
+    update_head(&setup, &mut validator, tip_at(HEIGHT + 1, b"a"), None);
+    assert_eq!(validator.actor().height_window(&key), None);
+    for partial_witness in &parts[1..] {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+    assert_eq!(validator.actor().height_window(&key), None);
+}
+
+#[test]
+fn alternative_tip_is_ignored_by_default() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts_at(FORK_WITNESS_HEIGHT);
+    let key = parts[0].chunk_production_key();
+    let validator_id = fork_witness_validator(&setup);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+
+    update_head(&setup, &mut validator, tip_at(HEIGHT, b"a"), Some(tip_at(HEIGHT + 3, b"b")));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.actor().height_window(&key), None);
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+#[test]
+fn parts_received_while_syncing_are_dropped_by_default() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let dropped = |delivery: PartDelivery| {
+        metrics::PARTIAL_WITNESS_PARTS_DROPPED_DURING_SYNC
+            .with_label_values(&[delivery.as_str()])
+            .get()
+    };
+    let dropped_direct_before = dropped(PartDelivery::Direct);
+    let dropped_forward_before = dropped(PartDelivery::Forward);
+
+    validator.send(SyncStatusChangedMessage { syncing: true });
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    assert_eq!(validator.actor().num_parts_held_during_sync(), 0);
+    assert_eq!(dropped(PartDelivery::Direct), dropped_direct_before + 1);
+    assert_eq!(dropped(PartDelivery::Forward), dropped_forward_before + parts.len() as u64 - 1);
+
+    // Nothing is left to handle once the sync is done, the parts arriving afterwards are
+    // handled as usual.
+    validator.send(SyncStatusChangedMessage { syncing: false });
+    assert!(validator.take_client_witnesses().is_empty());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+}
+
+#[test]
+fn parts_of_epoch_unknown_to_epoch_manager_are_deferred_until_it_catches_up() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let deferred_parts =
+        |outcome: &str| metrics::PARTIAL_WITNESS_DEFERRED_PARTS.with_label_values(&[outcome]).get();
+    let accepted_before = deferred_parts("accepted");
+    let rejected_before = deferred_parts("rejected");
+
+    // The epoch manager lags one epoch behind the head, which already entered the epoch of the
+    // chunk.
+    setup.epoch_manager.hide_epoch(EpochId::default(), true);
+    update_head(&setup, &mut validator, tip_at(HEIGHT - 1, b"main"), None);
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    // Rejected once validated, the chunk producer sends us directly only the part we own.
+    let other_part = part_of(&parts, &setup.validator(1)).clone();
+    validator.send(PartialEncodedStateWitnessMessage(other_part));
+    assert_eq!(validator.actor().num_deferred_parts(), parts.len() + 1);
+    validator.advance(EPOCH_INFO_CHECK_PERIOD);
+    assert_eq!(validator.actor().num_deferred_parts(), parts.len() + 1);
+    assert!(validator.take_client_witnesses().is_empty());
+
+    // The epoch manager is checked again without waiting for the next head update.
+    setup.epoch_manager.hide_epoch(EpochId::default(), false);
+    validator.advance(EPOCH_INFO_CHECK_PERIOD);
+    assert_eq!(validator.actor().num_deferred_parts(), 0);
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert_eq!(deferred_parts("accepted"), accepted_before + parts.len() as u64);
+    assert_eq!(deferred_parts("rejected"), rejected_before + 1);
+}
+
+#[test]
+fn owned_part_delivery_is_accounted_to_chunk_producer() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let key = parts[0].chunk_production_key();
+
+    let direct_id = setup.validator(0);
+    let mut direct = setup.driver(&direct_id, PartialWitnessConfig::default());
+    direct.send(PartialEncodedStateWitnessMessage(part_of(&parts, &direct_id).clone()));
+    assert_eq!(direct.actor().owned_part_delivery(&key), Some(PartDelivery::Direct));
+
+    // Our part may reach us in a forward first, e.g. when the chunk producer answers a request.
+    let forward_id = setup.validator(1);
+    let mut forward = setup.driver(&forward_id, PartialWitnessConfig::default());
+    let own_part = part_of(&parts, &forward_id).clone();
+    forward.send(forward_from_owner(own_part.clone()));
+    forward.send(PartialEncodedStateWitnessMessage(own_part));
+    assert_eq!(forward.actor().owned_part_delivery(&key), Some(PartDelivery::Forward));
+
+    let health = forward.actor().producer_distribution_health();
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].account_id, setup.chunk_producer());
+    assert_eq!(health[0].owned_parts_direct_first, 0);
+    assert_eq!(health[0].owned_parts_forward_first, 1);
+}
+
+#[test]
+fn lost_forwards_are_accounted_to_the_peer_link() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let lossy_id = setup.validator(1);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    for height in [HEIGHT, HEIGHT + 1] {
+        for partial_witness in setup.produce_parts_at(height) {
+            // The forward of the lossy owner is lost at the second height.
+            if height == HEIGHT + 1 && owner_of(&partial_witness) == lossy_id {
+                continue;
+            }
+            validator.send(forward_from_owner(partial_witness));
+        }
+    }
+    assert!(validator.actor().link_loss_estimates().is_empty());
+
+    // Well past the heights at which no more forwards are expected for both chunks.
+    let head = Tip {
+        height: HEIGHT + 10,
+        last_block_hash: CryptoHash::hash_bytes(b"head"),
+        prev_block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        next_epoch_id: EpochId::default(),
+    };
+    validator.send(ChainHeadUpdatedMessage {
+        head,
+        head_timestamp: setup.clock.now_utc(),
+        alternative_tip: None,
+    });
+
+    // We don't expect our own part to be forwarded to us.
+    let estimates = validator.actor().link_loss_estimates();
+    assert_eq!(estimates.len(), VALIDATORS.len() - 1);
+    assert_eq!(estimates[0].peer_id, peer_id_of(&lossy_id));
+    assert_eq!(estimates[0].expected_parts, 2);
+    assert_eq!(estimates[0].delivered_parts, 1);
+    assert_eq!(estimates[0].loss_bucket(), "above_50pct");
+    for estimate in &estimates[1..] {
+        assert_eq!(estimate.expected_parts, 2);
+        assert_eq!(estimate.delivered_parts, 2);
+    }
+}
+
+#[test]
+fn parts_of_nonexistent_shard_are_rejected_per_peer() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let sender_id = setup.validator(1);
+    // The epoch has a single shard, but the mock epoch manager would happily assign the chunk
+    // validators to any other shard.
+    let shard_id = setup.epoch_manager.shard_ids(&EpochId::default()).unwrap().len() as u64;
+    let partial_witness = TestWitnessBuilder::new()
+        .height(HEIGHT)
+        .shard_id(shard_id)
+        .encode_and_split_among(
+            &[validator_id.clone()],
+            &create_test_signer(setup.chunk_producer().as_str()),
+        )
+        .part(0)
+        .clone();
+    let config = PartialWitnessConfig { pre_tracked_shards: vec![shard_id], ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    validator.send(PartialEncodedStateWitnessMessage(partial_witness.clone()));
+    for _ in 0..2 {
+        validator.send(PartialEncodedStateWitnessForwardMessage(
+            partial_witness.clone(),
+            peer_id_of(&sender_id),
+        ));
+    }
+    assert_eq!(validator.actor().invalid_shard_id_parts_from(&peer_id_of(&sender_id)), 2);
+    assert_eq!(validator.actor().invalid_shard_id_parts_from(&peer_id_of(&validator_id)), 0);
+    assert!(validator.take_network_requests().is_empty());
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+#[test]
+fn distribution_failure_of_one_shard_does_not_affect_the_others() {
+    const FAILING_SHARD: ShardId = 1;
+    let setup = Setup::with_shards(100, 2);
+    // The mock epoch manager rotates the chunk producers by shard and height, so the producer of
+    // shard 0 at `HEIGHT` also produces the failing shard one height below.
+    let producer_id = setup.chunk_producer();
+    let failing_height = HEIGHT - 1;
+    assert_eq!(
+        setup
+            .epoch_manager
+            .get_chunk_producer(&EpochId::default(), failing_height, FAILING_SHARD)
+            .unwrap(),
+        producer_id
+    );
+    setup.epoch_manager.fail_chunk_validator_assignments(FAILING_SHARD);
+    let distribution_errors = |shard_id: &str| {
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&["distribute_witness", shard_id, "validator_error"])
+            .get()
+    };
+    let failing_shard_errors_before = distribution_errors("1");
+    let other_shard_errors_before = distribution_errors("0");
+
+    let mut producer = setup.driver(&producer_id, PartialWitnessConfig::default());
+    for (shard_id, height) in [(FAILING_SHARD, failing_height), (0, HEIGHT)] {
+        let witness = ChunkStateWitness::new_dummy(height, shard_id, CryptoHash::default());
+        producer.send(DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            setup.clock.now(),
+        ));
+    }
+
+    let mut parts = vec![];
+    for request in producer.take_network_requests() {
+        match request {
+            NetworkRequests::PartialEncodedStateWitness(owned_parts, _) => {
+                parts.extend(owned_parts.into_iter().map(|(_, partial_witness)| partial_witness))
+            }
+            NetworkRequests::PartialEncodedStateWitnessForward(_, partial_witness, _) => {
+                parts.push(partial_witness)
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(parts.len(), VALIDATORS.len());
+    assert!(parts
+        .iter()
+        .all(|partial_witness| partial_witness.chunk_production_key().shard_id == 0));
+    assert_eq!(distribution_errors("1"), failing_shard_errors_before + 1);
+    assert_eq!(distribution_errors("0"), other_shard_errors_before);
+
+    // The witness of the healthy shard is fully distributed, the validators can decode it.
+    let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+    for partial_witness in parts {
+        validator.send(forward_from_owner(partial_witness));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+}
+
+#[test]
+fn owned_part_conflicting_with_received_forwards_is_not_forwarded() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let conflicting_parts = setup.produce_parts_on(HEIGHT, CryptoHash::hash_bytes(b"other"));
+    let validator_id = setup.validator(0);
+    let other_part = parts.iter().find(|part| owner_of(part) != validator_id).unwrap();
+    let key = other_part.chunk_production_key();
+
+    for (own_part, consistent) in [
+        (part_of(&parts, &validator_id), true),
+        (part_of(&conflicting_parts, &validator_id), false),
+    ] {
+        let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+        validator.send(forward_from_owner(other_part.clone()));
+        validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+
+        let forwarded = forwards(&validator.take_network_requests());
+        if consistent {
+            assert_eq!(forwarded.len(), 1);
+            assert_eq!(forwarded[0].1, own_part.part_ord());
+            assert!(validator.actor().conflict_evidence(&key).is_none());
+        } else {
+            assert!(forwarded.is_empty());
+            let evidence = validator.actor().conflict_evidence(&key).unwrap();
+            assert_eq!(&evidence.reference_part, other_part);
+            assert_eq!(&evidence.conflicting_part, own_part);
+        }
+    }
+}
+
+#[test]
+fn witness_of_another_chunk_is_not_sent_to_client() {
+    let setup = Setup::new();
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    // The chunk producer signs the parts for the chunk at HEIGHT, but encodes into them the
+    // witness of the chunk at the next height.
+    let chunk_header = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default()).chunk_header;
+    let witness = TestWitnessBuilder::new().height(HEIGHT + 1).build();
+    producer.send(DistributeStateWitnessRequest::new(
+        EpochId::default(),
+        chunk_header,
+        Arc::new(witness.clone()),
+        setup.clock.now(),
+    ));
+    let mut parts = vec![];
+    for request in producer.take_network_requests() {
+        match request {
+            NetworkRequests::PartialEncodedStateWitness(owned_parts, _) => {
+                parts.extend(owned_parts.into_iter().map(|(_, partial_witness)| partial_witness))
+            }
+            NetworkRequests::PartialEncodedStateWitnessForward(_, partial_witness, _) => {
+                parts.push(partial_witness)
+            }
+            _ => {}
+        }
+    }
+    let key = parts[0].chunk_production_key();
+    assert_eq!(key.height_created, HEIGHT);
+    let mismatches = || {
+        metrics::PARTIAL_WITNESS_DECODED_KEY_MISMATCHES
+            .with_label_values(&["0", "height_created"])
+            .get()
+    };
+    let mismatches_before = mismatches();
+
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    for partial_witness in parts.iter().filter(|part| owner_of(part) != validator_id) {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+    let evidence = validator.actor().key_mismatch(&key).unwrap();
+    assert_eq!(evidence.decoded_key, witness.chunk_production_key());
+    assert_eq!(evidence.reference_part.chunk_production_key(), key);
+    // The decoded witness matches the hash signed by the chunk producer, if the parts carry one.
+    if let Some(witness_hash) = evidence.witness_hash {
+        assert_eq!(witness_hash, CryptoHash::hash_borsh(&witness));
+    }
+    assert_eq!(mismatches(), mismatches_before + 1);
+    let health = validator.actor().producer_distribution_health();
+    assert_eq!(health[0].decode_failures, 1);
+}
+
+#[test]
+fn owned_part_is_forwarded_before_it_is_stored() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let own_part = part_of(&parts, &validator_id);
+    let other_part = parts.iter().find(|part| owner_of(part) != validator_id).unwrap();
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+
+    // Number of requests sent to the network by the time the tracker starts storing each part.
+    let mut capture = TracingCapture::enable();
+    let sent_before_store = Arc::new(Mutex::new(vec![]));
+    capture.set_callback({
+        let sent_before_store = sent_before_store.clone();
+        let network_requests = validator.network_requests_counter();
+        move |log| {
+            if log.starts_with("store_partial_encoded_state_witness") {
+                sent_before_store.lock().unwrap().push(network_requests());
+            }
+        }
+    });
+    validator.send(forward_from_owner(other_part.clone()));
+    validator.take_network_requests();
+    sent_before_store.lock().unwrap().clear();
+
+    validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+    assert_eq!(*sent_before_store.lock().unwrap(), vec![1]);
+    let forwarded = forwards(&validator.take_network_requests());
+    assert_eq!(forwarded.len(), 1);
+    assert_eq!(forwarded[0].1, own_part.part_ord());
+}
+
+#[test]
+fn forward_conflicting_with_owned_part_is_rejected() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let conflicting_parts = setup.produce_parts_on(HEIGHT, CryptoHash::hash_bytes(b"other"));
+    let validator_id = setup.validator(0);
+    let own_part = part_of(&parts, &validator_id);
+    let key = own_part.chunk_production_key();
+
+    for (forwarded_parts, consistent) in [(&parts, true), (&conflicting_parts, false)] {
+        let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+        // Our part arrives first, so it is forwarded right away.
+        validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+        assert_eq!(forwards(&validator.take_network_requests()).len(), 1);
+
+        let other_part =
+            forwarded_parts.iter().find(|part| owner_of(part) != validator_id).unwrap();
+        validator.send(forward_from_owner(other_part.clone()));
+        if consistent {
+            assert!(validator.actor().conflict_evidence(&key).is_none());
+        } else {
+            let evidence = validator.actor().conflict_evidence(&key).unwrap();
+            assert_eq!(&evidence.reference_part, own_part);
+            assert_eq!(&evidence.conflicting_part, other_part);
+        }
+    }
+}
+
+/// The chunk producer signs the protocol version it created the parts under, which the chunk
+/// validators accept if it is the protocol version of the epoch of the parts or the one before.
+#[test]
+fn part_protocol_version_is_checked_at_the_upgrade_boundary() {
+    let setup = Setup::new();
+    let version = ProtocolFeature::PartialWitnessProtocolVersion.protocol_version() + 1;
+    let rejected_parts = || {
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&["forwarded_part", "0", "invalid_partial_witness_protocol_version"])
+            .get()
+    };
+
+    for (producer_version, validator_version, accepted) in [
+        (version, version, true),
+        // The producer is one version behind, e.g. the part was created right before the switch.
+        (version, version + 1, true),
+        (version - 1, version + 1, false),
+        // The producer applied the rules of a version we don't run yet.
+        (version + 1, version, false),
+    ] {
+        setup.epoch_manager.set_protocol_version(producer_version);
+        let parts = setup.produce_parts();
+        assert!(parts.iter().all(|part| part.protocol_version() == Some(producer_version)));
+
+        setup.epoch_manager.set_protocol_version(validator_version);
+        let rejected_parts_before = rejected_parts();
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        for partial_witness in parts {
+            validator.send(forward_from_owner(partial_witness));
+        }
+        let witnesses = validator.take_client_witnesses();
+        if accepted {
+            assert_eq!(witnesses.len(), 1, "{producer_version} -> {validator_version}");
+            assert_eq!(rejected_parts(), rejected_parts_before);
+        } else {
+            assert!(witnesses.is_empty(), "{producer_version} -> {validator_version}");
+            assert!(rejected_parts() > rejected_parts_before);
+        }
+    }
+}
+
+/// Right after the upgrade to `ProtocolFeature::PartialWitnessProtocolVersion` the parts of the
+/// same witness arrive in both formats. The chunk validators decode the witness from the mix and
+/// forward their own part in the format the chunk producer created it in.
+#[test]
+fn parts_of_both_formats_are_accepted_across_the_upgrade() {
+    let setup = Setup::new();
+    let version = ProtocolFeature::PartialWitnessProtocolVersion.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+    let previous_parts = setup.produce_parts();
+    assert!(previous_parts.iter().all(|part| part.protocol_version().is_none()));
+    setup.epoch_manager.set_protocol_version(version);
+    let parts = setup.produce_parts();
+    assert!(parts.iter().all(|part| part.protocol_version() == Some(version)));
+
+    let validator_id = setup.validator(0);
+    let own_part = part_of(&previous_parts, &validator_id);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+    let forwarded = validator
+        .take_network_requests()
+        .into_iter()
+        .filter_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitnessForward(_, partial_witness, _) => {
+                Some(partial_witness)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(forwarded, vec![own_part.clone()]);
+
+    for partial_witness in parts.into_iter().filter(|part| owner_of(part) != validator_id) {
+        validator.send(forward_from_owner(partial_witness));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert!(validator.actor().conflict_evidence(&own_part.chunk_production_key()).is_none());
+
+    // Two versions later the previous format is not accepted anymore.
+    setup.epoch_manager.set_protocol_version(version + 2);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+    assert!(forwards(&validator.take_network_requests()).is_empty());
+}
+
+/// Across the upgrade to `ProtocolFeature::PartialWitnessSendTimestamp` the parts switch from the
+/// V1 format, owned in the order of the chunk validator assignment, to the V2 format, owned in the
+/// order of the account ids. The parts of the last witness before the upgrade are still in flight
+/// when it happens, and both witnesses are decoded from the parts of their own format.
+#[test]
+fn parts_of_both_formats_are_accepted_across_the_send_timestamp_upgrade() {
+    let setup = Setup::new();
+    // The owners of the parts change across the upgrade if the assignment isn't sorted.
+    setup.epoch_manager.reverse_chunk_validators_order(true);
+    let version = ProtocolFeature::PartialWitnessSendTimestamp.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+    let previous_parts = setup.produce_owned_parts_on(HEIGHT, CryptoHash::default());
+    setup.epoch_manager.set_protocol_version(version);
+    let parts = setup.produce_owned_parts_on(HEIGHT + 1, CryptoHash::default());
+    // The V1 parts don't carry their owner nor the send timestamp, the V2 parts carry both.
+    assert!(previous_parts.iter().all(|(_, partial_witness)| {
+        partial_witness.owner().is_none() && partial_witness.sent_at().is_none()
+    }));
+    assert!(parts.iter().all(|(owner, partial_witness)| {
+        partial_witness.owner() == Some(owner)
+            && partial_witness.sent_at().is_some()
+            && partial_witness.parts_root().is_none()
+    }));
+    let owners = |parts: &[(AccountId, PartialEncodedStateWitness)]| {
+        parts.iter().map(|(owner, _)| owner.clone()).collect::<Vec<_>>()
+    };
+    let mut reversed_owners = sorted(owners(&parts));
+    reversed_owners.reverse();
+    assert_eq!(owners(&previous_parts), reversed_owners);
+    assert_eq!(owners(&parts), sorted(owners(&parts)));
+
+    let previous_format_parts =
+        || metrics::PARTIAL_WITNESS_PREVIOUS_FORMAT_PARTS.with_label_values(&["v1"]).get();
+    let previous_format_parts_before = previous_format_parts();
+    let validator_id = VALIDATORS
+        .iter()
+        .map(|account_id| account_id.parse::<AccountId>().unwrap())
+        .find(|account_id| {
+            account_id != &setup.chunk_producer_at(HEIGHT)
+                && account_id != &setup.chunk_producer_at(HEIGHT + 1)
+        })
+        .unwrap();
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    for (owner, partial_witness) in previous_parts.into_iter().chain(parts) {
+        if owner == validator_id {
+            validator.send(PartialEncodedStateWitnessMessage(partial_witness));
+        } else {
+            validator.send(PartialEncodedStateWitnessForwardMessage(
+                partial_witness,
+                peer_id_of(&owner),
+            ));
+        }
+    }
+    let witnesses = validator.take_client_witnesses();
+    let heights = witnesses
+        .iter()
+        .map(|witness| witness.witness.chunk_header.height_created())
+        .collect::<Vec<_>>();
+    assert_eq!(heights, vec![HEIGHT, HEIGHT + 1]);
+    assert_eq!(previous_format_parts(), previous_format_parts_before + VALIDATORS.len() as u64);
+}
+
+/// The chunk producer and the chunk validators agree on the owners of the parts, which are sorted
+/// by account id, but the chunk producer orders the assignment differently, as a node running
+/// another version with a bug in the assignment logic would.
+#[test]
+fn chunk_validators_mismatch_is_detected() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessMerkleCommitment.protocol_version());
+    let parts = setup.produce_parts();
+    setup.epoch_manager.reverse_chunk_validators_order(true);
+    let perturbed_parts = setup.produce_parts();
+    setup.epoch_manager.reverse_chunk_validators_order(false);
+    let validator_id = setup.validator(0);
+    let key = parts[0].chunk_production_key();
+
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    assert!(validator.actor().chunk_validators_mismatch(&key).is_none());
+
+    for reject_chunk_validators_mismatch in [false, true] {
+        let config =
+            PartialWitnessConfig { reject_chunk_validators_mismatch, ..Default::default() };
+        let mut validator = setup.driver(&validator_id, config);
+        let own_part = part_of(&perturbed_parts, &validator_id);
+        validator.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+
+        let (expected, actual) = validator.actor().chunk_validators_mismatch(&key).unwrap();
+        assert_eq!(expected.num_validators, VALIDATORS.len());
+        assert_eq!(actual, own_part.chunk_validators_digest().unwrap());
+        assert_eq!(expected.num_validators, actual.num_validators);
+        assert_ne!(expected.accounts_hash, actual.accounts_hash);
+        // Only detected by default, the part is forwarded as usual.
+        let forwarded = forwards(&validator.take_network_requests());
+        assert_eq!(forwarded.is_empty(), reject_chunk_validators_mismatch);
+    }
+}
+
+#[test]
+fn incomplete_witness_is_abandoned_after_deadline() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let config = PartialWitnessConfig::default();
+    let deadline = config.incomplete_witness_deadline;
+    let mut validator = setup.driver(&validator_id, config);
+
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    assert!(validator.pending_delayed_actions().contains(&"expire_incomplete_witnesses"));
+    validator.advance(deadline - Duration::milliseconds(1));
+    assert!(validator.pending_delayed_actions().contains(&"expire_incomplete_witnesses"));
+    validator.advance(Duration::milliseconds(1));
+    assert!(!validator.pending_delayed_actions().contains(&"expire_incomplete_witnesses"));
+
+    // The parts arriving after the deadline are dropped, so the witness is never decoded.
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+/// Context in which `account_id` validates the parts, as a chunk validator of the chunk.
+fn validation_context<'a>(
+    setup: &'a Setup,
+    account_id: &'a AccountId,
+    heads: ChainHeads,
+    deadline: Option<Instant>,
+) -> ValidationContext<'a> {
+    ValidationContext {
+        epoch_manager: setup.epoch_manager.as_ref(),
+        account_id,
+        pre_tracking: false,
+        heads,
+        alternative_tip: None,
+        deadline,
+        expired: false,
+    }
+}
+
+fn validate_part(
+    setup: &Setup,
+    partial_witness: &PartialEncodedStateWitness,
+    context: &ValidationContext,
+) -> Option<HeightWindowContext> {
+    validate_partial_encoded_state_witness(partial_witness, context, &setup.clock.clock(), || {
+        Ok(true)
+    })
+    .unwrap()
+}
+
+#[test]
+fn part_is_dropped_from_its_deadline_on() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let heads = ChainHeads { head: Some(tip_at(HEIGHT - 1, b"")), final_head: None };
+    let deadline = setup.clock.now() + Duration::milliseconds(100);
+    let context = validation_context(&setup, &validator_id, heads, Some(deadline));
+    setup.clock.advance(Duration::milliseconds(99));
+    assert_eq!(validate_part(&setup, &parts[0], &context), Some(HeightWindowContext::Head));
+    setup.clock.advance(Duration::milliseconds(1));
+    assert_eq!(validate_part(&setup, &parts[0], &context), None);
+
+    // The deadline of an abandoned witness is no longer pending.
+    let context = ValidationContext { deadline: None, expired: true, ..context };
+    assert_eq!(validate_part(&setup, &parts[0], &context), None);
+}
+
+#[test]
+fn part_height_is_checked_at_the_bounds_of_the_window() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    // The part is at the highest height above the head within the window of the head.
+    let parts = setup.produce_parts_at(HEIGHT + 5);
+    let heads = ChainHeads { head: Some(tip_at(HEIGHT, b"")), final_head: None };
+    let context = validation_context(&setup, &validator_id, heads, None);
+    assert_eq!(validate_part(&setup, &parts[0], &context), Some(HeightWindowContext::Head));
+    let heads = ChainHeads { head: Some(tip_at(HEIGHT - 1, b"")), final_head: None };
+    let context = validation_context(&setup, &validator_id, heads, None);
+    assert_eq!(validate_part(&setup, &parts[0], &context), None);
+
+    // The chunk can't be included once the final head reaches its height.
+    let parts = setup.produce_parts();
+    let final_head_at = |height| ChainHeads {
+        head: Some(tip_at(HEIGHT, b"")),
+        final_head: Some(tip_at(height, b"")),
+    };
+    let context = validation_context(&setup, &validator_id, final_head_at(HEIGHT - 1), None);
+    assert_eq!(validate_part(&setup, &parts[0], &context), Some(HeightWindowContext::Head));
+    let context = validation_context(&setup, &validator_id, final_head_at(HEIGHT), None);
+    assert_eq!(validate_part(&setup, &parts[0], &context), None);
+}
+
+#[test]
+fn owned_part_requests_are_answered_then_rebroadcast() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessRequests.protocol_version());
+    let parts = setup.produce_parts();
+    let owner_id = setup.validator(0);
+    let mut owner = setup.driver(&owner_id, PartialWitnessConfig::default());
+    let own_part = part_of(&parts, &owner_id).clone();
+    owner.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+    owner.take_network_requests();
+
+    let spoofed_request = |requester: &AccountId, author: Option<AccountId>| {
+        PartialEncodedStateWitnessRequestMessage(
+            PartialEncodedStateWitnessRequest {
+                requester: requester.clone(),
+                epoch_id: EpochId::default(),
+                shard_id: 0,
+                height_created: HEIGHT,
+                part_ord: own_part.part_ord(),
+            },
+            author,
+        )
+    };
+    let request = |requester: &AccountId| spoofed_request(requester, Some(requester.clone()));
+    // The requests not sent by the requester itself are ignored, they count neither as an
+    // answered request nor towards the re-broadcast.
+    owner.send(spoofed_request(&setup.validator(1), Some(setup.validator(2))));
+    owner.send(spoofed_request(&setup.validator(2), None));
+    assert!(owner.take_network_requests().is_empty());
+    // The first requester is answered directly.
+    owner.send(request(&setup.validator(1)));
+    assert_eq!(
+        forwards(&owner.take_network_requests()),
+        vec![(vec![setup.validator(1)], own_part.part_ord())]
+    );
+    // Another chunk validator missing the part suggests that our forward was lost, so the part
+    // is re-broadcast to all the chunk validators.
+    owner.send(request(&setup.validator(2)));
+    let forwards = forwards(&owner.take_network_requests());
+    assert_eq!(forwards.len(), 1);
+    assert_eq!(sorted(forwards[0].0.clone()), sorted(vec![setup.validator(1), setup.validator(2)]));
+
+    // Accounts which are not chunk validators of the chunk don't get the part.
+    owner.send(request(&"outsider".parse().unwrap()));
+    assert!(owner.take_network_requests().is_empty());
+}
+
+#[test]
+fn chunk_producer_serves_any_part_on_request() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessRequests.protocol_version());
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    setup.distribute_witness(&mut producer);
+    producer.take_network_requests();
+
+    let owner_id = setup.validator(1);
+    let part_ord = VALIDATORS.iter().position(|v| *v == owner_id.as_str()).unwrap();
+    producer.send(PartialEncodedStateWitnessRequestMessage(
+        PartialEncodedStateWitnessRequest {
+            requester: setup.validator(0),
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created: HEIGHT,
+            part_ord,
+        },
+        Some(setup.validator(0)),
+    ));
+    let requests = producer.take_network_requests();
+    assert_eq!(forwards(&requests), vec![(vec![setup.validator(0)], part_ord)]);
+    // The served part isn't routed directly, it's only an answer to a single validator.
+    assert!(matches!(
+        &requests[0],
+        NetworkRequests::PartialEncodedStateWitnessForward(_, _, hints)
+            if hints == &WitnessRoutingHints::default()
+    ));
+}
+
+/// Before `ProtocolFeature::PartialWitnessRequests`, the missing parts are not requested, and the
+/// requests are rejected.
+#[test]
+fn part_requests_are_not_used_before_the_feature() {
+    let setup = Setup::new();
+    let version = ProtocolFeature::PartialWitnessRequests.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let delay = Duration::milliseconds(300);
+    let config =
+        PartialWitnessConfig { missing_part_request_delay: Some(delay), ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    validator.take_network_requests();
+    validator.advance(delay);
+    assert!(validator
+        .take_network_requests()
+        .iter()
+        .all(|request| !matches!(request, NetworkRequests::PartialEncodedStateWitnessRequest(..))));
+
+    let request_errors = || {
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&["part_request", "0", "invalid_partial_chunk_state_witness"])
+            .get()
+    };
+    let request_errors_before = request_errors();
+    let owner_id = setup.validator(1);
+    let mut owner = setup.driver(&owner_id, PartialWitnessConfig::default());
+    let own_part = part_of(&parts, &owner_id).clone();
+    owner.send(PartialEncodedStateWitnessMessage(own_part.clone()));
+    owner.take_network_requests();
+    owner.send(PartialEncodedStateWitnessRequestMessage(
+        PartialEncodedStateWitnessRequest {
+            requester: validator_id.clone(),
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created: HEIGHT,
+            part_ord: own_part.part_ord(),
+        },
+        Some(validator_id),
+    ));
+    assert!(request_errors() > request_errors_before);
+    assert!(forwards(&owner.take_network_requests()).is_empty());
+}
+
+#[test]
+fn missing_parts_are_requested_from_their_owners_once() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessRequests.protocol_version());
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let delay = Duration::milliseconds(300);
+    let config =
+        PartialWitnessConfig { missing_part_request_delay: Some(delay), ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    validator.take_network_requests();
+
+    let part_requests = |validator: &mut PartialWitnessTestDriver| {
+        let mut part_requests = validator
+            .take_network_requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                NetworkRequests::PartialEncodedStateWitnessRequest(target, request) => {
+                    assert_eq!(request.requester, validator_id);
+                    Some((target, request.part_ord))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        part_requests.sort();
+        part_requests
+    };
+    validator.advance(delay - Duration::milliseconds(100));
+    assert!(part_requests(&mut validator).is_empty());
+
+    // Every part but ours is requested from its owner once the delay passes, and only once.
+    validator.advance(Duration::milliseconds(100));
+    let mut expected = parts
+        .iter()
+        .map(|partial_witness| (owner_of(partial_witness), partial_witness.part_ord()))
+        .filter(|(owner, _)| owner != &validator_id)
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(part_requests(&mut validator), expected);
+    validator.advance(delay);
+    assert!(part_requests(&mut validator).is_empty());
+}
+
+#[test]
+fn full_witness_takes_priority_over_parts() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let (target, full_witness) = setup.produce_full_witness();
+
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &target).clone()));
+    validator.send(FullEncodedStateWitnessMessage(full_witness));
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    // The parts already received are dropped, and the parts arriving later are ignored.
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+/// Number of the acks among the requests.
+fn num_acks(requests: &[NetworkRequests]) -> usize {
+    requests
+        .iter()
+        .filter(|request| matches!(request, NetworkRequests::ChunkStateWitnessAck(..)))
+        .count()
+}
+
+#[test]
+fn witness_delivered_in_full_and_in_parts_is_decoded_once() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let (target, full_witness) = setup.produce_full_witness();
+    let key = full_witness.chunk_production_key();
+    let redundant = |path: WitnessDecodePath| {
+        metrics::PARTIAL_WITNESS_REDUNDANT_DECODES.with_label_values(&["0", path.as_str()]).get()
+    };
+
+    // The full witness is decoded first.
+    let redundant_parts_before = redundant(WitnessDecodePath::Parts);
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    validator.send(FullEncodedStateWitnessMessage(full_witness.clone()));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert_eq!(num_acks(&validator.take_network_requests()), 1);
+    assert_eq!(redundant(WitnessDecodePath::Parts), redundant_parts_before + 1);
+    assert!(validator.actor().decode_conflict(&key).is_none());
+
+    // The witness is reconstructed from the parts first.
+    let redundant_full_witness_before = redundant(WitnessDecodePath::FullWitness);
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.send(FullEncodedStateWitnessMessage(full_witness));
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert_eq!(num_acks(&validator.take_network_requests()), 1);
+    assert_eq!(redundant(WitnessDecodePath::FullWitness), redundant_full_witness_before + 1);
+    assert!(validator.actor().decode_conflict(&key).is_none());
+}
+
+/// The actor restarted within the process, e.g. rebuilt after a change of the signer, starts with
+/// empty caches, but given the delivered witnesses of the previous instance it doesn't deliver
+/// the witness delivered before the restart again, neither from the parts nor from the full
+/// witness.
+#[test]
+fn witness_delivered_before_restart_is_not_delivered_again() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let (target, full_witness) = setup.produce_full_witness();
+    let store = create_test_store();
+    let delivered_witnesses = DeliveredWitnesses::new();
+    let restart = || {
+        let mut validator = PartialWitnessTestDriver::new_with_store(
+            setup.clock.clone(),
+            target.clone(),
+            setup.epoch_manager.clone(),
+            PartialWitnessConfig::default(),
+            store.clone(),
+        );
+        validator.actor_mut().set_delivered_witnesses(delivered_witnesses.clone());
+        validator
+    };
+    let deliver_parts = |validator: &mut PartialWitnessTestDriver| {
+        validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &target).clone()));
+        for partial_witness in &parts {
+            if owner_of(partial_witness) != target {
+                validator.send(forward_from_owner(partial_witness.clone()));
+            }
+        }
+    };
+
+    let mut validator = restart();
+    deliver_parts(&mut validator);
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert_eq!(num_acks(&validator.take_network_requests()), 1);
+
+    let skipped_before = metrics::PARTIAL_WITNESS_REDELIVERIES_SKIPPED.get();
+    let mut validator = restart();
+    deliver_parts(&mut validator);
+    validator.send(FullEncodedStateWitnessMessage(full_witness));
+    assert!(validator.take_client_witnesses().is_empty());
+    assert_eq!(num_acks(&validator.take_network_requests()), 0);
+    assert!(metrics::PARTIAL_WITNESS_REDELIVERIES_SKIPPED.get() > skipped_before);
+
+    // An actor which doesn't share the delivered witnesses is a different node, which gets the
+    // witness.
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    deliver_parts(&mut validator);
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+}
+
+/// The acks among the requests, in the order they were sent.
+fn take_acks(validator: &mut PartialWitnessTestDriver) -> Vec<VersionedChunkStateWitnessAck> {
+    validator
+        .take_network_requests()
+        .into_iter()
+        .filter_map(|request| match request {
+            NetworkRequests::ChunkStateWitnessAck(_, ack) => Some(ack),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Once `ProtocolFeature::WitnessAckDecodeStats` is enabled, the chunk validators report in the
+/// ack how many parts they had received when they decoded the witness and from which path.
+#[test]
+fn ack_reports_parts_received_at_decode() {
+    let setup = Setup::new();
+    let version = ProtocolFeature::WitnessAckDecodeStats.protocol_version();
+
+    setup.epoch_manager.set_protocol_version(version - 1);
+    let parts = setup.produce_parts();
+    let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let acks = take_acks(&mut validator);
+    assert!(matches!(acks[..], [VersionedChunkStateWitnessAck::V1(_)]), "{acks:?}");
+
+    setup.epoch_manager.set_protocol_version(version);
+    let parts = setup.produce_parts();
+    let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let acks = take_acks(&mut validator);
+    let [VersionedChunkStateWitnessAck::V2(ack)] = &acks[..] else {
+        panic!("expected a single V2 ack, got {acks:?}");
+    };
+    let witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default());
+    assert_eq!(ack.chunk_hash, witness.chunk_header.chunk_hash());
+    assert!(!ack.via_full_witness);
+    // The witness is decoded right after the part completing the data parts.
+    let data_parts = witness_parts_geometry::data_parts(parts.len());
+    assert_eq!(ack.parts_received_at_decode as usize, data_parts);
+
+    // The full witness arrives before any part.
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let (target, full_witness) = setup.produce_full_witness();
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    validator.send(FullEncodedStateWitnessMessage(full_witness));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let acks = take_acks(&mut validator);
+    let [VersionedChunkStateWitnessAck::V2(ack)] = &acks[..] else {
+        panic!("expected a single V2 ack, got {acks:?}");
+    };
+    assert!(ack.via_full_witness);
+    assert_eq!(ack.parts_received_at_decode, 0);
+}
+
+#[test]
+fn full_witness_conflicting_with_parts_is_surfaced() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let key = parts[0].chunk_production_key();
+    // The chunk producer signs a different witness of the same chunk in the full witness.
+    let other_witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::hash_bytes(b"other"));
+    let (encoded_witness, _) = EncodedChunkStateWitness::encode(&other_witness).unwrap();
+    let conflicting_full_witness = FullEncodedStateWitness::new(
+        EpochId::default(),
+        &other_witness.chunk_header,
+        encoded_witness,
+        &create_test_signer(setup.chunk_producer().as_str()),
+    );
+    let conflicts = |path: WitnessDecodePath| {
+        metrics::PARTIAL_WITNESS_DECODE_CONFLICTS.with_label_values(&["0", path.as_str()]).get()
+    };
+
+    // The witness reconstructed from the parts is sent to the client, the full witness arriving
+    // later is reported instead of replacing it.
+    let conflicts_before = conflicts(WitnessDecodePath::FullWitness);
+    let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.send(FullEncodedStateWitnessMessage(conflicting_full_witness.clone()));
+    let witnesses = validator.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert_eq!(witnesses[0].witness.chunk_header.prev_block_hash(), &CryptoHash::default());
+    let conflict = validator.actor().decode_conflict(&key).unwrap();
+    assert_eq!(conflict.decoded.path, WitnessDecodePath::Parts);
+    assert_eq!(conflict.conflicting_path, WitnessDecodePath::FullWitness);
+    assert_eq!(conflict.conflicting_witness_hash, Some(CryptoHash::hash_borsh(&other_witness)));
+    assert_eq!(conflicts(WitnessDecodePath::FullWitness), conflicts_before + 1);
+
+    // The full witness is decoded first, the parts arriving later carry the hash of a different
+    // witness.
+    let conflicts_before = conflicts(WitnessDecodePath::Parts);
+    let mut validator = setup.driver(&setup.validator(1), PartialWitnessConfig::default());
+    validator.send(FullEncodedStateWitnessMessage(conflicting_full_witness));
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    let conflict = validator.actor().decode_conflict(&key).unwrap();
+    assert_eq!(conflict.decoded.path, WitnessDecodePath::FullWitness);
+    assert_eq!(conflict.conflicting_path, WitnessDecodePath::Parts);
+    assert_eq!(conflicts(WitnessDecodePath::Parts), conflicts_before + 1);
+}
+
+/// Before `ProtocolFeature::DirectFullWitness`, the chunk producer doesn't send the full witness,
+/// and the full witnesses are rejected.
+#[test]
+fn full_witness_is_not_used_before_the_feature() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let (target, full_witness) = setup.produce_full_witness();
+    let version = ProtocolFeature::DirectFullWitness.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+
+    let config =
+        PartialWitnessConfig { direct_full_witness_targets: 1, ..PartialWitnessConfig::default() };
+    let mut producer = setup.driver(&setup.chunk_producer(), config);
+    setup.distribute_witness(&mut producer);
+    let requests = producer.take_network_requests();
+    assert!(requests
+        .iter()
+        .any(|request| matches!(request, NetworkRequests::PartialEncodedStateWitness(..))));
+    assert!(requests
+        .iter()
+        .all(|request| !matches!(request, NetworkRequests::FullEncodedStateWitness(..))));
+
+    let full_witness_errors = || {
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&["full_witness", "0", "invalid_chunk_state_witness"])
+            .get()
+    };
+    let full_witness_errors_before = full_witness_errors();
+    let mut validator = setup.driver(&target, PartialWitnessConfig::default());
+    validator.send(FullEncodedStateWitnessMessage(full_witness));
+    assert!(full_witness_errors() > full_witness_errors_before);
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+#[test]
+fn full_witness_failing_to_decode_does_not_stop_reconstruction() {
+    let setup = Setup::new();
+    setup.enable_full_witness();
+    let parts = setup.produce_parts();
+    let witness = ChunkStateWitness::new_dummy(HEIGHT, 0, CryptoHash::default());
+    let corrupted_full_witness = FullEncodedStateWitness::new(
+        EpochId::default(),
+        &witness.chunk_header,
+        EncodedChunkStateWitness::from_boxed_slice(vec![0xff; 100].into_boxed_slice()),
+        &create_test_signer(setup.chunk_producer().as_str()),
+    );
+
+    let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+    validator.send(FullEncodedStateWitnessMessage(corrupted_full_witness));
+    assert!(validator.take_client_witnesses().is_empty());
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+}
+
+#[test]
+fn distribution_uses_epoch_of_chunk_at_epoch_boundary() {
+    // With the epoch length of 1, the chunks built on the first block after genesis are already
+    // in the next epoch.
+    let setup = Setup::with_epoch_length(1);
+    let (genesis, block) = setup.save_blocks();
+    let block_epoch_id = setup.epoch_manager.get_epoch_id_from_prev_block(genesis.hash()).unwrap();
+    let chunk_epoch_id = setup.epoch_manager.get_epoch_id_from_prev_block(block.hash()).unwrap();
+    assert_ne!(block_epoch_id, chunk_epoch_id);
+    let height = block.header().height() + 1;
+    let chunk_producer =
+        setup.epoch_manager.get_chunk_producer(&chunk_epoch_id, height, 0).unwrap();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    let mismatches =
+        || metrics::PARTIAL_WITNESS_DISTRIBUTION_EPOCH_MISMATCHES.with_label_values(&["0"]).get();
+    let parts_epoch_ids = |producer: &PartialWitnessTestDriver| {
+        producer
+            .take_network_requests()
+            .into_iter()
+            .find_map(|request| match request {
+                NetworkRequests::PartialEncodedStateWitness(parts, _) => Some(
+                    parts
+                        .into_iter()
+                        .map(|(_, partial_witness)| partial_witness.chunk_production_key().epoch_id)
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    // The client uses the epoch of the prev block instead of the epoch of the chunk.
+    let mismatches_before = mismatches();
+    setup.distribute_witness_on(&mut producer, block_epoch_id, height, *block.hash());
+    assert_eq!(mismatches(), mismatches_before + 1);
+    let epoch_ids = parts_epoch_ids(&producer);
+    assert!(!epoch_ids.is_empty());
+    assert!(epoch_ids.iter().all(|epoch_id| epoch_id == &chunk_epoch_id));
+
+    let mismatches_before = mismatches();
+    setup.distribute_witness_on(&mut producer, chunk_epoch_id, height, *block.hash());
+    assert_eq!(mismatches(), mismatches_before);
+    assert!(parts_epoch_ids(&producer).iter().all(|epoch_id| epoch_id == &chunk_epoch_id));
+}
+
+#[test]
+fn incomplete_witness_is_decoded_after_restore() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&parts, &validator_id).clone()));
+    assert!(validator.take_client_witnesses().is_empty());
+
+    let snapshot = borsh::to_vec(&validator.actor().snapshot()).unwrap();
+    drop(validator);
+    let mut restarted = setup.driver(&validator_id, PartialWitnessConfig::default());
+    restarted.actor_mut().restore(borsh::from_slice::<PartialWitnessState>(&snapshot).unwrap());
+
+    for partial_witness in &parts {
+        if owner_of(partial_witness) != validator_id {
+            restarted.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    let witnesses = restarted.take_client_witnesses();
+    assert_eq!(witnesses.len(), 1);
+    assert_eq!(witnesses[0].witness.chunk_production_key().height_created, HEIGHT);
+}
+
+#[test]
+fn produced_parts_are_served_after_restore() {
+    let setup = Setup::new();
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessRequests.protocol_version());
+    let chunk_producer = setup.chunk_producer();
+    let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    setup.distribute_witness(&mut producer);
+    let snapshot = producer.actor().snapshot();
+    drop(producer);
+
+    let mut restarted = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+    restarted.actor_mut().restore(snapshot);
+    let part_ord = VALIDATORS.iter().position(|v| *v == setup.validator(1).as_str()).unwrap();
+    restarted.send(PartialEncodedStateWitnessRequestMessage(
+        PartialEncodedStateWitnessRequest {
+            requester: setup.validator(0),
+            epoch_id: EpochId::default(),
+            shard_id: 0,
+            height_created: HEIGHT,
+            part_ord,
+        },
+        Some(setup.validator(0)),
+    ));
+    assert_eq!(
+        forwards(&restarted.take_network_requests()),
+        vec![(vec![setup.validator(0)], part_ord)]
+    );
+}
+
+#[test]
+fn witnesses_completed_together_are_sent_newest_first() {
+    let setup = Setup::new();
+    let heights = [HEIGHT, HEIGHT + 1, HEIGHT + 2];
+    let parts = heights.map(|height| setup.produce_parts_at(height));
+    let window = Duration::milliseconds(50);
+    let config = PartialWitnessConfig { decode_batch_window: window, ..Default::default() };
+    let mut validator = setup.driver(&setup.validator(0), config);
+
+    // The parts of the oldest witness arrive first, as after a network blip.
+    for partial_witness in parts.iter().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+    validator.advance(window);
+    let decoded_heights = validator
+        .take_client_witnesses()
+        .iter()
+        .map(|msg| msg.witness.chunk_production_key().height_created)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded_heights, vec![HEIGHT + 2, HEIGHT + 1, HEIGHT]);
+}
+
+#[test]
+fn witnesses_decoded_in_parallel_are_sent_newest_first() {
+    let setup = Setup::new();
+    let heights = [HEIGHT, HEIGHT + 1, HEIGHT + 2];
+    let parts = heights.map(|height| setup.produce_parts_at(height));
+    let window = Duration::milliseconds(50);
+    let config = PartialWitnessConfig {
+        decode_batch_window: window,
+        max_concurrent_witness_decodes: 2,
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    for partial_witness in parts.iter().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.advance(window);
+    let decoded_heights = validator
+        .take_client_witnesses()
+        .iter()
+        .map(|msg| msg.witness.chunk_production_key().height_created)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded_heights, vec![HEIGHT + 2, HEIGHT + 1, HEIGHT]);
+}
+
+#[test]
+fn forwards_interleave_with_slow_decodes() {
+    let setup = Setup::new();
+    let heights = [HEIGHT, HEIGHT + 1, HEIGHT + 2];
+    let parts = heights.map(|height| setup.produce_parts_at(height));
+    let validator_id = setup.validator(0);
+    // The next witness is produced by another chunk validator, so that we receive our part.
+    let next_height =
+        (HEIGHT + 3..).find(|height| setup.chunk_producer_at(*height) != validator_id).unwrap();
+    let next_parts = setup.produce_parts_at(next_height);
+    let window = Duration::milliseconds(50);
+    let config = PartialWitnessConfig {
+        decode_batch_window: window,
+        max_concurrent_witness_decodes: 1,
+        processing_time_slice: Duration::milliseconds(5),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&validator_id, config);
+    validator.actor_mut().slow_down_decodes(setup.clock.clone(), Duration::milliseconds(10));
+    for partial_witness in parts.iter().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.take_network_requests();
+    let decoded_heights = |validator: &PartialWitnessTestDriver| {
+        validator
+            .take_client_witnesses()
+            .iter()
+            .map(|msg| msg.witness.chunk_production_key().height_created)
+            .collect::<Vec<_>>()
+    };
+
+    // The first decode uses up the time slice, the other witnesses wait in the mailbox.
+    validator.advance(window);
+    assert_eq!(decoded_heights(&validator), vec![HEIGHT + 2]);
+    assert!(validator.pending_delayed_actions().contains(&"continue_decode_ready_witnesses"));
+    // Our part of the next witness is forwarded right away rather than after the decodes.
+    validator.send(PartialEncodedStateWitnessMessage(part_of(&next_parts, &validator_id).clone()));
+    assert_eq!(forwards(&validator.take_network_requests()).len(), 1);
+    assert!(decoded_heights(&validator).is_empty());
+
+    validator.advance(Duration::ZERO);
+    assert_eq!(decoded_heights(&validator), vec![HEIGHT + 1]);
+    validator.advance(Duration::ZERO);
+    assert_eq!(decoded_heights(&validator), vec![HEIGHT]);
+    assert!(!validator.pending_delayed_actions().contains(&"continue_decode_ready_witnesses"));
+}
+
+#[test]
+fn witness_ready_past_deadline_is_not_decoded() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let config = PartialWitnessConfig {
+        incomplete_witness_deadline: Duration::milliseconds(100),
+        decode_batch_window: Duration::milliseconds(200),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.advance(Duration::milliseconds(200));
+    assert!(validator.take_client_witnesses().is_empty());
+}
+
+fn num_prioritizations(lane: &str) -> u64 {
+    metrics::PARTIAL_WITNESS_PRIORITIZATIONS.with_label_values(&[lane, "reordered"]).get()
+}
+
+#[test]
+fn prioritized_witness_jumps_the_decode_queue() {
+    let setup = Setup::new();
+    let heights = [HEIGHT, HEIGHT + 1, HEIGHT + 2];
+    let parts = heights.map(|height| setup.produce_parts_at(height));
+    let config = PartialWitnessConfig {
+        decode_batch_window: Duration::milliseconds(50),
+        ..Default::default()
+    };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    let reordered_before = num_prioritizations("decode_order");
+    let jumped_before = num_prioritizations("decode_batch_window");
+
+    // The client is blocked on the oldest witness, whose parts arrive last. Once it is complete,
+    // it doesn't wait for the batch window and is decoded before the newer witnesses.
+    validator.send(PrioritizeWitness(parts[0][0].chunk_production_key()));
+    for partial_witness in parts.iter().rev().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    let decoded_heights = validator
+        .take_client_witnesses()
+        .iter()
+        .map(|msg| msg.witness.chunk_production_key().height_created)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded_heights, vec![HEIGHT, HEIGHT + 2, HEIGHT + 1]);
+    assert_eq!(num_prioritizations("decode_order"), reordered_before + 1);
+    assert_eq!(num_prioritizations("decode_batch_window"), jumped_before + 1);
+}
+
+#[test]
+fn witness_prioritization_expires() {
+    let setup = Setup::new();
+    let heights = [HEIGHT, HEIGHT + 1, HEIGHT + 2];
+    let parts = heights.map(|height| setup.produce_parts_at(height));
+    let window = Duration::milliseconds(50);
+    let config = PartialWitnessConfig { decode_batch_window: window, ..Default::default() };
+    let mut validator = setup.driver(&setup.validator(0), config);
+
+    validator.send(PrioritizeWitness(parts[0][0].chunk_production_key()));
+    validator.advance(Duration::seconds(1));
+    for partial_witness in parts.iter().rev().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert!(validator.take_client_witnesses().is_empty());
+    validator.advance(window);
+    let decoded_heights = validator
+        .take_client_witnesses()
+        .iter()
+        .map(|msg| msg.witness.chunk_production_key().height_created)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded_heights, vec![HEIGHT + 2, HEIGHT + 1, HEIGHT]);
+}
+
+#[test]
+fn parts_of_prioritized_witness_are_handled_first_after_sync() {
+    let setup = Setup::new();
+    let parts = [HEIGHT + 1, HEIGHT].map(|height| setup.produce_parts_at(height));
+    let config = PartialWitnessConfig { max_parts_buffered_during_sync: 10, ..Default::default() };
+    let mut validator = setup.driver(&setup.validator(0), config);
+    let reordered_before = num_prioritizations("sync_buffer");
+
+    validator.send(SyncStatusChangedMessage { syncing: true });
+    for partial_witness in parts.iter().flatten() {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    validator.send(PrioritizeWitness(parts[1][0].chunk_production_key()));
+    validator.send(SyncStatusChangedMessage { syncing: false });
+
+    assert_eq!(num_prioritizations("sync_buffer"), reordered_before + 1);
+    let decoded_heights = validator
+        .take_client_witnesses()
+        .iter()
+        .map(|msg| msg.witness.chunk_production_key().height_created)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded_heights, vec![HEIGHT, HEIGHT + 1]);
+}
+
+#[test]
+fn parts_of_several_shards_are_sent_interleaved() {
+    let setup = Setup::with_shards(100, 2);
+    // The mock epoch manager rotates the chunk producers by shard and height, so the producer of
+    // shard 0 at `HEIGHT` also produces shard 1 one height below.
+    let producer_id = setup.chunk_producer();
+    assert_eq!(
+        setup.epoch_manager.get_chunk_producer(&EpochId::default(), HEIGHT - 1, 1).unwrap(),
+        producer_id
+    );
+    let window = Duration::milliseconds(10);
+    let config = PartialWitnessConfig { part_send_window: window, ..Default::default() };
+    let mut producer = setup.driver(&producer_id, config);
+    for (shard_id, height) in [(1, HEIGHT - 1), (0, HEIGHT)] {
+        let witness = ChunkStateWitness::new_dummy(height, shard_id, CryptoHash::default());
+        producer.send(DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            setup.clock.now(),
+        ));
+    }
+    let sent_shards = |requests: Vec<NetworkRequests>| {
+        requests
+            .into_iter()
+            .filter_map(|request| match request {
+                NetworkRequests::PartialEncodedStateWitness(parts, _) => Some(
+                    parts
+                        .iter()
+                        .map(|(_, partial_witness)| partial_witness.chunk_production_key().shard_id)
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    // Only the forwards of our own parts leave before the window passes.
+    assert!(sent_shards(producer.take_network_requests()).is_empty());
+
+    producer.advance(window);
+    let parts_to_owners = VALIDATORS.len() - 1;
+    let expected = (0..2 * parts_to_owners).map(|i| vec![[1, 0][i % 2]]).collect::<Vec<_>>();
+    assert_eq!(sent_shards(producer.take_network_requests()), expected);
+}
+
+#[test]
+fn epoch_witness_stats_are_persisted_once_the_head_leaves_the_epoch() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    update_head(&setup, &mut validator, tip_at(HEIGHT - 1, b""), None);
+    for partial_witness in &parts {
+        validator.send(forward_from_owner(partial_witness.clone()));
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+    assert!(load_epoch_witness_stats(validator.store()).unwrap().is_empty());
+
+    let next_epoch_id = EpochId(CryptoHash::hash_bytes(b"next"));
+    let head = Tip { epoch_id: next_epoch_id, ..tip_at(HEIGHT + 1, b"") };
+    update_head(&setup, &mut validator, head, None);
+    let saved = load_epoch_witness_stats(validator.store()).unwrap();
+    assert_eq!(saved.len(), 1);
+    let VersionedEpochWitnessStats::V1(stats) = &saved[0];
+    assert_eq!(stats.epoch_id, EpochId::default());
+    assert_eq!((stats.first_head_height, stats.last_head_height), (HEIGHT - 1, HEIGHT - 1));
+    assert_eq!(stats.shards.len(), 1);
+    assert_eq!(stats.shards[0].witnesses_expected, 1);
+    assert_eq!(stats.shards[0].decoded_on_time, 1);
+    assert_eq!(stats.shards[0].never_decoded, 0);
+}
+
+#[test]
+fn stateless_validation_health_follows_the_head_and_the_parts_cache() {
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let health_config = StatelessValidationHealthConfig {
+        max_head_age: Duration::seconds(10),
+        max_parts_cache_size: ByteSize::b(0),
+        ..Default::default()
+    };
+    let config = PartialWitnessConfig { health: health_config, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    // The head isn't known yet, so neither is its age nor the assignments at it.
+    let health = validator.actor().stateless_validation_health();
+    assert!(!health.is_check_healthy(HealthCheck::HeadFresh));
+    assert!(!health.is_check_healthy(HealthCheck::AssignmentsResolvable));
+
+    let (_, block) = setup.save_blocks();
+    let mut store_update = validator.store().store_update();
+    store_update.set_ser(DBCol::BlockHeader, block.hash().as_ref(), block.header()).unwrap();
+    store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, &Tip::from_header(block.header())).unwrap();
+    store_update.commit().unwrap();
+    let health = validator.actor().stateless_validation_health();
+    assert!(health.is_healthy(), "{:?}", health.failed_checks());
+
+    // An incomplete witness retains more than the budget.
+    validator.send(forward_from_owner(parts[0].clone()));
+    let health = validator.actor().stateless_validation_health();
+    assert_eq!(health.failed_checks(), vec![HealthCheck::PartsCacheWithinBudget.as_str()]);
+
+    // No block arrives for longer than the head is expected to be fresh.
+    setup.clock.advance(Duration::seconds(11));
+    let health = validator.actor().stateless_validation_health();
+    assert!(!health.is_check_healthy(HealthCheck::HeadFresh));
+    assert!(health.is_check_healthy(HealthCheck::SignerPresent));
+}
+
+#[test]
+fn witness_config_is_published_again_when_the_protocol_limits_change() {
+    let setup = Setup::new();
+    let increase = ProtocolFeature::WitnessSizeLimitIncrease.protocol_version();
+    setup.epoch_manager.set_protocol_version(increase - 1);
+    let validator_id = setup.validator(0);
+    let config = PartialWitnessConfig { direct_routing_targets: 3, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+    // Nothing is published until the head is known.
+    assert!(validator.take_witness_configs().is_empty());
+
+    update_head(&setup, &mut validator, tip_at(HEIGHT - 1, b""), None);
+    update_head(&setup, &mut validator, tip_at(HEIGHT, b""), None);
+    let configs = validator.take_witness_configs();
+    assert_eq!(configs.len(), 1);
+    assert_eq!(configs[0].partial_witness["direct_routing_targets"], 3);
+    assert_eq!(configs[0].protocol_limits.protocol_version, increase - 1);
+    assert!(configs[0].protocol_limits.witness_size_grace_percent > 0);
+
+    setup.epoch_manager.set_protocol_version(increase);
+    let next_epoch_id = EpochId(CryptoHash::hash_bytes(b"next"));
+    let head = Tip { epoch_id: next_epoch_id, ..tip_at(HEIGHT + 1, b"") };
+    update_head(&setup, &mut validator, head, None);
+    let configs = validator.take_witness_configs();
+    assert_eq!(configs.len(), 1);
+    assert_eq!(configs[0].protocol_limits.protocol_version, increase);
+    assert_eq!(configs[0].protocol_limits.witness_size_grace_percent, 0);
+}
+
+/// Heights of `count` witnesses the validator is a chunk validator but not the chunk producer of.
+fn heights_validated_by(setup: &Setup, validator_id: &AccountId, count: usize) -> Vec<BlockHeight> {
+    (HEIGHT..HEIGHT + 20)
+        .filter(|height| &setup.chunk_producer_at(*height) != validator_id)
+        .take(count)
+        .collect()
+}
+
+fn deliver_witness_at(
+    setup: &Setup,
+    validator: &mut PartialWitnessTestDriver,
+    height: BlockHeight,
+) {
+    for partial_witness in setup.produce_parts_at(height) {
+        validator.send(forward_from_owner(partial_witness));
+    }
+}
+
+fn client_witness_heights(validator: &PartialWitnessTestDriver) -> Vec<BlockHeight> {
+    let witnesses = validator.take_client_witnesses();
+    witnesses.iter().map(|msg| msg.witness.chunk_production_key().height_created).collect()
+}
+
+fn consume(validator: &mut PartialWitnessTestDriver, height: BlockHeight) {
+    let key =
+        ChunkProductionKey { epoch_id: EpochId::default(), shard_id: 0, height_created: height };
+    validator.send(ChunkStateWitnessConsumedMessage { key });
+}
+
+#[test]
+fn held_witnesses_are_pushed_as_credits_return_or_pulled() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let [h0, h1, h2, h3]: [BlockHeight; 4] =
+        heights_validated_by(&setup, &validator_id, 4).try_into().unwrap();
+    let config = PartialWitnessConfig { client_witness_credits: Some(1), ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    // The first witness takes the only credit, the next ones are held.
+    deliver_witness_at(&setup, &mut validator, h0);
+    assert_eq!(client_witness_heights(&validator), vec![h0]);
+    for height in [h1, h2, h3] {
+        deliver_witness_at(&setup, &mut validator, height);
+    }
+    assert!(client_witness_heights(&validator).is_empty());
+
+    // The client pulls the highest one, which fits in the cap, without returning the credit.
+    assert!(validator.send(RequestNextDecodedWitness { max_bytes: 0 }).is_none());
+    let pulled = validator.send(RequestNextDecodedWitness { max_bytes: usize::MAX }).unwrap();
+    assert_eq!(pulled.witness.chunk_production_key().height_created, h3);
+    consume(&mut validator, h3);
+    assert!(client_witness_heights(&validator).is_empty());
+
+    // Consuming the pushed witness returns the credit, taken by the highest held witness.
+    consume(&mut validator, h0);
+    assert_eq!(client_witness_heights(&validator), vec![h2]);
+    let pulled = validator.send(RequestNextDecodedWitness { max_bytes: usize::MAX }).unwrap();
+    assert_eq!(pulled.witness.chunk_production_key().height_created, h1);
+    assert!(validator.send(RequestNextDecodedWitness { max_bytes: usize::MAX }).is_none());
+
+    // Every witness reached the client exactly once, the late parts don't deliver them again.
+    consume(&mut validator, h2);
+    for height in [h0, h1, h2, h3] {
+        deliver_witness_at(&setup, &mut validator, height);
+    }
+    assert!(client_witness_heights(&validator).is_empty());
+    assert!(validator.send(RequestNextDecodedWitness { max_bytes: usize::MAX }).is_none());
+}
+
+#[test]
+fn credit_of_witness_never_consumed_is_returned() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let [h0, h1]: [BlockHeight; 2] =
+        heights_validated_by(&setup, &validator_id, 2).try_into().unwrap();
+    let config = PartialWitnessConfig { client_witness_credits: Some(1), ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+
+    deliver_witness_at(&setup, &mut validator, h0);
+    deliver_witness_at(&setup, &mut validator, h1);
+    assert_eq!(client_witness_heights(&validator), vec![h0]);
+    // The client never confirms consuming the first witness.
+    validator.advance(Duration::seconds(30));
+    assert_eq!(client_witness_heights(&validator), vec![h1]);
+}
+
+#[cfg(feature = "test_features")]
+mod adversarial_producer {
+    use near_chain_configs::PartialWitnessConfig;
+    use near_network::types::NetworkRequests;
+    use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+    use near_primitives::stateless_validation::ChunkProductionKey;
+    use near_primitives::types::AccountId;
+
+    use super::{forward_from_owner, part_of, Setup, HEIGHT, VALIDATORS};
+    use crate::metrics;
+    use crate::stateless_validation::partial_witness::{
+        AdvWitnessPartsMessage, AdvWitnessPartsMode, ForceRedistributeWitness,
+    };
+
+    /// Distributes the witness from the chunk producer simulating `mode`, and returns the parts
+    /// sent by the chunk producer along with their targets.
+    fn produce_adversarial_parts(
+        setup: &Setup,
+        mode: AdvWitnessPartsMode,
+    ) -> Vec<(Vec<AccountId>, PartialEncodedStateWitness)> {
+        let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+        producer.send(AdvWitnessPartsMessage(mode));
+        setup.distribute_witness(&mut producer);
+        sent_parts(producer.take_network_requests())
+    }
+
+    /// Parts sent in the requests along with their targets.
+    fn sent_parts(
+        requests: Vec<NetworkRequests>,
+    ) -> Vec<(Vec<AccountId>, PartialEncodedStateWitness)> {
+        let mut parts = vec![];
+        for request in requests {
+            match request {
+                NetworkRequests::PartialEncodedStateWitness(owned_parts, _) => parts.extend(
+                    owned_parts
+                        .into_iter()
+                        .map(|(owner, partial_witness)| (vec![owner], partial_witness)),
+                ),
+                NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, _) => {
+                    parts.push((targets, partial_witness))
+                }
+                _ => {}
+            }
+        }
+        parts
+    }
+
+    fn parts_by_ord(
+        parts: &[(Vec<AccountId>, PartialEncodedStateWitness)],
+    ) -> Vec<PartialEncodedStateWitness> {
+        let mut parts_by_ord =
+            parts.iter().map(|(_, partial_witness)| partial_witness.clone()).collect::<Vec<_>>();
+        parts_by_ord.sort_by_key(|partial_witness| partial_witness.part_ord());
+        parts_by_ord
+    }
+
+    #[test]
+    fn corrupt_part_fails_the_first_decode_attempt() {
+        let setup = Setup::new();
+        let parts =
+            parts_by_ord(&produce_adversarial_parts(&setup, AdvWitnessPartsMode::CorruptPart(0)));
+        assert_eq!(parts.len(), VALIDATORS.len());
+        assert_ne!(parts[0].part(), setup.produce_parts()[0].part());
+        let decoded_after_retry =
+            || metrics::PARTIAL_WITNESS_DECODED_AFTER_RETRY.with_label_values(&["0"]).get();
+        let decode_attempts =
+            || metrics::PARTIAL_WITNESS_DECODE_ATTEMPTS.with_label_values(&["0"]).get_sample_sum();
+        let decoded_after_retry_before = decoded_after_retry();
+        let decode_attempts_before = decode_attempts();
+
+        // The first two parts are the data parts, enough to attempt the decode.
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        for partial_witness in &parts[..2] {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+        assert!(validator.take_client_witnesses().is_empty());
+        // More parts may still arrive, so the failed attempt isn't final.
+        let health = validator.actor().producer_distribution_health();
+        assert_eq!(health[0].decode_failures, 0);
+
+        // The next part allows to decode the witness without the corrupted part.
+        validator.send(forward_from_owner(parts[2].clone()));
+        assert_eq!(validator.take_client_witnesses().len(), 1);
+        let corrupted_part = validator.actor().corrupted_part(&parts[0].chunk_production_key());
+        assert_eq!(corrupted_part.map(|corrupted_part| corrupted_part.part_ord), Some(0));
+        assert_eq!(decoded_after_retry(), decoded_after_retry_before + 1);
+        assert_eq!(decode_attempts(), decode_attempts_before + 2.0);
+
+        // With all the parts received, a failed attempt is final.
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        let mut corrupted_parts = parts.clone();
+        corrupted_parts[1] =
+            parts_by_ord(&produce_adversarial_parts(&setup, AdvWitnessPartsMode::CorruptPart(1)))
+                [1]
+            .clone();
+        for partial_witness in &corrupted_parts {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+        assert!(validator.take_client_witnesses().is_empty());
+        let health = validator.actor().producer_distribution_health();
+        assert_eq!(health[0].decode_failures, 1);
+    }
+
+    #[test]
+    fn forced_redistribution_resends_the_retained_parts() {
+        let setup = Setup::new();
+        let chunk_producer = setup.chunk_producer();
+        let mut producer = setup.driver(&chunk_producer, PartialWitnessConfig::default());
+        setup.distribute_witness(&mut producer);
+        let parts = parts_by_ord(&sent_parts(producer.take_network_requests()));
+        let key = parts[0].chunk_production_key();
+
+        let num_parts =
+            producer.send(ForceRedistributeWitness { chunk_production_key: key.clone() }).unwrap();
+        assert_eq!(num_parts, VALIDATORS.len());
+        let resent_parts = sent_parts(producer.take_network_requests());
+        assert_eq!(parts_by_ord(&resent_parts), parts);
+        // Every part goes to its owner, and our own part to all the other chunk validators.
+        for (targets, partial_witness) in &resent_parts {
+            if owner_of(partial_witness) == chunk_producer {
+                assert_eq!(targets.len(), VALIDATORS.len() - 1);
+                assert!(!targets.contains(&chunk_producer));
+            } else {
+                assert_eq!(targets, &vec![owner_of(partial_witness)]);
+            }
+        }
+
+        // The parts of a witness the producer doesn't retain can't be sent.
+        let missing_key = ChunkProductionKey { height_created: HEIGHT + 1, ..key };
+        let err = producer
+            .send(ForceRedistributeWitness { chunk_production_key: missing_key })
+            .unwrap_err();
+        assert!(err.contains("cache miss"), "{}", err);
+        assert!(producer.take_network_requests().is_empty());
+    }
+
+    #[test]
+    fn wrong_length_fails_the_decode() {
+        let setup = Setup::new();
+        let parts =
+            parts_by_ord(&produce_adversarial_parts(&setup, AdvWitnessPartsMode::WrongLength(-1)));
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        for partial_witness in &parts {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+        assert!(validator.take_client_witnesses().is_empty());
+        let health = validator.actor().producer_distribution_health();
+        assert_eq!(health[0].decode_failures, 1);
+    }
+
+    #[test]
+    fn witness_is_decoded_from_parity_parts_when_data_parts_are_withheld() {
+        let setup = Setup::new();
+        let parts = parts_by_ord(&produce_adversarial_parts(
+            &setup,
+            AdvWitnessPartsMode::Withhold(vec![0, 1]),
+        ));
+        let part_ords =
+            parts.iter().map(|partial_witness| partial_witness.part_ord()).collect::<Vec<_>>();
+        assert_eq!(part_ords, vec![2, 3]);
+
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        for partial_witness in &parts {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+        assert_eq!(validator.take_client_witnesses().len(), 1);
+    }
+
+    #[test]
+    fn equivocated_part_is_rejected() {
+        let setup = Setup::new();
+        let owner = setup.validator(1);
+        let equivocated_ord = part_of(&setup.produce_parts(), &owner).part_ord();
+        let parts =
+            produce_adversarial_parts(&setup, AdvWitnessPartsMode::Equivocate(equivocated_ord));
+        let (honest_part, conflicting_part) = {
+            let mut equivocated = parts
+                .iter()
+                .filter(|(_, partial_witness)| partial_witness.part_ord() == equivocated_ord);
+            let (honest_targets, honest_part) = equivocated.next().unwrap();
+            let (conflicting_targets, conflicting_part) = equivocated.next().unwrap();
+            assert_eq!(honest_targets, &vec![owner.clone()]);
+            assert!(!conflicting_targets.contains(&owner));
+            assert!(conflicting_targets.contains(&setup.validator(0)));
+            (honest_part.clone(), conflicting_part.clone())
+        };
+        let rejected_parts = || {
+            metrics::PARTIAL_WITNESS_ERRORS
+                .with_label_values(&["forwarded_part", "0", "invalid_partial_chunk_state_witness"])
+                .get()
+        };
+        let rejected_parts_before = rejected_parts();
+
+        // The owner forwards the honest part, the conflicting part is sent by the chunk producer.
+        let mut validator = setup.driver(&setup.validator(0), PartialWitnessConfig::default());
+        validator.send(forward_from_owner(honest_part));
+        validator.send(forward_from_owner(conflicting_part));
+        assert!(rejected_parts() > rejected_parts_before);
+
+        for partial_witness in setup.produce_parts() {
+            if partial_witness.part_ord() != equivocated_ord {
+                validator.send(forward_from_owner(partial_witness));
+            }
+        }
+        assert_eq!(validator.take_client_witnesses().len(), 1);
+    }
+}
+
+/// Makes the tracker of a chunk validator panic on its part, and returns whether the node was
+/// asked to shut down.
+fn check_tracker_panic(policy: HandlerPanicPolicy) -> bool {
+    install_handler_panic_hook();
+    let setup = Setup::new();
+    let parts = setup.produce_parts();
+    let validator_id = setup.validator(0);
+    let part = part_of(&parts, &validator_id).clone();
+    let config = PartialWitnessConfig { handler_panic_policy: policy, ..Default::default() };
+    let mut validator = setup.driver(&validator_id, config);
+    let (shutdown_signal, mut shutdown_receiver) = tokio::sync::broadcast::channel(1);
+    validator.actor_mut().set_shutdown_signal(shutdown_signal);
+    let panics = || {
+        metrics::PARTIAL_WITNESS_HANDLER_PANICS
+            .with_label_values(&[std::any::type_name::<PartialEncodedStateWitnessMessage>()])
+            .get()
+    };
+    let panics_before = panics();
+
+    validator.actor_mut().panic_on_next_part();
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        validator.send(PartialEncodedStateWitnessMessage(part.clone()))
+    }));
+    assert!(result.is_err());
+    let report = validator.actor().last_handler_panic().unwrap();
+    assert!(report.message_type.ends_with("PartialEncodedStateWitnessMessage"));
+    assert_eq!(report.key, Some(part.chunk_production_key()));
+    assert_eq!(report.panic_message, "injected tracker panic");
+    assert!(report.backtrace.is_some());
+    assert_eq!(panics(), panics_before + 1);
+    shutdown_receiver.try_recv().is_ok()
+}
+
+#[test]
+fn tracker_panic_shuts_down_the_node() {
+    assert!(check_tracker_panic(HandlerPanicPolicy::Shutdown));
+}
+
+#[test]
+fn tracker_panic_is_propagated() {
+    assert!(!check_tracker_panic(HandlerPanicPolicy::Propagate));
+}
+
+/// Max payload of a routed message below the size of the parts of the dummy witness.
+const SMALL_ROUTED_MESSAGE_PAYLOAD: usize = 256;
+
+/// Distributes the witness from a chunk producer whose parts exceed the max payload of a routed
+/// message, and returns the fragments sent to the validator, all of them from the chunk producer.
+/// The fragments are enabled from then on.
+fn produce_fragments_for(
+    setup: &Setup,
+    validator_id: &AccountId,
+) -> Vec<PartialEncodedStateWitnessFragment> {
+    setup
+        .epoch_manager
+        .set_protocol_version(ProtocolFeature::PartialWitnessFragments.protocol_version());
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    producer.actor_mut().set_max_routed_message_payload(SMALL_ROUTED_MESSAGE_PAYLOAD);
+    setup.distribute_witness(&mut producer);
+    let requests = producer.take_network_requests();
+    // No part exceeding the max payload is sent whole.
+    assert!(requests.iter().all(|request| !matches!(
+        request,
+        NetworkRequests::PartialEncodedStateWitness(..)
+            | NetworkRequests::PartialEncodedStateWitnessForward(..)
+    )));
+    requests
+        .into_iter()
+        .filter_map(|request| match request {
+            NetworkRequests::PartialEncodedStateWitnessFragments(targets, fragments, _)
+                if targets.contains(validator_id) =>
+            {
+                Some(fragments)
+            }
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+fn fragment_from_producer(
+    setup: &Setup,
+    fragment: PartialEncodedStateWitnessFragment,
+) -> PartialEncodedStateWitnessFragmentMessage {
+    PartialEncodedStateWitnessFragmentMessage(fragment, peer_id_of(&setup.chunk_producer()))
+}
+
+#[test]
+fn oversized_parts_are_sent_as_fragments() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let fragmented_parts_before = metrics::PARTIAL_WITNESS_FRAGMENTED_PARTS.get();
+    let fragments = produce_fragments_for(&setup, &validator_id);
+    // Every part is fragmented, the owned ones and the forward of the chunk producer's own part.
+    assert!(
+        metrics::PARTIAL_WITNESS_FRAGMENTED_PARTS.get()
+            >= fragmented_parts_before + VALIDATORS.len() as u64
+    );
+    // The validator gets the fragments of its own part and of the chunk producer's part.
+    assert!(fragments.iter().any(|fragment| !fragment.forward));
+    assert!(fragments.iter().any(|fragment| fragment.forward));
+    assert!(fragments.iter().all(|fragment| fragment.bytes.len() <= SMALL_ROUTED_MESSAGE_PAYLOAD));
+
+    let parts = setup.produce_parts();
+    let own_part_ord = part_of(&parts, &validator_id).part_ord();
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    for fragment in fragments.into_iter().rev() {
+        validator.send(fragment_from_producer(&setup, fragment));
+    }
+    // The joined own part is handled as if it was received whole, so it is forwarded.
+    let forwards = forwards(&validator.take_network_requests());
+    assert!(forwards.iter().any(|(_, part_ord)| *part_ord == own_part_ord));
+    // Together with the joined part of the chunk producer, that is enough to decode the witness.
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+}
+
+/// Before `ProtocolFeature::PartialWitnessFragments`, the oversized parts are sent whole, and
+/// the fragments are rejected before they are buffered.
+#[test]
+fn fragments_are_not_used_before_the_feature() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let fragments = produce_fragments_for(&setup, &validator_id);
+    let version = ProtocolFeature::PartialWitnessFragments.protocol_version();
+    setup.epoch_manager.set_protocol_version(version - 1);
+
+    let mut producer = setup.driver(&setup.chunk_producer(), PartialWitnessConfig::default());
+    producer.actor_mut().set_max_routed_message_payload(SMALL_ROUTED_MESSAGE_PAYLOAD);
+    setup.distribute_witness(&mut producer);
+    let requests = producer.take_network_requests();
+    assert!(requests
+        .iter()
+        .any(|request| matches!(request, NetworkRequests::PartialEncodedStateWitness(..))));
+    assert!(requests.iter().all(|request| !matches!(
+        request,
+        NetworkRequests::PartialEncodedStateWitnessFragments(..)
+    )));
+
+    let fragment_errors = || {
+        metrics::PARTIAL_WITNESS_ERRORS
+            .with_label_values(&["part_fragment", "0", "invalid_partial_chunk_state_witness"])
+            .get()
+    };
+    let fragment_errors_before = fragment_errors();
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    validator.send(fragment_from_producer(&setup, fragments[0].clone()));
+    assert!(fragment_errors() > fragment_errors_before);
+    assert_eq!(validator.actor().pending_part_fragments(), 0);
+}
+
+#[test]
+fn part_losing_a_fragment_is_dropped() {
+    let setup = Setup::new();
+    let validator_id = setup.validator(0);
+    let fragments = produce_fragments_for(&setup, &validator_id)
+        .into_iter()
+        .filter(|fragment| !fragment.forward)
+        .collect::<Vec<_>>();
+    assert!(fragments.len() > 1);
+    let expired_parts =
+        || metrics::PARTIAL_WITNESS_DROPPED_FRAGMENTED_PARTS.with_label_values(&["expired"]).get();
+    let expired_parts_before = expired_parts();
+
+    let parts = setup.produce_parts();
+    let own_part_ord = part_of(&parts, &validator_id).part_ord();
+    let mut validator = setup.driver(&validator_id, PartialWitnessConfig::default());
+    let (lost_fragment, received_fragments) = fragments.split_first().unwrap();
+    for fragment in received_fragments {
+        validator.send(fragment_from_producer(&setup, fragment.clone()));
+    }
+    // The part isn't complete, so it isn't stored nor forwarded.
+    assert!(forwards(&validator.take_network_requests()).is_empty());
+
+    // The witness is decoded from the forwards of the other parts, as if the part was lost whole.
+    for partial_witness in &parts {
+        if partial_witness.part_ord() != own_part_ord {
+            validator.send(forward_from_owner(partial_witness.clone()));
+        }
+    }
+    assert_eq!(validator.take_client_witnesses().len(), 1);
+
+    // The lost fragment arriving after the TTL doesn't complete the part, which was dropped.
+    validator.advance(PART_FRAGMENTS_TTL);
+    validator.send(fragment_from_producer(&setup, lost_fragment.clone()));
+    assert!(expired_parts() > expired_parts_before);
+    assert!(forwards(&validator.take_network_requests()).is_empty());
+}
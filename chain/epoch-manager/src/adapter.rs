@@ -13,7 +13,9 @@ use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::stateless_validation::chunk_endorsement::{
     ChunkEndorsementV1, ChunkEndorsementV2,
 };
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness,
+};
 use near_primitives::stateless_validation::validator_assignment::ChunkValidatorAssignments;
 use near_primitives::stateless_validation::ChunkProductionKey;
 use near_primitives::types::validator_stake::ValidatorStake;
@@ -431,6 +433,11 @@ pub trait EpochManagerAdapter: Send + Sync {
         partial_witness: &PartialEncodedStateWitness,
     ) -> Result<bool, Error>;
 
+    fn verify_full_witness_signature(
+        &self,
+        full_witness: &FullEncodedStateWitness,
+    ) -> Result<bool, Error>;
+
     fn cares_about_shard_in_epoch(
         &self,
         epoch_id: EpochId,
@@ -1089,6 +1096,18 @@ impl EpochManagerAdapter for EpochManagerHandle {
         Ok(partial_witness.verify(chunk_producer.public_key()))
     }
 
+    fn verify_full_witness_signature(
+        &self,
+        full_witness: &FullEncodedStateWitness,
+    ) -> Result<bool, Error> {
+        let epoch_manager = self.read();
+        let ChunkProductionKey { shard_id, epoch_id, height_created } =
+            full_witness.chunk_production_key();
+        let chunk_producer =
+            epoch_manager.get_chunk_producer_info(&epoch_id, height_created, shard_id)?;
+        Ok(full_witness.verify(chunk_producer.public_key()))
+    }
+
     fn cares_about_shard_from_prev_block(
         &self,
         parent_hash: &CryptoHash,
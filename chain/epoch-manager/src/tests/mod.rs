@@ -3179,7 +3179,7 @@ fn test_verify_partial_witness_signature() {
         epoch_id,
         chunk_header.clone(),
         0,
-        "witness".bytes().collect(),
+        b"witness".to_vec(),
         7,
         signer.as_ref(),
     );
@@ -3195,7 +3195,7 @@ fn test_verify_partial_witness_signature() {
         epoch_id,
         chunk_header,
         0,
-        "witness".bytes().collect(),
+        b"witness".to_vec(),
         7,
         bad_signer.as_ref(),
     );
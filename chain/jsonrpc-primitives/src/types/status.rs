@@ -58,6 +58,8 @@ pub enum RpcStatusError {
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
+    #[error("Node can't perform its chunk validator duties, failed checks: {failed_checks:?}")]
+    StatelessValidationUnhealthy { failed_checks: Vec<String> },
 }
 
 impl From<RpcStatusError> for crate::errors::RpcError {
@@ -61,6 +61,8 @@ pub fn start_all_with_validity_period(
         noop().into_multi_sender(),
         #[cfg(feature = "test_features")]
         noop().into_multi_sender(),
+        #[cfg(feature = "test_features")]
+        noop().into_multi_sender(),
         Arc::new(DummyEntityDebugHandler {}),
     );
     (actor_handles.view_client_actor, addr)
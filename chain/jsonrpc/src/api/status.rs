@@ -91,6 +91,9 @@ impl RpcFrom<StatusError> for RpcStatusError {
             StatusError::NodeIsSyncing => Self::NodeIsSyncing,
             StatusError::NoNewBlocks { elapsed } => Self::NoNewBlocks { elapsed },
             StatusError::EpochOutOfBounds { epoch_id } => Self::EpochOutOfBounds { epoch_id },
+            StatusError::StatelessValidationUnhealthy { failed_checks } => {
+                Self::StatelessValidationUnhealthy { failed_checks }
+            }
             StatusError::Unreachable { ref error_message } => {
                 tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
                 crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
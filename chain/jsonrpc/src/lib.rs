@@ -269,6 +269,15 @@ pub struct GCSenderForRpc(
     >,
 );
 
+#[cfg(feature = "test_features")]
+#[derive(Clone, near_async::MultiSend, near_async::MultiSenderFrom)]
+pub struct PartialWitnessSenderForRpc(
+    AsyncSender<
+        near_client::ForceRedistributeWitness,
+        ActixResult<near_client::ForceRedistributeWitness>,
+    >,
+);
+
 #[derive(Clone, near_async::MultiSend, near_async::MultiSenderFrom)]
 pub struct PeerManagerSenderForRpc(AsyncSender<GetDebugStatus, ActixResult<GetDebugStatus>>);
 
@@ -278,6 +287,8 @@ struct JsonRpcHandler {
     peer_manager_sender: PeerManagerSenderForRpc,
     #[cfg(feature = "test_features")]
     gc_sender: GCSenderForRpc,
+    #[cfg(feature = "test_features")]
+    partial_witness_sender: PartialWitnessSenderForRpc,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
     enable_debug_rpc: bool,
@@ -480,6 +491,10 @@ impl JsonRpcHandler {
             "adv_disable_doomslug" => self.adv_disable_doomslug(request.params).await,
             "adv_produce_blocks" => self.adv_produce_blocks(request.params).await,
             "adv_produce_chunks" => self.adv_produce_chunks(request.params).await,
+            "adv_witness_parts" => self.adv_witness_parts(request.params).await,
+            "adv_force_redistribute_witness" => {
+                self.adv_force_redistribute_witness(request.params).await
+            }
             "adv_switch_to_height" => self.adv_switch_to_height(request.params).await,
             "adv_get_saved_blocks" => self.adv_get_saved_blocks(request.params).await,
             "adv_check_store" => self.adv_check_store(request.params).await,
@@ -1302,6 +1317,30 @@ impl JsonRpcHandler {
         Ok(Value::String(String::new()))
     }
 
+    async fn adv_witness_parts(&self, params: Value) -> Result<Value, RpcError> {
+        let mode = crate::api::Params::parse(params)?;
+        self.client_sender.send(near_client::NetworkAdversarialMessage::AdvWitnessParts(mode));
+        Ok(Value::String(String::new()))
+    }
+
+    async fn adv_force_redistribute_witness(&self, params: Value) -> Result<Value, RpcError> {
+        let (epoch_id, shard_id, height_created) = crate::api::Params::parse(params)?;
+        let chunk_production_key = near_primitives::stateless_validation::ChunkProductionKey {
+            epoch_id,
+            shard_id,
+            height_created,
+        };
+        match self
+            .partial_witness_sender
+            .send_async(near_client::ForceRedistributeWitness { chunk_production_key })
+            .await
+        {
+            Ok(Ok(num_parts)) => Ok(Value::from(num_parts)),
+            Ok(Err(err)) => Err(RpcError::server_error(Some(err))),
+            Err(err) => Err(RpcError::server_error(Some(err.to_string()))),
+        }
+    }
+
     async fn adv_switch_to_height(&self, params: Value) -> Result<Value, RpcError> {
         let (height,) = crate::api::Params::parse(params)?;
         self.client_sender.send(near_client::NetworkAdversarialMessage::AdvSwitchToHeight(height));
@@ -1549,6 +1588,7 @@ pub fn start_http(
     view_client_sender: ViewClientSenderForRpc,
     peer_manager_sender: PeerManagerSenderForRpc,
     #[cfg(feature = "test_features")] gc_sender: GCSenderForRpc,
+    #[cfg(feature = "test_features")] partial_witness_sender: PartialWitnessSenderForRpc,
     entity_debug_handler: Arc<dyn EntityDebugHandler>,
 ) -> Vec<(&'static str, actix_web::dev::ServerHandle)> {
     let RpcConfig {
@@ -1578,6 +1618,8 @@ pub fn start_http(
                 entity_debug_handler: entity_debug_handler.clone(),
                 #[cfg(feature = "test_features")]
                 gc_sender: gc_sender.clone(),
+                #[cfg(feature = "test_features")]
+                partial_witness_sender: partial_witness_sender.clone(),
             }))
             .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
             .wrap(middleware::Logger::default())
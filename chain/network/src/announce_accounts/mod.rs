@@ -94,6 +94,17 @@ impl AnnounceAccountCache {
         self.0.lock().get_announce(account_id).map(|announce_account| announce_account.peer_id)
     }
 
+    /// Find the account owned by the peer, among the accounts on cache. The cache is scanned, which
+    /// is cheap as only the validators announce their accounts.
+    pub(crate) fn get_peer_account(&self, peer_id: &PeerId) -> Option<AccountId> {
+        self.0
+            .lock()
+            .account_peers
+            .iter()
+            .find(|(_, announce_account)| &announce_account.peer_id == peer_id)
+            .map(|(account_id, _)| account_id.clone())
+    }
+
     /// Public interface for `account_peers`.
     /// Get keys currently on cache.
     pub(crate) fn get_accounts_keys(&self) -> Vec<AccountId> {
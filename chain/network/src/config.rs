@@ -2,6 +2,7 @@ use crate::blacklist;
 use crate::concurrency::rate;
 use crate::network_protocol::PeerAddr;
 use crate::network_protocol::PeerInfo;
+use crate::peer::stream::NETWORK_MESSAGE_MAX_SIZE_BYTES;
 use crate::peer_manager::peer_store;
 use crate::rate_limits::messages_limits;
 use crate::snapshot_hosts;
@@ -32,6 +33,9 @@ pub const MAX_PEER_ADDRS: usize = 10;
 /// Maximum number of peers to include in a PeersResponse message.
 pub const PEERS_RESPONSE_MAX_PEERS: u32 = 512;
 
+/// Default of `NetworkConfig::max_routed_message_payload`.
+pub const DEFAULT_MAX_ROUTED_MESSAGE_PAYLOAD: usize = 8 * 1024 * 1024;
+
 /// ValidatorProxies are nodes with public IP (aka proxies) that this validator trusts to be honest
 /// and willing to forward traffic to this validator. Whenever this node is a TIER1 validator
 /// (i.e. whenever it is a block producer/chunk producer/approver for the given epoch),
@@ -195,6 +199,9 @@ pub struct NetworkConfig {
 
     /// Configuration of rate limits for incoming messages.
     pub received_messages_rate_limits: messages_limits::Config,
+    /// Maximum size of the payload sent in one routed message. The senders of larger payloads,
+    /// i.e. the state witness parts, split them across several messages.
+    pub max_routed_message_payload: usize,
 
     #[cfg(test)]
     pub(crate) event_sink:
@@ -380,6 +387,7 @@ impl NetworkConfig {
             },
             // Use a preset to configure rate limits and override entries with user defined values later.
             received_messages_rate_limits: messages_limits::Config::standard_preset(),
+            max_routed_message_payload: cfg.max_routed_message_payload_bytes as usize,
             #[cfg(test)]
             event_sink: near_async::messaging::IntoSender::into_sender(
                 near_async::messaging::noop(),
@@ -458,6 +466,7 @@ impl NetworkConfig {
             }),
             skip_tombstones: None,
             received_messages_rate_limits: messages_limits::Config::default(),
+            max_routed_message_payload: DEFAULT_MAX_ROUTED_MESSAGE_PAYLOAD,
             #[cfg(test)]
             event_sink: near_async::messaging::IntoSender::into_sender(
                 near_async::messaging::noop(),
@@ -504,6 +513,17 @@ impl NetworkConfig {
             );
         }
 
+        // The routed message carries the payload together with its own header and the envelope.
+        if !(0 < self.max_routed_message_payload
+            && self.max_routed_message_payload < NETWORK_MESSAGE_MAX_SIZE_BYTES / 2)
+        {
+            anyhow::bail!(
+                "max_routed_message_payload({}) must be positive and less than {}",
+                self.max_routed_message_payload,
+                NETWORK_MESSAGE_MAX_SIZE_BYTES / 2
+            );
+        }
+
         self.accounts_data_broadcast_rate_limit
             .validate()
             .context("accounts_Data_broadcast_rate_limit")?;
@@ -64,6 +64,10 @@ fn default_snapshot_hosts_cache_size() -> u32 {
     1000
 }
 /// Remove peers that we didn't hear about for this amount of time.
+fn default_max_routed_message_payload_bytes() -> u64 {
+    crate::config::DEFAULT_MAX_ROUTED_MESSAGE_PAYLOAD as u64
+}
+
 fn default_peer_expiration_duration() -> Duration {
     Duration::seconds(7 * 24 * 60 * 60)
 }
@@ -163,6 +167,11 @@ pub struct Config {
     #[serde(default = "default_peer_expiration_duration")]
     #[serde(with = "near_async::time::serde_duration_as_std")]
     pub peer_expiration_duration: Duration,
+    /// Maximum size of the payload sent in one routed message. The state witness parts above it
+    /// are split across several messages, which happens with few chunk validators and a large
+    /// witness.
+    #[serde(default = "default_max_routed_message_payload_bytes")]
+    pub max_routed_message_payload_bytes: u64,
 
     /// List of the public addresses (in the format "<node public key>@<IP>:<port>") of trusted nodes,
     /// which are willing to route messages to this node. Useful only if this node is a validator.
@@ -329,6 +338,7 @@ impl Default for Config {
             peer_stats_period: default_peer_stats_period(),
             monitor_peers_max_period: default_monitor_peers_max_period(),
             peer_expiration_duration: default_peer_expiration_duration(),
+            max_routed_message_payload_bytes: default_max_routed_message_payload_bytes(),
             public_addrs: vec![],
             allow_private_ip_in_public_addrs: false,
             trusted_stun_servers: default_trusted_stun_servers(),
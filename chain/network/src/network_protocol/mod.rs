@@ -9,8 +9,14 @@ mod state_sync;
 pub use edge::*;
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsement;
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsementV1;
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
-use near_primitives::stateless_validation::state_witness::ChunkStateWitnessAck;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+    PartialEncodedStateWitnessRequest, PartialEncodedStateWitnessV1, WitnessReceiverStatus,
+};
+use near_primitives::stateless_validation::state_witness::{
+    BatchedChunkStateWitnessAck, BatchedChunkStateWitnessAckV2, ChunkStateWitnessAck,
+    VersionedChunkStateWitnessAck,
+};
 pub use peer::*;
 pub use state_sync::*;
 
@@ -546,12 +552,33 @@ pub enum RoutedMessageBody {
     _UnusedChunkStateWitness,
     /// TODO(ChunkEndorsementV2): Deprecate once we move to VersionedChunkEndorsement
     ChunkEndorsement(ChunkEndorsementV1),
+    /// TODO(WitnessAckDecodeStats): Deprecate once we move to VersionedChunkStateWitnessAck
     ChunkStateWitnessAck(ChunkStateWitnessAck),
-    PartialEncodedStateWitness(PartialEncodedStateWitness),
-    PartialEncodedStateWitnessForward(PartialEncodedStateWitness),
+    /// TODO(PartialWitnessSendTimestamp): Deprecate once we move to
+    /// VersionedPartialEncodedStateWitness
+    PartialEncodedStateWitness(PartialEncodedStateWitnessV1),
+    /// TODO(PartialWitnessSendTimestamp): Deprecate once we move to
+    /// VersionedPartialEncodedStateWitnessForward
+    PartialEncodedStateWitnessForward(PartialEncodedStateWitnessV1),
     VersionedChunkEndorsement(ChunkEndorsement),
     EpochSyncRequest,
     EpochSyncResponse(EpochSyncProof),
+    /// Only sent once `ProtocolFeature::PartialWitnessRequests` is enabled.
+    PartialEncodedStateWitnessRequest(PartialEncodedStateWitnessRequest),
+    /// Only sent once `ProtocolFeature::DirectFullWitness` is enabled.
+    FullEncodedStateWitness(FullEncodedStateWitness),
+    /// Only sent once `ProtocolFeature::WitnessReceiverStatus` is enabled.
+    WitnessReceiverStatus(WitnessReceiverStatus),
+    /// Only sent once `ProtocolFeature::BatchedWitnessAcks` is enabled.
+    /// TODO(WitnessAckDecodeStats): Deprecate once we move to BatchedChunkStateWitnessAckV2
+    BatchedChunkStateWitnessAck(BatchedChunkStateWitnessAck),
+    VersionedChunkStateWitnessAck(VersionedChunkStateWitnessAck),
+    /// Only sent once `ProtocolFeature::BatchedWitnessAcks` is enabled.
+    BatchedChunkStateWitnessAckV2(BatchedChunkStateWitnessAckV2),
+    /// Only sent once `ProtocolFeature::PartialWitnessFragments` is enabled.
+    PartialEncodedStateWitnessFragment(PartialEncodedStateWitnessFragment),
+    VersionedPartialEncodedStateWitness(PartialEncodedStateWitness),
+    VersionedPartialEncodedStateWitnessForward(PartialEncodedStateWitness),
 }
 
 impl RoutedMessageBody {
@@ -577,6 +604,10 @@ impl RoutedMessageBody {
             RoutedMessageBody::ChunkEndorsement(_)
             | RoutedMessageBody::PartialEncodedStateWitness(_)
             | RoutedMessageBody::PartialEncodedStateWitnessForward(_)
+            | RoutedMessageBody::PartialEncodedStateWitnessRequest(_)
+            | RoutedMessageBody::PartialEncodedStateWitnessFragment(_)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitness(_)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(_)
             | RoutedMessageBody::VersionedChunkEndorsement(_) => true,
             _ => false,
         }
@@ -649,6 +680,37 @@ impl fmt::Debug for RoutedMessageBody {
                     proof.current_epoch.first_block_header_in_epoch.epoch_id(),
                 )
             }
+            RoutedMessageBody::PartialEncodedStateWitnessRequest(request) => write!(
+                f,
+                "PartialEncodedStateWitnessRequest({:?}, {}, {})",
+                request.chunk_production_key(),
+                request.part_ord,
+                request.requester
+            ),
+            RoutedMessageBody::FullEncodedStateWitness(witness) => {
+                write!(f, "FullEncodedStateWitness({:?})", witness.chunk_production_key())
+            }
+            RoutedMessageBody::WitnessReceiverStatus(status) => {
+                write!(f, "WitnessReceiverStatus({:?})", status)
+            }
+            RoutedMessageBody::BatchedChunkStateWitnessAck(batch) => {
+                f.debug_tuple("BatchedChunkStateWitnessAck").field(&batch.acks.len()).finish()
+            }
+            RoutedMessageBody::VersionedChunkStateWitnessAck(ack) => {
+                f.debug_tuple("VersionedChunkStateWitnessAck").field(ack.chunk_hash()).finish()
+            }
+            RoutedMessageBody::BatchedChunkStateWitnessAckV2(batch) => {
+                f.debug_tuple("BatchedChunkStateWitnessAckV2").field(&batch.acks.len()).finish()
+            }
+            RoutedMessageBody::PartialEncodedStateWitnessFragment(fragment) => {
+                write!(f, "PartialEncodedStateWitnessFragment({:?})", fragment)
+            }
+            RoutedMessageBody::VersionedPartialEncodedStateWitness(_) => {
+                write!(f, "VersionedPartialEncodedStateWitness")
+            }
+            RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(_) => {
+                write!(f, "VersionedPartialEncodedStateWitnessForward")
+            }
         }
     }
 }
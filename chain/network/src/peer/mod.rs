@@ -1,5 +1,5 @@
 pub(crate) mod peer_actor;
-mod stream;
+pub(crate) mod stream;
 mod tracker;
 mod transfer_stats;
 
@@ -956,10 +956,11 @@ impl PeerActor {
         clock: &time::Clock,
         network_state: &NetworkState,
         peer_id: PeerId,
+        author: PeerId,
         msg_hash: CryptoHash,
         body: RoutedMessageBody,
     ) -> Result<Option<RoutedMessageBody>, ReasonForBan> {
-        Ok(network_state.receive_routed_message(clock, peer_id, msg_hash, body).await)
+        Ok(network_state.receive_routed_message(clock, peer_id, author, msg_hash, body).await)
     }
 
     fn receive_message(
@@ -1007,6 +1008,7 @@ impl PeerActor {
                         &clock,
                         &network_state,
                         peer_id,
+                        msg.msg.author.clone(),
                         msg_hash,
                         msg.msg.body,
                     )
@@ -49,6 +49,12 @@ impl tcp::Tier {
             | RoutedMessageBody::ChunkEndorsement(..)
             | RoutedMessageBody::PartialEncodedStateWitness(..)
             | RoutedMessageBody::PartialEncodedStateWitnessForward(..)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitness(..)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(..)
+            | RoutedMessageBody::PartialEncodedStateWitnessRequest(..)
+            | RoutedMessageBody::FullEncodedStateWitness(..)
+            | RoutedMessageBody::WitnessReceiverStatus(..)
+            | RoutedMessageBody::PartialEncodedStateWitnessFragment(..)
             | RoutedMessageBody::VersionedPartialEncodedChunk(..) => true,
             _ => self == tcp::Tier::T2,
         }
@@ -22,8 +22,11 @@ use crate::routing::NetworkTopologyChange;
 use crate::shards_manager::ShardsManagerRequestFromNetwork;
 use crate::snapshot_hosts::{SnapshotHostInfoError, SnapshotHostsCache};
 use crate::state_witness::{
-    ChunkStateWitnessAckMessage, PartialEncodedStateWitnessForwardMessage,
-    PartialEncodedStateWitnessMessage, PartialWitnessSenderForNetwork,
+    with_send_outcome_metrics, BatchedChunkStateWitnessAckMessage, ChunkStateWitnessAckMessage,
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, PartialWitnessSenderForNetwork,
+    WitnessReceiverStatusMessage,
 };
 use crate::stats::metrics;
 use crate::store;
@@ -190,7 +193,7 @@ impl NetworkState {
             genesis_id,
             client,
             shards_manager_adapter,
-            partial_witness_adapter,
+            partial_witness_adapter: with_send_outcome_metrics(clock, partial_witness_adapter),
             chain_info: Default::default(),
             tier2: connection::Pool::new(config.node_id()),
             tier1: connection::Pool::new(config.node_id()),
@@ -582,7 +585,9 @@ impl NetworkState {
                 RawRoutedMessage { target: PeerIdOrHash::PeerId(peer_id.clone()), body: msg },
             );
             actix::spawn(async move {
-                this.receive_routed_message(&clock, peer_id, msg.hash(), msg.msg.body).await;
+                let author = peer_id.clone();
+                this.receive_routed_message(&clock, peer_id, author, msg.hash(), msg.msg.body)
+                    .await;
             });
             return true;
         }
@@ -655,6 +660,7 @@ impl NetworkState {
         &self,
         clock: &time::Clock,
         peer_id: PeerId,
+        author: PeerId,
         msg_hash: CryptoHash,
         body: RoutedMessageBody,
     ) -> Option<RoutedMessageBody> {
@@ -714,7 +720,26 @@ impl NetworkState {
                 None
             }
             RoutedMessageBody::ChunkStateWitnessAck(ack) => {
-                self.partial_witness_adapter.send(ChunkStateWitnessAckMessage(ack));
+                let validator = self.account_announcements.get_peer_account(&author);
+                self.partial_witness_adapter
+                    .send(ChunkStateWitnessAckMessage(ack.into(), validator));
+                None
+            }
+            RoutedMessageBody::BatchedChunkStateWitnessAck(batch) => {
+                let validator = self.account_announcements.get_peer_account(&author);
+                self.partial_witness_adapter
+                    .send(BatchedChunkStateWitnessAckMessage(batch.into(), validator));
+                None
+            }
+            RoutedMessageBody::VersionedChunkStateWitnessAck(ack) => {
+                let validator = self.account_announcements.get_peer_account(&author);
+                self.partial_witness_adapter.send(ChunkStateWitnessAckMessage(ack, validator));
+                None
+            }
+            RoutedMessageBody::BatchedChunkStateWitnessAckV2(batch) => {
+                let validator = self.account_announcements.get_peer_account(&author);
+                self.partial_witness_adapter
+                    .send(BatchedChunkStateWitnessAckMessage(batch, validator));
                 None
             }
             RoutedMessageBody::ChunkEndorsement(endorsement) => {
@@ -723,12 +748,41 @@ impl NetworkState {
                 None
             }
             RoutedMessageBody::PartialEncodedStateWitness(witness) => {
-                self.partial_witness_adapter.send(PartialEncodedStateWitnessMessage(witness));
+                self.partial_witness_adapter
+                    .send(PartialEncodedStateWitnessMessage(witness.into()));
                 None
             }
             RoutedMessageBody::PartialEncodedStateWitnessForward(witness) => {
                 self.partial_witness_adapter
-                    .send(PartialEncodedStateWitnessForwardMessage(witness));
+                    .send(PartialEncodedStateWitnessForwardMessage(witness.into(), peer_id));
+                None
+            }
+            RoutedMessageBody::VersionedPartialEncodedStateWitness(witness) => {
+                self.partial_witness_adapter.send(PartialEncodedStateWitnessMessage(witness));
+                None
+            }
+            RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(witness) => {
+                self.partial_witness_adapter
+                    .send(PartialEncodedStateWitnessForwardMessage(witness, peer_id));
+                None
+            }
+            RoutedMessageBody::PartialEncodedStateWitnessRequest(request) => {
+                let requester = self.account_announcements.get_peer_account(&author);
+                self.partial_witness_adapter
+                    .send(PartialEncodedStateWitnessRequestMessage(request, requester));
+                None
+            }
+            RoutedMessageBody::FullEncodedStateWitness(witness) => {
+                self.partial_witness_adapter.send(FullEncodedStateWitnessMessage(witness));
+                None
+            }
+            RoutedMessageBody::WitnessReceiverStatus(status) => {
+                self.partial_witness_adapter.send(WitnessReceiverStatusMessage(status));
+                None
+            }
+            RoutedMessageBody::PartialEncodedStateWitnessFragment(fragment) => {
+                self.partial_witness_adapter
+                    .send(PartialEncodedStateWitnessFragmentMessage(fragment, peer_id));
                 None
             }
             RoutedMessageBody::VersionedChunkEndorsement(endorsement) => {
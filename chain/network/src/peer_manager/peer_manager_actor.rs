@@ -11,7 +11,10 @@ use crate::peer_manager::connection;
 use crate::peer_manager::network_state::{NetworkState, WhitelistNode};
 use crate::peer_manager::peer_store;
 use crate::shards_manager::ShardsManagerRequestFromNetwork;
-use crate::state_witness::PartialWitnessSenderForNetwork;
+use crate::state_witness::{
+    PartialWitnessSenderForNetwork, WitnessDelivery, WitnessDeliveryPath,
+    WitnessDeliveryReportMessage, WitnessRoutingHints,
+};
 use crate::stats::metrics;
 use crate::store;
 use crate::tcp;
@@ -24,13 +27,15 @@ use ::time::ext::InstantExt as _;
 use actix::fut::future::wrap_future;
 use actix::{Actor as _, AsyncContext as _};
 use anyhow::Context as _;
-use near_async::messaging::{SendAsync, Sender};
+use near_async::messaging::{CanSend, SendAsync, Sender};
 use near_async::time;
 use near_o11y::{handler_debug_span, handler_trace_span, WithSpanContext};
 use near_performance_metrics_macros::perf;
 use near_primitives::block::GenesisId;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsement;
+use near_primitives::stateless_validation::state_witness::VersionedChunkStateWitnessAck;
+use near_primitives::types::AccountId;
 use near_primitives::views::{
     ConnectionInfoView, EdgeView, KnownPeerStateView, NetworkGraphView, PeerStoreView,
     RecentOutboundConnectionsView, SnapshotHostInfoView, SnapshotHostsView,
@@ -735,6 +740,32 @@ impl PeerManagerActor {
         );
     }
 
+    /// Sends the witness messages to the chunk validators and reports the paths used, or the
+    /// failure to send, to the partial witness actor. The routing preferences are not followed
+    /// yet, the messages are always routed the default way and reported as such.
+    fn send_witness_messages(
+        &self,
+        messages: impl Iterator<Item = (AccountId, RoutedMessageBody)>,
+        hints: &WitnessRoutingHints,
+    ) {
+        let mut deliveries = vec![];
+        for (chunk_validator, msg) in messages {
+            let path = if self.state.send_message_to_account(&self.clock, &chunk_validator, msg) {
+                WitnessDeliveryPath::Routed
+            } else {
+                WitnessDeliveryPath::Failed
+            };
+            deliveries.push(WitnessDelivery {
+                preference: hints.preference(&chunk_validator),
+                target: chunk_validator,
+                path,
+            });
+        }
+        if !deliveries.is_empty() {
+            self.state.partial_witness_adapter.send(WitnessDeliveryReportMessage(deliveries));
+        }
+    }
+
     #[perf]
     fn handle_msg_network_requests(
         &mut self,
@@ -973,11 +1004,21 @@ impl PeerManagerActor {
                 NetworkResponses::NoResponse
             }
             NetworkRequests::ChunkStateWitnessAck(target, ack) => {
-                self.state.send_message_to_account(
-                    &self.clock,
-                    &target,
-                    RoutedMessageBody::ChunkStateWitnessAck(ack),
-                );
+                let msg = match ack {
+                    VersionedChunkStateWitnessAck::V1(ack) => {
+                        RoutedMessageBody::ChunkStateWitnessAck(ack)
+                    }
+                    _ => RoutedMessageBody::VersionedChunkStateWitnessAck(ack),
+                };
+                self.state.send_message_to_account(&self.clock, &target, msg);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::BatchedChunkStateWitnessAck(target, batch) => {
+                let msg = match batch.into_v1() {
+                    Ok(batch) => RoutedMessageBody::BatchedChunkStateWitnessAck(batch),
+                    Err(batch) => RoutedMessageBody::BatchedChunkStateWitnessAckV2(batch),
+                };
+                self.state.send_message_to_account(&self.clock, &target, msg);
                 NetworkResponses::NoResponse
             }
             NetworkRequests::ChunkEndorsement(target, endorsement) => {
@@ -990,27 +1031,102 @@ impl PeerManagerActor {
                 self.state.send_message_to_account(&self.clock, &target, msg);
                 NetworkResponses::NoResponse
             }
-            NetworkRequests::PartialEncodedStateWitness(validator_witness_tuple) => {
-                for (chunk_validator, partial_witness) in validator_witness_tuple {
-                    self.state.send_message_to_account(
-                        &self.clock,
-                        &chunk_validator,
-                        RoutedMessageBody::PartialEncodedStateWitness(partial_witness),
-                    );
-                }
+            NetworkRequests::PartialEncodedStateWitness(validator_witness_tuple, hints) => {
+                let messages = validator_witness_tuple.into_iter().map(
+                    |(chunk_validator, partial_witness)| {
+                        let msg = match partial_witness.into_v1() {
+                            Ok(partial_witness) => {
+                                RoutedMessageBody::PartialEncodedStateWitness(partial_witness)
+                            }
+                            Err(partial_witness) => {
+                                RoutedMessageBody::VersionedPartialEncodedStateWitness(
+                                    partial_witness,
+                                )
+                            }
+                        };
+                        (chunk_validator, msg)
+                    },
+                );
+                self.send_witness_messages(messages, &hints);
                 NetworkResponses::NoResponse
             }
             NetworkRequests::PartialEncodedStateWitnessForward(
                 chunk_validators,
                 partial_witness,
+                hints,
             ) => {
+                let msg = match partial_witness.into_v1() {
+                    Ok(partial_witness) => {
+                        RoutedMessageBody::PartialEncodedStateWitnessForward(partial_witness)
+                    }
+                    Err(partial_witness) => {
+                        RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(
+                            partial_witness,
+                        )
+                    }
+                };
+                let messages = chunk_validators
+                    .into_iter()
+                    .map(|chunk_validator| (chunk_validator, msg.clone()));
+                self.send_witness_messages(messages, &hints);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::PartialEncodedStateWitnessFragments(
+                chunk_validators,
+                fragments,
+                hints,
+            ) => {
+                // Delivered only if all the fragments are, as the part can't be joined otherwise.
+                let mut deliveries = vec![];
                 for chunk_validator in chunk_validators {
+                    let mut delivered = true;
+                    for fragment in &fragments {
+                        let msg =
+                            RoutedMessageBody::PartialEncodedStateWitnessFragment(fragment.clone());
+                        delivered &=
+                            self.state.send_message_to_account(&self.clock, &chunk_validator, msg);
+                    }
+                    deliveries.push(WitnessDelivery {
+                        preference: hints.preference(&chunk_validator),
+                        target: chunk_validator,
+                        path: if delivered {
+                            WitnessDeliveryPath::Routed
+                        } else {
+                            WitnessDeliveryPath::Failed
+                        },
+                    });
+                }
+                if !deliveries.is_empty() {
+                    self.state
+                        .partial_witness_adapter
+                        .send(WitnessDeliveryReportMessage(deliveries));
+                }
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::PartialEncodedStateWitnessRequest(target, request) => {
+                self.state.send_message_to_account(
+                    &self.clock,
+                    &target,
+                    RoutedMessageBody::PartialEncodedStateWitnessRequest(request),
+                );
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::FullEncodedStateWitness(chunk_validators, full_witness, hints) => {
+                let messages = chunk_validators.into_iter().map(|chunk_validator| {
+                    (
+                        chunk_validator,
+                        RoutedMessageBody::FullEncodedStateWitness(full_witness.clone()),
+                    )
+                });
+                self.send_witness_messages(messages, &hints);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::WitnessReceiverStatus(chunk_producers, status) => {
+                for chunk_producer in chunk_producers {
                     self.state.send_message_to_account(
                         &self.clock,
-                        &chunk_validator,
-                        RoutedMessageBody::PartialEncodedStateWitnessForward(
-                            partial_witness.clone(),
-                        ),
+                        &chunk_producer,
+                        RoutedMessageBody::WitnessReceiverStatus(status.clone()),
                     );
                 }
                 NetworkResponses::NoResponse
@@ -170,6 +170,10 @@ pub enum RateLimitedPeerMessageKey {
     ChunkStateWitnessAck,
     PartialEncodedStateWitness,
     PartialEncodedStateWitnessForward,
+    PartialEncodedStateWitnessRequest,
+    FullEncodedStateWitness,
+    WitnessReceiverStatus,
+    PartialEncodedStateWitnessFragment,
 }
 
 /// Given a `PeerMessage` returns a tuple containing the `RateLimitedPeerMessageKey`
@@ -210,13 +214,33 @@ fn get_key_and_token_cost(message: &PeerMessage) -> Option<(RateLimitedPeerMessa
                 Some((PartialEncodedChunkForward, 1))
             }
             RoutedMessageBody::ChunkEndorsement(_) => Some((ChunkEndorsement, 1)),
-            RoutedMessageBody::ChunkStateWitnessAck(_) => Some((ChunkStateWitnessAck, 1)),
-            RoutedMessageBody::PartialEncodedStateWitness(_) => {
+            RoutedMessageBody::ChunkStateWitnessAck(_)
+            | RoutedMessageBody::VersionedChunkStateWitnessAck(_) => {
+                Some((ChunkStateWitnessAck, 1))
+            }
+            // The batched acks count against the same limit as the acks sent one by one.
+            RoutedMessageBody::BatchedChunkStateWitnessAck(batch) => {
+                Some((ChunkStateWitnessAck, batch.acks.len().max(1) as u32))
+            }
+            RoutedMessageBody::BatchedChunkStateWitnessAckV2(batch) => {
+                Some((ChunkStateWitnessAck, batch.acks.len().max(1) as u32))
+            }
+            RoutedMessageBody::PartialEncodedStateWitness(_)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitness(_) => {
                 Some((PartialEncodedStateWitness, 1))
             }
-            RoutedMessageBody::PartialEncodedStateWitnessForward(_) => {
+            RoutedMessageBody::PartialEncodedStateWitnessForward(_)
+            | RoutedMessageBody::VersionedPartialEncodedStateWitnessForward(_) => {
                 Some((PartialEncodedStateWitnessForward, 1))
             }
+            RoutedMessageBody::PartialEncodedStateWitnessRequest(_) => {
+                Some((PartialEncodedStateWitnessRequest, 1))
+            }
+            RoutedMessageBody::FullEncodedStateWitness(_) => Some((FullEncodedStateWitness, 1)),
+            RoutedMessageBody::WitnessReceiverStatus(_) => Some((WitnessReceiverStatus, 1)),
+            RoutedMessageBody::PartialEncodedStateWitnessFragment(_) => {
+                Some((PartialEncodedStateWitnessFragment, 1))
+            }
             RoutedMessageBody::VersionedChunkEndorsement(_) => Some((ChunkEndorsement, 1)),
             RoutedMessageBody::EpochSyncRequest => None,
             RoutedMessageBody::EpochSyncResponse(_) => None,
@@ -1,25 +1,331 @@
-use near_async::messaging::Sender;
+use std::sync::Mutex;
+
+use near_async::messaging::{CanSend, IntoSender, SendOutcome, Sender};
+use near_async::time;
 use near_async::{MultiSend, MultiSendMessage, MultiSenderFrom};
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
-use near_primitives::stateless_validation::state_witness::ChunkStateWitnessAck;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+    PartialEncodedStateWitnessRequest, WitnessReceiverStatus,
+};
+use near_primitives::stateless_validation::state_witness::{
+    BatchedChunkStateWitnessAckV2, VersionedChunkStateWitnessAck,
+};
+use near_primitives::types::AccountId;
+
+use crate::stats::metrics;
+
+/// How often at most the witness messages dropped on the way to the PartialWitnessActor are
+/// reported in the logs.
+const DROPPED_MESSAGES_WARN_PERIOD: time::Duration = time::Duration::seconds(10);
+
+/// Ack of a witness produced by us, together with the chunk validator which sent it if the
+/// network knows the account of the author of the message.
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct ChunkStateWitnessAckMessage(pub VersionedChunkStateWitnessAck, pub Option<AccountId>);
 
+/// Batch of acks, together with the chunk validator which sent it, see
+/// `ChunkStateWitnessAckMessage`.
 #[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
 #[rtype(result = "()")]
-pub struct ChunkStateWitnessAckMessage(pub ChunkStateWitnessAck);
+pub struct BatchedChunkStateWitnessAckMessage(
+    pub BatchedChunkStateWitnessAckV2,
+    pub Option<AccountId>,
+);
 
 #[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
 #[rtype(result = "()")]
 pub struct PartialEncodedStateWitnessMessage(pub PartialEncodedStateWitness);
 
+/// Part forwarded by its owner, together with the peer which delivered it to us. The peer is the
+/// owner itself unless the forward was routed through other nodes.
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct PartialEncodedStateWitnessForwardMessage(pub PartialEncodedStateWitness, pub PeerId);
+
+/// Request for a witness part, together with the account announced by the author of the routed
+/// message, None if it didn't announce any. Only the author authenticates the requester.
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct PartialEncodedStateWitnessRequestMessage(
+    pub PartialEncodedStateWitnessRequest,
+    pub Option<AccountId>,
+);
+
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct FullEncodedStateWitnessMessage(pub FullEncodedStateWitness);
+
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct WitnessReceiverStatusMessage(pub WitnessReceiverStatus);
+
+/// Fragment of a part too large for one routed message, together with the peer which delivered
+/// it to us, see `PartialEncodedStateWitnessFragment`.
+#[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct PartialEncodedStateWitnessFragmentMessage(
+    pub PartialEncodedStateWitnessFragment,
+    pub PeerId,
+);
+
+/// Preferred way of delivering a witness message to a chunk validator. The routing layer
+/// may not be able to follow the preference, see `WitnessDeliveryPath` for what it did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WitnessRoutingPreference {
+    /// Let the routing layer pick the route.
+    #[default]
+    Default,
+    /// Send over a direct TIER1 connection to the validator if there is one, otherwise over
+    /// the route with the lowest latency.
+    Direct,
+}
+
+impl WitnessRoutingPreference {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WitnessRoutingPreference::Default => "default",
+            WitnessRoutingPreference::Direct => "direct",
+        }
+    }
+}
+
+/// Routing preferences for the targets of a witness message. The targets which are not listed
+/// are routed the default way, so the default value expresses no preference at all.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WitnessRoutingHints {
+    /// Targets to which the message should preferably be sent directly.
+    pub direct: Vec<AccountId>,
+}
+
+impl WitnessRoutingHints {
+    pub fn preference(&self, target: &AccountId) -> WitnessRoutingPreference {
+        if self.direct.contains(target) {
+            WitnessRoutingPreference::Direct
+        } else {
+            WitnessRoutingPreference::Default
+        }
+    }
+}
+
+/// How a witness message reached the chunk validator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WitnessDeliveryPath {
+    /// Over a direct TIER1 connection to the validator.
+    Direct,
+    /// Over a route through other peers.
+    Routed,
+    /// The message couldn't be sent, e.g. because there is no route to the validator.
+    Failed,
+}
+
+impl WitnessDeliveryPath {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WitnessDeliveryPath::Direct => "direct",
+            WitnessDeliveryPath::Routed => "routed",
+            WitnessDeliveryPath::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessDelivery {
+    pub target: AccountId,
+    pub preference: WitnessRoutingPreference,
+    pub path: WitnessDeliveryPath,
+}
+
+/// Report from the network about the paths used to send the messages of a witness request,
+/// one entry per target the message was sent to, including the targets it failed to reach.
 #[derive(actix::Message, Clone, Debug, PartialEq, Eq)]
 #[rtype(result = "()")]
-pub struct PartialEncodedStateWitnessForwardMessage(pub PartialEncodedStateWitness);
+pub struct WitnessDeliveryReportMessage(pub Vec<WitnessDelivery>);
 
 #[derive(Clone, MultiSend, MultiSenderFrom, MultiSendMessage)]
 #[multi_send_message_derive(Debug)]
 #[multi_send_input_derive(Debug, Clone, PartialEq, Eq)]
 pub struct PartialWitnessSenderForNetwork {
     pub chunk_state_witness_ack: Sender<ChunkStateWitnessAckMessage>,
+    pub batched_chunk_state_witness_ack: Sender<BatchedChunkStateWitnessAckMessage>,
     pub partial_encoded_state_witness: Sender<PartialEncodedStateWitnessMessage>,
     pub partial_encoded_state_witness_forward: Sender<PartialEncodedStateWitnessForwardMessage>,
+    pub partial_encoded_state_witness_request: Sender<PartialEncodedStateWitnessRequestMessage>,
+    pub full_encoded_state_witness: Sender<FullEncodedStateWitnessMessage>,
+    pub witness_delivery_report: Sender<WitnessDeliveryReportMessage>,
+    pub witness_receiver_status: Sender<WitnessReceiverStatusMessage>,
+    pub partial_encoded_state_witness_fragment: Sender<PartialEncodedStateWitnessFragmentMessage>,
+}
+
+/// Wraps the senders of the witness messages received from the peers in great numbers, i.e. the
+/// parts, the forwards and the acks, to count what happens to every message on the way to the
+/// PartialWitnessActor, see `SendOutcome`. Under overload the messages may be shed before they
+/// reach the handlers of the actor, where everything looks calm, so they are accounted here.
+pub(crate) fn with_send_outcome_metrics(
+    clock: &time::Clock,
+    sender: PartialWitnessSenderForNetwork,
+) -> PartialWitnessSenderForNetwork {
+    PartialWitnessSenderForNetwork {
+        chunk_state_witness_ack: CountingSender::new(
+            clock,
+            "chunk_state_witness_ack",
+            sender.chunk_state_witness_ack,
+        )
+        .into_sender(),
+        batched_chunk_state_witness_ack: CountingSender::new(
+            clock,
+            "batched_chunk_state_witness_ack",
+            sender.batched_chunk_state_witness_ack,
+        )
+        .into_sender(),
+        partial_encoded_state_witness: CountingSender::new(
+            clock,
+            "partial_encoded_state_witness",
+            sender.partial_encoded_state_witness,
+        )
+        .into_sender(),
+        partial_encoded_state_witness_forward: CountingSender::new(
+            clock,
+            "partial_encoded_state_witness_forward",
+            sender.partial_encoded_state_witness_forward,
+        )
+        .into_sender(),
+        partial_encoded_state_witness_fragment: CountingSender::new(
+            clock,
+            "partial_encoded_state_witness_fragment",
+            sender.partial_encoded_state_witness_fragment,
+        )
+        .into_sender(),
+        ..sender
+    }
+}
+
+/// Counts the outcomes of the messages of one type in `PARTIAL_WITNESS_ACTOR_MESSAGES` and warns
+/// once per `DROPPED_MESSAGES_WARN_PERIOD` if any of them was dropped.
+struct CountingSender<M: 'static> {
+    clock: time::Clock,
+    message_type: &'static str,
+    inner: Sender<M>,
+    window: Mutex<DropWindow>,
+}
+
+/// Messages sent since the start of the current warning period.
+#[derive(Default)]
+struct DropWindow {
+    start: Option<time::Instant>,
+    sent: u64,
+    dropped: u64,
+}
+
+impl<M> CountingSender<M> {
+    fn new(clock: &time::Clock, message_type: &'static str, inner: Sender<M>) -> Self {
+        Self {
+            clock: clock.clone(),
+            message_type,
+            inner,
+            window: Mutex::new(DropWindow::default()),
+        }
+    }
+
+    fn record(&self, outcome: SendOutcome) {
+        metrics::PARTIAL_WITNESS_ACTOR_MESSAGES
+            .with_label_values(&[self.message_type, outcome.as_str()])
+            .inc();
+        let now = self.clock.now();
+        let mut window = self.window.lock().unwrap();
+        window.sent += 1;
+        if outcome == SendOutcome::Dropped {
+            window.dropped += 1;
+        }
+        let start = *window.start.get_or_insert(now);
+        if now - start < DROPPED_MESSAGES_WARN_PERIOD {
+            return;
+        }
+        if window.dropped > 0 {
+            tracing::warn!(
+                target: "network",
+                message_type = self.message_type,
+                sent = window.sent,
+                dropped = window.dropped,
+                period_secs = (now - start).whole_seconds(),
+                "Witness messages dropped on the way to the PartialWitnessActor",
+            );
+        }
+        *window = DropWindow { start: Some(now), sent: 0, dropped: 0 };
+    }
+}
+
+impl<M: Send + 'static> CanSend<M> for CountingSender<M> {
+    fn send(&self, message: M) {
+        self.send_with_outcome(message);
+    }
+
+    fn send_with_outcome(&self, message: M) -> SendOutcome {
+        let outcome = self.inner.send_with_outcome(message);
+        self.record(outcome);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use near_async::messaging::{CanSend, IntoSender, SendOutcome};
+    use near_async::time;
+
+    use super::{CountingSender, DROPPED_MESSAGES_WARN_PERIOD};
+    use crate::stats::metrics;
+
+    /// Channel of a fixed capacity dropping the messages beyond it.
+    struct BoundedChannel {
+        capacity: usize,
+        messages: Mutex<Vec<u32>>,
+    }
+
+    impl CanSend<u32> for BoundedChannel {
+        fn send(&self, message: u32) {
+            self.send_with_outcome(message);
+        }
+
+        fn send_with_outcome(&self, message: u32) -> SendOutcome {
+            let mut messages = self.messages.lock().unwrap();
+            if messages.len() >= self.capacity {
+                return SendOutcome::Dropped;
+            }
+            messages.push(message);
+            SendOutcome::Enqueued
+        }
+    }
+
+    fn count(outcome: SendOutcome) -> u64 {
+        metrics::PARTIAL_WITNESS_ACTOR_MESSAGES
+            .with_label_values(&["test_bounded_channel", outcome.as_str()])
+            .get()
+    }
+
+    #[test]
+    fn outcomes_of_full_channel_are_counted() {
+        let clock = time::FakeClock::default();
+        let channel = Arc::new(BoundedChannel { capacity: 3, messages: Mutex::new(vec![]) });
+        let sender =
+            CountingSender::new(&clock.clock(), "test_bounded_channel", channel.as_sender());
+
+        for message in 0..5 {
+            sender.send(message);
+        }
+        assert_eq!(*channel.messages.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(count(SendOutcome::Enqueued), 3);
+        assert_eq!(count(SendOutcome::Dropped), 2);
+        assert_eq!(count(SendOutcome::EnqueuedOverCapacity), 0);
+        assert_eq!(sender.window.lock().unwrap().dropped, 2);
+
+        // The drops are reported and forgotten once the period passes.
+        clock.advance(DROPPED_MESSAGES_WARN_PERIOD);
+        assert_eq!(sender.send_with_outcome(5), SendOutcome::Dropped);
+        assert_eq!(count(SendOutcome::Dropped), 3);
+        let window = sender.window.lock().unwrap();
+        assert_eq!((window.sent, window.dropped), (0, 0));
+    }
 }
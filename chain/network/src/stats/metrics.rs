@@ -392,6 +392,17 @@ pub(crate) static NETWORK_ROUTED_MSG_DISTANCES: LazyLock<IntCounterVec> = LazyLo
     .unwrap()
 });
 
+pub(crate) static PARTIAL_WITNESS_ACTOR_MESSAGES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_network_partial_witness_actor_messages_total",
+        "Witness messages passed from the network to the PartialWitnessActor, by message type \
+        and by what happened to them, i.e. enqueued, enqueued over the capacity of the actor \
+        or dropped",
+        &["message_type", "outcome"],
+    )
+    .unwrap()
+});
+
 /// Updated the prometheus metrics about the received routed message `msg`.
 /// `tier` indicates the network over which the message was transmitted.
 /// `fastest` indicates whether this message is the first copy of `msg` received -
@@ -8,8 +8,11 @@ use crate::client::{
 };
 use crate::shards_manager::ShardsManagerRequestFromNetwork;
 use crate::state_witness::{
-    ChunkStateWitnessAckMessage, PartialEncodedStateWitnessForwardMessage,
-    PartialEncodedStateWitnessMessage, PartialWitnessSenderForNetwork,
+    BatchedChunkStateWitnessAckMessage, ChunkStateWitnessAckMessage,
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, PartialWitnessSenderForNetwork,
+    WitnessReceiverStatusMessage,
 };
 use crate::types::{
     NetworkRequests, NetworkResponses, PeerManagerMessageRequest, PeerManagerMessageResponse,
@@ -332,11 +335,19 @@ fn network_message_to_partial_witness_handler(
             shared_state
                 .senders_for_account(&target)
                 .partial_witness_sender
-                .send(ChunkStateWitnessAckMessage(witness_ack));
+                .send(ChunkStateWitnessAckMessage(witness_ack, Some(my_account_id.clone())));
+            None
+        }
+        NetworkRequests::BatchedChunkStateWitnessAck(target, batch) => {
+            assert_ne!(target, my_account_id, "Sending message to self not supported.");
+            shared_state
+                .senders_for_account(&target)
+                .partial_witness_sender
+                .send(BatchedChunkStateWitnessAckMessage(batch, Some(my_account_id.clone())));
             None
         }
 
-        NetworkRequests::PartialEncodedStateWitness(validator_witness_tuple) => {
+        NetworkRequests::PartialEncodedStateWitness(validator_witness_tuple, _) => {
             for (target, partial_witness) in validator_witness_tuple.into_iter() {
                 assert_ne!(target, my_account_id, "Sending message to self not supported.");
                 shared_state
@@ -346,13 +357,61 @@ fn network_message_to_partial_witness_handler(
             }
             None
         }
-        NetworkRequests::PartialEncodedStateWitnessForward(chunk_validators, partial_witness) => {
+        NetworkRequests::PartialEncodedStateWitnessForward(
+            chunk_validators,
+            partial_witness,
+            _,
+        ) => {
+            let my_peer_id = shared_state.account_to_peer_id.get(&my_account_id).unwrap();
+            for target in chunk_validators {
+                assert_ne!(target, my_account_id, "Sending message to self not supported.");
+                shared_state.senders_for_account(&target).partial_witness_sender.send(
+                    PartialEncodedStateWitnessForwardMessage(
+                        partial_witness.clone(),
+                        my_peer_id.clone(),
+                    ),
+                );
+            }
+            None
+        }
+        NetworkRequests::PartialEncodedStateWitnessFragments(chunk_validators, fragments, _) => {
+            let my_peer_id = shared_state.account_to_peer_id.get(&my_account_id).unwrap();
+            for target in chunk_validators {
+                assert_ne!(target, my_account_id, "Sending message to self not supported.");
+                let sender = &shared_state.senders_for_account(&target).partial_witness_sender;
+                for fragment in &fragments {
+                    sender.send(PartialEncodedStateWitnessFragmentMessage(
+                        fragment.clone(),
+                        my_peer_id.clone(),
+                    ));
+                }
+            }
+            None
+        }
+        NetworkRequests::PartialEncodedStateWitnessRequest(target, request) => {
+            assert_ne!(target, my_account_id, "Sending message to self not supported.");
+            shared_state.senders_for_account(&target).partial_witness_sender.send(
+                PartialEncodedStateWitnessRequestMessage(request, Some(my_account_id.clone())),
+            );
+            None
+        }
+        NetworkRequests::FullEncodedStateWitness(chunk_validators, full_witness, _) => {
             for target in chunk_validators {
                 assert_ne!(target, my_account_id, "Sending message to self not supported.");
                 shared_state
                     .senders_for_account(&target)
                     .partial_witness_sender
-                    .send(PartialEncodedStateWitnessForwardMessage(partial_witness.clone()));
+                    .send(FullEncodedStateWitnessMessage(full_witness.clone()));
+            }
+            None
+        }
+        NetworkRequests::WitnessReceiverStatus(chunk_producers, status) => {
+            for target in chunk_producers {
+                assert_ne!(target, my_account_id, "Sending message to self not supported.");
+                shared_state
+                    .senders_for_account(&target)
+                    .partial_witness_sender
+                    .send(WitnessReceiverStatusMessage(status.clone()));
             }
             None
         }
@@ -11,6 +11,7 @@ pub use crate::network_protocol::{
 };
 use crate::routing::routing_table_view::RoutingTableInfo;
 pub use crate::state_sync::StateSyncResponse;
+use crate::state_witness::WitnessRoutingHints;
 use near_async::messaging::{AsyncSender, Sender};
 use near_async::time;
 use near_crypto::PublicKey;
@@ -21,8 +22,13 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::PartialEncodedChunkWithArcReceipts;
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsement;
-use near_primitives::stateless_validation::partial_witness::PartialEncodedStateWitness;
-use near_primitives::stateless_validation::state_witness::ChunkStateWitnessAck;
+use near_primitives::stateless_validation::partial_witness::{
+    FullEncodedStateWitness, PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+    PartialEncodedStateWitnessRequest, WitnessReceiverStatus,
+};
+use near_primitives::stateless_validation::state_witness::{
+    BatchedChunkStateWitnessAckV2, VersionedChunkStateWitnessAck,
+};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockHeight, EpochHeight, ShardId};
 use near_schema_checker_lib::ProtocolSchema;
@@ -275,13 +281,35 @@ pub enum NetworkRequests {
     /// A challenge to invalidate a block.
     Challenge(Challenge),
     /// Acknowledgement to a chunk's state witness, sent back to the originating chunk producer.
-    ChunkStateWitnessAck(AccountId, ChunkStateWitnessAck),
+    ChunkStateWitnessAck(AccountId, VersionedChunkStateWitnessAck),
+    /// Acknowledgements to several state witnesses of the same chunk producer, sent back to it
+    /// in one message.
+    BatchedChunkStateWitnessAck(AccountId, BatchedChunkStateWitnessAckV2),
     /// Message for a chunk endorsement, sent by a chunk validator to the block producer.
     ChunkEndorsement(AccountId, ChunkEndorsement),
     /// Message from chunk producer to set of chunk validators to send state witness part.
-    PartialEncodedStateWitness(Vec<(AccountId, PartialEncodedStateWitness)>),
+    PartialEncodedStateWitness(Vec<(AccountId, PartialEncodedStateWitness)>, WitnessRoutingHints),
     /// Message from chunk validator to all other chunk validators to forward state witness part.
-    PartialEncodedStateWitnessForward(Vec<AccountId>, PartialEncodedStateWitness),
+    PartialEncodedStateWitnessForward(
+        Vec<AccountId>,
+        PartialEncodedStateWitness,
+        WitnessRoutingHints,
+    ),
+    /// Fragments of a state witness part too large for one routed message, each of them sent to
+    /// all the chunk validators, see `PartialEncodedStateWitnessFragment`.
+    PartialEncodedStateWitnessFragments(
+        Vec<AccountId>,
+        Vec<PartialEncodedStateWitnessFragment>,
+        WitnessRoutingHints,
+    ),
+    /// Message from chunk validator to the owner of a state witness part to request the part.
+    PartialEncodedStateWitnessRequest(AccountId, PartialEncodedStateWitnessRequest),
+    /// Message from chunk producer to the chunk validators with the highest stake to send
+    /// the full state witness in addition to their part.
+    FullEncodedStateWitness(Vec<AccountId>, FullEncodedStateWitness, WitnessRoutingHints),
+    /// Message from chunk validator to the chunk producers to announce whether it can receive
+    /// the state witness parts.
+    WitnessReceiverStatus(Vec<AccountId>, WitnessReceiverStatus),
     /// Requests an epoch sync
     EpochSyncRequest { peer_id: PeerId },
     /// Response to an epoch sync request
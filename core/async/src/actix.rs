@@ -1,4 +1,4 @@
-use crate::messaging::{AsyncSendError, CanSend, MessageWithCallback};
+use crate::messaging::{AsyncSendError, CanSend, MessageWithCallback, SendOutcome};
 use near_o11y::{WithSpanContext, WithSpanContextExt};
 
 /// An actix Addr implements CanSend for any message type that the actor handles.
@@ -10,17 +10,23 @@ where
     A::Context: actix::dev::ToEnvelope<A, M>,
 {
     fn send(&self, message: M) {
+        self.send_with_outcome(message);
+    }
+
+    fn send_with_outcome(&self, message: M) -> SendOutcome {
         match self.try_send(message) {
-            Ok(_) => {}
+            Ok(_) => SendOutcome::Enqueued,
             Err(err) => match err {
                 actix::dev::SendError::Full(message) => {
                     self.do_send(message);
+                    SendOutcome::EnqueuedOverCapacity
                 }
                 actix::dev::SendError::Closed(_) => {
                     near_o11y::tracing::warn!(
                         "Tried to send {} message to closed actor",
                         std::any::type_name::<M>()
                     );
+                    SendOutcome::Dropped
                 }
             },
         }
@@ -94,6 +100,10 @@ where
     fn send(&self, message: M) {
         CanSend::send(&self.inner, message.with_span_context());
     }
+
+    fn send_with_outcome(&self, message: M) -> SendOutcome {
+        CanSend::send_with_outcome(&self.inner, message.with_span_context())
+    }
 }
 
 impl<M, S> CanSend<MessageWithCallback<M, M::Result>> for AddrWithAutoSpanContext<S>
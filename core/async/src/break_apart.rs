@@ -1,4 +1,4 @@
-use crate::messaging::{CanSend, Sender};
+use crate::messaging::{CanSend, SendOutcome, Sender};
 
 /// Allows a Sender<M> to be used like a Sender<S> as long as S can be converted to M.
 pub struct BreakApart<M: 'static> {
@@ -9,4 +9,8 @@ impl<S, M: From<S> + 'static> CanSend<S> for BreakApart<M> {
     fn send(&self, message: S) {
         self.sender.send(M::from(message))
     }
+
+    fn send_with_outcome(&self, message: S) -> SendOutcome {
+        self.sender.send_with_outcome(M::from(message))
+    }
 }
@@ -59,6 +59,36 @@ where
 /// See [`Handler`] trait for more details.
 pub trait CanSend<M>: Send + Sync + 'static {
     fn send(&self, message: M);
+
+    /// Same as `send`, but also tells what happened to the message, so that the sender can
+    /// account for the messages the receiver couldn't keep up with. The implementations which
+    /// can't tell report every message as enqueued.
+    fn send_with_outcome(&self, message: M) -> SendOutcome {
+        self.send(message);
+        SendOutcome::Enqueued
+    }
+}
+
+/// What happened to a message sent with `CanSend::send_with_outcome`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// Enqueued within the capacity of the receiver.
+    Enqueued,
+    /// Enqueued even though the receiver was already at capacity, i.e. the receiver is falling
+    /// behind.
+    EnqueuedOverCapacity,
+    /// Not delivered, e.g. because the receiver is closed or its queue is full.
+    Dropped,
+}
+
+impl SendOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SendOutcome::Enqueued => "enqueued",
+            SendOutcome::EnqueuedOverCapacity => "enqueued_over_capacity",
+            SendOutcome::Dropped => "dropped",
+        }
+    }
 }
 
 /// Wraps a CanSend. This should be used to pass around an Arc<dyn CanSend<M>>, instead
@@ -98,6 +128,11 @@ impl<M> Sender<M> {
         self.sender.send(message)
     }
 
+    /// Sends a message and tells what happened to it, see `CanSend::send_with_outcome`.
+    pub fn send_with_outcome(&self, message: M) -> SendOutcome {
+        self.sender.send_with_outcome(message)
+    }
+
     fn from_impl(sender: impl CanSend<M> + 'static) -> Self {
         Self { sender: Arc::new(sender) }
     }
@@ -225,6 +260,10 @@ impl<M, S: CanSend<M>> CanSend<M> for LateBoundSender<S> {
     fn send(&self, message: M) {
         self.sender.wait().send(message);
     }
+
+    fn send_with_outcome(&self, message: M) -> SendOutcome {
+        self.sender.wait().send_with_outcome(message)
+    }
 }
 
 pub struct Noop;
@@ -196,6 +196,332 @@ impl Default for EpochSyncConfig {
     }
 }
 
+/// Reed Solomon implementation used to encode and decode the state witness parts.
+/// All of them produce identical parts, they only differ in speed on different CPUs.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ReedSolomonBackendConfig {
+    /// Use the `galois_8` implementation of reed-solomon-erasure, with the SIMD kernels if they
+    /// were compiled in. If they were but the CPU doesn't support them, use the portable one.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    /// SIMD-accelerated implementation. Requires the `reed_solomon_simd` feature of
+    /// near-client, `galois_8` is used without the SIMD kernels if it isn't enabled, and the
+    /// portable implementation if the CPU doesn't support them.
+    #[serde(rename = "simd")]
+    Simd,
+    /// Pure-Rust implementation that works on every CPU.
+    #[serde(rename = "portable")]
+    Portable,
+}
+
+/// What the PartialWitnessActor does after a panic in the handler of a message, once the panic
+/// is reported. Only applies to the builds which unwind on panic. neard is built with
+/// `panic = 'abort'` in the dev and release profiles, so it aborts right after the report
+/// whatever the policy is.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum HandlerPanicPolicy {
+    /// Asks the node to shut down gracefully, then re-raises the panic so that the actor doesn't
+    /// handle any more messages with the state the panic left behind.
+    #[default]
+    #[serde(rename = "shutdown")]
+    Shutdown,
+    /// Re-raises the panic, which stops the actor while the rest of the node keeps running.
+    #[serde(rename = "propagate")]
+    Propagate,
+}
+
+/// Configuration for the PartialWitnessActor, which distributes the state witness parts
+/// between chunk producers and chunk validators.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PartialWitnessConfig {
+    /// Shards for which the node accepts state witness parts while it is a validator in the
+    /// next epoch, but not a chunk validator for the shard in the current one. Useful to warm
+    /// up caches before the node starts validating the shard, e.g. a child shard after resharding.
+    /// Witnesses reconstructed for these shards may be validated, but are never endorsed.
+    pub pre_tracked_shards: Vec<ShardId>,
+    /// Shards for which the node doesn't forward the witness parts it owns to the other chunk
+    /// validators. The node still receives the parts, validates and endorses the chunks of these
+    /// shards. Useful for the nodes tracking all shards with limited bandwidth, but every excluded
+    /// owner makes the other chunk validators rely on the remaining parts to decode the witness,
+    /// so excluding shards on many chunk validators endangers the liveness of the shards.
+    pub no_forward_shards: Vec<ShardId>,
+    /// If enabled, the parts of the oldest incomplete witnesses are spilled to the database
+    /// once the total size of the parts held in memory exceeds `spill_threshold`, instead of
+    /// being kept in memory. The spilled parts are restored when the remaining parts arrive.
+    pub spill_to_disk: bool,
+    /// Memory retained by the incomplete witnesses, i.e. their parts held in memory together
+    /// with the metadata kept per part, above which the parts are spilled to the database.
+    /// Only used when `spill_to_disk` is enabled.
+    pub spill_threshold: ByteSize,
+    /// Number of chunk validators with the highest stake to which the chunk producer sends
+    /// the full encoded witness directly, in addition to their part. This saves them the round
+    /// trip of collecting the forwarded parts. Zero disables sending the full witness, and so
+    /// does a protocol version before `ProtocolFeature::DirectFullWitness`.
+    pub direct_full_witness_targets: usize,
+    /// Maximum number of bytes of the full witnesses sent directly per block height,
+    /// summed over all the shards the node produces chunks for.
+    pub direct_full_witness_budget_per_height: ByteSize,
+    /// Number of chunk validators with the highest stake to which the network is asked to send
+    /// the witness messages over a direct connection if possible. The other chunk validators are
+    /// reached over the default route. Zero disables the preference.
+    pub direct_routing_targets: usize,
+    /// Reed Solomon implementation used for the witness parts.
+    pub reed_solomon_backend: ReedSolomonBackendConfig,
+    /// Memory retained by the incomplete witnesses kept after the node asks the
+    /// PartialWitnessActor to reduce its memory usage. The oldest witnesses are dropped until
+    /// the rest fits.
+    pub memory_pressure_parts_budget: ByteSize,
+    /// Number of heights after the head for which the PartialWitnessActor prepares the chunk
+    /// validator assignments and the Reed Solomon encoders when the node starts.
+    pub warm_up_heights: u64,
+    /// Maximum time the node waits for the PartialWitnessActor to warm up after start before
+    /// announcing its account to the peers.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub warm_up_timeout: Duration,
+    /// Time after the first part of a witness arrives within which the witness has to be
+    /// decoded. Past it, the witness can't be endorsed in time anymore, so its parts are freed
+    /// and the parts arriving later are dropped.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub incomplete_witness_deadline: Duration,
+    /// If set, the witness parts received by the node are recorded to this file together with
+    /// the epoch data needed to validate them, so that they can be replayed offline with
+    /// `neard view-state replay-partial-witnesses`. A relative path is relative to the home dir.
+    pub record_messages_path: Option<PathBuf>,
+    /// Maximum size of the recording, no more messages are recorded once it is reached.
+    pub record_messages_max_size: ByteSize,
+    /// Fraction of one core spent on verifying the signatures of the received witness parts
+    /// above which a warning is logged, if the load lasts for several consecutive windows.
+    /// Values above 1.0 disable the warning.
+    pub signature_verification_warn_utilization: f64,
+    /// Size of the borsh-serialized state witness below which the chunk producer sends the
+    /// witness uncompressed, once `ProtocolFeature::UncompressedSmallWitness` is enabled.
+    /// Compressing small witnesses costs more time than it saves on the network, and the
+    /// output may even be larger than the input. Zero compresses every witness.
+    pub uncompressed_witness_threshold: ByteSize,
+    /// If set, the node announces to the chunk producers on graceful shutdown that it can't
+    /// receive the witness parts for this long, e.g. the expected duration of a restart, and
+    /// clears the announcement when it starts again. Meanwhile the chunk producers send the parts
+    /// owned by the node directly to the other chunk validators, since the node wouldn't forward
+    /// them. None disables the announcements, and so does a protocol version before
+    /// `ProtocolFeature::WitnessReceiverStatus`.
+    #[serde(with = "near_time::serde_opt_duration_as_std")]
+    pub announce_unavailability_on_shutdown: Option<Duration>,
+    /// Time for which the witnesses that received enough parts wait before being decoded, so
+    /// that the witnesses completed by a burst of parts are decoded and sent to the client newest
+    /// first. Zero decodes the witnesses right after handling the part which completed them.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub decode_batch_window: Duration,
+    /// Median send skew of the parts sent directly by a chunk producer above which the producer
+    /// is flagged in the distribution health, see `PartialEncodedStateWitness::sent_at`.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub high_send_skew_threshold: Duration,
+    /// Maximum number of witnesses reconstructed and decompressed at once. The witnesses
+    /// completed together beyond it wait for a free slot, newest first, and the ones reaching
+    /// their deadline meanwhile are not decoded. Defaults to a quarter of the cores, at most 2.
+    pub max_concurrent_witness_decodes: usize,
+    /// If set, the sizes of the witnesses produced and reconstructed by the node are exported to
+    /// JSON lines files in this directory, for analyzing the growth of the witnesses offline. A
+    /// relative path is relative to the home dir.
+    pub export_witness_stats_dir: Option<PathBuf>,
+    /// Size of an export file above which the export continues in a new file.
+    pub export_witness_stats_rotation_size: ByteSize,
+    /// Maximum total size of the export files, the oldest files are deleted to stay below it.
+    pub export_witness_stats_max_disk_usage: ByteSize,
+    /// If enabled, the witness parts whose chunk producer signed a view of the chunk validators
+    /// different from ours are rejected, see `ChunkValidatorsDigest`. Otherwise the mismatches
+    /// are only counted and logged.
+    pub reject_chunk_validators_mismatch: bool,
+    /// If set, every part we own is forwarded to at most this many chunk validators instead of
+    /// all of them, which bounds the forwarding traffic on the shards with many chunk validators.
+    /// The subsets of the parts together cover every chunk validator several times, see
+    /// `ForwardTargets::capped`, and the chunk validators missing parts request them or rely on
+    /// the full witness sent by the chunk producer. None forwards to all the chunk validators.
+    pub max_forward_targets: Option<usize>,
+    /// Time for which the acks of the reconstructed witnesses are held, so that the acks to the
+    /// same chunk producer are sent together in one message. The chunk producer subtracts the
+    /// time the acks were held from the round trip time. Capped at 20ms, zero sends every ack
+    /// right after the reconstruction, and so does a protocol version before
+    /// `ProtocolFeature::BatchedWitnessAcks`.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub ack_batching_delay: Duration,
+    /// Time after sending a witness produced by us by which the chunk validators holding two
+    /// thirds of the stake are expected to have acked it. If they haven't, the chunk validators
+    /// missing the ack and the acked stake are reported, as the chunk likely won't be endorsed in
+    /// time. A bit under the time the endorsements have before the next block by default.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub ack_threshold_deadline: Duration,
+    /// Maximum number of the witness parts received while the node syncs the chain which are
+    /// kept and handled once the sync is done, the oldest ones are dropped beyond it. Zero drops
+    /// all the parts received during the sync.
+    pub max_parts_buffered_during_sync: usize,
+    /// Time for which the parts of the witnesses produced by us wait before being sent, so that
+    /// the parts of the witnesses of all the shards we produce at the same height are sent
+    /// interleaved and the chunk validators of no shard wait for the parts of the others. Zero
+    /// sends the parts right after the distribution request is handled.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub part_send_window: Duration,
+    /// If enabled, the witness parts outside of the height window of the head are still accepted
+    /// within the height window of the highest known tip of another fork, as they become relevant
+    /// if the chain reorgs to that fork. Their witnesses are only decoded once the head window
+    /// admits them, and they are dropped once neither window does. Otherwise only the window of
+    /// the head is considered.
+    pub fork_aware_height_window: bool,
+    /// Thresholds of the checks telling whether the node can currently perform its chunk
+    /// validator duties, reported in the status RPC.
+    pub health: StatelessValidationHealthConfig,
+    /// Number of the most recent epochs whose witness distribution summary is kept in the store
+    /// and served by the status RPC. Zero doesn't persist the summaries.
+    pub epoch_witness_stats_retained: usize,
+    /// Reduces the forwarding of our parts while the network fails to send the witness messages.
+    pub forward_backoff: ForwardBackoffConfig,
+    /// If enabled, the received witnesses are decompressed into a buffer before being
+    /// deserialized, rather than deserialized as they are decompressed. The buffer holds the raw
+    /// witness next to the decoded one, so this is only meant for debugging the decoding.
+    pub buffered_witness_decode: bool,
+    /// What the actor does after a panic in the handler of a message in the builds which unwind on
+    /// panic, see `HandlerPanicPolicy`.
+    pub handler_panic_policy: HandlerPanicPolicy,
+    /// Time for which the loops of the actor over many witnesses or parts, e.g. decoding the
+    /// witnesses completed together, run before yielding to the messages received meanwhile, so
+    /// that they don't hold back the forwards of the parts of the other shards. The loop resumes
+    /// right after these messages. Zero runs the loops to completion.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub processing_time_slice: Duration,
+    /// If set, at most this many decoded witnesses are pushed to the client before it confirms
+    /// consuming them. The witnesses decoded beyond it are held by the actor and pushed once the
+    /// client consumes one, or pulled by the client with `RequestNextDecodedWitness`. None pushes
+    /// every witness as soon as it is decoded.
+    pub client_witness_credits: Option<usize>,
+    /// If set, the parts still missing this long after the first part of a witness arrived are
+    /// requested from their owners, once per witness, see `PartialEncodedStateWitnessRequest`.
+    /// Covers the parts whose owners don't forward them, e.g. with `max_forward_targets`. None
+    /// never requests the missing parts, and neither does a protocol version before
+    /// `ProtocolFeature::PartialWitnessRequests`.
+    #[serde(with = "near_time::serde_opt_duration_as_std")]
+    pub missing_part_request_delay: Option<Duration>,
+}
+
+impl Default for PartialWitnessConfig {
+    fn default() -> Self {
+        Self {
+            pre_tracked_shards: vec![],
+            no_forward_shards: vec![],
+            spill_to_disk: false,
+            spill_threshold: ByteSize::mb(500),
+            direct_full_witness_targets: 0,
+            direct_full_witness_budget_per_height: ByteSize::mb(64),
+            direct_routing_targets: 10,
+            reed_solomon_backend: ReedSolomonBackendConfig::Auto,
+            memory_pressure_parts_budget: ByteSize::mb(100),
+            warm_up_heights: 3,
+            warm_up_timeout: Duration::seconds(5),
+            incomplete_witness_deadline: Duration::seconds(5),
+            record_messages_path: None,
+            record_messages_max_size: ByteSize::gb(1),
+            signature_verification_warn_utilization: 0.8,
+            uncompressed_witness_threshold: ByteSize::kib(16),
+            announce_unavailability_on_shutdown: None,
+            decode_batch_window: Duration::ZERO,
+            high_send_skew_threshold: Duration::milliseconds(500),
+            max_concurrent_witness_decodes: default_max_concurrent_witness_decodes(),
+            export_witness_stats_dir: None,
+            export_witness_stats_rotation_size: ByteSize::mb(64),
+            export_witness_stats_max_disk_usage: ByteSize::gb(1),
+            reject_chunk_validators_mismatch: false,
+            max_forward_targets: None,
+            ack_batching_delay: Duration::ZERO,
+            ack_threshold_deadline: Duration::milliseconds(800),
+            max_parts_buffered_during_sync: 0,
+            part_send_window: Duration::ZERO,
+            fork_aware_height_window: false,
+            health: StatelessValidationHealthConfig::default(),
+            epoch_witness_stats_retained: 10,
+            forward_backoff: ForwardBackoffConfig::default(),
+            buffered_witness_decode: false,
+            handler_panic_policy: HandlerPanicPolicy::Shutdown,
+            processing_time_slice: Duration::milliseconds(5),
+            client_witness_credits: None,
+            missing_part_request_delay: None,
+        }
+    }
+}
+
+/// Thresholds of the stateless validation health checks, see `PartialWitnessConfig::health`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct StatelessValidationHealthConfig {
+    /// Age of the block of the head above which the head is considered stale.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub max_head_age: Duration,
+    /// Memory retained by the incomplete witnesses above which the node is considered unable to
+    /// keep up with the witnesses it receives.
+    pub max_parts_cache_size: ByteSize,
+    /// Number of witnesses which failed to decode within the last minute above which the node is
+    /// considered unhealthy.
+    pub max_recent_decode_failures: usize,
+    /// If enabled, the health check endpoint fails while any of the checks fails. Otherwise the
+    /// checks are only reported in the status.
+    pub fail_health_check: bool,
+}
+
+impl Default for StatelessValidationHealthConfig {
+    fn default() -> Self {
+        Self {
+            max_head_age: Duration::seconds(30),
+            max_parts_cache_size: ByteSize::mb(500),
+            max_recent_decode_failures: 10,
+            fail_health_check: false,
+        }
+    }
+}
+
+/// Thresholds of the forward backoff, see `PartialWitnessConfig::forward_backoff`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ForwardBackoffConfig {
+    /// If disabled, the parts are always forwarded to all the forward targets right away.
+    pub enabled: bool,
+    /// Time after which the weight of a send outcome reported by the network halves.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub half_life: Duration,
+    /// Failure rate of the sends to the forward targets of a shard above which the forwards of
+    /// the shard back off.
+    pub engage_failure_rate: f64,
+    /// Failure rate below which the forwards of a shard return to normal, lower than
+    /// `engage_failure_rate` so that the forwards don't flap around a single threshold.
+    pub disengage_failure_rate: f64,
+    /// Decayed number of sends to the forward targets of a shard below which the failure rate is
+    /// not trusted and the forwards don't back off.
+    pub min_sends: f64,
+    /// Number of chunk validators every part is forwarded to while backing off, see
+    /// `PartialWitnessConfig::max_forward_targets`.
+    pub max_forward_targets: usize,
+    /// Time for which the forwards wait while backing off, so that they are sent together.
+    #[serde(with = "near_time::serde_duration_as_std")]
+    pub pacing: Duration,
+}
+
+impl Default for ForwardBackoffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            half_life: Duration::seconds(10),
+            engage_failure_rate: 0.3,
+            disengage_failure_rate: 0.1,
+            min_sends: 20.0,
+            max_forward_targets: 8,
+            pacing: Duration::milliseconds(50),
+        }
+    }
+}
+
+fn default_max_concurrent_witness_decodes() -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    (cores / 4).clamp(1, 2)
+}
+
 // A handle that allows the main process to interrupt resharding if needed.
 // This typically happens when the main process is interrupted.
 #[derive(Clone)]
@@ -516,6 +842,8 @@ pub struct ClientConfig {
     /// which can cause extra load on the database. This option is not recommended for production use,
     /// as a large number of incoming witnesses could cause denial of service.
     pub save_latest_witnesses: bool,
+    /// Configuration for the distribution of state witness parts.
+    pub partial_witness: PartialWitnessConfig,
 }
 
 impl ClientConfig {
@@ -604,6 +932,7 @@ impl ClientConfig {
             orphan_state_witness_pool_size: default_orphan_state_witness_pool_size(),
             orphan_state_witness_max_size: default_orphan_state_witness_max_size(),
             save_latest_witnesses: false,
+            partial_witness: PartialWitnessConfig::default(),
         }
     }
 }
@@ -180,6 +180,14 @@ impl<'a> GenesisValidator<'a> {
             let error_message = format!("Epoch Length must be greater than 0");
             self.validation_errors.push_genesis_semantics_error(error_message)
         }
+
+        if self.genesis_config.target_validator_mandates_per_shard == 0 {
+            let error_message = format!(
+                "Target validator mandates per shard must be greater than 0, \
+                 otherwise the chunks have no chunk validators"
+            );
+            self.validation_errors.push_genesis_semantics_error(error_message)
+        }
     }
 
     fn result_with_full_error(&self) -> Result<(), ValidationError> {
@@ -291,6 +299,24 @@ mod test {
         validate_genesis(genesis).unwrap();
     }
 
+    #[test]
+    #[should_panic(expected = "Target validator mandates per shard must be greater than 0")]
+    fn test_no_validator_mandates() {
+        let mut config = GenesisConfig::default();
+        config.target_validator_mandates_per_shard = 0;
+        config.validators = vec![AccountInfo {
+            account_id: "test".parse().unwrap(),
+            public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+            amount: 10,
+        }];
+        let records = GenesisRecords(vec![StateRecord::Account {
+            account_id: "test".parse().unwrap(),
+            account: create_account(),
+        }]);
+        let genesis = &Genesis::new(config, records).unwrap();
+        validate_genesis(genesis).unwrap();
+    }
+
     #[test]
     #[should_panic(expected = "access key account test1 does not exist")]
     fn test_access_key_with_nonexistent_account() {
@@ -2,7 +2,7 @@
 extern crate bencher;
 
 use bencher::Bencher;
-use near_o11y::metrics::{try_create_int_counter_vec, IntCounter, IntCounterVec};
+use near_o11y::metrics::{int_label, try_create_int_counter_vec, IntCounter, IntCounterVec};
 use std::sync::LazyLock;
 
 static COUNTERS: LazyLock<IntCounterVec> = LazyLock::new(|| {
@@ -74,6 +74,14 @@ fn inc_counter_vec_with_label_values_stack_no_format(bench: &mut Bencher) {
     });
 }
 
+fn inc_counter_vec_with_label_values_interned(bench: &mut Bencher) {
+    bench.iter(|| {
+        for shard_id in 0..NUM_SHARDS {
+            COUNTERS.with_label_values(&[&int_label(shard_id as u64)]).inc();
+        }
+    });
+}
+
 fn inc_counter_vec_cached(bench: &mut Bencher) {
     const NUM_SHARDS: usize = 8;
     let counters: Vec<IntCounter> = (0..NUM_SHARDS)
@@ -103,6 +111,7 @@ benchmark_group!(
     inc_counter_vec_with_label_values_smartstring,
     inc_counter_vec_with_label_values_stack,
     inc_counter_vec_with_label_values_stack_no_format,
+    inc_counter_vec_with_label_values_interned,
     inc_counter_vec_cached_str,
     inc_counter_vec_cached,
 );
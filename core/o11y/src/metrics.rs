@@ -72,8 +72,9 @@ pub use prometheus::{
     CounterVec, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter,
     IntCounterVec, IntGauge, IntGaugeVec, Opts, Result, TextEncoder,
 };
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, OnceLock};
 
 /// Collect all the metrics for reporting.
 pub fn gather() -> Vec<prometheus::proto::MetricFamily> {
@@ -212,6 +213,24 @@ pub fn processing_time_buckets() -> Vec<f64> {
     buckets
 }
 
+/// Number of the smallest integer ids whose labels are interned by `int_label`. Covers the shard
+/// ids of any realistic shard layout.
+const NUM_INTERNED_INT_LABELS: usize = 1024;
+
+static INT_LABELS: [OnceLock<Box<str>>; NUM_INTERNED_INT_LABELS] =
+    [const { OnceLock::new() }; NUM_INTERNED_INT_LABELS];
+
+/// Label of an integer id, such as a shard id, for looking up a labeled metric. The labels of the
+/// ids below `NUM_INTERNED_INT_LABELS` are formatted once and shared, so that the hot paths don't
+/// allocate a String per metric call. The larger ids, e.g. the nonexistent shard ids of invalid
+/// messages, are formatted on every call rather than interned, so they can't grow the memory.
+pub fn int_label(id: u64) -> Cow<'static, str> {
+    match INT_LABELS.get(id as usize) {
+        Some(label) => Cow::Borrowed(label.get_or_init(|| id.to_string().into_boxed_str())),
+        None => Cow::Owned(id.to_string()),
+    }
+}
+
 static EXCEPTIONS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
     HashSet::from([
         "flat_storage_cached_changes_num_items",
@@ -247,7 +266,9 @@ fn check_metric_near_prefix(name: &str) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::metrics::check_metric_near_prefix;
+    use std::borrow::Cow;
+
+    use crate::metrics::{check_metric_near_prefix, int_label, NUM_INTERNED_INT_LABELS};
 
     #[test]
     fn test_near_prefix() {
@@ -256,4 +277,17 @@ mod tests {
         assert!(check_metric_near_prefix("near").is_err());
         assert!(check_metric_near_prefix("abc").is_err());
     }
+
+    #[test]
+    fn test_int_label() {
+        let label = int_label(7);
+        assert_eq!(label, "7");
+        assert!(matches!(label, Cow::Borrowed(_)));
+        // The same interned label is returned on every call.
+        assert!(std::ptr::eq(label.as_ref(), int_label(7).as_ref()));
+
+        let id = NUM_INTERNED_INT_LABELS as u64;
+        assert_eq!(int_label(id), id.to_string());
+        assert!(matches!(int_label(id), Cow::Owned(_)));
+    }
 }
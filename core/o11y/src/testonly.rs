@@ -2,7 +2,7 @@ mod tracing_capture;
 use crate::subscriber::use_color_auto;
 use core::fmt::Result;
 use std::time::Instant;
-pub use tracing_capture::TracingCapture;
+pub use tracing_capture::{CapturedSpan, TracingCapture};
 use tracing_subscriber::fmt;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::time::FormatTime;
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::Write;
 use std::mem;
 use std::sync::{Arc, Mutex};
@@ -11,9 +12,25 @@ pub struct TracingCapture {
     _guard: tracing::subscriber::DefaultGuard,
 }
 
+/// Span created while `TracingCapture` was enabled, see `TracingCapture::spans`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedSpan {
+    pub name: String,
+    /// Name of the parent span, either given explicitly or the span entered when this one was
+    /// created.
+    pub parent: Option<String>,
+    /// Values of the fields formatted with `Debug`, including the ones recorded after the span
+    /// was created. The fields left empty are missing.
+    pub fields: BTreeMap<String, String>,
+}
+
 struct Captured {
     on_log: Arc<dyn Fn(&str) + Send + Sync>,
     logs: Vec<String>,
+    /// All the spans created so far, the id of a span is its index plus one.
+    spans: Vec<CapturedSpan>,
+    /// Ids of the spans entered and not exited yet, the innermost last.
+    entered: Vec<u64>,
 }
 
 struct Subscriber(Arc<Mutex<Captured>>);
@@ -25,8 +42,12 @@ impl TracingCapture {
     /// taken to properly propagate this across threads for multi-threaded
     /// tests.
     pub fn enable() -> TracingCapture {
-        let captured =
-            Arc::new(Mutex::new(Captured { on_log: Arc::new(|_| ()), logs: Vec::new() }));
+        let captured = Arc::new(Mutex::new(Captured {
+            on_log: Arc::new(|_| ()),
+            logs: Vec::new(),
+            spans: Vec::new(),
+            entered: Vec::new(),
+        }));
         let subscriber = Subscriber(Arc::clone(&captured));
         let _guard = tracing::subscriber::set_default(subscriber);
         TracingCapture { captured, _guard }
@@ -38,6 +59,13 @@ impl TracingCapture {
         let mut guard = self.captured.lock().unwrap();
         mem::take(&mut guard.logs)
     }
+    /// Get all the spans created so-far, in the order of creation, together
+    /// with their parents.
+    ///
+    /// Useful to verify the span hierarchy the traces are exported with.
+    pub fn spans(&self) -> Vec<CapturedSpan> {
+        self.captured.lock().unwrap().spans.clone()
+    }
     /// Sets the callback to execute on every log line emitted.
     ///
     /// The intended use-case is for testing multithreaded code: by *blocking*
@@ -48,6 +76,12 @@ impl TracingCapture {
     }
 }
 
+impl Captured {
+    fn span_name(&self, id: u64) -> Option<String> {
+        self.spans.get(id as usize - 1).map(|span| span.name.clone())
+    }
+}
+
 impl tracing::Subscriber for Subscriber {
     fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
         true
@@ -70,13 +104,42 @@ impl tracing::Subscriber for Subscriber {
         let mut guard = self.0.lock().unwrap();
         guard.logs.push(buf);
 
-        tracing::span::Id::from_u64(guard.logs.len() as u64)
+        let parent = if let Some(parent) = span.parent() {
+            guard.span_name(parent.into_u64())
+        } else if span.is_contextual() {
+            guard.entered.last().and_then(|parent| guard.span_name(*parent))
+        } else {
+            None
+        };
+        let mut fields = CollectFields(BTreeMap::new());
+        span.record(&mut fields);
+        guard.spans.push(CapturedSpan {
+            name: span.metadata().name().to_string(),
+            parent,
+            fields: fields.0,
+        });
+
+        tracing::span::Id::from_u64(guard.spans.len() as u64)
+    }
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut guard = self.0.lock().unwrap();
+        if let Some(span) = guard.spans.get_mut(span.into_u64() as usize - 1) {
+            let mut fields = CollectFields(mem::take(&mut span.fields));
+            values.record(&mut fields);
+            span.fields = fields.0;
+        }
     }
-    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
     fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
     fn event(&self, _event: &tracing::Event<'_>) {}
-    fn enter(&self, _span: &tracing::span::Id) {}
-    fn exit(&self, _span: &tracing::span::Id) {}
+    fn enter(&self, span: &tracing::span::Id) {
+        self.0.lock().unwrap().entered.push(span.into_u64());
+    }
+    fn exit(&self, span: &tracing::span::Id) {
+        let mut guard = self.0.lock().unwrap();
+        if let Some(pos) = guard.entered.iter().rposition(|entered| *entered == span.into_u64()) {
+            guard.entered.remove(pos);
+        }
+    }
 }
 
 struct AppendToString(String);
@@ -85,3 +148,10 @@ impl tracing::field::Visit for AppendToString {
         let _ = write!(self.0, " {}={:?}", field.name(), value);
     }
 }
+
+struct CollectFields(BTreeMap<String, String>);
+impl tracing::field::Visit for CollectFields {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
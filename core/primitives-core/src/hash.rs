@@ -235,6 +235,32 @@ pub fn hash(data: &[u8]) -> CryptoHash {
     CryptoHash::hash_bytes(data)
 }
 
+/// Calculates a hash of the bytes written to it, the same as `CryptoHash::hash_bytes` would of
+/// all of them concatenated. Lets a hash be computed while the bytes are streamed elsewhere.
+#[derive(Default)]
+pub struct CryptoHasher(sha2::Sha256);
+
+impl CryptoHasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub fn finalize(self) -> CryptoHash {
+        CryptoHash(self.0.finalize().into())
+    }
+}
+
+impl Write for CryptoHasher {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.update(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -282,6 +308,14 @@ mod tests {
         slice("CuoNgQBWsXnTqup6FY3UXNz6RRufnYyQVxx8HKZLUaRt", &[b'f', b'o', b'o']);
     }
 
+    #[test]
+    fn test_crypto_hasher() {
+        let mut hasher = CryptoHasher::default();
+        hasher.update(&[3, 0, 0, 0]);
+        hasher.write_all(b"foo").unwrap();
+        assert_eq!(CryptoHash::hash_borsh("foo"), hasher.finalize());
+    }
+
     #[test]
     fn test_base58_successes() {
         for (encoded, hash) in [
@@ -170,6 +170,46 @@ pub enum ProtocolFeature {
     // in order to calculate the rewards and kickouts for the chunk validators.
     // This feature introduces BlockHeaderV5.
     ChunkEndorsementsInBlockHeader,
+    /// The chunk producer signs the hash of the uncompressed state witness into every witness
+    /// part, so that the chunk validators can detect corruption after the reconstruction.
+    WitnessChecksum,
+    /// The chunk producer sends the state witnesses below
+    /// `PartialWitnessConfig::uncompressed_witness_threshold` without compressing them, marked
+    /// as uncompressed by a prefix the older nodes can't decode.
+    UncompressedSmallWitness,
+    /// The chunk producer signs the time at which it sends the witness parts into the parts,
+    /// see `PartialEncodedStateWitness::sent_at`. The parts of this format carry their owner as
+    /// well, and the parts are owned by the chunk validators sorted by account id instead of in
+    /// the order of the chunk validator assignment.
+    PartialWitnessSendTimestamp,
+    /// The chunk producer signs the merkle root over all the witness parts once instead of
+    /// signing every part, and every part carries the proof of its inclusion under the root.
+    PartialWitnessMerkleCommitment,
+    /// The chunk producer signs the protocol version it created the witness parts under into the
+    /// parts, see `PartialEncodedStateWitness::protocol_version`.
+    PartialWitnessProtocolVersion,
+    /// Raises the limit of the compressed state witness, see `WitnessSizeLimits`.
+    WitnessSizeLimitIncrease,
+    /// The chunk validators tell the chunk producer in the witness ack how many parts they had
+    /// received when they decoded the witness, see `ChunkStateWitnessAckV2`.
+    WitnessAckDecodeStats,
+    /// The witness parts above the max payload of a routed message are sent as a sequence of
+    /// fragments the older nodes can't decode, see `PartialEncodedStateWitnessFragment`.
+    PartialWitnessFragments,
+    /// The chunk producer sends the full encoded witness directly to the chunk validators with the
+    /// highest stake, see `PartialWitnessConfig::direct_full_witness_targets`. The older nodes
+    /// don't handle `FullEncodedStateWitness`.
+    DirectFullWitness,
+    /// The chunk validators request the witness parts they are missing from their owners, see
+    /// `PartialEncodedStateWitnessRequest`. The older nodes don't answer the requests.
+    PartialWitnessRequests,
+    /// The chunk validators announce to the chunk producers until when they can't receive the
+    /// witness parts, see `PartialWitnessConfig::announce_unavailability_on_shutdown`. The older
+    /// nodes don't handle the announcements.
+    WitnessReceiverStatus,
+    /// The chunk validators send the acks to the same chunk producer together, see
+    /// `PartialWitnessConfig::ack_batching_delay`. The older nodes don't handle the batches.
+    BatchedWitnessAcks,
 }
 
 impl ProtocolFeature {
@@ -245,6 +285,18 @@ impl ProtocolFeature {
             // that always enables this for mocknet (see config_mocknet function).
             ProtocolFeature::ShuffleShardAssignments => 143,
             ProtocolFeature::ChunkEndorsementsInBlockHeader => 145,
+            ProtocolFeature::UncompressedSmallWitness => 147,
+            // The witness hash is carried by the parts of the V2 format.
+            ProtocolFeature::WitnessChecksum | ProtocolFeature::PartialWitnessSendTimestamp => 148,
+            ProtocolFeature::PartialWitnessMerkleCommitment => 149,
+            ProtocolFeature::PartialWitnessProtocolVersion => 150,
+            ProtocolFeature::WitnessSizeLimitIncrease => 151,
+            ProtocolFeature::WitnessAckDecodeStats => 152,
+            ProtocolFeature::PartialWitnessFragments => 153,
+            ProtocolFeature::DirectFullWitness => 154,
+            ProtocolFeature::PartialWitnessRequests => 155,
+            ProtocolFeature::WitnessReceiverStatus => 156,
+            ProtocolFeature::BatchedWitnessAcks => 157,
         }
     }
 
@@ -0,0 +1,106 @@
+//! Cost of recognizing a witness part we already hold, measured in time and in heap allocations
+//! per received message. The part is read from the received bytes either by decoding the whole
+//! part, or by reading only its prefix in place, see `PartialEncodedStateWitnessPrefix`.
+
+#[macro_use]
+extern crate bencher;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bencher::{black_box, Bencher};
+use borsh::BorshDeserialize;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::partial_witness::{
+    PartialEncodedStateWitness, PartialEncodedStateWitnessPrefix,
+};
+use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::test_utils::create_test_signer;
+use near_primitives::types::EpochId;
+
+/// Roughly the part of a 4 MB witness distributed to 68 chunk validators.
+const PART_SIZE: usize = 60_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Parts held by the chunk validator, as the parts cache of the partial witness tracker does.
+type HeldParts = HashMap<(ChunkProductionKey, usize), Box<[u8]>>;
+
+/// Borsh-serialized part as received from the network, together with the parts already held,
+/// which include the same part.
+fn received_duplicate() -> (Vec<u8>, HeldParts) {
+    let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+    let part = PartialEncodedStateWitness::new(
+        EpochId::default(),
+        chunk_header,
+        3,
+        vec![7; PART_SIZE],
+        40 * PART_SIZE,
+        &create_test_signer("producer.near"),
+    );
+    let held_parts = HashMap::from([(
+        (part.chunk_production_key(), part.part_ord()),
+        Box::<[u8]>::from(part.part()),
+    )]);
+    (borsh::to_vec(&part).unwrap(), held_parts)
+}
+
+/// Checks by decoding the whole part first, which copies the part out of the received bytes.
+fn is_duplicate_decoded(bytes: &[u8], held_parts: &HeldParts) -> bool {
+    let part = PartialEncodedStateWitness::try_from_slice(bytes).unwrap();
+    held_parts
+        .get(&(part.chunk_production_key(), part.part_ord()))
+        .is_some_and(|held| **held == *part.part())
+}
+
+/// Checks on the prefix read in place from the received bytes.
+fn is_duplicate_prefix(bytes: &[u8], held_parts: &HeldParts) -> bool {
+    let prefix = PartialEncodedStateWitnessPrefix::read(bytes).unwrap();
+    held_parts
+        .get(&(prefix.chunk_production_key(), prefix.part_ord))
+        .is_some_and(|held| **held == *prefix.part)
+}
+
+/// Heap allocations made by one check of the duplicate.
+fn allocations_per_check(is_duplicate: fn(&[u8], &HeldParts) -> bool) -> usize {
+    let (bytes, held_parts) = received_duplicate();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    assert!(black_box(is_duplicate(&bytes, &held_parts)));
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn duplicate_check(bench: &mut Bencher, name: &str, is_duplicate: fn(&[u8], &HeldParts) -> bool) {
+    eprintln!("{name}: {} heap allocations per duplicate", allocations_per_check(is_duplicate));
+    let (bytes, held_parts) = received_duplicate();
+    bench.iter(|| black_box(is_duplicate(&bytes, &held_parts)));
+}
+
+fn duplicate_check_decoded(bench: &mut Bencher) {
+    duplicate_check(bench, "decoded", is_duplicate_decoded);
+}
+
+fn duplicate_check_prefix(bench: &mut Bencher) {
+    assert_eq!(allocations_per_check(is_duplicate_prefix), 0);
+    duplicate_check(bench, "prefix", is_duplicate_prefix);
+}
+
+benchmark_group!(benches, duplicate_check_decoded, duplicate_check_prefix);
+benchmark_main!(benches);
@@ -0,0 +1,60 @@
+#[macro_use]
+extern crate bencher;
+
+use bencher::{black_box, Bencher};
+
+use near_primitives::reed_solomon::{
+    reed_solomon_decode, reed_solomon_encode, PortableGalois8Field,
+};
+use reed_solomon_erasure::{galois_8, Field, ReedSolomon};
+
+/// Roughly the geometry of a witness distributed to 68 chunk validators.
+const DATA_PARTS: usize = 40;
+const PARITY_PARTS: usize = 28;
+const WITNESS_SIZE: usize = 4_000_000;
+
+fn witness_bytes() -> Vec<u8> {
+    (0..WITNESS_SIZE).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn encode<F: Field<Elem = u8>>(bench: &mut Bencher) {
+    let rs = ReedSolomon::<F>::new(DATA_PARTS, PARITY_PARTS).unwrap();
+    let bytes = witness_bytes();
+    bench.iter(|| {
+        let result = reed_solomon_encode(&rs, &bytes);
+        black_box(result);
+    });
+}
+
+fn decode<F: Field<Elem = u8>>(bench: &mut Bencher) {
+    let rs = ReedSolomon::<F>::new(DATA_PARTS, PARITY_PARTS).unwrap();
+    let (parts, encoded_length) = reed_solomon_encode(&rs, witness_bytes());
+    bench.iter(|| {
+        // Drop the first data parts so that they have to be reconstructed from the parity parts.
+        let mut parts = parts.clone();
+        for part in parts.iter_mut().take(PARITY_PARTS) {
+            *part = None;
+        }
+        let result: Vec<u8> = reed_solomon_decode(&rs, &mut parts, encoded_length).unwrap();
+        black_box(result);
+    });
+}
+
+fn encode_simd(bench: &mut Bencher) {
+    encode::<galois_8::Field>(bench);
+}
+
+fn encode_portable(bench: &mut Bencher) {
+    encode::<PortableGalois8Field>(bench);
+}
+
+fn decode_simd(bench: &mut Bencher) {
+    decode::<galois_8::Field>(bench);
+}
+
+fn decode_portable(bench: &mut Bencher) {
+    decode::<PortableGalois8Field>(bench);
+}
+
+benchmark_group!(benches, encode_simd, encode_portable, decode_simd, decode_portable);
+benchmark_main!(benches);
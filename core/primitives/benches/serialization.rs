@@ -4,11 +4,16 @@ extern crate bencher;
 use bencher::{black_box, Bencher};
 
 use borsh::BorshDeserialize;
+use bytesize::ByteSize;
 use near_crypto::{KeyType, PublicKey, Signature};
 use near_primitives::account::Account;
 use near_primitives::block::{genesis_chunks, Block};
+use near_primitives::challenge::PartialState;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::combine_hash;
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, EncodedChunkStateWitness,
+};
 use near_primitives::test_utils::account_new;
 use near_primitives::transaction::{
     Action, SignedTransaction, Transaction, TransactionV0, TransferAction,
@@ -148,6 +153,76 @@ fn combine_hash_bench(bench: &mut Bencher) {
     });
 }
 
+fn create_state_witness() -> ChunkStateWitness {
+    let mut witness = ChunkStateWitness::new_dummy(100, 0, CryptoHash::default());
+    witness.main_state_transition.base_state = PartialState::TrieValues(
+        (0..10_000u32).map(|i| i.to_le_bytes().repeat(64).into()).collect(),
+    );
+    witness.transactions = (0..100).map(|_| create_transaction()).collect();
+    witness.new_transactions = witness.transactions.clone();
+    witness
+}
+
+fn encode_state_witness(bench: &mut Bencher) {
+    let witness = create_state_witness();
+    bench.iter(|| {
+        let result = EncodedChunkStateWitness::encode(&witness).unwrap();
+        black_box(result);
+    });
+}
+
+fn encode_state_witness_with_section_sizes(bench: &mut Bencher) {
+    let witness = create_state_witness();
+    bench.iter(|| {
+        let result = EncodedChunkStateWitness::encode_with_section_sizes(&witness).unwrap();
+        black_box(result);
+    });
+}
+
+/// Threshold below which the witnesses are sent uncompressed, as in the default config.
+const UNCOMPRESSED_WITNESS_THRESHOLD: ByteSize = ByteSize::kib(16);
+
+/// Witness of an empty chunk, well below `UNCOMPRESSED_WITNESS_THRESHOLD`.
+fn create_small_state_witness() -> ChunkStateWitness {
+    ChunkStateWitness::new_dummy(100, 0, CryptoHash::default())
+}
+
+fn encode_decode_state_witness(
+    bench: &mut Bencher,
+    witness: ChunkStateWitness,
+    compression_threshold: ByteSize,
+) {
+    bench.iter(|| {
+        let (encoded, _, _) = EncodedChunkStateWitness::encode_with_compression_threshold(
+            &witness,
+            compression_threshold,
+        )
+        .unwrap();
+        let result = encoded.decode().unwrap();
+        black_box(result);
+    });
+}
+
+fn encode_decode_small_state_witness_compressed(bench: &mut Bencher) {
+    encode_decode_state_witness(bench, create_small_state_witness(), ByteSize::b(0));
+}
+
+fn encode_decode_small_state_witness_uncompressed(bench: &mut Bencher) {
+    encode_decode_state_witness(
+        bench,
+        create_small_state_witness(),
+        UNCOMPRESSED_WITNESS_THRESHOLD,
+    );
+}
+
+fn encode_decode_large_state_witness(bench: &mut Bencher) {
+    encode_decode_state_witness(bench, create_state_witness(), ByteSize::b(0));
+}
+
+fn encode_decode_large_state_witness_with_threshold(bench: &mut Bencher) {
+    encode_decode_state_witness(bench, create_state_witness(), UNCOMPRESSED_WITNESS_THRESHOLD);
+}
+
 benchmark_group!(
     benches,
     serialize_tx,
@@ -157,5 +232,11 @@ benchmark_group!(
     serialize_account,
     deserialize_account,
     combine_hash_bench,
+    encode_state_witness,
+    encode_state_witness_with_section_sizes,
+    encode_decode_small_state_witness_compressed,
+    encode_decode_small_state_witness_uncompressed,
+    encode_decode_large_state_witness,
+    encode_decode_large_state_witness_with_threshold,
 );
 benchmark_main!(benches);
@@ -0,0 +1,83 @@
+//! Peak heap memory and time of decoding a large state witness, with the decompressed witness
+//! either streamed into the deserialization or buffered in full first, see `WitnessDecodeMode`.
+
+#[macro_use]
+extern crate bencher;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bencher::{black_box, Bencher};
+use bytesize::ByteSize;
+use near_primitives::challenge::PartialState;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, EncodedChunkStateWitness, WitnessDecodeMode,
+};
+
+/// Size of the base state of the witness, close to the largest witnesses seen on mainnet.
+const WITNESS_SIZE: usize = 30_000_000;
+
+/// Tracks the heap memory currently allocated and its peak.
+struct PeakAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAllocator = PeakAllocator;
+
+/// Witness whose base state holds `WITNESS_SIZE` bytes in values of 1 KiB, encoded as the chunk
+/// producer does.
+fn encoded_large_witness() -> EncodedChunkStateWitness {
+    let mut witness = ChunkStateWitness::new_dummy(100, 0, CryptoHash::default());
+    witness.main_state_transition.base_state = PartialState::TrieValues(
+        (0..WITNESS_SIZE / 1000)
+            .map(|i| CryptoHash::hash_bytes(&i.to_le_bytes()).0.repeat(32).into())
+            .collect(),
+    );
+    EncodedChunkStateWitness::encode(&witness).unwrap().0
+}
+
+/// Heap memory allocated at peak by one decode, above the memory allocated before it.
+fn peak_decode_memory(encoded_witness: &EncodedChunkStateWitness, mode: WitnessDecodeMode) -> u64 {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    black_box(encoded_witness.decode_with_mode(mode).unwrap());
+    (PEAK.load(Ordering::Relaxed) - before) as u64
+}
+
+fn decode(bench: &mut Bencher, mode: WitnessDecodeMode) {
+    let encoded_witness = encoded_large_witness();
+    eprintln!(
+        "{mode:?}: peak heap memory {} to decode a witness of {} compressed to {}",
+        ByteSize::b(peak_decode_memory(&encoded_witness, mode)),
+        ByteSize::b(WITNESS_SIZE as u64),
+        ByteSize::b(encoded_witness.size_bytes() as u64),
+    );
+    bench.iter(|| black_box(encoded_witness.decode_with_mode(mode).unwrap()));
+}
+
+fn decode_large_witness_streamed(bench: &mut Bencher) {
+    decode(bench, WitnessDecodeMode::Streamed);
+}
+
+fn decode_large_witness_buffered(bench: &mut Bencher) {
+    decode(bench, WitnessDecodeMode::Buffered);
+}
+
+benchmark_group!(benches, decode_large_witness_streamed, decode_large_witness_buffered);
+benchmark_main!(benches);
@@ -1,4 +1,5 @@
 use crate::hash::CryptoHash;
+use crate::stateless_validation::estimate_size::{EstimateSize, SizeEstimator};
 use crate::types::{AccountId, EpochId};
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::{PublicKey, Signature};
@@ -41,6 +42,12 @@ impl PeerId {
     }
 }
 
+impl EstimateSize for PeerId {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_shared(&self.0);
+    }
+}
+
 impl fmt::Display for PeerId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -1,11 +1,11 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use itertools::Itertools;
-use reed_solomon_erasure::galois_8::ReedSolomon;
+use reed_solomon_erasure::{galois_8, Field, ReedSolomon};
 use std::io::Error;
 
 // Encode function takes a serializable object and returns a tuple of parts and length of encoded data
-pub fn reed_solomon_encode<T: BorshSerialize>(
-    rs: &ReedSolomon,
+pub fn reed_solomon_encode<T: BorshSerialize, F: Field<Elem = u8>>(
+    rs: &ReedSolomon<F>,
     data: T,
 ) -> (Vec<Option<Box<[u8]>>>, usize) {
     let mut bytes = borsh::to_vec(&data).unwrap();
@@ -34,8 +34,8 @@ pub fn reed_solomon_encode<T: BorshSerialize>(
 // Decode function is the reverse of encode function. It takes parts and length of encoded data
 // and returns the deserialized object.
 // Return an error if the reed solomon decoding fails or borsh deserialization fails.
-pub fn reed_solomon_decode<T: BorshDeserialize>(
-    rs: &ReedSolomon,
+pub fn reed_solomon_decode<T: BorshDeserialize, F: Field<Elem = u8>>(
+    rs: &ReedSolomon<F>,
     parts: &mut [Option<Box<[u8]>>],
     encoded_length: usize,
 ) -> Result<T, Error> {
@@ -56,3 +56,64 @@ pub fn reed_solomon_decode<T: BorshDeserialize>(
 pub fn reed_solomon_part_length(encoded_length: usize, data_parts: usize) -> usize {
     (encoded_length + data_parts - 1) / data_parts
 }
+
+/// The same GF(2^8) field as `galois_8::Field`, but with the slice operations implemented in
+/// plain Rust with a per-call multiplication table instead of the SIMD kernels `galois_8` uses
+/// when reed-solomon-erasure is built with `simd-accel`. Both fields produce identical parts,
+/// so they can be used interchangeably by the encoding and the decoding side.
+pub struct PortableGalois8Field;
+
+impl PortableGalois8Field {
+    fn mul_table(c: u8) -> [u8; 256] {
+        std::array::from_fn(|x| <galois_8::Field as Field>::mul(c, x as u8))
+    }
+}
+
+impl Field for PortableGalois8Field {
+    const ORDER: usize = <galois_8::Field as Field>::ORDER;
+    type Elem = u8;
+
+    fn add(a: u8, b: u8) -> u8 {
+        <galois_8::Field as Field>::add(a, b)
+    }
+
+    fn mul(a: u8, b: u8) -> u8 {
+        <galois_8::Field as Field>::mul(a, b)
+    }
+
+    fn div(a: u8, b: u8) -> u8 {
+        <galois_8::Field as Field>::div(a, b)
+    }
+
+    fn exp(a: u8, n: usize) -> u8 {
+        <galois_8::Field as Field>::exp(a, n)
+    }
+
+    fn zero() -> u8 {
+        <galois_8::Field as Field>::zero()
+    }
+
+    fn one() -> u8 {
+        <galois_8::Field as Field>::one()
+    }
+
+    fn nth_internal(n: usize) -> u8 {
+        <galois_8::Field as Field>::nth_internal(n)
+    }
+
+    fn mul_slice(c: u8, input: &[u8], out: &mut [u8]) {
+        assert_eq!(input.len(), out.len());
+        let table = Self::mul_table(c);
+        for (out, input) in out.iter_mut().zip(input) {
+            *out = table[*input as usize];
+        }
+    }
+
+    fn mul_slice_add(c: u8, input: &[u8], out: &mut [u8]) {
+        assert_eq!(input.len(), out.len());
+        let table = Self::mul_table(c);
+        for (out, input) in out.iter_mut().zip(input) {
+            *out ^= table[*input as usize];
+        }
+    }
+}
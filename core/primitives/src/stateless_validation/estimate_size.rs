@@ -0,0 +1,306 @@
+//! Estimation of the memory retained by the witness-related objects.
+//!
+//! The memory budgets, the metrics and the flow control of the witness distribution all need to
+//! know how many bytes an object keeps alive. `EstimateSize` answers it the same way for all of
+//! them: the size of the object itself plus everything it owns on the heap, including the buffers
+//! behind the vectors and the strings. The payloads shared behind an `Arc`, e.g. a witness part
+//! kept in several caches at once, are counted once per `SizeEstimator`, so that the objects
+//! sharing them can be summed up with one estimator.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use near_crypto::PublicKey;
+use near_primitives_core::types::AccountId;
+
+use crate::merkle::MerklePathItem;
+
+/// Size of the reference counts allocated in front of the value of an `Arc`.
+const ARC_COUNTS_SIZE: usize = 2 * std::mem::size_of::<usize>();
+
+pub trait EstimateSize {
+    /// Adds the bytes the object owns on the heap to `estimator`, without the size of the object
+    /// itself, which is accounted by its owner.
+    fn add_heap_size(&self, estimator: &mut SizeEstimator);
+
+    /// Memory retained by the object, including the object itself.
+    fn estimate_size(&self) -> usize {
+        let mut estimator = SizeEstimator::new();
+        estimator.add(self);
+        estimator.total()
+    }
+}
+
+/// Sums up the memory retained by several objects, counting every shared payload once.
+#[derive(Debug, Default)]
+pub struct SizeEstimator {
+    total: usize,
+    /// Addresses of the shared payloads counted so far, or skipped, see `skip_shared`.
+    shared: HashSet<usize>,
+}
+
+impl SizeEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the object together with everything it owns on the heap.
+    pub fn add<T: EstimateSize + ?Sized>(&mut self, object: &T) {
+        self.total += std::mem::size_of_val(object);
+        object.add_heap_size(self);
+    }
+
+    /// Adds a heap allocation of `bytes`.
+    pub fn add_bytes(&mut self, bytes: usize) {
+        self.total += bytes;
+    }
+
+    /// Adds the value behind the `Arc` together with the reference counts, unless it was already
+    /// counted by this estimator.
+    pub fn add_shared<T: EstimateSize + ?Sized>(&mut self, shared: &Arc<T>) {
+        if !self.shared.insert(shared_address(shared)) {
+            return;
+        }
+        let value: &T = shared;
+        let allocation = ARC_COUNTS_SIZE + std::mem::size_of_val(value);
+        self.total += allocation.next_multiple_of(std::mem::align_of::<usize>());
+        value.add_heap_size(self);
+    }
+
+    /// Excludes the value behind the `Arc` from the estimate, e.g. because another owner keeps it
+    /// alive and it won't be freed with the objects estimated.
+    pub fn skip_shared<T: ?Sized>(&mut self, shared: &Arc<T>) {
+        self.shared.insert(shared_address(shared));
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+fn shared_address<T: ?Sized>(shared: &Arc<T>) -> usize {
+    Arc::as_ptr(shared) as *const u8 as usize
+}
+
+impl EstimateSize for usize {
+    fn add_heap_size(&self, _estimator: &mut SizeEstimator) {}
+}
+
+impl EstimateSize for [u8] {
+    fn add_heap_size(&self, _estimator: &mut SizeEstimator) {}
+}
+
+impl EstimateSize for Box<[u8]> {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_bytes(self.len());
+    }
+}
+
+impl EstimateSize for String {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_bytes(self.capacity());
+    }
+}
+
+impl EstimateSize for AccountId {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_bytes(self.len());
+    }
+}
+
+impl<T: EstimateSize> EstimateSize for Vec<T> {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_bytes(self.capacity() * std::mem::size_of::<T>());
+        for item in self {
+            item.add_heap_size(estimator);
+        }
+    }
+}
+
+impl<T: EstimateSize> EstimateSize for Option<T> {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        if let Some(value) = self {
+            value.add_heap_size(estimator);
+        }
+    }
+}
+
+impl EstimateSize for MerklePathItem {
+    fn add_heap_size(&self, _estimator: &mut SizeEstimator) {}
+}
+
+impl EstimateSize for PublicKey {
+    fn add_heap_size(&self, _estimator: &mut SizeEstimator) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::Arc;
+
+    use near_primitives_core::hash::CryptoHash;
+    use near_time::Utc;
+
+    use super::{EstimateSize, SizeEstimator};
+    use crate::challenge::PartialState;
+    use crate::network::PeerId;
+    use crate::stateless_validation::partial_witness::{
+        ChunkValidatorsDigest, PartialEncodedStateWitness,
+    };
+    use crate::stateless_validation::state_witness::{ChunkStateWitness, EncodedChunkStateWitness};
+    use crate::test_utils::create_test_signer;
+    use crate::types::EpochId;
+
+    /// Counts the bytes allocated and not freed yet by the current thread, so that the tests
+    /// running in parallel don't disturb each other.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn record(bytes: isize) {
+        // The thread local may already be destroyed while the thread exits.
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record(layout.size() as isize);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            record(-(layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    /// Memory retained by the object returned by `build`, measured as the size of the object
+    /// plus the bytes allocated by `build` and not freed by the time it returns. `build` is run
+    /// once before measuring, so that the lazily initialized statics it touches are not counted.
+    fn measure_size<T>(build: impl Fn() -> T) -> (T, usize) {
+        drop(build());
+        let before = ALLOCATED.with(|allocated| allocated.get());
+        let object = build();
+        let allocated = ALLOCATED.with(|allocated| allocated.get()) - before;
+        (object, std::mem::size_of::<T>() + allocated as usize)
+    }
+
+    #[test]
+    fn part_size_matches_allocations() {
+        let signer = create_test_signer("alice.near");
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        let (part, measured) = measure_size(|| {
+            PartialEncodedStateWitness::new_v2(
+                EpochId::default(),
+                chunk_header.clone(),
+                3,
+                "bob.near".parse().unwrap(),
+                vec![7; 1000],
+                1000,
+                None,
+                Utc::UNIX_EPOCH,
+                &signer,
+            )
+        });
+        assert_eq!(part.estimate_size(), measured);
+
+        let (parts, measured) = measure_size(|| {
+            let parts = (0..5)
+                .map(|part_ord| {
+                    let owner = format!("validator{part_ord}.near").parse().unwrap();
+                    (owner, Arc::from(vec![part_ord as u8; 1000]))
+                })
+                .collect::<Vec<_>>();
+            let chunk_validators = ChunkValidatorsDigest::new(
+                &parts.iter().map(|(owner, _)| owner.clone()).collect::<Vec<_>>(),
+            );
+            PartialEncodedStateWitness::new_committed_parts(
+                EpochId::default(),
+                &chunk_header,
+                parts,
+                5000,
+                None,
+                chunk_validators,
+                Utc::UNIX_EPOCH,
+                None,
+                &signer,
+            )
+        });
+        assert_eq!(parts.estimate_size(), measured);
+    }
+
+    #[test]
+    fn shared_part_is_counted_once() {
+        let signer = create_test_signer("alice.near");
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        let part = PartialEncodedStateWitness::new_v2(
+            EpochId::default(),
+            chunk_header,
+            3,
+            "bob.near".parse().unwrap(),
+            vec![7; 1000],
+            1000,
+            None,
+            Utc::UNIX_EPOCH,
+            &signer,
+        );
+        let clones = vec![part.clone(), part.clone()];
+        let mut estimator = SizeEstimator::new();
+        estimator.add(&part);
+        let single = estimator.total();
+        estimator.add(&clones);
+        // The clones only add their own inline size and the vector holding them, the payload
+        // and the owners are the same as the ones of the part.
+        let owner_and_differentiator = "bob.near".len() + "PartialEncodedStateWitness".len();
+        assert_eq!(
+            estimator.total(),
+            single
+                + std::mem::size_of::<Vec<PartialEncodedStateWitness>>()
+                + clones.capacity() * std::mem::size_of::<PartialEncodedStateWitness>()
+                + 2 * owner_and_differentiator
+        );
+
+        // Skipping the payload leaves out the payload and its reference counts.
+        let mut estimator = SizeEstimator::new();
+        estimator.skip_shared(part.shared_part());
+        estimator.add(&part);
+        assert!(estimator.total() + 1000 <= single);
+
+        let peer_id = PeerId::new(signer.public_key());
+        let public_key_size = peer_id.estimate_size() - std::mem::size_of::<PeerId>();
+        let peers = vec![peer_id.clone(), peer_id.clone(), peer_id];
+        assert_eq!(
+            peers.estimate_size(),
+            std::mem::size_of::<Vec<PeerId>>()
+                + peers.capacity() * std::mem::size_of::<PeerId>()
+                + public_key_size
+        );
+    }
+
+    #[test]
+    fn encoded_witness_size_matches_allocations() {
+        let (encoded_witness, measured) = measure_size(|| {
+            EncodedChunkStateWitness::from_boxed_slice(vec![7; 1000].into_boxed_slice())
+        });
+        assert_eq!(encoded_witness.estimate_size(), measured);
+    }
+
+    #[test]
+    fn witness_size_is_close_to_allocations() {
+        let (witness, measured) = measure_size(|| {
+            let mut witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+            let trie_value = vec![0x5a; 1 << 20];
+            witness.main_state_transition.base_state =
+                PartialState::TrieValues(vec![trie_value.into()]);
+            witness
+        });
+        let estimated = witness.estimate_size();
+        assert!(estimated.abs_diff(measured) * 100 < measured, "{estimated} vs {measured}");
+    }
+}
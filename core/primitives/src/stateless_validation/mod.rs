@@ -4,6 +4,7 @@ use crate::types::EpochId;
 
 pub mod chunk_endorsement;
 pub mod chunk_endorsements_bitmap;
+pub mod estimate_size;
 pub mod partial_witness;
 pub mod state_witness;
 pub mod stored_chunk_state_transition_data;
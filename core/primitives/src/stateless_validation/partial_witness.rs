@@ -1,14 +1,21 @@
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
+use super::estimate_size::{EstimateSize, SizeEstimator};
+use super::state_witness::EncodedChunkStateWitness;
 use super::{ChunkProductionKey, SignatureDifferentiator};
+use crate::merkle::{merklize, verify_path, MerklePath};
 use crate::sharding::ShardChunkHeader;
 use crate::types::EpochId;
 use crate::validator_signer::ValidatorSigner;
+use crate::version::{ProtocolFeature, ProtocolVersion};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytesize::ByteSize;
 use near_crypto::{PublicKey, Signature};
-use near_primitives_core::types::{BlockHeight, ShardId};
+use near_primitives_core::hash::{hash, CryptoHash};
+use near_primitives_core::types::{AccountId, BlockHeight, ShardId};
 use near_schema_checker_lib::ProtocolSchema;
+use near_time::Utc;
 
 /// Represents max allowed size of the compressed state witness,
 /// corresponds to EncodedChunkStateWitness struct size.
@@ -18,33 +25,118 @@ use near_schema_checker_lib::ProtocolSchema;
 pub const MAX_COMPRESSED_STATE_WITNESS_SIZE: ByteSize =
     ByteSize::mib(if cfg!(feature = "test_features") { 512 } else { 48 });
 
+/// Max allowed size of the compressed state witness once
+/// `ProtocolFeature::WitnessSizeLimitIncrease` is enabled.
+pub const MAX_COMPRESSED_STATE_WITNESS_SIZE_V2: ByteSize =
+    ByteSize::mib(if cfg!(feature = "test_features") { 512 } else { 60 });
+
+/// Grace band of the size limit at the protocol version right before
+/// `ProtocolFeature::WitnessSizeLimitIncrease`, which covers the increased limit.
+const WITNESS_SIZE_LIMIT_INCREASE_GRACE_PERCENT: u64 = 25;
+
+/// Size limits of the compressed state witness accepted by the chunk validators at a protocol
+/// version.
+///
+/// The witnesses above the limit are still accepted within the grace band, and rejected beyond
+/// it. The grace band is only open at the protocol version right before the limit is raised:
+/// the nodes don't switch to the raised limit at the same time, so the validators accept the
+/// witnesses of the producers which already did instead of losing their endorsements. Once the
+/// raised limit is in force, the grace band closes again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WitnessSizeLimits {
+    /// Size the witnesses are expected to stay below.
+    pub limit: ByteSize,
+    /// Percentage of the limit by which a witness may exceed it and still be accepted.
+    pub grace_percent: u64,
+}
+
+/// Where the size of a witness falls relative to `WitnessSizeLimits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessSizeBand {
+    WithinLimit,
+    /// Above the limit but within the grace band, accepted.
+    GraceBand,
+    /// Beyond the grace band, rejected.
+    TooLarge,
+}
+
+impl WitnessSizeLimits {
+    pub fn for_protocol_version(protocol_version: ProtocolVersion) -> Self {
+        let increase = ProtocolFeature::WitnessSizeLimitIncrease;
+        if increase.enabled(protocol_version) {
+            Self { limit: MAX_COMPRESSED_STATE_WITNESS_SIZE_V2, grace_percent: 0 }
+        } else if increase.enabled(protocol_version + 1) {
+            Self {
+                limit: MAX_COMPRESSED_STATE_WITNESS_SIZE,
+                grace_percent: WITNESS_SIZE_LIMIT_INCREASE_GRACE_PERCENT,
+            }
+        } else {
+            Self { limit: MAX_COMPRESSED_STATE_WITNESS_SIZE, grace_percent: 0 }
+        }
+    }
+
+    /// Size above which the witnesses are rejected.
+    pub fn max_accepted_size(&self) -> ByteSize {
+        let limit = self.limit.as_u64();
+        ByteSize::b(limit + limit * self.grace_percent / 100)
+    }
+
+    pub fn band(&self, size: usize) -> WitnessSizeBand {
+        let size = size as u64;
+        if size <= self.limit.as_u64() {
+            WitnessSizeBand::WithinLimit
+        } else if size <= self.max_accepted_size().as_u64() {
+            WitnessSizeBand::GraceBand
+        } else {
+            WitnessSizeBand::TooLarge
+        }
+    }
+}
+
 /// Represents the Reed Solomon erasure encoded parts of the `EncodedChunkStateWitness`.
 /// These are created and signed by the chunk producer and sent to the chunk validators.
 /// Note that the chunk validators do not require all the parts of the state witness to
 /// reconstruct the full state witness due to the Reed Solomon erasure encoding.
 #[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
 pub struct PartialEncodedStateWitness {
-    inner: PartialEncodedStateWitnessInner,
+    inner: VersionedPartialEncodedStateWitnessInner,
     pub signature: Signature,
 }
 
 impl Debug for PartialEncodedStateWitness {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PartialEncodedStateWitness")
-            .field("epoch_id", &self.inner.epoch_id)
-            .field("shard_id", &self.inner.shard_id)
-            .field("height_created", &self.inner.height_created)
-            .field("part_ord", &self.inner.part_ord)
+            .field("epoch_id", &self.common().epoch_id)
+            .field("shard_id", &self.common().shard_id)
+            .field("height_created", &self.common().height_created)
+            .field("part_ord", &self.common().part_ord)
+            .field("owner", &self.owner())
+            .field("part_size", &self.part_size())
+            .field("part_hash", &self.short_part_hash())
             .finish()
     }
 }
 
+impl Display for PartialEncodedStateWitness {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "part {} of shard {} at height {} ({} bytes, {})",
+            self.common().part_ord,
+            self.common().shard_id,
+            self.common().height_created,
+            self.part_size(),
+            self.short_part_hash()
+        )
+    }
+}
+
 impl PartialEncodedStateWitness {
     pub fn new(
         epoch_id: EpochId,
         chunk_header: ShardChunkHeader,
         part_ord: usize,
-        part: Vec<u8>,
+        part: impl Into<Arc<[u8]>>,
         encoded_length: usize,
         signer: &ValidatorSigner,
     ) -> Self {
@@ -52,47 +144,493 @@ impl PartialEncodedStateWitness {
             epoch_id,
             chunk_header,
             part_ord,
-            part,
+            part.into(),
             encoded_length,
         );
         let signature = signer.sign_partial_encoded_state_witness(&inner);
-        Self { inner, signature }
+        Self { inner: VersionedPartialEncodedStateWitnessInner::V1(inner), signature }
+    }
+
+    /// Creates the part in the V2 format, see `ProtocolFeature::PartialWitnessSendTimestamp`.
+    /// Unlike V1, the part carries its owner and the witness hash.
+    pub fn new_v2(
+        epoch_id: EpochId,
+        chunk_header: ShardChunkHeader,
+        part_ord: usize,
+        owner: AccountId,
+        part: impl Into<Arc<[u8]>>,
+        encoded_length: usize,
+        witness_hash: Option<CryptoHash>,
+        sent_at: Utc,
+        signer: &ValidatorSigner,
+    ) -> Self {
+        let inner = PartialEncodedStateWitnessInnerV2 {
+            inner: PartialEncodedStateWitnessInner::new(
+                epoch_id,
+                chunk_header,
+                part_ord,
+                part.into(),
+                encoded_length,
+            ),
+            owner,
+            witness_hash,
+            sent_at: sent_at.unix_timestamp_nanos() as u64,
+        };
+        let signature = signer.sign_partial_encoded_state_witness_v2(&inner);
+        Self { inner: VersionedPartialEncodedStateWitnessInner::V2(inner), signature }
+    }
+
+    /// Creates all the parts of the witness in the V3 format, see
+    /// `ProtocolFeature::PartialWitnessMerkleCommitment`. The chunk producer signs the merkle
+    /// root over the parts once, and every part carries the proof of its inclusion under the
+    /// root. `parts` are the owners and the parts ordered by `part_ord`. The parts are in the V4
+    /// format if `protocol_version` is set, see `ProtocolFeature::PartialWitnessProtocolVersion`.
+    pub fn new_committed_parts(
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+        parts: Vec<(AccountId, Arc<[u8]>)>,
+        encoded_length: usize,
+        witness_hash: Option<CryptoHash>,
+        chunk_validators: ChunkValidatorsDigest,
+        sent_at: Utc,
+        protocol_version: Option<ProtocolVersion>,
+        signer: &ValidatorSigner,
+    ) -> Vec<Self> {
+        let leaves = parts
+            .iter()
+            .enumerate()
+            .map(|(part_ord, (owner, part))| PartialWitnessPartLeaf::new(part_ord, owner, part))
+            .collect::<Vec<_>>();
+        let (parts_root, part_proofs) = merklize(&leaves);
+        let commitment = PartialWitnessPartsCommitment {
+            epoch_id,
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+            num_parts: parts.len(),
+            parts_root,
+            encoded_length,
+            witness_hash,
+            chunk_validators,
+            sent_at: sent_at.unix_timestamp_nanos() as u64,
+            signature_differentiator: "PartialWitnessPartsCommitment".to_owned(),
+        };
+        let signature = match protocol_version {
+            Some(protocol_version) => {
+                signer.sign_partial_witness_parts_commitment_v2(&PartialWitnessPartsCommitmentV2 {
+                    commitment: commitment.clone(),
+                    protocol_version,
+                })
+            }
+            None => signer.sign_partial_witness_parts_commitment(&commitment),
+        };
+        parts
+            .into_iter()
+            .zip(part_proofs)
+            .enumerate()
+            .map(|(part_ord, ((owner, part), part_proof))| {
+                let inner = PartialEncodedStateWitnessInner::new(
+                    epoch_id,
+                    chunk_header.clone(),
+                    part_ord,
+                    part,
+                    encoded_length,
+                );
+                let inner = PartialEncodedStateWitnessInnerV3 {
+                    inner,
+                    owner,
+                    witness_hash,
+                    sent_at: commitment.sent_at,
+                    num_parts: commitment.num_parts,
+                    parts_root,
+                    chunk_validators,
+                    part_proof,
+                };
+                let inner = match protocol_version {
+                    Some(protocol_version) => VersionedPartialEncodedStateWitnessInner::V4(
+                        PartialEncodedStateWitnessInnerV4 {
+                            inner,
+                            protocol_version,
+                            signer: signer.validator_id().clone(),
+                        },
+                    ),
+                    None => VersionedPartialEncodedStateWitnessInner::V3(inner),
+                };
+                Self { inner, signature: signature.clone() }
+            })
+            .collect()
+    }
+
+    /// Fields shared by all the versions of the part.
+    fn common(&self) -> &PartialEncodedStateWitnessInner {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(inner) => inner,
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => &inner.inner,
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => &inner.inner,
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => &inner.inner.inner,
+        }
+    }
+
+    /// Fields of the parts committed to by the merkle root, present in the V3 and V4 parts.
+    fn committed(&self) -> Option<&PartialEncodedStateWitnessInnerV3> {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_)
+            | VersionedPartialEncodedStateWitnessInner::V2(_) => None,
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => Some(inner),
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => Some(&inner.inner),
+        }
     }
 
     pub fn chunk_production_key(&self) -> ChunkProductionKey {
         ChunkProductionKey {
-            shard_id: self.inner.shard_id,
-            epoch_id: self.inner.epoch_id,
-            height_created: self.inner.height_created,
+            shard_id: self.common().shard_id,
+            epoch_id: self.common().epoch_id,
+            height_created: self.common().height_created,
         }
     }
 
+    /// Checks the signature of the part. For the V3 and V4 parts the signature covers the
+    /// commitment to all the parts of the witness, so the inclusion proof of the part is checked
+    /// as well.
     pub fn verify(&self, public_key: &PublicKey) -> bool {
-        let data = borsh::to_vec(&self.inner).unwrap();
-        self.signature.verify(&data, public_key)
+        let data = match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(inner) => borsh::to_vec(inner),
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => borsh::to_vec(inner),
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => {
+                if !inner.verify_part_proof() {
+                    return false;
+                }
+                borsh::to_vec(&inner.commitment())
+            }
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => {
+                if !inner.inner.verify_part_proof() {
+                    return false;
+                }
+                borsh::to_vec(&inner.commitment())
+            }
+        };
+        self.signature.verify(&data.unwrap(), public_key)
+    }
+
+    /// Commitment of the chunk producer to all the parts of the witness, present in the V3 and V4
+    /// parts only. It is signed by `self.signature`, together with the protocol version in the V4
+    /// parts. Two commitments with valid signatures for the same chunk and different `parts_root`
+    /// prove that the chunk producer equivocated.
+    pub fn parts_commitment(&self) -> Option<PartialWitnessPartsCommitment> {
+        self.committed().map(|inner| inner.commitment())
+    }
+
+    /// Merkle root over all the parts of the witness, present in the V3 and V4 parts only.
+    pub fn parts_root(&self) -> Option<&CryptoHash> {
+        self.committed().map(|inner| &inner.parts_root)
+    }
+
+    /// Number of parts committed to by the chunk producer, present in the V3 and V4 parts only.
+    pub fn num_parts(&self) -> Option<usize> {
+        self.committed().map(|inner| inner.num_parts)
+    }
+
+    /// Chunk producer's view of the chunk validators of the chunk, present in the V3 and V4 parts
+    /// only.
+    pub fn chunk_validators_digest(&self) -> Option<&ChunkValidatorsDigest> {
+        self.committed().map(|inner| &inner.chunk_validators)
+    }
+
+    /// Protocol version the chunk producer created the part under, present in the V4 parts only,
+    /// see `ProtocolFeature::PartialWitnessProtocolVersion`. It tells the chunk validators which
+    /// rules the chunk producer applied, which matters for the parts created around a protocol
+    /// upgrade and delivered late.
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_)
+            | VersionedPartialEncodedStateWitnessInner::V2(_)
+            | VersionedPartialEncodedStateWitnessInner::V3(_) => None,
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => Some(inner.protocol_version),
+        }
+    }
+
+    /// Account which claims to have signed the part, present in the V4 parts only. It isn't
+    /// covered by the signature, it only tells the receivers which key to check a part failing
+    /// the check against the chunk producer's key with. If that key verifies the signature, the
+    /// claimed account signed the part for a chunk it doesn't produce.
+    pub fn claimed_signer(&self) -> Option<&AccountId> {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_)
+            | VersionedPartialEncodedStateWitnessInner::V2(_)
+            | VersionedPartialEncodedStateWitnessInner::V3(_) => None,
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => Some(&inner.signer),
+        }
     }
 
     pub fn part_ord(&self) -> usize {
-        self.inner.part_ord
+        self.common().part_ord
+    }
+
+    /// Chunk validator that receives the part directly from the chunk producer and forwards
+    /// it to the other chunk validators, signed into the parts since the V2 format. The V1 parts
+    /// don't carry it, their owner is the chunk validator at `part_ord` in the order of the
+    /// chunk validator assignment.
+    pub fn owner(&self) -> Option<&AccountId> {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_) => None,
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => Some(&inner.owner),
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => Some(&inner.owner),
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => Some(&inner.inner.owner),
+        }
+    }
+
+    /// Hash of the borsh-serialized witness before the compression, set by the chunk producer
+    /// once `ProtocolFeature::WitnessChecksum` is enabled. The V1 parts don't carry it.
+    pub fn witness_hash(&self) -> Option<&CryptoHash> {
+        match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_) => None,
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => inner.witness_hash.as_ref(),
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => inner.witness_hash.as_ref(),
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => {
+                inner.inner.witness_hash.as_ref()
+            }
+        }
+    }
+
+    /// Time at which the chunk producer signed the part, set by the chunk producer once
+    /// `ProtocolFeature::PartialWitnessSendTimestamp` is enabled.
+    ///
+    /// The timestamp comes from the clock of the chunk producer, which is neither synchronized
+    /// with ours nor trusted, so it is only meant for the metrics. It must never be used to decide
+    /// anything about the part or the witness, e.g. whether the part is too old to be accepted.
+    pub fn sent_at(&self) -> Option<Utc> {
+        let sent_at = match &self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(_) => return None,
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => inner.sent_at,
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => inner.sent_at,
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => inner.inner.sent_at,
+        };
+        Utc::from_unix_timestamp_nanos(sent_at as i128).ok()
     }
 
     pub fn part_size(&self) -> usize {
-        self.inner.part.len()
+        self.common().part.len()
+    }
+
+    /// Length of the encoded witness the part was cut from, the same in all the parts of the
+    /// witness.
+    pub fn encoded_length(&self) -> usize {
+        self.common().encoded_length
+    }
+
+    pub fn part(&self) -> &[u8] {
+        &self.common().part
+    }
+
+    /// The bytes of the part, shared by all the clones of the part.
+    pub fn shared_part(&self) -> &Arc<[u8]> {
+        &self.common().part
+    }
+
+    /// Prefix of the part hash, enough to tell parts apart in the logs without printing the
+    /// part itself, which may be hundreds of KB.
+    fn short_part_hash(&self) -> String {
+        let mut part_hash = hash(&self.common().part).to_string();
+        part_hash.truncate(8);
+        part_hash
     }
 
     /// Decomposes the partial witness to return (part_ord, part, encoded_length)
-    pub fn decompose(self) -> (usize, Box<[u8]>, usize) {
-        (self.inner.part_ord, self.inner.part, self.inner.encoded_length)
+    pub fn decompose(self) -> (usize, Arc<[u8]>, usize) {
+        let inner = match self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(inner) => inner,
+            VersionedPartialEncodedStateWitnessInner::V2(inner) => inner.inner,
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => inner.inner,
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => inner.inner.inner,
+        };
+        (inner.part_ord, inner.part, inner.encoded_length)
+    }
+
+    /// Converts the part to the V1 wire format if it is a V1 part, returns it back otherwise.
+    pub fn into_v1(self) -> Result<PartialEncodedStateWitnessV1, Self> {
+        match self.inner {
+            VersionedPartialEncodedStateWitnessInner::V1(inner) => {
+                Ok(PartialEncodedStateWitnessV1 { inner, signature: self.signature })
+            }
+            _ => Err(self),
+        }
+    }
+}
+
+/// Part in the V1 format as sent before the parts were versioned, in
+/// `RoutedMessageBody::PartialEncodedStateWitness` and
+/// `RoutedMessageBody::PartialEncodedStateWitnessForward`.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessV1 {
+    inner: PartialEncodedStateWitnessInner,
+    pub signature: Signature,
+}
+
+impl From<PartialEncodedStateWitnessV1> for PartialEncodedStateWitness {
+    fn from(partial_witness: PartialEncodedStateWitnessV1) -> Self {
+        Self {
+            inner: VersionedPartialEncodedStateWitnessInner::V1(partial_witness.inner),
+            signature: partial_witness.signature,
+        }
+    }
+}
+
+impl EstimateSize for PartialEncodedStateWitness {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        let common = self.common();
+        if let Some(owner) = self.owner() {
+            owner.add_heap_size(estimator);
+        }
+        estimator.add_shared(&common.part);
+        common.signature_differentiator.add_heap_size(estimator);
+        if let Some(committed) = self.committed() {
+            committed.part_proof.add_heap_size(estimator);
+        }
+        if let Some(signer) = self.claimed_signer() {
+            signer.add_heap_size(estimator);
+        }
+    }
+}
+
+/// Fragment of a `PartialEncodedStateWitness` too large to be sent in one routed message. The part
+/// is sent as a sequence of fragments of its borsh serialization, see `split`, which the receiver
+/// joins back before handling the part as if it was received whole. The fragments aren't signed,
+/// the joined part must match `part_hash` and is then validated, signature included, like any
+/// other part.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessFragment {
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub part_ord: usize,
+    /// Whether the part is forwarded by its owner rather than sent by the chunk producer.
+    pub forward: bool,
+    /// Hash of the borsh serialized part.
+    pub part_hash: CryptoHash,
+    pub fragment_ord: usize,
+    pub num_fragments: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl Debug for PartialEncodedStateWitnessFragment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartialEncodedStateWitnessFragment")
+            .field("epoch_id", &self.epoch_id)
+            .field("shard_id", &self.shard_id)
+            .field("height_created", &self.height_created)
+            .field("part_ord", &self.part_ord)
+            .field("forward", &self.forward)
+            .field("part_hash", &self.part_hash)
+            .field("fragment_ord", &self.fragment_ord)
+            .field("num_fragments", &self.num_fragments)
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl PartialEncodedStateWitnessFragment {
+    /// Splits the borsh serialization of the part into fragments of at most
+    /// `max_fragment_size` bytes.
+    pub fn split(
+        partial_witness: &PartialEncodedStateWitness,
+        forward: bool,
+        max_fragment_size: usize,
+    ) -> Vec<Self> {
+        let bytes = borsh::to_vec(partial_witness).unwrap();
+        let part_hash = hash(&bytes);
+        let num_fragments = bytes.len().div_ceil(max_fragment_size);
+        let common = partial_witness.common();
+        bytes
+            .chunks(max_fragment_size)
+            .enumerate()
+            .map(|(fragment_ord, fragment)| Self {
+                epoch_id: common.epoch_id,
+                shard_id: common.shard_id,
+                height_created: common.height_created,
+                part_ord: common.part_ord,
+                forward,
+                part_hash,
+                fragment_ord,
+                num_fragments,
+                bytes: fragment.to_vec(),
+            })
+            .collect()
+    }
+
+    pub fn chunk_production_key(&self) -> ChunkProductionKey {
+        ChunkProductionKey {
+            shard_id: self.shard_id,
+            epoch_id: self.epoch_id,
+            height_created: self.height_created,
+        }
+    }
+}
+
+/// Fields at the start of a borsh-serialized `PartialEncodedStateWitness`, read in place from the
+/// received bytes. Every version of the part starts with `PartialEncodedStateWitnessInner`, so
+/// these fields are laid out the same way in all the versions and can be read without decoding
+/// the rest of the part, in particular without copying the part itself. The owner of the part
+/// follows the inner fields in the versions carrying it, so it is not part of the prefix.
+///
+/// Nothing here is validated, the signature is further down the message. It is only good for
+/// telling that we already hold the exact same part, which can then be dropped for free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialEncodedStateWitnessPrefix<'a> {
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub part_ord: usize,
+    pub part: &'a [u8],
+}
+
+impl<'a> PartialEncodedStateWitnessPrefix<'a> {
+    /// Reads the prefix of the borsh-serialized part `bytes`, without allocating.
+    pub fn read(bytes: &'a [u8]) -> std::io::Result<Self> {
+        let mut rest = bytes;
+        // Tag of `VersionedPartialEncodedStateWitnessInner`, V1 to V4.
+        let version = u8::deserialize(&mut rest)?;
+        if version > 3 {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        let epoch_id = EpochId::deserialize(&mut rest)?;
+        let shard_id = ShardId::deserialize(&mut rest)?;
+        let height_created = BlockHeight::deserialize(&mut rest)?;
+        let part_ord = usize::deserialize(&mut rest)?;
+        let part = read_borsh_bytes(&mut rest)?;
+        Ok(Self { epoch_id, shard_id, height_created, part_ord, part })
+    }
+
+    pub fn chunk_production_key(&self) -> ChunkProductionKey {
+        ChunkProductionKey {
+            shard_id: self.shard_id,
+            epoch_id: self.epoch_id,
+            height_created: self.height_created,
+        }
+    }
+}
+
+/// Reads a borsh-serialized byte sequence, i.e. its length followed by the bytes, and returns
+/// the bytes as a slice of `rest`.
+fn read_borsh_bytes<'a>(rest: &mut &'a [u8]) -> std::io::Result<&'a [u8]> {
+    let len = u32::deserialize(rest)? as usize;
+    if rest.len() < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
     }
+    let (bytes, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(bytes)
 }
 
+/// Signed part of the V1 format. The layout predates the versioning of the parts and must not
+/// change, the fields added since are in the later versions.
 #[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
 pub struct PartialEncodedStateWitnessInner {
     epoch_id: EpochId,
     shard_id: ShardId,
     height_created: BlockHeight,
     part_ord: usize,
-    part: Box<[u8]>,
+    /// Shared by the clones of the part, so that the part kept in several caches and sent in
+    /// several messages is only held in memory once. Serialized like the `Box<[u8]>` it was.
+    part: Arc<[u8]>,
     encoded_length: usize,
     signature_differentiator: SignatureDifferentiator,
 }
@@ -102,7 +640,7 @@ impl PartialEncodedStateWitnessInner {
         epoch_id: EpochId,
         chunk_header: ShardChunkHeader,
         part_ord: usize,
-        part: Vec<u8>,
+        part: Arc<[u8]>,
         encoded_length: usize,
     ) -> Self {
         Self {
@@ -110,9 +648,764 @@ impl PartialEncodedStateWitnessInner {
             shard_id: chunk_header.shard_id(),
             height_created: chunk_header.height_created(),
             part_ord,
-            part: part.into_boxed_slice(),
+            part,
             encoded_length,
             signature_differentiator: "PartialEncodedStateWitness".to_owned(),
         }
     }
 }
+
+/// Signed part of `PartialEncodedStateWitness`, the chunk producer signs V2, which carries the
+/// owner and the witness hash as well, once `ProtocolFeature::PartialWitnessSendTimestamp` is
+/// enabled, commits to all the parts at once with V3 once
+/// `ProtocolFeature::PartialWitnessMerkleCommitment` is enabled, and commits to its protocol
+/// version as well with V4 once `ProtocolFeature::PartialWitnessProtocolVersion` is enabled.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub enum VersionedPartialEncodedStateWitnessInner {
+    V1(PartialEncodedStateWitnessInner),
+    V2(PartialEncodedStateWitnessInnerV2),
+    V3(PartialEncodedStateWitnessInnerV3),
+    V4(PartialEncodedStateWitnessInnerV4),
+}
+
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessInnerV2 {
+    inner: PartialEncodedStateWitnessInner,
+    /// Owner of the part at `part_ord`. Signed together with `part_ord` so that the chunk
+    /// validators can check that they agree with the chunk producer on the assignment of parts.
+    owner: AccountId,
+    /// Signed by the chunk producer in every part, so that the witness reconstructed from the
+    /// parts can be checked end to end, no matter which validators forwarded the parts.
+    witness_hash: Option<CryptoHash>,
+    /// Unix timestamp in nanoseconds, see `PartialEncodedStateWitness::sent_at`.
+    sent_at: u64,
+}
+
+/// Part committed to by the merkle root signed by the chunk producer. Unlike V1 and V2, the part
+/// itself isn't signed, the signature covers `PartialWitnessPartsCommitment` which is rebuilt
+/// from the fields of the part, and `part_proof` proves that the part is under `parts_root`.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessInnerV3 {
+    inner: PartialEncodedStateWitnessInner,
+    /// See `PartialEncodedStateWitnessInnerV2::owner`.
+    owner: AccountId,
+    /// See `PartialEncodedStateWitnessInnerV2::witness_hash`.
+    witness_hash: Option<CryptoHash>,
+    /// Unix timestamp in nanoseconds, see `PartialEncodedStateWitness::sent_at`.
+    sent_at: u64,
+    num_parts: usize,
+    parts_root: CryptoHash,
+    chunk_validators: ChunkValidatorsDigest,
+    /// Path from `PartialWitnessPartLeaf` of the part to `parts_root`.
+    part_proof: MerklePath,
+}
+
+impl PartialEncodedStateWitnessInnerV3 {
+    fn commitment(&self) -> PartialWitnessPartsCommitment {
+        PartialWitnessPartsCommitment {
+            epoch_id: self.inner.epoch_id,
+            shard_id: self.inner.shard_id,
+            height_created: self.inner.height_created,
+            num_parts: self.num_parts,
+            parts_root: self.parts_root,
+            encoded_length: self.inner.encoded_length,
+            witness_hash: self.witness_hash,
+            chunk_validators: self.chunk_validators,
+            sent_at: self.sent_at,
+            signature_differentiator: "PartialWitnessPartsCommitment".to_owned(),
+        }
+    }
+
+    fn verify_part_proof(&self) -> bool {
+        if self.inner.part_ord >= self.num_parts {
+            return false;
+        }
+        // A tree over `num_parts` leaves is never deeper than this, longer proofs would only
+        // make us hash for nothing.
+        let max_proof_len = self.num_parts.next_power_of_two().trailing_zeros() as usize;
+        if self.part_proof.len() > max_proof_len {
+            return false;
+        }
+        let leaf = PartialWitnessPartLeaf::new(self.inner.part_ord, &self.owner, &self.inner.part);
+        verify_path(self.parts_root, &self.part_proof, &leaf)
+    }
+}
+
+/// Part in the V3 format together with the protocol version the chunk producer created it under.
+/// The signature covers `PartialWitnessPartsCommitmentV2` instead of the commitment of V3.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessInnerV4 {
+    inner: PartialEncodedStateWitnessInnerV3,
+    protocol_version: ProtocolVersion,
+    /// See `PartialEncodedStateWitness::claimed_signer`.
+    signer: AccountId,
+}
+
+impl PartialEncodedStateWitnessInnerV4 {
+    fn commitment(&self) -> PartialWitnessPartsCommitmentV2 {
+        PartialWitnessPartsCommitmentV2 {
+            commitment: self.inner.commitment(),
+            protocol_version: self.protocol_version,
+        }
+    }
+}
+
+/// Signed by the chunk producer once per witness in the V3 format. It is not sent over the
+/// network, every V3 part carries the fields needed to rebuild it.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialWitnessPartsCommitment {
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub num_parts: usize,
+    /// Merkle root over `PartialWitnessPartLeaf` of the parts ordered by `part_ord`.
+    pub parts_root: CryptoHash,
+    pub encoded_length: usize,
+    pub witness_hash: Option<CryptoHash>,
+    pub chunk_validators: ChunkValidatorsDigest,
+    /// Unix timestamp in nanoseconds, see `PartialEncodedStateWitness::sent_at`.
+    pub sent_at: u64,
+    signature_differentiator: SignatureDifferentiator,
+}
+
+/// Signed by the chunk producer once per witness in the V4 format, like the V3 commitment.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialWitnessPartsCommitmentV2 {
+    pub commitment: PartialWitnessPartsCommitment,
+    /// See `PartialEncodedStateWitness::protocol_version`.
+    pub protocol_version: ProtocolVersion,
+}
+
+/// Chunk producer's view of the chunk validators of the chunk, signed into the V3 parts. The
+/// chunk validators compare it with their own view to detect a divergence of the chunk validator
+/// assignment between the nodes, e.g. running different versions, which would otherwise go
+/// unnoticed as long as the validators agree on the owners of the parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct ChunkValidatorsDigest {
+    pub num_validators: usize,
+    /// Prefix of the hash of the chunk validators in the order of the assignment.
+    pub accounts_hash: u64,
+}
+
+impl ChunkValidatorsDigest {
+    pub fn new(ordered_chunk_validators: &[AccountId]) -> Self {
+        let accounts_hash = CryptoHash::hash_borsh(ordered_chunk_validators);
+        Self {
+            num_validators: ordered_chunk_validators.len(),
+            accounts_hash: u64::from_le_bytes(accounts_hash.as_ref()[..8].try_into().unwrap()),
+        }
+    }
+}
+
+impl Display for ChunkValidatorsDigest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} validators, {:016x}", self.num_validators, self.accounts_hash)
+    }
+}
+
+/// Merkle tree leaf of a part. The ordinal and the owner are committed to together with the part,
+/// so that the proof of a part can't be replayed for another ordinal or owner.
+#[derive(BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialWitnessPartLeaf {
+    part_ord: usize,
+    owner: AccountId,
+    part_hash: CryptoHash,
+}
+
+impl PartialWitnessPartLeaf {
+    fn new(part_ord: usize, owner: &AccountId, part: &[u8]) -> Self {
+        Self { part_ord, owner: owner.clone(), part_hash: hash(part) }
+    }
+}
+
+/// Request sent by a chunk validator to the owner of a state witness part, i.e. the
+/// chunk validator that received the part directly from the chunk producer, when the
+/// forwarded part was never received. Only the owner can help in that case since the
+/// chunk producer sends every part exactly once.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct PartialEncodedStateWitnessRequest {
+    pub requester: AccountId,
+    pub epoch_id: EpochId,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub part_ord: usize,
+}
+
+impl PartialEncodedStateWitnessRequest {
+    pub fn chunk_production_key(&self) -> ChunkProductionKey {
+        ChunkProductionKey {
+            shard_id: self.shard_id,
+            epoch_id: self.epoch_id,
+            height_created: self.height_created,
+        }
+    }
+}
+
+/// The complete `EncodedChunkStateWitness` sent by the chunk producer directly to the chunk
+/// validators with the highest stake, in addition to their part. This saves them the forwarding
+/// round trip needed to collect enough parts, at the cost of extra bandwidth for the producer.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct FullEncodedStateWitness {
+    inner: FullEncodedStateWitnessInner,
+    pub signature: Signature,
+}
+
+impl Debug for FullEncodedStateWitness {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FullEncodedStateWitness")
+            .field("epoch_id", &self.inner.epoch_id)
+            .field("shard_id", &self.inner.shard_id)
+            .field("height_created", &self.inner.height_created)
+            .field("size", &self.inner.encoded_witness.size_bytes())
+            .finish()
+    }
+}
+
+impl FullEncodedStateWitness {
+    pub fn new(
+        epoch_id: EpochId,
+        chunk_header: &ShardChunkHeader,
+        encoded_witness: EncodedChunkStateWitness,
+        signer: &ValidatorSigner,
+    ) -> Self {
+        let inner = FullEncodedStateWitnessInner {
+            epoch_id,
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+            encoded_witness,
+            signature_differentiator: "FullEncodedStateWitness".to_owned(),
+        };
+        let signature = signer.sign_full_encoded_state_witness(&inner);
+        Self { inner, signature }
+    }
+
+    pub fn chunk_production_key(&self) -> ChunkProductionKey {
+        ChunkProductionKey {
+            shard_id: self.inner.shard_id,
+            epoch_id: self.inner.epoch_id,
+            height_created: self.inner.height_created,
+        }
+    }
+
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        let data = borsh::to_vec(&self.inner).unwrap();
+        self.signature.verify(&data, public_key)
+    }
+
+    pub fn encoded_witness(&self) -> &EncodedChunkStateWitness {
+        &self.inner.encoded_witness
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.inner.encoded_witness.size_bytes()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct FullEncodedStateWitnessInner {
+    epoch_id: EpochId,
+    shard_id: ShardId,
+    height_created: BlockHeight,
+    encoded_witness: EncodedChunkStateWitness,
+    signature_differentiator: SignatureDifferentiator,
+}
+
+impl EstimateSize for FullEncodedStateWitness {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        self.inner.encoded_witness.add_heap_size(estimator);
+        self.inner.signature_differentiator.add_heap_size(estimator);
+    }
+}
+
+/// Announcement by a chunk validator of the time until which it can't receive the witness parts,
+/// e.g. because it is restarting. The chunk producers don't send the part owned by the validator
+/// to it until then, and send the part directly to all the other chunk validators instead, since
+/// the owner wouldn't forward it. The validator clears the status when it starts again.
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct WitnessReceiverStatus {
+    inner: WitnessReceiverStatusInner,
+    pub signature: Signature,
+}
+
+impl Debug for WitnessReceiverStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WitnessReceiverStatus")
+            .field("account_id", &self.inner.account_id)
+            .field("issued_at", &self.issued_at())
+            .field("unavailable_until", &self.unavailable_until())
+            .finish()
+    }
+}
+
+impl WitnessReceiverStatus {
+    pub fn new(
+        account_id: AccountId,
+        issued_at: Utc,
+        unavailable_until: Option<Utc>,
+        signer: &ValidatorSigner,
+    ) -> Self {
+        let inner = WitnessReceiverStatusInner {
+            account_id,
+            issued_at: issued_at.unix_timestamp_nanos() as u64,
+            unavailable_until: unavailable_until
+                .map(|unavailable_until| unavailable_until.unix_timestamp_nanos() as u64),
+            signature_differentiator: "WitnessReceiverStatus".to_owned(),
+        };
+        let signature = signer.sign_witness_receiver_status(&inner);
+        Self { inner, signature }
+    }
+
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        let data = borsh::to_vec(&self.inner).unwrap();
+        self.signature.verify(&data, public_key)
+    }
+
+    /// Chunk validator announcing its status, the status must be signed by its validator key.
+    pub fn account_id(&self) -> &AccountId {
+        &self.inner.account_id
+    }
+
+    /// Time at which the status was issued, only the latest status of the validator counts.
+    pub fn issued_at(&self) -> Utc {
+        Utc::from_unix_timestamp_nanos(self.inner.issued_at as i128).unwrap()
+    }
+
+    /// Time until which the validator can't receive the witness parts, None if it can receive
+    /// them again.
+    pub fn unavailable_until(&self) -> Option<Utc> {
+        self.inner.unavailable_until.map(|unavailable_until| {
+            Utc::from_unix_timestamp_nanos(unavailable_until as i128).unwrap()
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct WitnessReceiverStatusInner {
+    account_id: AccountId,
+    /// Unix timestamp in nanoseconds.
+    issued_at: u64,
+    /// Unix timestamp in nanoseconds.
+    unavailable_until: Option<u64>,
+    signature_differentiator: SignatureDifferentiator,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use near_crypto::Signature;
+    use near_primitives_core::hash::CryptoHash;
+    use near_primitives_core::types::{AccountId, BlockHeight, ShardId};
+
+    use near_time::{Duration, Utc};
+
+    use super::{
+        ChunkValidatorsDigest, PartialEncodedStateWitness, PartialEncodedStateWitnessFragment,
+        PartialEncodedStateWitnessInnerV3, PartialEncodedStateWitnessPrefix,
+        PartialEncodedStateWitnessV1, VersionedPartialEncodedStateWitnessInner,
+        WitnessReceiverStatus, WitnessSizeBand, WitnessSizeLimits,
+        MAX_COMPRESSED_STATE_WITNESS_SIZE, MAX_COMPRESSED_STATE_WITNESS_SIZE_V2,
+    };
+    use crate::merkle::{Direction, MerklePathItem};
+    use crate::stateless_validation::state_witness::ChunkStateWitness;
+    use crate::test_utils::create_test_signer;
+    use crate::types::EpochId;
+    use crate::validator_signer::EmptyValidatorSigner;
+    use crate::version::{ProtocolFeature, ProtocolVersion};
+
+    fn partial_witness_with_part_size(part_size: usize) -> PartialEncodedStateWitness {
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        PartialEncodedStateWitness::new(
+            EpochId::default(),
+            chunk_header,
+            3,
+            vec![7; part_size],
+            part_size,
+            &EmptyValidatorSigner::default().into(),
+        )
+    }
+
+    #[test]
+    fn witness_size_grace_band_is_open_only_before_the_limit_increase() {
+        let increase = ProtocolFeature::WitnessSizeLimitIncrease.protocol_version();
+        let old_limit = MAX_COMPRESSED_STATE_WITNESS_SIZE.as_u64() as usize;
+        let new_limit = MAX_COMPRESSED_STATE_WITNESS_SIZE_V2.as_u64() as usize;
+
+        // Long before the increase, there is no grace band.
+        let limits = WitnessSizeLimits::for_protocol_version(increase - 2);
+        assert_eq!(limits.band(old_limit), WitnessSizeBand::WithinLimit);
+        assert_eq!(limits.band(old_limit + 1), WitnessSizeBand::TooLarge);
+
+        // Right before the increase, the witnesses up to the increased limit are accepted, but
+        // they are above the limit in force.
+        let limits = WitnessSizeLimits::for_protocol_version(increase - 1);
+        assert_eq!(limits.limit, MAX_COMPRESSED_STATE_WITNESS_SIZE);
+        assert_eq!(limits.band(old_limit), WitnessSizeBand::WithinLimit);
+        assert_eq!(limits.band(old_limit + 1), WitnessSizeBand::GraceBand);
+        assert_eq!(limits.band(new_limit), WitnessSizeBand::GraceBand);
+        assert_eq!(limits.band(new_limit + 1), WitnessSizeBand::TooLarge);
+
+        // Once the increase is in force, the grace band closes.
+        let limits = WitnessSizeLimits::for_protocol_version(increase);
+        assert_eq!(limits.band(new_limit), WitnessSizeBand::WithinLimit);
+        assert_eq!(limits.band(new_limit + 1), WitnessSizeBand::TooLarge);
+    }
+
+    #[test]
+    fn debug_output_does_not_grow_with_part_size() {
+        let small = partial_witness_with_part_size(16);
+        let large = partial_witness_with_part_size(1 << 20);
+        let small_debug = format!("{:?}", small);
+        let large_debug = format!("{:?}", large);
+        assert!(large_debug.contains("part_size: 1048576"));
+        assert!(large_debug.len() < 512);
+        assert!(large_debug.len() <= small_debug.len() + 8);
+        assert!(format!("{}", large).len() < 128);
+    }
+
+    #[test]
+    fn fragments_join_back_into_the_part() {
+        let partial_witness = partial_witness_with_part_size(1000);
+        let bytes = borsh::to_vec(&partial_witness).unwrap();
+        let fragments = PartialEncodedStateWitnessFragment::split(&partial_witness, true, 300);
+        assert_eq!(fragments.len(), bytes.len().div_ceil(300));
+        for (fragment_ord, fragment) in fragments.iter().enumerate() {
+            assert_eq!(fragment.fragment_ord, fragment_ord);
+            assert_eq!(fragment.num_fragments, fragments.len());
+            assert_eq!(fragment.part_ord, 3);
+            assert!(fragment.forward);
+            assert_eq!(fragment.part_hash, CryptoHash::hash_bytes(&bytes));
+            assert_eq!(fragment.chunk_production_key(), partial_witness.chunk_production_key());
+        }
+        let joined = fragments.iter().map(|fragment| fragment.bytes.as_slice()).collect::<Vec<_>>();
+        let joined = joined.concat();
+        assert_eq!(
+            borsh::from_slice::<PartialEncodedStateWitness>(&joined).unwrap(),
+            partial_witness
+        );
+        // A part fitting in one fragment is still sent as one.
+        assert_eq!(
+            PartialEncodedStateWitnessFragment::split(&partial_witness, false, 1 << 20).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn send_timestamp_is_signed_into_v2_parts() {
+        let signer = create_test_signer("alice.near");
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        let sent_at = Utc::UNIX_EPOCH + Duration::seconds(1_700_000_000);
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let v1 = PartialEncodedStateWitness::new(
+            EpochId::default(),
+            chunk_header.clone(),
+            3,
+            vec![7; 16],
+            16,
+            &signer,
+        );
+        let v2 = PartialEncodedStateWitness::new_v2(
+            EpochId::default(),
+            chunk_header,
+            3,
+            owner.clone(),
+            vec![7; 16],
+            16,
+            None,
+            sent_at,
+            &signer,
+        );
+        assert_eq!(v1.sent_at(), None);
+        assert_eq!(v2.sent_at(), Some(sent_at));
+        assert_eq!(v1.owner(), None);
+        assert_eq!(v2.owner(), Some(&owner));
+        assert!(v1.verify(&signer.public_key()));
+        assert!(v2.verify(&signer.public_key()));
+        assert_eq!(v1.clone().decompose(), v2.clone().decompose());
+
+        // The signature of the V1 part doesn't cover the send timestamp.
+        let forged = PartialEncodedStateWitness { inner: v2.inner, signature: v1.signature };
+        assert!(!forged.verify(&signer.public_key()));
+    }
+
+    fn committed_parts(part_byte: u8, num_parts: usize) -> Vec<PartialEncodedStateWitness> {
+        committed_parts_under(part_byte, num_parts, None)
+    }
+
+    fn committed_parts_under(
+        part_byte: u8,
+        num_parts: usize,
+        protocol_version: Option<ProtocolVersion>,
+    ) -> Vec<PartialEncodedStateWitness> {
+        let chunk_header = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header;
+        let parts = (0..num_parts)
+            .map(|part_ord| {
+                let owner = format!("validator{part_ord}.near").parse().unwrap();
+                (owner, Arc::from(vec![part_byte + part_ord as u8; 16]))
+            })
+            .collect::<Vec<_>>();
+        let chunk_validators = ChunkValidatorsDigest::new(
+            &parts.iter().map(|(owner, _)| owner.clone()).collect::<Vec<_>>(),
+        );
+        PartialEncodedStateWitness::new_committed_parts(
+            EpochId::default(),
+            &chunk_header,
+            parts,
+            16 * num_parts,
+            None,
+            chunk_validators,
+            Utc::UNIX_EPOCH + Duration::seconds(1_700_000_000),
+            protocol_version,
+            &create_test_signer("alice.near"),
+        )
+    }
+
+    fn v3_inner(
+        partial_witness: &mut PartialEncodedStateWitness,
+    ) -> &mut PartialEncodedStateWitnessInnerV3 {
+        match &mut partial_witness.inner {
+            VersionedPartialEncodedStateWitnessInner::V3(inner) => inner,
+            _ => panic!("expected a V3 part"),
+        }
+    }
+
+    #[test]
+    fn committed_parts_share_one_signature() {
+        let public_key = create_test_signer("alice.near").public_key();
+        for num_parts in [1, 2, 5, 8] {
+            let parts = committed_parts(0, num_parts);
+            assert_eq!(parts.len(), num_parts);
+            for (part_ord, part) in parts.iter().enumerate() {
+                assert_eq!(part.part_ord(), part_ord);
+                assert_eq!(part.num_parts(), Some(num_parts));
+                assert_eq!(part.parts_root(), parts[0].parts_root());
+                assert_eq!(part.signature, parts[0].signature);
+                assert!(part.verify(&public_key), "part {part_ord} of {num_parts}");
+                assert!(!part.verify(&create_test_signer("bob.near").public_key()));
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_committed_parts_are_rejected() {
+        let public_key = create_test_signer("alice.near").public_key();
+        let parts = committed_parts(0, 5);
+
+        let mut tampered_part = parts[1].clone();
+        let mut part = tampered_part.part().to_vec();
+        part[0] ^= 1;
+        v3_inner(&mut tampered_part).inner.part = part.into();
+        assert!(!tampered_part.verify(&public_key));
+
+        let mut tampered_proof = parts[1].clone();
+        v3_inner(&mut tampered_proof).part_proof[0].hash = CryptoHash::hash_bytes(b"forged");
+        assert!(!tampered_proof.verify(&public_key));
+
+        let mut flipped_proof = parts[1].clone();
+        let item = &mut v3_inner(&mut flipped_proof).part_proof[0];
+        item.direction = match item.direction {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        };
+        assert!(!flipped_proof.verify(&public_key));
+
+        // The proof of another part doesn't prove this one.
+        let other_proof = v3_inner(&mut parts[2].clone()).part_proof.clone();
+        let mut swapped_proof = parts[1].clone();
+        v3_inner(&mut swapped_proof).part_proof = other_proof;
+        assert!(!swapped_proof.verify(&public_key));
+
+        // The part can't be replayed under another ordinal or owner.
+        let mut wrong_ord = parts[1].clone();
+        v3_inner(&mut wrong_ord).inner.part_ord = 2;
+        assert!(!wrong_ord.verify(&public_key));
+        let mut wrong_owner = parts[1].clone();
+        v3_inner(&mut wrong_owner).owner = "mallory.near".parse().unwrap();
+        assert!(!wrong_owner.verify(&public_key));
+
+        let mut out_of_range = parts[4].clone();
+        v3_inner(&mut out_of_range).num_parts = 4;
+        assert!(!out_of_range.verify(&public_key));
+
+        let mut padded_proof = parts[1].clone();
+        v3_inner(&mut padded_proof)
+            .part_proof
+            .push(MerklePathItem { hash: CryptoHash::default(), direction: Direction::Right });
+        assert!(!padded_proof.verify(&public_key));
+
+        // The signed fields outside of the tree are covered by the signature.
+        let mut shifted_send_time = parts[1].clone();
+        v3_inner(&mut shifted_send_time).sent_at += 1;
+        assert!(!shifted_send_time.verify(&public_key));
+        let mut other_validators = parts[1].clone();
+        v3_inner(&mut other_validators).chunk_validators.accounts_hash ^= 1;
+        assert!(!other_validators.verify(&public_key));
+    }
+
+    #[test]
+    fn protocol_version_is_signed_into_v4_parts() {
+        let public_key = create_test_signer("alice.near").public_key();
+        let v3_parts = committed_parts_under(0, 3, None);
+        let v4_parts = committed_parts_under(0, 3, Some(150));
+        for (v3, v4) in v3_parts.iter().zip(&v4_parts) {
+            assert_eq!(v3.protocol_version(), None);
+            assert_eq!(v4.protocol_version(), Some(150));
+            assert_eq!(v3.claimed_signer(), None);
+            assert_eq!(v4.claimed_signer().map(AccountId::as_str), Some("alice.near"));
+            assert!(v4.verify(&public_key));
+            assert_eq!(v3.parts_commitment(), v4.parts_commitment());
+            assert_eq!(v3.clone().decompose(), v4.clone().decompose());
+        }
+
+        let mut other_version = v4_parts[1].clone();
+        match &mut other_version.inner {
+            VersionedPartialEncodedStateWitnessInner::V4(inner) => inner.protocol_version = 149,
+            _ => panic!("expected a V4 part"),
+        }
+        assert!(!other_version.verify(&public_key));
+
+        // The signature of the V3 parts doesn't cover the protocol version.
+        let forged = PartialEncodedStateWitness {
+            inner: v4_parts[1].inner.clone(),
+            signature: v3_parts[1].signature.clone(),
+        };
+        assert!(!forged.verify(&public_key));
+    }
+
+    #[test]
+    fn chunk_validators_digest_depends_on_the_order() {
+        let validators: Vec<AccountId> =
+            vec!["alice.near".parse().unwrap(), "bob.near".parse().unwrap()];
+        let digest = ChunkValidatorsDigest::new(&validators);
+        assert_eq!(digest.num_validators, 2);
+        assert_eq!(digest, ChunkValidatorsDigest::new(&validators.clone()));
+        let reversed = validators.iter().rev().cloned().collect::<Vec<_>>();
+        assert_ne!(digest.accounts_hash, ChunkValidatorsDigest::new(&reversed).accounts_hash);
+        assert_ne!(digest, ChunkValidatorsDigest::new(&validators[..1]));
+
+        let parts = committed_parts(0, 3);
+        assert_eq!(parts[0].chunk_validators_digest().unwrap().num_validators, 3);
+        assert_eq!(partial_witness_with_part_size(16).chunk_validators_digest(), None);
+    }
+
+    #[test]
+    fn two_signed_roots_are_equivocation_evidence() {
+        let public_key = create_test_signer("alice.near").public_key();
+        let first = committed_parts(0, 4);
+        let second = committed_parts(100, 4);
+        assert!(first[0].verify(&public_key));
+        assert!(second[0].verify(&public_key));
+        let first_commitment = first[0].parts_commitment().unwrap();
+        let second_commitment = second[0].parts_commitment().unwrap();
+        assert_eq!(first[0].chunk_production_key(), second[0].chunk_production_key());
+        assert_ne!(first_commitment.parts_root, second_commitment.parts_root);
+        assert_eq!(first_commitment, first[3].parts_commitment().unwrap());
+        // The parts of one witness can't be passed off as parts of the other.
+        let mixed = PartialEncodedStateWitness {
+            inner: first[1].inner.clone(),
+            signature: second[1].signature.clone(),
+        };
+        assert!(!mixed.verify(&public_key));
+    }
+
+    #[test]
+    fn witness_receiver_status_is_signed_by_the_account() {
+        let signer = create_test_signer("alice.near");
+        let issued_at = Utc::UNIX_EPOCH + Duration::seconds(1_700_000_000);
+        let unavailable_until = issued_at + Duration::minutes(2);
+        let status = WitnessReceiverStatus::new(
+            "alice.near".parse().unwrap(),
+            issued_at,
+            Some(unavailable_until),
+            &signer,
+        );
+        assert_eq!(status.issued_at(), issued_at);
+        assert_eq!(status.unavailable_until(), Some(unavailable_until));
+        assert!(status.verify(&signer.public_key()));
+        assert!(!status.verify(&create_test_signer("bob.near").public_key()));
+
+        let cleared =
+            WitnessReceiverStatus::new("alice.near".parse().unwrap(), issued_at, None, &signer);
+        assert_eq!(cleared.unavailable_until(), None);
+    }
+
+    #[test]
+    fn prefix_is_read_from_all_part_versions() {
+        let v1 = partial_witness_with_part_size(16);
+        let v2 = PartialEncodedStateWitness::new_v2(
+            EpochId::default(),
+            ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header,
+            3,
+            "bob.near".parse().unwrap(),
+            vec![7; 16],
+            16,
+            None,
+            Utc::UNIX_EPOCH + Duration::seconds(1_700_000_000),
+            &create_test_signer("alice.near"),
+        );
+        let v3 = committed_parts_under(0, 3, None).pop().unwrap();
+        let v4 = committed_parts_under(0, 3, Some(150)).pop().unwrap();
+        for part in [v1, v2, v3, v4] {
+            let bytes = borsh::to_vec(&part).unwrap();
+            let prefix = PartialEncodedStateWitnessPrefix::read(&bytes).unwrap();
+            assert_eq!(prefix.chunk_production_key(), part.chunk_production_key());
+            assert_eq!(prefix.part_ord, part.part_ord());
+            assert_eq!(prefix.part, part.part());
+            // The part is borrowed from the received bytes, not copied.
+            assert!(bytes.as_ptr_range().contains(&prefix.part.as_ptr()));
+        }
+    }
+
+    #[test]
+    fn malformed_prefix_is_rejected() {
+        let bytes = borsh::to_vec(&partial_witness_with_part_size(16)).unwrap();
+        // Cut in the middle of the part.
+        let part_end = bytes.len() - 10;
+        assert!(PartialEncodedStateWitnessPrefix::read(&bytes[..part_end]).is_err());
+        assert!(PartialEncodedStateWitnessPrefix::read(&[]).is_err());
+
+        let mut unknown_version = bytes;
+        unknown_version[0] = 4;
+        assert!(PartialEncodedStateWitnessPrefix::read(&unknown_version).is_err());
+    }
+
+    /// Layout of the part before the parts were versioned.
+    #[derive(BorshSerialize, BorshDeserialize)]
+    struct BaselinePartialEncodedStateWitness {
+        epoch_id: EpochId,
+        shard_id: ShardId,
+        height_created: BlockHeight,
+        part_ord: usize,
+        part: Box<[u8]>,
+        encoded_length: usize,
+        signature_differentiator: String,
+        signature: Signature,
+    }
+
+    #[test]
+    fn v1_parts_keep_the_baseline_layout() {
+        let signer = create_test_signer("alice.near");
+        let part = PartialEncodedStateWitness::new(
+            EpochId::default(),
+            ChunkStateWitness::new_dummy(42, 0, CryptoHash::default()).chunk_header,
+            3,
+            vec![7; 16],
+            1000,
+            &signer,
+        );
+        let v1 = part.clone().into_v1().unwrap();
+        let bytes = borsh::to_vec(&v1).unwrap();
+        let baseline = BaselinePartialEncodedStateWitness::try_from_slice(&bytes).unwrap();
+        assert_eq!(baseline.part_ord, 3);
+        assert_eq!(*baseline.part, [7; 16]);
+        assert_eq!(baseline.encoded_length, 1000);
+        assert_eq!(borsh::to_vec(&baseline).unwrap(), bytes);
+        let decoded = PartialEncodedStateWitnessV1::try_from_slice(&bytes).unwrap();
+        assert_eq!(PartialEncodedStateWitness::from(decoded), part);
+        assert!(part.verify(&signer.public_key()));
+
+        // The later versions don't fit in the V1 format.
+        assert!(committed_parts(0, 1).pop().unwrap().into_v1().is_err());
+    }
+}
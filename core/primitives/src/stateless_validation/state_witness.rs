@@ -1,34 +1,91 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io::{Read, Write};
 
+use super::estimate_size::{EstimateSize, SizeEstimator};
 use super::{ChunkProductionKey, SignatureDifferentiator};
 use crate::challenge::PartialState;
 use crate::congestion_info::CongestionInfo;
 use crate::sharding::{ChunkHash, ReceiptProof, ShardChunkHeader, ShardChunkHeaderV3};
 use crate::transaction::SignedTransaction;
 use crate::types::EpochId;
-use crate::utils::io::{CountingRead, CountingWrite};
+use crate::utils::io::{CountingRead, CountingWrite, HashingRead, HashingWrite};
 use crate::validator_signer::EmptyValidatorSigner;
 use borsh::{BorshDeserialize, BorshSerialize};
-use bytes::{Buf, BufMut};
+use bytes::Buf;
 use bytesize::ByteSize;
 use near_primitives_core::hash::CryptoHash;
-use near_primitives_core::types::{AccountId, BlockHeight, ShardId};
+use near_primitives_core::types::{AccountId, BlockHeight, ProtocolVersion, ShardId};
 use near_primitives_core::version::{ProtocolFeature, PROTOCOL_VERSION};
 use near_schema_checker_lib::ProtocolSchema;
+use near_time::Duration;
 
 /// Represents max allowed size of the raw (not compressed) state witness,
 /// corresponds to the size of borsh-serialized ChunkStateWitness.
 pub const MAX_UNCOMPRESSED_STATE_WITNESS_SIZE: ByteSize =
     ByteSize::mib(if cfg!(feature = "test_features") { 512 } else { 64 });
 
+/// Prefix of the encoded witnesses sent without compression, see
+/// `ProtocolFeature::UncompressedSmallWitness`. Neither a zstd frame nor a zstd skippable frame
+/// starts with these bytes, so the prefix can't be confused with a compressed witness.
+const UNCOMPRESSED_WITNESS_MAGIC: [u8; 4] = [0xff; 4];
+
+/// Maximum time for which a chunk validator holds an ack to batch it with others, see
+/// `BatchedChunkStateWitnessAck`. The chunk producer doesn't subtract more than this from the round
+/// trip time, which bounds how much a validator can distort it.
+pub const MAX_ACK_HELD_TIME: Duration = Duration::milliseconds(20);
+
+/// Level of the zstd compression of the state witnesses.
+const STATE_WITNESS_COMPRESSION_LEVEL: i32 = 3;
+
 /// Represents bytes of encoded ChunkStateWitness.
-/// This is the compressed version of borsh-serialized state witness.
+/// This is the compressed version of borsh-serialized state witness, or the borsh-serialized
+/// state witness prefixed by `UNCOMPRESSED_WITNESS_MAGIC` for the witnesses sent uncompressed.
 #[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
 pub struct EncodedChunkStateWitness(Box<[u8]>);
 
 pub type ChunkStateWitnessSize = usize;
 
+/// How the decompressed witness is fed to the borsh deserialization, see
+/// `EncodedChunkStateWitness::decode_with_limit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WitnessDecodeMode {
+    /// The witness is decompressed as the deserialization reads it, so the decompressed bytes
+    /// are never held in full and the peak memory is about the size of the decoded witness.
+    #[default]
+    Streamed,
+    /// The witness is decompressed into a buffer first, which holds the raw witness next to the
+    /// decoded one at peak. Only meant for debugging the streamed decode.
+    Buffered,
+}
+
+/// Sizes in bytes of the borsh-serialized top-level sections of a ChunkStateWitness.
+/// Used to find out which part of the witness is responsible for its size.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkStateWitnessSectionSizes {
+    /// Chunk producer, epoch id, chunk header, applied receipts hash and signature differentiator.
+    pub header: usize,
+    pub main_state_transition: usize,
+    pub source_receipt_proofs: usize,
+    pub transactions: usize,
+    pub implicit_transitions: usize,
+    pub new_transactions: usize,
+    pub new_transactions_validation_state: usize,
+}
+
+impl ChunkStateWitnessSectionSizes {
+    /// Total size of the borsh-serialized witness.
+    pub fn total(&self) -> usize {
+        self.header
+            + self.main_state_transition
+            + self.source_receipt_proofs
+            + self.transactions
+            + self.implicit_transitions
+            + self.new_transactions
+            + self.new_transactions_validation_state
+    }
+}
+
 impl EncodedChunkStateWitness {
     /// Only use this if you are sure that the data is already encoded.
     pub fn from_boxed_slice(data: Box<[u8]>) -> Self {
@@ -38,27 +95,92 @@ impl EncodedChunkStateWitness {
     /// Borsh-serialize and compress state witness.
     /// Returns encoded witness along with the raw (uncompressed) witness size.
     pub fn encode(witness: &ChunkStateWitness) -> std::io::Result<(Self, ChunkStateWitnessSize)> {
-        const STATE_WITNESS_COMPRESSION_LEVEL: i32 = 3;
+        let (encoded, raw_witness_size, _) = Self::encode_with_section_sizes(witness)?;
+        Ok((encoded, raw_witness_size))
+    }
 
+    /// Same as `encode`, but additionally returns the sizes of the borsh-serialized top-level
+    /// sections of the witness. The sizes are collected during the same serialization pass.
+    pub fn encode_with_section_sizes(
+        witness: &ChunkStateWitness,
+    ) -> std::io::Result<(Self, ChunkStateWitnessSize, ChunkStateWitnessSectionSizes)> {
+        Self::encode_with_compression_threshold(witness, ByteSize::b(0))
+    }
+
+    /// Same as `encode_with_section_sizes`, but the witnesses whose borsh-serialized size is
+    /// below `compression_threshold` are not compressed. Only use a non-zero threshold once
+    /// `ProtocolFeature::UncompressedSmallWitness` is enabled.
+    pub fn encode_with_compression_threshold(
+        witness: &ChunkStateWitness,
+        compression_threshold: ByteSize,
+    ) -> std::io::Result<(Self, ChunkStateWitnessSize, ChunkStateWitnessSectionSizes)> {
+        let compressor = ThresholdCompressor::new(compression_threshold.as_u64() as usize)?;
+        let (compressor, raw_witness_size, section_sizes) =
+            Self::serialize_into(witness, compressor)?;
+        Ok((Self(compressor.finish()?.into()), raw_witness_size, section_sizes))
+    }
+
+    /// Same as `encode_with_compression_threshold`, but additionally returns the hash of the
+    /// borsh-serialized witness, computed from the bytes as they are fed to the compression.
+    /// The hash is the same as `CryptoHash::hash_borsh` of the witness.
+    pub fn encode_and_hash(
+        witness: &ChunkStateWitness,
+        compression_threshold: ByteSize,
+    ) -> std::io::Result<(Self, ChunkStateWitnessSize, ChunkStateWitnessSectionSizes, CryptoHash)>
+    {
+        let compressor = ThresholdCompressor::new(compression_threshold.as_u64() as usize)?;
+        let (hashing_write, raw_witness_size, section_sizes) =
+            Self::serialize_into(witness, HashingWrite::new(compressor))?;
+        let (compressor, witness_hash) = hashing_write.finish();
+        Ok((Self(compressor.finish()?.into()), raw_witness_size, section_sizes, witness_hash))
+    }
+
+    /// Borsh-serializes the witness into `writer`, returns the writer along with the size of the
+    /// serialized witness and of its sections.
+    fn serialize_into<W: Write>(
+        witness: &ChunkStateWitness,
+        writer: W,
+    ) -> std::io::Result<(W, ChunkStateWitnessSize, ChunkStateWitnessSectionSizes)> {
         // Flow of data: State witness --> Borsh serialization --> Counting write --> zstd compression --> Bytes.
         // CountingWrite will count the number of bytes for the Borsh-serialized witness, before compression.
-        let mut counting_write = CountingWrite::new(zstd::stream::Encoder::new(
-            Vec::new().writer(),
-            STATE_WITNESS_COMPRESSION_LEVEL,
-        )?);
-        borsh::to_writer(&mut counting_write, witness)?;
+        let mut counting_write = CountingWrite::new(writer);
+        let section_sizes = witness.serialize_with_section_sizes(&mut counting_write)?;
 
         let borsh_bytes_len = counting_write.bytes_written();
-        let encoded_bytes = counting_write.into_inner().finish()?.into_inner();
-
-        Ok((Self(encoded_bytes.into()), borsh_bytes_len.as_u64() as usize))
+        debug_assert_eq!(section_sizes.total() as u64, borsh_bytes_len.as_u64());
+        Ok((counting_write.into_inner(), borsh_bytes_len.as_u64() as usize, section_sizes))
     }
 
     /// Decompress and borsh-deserialize encoded witness bytes.
     /// Returns decoded witness along with the raw (uncompressed) witness size.
     pub fn decode(&self) -> std::io::Result<(ChunkStateWitness, ChunkStateWitnessSize)> {
+        self.decode_with_mode(WitnessDecodeMode::Streamed)
+    }
+
+    /// Same as `decode`, but feeds the decompressed witness to the deserialization as `mode` says.
+    pub fn decode_with_mode(
+        &self,
+        mode: WitnessDecodeMode,
+    ) -> std::io::Result<(ChunkStateWitness, ChunkStateWitnessSize)> {
         // We want to limit the size of decompressed data to address "Zip bomb" attack.
-        self.decode_with_limit(MAX_UNCOMPRESSED_STATE_WITNESS_SIZE)
+        self.decode_with_limit(MAX_UNCOMPRESSED_STATE_WITNESS_SIZE, mode)
+    }
+
+    /// Same as `decode_with_mode`, but additionally returns the hash of the borsh-serialized
+    /// witness, computed from the decompressed bytes as they are deserialized. The hash is the
+    /// same as `CryptoHash::hash_borsh` of the decoded witness, as borsh serialization is
+    /// bijective.
+    pub fn decode_and_hash(
+        &self,
+        mode: WitnessDecodeMode,
+    ) -> std::io::Result<(ChunkStateWitness, ChunkStateWitnessSize, CryptoHash)> {
+        let limit = MAX_UNCOMPRESSED_STATE_WITNESS_SIZE;
+        self.decompress_with(limit, mode, |reader| {
+            let mut hashing_read = HashingRead::new(reader);
+            let (witness, raw_witness_size) =
+                Self::deserialize_with_limit(&mut hashing_read, limit)?;
+            Ok((witness, raw_witness_size, hashing_read.finish().1))
+        })
     }
 
     /// Decompress and borsh-deserialize encoded witness bytes.
@@ -66,27 +188,47 @@ impl EncodedChunkStateWitness {
     pub fn decode_with_limit(
         &self,
         limit: ByteSize,
+        mode: WitnessDecodeMode,
     ) -> std::io::Result<(ChunkStateWitness, ChunkStateWitnessSize)> {
-        // Flow of data: Bytes --> zstd decompression --> Counting read --> Borsh deserialization --> State witness.
+        self.decompress_with(limit, mode, |reader| Self::deserialize_with_limit(reader, limit))
+    }
+
+    /// Feeds the decompressed witness bytes to `deserialize` as `mode` says.
+    fn decompress_with<T>(
+        &self,
+        limit: ByteSize,
+        mode: WitnessDecodeMode,
+        deserialize: impl FnOnce(&mut dyn Read) -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let borsh_bytes = self.0.strip_prefix(&UNCOMPRESSED_WITNESS_MAGIC);
+        match (borsh_bytes, mode) {
+            (Some(mut borsh_bytes), _) => deserialize(&mut borsh_bytes),
+            (None, WitnessDecodeMode::Streamed) => deserialize(&mut self.decompressor()?),
+            (None, WitnessDecodeMode::Buffered) => {
+                // The limit applies to the decompression, so that the buffer never outgrows it.
+                let mut decompressed = vec![];
+                CountingRead::new_with_limit(self.decompressor()?, limit)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|err| describe_limit_error(err, limit))?;
+                deserialize(&mut decompressed.as_slice())
+            }
+        }
+    }
+
+    fn decompressor(&self) -> std::io::Result<impl Read + '_> {
+        zstd::stream::Decoder::new(self.0.as_ref().reader())
+    }
+
+    fn deserialize_with_limit(
+        reader: impl Read,
+        limit: ByteSize,
+    ) -> std::io::Result<(ChunkStateWitness, ChunkStateWitnessSize)> {
+        // Flow of data: Bytes --> zstd decompression (if compressed) --> Counting read --> Borsh deserialization --> State witness.
         // CountingRead will count the number of bytes for the Borsh-deserialized witness, after decompression.
-        let mut counting_read = CountingRead::new_with_limit(
-            zstd::stream::Decoder::new(self.0.as_ref().reader())?,
-            limit,
-        );
+        let mut counting_read = CountingRead::new_with_limit(reader, limit);
 
         match borsh::from_reader(&mut counting_read) {
-            Err(err) => {
-                // If decompressed data exceeds the limit then CountingRead will return a WriteZero error.
-                // Here we convert it to a more descriptive error to make debugging easier.
-                let err = if err.kind() == std::io::ErrorKind::WriteZero {
-                    std::io::Error::other(format!(
-                        "Decompressed data exceeded limit of {limit}: {err}"
-                    ))
-                } else {
-                    err
-                };
-                Err(err)
-            }
+            Err(err) => Err(describe_limit_error(err, limit)),
             Ok(witness) => Ok((witness, counting_read.bytes_read().as_u64().try_into().unwrap())),
         }
     }
@@ -95,19 +237,96 @@ impl EncodedChunkStateWitness {
         self.0.len()
     }
 
+    /// Whether the witness was compressed, see `encode_with_compression_threshold`.
+    pub fn is_compressed(&self) -> bool {
+        !self.0.starts_with(&UNCOMPRESSED_WITNESS_MAGIC)
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         &self.0
     }
 }
 
-/// An acknowledgement sent from the chunk producer upon receiving the state witness to
-/// the originator of the witness (chunk producer).
+impl EstimateSize for EncodedChunkStateWitness {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        self.0.add_heap_size(estimator);
+    }
+}
+
+/// If decompressed data exceeds the limit then CountingRead will return a WriteZero error.
+/// Here we convert it to a more descriptive error to make debugging easier.
+fn describe_limit_error(err: std::io::Error, limit: ByteSize) -> std::io::Error {
+    if err.kind() == std::io::ErrorKind::WriteZero {
+        std::io::Error::other(format!("Decompressed data exceeded limit of {limit}: {err}"))
+    } else {
+        err
+    }
+}
+
+/// Writer which keeps the written bytes uncompressed as long as their total size stays below the
+/// threshold. Once the threshold is reached, the bytes written so far and all the following ones
+/// are compressed, so large witnesses are compressed as they are serialized.
+enum ThresholdCompressor {
+    /// Starts with `UNCOMPRESSED_WITNESS_MAGIC`, followed by the bytes written so far.
+    Uncompressed {
+        bytes: Vec<u8>,
+        threshold: usize,
+    },
+    Compressed(zstd::stream::Encoder<'static, Vec<u8>>),
+}
+
+impl ThresholdCompressor {
+    fn new(threshold: usize) -> std::io::Result<Self> {
+        if threshold == 0 {
+            return Self::compressor().map(Self::Compressed);
+        }
+        Ok(Self::Uncompressed { bytes: UNCOMPRESSED_WITNESS_MAGIC.to_vec(), threshold })
+    }
+
+    fn compressor() -> std::io::Result<zstd::stream::Encoder<'static, Vec<u8>>> {
+        zstd::stream::Encoder::new(Vec::new(), STATE_WITNESS_COMPRESSION_LEVEL)
+    }
+
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Uncompressed { bytes, .. } => Ok(bytes),
+            Self::Compressed(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for ThresholdCompressor {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Self::Uncompressed { bytes, threshold } = self {
+            let written = bytes.len() - UNCOMPRESSED_WITNESS_MAGIC.len();
+            if written + buf.len() < *threshold {
+                bytes.extend_from_slice(buf);
+                return Ok(buf.len());
+            }
+            let mut encoder = Self::compressor()?;
+            encoder.write_all(&bytes[UNCOMPRESSED_WITNESS_MAGIC.len()..])?;
+            *self = Self::Compressed(encoder);
+        }
+        match self {
+            Self::Compressed(encoder) => encoder.write(buf),
+            Self::Uncompressed { .. } => unreachable!(),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Uncompressed { .. } => Ok(()),
+            Self::Compressed(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// An acknowledgement sent from the chunk validator to the originator of the witness
+/// (chunk producer) as soon as the witness is reconstructed from the parts, before it is validated.
 ///
-/// This message is currently used for computing
-/// the network round-trip time of sending the state witness to the chunk producer and receiving the
-/// endorsement message. Note that the endorsement message is sent to the next block producer,
-/// while this message is sent back to the originator of the state witness, though this allows
-/// us to approximate the time for transmitting the state witness + transmitting the endorsement.
+/// This message is currently used for computing the network round-trip time of distributing the
+/// state witness to the chunk validators. Since it is sent before the validation, the round-trip
+/// time doesn't include the time the chunk validator spends validating the witness.
 #[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
 pub struct ChunkStateWitnessAck {
     /// Hash of the chunk for which the state witness was generated.
@@ -120,6 +339,155 @@ impl ChunkStateWitnessAck {
     }
 }
 
+/// Ack which also tells the chunk producer how the chunk validator reconstructed the witness, so
+/// that the producer can see how close the distribution of its parts came to failing.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct ChunkStateWitnessAckV2 {
+    pub chunk_hash: ChunkHash,
+    /// Number of the witness parts the chunk validator had received when it decoded the witness.
+    pub parts_received_at_decode: u32,
+    /// Whether the witness was decoded from the full witness sent directly by the chunk
+    /// producer rather than from the parts.
+    pub via_full_witness: bool,
+    /// Number of attempts it took to decode the witness. An attempt fails, e.g. on a corrupted
+    /// part, and the next one is made once another part arrives.
+    pub decode_attempts: u32,
+}
+
+/// Ack sent by the chunk validators, V2 once `ProtocolFeature::WitnessAckDecodeStats` is enabled.
+/// V1 is sent in `RoutedMessageBody::ChunkStateWitnessAck` as before, so that it is still
+/// understood by the nodes not aware of the versioning.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub enum VersionedChunkStateWitnessAck {
+    V1(ChunkStateWitnessAck),
+    V2(ChunkStateWitnessAckV2),
+}
+
+impl VersionedChunkStateWitnessAck {
+    pub fn new(
+        witness: &ChunkStateWitness,
+        parts_received_at_decode: usize,
+        via_full_witness: bool,
+        decode_attempts: usize,
+        protocol_version: ProtocolVersion,
+    ) -> Self {
+        if !ProtocolFeature::WitnessAckDecodeStats.enabled(protocol_version) {
+            return Self::V1(ChunkStateWitnessAck::new(witness));
+        }
+        Self::V2(ChunkStateWitnessAckV2 {
+            chunk_hash: witness.chunk_header.chunk_hash(),
+            parts_received_at_decode: parts_received_at_decode.try_into().unwrap_or(u32::MAX),
+            via_full_witness,
+            decode_attempts: decode_attempts.try_into().unwrap_or(u32::MAX),
+        })
+    }
+
+    pub fn chunk_hash(&self) -> &ChunkHash {
+        match self {
+            Self::V1(ack) => &ack.chunk_hash,
+            Self::V2(ack) => &ack.chunk_hash,
+        }
+    }
+}
+
+impl From<ChunkStateWitnessAck> for VersionedChunkStateWitnessAck {
+    fn from(ack: ChunkStateWitnessAck) -> Self {
+        Self::V1(ack)
+    }
+}
+
+/// Acks of several state witnesses sent by a chunk validator to the same chunk producer in one
+/// message. The acks ready within a short window are coalesced, so that every ack doesn't pay the
+/// framing of a routed message of its own.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct BatchedChunkStateWitnessAck {
+    pub acks: Vec<HeldChunkStateWitnessAck>,
+}
+
+/// Ack in `BatchedChunkStateWitnessAck`, together with the time the chunk validator held it
+/// before sending, which the chunk producer subtracts from the round trip time.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct HeldChunkStateWitnessAck {
+    pub ack: ChunkStateWitnessAck,
+    pub held_micros: u32,
+}
+
+impl HeldChunkStateWitnessAck {
+    pub fn new(ack: ChunkStateWitnessAck, held: Duration) -> Self {
+        Self { ack, held_micros: held_micros(held) }
+    }
+
+    pub fn held(&self) -> Duration {
+        Duration::microseconds(self.held_micros.into())
+    }
+}
+
+/// Batch of versioned acks, see `VersionedChunkStateWitnessAck`. A batch of V1 acks only is sent
+/// as `BatchedChunkStateWitnessAck` instead.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct BatchedChunkStateWitnessAckV2 {
+    pub acks: Vec<HeldChunkStateWitnessAckV2>,
+}
+
+impl BatchedChunkStateWitnessAckV2 {
+    /// Converts the batch to the V1 format if all its acks are V1, returns it back otherwise.
+    pub fn into_v1(self) -> Result<BatchedChunkStateWitnessAck, Self> {
+        if !self
+            .acks
+            .iter()
+            .all(|held_ack| matches!(held_ack.ack, VersionedChunkStateWitnessAck::V1(_)))
+        {
+            return Err(self);
+        }
+        let acks = self
+            .acks
+            .into_iter()
+            .map(|held_ack| match held_ack.ack {
+                VersionedChunkStateWitnessAck::V1(ack) => {
+                    HeldChunkStateWitnessAck { ack, held_micros: held_ack.held_micros }
+                }
+                VersionedChunkStateWitnessAck::V2(_) => unreachable!(),
+            })
+            .collect();
+        Ok(BatchedChunkStateWitnessAck { acks })
+    }
+}
+
+impl From<BatchedChunkStateWitnessAck> for BatchedChunkStateWitnessAckV2 {
+    fn from(batch: BatchedChunkStateWitnessAck) -> Self {
+        let acks = batch
+            .acks
+            .into_iter()
+            .map(|held_ack| HeldChunkStateWitnessAckV2 {
+                ack: held_ack.ack.into(),
+                held_micros: held_ack.held_micros,
+            })
+            .collect();
+        Self { acks }
+    }
+}
+
+/// Versioned ack in `BatchedChunkStateWitnessAckV2`, see `HeldChunkStateWitnessAck`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
+pub struct HeldChunkStateWitnessAckV2 {
+    pub ack: VersionedChunkStateWitnessAck,
+    pub held_micros: u32,
+}
+
+impl HeldChunkStateWitnessAckV2 {
+    pub fn new(ack: VersionedChunkStateWitnessAck, held: Duration) -> Self {
+        Self { ack, held_micros: held_micros(held) }
+    }
+
+    pub fn held(&self) -> Duration {
+        Duration::microseconds(self.held_micros.into())
+    }
+}
+
+fn held_micros(held: Duration) -> u32 {
+    held.whole_microseconds().clamp(0, u32::MAX as i128) as u32
+}
+
 /// The state witness for a chunk; proves the state transition that the
 /// chunk attests to.
 #[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, ProtocolSchema)]
@@ -200,6 +568,17 @@ pub struct ChunkStateWitness {
     signature_differentiator: SignatureDifferentiator,
 }
 
+/// The witness is a deep tree of headers, receipts, transactions and trie values, so instead of
+/// walking it, the heap it retains is approximated by its borsh-serialized size. The serialized
+/// size misses the padding and the spare capacity of the collections, but the witnesses large
+/// enough to matter for the memory budgets are dominated by the trie values, for which it is
+/// accurate within a percent.
+impl EstimateSize for ChunkStateWitness {
+    fn add_heap_size(&self, estimator: &mut SizeEstimator) {
+        estimator.add_bytes(borsh::object_length(self).unwrap());
+    }
+}
+
 impl ChunkStateWitness {
     pub fn new(
         chunk_producer: AccountId,
@@ -236,6 +615,52 @@ impl ChunkStateWitness {
         }
     }
 
+    /// Computes the sizes of the borsh-serialized top-level sections of the witness
+    /// without allocating the serialized bytes.
+    pub fn section_sizes(&self) -> std::io::Result<ChunkStateWitnessSectionSizes> {
+        self.serialize_with_section_sizes(&mut std::io::sink())
+    }
+
+    /// Borsh-serializes the witness into `writer` field by field, which produces exactly the
+    /// same bytes as `borsh::to_writer(writer, self)`, and records the size of every section.
+    /// Keep the order of the fields in sync with the struct definition.
+    fn serialize_with_section_sizes<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> std::io::Result<ChunkStateWitnessSectionSizes> {
+        let mut writer = CountingWrite::new(writer);
+        let mut sizes = ChunkStateWitnessSectionSizes::default();
+        let mut section_start = 0;
+        let mut section_size = |writer: &CountingWrite<&mut W>| {
+            let written = writer.bytes_written().as_u64() as usize;
+            let size = written - section_start;
+            section_start = written;
+            size
+        };
+
+        self.chunk_producer.serialize(&mut writer)?;
+        self.epoch_id.serialize(&mut writer)?;
+        self.chunk_header.serialize(&mut writer)?;
+        sizes.header += section_size(&writer);
+        self.main_state_transition.serialize(&mut writer)?;
+        sizes.main_state_transition = section_size(&writer);
+        self.source_receipt_proofs.serialize(&mut writer)?;
+        sizes.source_receipt_proofs = section_size(&writer);
+        self.applied_receipts_hash.serialize(&mut writer)?;
+        sizes.header += section_size(&writer);
+        self.transactions.serialize(&mut writer)?;
+        sizes.transactions = section_size(&writer);
+        self.implicit_transitions.serialize(&mut writer)?;
+        sizes.implicit_transitions = section_size(&writer);
+        self.new_transactions.serialize(&mut writer)?;
+        sizes.new_transactions = section_size(&writer);
+        self.new_transactions_validation_state.serialize(&mut writer)?;
+        sizes.new_transactions_validation_state = section_size(&writer);
+        self.signature_differentiator.serialize(&mut writer)?;
+        sizes.header += section_size(&writer);
+        Ok(sizes)
+    }
+
     pub fn new_dummy(height: BlockHeight, shard_id: ShardId, prev_block_hash: CryptoHash) -> Self {
         let congestion_info = ProtocolFeature::CongestionControl
             .enabled(PROTOCOL_VERSION)
@@ -300,7 +725,15 @@ mod tests {
     use near_primitives_core::hash::CryptoHash;
     use std::io::ErrorKind;
 
-    use crate::stateless_validation::state_witness::{ChunkStateWitness, EncodedChunkStateWitness};
+    use near_primitives_core::version::ProtocolFeature;
+    use near_time::Duration;
+
+    use crate::challenge::PartialState;
+    use crate::stateless_validation::state_witness::{
+        BatchedChunkStateWitnessAck, BatchedChunkStateWitnessAckV2, ChunkStateWitness,
+        ChunkStateWitnessAck, EncodedChunkStateWitness, HeldChunkStateWitnessAck,
+        HeldChunkStateWitnessAckV2, VersionedChunkStateWitnessAck, WitnessDecodeMode,
+    };
 
     #[test]
     fn encode_decode_state_dummy_witness_default_limit() {
@@ -314,6 +747,38 @@ mod tests {
         assert_eq!(borsh::to_vec(&original_witness).unwrap().len(), borsh_bytes_from_encode);
     }
 
+    #[test]
+    fn witness_hash_matches_decompressed_bytes() {
+        let original_witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let (encoded_witness, _) = EncodedChunkStateWitness::encode(&original_witness).unwrap();
+        let raw_bytes = zstd::decode_all(encoded_witness.as_slice()).unwrap();
+        let (decoded_witness, _) = encoded_witness.decode().unwrap();
+        assert_eq!(CryptoHash::hash_borsh(&decoded_witness), CryptoHash::hash_bytes(&raw_bytes));
+        assert_eq!(
+            CryptoHash::hash_borsh(&decoded_witness),
+            CryptoHash::hash_borsh(&original_witness)
+        );
+    }
+
+    #[test]
+    fn witness_hash_is_computed_while_encoding_and_decoding() {
+        let original_witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let expected_hash = CryptoHash::hash_borsh(&original_witness);
+        // Zero compresses the witness, the maximum threshold keeps it uncompressed.
+        for compression_threshold in [ByteSize::b(0), ByteSize::b(u64::MAX)] {
+            let (encoded_witness, _, _, encode_hash) =
+                EncodedChunkStateWitness::encode_and_hash(&original_witness, compression_threshold)
+                    .unwrap();
+            assert_eq!(encode_hash, expected_hash);
+            for mode in [WitnessDecodeMode::Streamed, WitnessDecodeMode::Buffered] {
+                let (decoded_witness, _, decode_hash) =
+                    encoded_witness.decode_and_hash(mode).unwrap();
+                assert_eq!(decoded_witness, original_witness);
+                assert_eq!(decode_hash, expected_hash);
+            }
+        }
+    }
+
     #[test]
     fn encode_decode_state_dummy_witness_within_limit() {
         const LIMIT: ByteSize = ByteSize::mib(32);
@@ -322,7 +787,7 @@ mod tests {
             EncodedChunkStateWitness::encode(&original_witness).unwrap();
         let (decoded_witness, borsh_bytes_from_decode) =
             EncodedChunkStateWitness::from_boxed_slice(encoded_witness.0)
-                .decode_with_limit(LIMIT)
+                .decode_with_limit(LIMIT, WitnessDecodeMode::Streamed)
                 .unwrap();
         assert_eq!(decoded_witness, original_witness);
         assert_eq!(borsh_bytes_from_encode, borsh_bytes_from_decode);
@@ -337,7 +802,7 @@ mod tests {
             EncodedChunkStateWitness::encode(&original_witness).unwrap();
         assert!(borsh_bytes_from_encode > LIMIT.as_u64() as usize);
         let error = EncodedChunkStateWitness::from_boxed_slice(encoded_witness.0)
-            .decode_with_limit(LIMIT)
+            .decode_with_limit(LIMIT, WitnessDecodeMode::Streamed)
             .unwrap_err();
         assert_eq!(error.kind(), ErrorKind::Other);
         assert_eq!(
@@ -346,6 +811,117 @@ mod tests {
         );
     }
 
+    #[test]
+    fn encode_state_dummy_witness_section_sizes() {
+        let original_witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let (encoded_witness, raw_witness_size, section_sizes) =
+            EncodedChunkStateWitness::encode_with_section_sizes(&original_witness).unwrap();
+        let (decoded_witness, _) =
+            EncodedChunkStateWitness::from_boxed_slice(encoded_witness.0).decode().unwrap();
+        assert_eq!(decoded_witness, original_witness);
+        assert_eq!(section_sizes.total(), raw_witness_size);
+        assert_eq!(original_witness.section_sizes().unwrap(), section_sizes);
+        assert_eq!(
+            section_sizes.main_state_transition,
+            borsh::object_length(&original_witness.main_state_transition).unwrap()
+        );
+        assert_eq!(
+            section_sizes.new_transactions_validation_state,
+            borsh::object_length(&original_witness.new_transactions_validation_state).unwrap()
+        );
+    }
+
+    #[test]
+    fn witness_below_threshold_is_not_compressed() {
+        let original_witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let raw_size = borsh::to_vec(&original_witness).unwrap().len();
+
+        let (encoded_witness, raw_witness_size, section_sizes) =
+            EncodedChunkStateWitness::encode_with_compression_threshold(
+                &original_witness,
+                ByteSize::b(raw_size as u64 + 1),
+            )
+            .unwrap();
+        assert!(!encoded_witness.is_compressed());
+        assert_eq!(encoded_witness.size_bytes(), raw_size + 4);
+        assert_eq!(raw_witness_size, raw_size);
+        assert_eq!(section_sizes.total(), raw_size);
+        let (decoded_witness, borsh_bytes_from_decode) = encoded_witness.decode().unwrap();
+        assert_eq!(decoded_witness, original_witness);
+        assert_eq!(borsh_bytes_from_decode, raw_size);
+
+        // The witness is compressed as soon as it reaches the threshold.
+        let (encoded_witness, raw_witness_size, _) =
+            EncodedChunkStateWitness::encode_with_compression_threshold(
+                &original_witness,
+                ByteSize::b(raw_size as u64),
+            )
+            .unwrap();
+        assert!(encoded_witness.is_compressed());
+        assert_eq!(raw_witness_size, raw_size);
+        assert_eq!(
+            zstd::decode_all(encoded_witness.as_slice()).unwrap(),
+            borsh::to_vec(&original_witness).unwrap()
+        );
+        assert_eq!(encoded_witness.decode().unwrap().0, original_witness);
+    }
+
+    #[test]
+    fn uncompressed_witness_respects_limit() {
+        const LIMIT: ByteSize = ByteSize::b(32);
+        let original_witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let (encoded_witness, _, _) = EncodedChunkStateWitness::encode_with_compression_threshold(
+            &original_witness,
+            ByteSize::kib(16),
+        )
+        .unwrap();
+        assert!(!encoded_witness.is_compressed());
+        let error =
+            encoded_witness.decode_with_limit(LIMIT, WitnessDecodeMode::Streamed).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Decompressed data exceeded limit of 32 B: Exceeded the limit of 32 bytes"
+        );
+    }
+
+    /// The streamed and the buffered decode return the same witness and size, and fail on the
+    /// same limit, whether the witness is compressed or not.
+    #[test]
+    fn streamed_decode_matches_buffered_decode() {
+        bolero::check!().with_type().for_each(
+            |(values, compression_threshold, limit): &(Vec<Vec<u8>>, u16, u16)| {
+                let mut witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+                witness.main_state_transition.base_state = PartialState::TrieValues(
+                    values.iter().map(|value| value.clone().into()).collect(),
+                );
+                let (encoded_witness, _, _) =
+                    EncodedChunkStateWitness::encode_with_compression_threshold(
+                        &witness,
+                        ByteSize::b(*compression_threshold as u64),
+                    )
+                    .unwrap();
+                for limit in [ByteSize::b(*limit as u64), ByteSize::mib(1)] {
+                    let streamed =
+                        encoded_witness.decode_with_limit(limit, WitnessDecodeMode::Streamed);
+                    let buffered =
+                        encoded_witness.decode_with_limit(limit, WitnessDecodeMode::Buffered);
+                    match (streamed, buffered) {
+                        (Ok(streamed), Ok(buffered)) => {
+                            assert_eq!(streamed.0, witness);
+                            assert_eq!(streamed, buffered);
+                        }
+                        (Err(streamed), Err(buffered)) => {
+                            assert_eq!(streamed.to_string(), buffered.to_string());
+                        }
+                        (streamed, buffered) => {
+                            panic!("streamed: {streamed:?}, buffered: {buffered:?}")
+                        }
+                    }
+                }
+            },
+        );
+    }
+
     #[test]
     fn decode_state_dummy_witness_invalid_data() {
         let invalid_data = [0; 10];
@@ -354,4 +930,51 @@ mod tests {
             .unwrap_err();
         assert_eq!(error.kind(), ErrorKind::Other);
     }
+
+    #[test]
+    fn versioned_ack_is_gated_by_protocol_feature() {
+        let witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let version = ProtocolFeature::WitnessAckDecodeStats.protocol_version();
+        assert_eq!(
+            VersionedChunkStateWitnessAck::new(&witness, 3, false, 1, version - 1),
+            VersionedChunkStateWitnessAck::V1(ChunkStateWitnessAck::new(&witness))
+        );
+        let VersionedChunkStateWitnessAck::V2(ack) =
+            VersionedChunkStateWitnessAck::new(&witness, 3, true, 2, version)
+        else {
+            panic!("expected V2 ack");
+        };
+        assert_eq!(ack.chunk_hash, witness.chunk_header.chunk_hash());
+        assert_eq!(ack.parts_received_at_decode, 3);
+        assert!(ack.via_full_witness);
+        assert_eq!(ack.decode_attempts, 2);
+    }
+
+    /// The V1 acks keep the layout understood by the nodes not aware of the versioning.
+    #[test]
+    fn v1_ack_layout_is_unchanged() {
+        let witness = ChunkStateWitness::new_dummy(42, 0, CryptoHash::default());
+        let ack = ChunkStateWitnessAck::new(&witness);
+        let chunk_hash = borsh::to_vec(&witness.chunk_header.chunk_hash()).unwrap();
+        assert_eq!(borsh::to_vec(&ack).unwrap(), chunk_hash);
+
+        let held_ack = HeldChunkStateWitnessAck::new(ack.clone(), Duration::microseconds(7));
+        let batch = BatchedChunkStateWitnessAck { acks: vec![held_ack.clone()] };
+        let mut expected = 1u32.to_le_bytes().to_vec();
+        expected.extend(&chunk_hash);
+        expected.extend(7u32.to_le_bytes());
+        assert_eq!(borsh::to_vec(&batch).unwrap(), expected);
+
+        // A batch of V1 acks goes back to the V1 layout, one with a V2 ack can't.
+        let batch_v2 = BatchedChunkStateWitnessAckV2::from(batch.clone());
+        assert_eq!(batch_v2.acks[0].held(), Duration::microseconds(7));
+        assert_eq!(batch_v2.clone().into_v1(), Ok(batch));
+        let mut mixed_batch = batch_v2;
+        let version = ProtocolFeature::WitnessAckDecodeStats.protocol_version();
+        mixed_batch.acks.push(HeldChunkStateWitnessAckV2::new(
+            VersionedChunkStateWitnessAck::new(&witness, 3, false, 1, version),
+            Duration::ZERO,
+        ));
+        assert_eq!(mixed_batch.clone().into_v1(), Err(mixed_batch));
+    }
 }
@@ -1,3 +1,4 @@
+use near_primitives_core::hash::{CryptoHash, CryptoHasher};
 use std::io::{self, Read, Write};
 
 /// Wrapper for Write that counts number of bytes written.
@@ -92,6 +93,60 @@ impl<R: Read> Read for CountingRead<R> {
     }
 }
 
+/// Wrapper for Write that hashes the bytes written, see `CryptoHasher`.
+pub struct HashingWrite<W: Write> {
+    inner: W,
+    hasher: CryptoHasher,
+}
+
+impl<W: Write> HashingWrite<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, hasher: CryptoHasher::default() }
+    }
+
+    /// Returns the inner writer along with the hash of the bytes written.
+    pub fn finish(self) -> (W, CryptoHash) {
+        (self.inner, self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWrite<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let last_written = self.inner.write(buffer)?;
+        self.hasher.update(&buffer[..last_written]);
+        Ok(last_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Wrapper for Read that hashes the bytes read, see `CryptoHasher`.
+pub struct HashingRead<R: Read> {
+    inner: R,
+    hasher: CryptoHasher,
+}
+
+impl<R: Read> HashingRead<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, hasher: CryptoHasher::default() }
+    }
+
+    /// Returns the inner reader along with the hash of the bytes read.
+    pub fn finish(self) -> (R, CryptoHash) {
+        (self.inner, self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingRead<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let last_read = self.inner.read(buffer)?;
+        self.hasher.update(&buffer[..last_read]);
+        Ok(last_read)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::{Buf, BufMut};
@@ -175,4 +230,20 @@ mod tests {
         assert_eq!("Exceeded the limit of 41 bytes", error.to_string());
         assert_eq!(io::ErrorKind::WriteZero, error.kind());
     }
+
+    #[test]
+    fn hashing_write_and_read() {
+        let source: Vec<u8> = (1..=42).collect();
+        let expected = near_primitives_core::hash::CryptoHash::hash_bytes(&source);
+
+        let mut hashing_write = super::HashingWrite::new(Vec::new().writer());
+        io::copy(&mut source.reader(), &mut hashing_write).unwrap();
+        let (target, hash) = hashing_write.finish();
+        assert_eq!(target.into_inner(), source);
+        assert_eq!(hash, expected);
+
+        let mut hashing_read = super::HashingRead::new(source.reader());
+        io::copy(&mut hashing_read, &mut io::sink()).unwrap();
+        assert_eq!(hashing_read.finish().1, expected);
+    }
 }
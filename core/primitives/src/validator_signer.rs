@@ -12,7 +12,11 @@ use crate::sharding::ChunkHash;
 use crate::stateless_validation::chunk_endorsement::{
     ChunkEndorsementInner, ChunkEndorsementMetadata,
 };
-use crate::stateless_validation::partial_witness::PartialEncodedStateWitnessInner;
+use crate::stateless_validation::partial_witness::{
+    FullEncodedStateWitnessInner, PartialEncodedStateWitnessInner,
+    PartialEncodedStateWitnessInnerV2, PartialWitnessPartsCommitment,
+    PartialWitnessPartsCommitmentV2, WitnessReceiverStatusInner,
+};
 use crate::stateless_validation::state_witness::EncodedChunkStateWitness;
 use crate::telemetry::TelemetryInfo;
 use crate::types::{AccountId, BlockHeight, EpochId};
@@ -120,6 +124,65 @@ impl ValidatorSigner {
         }
     }
 
+    /// Signs partial encoded state witness carrying the time at which it is sent.
+    pub fn sign_partial_encoded_state_witness_v2(
+        &self,
+        part: &PartialEncodedStateWitnessInnerV2,
+    ) -> Signature {
+        match self {
+            ValidatorSigner::Empty(signer) => signer.sign_partial_encoded_state_witness_v2(part),
+            ValidatorSigner::InMemory(signer) => signer.sign_partial_encoded_state_witness_v2(part),
+        }
+    }
+
+    /// Signs the commitment to all the parts of a partial encoded state witness.
+    pub fn sign_partial_witness_parts_commitment(
+        &self,
+        commitment: &PartialWitnessPartsCommitment,
+    ) -> Signature {
+        match self {
+            ValidatorSigner::Empty(signer) => {
+                signer.sign_partial_witness_parts_commitment(commitment)
+            }
+            ValidatorSigner::InMemory(signer) => {
+                signer.sign_partial_witness_parts_commitment(commitment)
+            }
+        }
+    }
+
+    /// Signs the commitment to all the parts of a partial encoded state witness together with the
+    /// protocol version the parts were created under.
+    pub fn sign_partial_witness_parts_commitment_v2(
+        &self,
+        commitment: &PartialWitnessPartsCommitmentV2,
+    ) -> Signature {
+        match self {
+            ValidatorSigner::Empty(signer) => {
+                signer.sign_partial_witness_parts_commitment_v2(commitment)
+            }
+            ValidatorSigner::InMemory(signer) => {
+                signer.sign_partial_witness_parts_commitment_v2(commitment)
+            }
+        }
+    }
+
+    pub fn sign_full_encoded_state_witness(
+        &self,
+        witness: &FullEncodedStateWitnessInner,
+    ) -> Signature {
+        match self {
+            ValidatorSigner::Empty(signer) => signer.sign_full_encoded_state_witness(witness),
+            ValidatorSigner::InMemory(signer) => signer.sign_full_encoded_state_witness(witness),
+        }
+    }
+
+    pub fn sign_witness_receiver_status(&self, status: &WitnessReceiverStatusInner) -> Signature {
+        match self {
+            ValidatorSigner::Empty(signer) => signer.sign_witness_receiver_status(status),
+            ValidatorSigner::InMemory(signer) => signer.sign_witness_receiver_status(status),
+        }
+    }
+
     /// Signs challenge body.
     pub fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
         match self {
@@ -256,6 +319,38 @@ impl EmptyValidatorSigner {
         Signature::default()
     }
 
+    fn sign_partial_encoded_state_witness_v2(
+        &self,
+        _part: &PartialEncodedStateWitnessInnerV2,
+    ) -> Signature {
+        Signature::default()
+    }
+
+    fn sign_partial_witness_parts_commitment(
+        &self,
+        _commitment: &PartialWitnessPartsCommitment,
+    ) -> Signature {
+        Signature::default()
+    }
+
+    fn sign_partial_witness_parts_commitment_v2(
+        &self,
+        _commitment: &PartialWitnessPartsCommitmentV2,
+    ) -> Signature {
+        Signature::default()
+    }
+
+    fn sign_full_encoded_state_witness(
+        &self,
+        _witness: &FullEncodedStateWitnessInner,
+    ) -> Signature {
+        Signature::default()
+    }
+
+    fn sign_witness_receiver_status(&self, _status: &WitnessReceiverStatusInner) -> Signature {
+        Signature::default()
+    }
+
     fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
         (CryptoHash::hash_borsh(challenge_body), Signature::default())
     }
@@ -357,6 +452,35 @@ impl InMemoryValidatorSigner {
         self.signer.sign(&borsh::to_vec(part).unwrap())
     }
 
+    fn sign_partial_encoded_state_witness_v2(
+        &self,
+        part: &PartialEncodedStateWitnessInnerV2,
+    ) -> Signature {
+        self.signer.sign(&borsh::to_vec(part).unwrap())
+    }
+
+    fn sign_partial_witness_parts_commitment(
+        &self,
+        commitment: &PartialWitnessPartsCommitment,
+    ) -> Signature {
+        self.signer.sign(&borsh::to_vec(commitment).unwrap())
+    }
+
+    fn sign_partial_witness_parts_commitment_v2(
+        &self,
+        commitment: &PartialWitnessPartsCommitmentV2,
+    ) -> Signature {
+        self.signer.sign(&borsh::to_vec(commitment).unwrap())
+    }
+
+    fn sign_full_encoded_state_witness(&self, witness: &FullEncodedStateWitnessInner) -> Signature {
+        self.signer.sign(&borsh::to_vec(witness).unwrap())
+    }
+
+    fn sign_witness_receiver_status(&self, status: &WitnessReceiverStatusInner) -> Signature {
+        self.signer.sign(&borsh::to_vec(status).unwrap())
+    }
+
     fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
         let hash = CryptoHash::hash_borsh(challenge_body);
         let signature = self.signer.sign(hash.as_ref());
@@ -24,6 +24,7 @@ use crate::sharding::{
     ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderInnerV2,
     ShardChunkHeaderInnerV3, ShardChunkHeaderV3,
 };
+use crate::stateless_validation::state_witness::ChunkStateWitnessSectionSizes;
 #[cfg(feature = "protocol_feature_nonrefundable_transfer_nep491")]
 use crate::transaction::NonrefundableStorageTransferAction;
 use crate::transaction::{
@@ -33,10 +34,10 @@ use crate::transaction::{
     SignedTransaction, StakeAction, TransferAction,
 };
 use crate::types::{
-    AccountId, AccountWithPublicKey, Balance, BlockHeight, EpochHeight, EpochId, FunctionArgs, Gas,
-    Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind, StateChangeValue,
-    StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey, StoreValue,
-    ValidatorKickoutReason,
+    AccountId, AccountWithPublicKey, Balance, BlockHeight, BlockHeightDelta, EpochHeight, EpochId,
+    FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
+    StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
+    StoreValue, ValidatorKickoutReason,
 };
 use crate::version::{ProtocolVersion, Version};
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -717,6 +718,122 @@ pub struct StatusResponse {
     /// Information about last blocks, network, epoch and chain & chunk info.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detailed_debug_status: Option<DetailedDebugStatus>,
+    /// Whether the node is currently able to perform its chunk validator duties. None until the
+    /// first health report of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stateless_validation_health: Option<StatelessValidationHealthView>,
+    /// Witness distribution summaries of the recent epochs, starting from the most recent one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub epoch_witness_stats: Vec<EpochWitnessStatsView>,
+    /// Effective configuration of the witness distribution. None until the head of the node is
+    /// known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness_config: Option<WitnessConfigView>,
+    /// Recent activity of the witness distribution of the node. None until the first report of
+    /// the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_witness_debug: Option<PartialWitnessDebugView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatelessValidationHealthView {
+    /// Whether all the checks pass.
+    pub healthy: bool,
+    pub checks: Vec<StatelessValidationHealthCheckView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatelessValidationHealthCheckView {
+    pub name: String,
+    pub healthy: bool,
+    /// What the check found, e.g. the measured value and the threshold.
+    pub details: String,
+}
+
+/// Witness distribution of an epoch as seen by the node, persisted once the head left the epoch.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochWitnessStatsView {
+    pub epoch_id: CryptoHash,
+    /// Heights of the first and the last head seen in the epoch, the chunks outside of them are
+    /// not covered, e.g. when the node was started in the middle of the epoch.
+    pub first_head_height: BlockHeight,
+    pub last_head_height: BlockHeight,
+    pub shards: Vec<ShardWitnessStatsView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardWitnessStatsView {
+    pub shard_id: ShardId,
+    /// Witnesses of the chunks the node was a chunk validator of.
+    pub witnesses_expected: u64,
+    /// Witnesses decoded before the block at their height.
+    pub decoded_on_time: u64,
+    /// Witnesses decoded after the block at their height, too late to be endorsed.
+    pub decoded_late: u64,
+    /// Witnesses not decoded by the end of the epoch.
+    pub never_decoded: u64,
+    /// Witnesses distributed by the node as the chunk producer.
+    pub witnesses_distributed: u64,
+    /// Median time from distributing a witness to the acks of two thirds of its chunk
+    /// validators, None if no witness reached it.
+    pub median_time_to_ack_threshold_millis: Option<u64>,
+}
+
+/// Configuration of the witness distribution in effect on the node.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct WitnessConfigView {
+    /// `PartialWitnessConfig` of the node, including the defaults of the options not set in
+    /// config.json.
+    pub partial_witness: serde_json::Value,
+    /// Max serialized size of a part sent in one routed message, the larger parts are sent as
+    /// fragments.
+    pub max_routed_message_payload: usize,
+    /// Limits derived from the protocol version of the epoch of the head.
+    pub protocol_limits: WitnessProtocolLimitsView,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WitnessProtocolLimitsView {
+    pub protocol_version: ProtocolVersion,
+    /// Size of the compressed witness the chunk producers are expected to stay below.
+    pub witness_size_limit: u64,
+    /// Percentage of the limit by which a witness may exceed it and still be accepted.
+    pub witness_size_grace_percent: u64,
+    /// Size above which the witnesses are rejected.
+    pub max_accepted_witness_size: u64,
+    /// Size below which the produced witnesses are sent uncompressed, zero if the protocol
+    /// version requires all the witnesses to be compressed.
+    pub uncompressed_witness_threshold: u64,
+    /// Format of the parts produced by the node.
+    pub part_format: String,
+    /// Oldest format of the parts accepted from the chunk producers.
+    pub oldest_accepted_part_format: String,
+}
+
+/// Recent activity of the witness distribution of the node, for debugging.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartialWitnessDebugView {
+    /// Section sizes of the most recently produced witnesses, starting from the most recent one.
+    pub produced_witness_section_sizes: Vec<ProducedWitnessSectionSizesView>,
+    /// Lag of the chunk validator duty of the node behind the head, per shard of the epoch of
+    /// the head.
+    pub validation_lag: Vec<ShardValidationLagView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProducedWitnessSectionSizesView {
+    pub epoch_id: CryptoHash,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub section_sizes: ChunkStateWitnessSectionSizes,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardValidationLagView {
+    pub shard_id: ShardId,
+    /// Number of the heads since the last chunk validated by the node, see the
+    /// `near_partial_witness_validation_lag` metric.
+    pub lag: BlockHeightDelta,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -293,6 +293,17 @@ pub enum DBCol {
     /// Witnesses with the lowest index are garbage collected first.
     /// u64 -> LatestWitnessesKey
     LatestWitnessesByIndex,
+    /// Temporary storage for the state witness parts spilled from memory by the partial witness
+    /// tracker. The column is cleared on startup.
+    /// - *Rows*: EpochId || ShardId || BlockHeight
+    /// - *Column type*: `Vec<(usize, Box<[u8]>)>`, i.e. (part_ord, part) pairs
+    PartialWitnessSpilledParts,
+    /// Summaries of the witness distribution of the recent epochs, as seen by this node as a
+    /// chunk validator and as a chunk producer. Only the configured number of the most recent
+    /// epochs is kept.
+    /// - *Rows*: EpochId
+    /// - *Column type*: `VersionedEpochWitnessStats`
+    PartialWitnessEpochStats,
 }
 
 /// Defines different logical parts of a db key.
@@ -465,6 +476,11 @@ impl DBCol {
             // LatestChunkStateWitnesses stores the last N observed witnesses, used only for debugging.
             DBCol::LatestChunkStateWitnesses => false,
             DBCol::LatestWitnessesByIndex => false,
+            // PartialWitnessSpilledParts is only needed while reconstructing witnesses.
+            DBCol::PartialWitnessSpilledParts => false,
+            // PartialWitnessEpochStats is pruned by the partial witness actor, used only for
+            // the status RPC.
+            DBCol::PartialWitnessEpochStats => false,
             // Deprecated.
             DBCol::_ReceiptIdToShardId => false,
 
@@ -566,6 +582,10 @@ impl DBCol {
             DBCol::StateTransitionData => &[DBKeyType::BlockHash, DBKeyType::ShardId],
             DBCol::LatestChunkStateWitnesses => &[DBKeyType::LatestWitnessesKey],
             DBCol::LatestWitnessesByIndex => &[DBKeyType::LatestWitnessIndex],
+            DBCol::PartialWitnessSpilledParts => {
+                &[DBKeyType::EpochId, DBKeyType::ShardId, DBKeyType::BlockHeight]
+            }
+            DBCol::PartialWitnessEpochStats => &[DBKeyType::EpochId],
         }
     }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex, RwLock};
 
 use near_async::futures::FutureSpawner;
@@ -29,7 +30,7 @@ use near_network::test_loop::{TestLoopNetworkSharedState, TestLoopPeerManagerAct
 use near_parameters::RuntimeConfigStore;
 use near_primitives::network::PeerId;
 use near_primitives::test_utils::create_test_signer;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, BlockHeight};
 use near_store::config::StateSnapshotType;
 use near_store::genesis::initialize_genesis_state;
 use near_store::test_utils::{create_test_split_store, create_test_store};
@@ -39,7 +40,10 @@ use nearcore::state_sync::StateSyncDumper;
 use tempfile::TempDir;
 
 use super::env::{ClientToShardsManagerSender, TestData, TestLoopChunksStorage, TestLoopEnv};
-use super::utils::network::partial_encoded_chunks_dropper;
+use super::utils::network::{
+    blocks_at_heights_dropper, blocks_delayer, partial_encoded_chunks_dropper,
+    witness_parts_recorder, witness_sends_failer, SentWitnessParts,
+};
 
 pub(crate) struct TestLoopBuilder {
     test_loop: TestLoopV2,
@@ -60,6 +64,14 @@ pub(crate) struct TestLoopBuilder {
     chunks_storage: Arc<Mutex<TestLoopChunksStorage>>,
     /// Whether test loop should drop all chunks validated by the given account.
     drop_chunks_validated_by: Option<AccountId>,
+    /// Heights at which test loop should drop the produced blocks, effectively skipping them.
+    skip_block_heights: HashSet<BlockHeight>,
+    /// Account to which test loop should deliver the blocks with the given additional delay.
+    delay_blocks_to: Option<(AccountId, Duration)>,
+    /// Where test loop should record the recipients of the witness parts sent over the network.
+    record_witness_parts: Option<SentWitnessParts>,
+    /// While set, test loop fails every other witness part and forward sent by the nodes.
+    fail_witness_sends: Option<Arc<AtomicBool>>,
     /// Number of latest epochs to keep before garbage collecting associated data.
     gc_num_epochs_to_keep: Option<u64>,
     /// The store of runtime configurations to be passed into runtime adapters.
@@ -81,6 +93,10 @@ impl TestLoopBuilder {
             archival_clients: HashSet::new(),
             chunks_storage: Default::default(),
             drop_chunks_validated_by: None,
+            skip_block_heights: HashSet::new(),
+            delay_blocks_to: None,
+            record_witness_parts: None,
+            fail_witness_sends: None,
             gc_num_epochs_to_keep: None,
             runtime_config_store: None,
             config_modifier: None,
@@ -124,6 +140,32 @@ impl TestLoopBuilder {
         self
     }
 
+    /// Drops the blocks produced at the given heights, so that the chain skips them.
+    pub(crate) fn skip_block_heights(mut self, heights: HashSet<BlockHeight>) -> Self {
+        self.skip_block_heights = heights;
+        self
+    }
+
+    /// Delivers the blocks to the given account with an additional delay, so that it lags
+    /// behind the rest of the chain.
+    pub(crate) fn delay_blocks_to(mut self, account_id: &str, delay: Duration) -> Self {
+        self.delay_blocks_to = Some((account_id.parse().unwrap(), delay));
+        self
+    }
+
+    /// Records the recipients of all the witness parts sent over the network into `sent_parts`.
+    pub(crate) fn record_witness_parts(mut self, sent_parts: SentWitnessParts) -> Self {
+        self.record_witness_parts = Some(sent_parts);
+        self
+    }
+
+    /// Fails every other witness part and forward sent by the nodes while `failing` is set, and
+    /// reports the outcomes of the sends to the nodes.
+    pub(crate) fn fail_witness_sends(mut self, failing: Arc<AtomicBool>) -> Self {
+        self.fail_witness_sends = Some(failing);
+        self
+    }
+
     pub(crate) fn gc_num_epochs_to_keep(mut self, num_epochs: u64) -> Self {
         self.gc_num_epochs_to_keep = Some(num_epochs);
         self
@@ -424,7 +466,9 @@ impl TestLoopBuilder {
             client_adapter.as_multi_sender(),
             validator_signer.clone(),
             epoch_manager.clone(),
+            shard_tracker.clone(),
             store,
+            client_config.partial_witness.clone(),
         );
 
         let gc_actor = GCActor::new(
@@ -510,6 +554,14 @@ impl TestLoopBuilder {
                 Arc::new(self.test_loop.future_spawner()),
             );
 
+            // The handlers run in the reverse order of registration, so the recorder registered
+            // first only sees the parts which are actually sent.
+            if let Some(sent_parts) = &self.record_witness_parts {
+                peer_manager_actor.register_override_handler(witness_parts_recorder(
+                    data.account_id.clone(),
+                    sent_parts.clone(),
+                ));
+            }
             if let Some(account_id) = &self.drop_chunks_validated_by {
                 peer_manager_actor.register_override_handler(partial_encoded_chunks_dropper(
                     self.chunks_storage.clone(),
@@ -517,6 +569,25 @@ impl TestLoopBuilder {
                     account_id.clone(),
                 ));
             }
+            if !self.skip_block_heights.is_empty() {
+                peer_manager_actor.register_override_handler(blocks_at_heights_dropper(
+                    self.skip_block_heights.clone(),
+                ));
+            }
+            if let Some((account_id, delay)) = &self.delay_blocks_to {
+                peer_manager_actor.register_override_handler(blocks_delayer(
+                    datas,
+                    &data.account_id,
+                    account_id.clone(),
+                    *delay,
+                ));
+            }
+            if let Some(failing) = &self.fail_witness_sends {
+                peer_manager_actor.register_override_handler(witness_sends_failer(
+                    data.partial_witness_sender.clone(),
+                    failing.clone(),
+                ));
+            }
 
             self.test_loop.register_actor_for_index(
                 idx,
@@ -0,0 +1,77 @@
+use near_async::time::Duration;
+use near_client::WitnessDecodePath;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::witness_test_genesis_builder;
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Runs the chain with the chunk producers sending the full witness directly to the two chunk
+/// validators with the highest stake. These validators receive the witness both in full and in
+/// parts, which must not interfere with the validation: all the chunks must keep getting
+/// endorsed and included. Some of the witnesses must be decoded from the full witness, unless
+/// `ProtocolFeature::DirectFullWitness` isn't enabled yet, in which case none is sent.
+#[test]
+fn test_direct_full_witness() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .config_modifier(|config, _| {
+            config.partial_witness.direct_full_witness_targets = 2;
+        })
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    let mut keys = vec![];
+    while block.header().height() > start_height {
+        assert!(
+            block.header().chunk_mask().iter().all(|included| *included),
+            "missing chunks at height {}: {:?}",
+            block.header().height(),
+            block.header().chunk_mask()
+        );
+        keys.extend(block.chunks().iter().map(|chunk_header| ChunkProductionKey {
+            epoch_id: *block.header().epoch_id(),
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+        }));
+        block = chain.get_block(block.header().prev_hash()).unwrap();
+    }
+
+    // The full witness reaches its targets before enough parts do, at least for some chunks.
+    let full_witness_decodes = node_datas
+        .iter()
+        .map(|node| test_loop.data.get(&node.partial_witness_sender.actor_handle()))
+        .flat_map(|actor| keys.iter().filter_map(|key| actor.witness_decode_path(key)))
+        .filter(|path| *path == WitnessDecodePath::FullWitness)
+        .count();
+    if ProtocolFeature::DirectFullWitness.enabled(PROTOCOL_VERSION) {
+        assert!(full_witness_decodes > 0, "no witness was decoded from the full witness");
+    } else {
+        assert_eq!(full_witness_decodes, 0, "full witness sent before the feature is enabled");
+    }
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
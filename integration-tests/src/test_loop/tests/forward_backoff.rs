@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_o11y::testonly::init_test_logger;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::witness_test_genesis_builder;
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Runs the chain with the network failing half of the witness parts and forwards. The forwards
+/// must back off on some validator once the failures are reported, and return to normal on all
+/// of them once the sends succeed again and the failures decay.
+#[test]
+fn test_forward_backoff() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    let failing = Arc::new(AtomicBool::new(true));
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .config_modifier(|config, _| {
+            // Shorter than the default, so that the failures decay within a few blocks.
+            config.partial_witness.forward_backoff.half_life = Duration::seconds(2);
+        })
+        .fail_witness_sends(failing.clone())
+        .build();
+
+    let partial_witness_handles =
+        node_datas.iter().map(|data| data.partial_witness_sender.actor_handle()).collect_vec();
+    test_loop.run_until(
+        |test_loop_data| {
+            partial_witness_handles.iter().any(|handle| {
+                !test_loop_data.get(handle).forward_backoff_engaged_shards().is_empty()
+            })
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    failing.store(false, Ordering::Relaxed);
+    test_loop.run_until(
+        |test_loop_data| {
+            partial_witness_handles.iter().all(|handle| {
+                test_loop_data.get(handle).forward_backoff_engaged_shards().is_empty()
+            })
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
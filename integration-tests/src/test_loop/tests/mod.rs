@@ -1,12 +1,24 @@
 mod chunk_validator_kickout;
 pub mod congestion_control;
 pub mod congestion_control_genesis_bootstrap;
+pub mod direct_full_witness;
 pub mod epoch_sync;
 pub mod fix_min_stake_ratio;
+pub mod forward_backoff;
 pub mod in_memory_tries;
 pub mod max_receipt_size;
 pub mod multinode_stateless_validators;
 pub mod multinode_test_loop_example;
+pub mod no_forward_shards;
+pub mod part_format_upgrade;
+pub mod pre_tracked_shards;
 pub mod simple_test_loop_example;
+pub mod skipped_blocks;
+pub mod sole_chunk_validator;
 pub mod syncing;
+pub mod validator_churn;
 pub mod view_requests_to_archival_node;
+pub mod witness_ahead_of_block;
+pub mod witness_on_fork;
+pub mod witness_receiver_unavailable;
+pub mod witness_stats_export;
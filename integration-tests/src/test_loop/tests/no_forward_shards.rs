@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::ShardId;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::network::SentWitnessParts;
+use crate::test_loop::utils::witness_distribution::{
+    assert_all_chunks_included, witness_test_genesis_builder,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+/// Index of the chunk validator which doesn't forward the parts of `NO_FORWARD_SHARD`.
+const NO_FORWARD_VALIDATOR: usize = 5;
+const NO_FORWARD_SHARD: ShardId = 0;
+
+/// Runs the chain with one chunk validator excluding a shard from forwarding. The validator must
+/// not forward any part of the excluded shard while still forwarding the parts of the other
+/// shards, and the other validators must keep forwarding the parts of the excluded shard. The
+/// remaining parts must be enough for all the chunks to get endorsed and included.
+#[test]
+fn test_no_forward_shards() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    let sent_parts: SentWitnessParts = Arc::new(Mutex::new(vec![]));
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts.clone())
+        .config_modifier(|config, idx| {
+            if idx == NO_FORWARD_VALIDATOR {
+                config.partial_witness.no_forward_shards = vec![NO_FORWARD_SHARD];
+            }
+        })
+        .record_witness_parts(sent_parts.clone())
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    let no_forward_validator = &accounts[NO_FORWARD_VALIDATOR];
+    let forwarded_parts = sent_parts
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|part| part.forwarded)
+        .map(|part| (part.sender.clone(), part.key.shard_id))
+        .collect_vec();
+    assert!(
+        !forwarded_parts.contains(&(no_forward_validator.clone(), NO_FORWARD_SHARD)),
+        "{} forwarded a part of shard {}",
+        no_forward_validator,
+        NO_FORWARD_SHARD
+    );
+    assert!(forwarded_parts
+        .iter()
+        .any(|(sender, shard_id)| sender == no_forward_validator && *shard_id != NO_FORWARD_SHARD));
+    assert!(forwarded_parts
+        .iter()
+        .any(|(sender, shard_id)| sender != no_forward_validator && *shard_id == NO_FORWARD_SHARD));
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    assert_all_chunks_included(chain, start_height);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,64 @@
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::AccountId;
+use near_primitives::version::PROTOCOL_VERSION;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::assert_all_chunks_included;
+use crate::test_loop::utils::ONE_NEAR;
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Runs the network across a protocol upgrade. The parts of the witnesses of the last chunks of
+/// the epoch before the upgrade are still in flight when the head enters the upgraded epoch, so
+/// the chunk validators receive the parts of both protocol versions at once. All the chunks must
+/// be endorsed and included on both sides of the upgrade. The nodes can't vote for a protocol
+/// version above `PROTOCOL_VERSION`, below which the parts don't change format yet, so the format
+/// change itself is covered by the client test
+/// `parts_of_both_formats_are_accepted_across_the_send_timestamp_upgrade`.
+#[test]
+fn test_witness_parts_across_protocol_upgrade() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let accounts =
+        (0..4).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version(PROTOCOL_VERSION - 1)
+        .genesis_height(10000)
+        .shard_layout_simple_v1(&["account1", "account3"])
+        .epoch_length(EPOCH_LENGTH)
+        .validators_desired_roles(&accounts_str, &[]);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), 10000 * ONE_NEAR);
+    }
+    let genesis = genesis_builder.build();
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } =
+        builder.genesis(genesis).clients(accounts).build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    // The nodes vote for the new version in the first epoch, which is adopted two epochs later.
+    test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            let protocol_version =
+                client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+            protocol_version == PROTOCOL_VERSION && tip.height >= start_height + 4 * EPOCH_LENGTH
+        },
+        Duration::seconds(5 * EPOCH_LENGTH as i64),
+    );
+    let client = &test_loop.data.get(&client_handle).client;
+    assert_all_chunks_included(&client.chain, start_height);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
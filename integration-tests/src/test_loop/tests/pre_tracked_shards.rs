@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_client::ChunkStateWitnessOutcome;
+use near_o11y::testonly::init_test_logger;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::network::SentWitnessParts;
+use crate::test_loop::utils::witness_distribution::{
+    assert_all_chunks_included, witness_test_genesis_builder,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Runs the chain across several epoch boundaries with all validators pre-tracking all
+/// the shards. Towards the end of every epoch, the owners of the parts forward them to the
+/// chunk validators of the shard in the next epoch, which reconstruct the witnesses without
+/// endorsing them. Pre-tracking must not interfere with the regular witness distribution,
+/// i.e. all the chunks keep getting endorsed and included.
+#[test]
+fn test_pre_tracked_shards_across_epoch_boundary() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, mut genesis_builder) =
+        witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    genesis_builder
+        .shuffle_shard_assignment_for_chunk_producers(true)
+        // Give one mandate to each chunk validator, so that most of the validators
+        // are assigned to a single shard and pre-track the other ones.
+        .target_validator_mandates_per_shard(1);
+    let genesis = genesis_builder.build();
+    let shard_ids = genesis.config.shard_layout.shard_ids().collect_vec();
+
+    let sent_parts: SentWitnessParts = Arc::new(Mutex::new(vec![]));
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .config_modifier(move |config, _| {
+            config.partial_witness.pre_tracked_shards = shard_ids.clone();
+        })
+        .record_witness_parts(sent_parts.clone())
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 3 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    // Some parts were forwarded to validators which are not chunk validators of the chunk.
+    let epoch_manager = test_loop.data.get(&client_handle).client.epoch_manager.clone();
+    let pre_tracking_forwards = sent_parts
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|part| part.forwarded)
+        .filter(|part| {
+            !epoch_manager
+                .get_chunk_validator_assignments(
+                    &part.key.epoch_id,
+                    part.key.shard_id,
+                    part.key.height_created,
+                )
+                .unwrap()
+                .contains(&part.recipient)
+        })
+        .map(|part| (part.recipient.clone(), part.key.clone()))
+        .collect_vec();
+    assert!(!pre_tracking_forwards.is_empty(), "no part was forwarded for pre-tracking");
+
+    // The pre-tracking validators reconstructed the witnesses and didn't endorse them.
+    let mut pre_tracked_witnesses = 0;
+    for node in &node_datas {
+        let actor = test_loop.data.get(&node.partial_witness_sender.actor_handle());
+        let outcomes = actor.recent_witness_outcomes().collect_vec();
+        for record in &outcomes {
+            if record.outcome != ChunkStateWitnessOutcome::PreTracked {
+                continue;
+            }
+            pre_tracked_witnesses += 1;
+            assert!(
+                pre_tracking_forwards.contains(&(node.account_id.clone(), record.key.clone())),
+                "{} pre-tracked {:?} without receiving its parts",
+                node.account_id,
+                record.key
+            );
+            assert!(
+                !outcomes.iter().any(|other| other.key == record.key
+                    && other.outcome == ChunkStateWitnessOutcome::Endorsed),
+                "{} endorsed the pre-tracked {:?}",
+                node.account_id,
+                record.key
+            );
+        }
+    }
+    assert!(pre_tracked_witnesses > 0, "no witness was reconstructed for pre-tracking");
+
+    // Check that all the chunks were endorsed and included, including the ones around
+    // the epoch boundaries where the set of pre-tracking validators changes.
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    assert_all_chunks_included(chain, start_height);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
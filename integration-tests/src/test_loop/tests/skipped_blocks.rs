@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use near_async::time::Duration;
+use near_o11y::testonly::init_test_logger;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::{
+    witness_test_genesis_builder, WITNESS_TEST_GENESIS_HEIGHT,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Skips a single height and then two consecutive heights while the chunk production keeps
+/// going. The chunks created at the skipped heights are built on top of the last produced
+/// block, so their witnesses must pass the validation and reconstruction and the chunks must
+/// get endorsed and included in the first block after the skip.
+#[test]
+fn test_witness_distribution_with_skipped_blocks() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    // The heights are after the warmup, and the second skip crosses the epoch boundary.
+    let skipped_heights: HashSet<u64> = [
+        WITNESS_TEST_GENESIS_HEIGHT + 6,
+        WITNESS_TEST_GENESIS_HEIGHT + 10,
+        WITNESS_TEST_GENESIS_HEIGHT + 11,
+    ]
+    .into_iter()
+    .collect();
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .skip_block_heights(skipped_heights.clone())
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = WITNESS_TEST_GENESIS_HEIGHT + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    let mut chunk_production_keys = HashSet::new();
+    while block.header().height() > start_height {
+        let height = block.header().height();
+        let prev_block = chain.get_block(block.header().prev_hash()).unwrap();
+        let prev_height = prev_block.header().height();
+        assert!(!skipped_heights.contains(&height), "block at skipped height {height}");
+
+        // All the chunks must be endorsed, including the ones created at the skipped heights.
+        assert!(
+            block.header().chunk_mask().iter().all(|included| *included),
+            "missing chunks at height {}: {:?}",
+            height,
+            block.header().chunk_mask()
+        );
+        for chunk in block.chunks().iter() {
+            // The chunks are produced on top of the previous block, so after a skip they are
+            // created at a lower height than the one they get included at.
+            assert_eq!(chunk.height_created(), prev_height + 1);
+            assert_eq!(chunk.height_included(), height);
+            // The witnesses are tracked by the chunk production key, which must not repeat.
+            assert!(
+                chunk_production_keys.insert((chunk.shard_id(), chunk.height_created())),
+                "chunk production key repeated for shard {} at height {}",
+                chunk.shard_id(),
+                chunk.height_created()
+            );
+        }
+        block = prev_block;
+    }
+    // The chunks created at the first height of each skipped range were built on top of the
+    // block before the skip, i.e. the scenario was actually exercised.
+    for skipped_height in &skipped_heights {
+        if skipped_heights.contains(&(skipped_height - 1)) {
+            continue;
+        }
+        assert!(
+            chunk_production_keys
+                .iter()
+                .any(|(_, height_created)| height_created == skipped_height),
+            "no chunks created at skipped height {skipped_height}"
+        );
+    }
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,120 @@
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::AccountId;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::assert_all_chunks_included;
+use crate::test_loop::utils::ONE_NEAR;
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// Runs a single node network, where the node produces all the chunks and is their only chunk
+/// validator, so the witnesses never leave the node. All the chunks must be endorsed and included.
+#[test]
+fn test_single_node_network() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let account: AccountId = "account0".parse().unwrap();
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .shard_layout_simple_v1(&["account3", "account5"])
+        .epoch_length(EPOCH_LENGTH)
+        .validators_desired_roles(&[account.as_str()], &[])
+        .add_user_account_simple(account.clone(), 10000 * ONE_NEAR);
+    let genesis = genesis_builder.build();
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } =
+        builder.genesis(genesis).clients(vec![account]).build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+    assert_all_chunks_included(&test_loop.data.get(&client_handle).client.chain, start_height);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Runs two validators with one mandate per shard, so that most of the chunks have a single chunk
+/// validator, which often isn't their chunk producer. Such a validator receives the only part of
+/// the witness and has nobody to forward it to. All the chunks must be endorsed and included.
+#[test]
+fn test_two_nodes_with_sole_chunk_validator() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let accounts =
+        (0..2).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .shard_layout_simple_v1(&["account1"])
+        .epoch_length(EPOCH_LENGTH)
+        .validators_desired_roles(&accounts_str, &[])
+        .shuffle_shard_assignment_for_chunk_producers(true)
+        .target_validator_mandates_per_shard(1);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), 10000 * ONE_NEAR);
+    }
+    let genesis = genesis_builder.build();
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } =
+        builder.genesis(genesis).clients(accounts).build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 3 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+    let client = &test_loop.data.get(&client_handle).client;
+    assert_all_chunks_included(&client.chain, start_height);
+
+    // Make sure the almost degenerate case was exercised.
+    let chain = &client.chain;
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    let mut num_remote_sole_validators = 0;
+    while block.header().height() > start_height {
+        let epoch_id = block.header().epoch_id();
+        for chunk_header in block.chunks().iter() {
+            let shard_id = chunk_header.shard_id();
+            let height_created = chunk_header.height_created();
+            let chunk_producer = client
+                .epoch_manager
+                .get_chunk_producer(epoch_id, height_created, shard_id)
+                .unwrap();
+            let chunk_validators = client
+                .epoch_manager
+                .get_chunk_validator_assignments(epoch_id, shard_id, height_created)
+                .unwrap()
+                .ordered_chunk_validators();
+            if chunk_validators.len() == 1 && chunk_validators[0] != chunk_producer {
+                num_remote_sole_validators += 1;
+            }
+        }
+        block = chain.get_block(block.header().prev_hash()).unwrap();
+    }
+    assert!(num_remote_sole_validators > 0);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
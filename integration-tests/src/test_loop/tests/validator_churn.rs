@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_epoch_manager::EpochManagerAdapter;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::test_utils::{create_test_signer, create_user_test_signer};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Balance, EpochId};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::network::{SentWitnessPart, SentWitnessParts};
+use crate::test_loop::utils::transactions::{get_shared_block_hash, run_tx};
+use crate::test_loop::utils::ONE_NEAR;
+
+const EPOCH_LENGTH: u64 = 10;
+/// Number of heights on each side of the epoch boundary checked in detail.
+const BOUNDARY_HEIGHTS: u64 = 3;
+
+/// Half of the chunk validators leave and the same number of new validators join at an epoch
+/// boundary. The witness parts of the last chunks of the old epoch must reach only the old
+/// chunk validators, the parts of the first chunks of the new epoch only the new ones, and no
+/// part, sent or forwarded, may reach a node which isn't a chunk validator of the chunk, which
+/// would fail on it with `NotAChunkValidator`. All the chunks around the boundary must gather
+/// enough endorsements to be included.
+#[test]
+fn test_chunk_validators_rotate_between_epochs() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let initial_balance = 1_000_000 * ONE_NEAR;
+    let accounts =
+        (0..6).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let clients = accounts.clone();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+    let block_and_chunk_producers = &accounts_str[0..2];
+    let departing_validators = &accounts[2..4];
+    let joining_validators = &accounts[4..6];
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .shard_layout_simple_v1(&["account2", "account4"])
+        .epoch_length(EPOCH_LENGTH)
+        .validators_desired_roles(block_and_chunk_producers, &accounts_str[2..4]);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let genesis = genesis_builder.build();
+
+    let sent_parts: SentWitnessParts = Arc::new(Mutex::new(vec![]));
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } =
+        builder.genesis(genesis).clients(clients).record_witness_parts(sent_parts.clone()).build();
+
+    // The departing validators unstake and the joining ones stake slightly less than the
+    // producers, so that they take over the chunk validator only seats two epochs later.
+    let stakes: Vec<(&AccountId, Balance)> = departing_validators
+        .iter()
+        .map(|account_id| (account_id, 0))
+        .chain(joining_validators.iter().map(|account_id| (account_id, 9990 * ONE_NEAR)))
+        .collect();
+    for (account_id, stake) in stakes {
+        let tx = SignedTransaction::stake(
+            1,
+            account_id.clone(),
+            &create_user_test_signer(account_id).into(),
+            stake,
+            create_test_signer(account_id.as_str()).public_key(),
+            get_shared_block_hash(&node_datas, &test_loop),
+        );
+        run_tx(&mut test_loop, tx, &node_datas, Duration::seconds(5));
+    }
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let joined = |client: &near_client::Client| {
+        let head = client.chain.head().unwrap();
+        let validators = client.epoch_manager.get_epoch_all_validators(&head.epoch_id).unwrap();
+        validators.iter().any(|validator| validator.account_id() == &joining_validators[0])
+    };
+    test_loop.run_until(
+        |test_loop_data| joined(&test_loop_data.get(&client_handle).client),
+        Duration::seconds(4 * EPOCH_LENGTH as i64),
+    );
+    let new_epoch_start = {
+        let client = &test_loop.data.get(&client_handle).client;
+        let head = client.chain.head().unwrap();
+        client.epoch_manager.get_epoch_start_height(&head.last_block_hash).unwrap()
+    };
+    test_loop.run_until(
+        |test_loop_data| {
+            let head = test_loop_data.get(&client_handle).client.chain.head().unwrap();
+            head.height >= new_epoch_start + 2 * BOUNDARY_HEIGHTS
+        },
+        Duration::seconds(4 * BOUNDARY_HEIGHTS as i64),
+    );
+
+    let client = &test_loop.data.get(&client_handle).client;
+    let chain = &client.chain;
+    let epoch_manager = client.epoch_manager.as_ref();
+    let new_epoch_id =
+        chain.get_block_by_height(new_epoch_start).unwrap().header().epoch_id().clone();
+    let old_epoch_id =
+        chain.get_block_by_height(new_epoch_start - 1).unwrap().header().epoch_id().clone();
+    let epoch_validators = |epoch_id: &EpochId| {
+        epoch_manager
+            .get_epoch_all_validators(epoch_id)
+            .unwrap()
+            .into_iter()
+            .map(|validator| validator.account_id().clone())
+            .collect::<HashSet<_>>()
+    };
+    let old_validators = epoch_validators(&old_epoch_id);
+    let new_validators = epoch_validators(&new_epoch_id);
+    assert!(departing_validators.iter().all(|account_id| old_validators.contains(account_id)));
+    assert!(departing_validators.iter().all(|account_id| !new_validators.contains(account_id)));
+    assert!(joining_validators.iter().all(|account_id| !old_validators.contains(account_id)));
+    assert!(joining_validators.iter().all(|account_id| new_validators.contains(account_id)));
+
+    let boundary_heights = new_epoch_start - BOUNDARY_HEIGHTS..new_epoch_start + BOUNDARY_HEIGHTS;
+    let mut joining_recipients = HashSet::new();
+    let mut num_old_epoch_parts = 0;
+    for SentWitnessPart { key, recipient, .. } in sent_parts.lock().unwrap().iter() {
+        let chunk_validators = epoch_manager
+            .get_chunk_validator_assignments(&key.epoch_id, key.shard_id, key.height_created)
+            .unwrap();
+        assert!(
+            chunk_validators.contains(recipient),
+            "part of chunk at height {} of shard {} sent to {} which is not its chunk validator",
+            key.height_created,
+            key.shard_id,
+            recipient
+        );
+        if !boundary_heights.contains(&key.height_created) {
+            continue;
+        }
+        if key.epoch_id == old_epoch_id {
+            assert!(!joining_validators.contains(recipient));
+            num_old_epoch_parts += 1;
+        } else {
+            assert_eq!(key.epoch_id, new_epoch_id);
+            assert!(!departing_validators.contains(recipient));
+            joining_recipients.insert(recipient.clone());
+        }
+    }
+    assert!(num_old_epoch_parts > 0);
+    for account_id in joining_validators {
+        assert!(joining_recipients.contains(account_id), "{} didn't receive any part", account_id);
+    }
+
+    // The chunks are included only once their endorsements reach the threshold.
+    for height in boundary_heights {
+        let block = chain.get_block_by_height(height).unwrap();
+        for chunk in block.chunks().iter() {
+            assert_eq!(
+                chunk.height_included(),
+                height,
+                "chunk of shard {} missing in block at height {}",
+                chunk.shard_id(),
+                height
+            );
+        }
+    }
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,83 @@
+use near_async::time::Duration;
+use near_epoch_manager::EpochManagerAdapter;
+use near_o11y::testonly::init_test_logger;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::{
+    witness_test_genesis_builder, WITNESS_TEST_NUM_VALIDATORS,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+
+/// The blocks reach one of the chunk validators with a delay, so it reconstructs the witnesses
+/// before the blocks the chunks are built on. The witnesses must wait in the orphan witness
+/// pool and get validated once the blocks arrive, so the validator still endorses every chunk
+/// it is assigned to.
+#[test]
+fn test_witness_reconstructed_ahead_of_prev_block() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let lagging_validator = accounts[WITNESS_TEST_NUM_VALIDATORS - 1].clone();
+    let genesis = genesis_builder.build();
+
+    // Much longer than distributing the witness, but well within the block production time.
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .delay_blocks_to(lagging_validator.as_str(), Duration::milliseconds(300))
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    let client = &test_loop.data.get(&client_handle).client;
+    let chain = &client.chain;
+    let epoch_manager = client.epoch_manager.as_ref();
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    let mut num_endorsed_chunks = 0;
+    while block.header().height() > start_height {
+        for (chunk, endorsements) in block.chunks().iter().zip_eq(block.chunk_endorsements()) {
+            if chunk.height_included() != block.header().height() {
+                continue;
+            }
+            let epoch_id =
+                epoch_manager.get_epoch_id_from_prev_block(chunk.prev_block_hash()).unwrap();
+            let chunk_validators = epoch_manager
+                .get_chunk_validator_assignments(
+                    &epoch_id,
+                    chunk.shard_id(),
+                    chunk.height_created(),
+                )
+                .unwrap()
+                .ordered_chunk_validators();
+            let Some(position) =
+                chunk_validators.iter().position(|account_id| account_id == &lagging_validator)
+            else {
+                continue;
+            };
+            assert!(
+                endorsements[position].is_some(),
+                "chunk at height {} of shard {} isn't endorsed by {}",
+                chunk.height_created(),
+                chunk.shard_id(),
+                lagging_validator
+            );
+            num_endorsed_chunks += 1;
+        }
+        block = chain.get_block(block.header().prev_hash()).unwrap();
+    }
+    assert!(num_endorsed_chunks > 0);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
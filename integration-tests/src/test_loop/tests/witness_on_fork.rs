@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+
+use near_async::time::Duration;
+use near_epoch_manager::EpochManagerAdapter;
+use near_o11y::testonly::init_test_logger;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::witness_distribution::{
+    witness_test_genesis_builder, WITNESS_TEST_GENESIS_HEIGHT,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+/// Height whose block only its producer knows, see `test_witness_on_losing_then_winning_fork`.
+const FORK_HEIGHT: u64 = WITNESS_TEST_GENESIS_HEIGHT + 6;
+
+/// The block at `FORK_HEIGHT` reaches nobody but its producer, so the producer keeps it as its
+/// head while the rest of the chain builds the next block on the previous one, making a
+/// two-block fork. The witnesses of the chunks of the next block reach the producer while its
+/// head is on the losing side of the fork, and the producer switches to the winning side once
+/// that block arrives. With the fork-aware height window, the producer must still validate and
+/// endorse these witnesses instead of dropping them, and the fork block must stay off the chain.
+#[test]
+fn test_witness_on_losing_then_winning_fork() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts)
+        .skip_block_heights(HashSet::from([FORK_HEIGHT]))
+        .config_modifier(|config, _| {
+            config.partial_witness.fork_aware_height_window = true;
+        })
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let target_height = WITNESS_TEST_GENESIS_HEIGHT + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    // The first block after the fork is built on the block before it, which is the winning side.
+    let client = &test_loop.data.get(&client_handle).client;
+    let chain = &client.chain;
+    let epoch_manager = client.epoch_manager.as_ref();
+    let fork_prev_hash = chain.get_block_hash_by_height(FORK_HEIGHT - 1).unwrap();
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&fork_prev_hash).unwrap();
+    let fork_producer = epoch_manager.get_block_producer(&epoch_id, FORK_HEIGHT).unwrap();
+    assert!(chain.get_block_hash_by_height(FORK_HEIGHT).is_err());
+    let winning_block =
+        chain.get_block(&chain.get_block_hash_by_height(FORK_HEIGHT + 1).unwrap()).unwrap();
+    assert_eq!(winning_block.header().prev_hash(), &fork_prev_hash);
+
+    // The producer of the fork block has it in its store, off the canonical chain.
+    let fork_producer_data =
+        node_datas.iter().find(|data| data.account_id == fork_producer).unwrap();
+    let fork_producer_chain =
+        &test_loop.data.get(&fork_producer_data.client_sender.actor_handle()).client.chain;
+    let fork_blocks =
+        fork_producer_chain.chain_store().get_all_block_hashes_by_height(FORK_HEIGHT).unwrap();
+    assert_eq!(fork_blocks.values().flatten().count(), 1);
+    assert!(fork_producer_chain.get_block_hash_by_height(FORK_HEIGHT).is_err());
+
+    // The chunks of the winning block were endorsed by the producer of the fork block wherever
+    // it is one of their chunk validators, although its head was on the losing side.
+    let mut num_endorsed_chunks = 0;
+    for (chunk, endorsements) in
+        winning_block.chunks().iter().zip_eq(winning_block.chunk_endorsements())
+    {
+        assert_eq!(chunk.height_included(), FORK_HEIGHT + 1);
+        let chunk_validators = epoch_manager
+            .get_chunk_validator_assignments(&epoch_id, chunk.shard_id(), chunk.height_created())
+            .unwrap()
+            .ordered_chunk_validators();
+        let Some(position) =
+            chunk_validators.iter().position(|account_id| account_id == &fork_producer)
+        else {
+            continue;
+        };
+        assert!(
+            endorsements[position].is_some(),
+            "chunk at height {} of shard {} isn't endorsed by {}",
+            chunk.height_created(),
+            chunk.shard_id(),
+            fork_producer
+        );
+        num_endorsed_chunks += 1;
+    }
+    assert!(num_endorsed_chunks > 0);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
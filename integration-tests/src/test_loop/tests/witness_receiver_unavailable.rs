@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex};
+
+use itertools::Itertools;
+use near_async::messaging::CanSend;
+use near_async::time::Duration;
+use near_client::AnnounceWitnessReceiverUnavailable;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::network::SentWitnessParts;
+use crate::test_loop::utils::witness_distribution::{
+    assert_all_chunks_included, witness_test_genesis_builder,
+};
+
+const EPOCH_LENGTH: u64 = 10;
+/// Index of the chunk validator announcing that it can't receive the witness parts.
+const UNAVAILABLE_VALIDATOR: usize = 5;
+/// Number of heights after the announcement within which the chunk producers may still send
+/// the parts to the unavailable validator, since the announcement takes a while to arrive.
+const ANNOUNCEMENT_DELAY_HEIGHTS: u64 = 2;
+
+/// Runs the chain with one chunk validator announcing that it is unavailable, as it does on
+/// graceful shutdown. The chunk producers must stop sending the validator its parts and send
+/// these parts directly to the other chunk validators instead, so that all the chunks still get
+/// endorsed and included. Before `ProtocolFeature::WitnessReceiverStatus` nothing is announced,
+/// so the validator keeps receiving its parts.
+#[test]
+fn test_witness_receiver_unavailable() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let (accounts, genesis_builder) = witness_test_genesis_builder(&builder.clock(), EPOCH_LENGTH);
+    let genesis = genesis_builder.build();
+
+    let sent_parts: SentWitnessParts = Arc::new(Mutex::new(vec![]));
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(accounts.clone())
+        .config_modifier(|config, idx| {
+            if idx == UNAVAILABLE_VALIDATOR {
+                config.partial_witness.announce_unavailability_on_shutdown =
+                    Some(Duration::minutes(5));
+            }
+        })
+        .record_witness_parts(sent_parts.clone())
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height
+                >= start_height + 3
+        },
+        Duration::seconds(5),
+    );
+
+    node_datas[UNAVAILABLE_VALIDATOR]
+        .partial_witness_sender
+        .send(AnnounceWitnessReceiverUnavailable);
+    let announce_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = announce_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    let unavailable_validator = &accounts[UNAVAILABLE_VALIDATOR];
+    let sent_parts = sent_parts.lock().unwrap();
+    assert!(
+        sent_parts.iter().any(|part| !part.forwarded
+            && &part.recipient == unavailable_validator
+            && part.key.height_created <= announce_height),
+        "{} didn't own any part before the announcement",
+        unavailable_validator
+    );
+    let parts_after_announcement = sent_parts
+        .iter()
+        .filter(|part| part.key.height_created > announce_height + ANNOUNCEMENT_DELAY_HEIGHTS)
+        .collect_vec();
+    let sent_own_parts = parts_after_announcement
+        .iter()
+        .any(|part| !part.forwarded && &part.recipient == unavailable_validator);
+    if ProtocolFeature::WitnessReceiverStatus.enabled(PROTOCOL_VERSION) {
+        assert!(
+            !sent_own_parts,
+            "{} was sent its part after announcing that it is unavailable",
+            unavailable_validator
+        );
+        let redistributed_parts = parts_after_announcement
+            .iter()
+            .filter(|part| &part.owner == unavailable_validator)
+            .collect_vec();
+        // The validator is still running in the test, so it may request its parts from the chunk
+        // producers, which is the only way it receives them.
+        assert!(redistributed_parts.iter().any(|part| &part.recipient != unavailable_validator));
+        for part in &redistributed_parts {
+            assert!(part.forwarded);
+            assert_ne!(&part.sender, unavailable_validator);
+        }
+    } else {
+        assert!(
+            sent_own_parts,
+            "{} wasn't sent its part after the announcement",
+            unavailable_validator
+        );
+    }
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    assert_all_chunks_included(chain, announce_height);
+
+    drop(sent_parts);
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
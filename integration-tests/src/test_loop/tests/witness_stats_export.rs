@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_client::test_utils::witness_stats::read_witness_stats;
+use near_client::{WitnessStatsRecord, WitnessStatsSource};
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::ONE_NEAR;
+
+const EPOCH_LENGTH: u64 = 10;
+const NUM_VALIDATORS: usize = 4;
+/// Real time within which the export threads are expected to write the queued records.
+const EXPORT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Runs the chain with every node exporting the witness stats. Every chunk in the chain must be
+/// exported exactly once as produced, by its chunk producer, and be reconstructed by the other
+/// chunk validators.
+#[test]
+fn test_witness_stats_export() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let initial_balance = 10000 * ONE_NEAR;
+    let accounts = (0..NUM_VALIDATORS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let clients: Vec<AccountId> = accounts.clone();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .shard_layout_simple_v1(&["account2"])
+        .epoch_length(EPOCH_LENGTH)
+        .validators_desired_roles(&accounts_str, &[]);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let genesis = genesis_builder.build();
+
+    let export_dir = tempfile::tempdir().unwrap();
+    let export_dirs =
+        (0..NUM_VALIDATORS).map(|idx| export_dir.path().join(format!("node{idx}"))).collect_vec();
+    let node_export_dirs = export_dirs.clone();
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .clients(clients)
+        .config_modifier(move |config, idx| {
+            config.partial_witness.export_witness_stats_dir = Some(node_export_dirs[idx].clone());
+        })
+        .build();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let target_height = start_height + 2 * EPOCH_LENGTH;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    let mut chunk_keys = HashSet::<(ShardId, BlockHeight)>::new();
+    while block.header().height() > start_height {
+        for chunk in block.chunks().iter() {
+            if chunk.height_included() == block.header().height() {
+                chunk_keys.insert((chunk.shard_id(), chunk.height_created()));
+            }
+        }
+        block = chain.get_block(block.header().prev_hash()).unwrap();
+    }
+    assert!(!chunk_keys.is_empty());
+
+    // The records are written by the export threads, wait until all the chunks are exported.
+    let deadline = std::time::Instant::now() + EXPORT_TIMEOUT;
+    let records = loop {
+        let records = read_all_witness_stats(&export_dirs);
+        let exported = records
+            .iter()
+            .filter(|(_, record)| record.source == WitnessStatsSource::Produced)
+            .map(|(_, record)| (record.shard_id, record.height_created))
+            .collect::<HashSet<_>>();
+        if chunk_keys.is_subset(&exported) || std::time::Instant::now() > deadline {
+            break records;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    };
+
+    let mut produced = HashMap::<(ShardId, BlockHeight), Vec<(usize, &WitnessStatsRecord)>>::new();
+    let mut reconstructed = HashMap::<(ShardId, BlockHeight), usize>::new();
+    for (idx, record) in &records {
+        let key = (record.shard_id, record.height_created);
+        match record.source {
+            WitnessStatsSource::Produced => produced.entry(key).or_default().push((*idx, record)),
+            WitnessStatsSource::Reconstructed => *reconstructed.entry(key).or_default() += 1,
+        }
+    }
+    for (key, exports) in &produced {
+        assert_eq!(exports.len(), 1, "witness of chunk {key:?} exported as produced repeatedly");
+    }
+    let epoch_manager = &test_loop.data.get(&client_handle).client.epoch_manager;
+    for key in &chunk_keys {
+        let Some([(idx, record)]) = produced.get(key).map(Vec::as_slice) else {
+            panic!("witness of chunk {key:?} not exported as produced");
+        };
+        let chunk_producer = epoch_manager
+            .get_chunk_producer(&record.epoch_id, record.height_created, record.shard_id)
+            .unwrap();
+        assert_eq!(chunk_producer, accounts[*idx], "chunk {key:?} exported by a non-producer");
+        let section_sizes = record.section_sizes.as_ref().unwrap();
+        assert_eq!(record.raw_size, section_sizes.total());
+        assert!(record.compressed_size > 0);
+        assert!(record.num_validators > 0);
+        assert!(reconstructed.contains_key(key), "witness of chunk {key:?} never reconstructed");
+    }
+    for (_, record) in records.iter().filter(|(_, r)| r.source == WitnessStatsSource::Reconstructed)
+    {
+        assert!(record.section_sizes.is_none());
+        assert!(record.decode_latency_millis.is_some());
+    }
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Reads the witness stats exported by each node, together with the index of the node.
+fn read_all_witness_stats(export_dirs: &[PathBuf]) -> Vec<(usize, WitnessStatsRecord)> {
+    export_dirs
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, dir)| {
+            read_witness_stats(dir).unwrap().into_iter().map(move |record| (idx, record))
+        })
+        .collect()
+}
@@ -2,6 +2,7 @@ pub mod network;
 pub mod setups;
 pub mod transactions;
 pub mod validators;
+pub mod witness_distribution;
 
 pub(crate) const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
 pub(crate) const TGAS: u64 = 1_000_000_000_000;
@@ -1,7 +1,20 @@
-use crate::test_loop::env::TestLoopChunksStorage;
+use crate::test_loop::env::{TestData, TestLoopChunksStorage, NETWORK_DELAY};
+use near_async::messaging::CanSend;
+use near_async::test_loop::sender::TestLoopSender;
+use near_async::time::Duration;
+use near_client::PartialWitnessActor;
 use near_epoch_manager::EpochManagerAdapter;
+use near_network::client::BlockResponse;
+use near_network::state_witness::{
+    WitnessDelivery, WitnessDeliveryPath, WitnessDeliveryReportMessage, WitnessRoutingHints,
+};
 use near_network::types::NetworkRequests;
-use near_primitives::types::AccountId;
+use near_primitives::hash::CryptoHash;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 /// Handler to drop all network messages relevant to chunk validated by
@@ -75,3 +88,162 @@ pub fn partial_encoded_chunks_dropper(
         return None;
     })
 }
+
+/// Handler to drop the blocks produced at `heights_to_skip`, so that the other nodes never
+/// see them and the next block producer builds on top of the previous block instead, as if
+/// the heights were skipped. The requests for the dropped blocks are dropped as well, otherwise
+/// the nodes would fetch them once they learn about them, e.g. from a block built on top.
+pub fn blocks_at_heights_dropper(
+    heights_to_skip: HashSet<BlockHeight>,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let dropped_blocks = Mutex::new(HashSet::<CryptoHash>::new());
+    Box::new(move |request| match &request {
+        NetworkRequests::Block { block } if heights_to_skip.contains(&block.header().height()) => {
+            dropped_blocks.lock().unwrap().insert(*block.hash());
+            None
+        }
+        NetworkRequests::BlockRequest { hash, .. }
+            if dropped_blocks.lock().unwrap().contains(hash) =>
+        {
+            None
+        }
+        _ => Some(request),
+    })
+}
+
+/// Handler to deliver the blocks broadcasted by `my_account_id` to `delayed_account_id` with
+/// an additional `delay`, so that the node lags behind the chain and receives the witnesses of
+/// the chunks before the blocks they are built on. The other nodes get the blocks as usual.
+pub fn blocks_delayer(
+    datas: &[TestData],
+    my_account_id: &AccountId,
+    delayed_account_id: AccountId,
+    delay: Duration,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let my_peer_id =
+        datas.iter().find(|data| &data.account_id == my_account_id).unwrap().peer_id.clone();
+    let client_senders = datas
+        .iter()
+        .filter(|data| &data.account_id != my_account_id)
+        .map(|data| {
+            let delay = if data.account_id == delayed_account_id {
+                NETWORK_DELAY + delay
+            } else {
+                NETWORK_DELAY
+            };
+            data.client_sender.clone().with_delay(delay)
+        })
+        .collect::<Vec<_>>();
+    Box::new(move |request| match request {
+        NetworkRequests::Block { block } => {
+            for client_sender in &client_senders {
+                client_sender.send(BlockResponse {
+                    block: block.clone(),
+                    peer_id: my_peer_id.clone(),
+                    was_requested: false,
+                });
+            }
+            None
+        }
+        _ => Some(request),
+    })
+}
+
+/// Witness part sent over the network.
+#[derive(Debug, Clone)]
+pub struct SentWitnessPart {
+    pub key: ChunkProductionKey,
+    pub sender: AccountId,
+    pub recipient: AccountId,
+    /// Chunk validator owning the part: the recipient of the parts sent by the chunk producer
+    /// and the sender of the forwarded ones.
+    pub owner: AccountId,
+    /// Whether the part was forwarded by its owner rather than sent by the chunk producer.
+    pub forwarded: bool,
+}
+
+/// Witness parts sent over the network by all the nodes.
+pub type SentWitnessParts = Arc<Mutex<Vec<SentWitnessPart>>>;
+
+/// Handler to record the witness parts sent by `sender`, both the parts sent by the chunk
+/// producers and the parts forwarded by their owners. The requests are passed on unchanged.
+pub fn witness_parts_recorder(
+    sender: AccountId,
+    sent_parts: SentWitnessParts,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    Box::new(move |request| {
+        match &request {
+            NetworkRequests::PartialEncodedStateWitness(parts, _) => {
+                let mut sent_parts = sent_parts.lock().unwrap();
+                for (target, partial_witness) in parts {
+                    sent_parts.push(SentWitnessPart {
+                        key: partial_witness.chunk_production_key(),
+                        sender: sender.clone(),
+                        recipient: target.clone(),
+                        owner: target.clone(),
+                        forwarded: false,
+                    });
+                }
+            }
+            NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, _) => {
+                let key = partial_witness.chunk_production_key();
+                sent_parts.lock().unwrap().extend(targets.iter().map(|target| SentWitnessPart {
+                    key: key.clone(),
+                    sender: sender.clone(),
+                    recipient: target.clone(),
+                    owner: sender.clone(),
+                    forwarded: true,
+                }));
+            }
+            _ => {}
+        }
+        Some(request)
+    })
+}
+
+/// Handler to fail every other witness part and forward sent by the node while `failing` is set,
+/// as if the network couldn't reach half of the chunk validators. The outcome of every send is
+/// reported to the partial witness actor of the node, as the peer manager does.
+pub fn witness_sends_failer(
+    partial_witness_sender: TestLoopSender<PartialWitnessActor>,
+    failing: Arc<AtomicBool>,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let sends = Cell::new(0usize);
+    let send = move |target: &AccountId, hints: &WitnessRoutingHints| {
+        let failed = failing.load(Ordering::Relaxed) && sends.get() % 2 == 0;
+        sends.set(sends.get() + 1);
+        WitnessDelivery {
+            target: target.clone(),
+            preference: hints.preference(target),
+            path: if failed { WitnessDeliveryPath::Failed } else { WitnessDeliveryPath::Routed },
+        }
+    };
+    Box::new(move |request| {
+        let mut deliveries = vec![];
+        let request = match request {
+            NetworkRequests::PartialEncodedStateWitness(parts, hints) => {
+                let parts = parts
+                    .into_iter()
+                    .filter(|(target, _)| {
+                        deliveries.push(send(target, &hints));
+                        deliveries.last().unwrap().path != WitnessDeliveryPath::Failed
+                    })
+                    .collect();
+                NetworkRequests::PartialEncodedStateWitness(parts, hints)
+            }
+            NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, hints) => {
+                let targets = targets
+                    .into_iter()
+                    .filter(|target| {
+                        deliveries.push(send(target, &hints));
+                        deliveries.last().unwrap().path != WitnessDeliveryPath::Failed
+                    })
+                    .collect();
+                NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, hints)
+            }
+            _ => return Some(request),
+        };
+        partial_witness_sender.send(WitnessDeliveryReportMessage(deliveries));
+        Some(request)
+    })
+}
@@ -0,0 +1,57 @@
+//! Setup and checks shared by the tests of the witness distribution.
+
+use itertools::Itertools;
+use near_async::time::Clock;
+use near_chain::Chain;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta};
+
+use crate::test_loop::utils::ONE_NEAR;
+
+pub(crate) const WITNESS_TEST_GENESIS_HEIGHT: BlockHeight = 10000;
+
+/// Number of the validators, the first 4 of which produce blocks and chunks and the others only
+/// validate chunks.
+pub(crate) const WITNESS_TEST_NUM_VALIDATORS: usize = 6;
+
+/// Genesis with `WITNESS_TEST_NUM_VALIDATORS` validators account{i} with 10k NEAR each, over
+/// 3 shards. Returns the accounts, which are all meant to be clients, and the genesis builder,
+/// which the tests may adjust further before building the genesis.
+pub(crate) fn witness_test_genesis_builder(
+    clock: &Clock,
+    epoch_length: BlockHeightDelta,
+) -> (Vec<AccountId>, TestGenesisBuilder) {
+    let accounts = (0..WITNESS_TEST_NUM_VALIDATORS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+    let (block_and_chunk_producers, chunk_validators_only) = accounts_str.split_at(4);
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(clock)
+        .protocol_version_latest()
+        .genesis_height(WITNESS_TEST_GENESIS_HEIGHT)
+        .shard_layout_simple_v1(&["account2", "account4"])
+        .epoch_length(epoch_length)
+        .validators_desired_roles(block_and_chunk_producers, chunk_validators_only);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), 10000 * ONE_NEAR);
+    }
+    (accounts, genesis_builder)
+}
+
+/// Checks that every block above `start_height` on the canonical chain includes all the chunks,
+/// i.e. that all of them were endorsed.
+pub(crate) fn assert_all_chunks_included(chain: &Chain, start_height: BlockHeight) {
+    let mut block = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+    while block.header().height() > start_height {
+        assert!(
+            block.header().chunk_mask().iter().all(|included| *included),
+            "missing chunks at height {}: {:?}",
+            block.header().height(),
+            block.header().chunk_mask()
+        );
+        block = chain.get_block(block.header().prev_hash()).unwrap();
+    }
+}
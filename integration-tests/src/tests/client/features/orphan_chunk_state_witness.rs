@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::sync::Arc;
 
 use near_chain::stateless_validation::processing_tracker::{
     ProcessingDoneTracker, ProcessingDoneWaiter,
@@ -133,7 +134,8 @@ fn setup_orphan_witness_test() -> OrphanWitnessTestEnv {
     let partial_witness_adapter =
         env.partial_witness_adapters[env.get_client_index(&block2_chunk_producer)].clone();
     while let Some(request) = partial_witness_adapter.pop_distribution_request() {
-        let DistributeStateWitnessRequest { epoch_id, chunk_header, state_witness } = request;
+        let DistributeStateWitnessRequest { epoch_id, chunk_header, state_witness, .. } = request;
+        let state_witness = Arc::unwrap_or_clone(state_witness);
         let raw_witness_size = borsh_size(&state_witness);
         let chunk_validators = env
             .client(&block2_chunk_producer)
@@ -500,6 +500,7 @@ fn test_invalid_transactions() {
                     &shard_chunk,
                     transactions_storage_proof,
                     &client.validator_signer.get(),
+                    client.clock.now(),
                 )
                 .unwrap();
 
@@ -648,6 +649,49 @@ fn test_eth_implicit_accounts() {
     assert!(gas_cost < ONE_NEAR / 500);
 }
 
+/// Checks that the clients request the witness distribution only for the chunks they produce,
+/// including around the epoch boundaries where the chunk producers change due to unstaking.
+/// `TestEnv::propagate_chunk_state_witnesses` asserts that for every request, so the
+/// `NotThisChunksProducer` error of the partial witness actor can only be caused by a bug.
+#[test]
+fn test_distribute_state_witness_only_for_own_chunks() {
+    init_integration_logger();
+    let accounts: Vec<AccountId> = (0..4).map(|i| format!("test{i}").parse().unwrap()).collect();
+    let mut genesis = Genesis::test(accounts.clone(), accounts.len() as NumSeats);
+    let epoch_length = 5;
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(&genesis.config)
+        .clients(accounts.clone())
+        .validators(accounts.clone())
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let unstaking = accounts[3].clone();
+    let signer =
+        InMemorySigner::from_seed(unstaking.clone(), KeyType::ED25519, unstaking.as_str()).into();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let tx = SignedTransaction::stake(
+        1,
+        unstaking.clone(),
+        &signer,
+        0,
+        signer.public_key(),
+        genesis_hash,
+    );
+    for client in &mut env.clients {
+        let _ = client.process_tx(tx.clone(), false, false);
+    }
+
+    for _ in 0..4 * epoch_length {
+        produce_block(&mut env);
+    }
+
+    let client = &env.clients[0];
+    let epoch_id = client.chain.head().unwrap().epoch_id;
+    let epoch_info = client.epoch_manager.get_epoch_info(&epoch_id).unwrap();
+    assert!(!epoch_info.account_is_validator(&unstaking));
+}
+
 /// Produce a block, apply it and propagate it through the network (including state witnesses).
 fn produce_block(env: &mut TestEnv) {
     let heads = env
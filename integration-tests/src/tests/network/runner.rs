@@ -133,7 +133,7 @@ fn setup_network_node(
     );
     let (shards_manager_actor, _) = start_shards_manager(
         epoch_manager.clone(),
-        shard_tracker,
+        shard_tracker.clone(),
         network_adapter.as_sender(),
         client_actor.clone().with_auto_span_context().into_sender(),
         validator_signer.clone(),
@@ -146,7 +146,9 @@ fn setup_network_node(
         client_actor.clone().with_auto_span_context().into_multi_sender(),
         validator_signer,
         epoch_manager,
+        shard_tracker,
         runtime.store().clone(),
+        client_config.partial_witness.clone(),
     ));
     shards_manager_adapter.bind(shards_manager_actor.with_auto_span_context());
     let peer_manager = PeerManagerActor::spawn(
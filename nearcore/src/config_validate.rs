@@ -1,5 +1,7 @@
-use near_chain_configs::{ExternalStorageLocation, SyncConfig};
+use near_chain_configs::{ExternalStorageLocation, GenesisConfig, SyncConfig};
 use near_config_utils::{ValidationError, ValidationErrors};
+use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig};
+use near_primitives::version::PROTOCOL_VERSION;
 use std::collections::HashSet;
 use std::path::Path;
 
@@ -14,6 +16,41 @@ pub fn validate_config(config: &Config) -> Result<(), ValidationError> {
     config_validator.validate()
 }
 
+/// Validate the parts of Config which refer to the shards against the shard layout of the latest
+/// protocol version supported by this binary. The genesis shard layout isn't used, as the shards
+/// may have been split since genesis.
+pub fn validate_config_shards(
+    config: &Config,
+    genesis_config: &GenesisConfig,
+) -> Result<(), ValidationError> {
+    let mut validation_errors = ValidationErrors::new();
+    let shard_layout = AllEpochConfig::new_with_test_overrides(
+        genesis_config.use_production_config(),
+        genesis_config.protocol_version,
+        EpochConfig::from(genesis_config),
+        &genesis_config.chain_id,
+        None,
+    )
+    .for_protocol_version(PROTOCOL_VERSION)
+    .shard_layout;
+    let shard_ids: HashSet<_> = shard_layout.shard_ids().collect();
+    for shard_id in &config.partial_witness.no_forward_shards {
+        if !shard_ids.contains(shard_id) {
+            let error_message = format!(
+                "'config.partial_witness.no_forward_shards' contains shard {shard_id} which is not in the shard layout, the valid shards are {:?}.",
+                shard_layout.shard_ids().collect::<Vec<_>>()
+            );
+            validation_errors.push_config_semantics_error(error_message);
+        }
+    }
+    if validation_errors.is_empty() {
+        Ok(())
+    } else {
+        let full_err_msg = validation_errors.generate_error_message_per_type().unwrap();
+        Err(ValidationError::ConfigSemanticsError { error_message: full_err_msg })
+    }
+}
+
 struct ConfigValidator<'a> {
     config: &'a Config,
     validation_errors: &'a mut ValidationErrors,
@@ -173,6 +210,18 @@ impl<'a> ConfigValidator<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_no_forward_shards_in_shard_layout() {
+        let mut config = Config::default();
+        let genesis_config = GenesisConfig::default();
+        config.partial_witness.no_forward_shards = vec![0];
+        validate_config_shards(&config, &genesis_config).unwrap();
+
+        config.partial_witness.no_forward_shards = vec![0, 5];
+        let err = validate_config_shards(&config, &genesis_config).unwrap_err();
+        assert!(err.to_string().contains("contains shard 5 which is not in the shard layout"));
+    }
+
     #[test]
     #[should_panic(expected = "gc config values should all be greater than 0")]
     fn test_gc_config_value_nonzero() {
@@ -27,8 +27,8 @@ use near_client::adapter::client_sender_for_network;
 use near_client::gc_actor::GCActor;
 use near_client::sync::adapter::SyncAdapter;
 use near_client::{
-    start_client, ClientActor, ConfigUpdater, PartialWitnessActor, StartClientResult,
-    ViewClientActor, ViewClientActorInner,
+    clear_spilled_witness_parts, start_client, ClientActor, ConfigUpdater, PartialWitnessActor,
+    StartClientResult, ViewClientActor, ViewClientActorInner,
 };
 use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
 use near_epoch_manager::EpochManager;
@@ -214,6 +214,7 @@ fn get_split_store(config: &NearConfig, storage: &NodeStorage) -> anyhow::Result
 pub struct NearNode {
     pub client: Addr<ClientActor>,
     pub view_client: Addr<ViewClientActor>,
+    pub partial_witness: Addr<ActixWrapper<PartialWitnessActor>>,
     pub arbiters: Vec<ArbiterHandle>,
     pub rpc_servers: Vec<(&'static str, actix_web::dev::ServerHandle)>,
     /// The cold_store_loop_handle will only be set if the cold store is configured.
@@ -366,15 +367,31 @@ pub fn start_with_config_and_synchronization(
     );
     let snapshot_callbacks = SnapshotCallbacks { make_snapshot_callback, delete_snapshot_callback };
 
-    let (partial_witness_actor, partial_witness_arbiter) =
-        spawn_actix_actor(PartialWitnessActor::new(
-            Clock::real(),
-            network_adapter.as_multi_sender(),
-            client_adapter_for_partial_witness_actor.as_multi_sender(),
-            config.validator_signer.clone(),
-            epoch_manager.clone(),
-            storage.get_hot_store(),
-        ));
+    // The parts spilled by a previous run are only meaningful together with the parts held in
+    // the memory of that run.
+    clear_spilled_witness_parts(&storage.get_hot_store())
+        .context("could not clear the spilled witness parts")?;
+    let mut partial_witness_config = config.client_config.partial_witness.clone();
+    partial_witness_config.record_messages_path =
+        partial_witness_config.record_messages_path.map(|path| home_dir.join(path));
+    partial_witness_config.export_witness_stats_dir =
+        partial_witness_config.export_witness_stats_dir.map(|path| home_dir.join(path));
+    let mut partial_witness_actor = PartialWitnessActor::new(
+        Clock::real(),
+        network_adapter.as_multi_sender(),
+        client_adapter_for_partial_witness_actor.as_multi_sender(),
+        config.validator_signer.clone(),
+        epoch_manager.clone(),
+        shard_tracker.clone(),
+        storage.get_hot_store(),
+        partial_witness_config,
+    );
+    if let Some(shutdown_signal) = &shutdown_signal {
+        partial_witness_actor.set_shutdown_signal(shutdown_signal.clone());
+    }
+    partial_witness_actor
+        .set_max_routed_message_payload(config.network_config.max_routed_message_payload);
+    let (partial_witness_actor, partial_witness_arbiter) = spawn_actix_actor(partial_witness_actor);
 
     let (_gc_actor, gc_arbiter) = spawn_actix_actor(GCActor::new(
         runtime.store().clone(),
@@ -451,7 +468,7 @@ pub fn start_with_config_and_synchronization(
         config.network_config,
         client_sender_for_network(client_actor.clone(), view_client_addr.clone()),
         shards_manager_adapter.as_sender(),
-        partial_witness_actor.with_auto_span_context().into_multi_sender(),
+        partial_witness_actor.clone().with_auto_span_context().into_multi_sender(),
         genesis_id,
     )
     .context("PeerManager::spawn()")?;
@@ -474,6 +491,8 @@ pub fn start_with_config_and_synchronization(
             network_actor.into_multi_sender(),
             #[cfg(feature = "test_features")]
             _gc_actor.with_auto_span_context().into_multi_sender(),
+            #[cfg(feature = "test_features")]
+            partial_witness_actor.clone().with_auto_span_context().into_multi_sender(),
             Arc::new(entity_debug_handler),
         ));
     }
@@ -511,6 +530,7 @@ pub fn start_with_config_and_synchronization(
     Ok(NearNode {
         client: client_actor,
         view_client: view_client_addr,
+        partial_witness: partial_witness_actor,
         rpc_servers,
         arbiters,
         cold_store_loop_handle,
@@ -2,7 +2,7 @@
 use anyhow::Context;
 use near_amend_genesis::AmendGenesisCommand;
 use near_chain_configs::GenesisValidationMode;
-use near_client::ConfigUpdater;
+use near_client::{AnnounceWitnessReceiverUnavailable, ConfigUpdater};
 use near_cold_store_tool::ColdStoreCommand;
 use near_database_tool::commands::DatabaseCommand;
 use near_dyn_configs::{UpdateableConfigLoader, UpdateableConfigLoaderError, UpdateableConfigs};
@@ -14,7 +14,7 @@ use near_network::tcp;
 use near_o11y::tracing_subscriber::EnvFilter;
 use near_o11y::{
     default_subscriber, default_subscriber_with_opentelemetry, BuildEnvFilterError,
-    EnvFilterBuilder,
+    EnvFilterBuilder, WithSpanContextExt,
 };
 use near_ping::PingCommand;
 use near_primitives::hash::CryptoHash;
@@ -517,6 +517,7 @@ impl RunCmd {
             let config_updater = ConfigUpdater::new(rx_config_update);
 
             let nearcore::NearNode {
+                partial_witness,
                 rpc_servers,
                 cold_store_loop_handle,
                 mut state_sync_dumper,
@@ -542,6 +543,13 @@ impl RunCmd {
                 }
             };
             warn!(target: "neard", "{}, stopping... this may take a few minutes.", sig);
+            // Sent first, while the network is still up, so that the chunk producers stop sending
+            // the witness parts to this node.
+            if let Err(err) =
+                partial_witness.send(AnnounceWitnessReceiverUnavailable.with_span_context()).await
+            {
+                warn!(target: "neard", ?err, "Failed to announce witness receiver unavailability");
+            }
             if let Some(handle) = cold_store_loop_handle {
                 handle.stop()
             }
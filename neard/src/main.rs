@@ -40,6 +40,9 @@ fn main() -> anyhow::Result<()> {
         // Enable backtraces on panics by default.
         env::set_var("RUST_BACKTRACE", "1");
     }
+    // Report the panics in the partial witness actor with the message it was handling, before
+    // any of the threads which could panic start.
+    near_client::install_handler_panic_hook();
 
     rayon::ThreadPoolBuilder::new()
         .stack_size(8 * 1024 * 1024)
@@ -93,6 +93,30 @@ fn compute_type_hash(
 
 const PROTOCOL_SCHEMA_FILE: &str = "protocol_schema.toml";
 
+fn stored_schema_path() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(PROTOCOL_SCHEMA_FILE)
+}
+
+fn load_stored_hashes(source_path: &Path) -> BTreeMap<String, u32> {
+    if source_path.exists() {
+        toml::from_str(&fs::read_to_string(source_path).unwrap_or_else(|_| "".to_string())).unwrap()
+    } else {
+        BTreeMap::new()
+    }
+}
+
+fn compute_current_hashes(
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+) -> BTreeMap<String, u32> {
+    let mut current_hashes: BTreeMap<String, u32> = Default::default();
+    for info in inventory::iter::<ProtocolSchemaInfo> {
+        let mut types_in_compute: HashSet<TypeId> = Default::default();
+        let hash = compute_hash(info, structs, &mut types_in_compute);
+        current_hashes.insert(info.type_name().to_string(), hash);
+    }
+    current_hashes
+}
+
 fn main() {
     #[cfg(enable_const_type_id)]
     {
@@ -109,18 +133,13 @@ fn main() {
         ServerError::ensure_registration();
     }
 
-    let source_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(PROTOCOL_SCHEMA_FILE);
+    let source_path = stored_schema_path();
     let target_dir = std::env::var("CARGO_TARGET_DIR")
         .map(std::path::PathBuf::from)
         .unwrap_or_else(|_| std::path::PathBuf::from("./target"));
     let target_path = target_dir.join(PROTOCOL_SCHEMA_FILE);
 
-    let stored_hashes: BTreeMap<String, u32> = if source_path.exists() {
-        toml::from_str(&fs::read_to_string(&source_path).unwrap_or_else(|_| "".to_string()))
-            .unwrap()
-    } else {
-        BTreeMap::new()
-    };
+    let stored_hashes = load_stored_hashes(&source_path);
 
     let structs: BTreeMap<TypeId, &'static ProtocolSchemaInfo> =
         inventory::iter::<ProtocolSchemaInfo>
@@ -130,12 +149,7 @@ fn main() {
 
     println!("Loaded {} structs", structs.len());
 
-    let mut current_hashes: BTreeMap<String, u32> = Default::default();
-    for info in inventory::iter::<ProtocolSchemaInfo> {
-        let mut types_in_compute: HashSet<TypeId> = Default::default();
-        let hash = compute_hash(info, &structs, &mut types_in_compute);
-        current_hashes.insert(info.type_name().to_string(), hash);
-    }
+    let current_hashes = compute_current_hashes(&structs);
 
     let mut has_changes = false;
     for (name, hash) in &current_hashes {
@@ -455,4 +469,65 @@ mod tests {
             &collect_structs(),
         );
     }
+
+    /// Stateless validation types exchanged between the chunk producers and the chunk validators,
+    /// together with the network message carrying them.
+    const STATELESS_VALIDATION_WIRE_TYPES: &[&str] = &[
+        "ChunkStateWitness",
+        "ChunkStateWitnessAck",
+        "BatchedChunkStateWitnessAck",
+        "HeldChunkStateWitnessAck",
+        "VersionedChunkStateWitnessAck",
+        "ChunkStateWitnessAckV2",
+        "BatchedChunkStateWitnessAckV2",
+        "HeldChunkStateWitnessAckV2",
+        "EncodedChunkStateWitness",
+        "PartialEncodedStateWitness",
+        "PartialEncodedStateWitnessInner",
+        "VersionedPartialEncodedStateWitnessInner",
+        "PartialEncodedStateWitnessInnerV2",
+        "PartialEncodedStateWitnessInnerV3",
+        "PartialEncodedStateWitnessInnerV4",
+        "PartialWitnessPartsCommitment",
+        "PartialWitnessPartsCommitmentV2",
+        "PartialWitnessPartLeaf",
+        "ChunkValidatorsDigest",
+        "PartialEncodedStateWitnessRequest",
+        "FullEncodedStateWitness",
+        "FullEncodedStateWitnessInner",
+        "WitnessReceiverStatus",
+        "WitnessReceiverStatusInner",
+        "PartialEncodedStateWitnessFragment",
+        "RoutedMessageBody",
+    ];
+
+    /// Checks that the layouts of the stateless validation messages match the stored hashes.
+    /// The nodes of different versions exchange these messages within the same epoch, so
+    /// a changed layout breaks the witness distribution unless the type is versioned.
+    #[test]
+    fn test_stateless_validation_wire_types() {
+        let current_hashes = compute_current_hashes(&collect_structs());
+        let stored_hashes = load_stored_hashes(&stored_schema_path());
+        for name in STATELESS_VALIDATION_WIRE_TYPES {
+            let Some(current_hash) = current_hashes.get(*name) else {
+                panic!(
+                    "{name} is not registered in the protocol schema. Derive ProtocolSchema for \
+                    it and for the types of all its fields."
+                );
+            };
+            let Some(stored_hash) = stored_hashes.get(*name) else {
+                panic!(
+                    "{name} with hash {current_hash} is missing from res/{PROTOCOL_SCHEMA_FILE}. \
+                    Run the tool and copy the generated file to res/, see README.md."
+                );
+            };
+            assert_eq!(
+                stored_hash, current_hash,
+                "Layout of {name} changed. The nodes of the previous version won't be able to \
+                decode it: add a new version of the type instead of changing it, gated by \
+                a protocol feature, then run the tool and copy the generated file to res/, \
+                see README.md."
+            );
+        }
+    }
 }
@@ -2,6 +2,7 @@ use crate::commands::*;
 use crate::congestion_control::CongestionControlCmd;
 use crate::contract_accounts::ContractAccountFilter;
 use crate::replay_headers::replay_headers;
+use crate::replay_partial_witnesses::ReplayPartialWitnessesCmd;
 use crate::rocksdb_stats::get_rocksdb_stats;
 use crate::trie_iteration_benchmark::TrieIterationBenchmarkCmd;
 
@@ -79,6 +80,9 @@ pub enum StateViewerSubCommand {
     Receipts(ReceiptsCmd),
     /// Replay block headers from chain.
     ReplayHeaders(ReplayHeadersCmd),
+    /// Replay the witness parts recorded by a node with `partial_witness.record_messages_path`
+    /// against a partial witness actor and print what happened to each witness.
+    ReplayPartialWitnesses(ReplayPartialWitnessesCmd),
     /// Dump stats for the RocksDB storage.
     #[clap(name = "rocksdb-stats", alias = "rocksdb_stats")]
     RocksDBStats(RocksDBStatsCmd),
@@ -172,6 +176,7 @@ impl StateViewerSubCommand {
             StateViewerSubCommand::PartialChunks(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::Receipts(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::ReplayHeaders(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::ReplayPartialWitnesses(cmd) => cmd.run(near_config),
             StateViewerSubCommand::RocksDBStats(cmd) => cmd.run(store_opener.path()),
             StateViewerSubCommand::ScanDbColumn(cmd) => cmd.run(store),
             StateViewerSubCommand::State => state(home_dir, near_config, store),
@@ -0,0 +1,236 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use near_async::messaging::{noop, IntoSender, Sender};
+use near_async::time::{Clock, FakeClock, Utc};
+use near_chain::chain::ChunkStateWitnessMessage;
+use near_chain_configs::MutableConfigValue;
+use near_client::client_actor::ClientSenderForPartialWitness;
+use near_client::{
+    PartialWitnessActor, RecordedEntry, RecordedMessageKind, WitnessMessageRecordingReader,
+};
+use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
+use near_epoch_manager::EpochManager;
+use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::validator_signer::EmptyValidatorSigner;
+use near_store::test_utils::create_test_store;
+use nearcore::NearConfig;
+
+/// How the recorded parts are delivered to the actor.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+#[clap(rename_all = "kebab_case")]
+pub enum ReplayTiming {
+    /// Deliver the parts with the same delays between them as recorded.
+    Original,
+    /// Deliver the parts right after each other, advancing a fake clock by the recorded delays.
+    Fast,
+}
+
+#[derive(clap::Parser)]
+pub struct ReplayPartialWitnessesCmd {
+    /// Recording written by the node, see `partial_witness.record_messages_path` in config.json.
+    #[clap(long)]
+    recording: PathBuf,
+    #[clap(long, value_enum, default_value = "fast")]
+    timing: ReplayTiming,
+}
+
+impl ReplayPartialWitnessesCmd {
+    pub fn run(self, near_config: NearConfig) {
+        replay_partial_witnesses(&self.recording, self.timing, near_config);
+    }
+}
+
+/// What happened to a witness during the replay.
+#[derive(Default)]
+struct WitnessReplayDiagnostics {
+    owned_parts: usize,
+    forwarded_parts: usize,
+    rejected_parts: usize,
+    /// Distinct errors returned for the rejected parts.
+    errors: BTreeSet<String>,
+    /// Number of chunk validators to which the owned parts were forwarded.
+    forward_targets: usize,
+    first_part_after: Option<Duration>,
+    reconstructed_after: Option<Duration>,
+}
+
+type ReplayDiagnostics = Arc<Mutex<HashMap<ChunkProductionKey, WitnessReplayDiagnostics>>>;
+
+/// Replays the witness parts from the recording against a `PartialWitnessActor` seeded with the
+/// epoch data from the recording and prints what happened to each witness. The genesis config is
+/// the only data taken from the node, the store of the node isn't used.
+fn replay_partial_witnesses(recording: &Path, timing: ReplayTiming, near_config: NearConfig) {
+    let reader = WitnessMessageRecordingReader::open(recording).unwrap_or_else(|err| {
+        panic!("Failed to open the recording {}: {err}", recording.display())
+    });
+    let (clock, fake_clock) = match timing {
+        ReplayTiming::Original => (Clock::real(), None),
+        ReplayTiming::Fast => {
+            let fake_clock = FakeClock::new(Utc::UNIX_EPOCH);
+            (fake_clock.clock(), Some(fake_clock))
+        }
+    };
+    let started_at = clock.now();
+    let diagnostics: ReplayDiagnostics = Arc::new(Mutex::new(HashMap::new()));
+
+    let client_sender = ClientSenderForPartialWitness {
+        chunk_state_witness: Sender::from_fn({
+            let clock = clock.clone();
+            let diagnostics = diagnostics.clone();
+            move |msg: ChunkStateWitnessMessage| {
+                let mut diagnostics = diagnostics.lock().unwrap();
+                let witness = diagnostics.entry(msg.witness.chunk_production_key()).or_default();
+                witness.reconstructed_after.get_or_insert(clock.now() - started_at);
+            }
+        }),
+        partial_witness_warmed_up: noop().into_sender(),
+        stateless_validation_health: noop().into_sender(),
+        witness_config: noop().into_sender(),
+        partial_witness_error: noop().into_sender(),
+        partial_witness_debug: noop().into_sender(),
+    };
+    let network_adapter = PeerManagerAdapter {
+        async_request_sender: noop().into_sender(),
+        request_sender: Sender::from_fn({
+            let diagnostics = diagnostics.clone();
+            move |request: PeerManagerMessageRequest| {
+                if let PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, _),
+                ) = request
+                {
+                    let mut diagnostics = diagnostics.lock().unwrap();
+                    let key = partial_witness.chunk_production_key();
+                    diagnostics.entry(key).or_default().forward_targets += targets.len();
+                }
+            }
+        }),
+        set_chain_info_sender: noop().into_sender(),
+    };
+
+    let store = create_test_store();
+    let epoch_manager = EpochManager::new_arc_handle(store.clone(), &near_config.genesis.config);
+    let signer = MutableConfigValue::new(None, "validator_signer");
+    let mut config = near_config.client_config.partial_witness.clone();
+    config.record_messages_path = None;
+    // The replayed parts are handled as if the node tracked all the shards.
+    let shard_tracker = ShardTracker::new(TrackedConfig::AllShards, epoch_manager.clone());
+    let mut actor = PartialWitnessActor::new(
+        clock.clone(),
+        network_adapter,
+        client_sender,
+        signer.clone(),
+        epoch_manager,
+        shard_tracker,
+        store.clone(),
+        config,
+    );
+
+    let mut num_messages = 0;
+    for entry in reader {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                // The last frame is truncated if the node crashed while writing it.
+                eprintln!("Stopping the replay at an unreadable entry: {err}");
+                break;
+            }
+        };
+        entry.save_epoch_data(&store).unwrap();
+        let (received_after_nanos, kind, partial_witness) = match entry {
+            RecordedEntry::Signer { account_id } => {
+                signer.update(Some(Arc::new(EmptyValidatorSigner::new(account_id))));
+                continue;
+            }
+            RecordedEntry::Message { received_after_nanos, kind, partial_witness } => {
+                (received_after_nanos, kind, partial_witness)
+            }
+            RecordedEntry::Epoch { .. } | RecordedEntry::Head { .. } => continue,
+        };
+
+        let received_after = Duration::from_nanos(received_after_nanos);
+        match &fake_clock {
+            Some(fake_clock) => fake_clock.advance_until(started_at + received_after),
+            None => {
+                if let Some(wait) = received_after.checked_sub(clock.now() - started_at) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }
+
+        let key = partial_witness.chunk_production_key();
+        {
+            let mut diagnostics = diagnostics.lock().unwrap();
+            let witness = diagnostics.entry(key.clone()).or_default();
+            witness.first_part_after.get_or_insert(received_after);
+            match kind {
+                RecordedMessageKind::Owned => witness.owned_parts += 1,
+                RecordedMessageKind::Forwarded => witness.forwarded_parts += 1,
+            }
+        }
+        let result = match kind {
+            RecordedMessageKind::Owned => {
+                actor.handle_partial_encoded_state_witness(partial_witness)
+            }
+            RecordedMessageKind::Forwarded => {
+                actor.handle_partial_encoded_state_witness_forward(partial_witness, None)
+            }
+        };
+        if let Err(err) = result {
+            let mut diagnostics = diagnostics.lock().unwrap();
+            let witness = diagnostics.get_mut(&key).unwrap();
+            witness.rejected_parts += 1;
+            witness.errors.insert(err.to_string());
+        }
+        // The handlers are called directly, so the completed witnesses are decoded here instead
+        // of after the handler, regardless of `PartialWitnessConfig::decode_batch_window`.
+        for (key, err) in actor.decode_ready_witnesses() {
+            let mut diagnostics = diagnostics.lock().unwrap();
+            diagnostics.entry(key).or_default().errors.insert(err.to_string());
+        }
+        num_messages += 1;
+    }
+
+    println!("Replayed {num_messages} witness parts");
+    println!(
+        "{:>12} {:>5} {:>6} {:>9} {:>8} {:>15} {:>16} {:>19}",
+        "height",
+        "shard",
+        "owned",
+        "forwarded",
+        "rejected",
+        "forward_targets",
+        "first_part_after",
+        "reconstructed_after"
+    );
+    let diagnostics = diagnostics.lock().unwrap();
+    let mut keys: Vec<_> = diagnostics.keys().collect();
+    keys.sort_by_key(|key| (key.height_created, key.shard_id));
+    for key in keys {
+        let witness = &diagnostics[key];
+        println!(
+            "{:>12} {:>5} {:>6} {:>9} {:>8} {:>15} {:>16} {:>19}",
+            key.height_created,
+            key.shard_id,
+            witness.owned_parts,
+            witness.forwarded_parts,
+            witness.rejected_parts,
+            witness.forward_targets,
+            format_duration(witness.first_part_after),
+            format_duration(witness.reconstructed_after),
+        );
+        for error in &witness.errors {
+            println!("    {error}");
+        }
+    }
+}
+
+fn format_duration(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{}ms", duration.as_millis()),
+        None => "-".to_string(),
+    }
+}
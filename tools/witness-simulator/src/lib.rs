@@ -0,0 +1,15 @@
+//! Simulator of the distribution of the state witnesses between the chunk validators of a shard,
+//! to estimate the reconstruction latency and the traffic of a network before deploying it.
+//!
+//! The validators run the real `PartialWitnessActor`, encoding, forwarding and decoding the
+//! witnesses exactly as the nodes do, and only the network between them is modelled, see
+//! `NetworkModel`.
+
+mod network;
+mod report;
+mod scenario;
+mod simulation;
+
+pub use report::{Geometry, LatencyStats, Report, TrafficStats};
+pub use scenario::{LatencyModel, NetworkModel, Scenario};
+pub use simulation::Simulation;
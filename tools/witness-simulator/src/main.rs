@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use near_witness_simulator::{Scenario, Simulation};
+
+/// Simulates the distribution of the state witnesses described by the scenario and prints the
+/// latency and traffic statistics as JSON.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// JSON file with the scenario, see the examples in the scenarios directory.
+    scenario: PathBuf,
+    /// File to write the report to instead of the standard output.
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let scenario = std::fs::read_to_string(&args.scenario)
+        .with_context(|| format!("failed to read {}", args.scenario.display()))?;
+    let scenario: Scenario = serde_json::from_str(&scenario).context("invalid scenario")?;
+    let report = Simulation::new(scenario)?.run()?;
+    let report = serde_json::to_string_pretty(&report)?;
+    match args.output {
+        Some(output) => std::fs::write(output, report + "\n")?,
+        None => println!("{report}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use near_async::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::scenario::{LatencyModel, NetworkModel};
+
+/// Links between the validators, delivering or losing the messages according to the
+/// `NetworkModel`.
+pub struct Network {
+    /// Latency of the link from the validator at the first index to the one at the second.
+    latencies: Vec<Vec<Duration>>,
+    jitter_ms: u64,
+    loss_rate: f64,
+    upload_bytes_per_sec: u64,
+    /// Time the upload of every validator is done with the messages queued so far.
+    upload_free_at: Vec<Instant>,
+    rng: StdRng,
+}
+
+impl Network {
+    pub fn new(model: &NetworkModel, num_validators: usize, now: Instant, mut rng: StdRng) -> Self {
+        let latencies = (0..num_validators)
+            .map(|_| {
+                (0..num_validators)
+                    .map(|_| {
+                        let ms = match model.latency {
+                            LatencyModel::Fixed { ms } => ms,
+                            LatencyModel::Uniform { min_ms, max_ms } => {
+                                rng.gen_range(min_ms..=max_ms)
+                            }
+                        };
+                        Duration::milliseconds(ms as i64)
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            latencies,
+            jitter_ms: model.jitter_ms,
+            loss_rate: model.loss_rate,
+            upload_bytes_per_sec: model.upload_bytes_per_sec,
+            upload_free_at: vec![now; num_validators],
+            rng,
+        }
+    }
+
+    /// Sends a message of `bytes` from the validator `from` to the validator `to`. Returns the
+    /// time the message arrives at, or None if it is lost. The lost messages still take their
+    /// share of the upload of the sender.
+    pub fn send(&mut self, from: usize, to: usize, bytes: usize, now: Instant) -> Option<Instant> {
+        let uploaded_at = if self.upload_bytes_per_sec == 0 {
+            now
+        } else {
+            let upload_time = Duration::nanoseconds(
+                (bytes as u128 * 1_000_000_000 / self.upload_bytes_per_sec as u128) as i64,
+            );
+            let uploaded_at = self.upload_free_at[from].max(now) + upload_time;
+            self.upload_free_at[from] = uploaded_at;
+            uploaded_at
+        };
+        if self.loss_rate > 0.0 && self.rng.gen_bool(self.loss_rate) {
+            return None;
+        }
+        let jitter = if self.jitter_ms == 0 {
+            Duration::ZERO
+        } else {
+            Duration::milliseconds(self.rng.gen_range(0..=self.jitter_ms) as i64)
+        };
+        Some(uploaded_at + self.latencies[from][to] + jitter)
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use near_async::time::Duration;
+
+/// Output of a simulation, written as JSON.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Report {
+    pub geometry: Geometry,
+    /// Number of witnesses produced.
+    pub witnesses: u64,
+    /// Number of witnesses reconstructed by the chunk validators, excluding their producers.
+    pub reconstructions: u64,
+    /// Number of witnesses the chunk validators, excluding their producers, didn't reconstruct
+    /// by the end of the simulation.
+    pub failures: u64,
+    /// Time from the production of the witness to its reconstruction by a chunk validator, None
+    /// if no witness was reconstructed.
+    pub reconstruction_latency: Option<LatencyStats>,
+    /// Traffic between the validators by the kind of the message, e.g. "forward".
+    pub messages: BTreeMap<String, TrafficStats>,
+}
+
+/// Geometry of the encoding of the witnesses, computed by the encoder the validators use.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Geometry {
+    pub total_parts: usize,
+    pub data_parts: usize,
+    pub min_parts_to_decode: usize,
+    pub encoded_witness_bytes: usize,
+    pub part_bytes: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct LatencyStats {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    /// Nearest-rank percentiles of the latencies, None if there are none.
+    pub fn new(mut latencies: Vec<Duration>) -> Option<Self> {
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort();
+        let percentile = |p: f64| {
+            let rank = (p * latencies.len() as f64).ceil() as usize;
+            latencies[rank.clamp(1, latencies.len()) - 1].as_seconds_f64() * 1000.0
+        };
+        Some(Self {
+            p50_ms: percentile(0.5),
+            p90_ms: percentile(0.9),
+            p99_ms: percentile(0.99),
+            max_ms: percentile(1.0),
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TrafficStats {
+    /// Number of messages sent, including the lost ones.
+    pub sent: u64,
+    /// Number of messages lost on the way.
+    pub dropped: u64,
+    /// Size of the messages sent, including the lost ones.
+    pub bytes: u64,
+}
@@ -0,0 +1,72 @@
+use near_async::time::Duration;
+use near_chain_configs::PartialWitnessConfig;
+use near_primitives::version::ProtocolVersion;
+
+/// Input of a simulation, read from a JSON file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Number of chunk validators of the single shard, which are also its chunk producers.
+    pub num_validators: usize,
+    /// Number of witnesses produced, one per height.
+    pub num_witnesses: u64,
+    /// Size of the random payload of every witness, which is about the size of the encoded
+    /// witness since the payload doesn't compress.
+    pub witness_size_bytes: usize,
+    /// Time between the productions of two consecutive witnesses.
+    pub block_interval_ms: u64,
+    /// Time the simulation keeps running after the last witness is produced. The witnesses not
+    /// reconstructed by then count as failures.
+    pub drain_ms: u64,
+    /// Seed of the random samples of the network model.
+    #[serde(default)]
+    pub seed: u64,
+    pub network: NetworkModel,
+    /// Maximum size of a routed message above which the parts are sent in fragments, the network
+    /// default if not set. The fragments are only sent from
+    /// `ProtocolFeature::PartialWitnessFragments` on, see `protocol_version`.
+    #[serde(default)]
+    pub max_routed_message_payload: Option<usize>,
+    /// Protocol version of the simulated epoch, the latest one of the binary if not set.
+    #[serde(default)]
+    pub protocol_version: Option<ProtocolVersion>,
+    /// Config of the partial witness actor of every validator.
+    #[serde(default)]
+    pub partial_witness: PartialWitnessConfig,
+}
+
+impl Scenario {
+    pub fn block_interval(&self) -> Duration {
+        Duration::milliseconds(self.block_interval_ms as i64)
+    }
+
+    pub fn drain(&self) -> Duration {
+        Duration::milliseconds(self.drain_ms as i64)
+    }
+}
+
+/// Model of the links between every pair of validators.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkModel {
+    /// Latency of the link, sampled once per link.
+    pub latency: LatencyModel,
+    /// Upper bound of the uniformly distributed delay added to the latency of every message.
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// Probability of losing every message.
+    #[serde(default)]
+    pub loss_rate: f64,
+    /// Upload bandwidth of every validator, shared by all of its links. Zero is unlimited.
+    #[serde(default)]
+    pub upload_bytes_per_sec: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LatencyModel {
+    /// The same latency for all the links.
+    Fixed { ms: u64 },
+    /// Latency of every link uniformly distributed within the bounds, inclusive.
+    Uniform { min_ms: u64, max_ms: u64 },
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use near_async::time::{Duration, FakeClock, Instant, Utc};
+use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+use near_client::test_utils::{PartialWitnessTestDriver, TestPayload, TestWitnessBuilder};
+use near_client::{
+    witness_parts_geometry, DistributeStateWitnessRequest, ReedSolomonBackend, WitnessEncoderCache,
+};
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::state_witness::{
+    BatchedChunkStateWitnessAckMessage, ChunkStateWitnessAckMessage,
+    FullEncodedStateWitnessMessage, PartialEncodedStateWitnessForwardMessage,
+    PartialEncodedStateWitnessFragmentMessage, PartialEncodedStateWitnessMessage,
+    PartialEncodedStateWitnessRequestMessage, WitnessReceiverStatusMessage,
+};
+use near_network::types::NetworkRequests;
+use near_primitives::network::PeerId;
+use near_primitives::stateless_validation::state_witness::{
+    ChunkStateWitness, EncodedChunkStateWitness,
+};
+use near_primitives::test_utils::create_test_signer;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_store::test_utils::create_test_store;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use time::ext::InstantExt as _;
+
+use crate::network::Network;
+use crate::report::{Geometry, LatencyStats, Report, TrafficStats};
+use crate::scenario::{LatencyModel, Scenario};
+
+/// Height of the first witness, the following ones are produced at the next heights.
+const FIRST_HEIGHT: BlockHeight = 1;
+
+/// All the witnesses are produced in the first epoch.
+const EPOCH_LENGTH: u64 = 1_000_000;
+
+/// Message delivered from one validator to another.
+enum Message {
+    Part(PartialEncodedStateWitnessMessage),
+    Forward(PartialEncodedStateWitnessForwardMessage),
+    Fragment(PartialEncodedStateWitnessFragmentMessage),
+    Request(PartialEncodedStateWitnessRequestMessage),
+    FullWitness(FullEncodedStateWitnessMessage),
+    Ack(ChunkStateWitnessAckMessage),
+    BatchedAck(BatchedChunkStateWitnessAckMessage),
+    ReceiverStatus(WitnessReceiverStatusMessage),
+}
+
+impl Message {
+    /// Kind of the message in the traffic stats of the report.
+    fn kind(&self) -> &'static str {
+        match self {
+            Message::Part(_) => "part",
+            Message::Forward(_) => "forward",
+            Message::Fragment(_) => "fragment",
+            Message::Request(_) => "request",
+            Message::FullWitness(_) => "full_witness",
+            Message::Ack(_) => "ack",
+            Message::BatchedAck(_) => "batched_ack",
+            Message::ReceiverStatus(_) => "receiver_status",
+        }
+    }
+
+    fn deliver(self, driver: &mut PartialWitnessTestDriver) {
+        match self {
+            Message::Part(msg) => driver.send(msg),
+            Message::Forward(msg) => driver.send(msg),
+            Message::Fragment(msg) => driver.send(msg),
+            Message::Request(msg) => driver.send(msg),
+            Message::FullWitness(msg) => driver.send(msg),
+            Message::Ack(msg) => driver.send(msg),
+            Message::BatchedAck(msg) => driver.send(msg),
+            Message::ReceiverStatus(msg) => driver.send(msg),
+        }
+    }
+}
+
+enum Event {
+    /// The chunk producer of the height distributes its witness.
+    Produce(BlockHeight),
+    /// The message arrives at the validator at the index.
+    Deliver { to: usize, message: Message },
+}
+
+/// Distribution of the witnesses of a single shard between its chunk validators. Every validator
+/// runs the real `PartialWitnessActor` driven by a `PartialWitnessTestDriver`, on a fake clock
+/// shared by all of them, and the messages they send to each other go through the `Network`.
+pub struct Simulation {
+    scenario: Scenario,
+    clock: FakeClock,
+    epoch_manager: Arc<MockEpochManager>,
+    validators: Vec<AccountId>,
+    indices: HashMap<AccountId, usize>,
+    peer_ids: Vec<PeerId>,
+    drivers: Vec<PartialWitnessTestDriver>,
+    network: Network,
+    /// Events ordered by their time, the events at the same time in the order of scheduling.
+    events: BTreeMap<(Instant, u64), Event>,
+    num_scheduled_events: u64,
+    ends_at: Instant,
+    /// Index of the chunk producer and the production time of the witnesses produced so far.
+    produced: HashMap<BlockHeight, (usize, Instant)>,
+    reconstructed: HashSet<(usize, BlockHeight)>,
+    latencies: Vec<Duration>,
+    messages: BTreeMap<String, TrafficStats>,
+}
+
+impl Simulation {
+    pub fn new(scenario: Scenario) -> anyhow::Result<Self> {
+        anyhow::ensure!(scenario.num_validators > 0, "num_validators must be positive");
+        anyhow::ensure!(scenario.num_witnesses > 0, "num_witnesses must be positive");
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&scenario.network.loss_rate),
+            "loss_rate must be between 0 and 1"
+        );
+        if let LatencyModel::Uniform { min_ms, max_ms } = scenario.network.latency {
+            anyhow::ensure!(min_ms <= max_ms, "min_ms of the latency must not exceed max_ms");
+        }
+
+        let clock = FakeClock::new(Utc::UNIX_EPOCH);
+        let validators = (0..scenario.num_validators)
+            .map(|index| format!("test{index}").parse())
+            .collect::<Result<Vec<AccountId>, _>>()?;
+        let indices = validators
+            .iter()
+            .enumerate()
+            .map(|(index, account_id)| (account_id.clone(), index))
+            .collect();
+        let peer_ids = validators
+            .iter()
+            .map(|account_id| PeerId::new(create_test_signer(account_id.as_str()).public_key()))
+            .collect();
+        let validator_schedule = ValidatorSchedule::new()
+            .num_shards(1)
+            .block_producers_per_epoch(vec![validators.clone()]);
+        let epoch_manager = MockEpochManager::new_with_validators(
+            create_test_store(),
+            validator_schedule,
+            EPOCH_LENGTH,
+        );
+        if let Some(protocol_version) = scenario.protocol_version {
+            epoch_manager.set_protocol_version(protocol_version);
+        }
+        let drivers = validators
+            .iter()
+            .map(|account_id| {
+                let mut driver = PartialWitnessTestDriver::new(
+                    clock.clone(),
+                    account_id.clone(),
+                    epoch_manager.clone(),
+                    scenario.partial_witness.clone(),
+                );
+                if let Some(max_payload) = scenario.max_routed_message_payload {
+                    driver.actor_mut().set_max_routed_message_payload(max_payload);
+                }
+                driver
+            })
+            .collect();
+        let network = Network::new(
+            &scenario.network,
+            scenario.num_validators,
+            clock.now(),
+            StdRng::seed_from_u64(scenario.seed),
+        );
+
+        let started_at = clock.now();
+        let last_production_ms = scenario.block_interval_ms * (scenario.num_witnesses - 1);
+        let ends_at =
+            started_at + Duration::milliseconds(last_production_ms as i64) + scenario.drain();
+        let mut simulation = Self {
+            scenario,
+            clock,
+            epoch_manager,
+            validators,
+            indices,
+            peer_ids,
+            drivers,
+            network,
+            events: BTreeMap::new(),
+            num_scheduled_events: 0,
+            ends_at,
+            produced: HashMap::new(),
+            reconstructed: HashSet::new(),
+            latencies: vec![],
+            messages: BTreeMap::new(),
+        };
+        for index in 0..simulation.scenario.num_witnesses {
+            let produced_at = started_at + simulation.scenario.block_interval() * index as u32;
+            simulation.schedule(produced_at, Event::Produce(FIRST_HEIGHT + index));
+        }
+        Ok(simulation)
+    }
+
+    /// Runs the simulation until the end of the drain after the last witness.
+    pub fn run(mut self) -> anyhow::Result<Report> {
+        let geometry = self.geometry()?;
+        while self.step()? {}
+
+        let num_witnesses = self.scenario.num_witnesses;
+        let expected_reconstructions = num_witnesses * (self.validators.len() as u64 - 1);
+        let reconstructions = self.reconstructed.len() as u64;
+        Ok(Report {
+            geometry,
+            witnesses: num_witnesses,
+            reconstructions,
+            failures: expected_reconstructions - reconstructions,
+            reconstruction_latency: LatencyStats::new(self.latencies),
+            messages: self.messages,
+        })
+    }
+
+    /// Geometry of the witnesses, as encoded by the chunk producers.
+    fn geometry(&self) -> anyhow::Result<Geometry> {
+        let num_parts = self.validators.len();
+        let config = &self.scenario.partial_witness;
+        let (encoded_witness, _, _) = EncodedChunkStateWitness::encode_with_compression_threshold(
+            &self.witness(FIRST_HEIGHT),
+            config.uncompressed_witness_threshold,
+        )?;
+        let mut encoders =
+            WitnessEncoderCache::new(ReedSolomonBackend::select(config.reed_solomon_backend));
+        let encoder = encoders.entry(num_parts)?;
+        let (_, encoded_length) = encoder.encode(&encoded_witness);
+        Ok(Geometry {
+            total_parts: encoder.total_parts(),
+            data_parts: encoder.data_parts(),
+            min_parts_to_decode: witness_parts_geometry::min_parts_to_decode(num_parts),
+            encoded_witness_bytes: encoded_length,
+            part_bytes: witness_parts_geometry::part_len(encoded_length, num_parts),
+        })
+    }
+
+    fn witness(&self, height: BlockHeight) -> ChunkStateWitness {
+        let mut builder = TestWitnessBuilder::new().height(height);
+        if self.scenario.witness_size_bytes > 0 {
+            builder =
+                builder.payload(self.scenario.witness_size_bytes, TestPayload::Incompressible);
+        }
+        builder.build()
+    }
+
+    fn schedule(&mut self, at: Instant, event: Event) {
+        self.events.insert((at, self.num_scheduled_events), event);
+        self.num_scheduled_events += 1;
+    }
+
+    /// Runs the earliest delayed action of the validators, or handles the earliest event if it
+    /// comes first. The events at the time of a delayed action are handled before it, as the
+    /// actor handles the messages already in its mailbox before its delayed actions. Returns
+    /// false once there is nothing left to do before the end of the simulation.
+    fn step(&mut self) -> anyhow::Result<bool> {
+        let next_event_at = self.events.first_key_value().map(|((at, _), _)| *at);
+        let next_action = self
+            .drivers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, driver)| Some((driver.next_delayed_action_at()?, index)))
+            .min();
+        let run_action = match (next_action, next_event_at) {
+            (Some((action_at, _)), Some(event_at)) => action_at < event_at,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if run_action {
+            let (at, index) = next_action.unwrap();
+            if at > self.ends_at {
+                return Ok(false);
+            }
+            let until = at.signed_duration_since(self.clock.now()).max(Duration::ZERO);
+            self.drivers[index].advance(until);
+            self.collect_outputs(index);
+            return Ok(true);
+        }
+        let Some(at) = next_event_at.filter(|at| *at <= self.ends_at) else {
+            return Ok(false);
+        };
+        let (_, event) = self.events.pop_first().unwrap();
+        if at > self.clock.now() {
+            self.clock.advance_until(at);
+        }
+        match event {
+            Event::Produce(height) => self.produce(height)?,
+            Event::Deliver { to, message } => {
+                message.deliver(&mut self.drivers[to]);
+                self.collect_outputs(to);
+            }
+        }
+        Ok(true)
+    }
+
+    fn produce(&mut self, height: BlockHeight) -> anyhow::Result<()> {
+        let chunk_producer =
+            self.epoch_manager.get_chunk_producer(&EpochId::default(), height, 0)?;
+        let index = self.indices[&chunk_producer];
+        let witness = self.witness(height);
+        self.produced.insert(height, (index, self.clock.now()));
+        self.drivers[index].send(DistributeStateWitnessRequest::new(
+            EpochId::default(),
+            witness.chunk_header.clone(),
+            Arc::new(witness),
+            self.clock.now(),
+        ));
+        self.collect_outputs(index);
+        Ok(())
+    }
+
+    /// Sends the network requests of the validator and records the witnesses it reconstructed.
+    fn collect_outputs(&mut self, index: usize) {
+        for request in self.drivers[index].take_network_requests() {
+            self.route(index, request);
+        }
+        for msg in self.drivers[index].take_client_witnesses() {
+            let height = msg.witness.chunk_production_key().height_created;
+            let Some((chunk_producer, produced_at)) = self.produced.get(&height) else {
+                continue;
+            };
+            if *chunk_producer != index && self.reconstructed.insert((index, height)) {
+                self.latencies.push(self.clock.now().signed_duration_since(*produced_at));
+            }
+        }
+    }
+
+    fn route(&mut self, from: usize, request: NetworkRequests) {
+        let peer_id = self.peer_ids[from].clone();
+        match request {
+            NetworkRequests::PartialEncodedStateWitness(owned_parts, _) => {
+                for (owner, partial_witness) in owned_parts {
+                    let bytes = borsh::object_length(&partial_witness).unwrap();
+                    let message = Message::Part(PartialEncodedStateWitnessMessage(partial_witness));
+                    self.send(from, &owner, bytes, message);
+                }
+            }
+            NetworkRequests::PartialEncodedStateWitnessForward(targets, partial_witness, _) => {
+                let bytes = borsh::object_length(&partial_witness).unwrap();
+                for target in &targets {
+                    let message = Message::Forward(PartialEncodedStateWitnessForwardMessage(
+                        partial_witness.clone(),
+                        peer_id.clone(),
+                    ));
+                    self.send(from, target, bytes, message);
+                }
+            }
+            NetworkRequests::PartialEncodedStateWitnessFragments(targets, fragments, _) => {
+                for target in &targets {
+                    for fragment in &fragments {
+                        let bytes = borsh::object_length(fragment).unwrap();
+                        let message = Message::Fragment(PartialEncodedStateWitnessFragmentMessage(
+                            fragment.clone(),
+                            peer_id.clone(),
+                        ));
+                        self.send(from, target, bytes, message);
+                    }
+                }
+            }
+            NetworkRequests::PartialEncodedStateWitnessRequest(target, request) => {
+                let bytes = borsh::object_length(&request).unwrap();
+                let sender = Some(self.validators[from].clone());
+                let message =
+                    Message::Request(PartialEncodedStateWitnessRequestMessage(request, sender));
+                self.send(from, &target, bytes, message);
+            }
+            NetworkRequests::FullEncodedStateWitness(targets, full_witness, _) => {
+                let bytes = borsh::object_length(&full_witness).unwrap();
+                for target in &targets {
+                    let message =
+                        Message::FullWitness(FullEncodedStateWitnessMessage(full_witness.clone()));
+                    self.send(from, target, bytes, message);
+                }
+            }
+            NetworkRequests::ChunkStateWitnessAck(target, ack) => {
+                let bytes = borsh::object_length(&ack).unwrap();
+                let sender = Some(self.validators[from].clone());
+                let message = Message::Ack(ChunkStateWitnessAckMessage(ack, sender));
+                self.send(from, &target, bytes, message);
+            }
+            NetworkRequests::BatchedChunkStateWitnessAck(target, acks) => {
+                let bytes = borsh::object_length(&acks).unwrap();
+                let sender = Some(self.validators[from].clone());
+                let message = Message::BatchedAck(BatchedChunkStateWitnessAckMessage(acks, sender));
+                self.send(from, &target, bytes, message);
+            }
+            NetworkRequests::WitnessReceiverStatus(targets, status) => {
+                let bytes = borsh::object_length(&status).unwrap();
+                for target in &targets {
+                    let message =
+                        Message::ReceiverStatus(WitnessReceiverStatusMessage(status.clone()));
+                    self.send(from, target, bytes, message);
+                }
+            }
+            request => {
+                tracing::debug!(target: "witness_simulator", ?request, "Ignoring network request");
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: &AccountId, bytes: usize, message: Message) {
+        let Some(&to) = self.indices.get(to) else {
+            tracing::warn!(target: "witness_simulator", %to, "Message to an unknown validator");
+            return;
+        };
+        let arrives_at = self.network.send(from, to, bytes, self.clock.now());
+        let stats = self.messages.entry(message.kind().to_string()).or_default();
+        stats.sent += 1;
+        stats.bytes += bytes as u64;
+        match arrives_at {
+            Some(arrives_at) => self.schedule(arrives_at, Event::Deliver { to, message }),
+            None => stats.dropped += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(scenario: &str) -> Report {
+        let scenario: Scenario = serde_json::from_str(scenario).unwrap();
+        Simulation::new(scenario).unwrap().run().unwrap()
+    }
+
+    fn latency(ms: f64) -> Option<LatencyStats> {
+        Some(LatencyStats { p50_ms: ms, p90_ms: ms, p99_ms: ms, max_ms: ms })
+    }
+
+    /// Each chunk validator decodes the witness from its own part and the part of the chunk
+    /// producer, both of which arrive one hop after the production.
+    #[test]
+    fn four_validators() {
+        let report = run(include_str!("../scenarios/four_validators.json"));
+        assert_eq!(report.geometry.total_parts, 4);
+        assert_eq!(report.geometry.data_parts, 2);
+        assert_eq!((report.witnesses, report.reconstructions, report.failures), (1, 3, 0));
+        assert_eq!(report.reconstruction_latency, latency(10.0));
+        // The owned parts, then the forwards of the part of the chunk producer and of the two
+        // other chunk validators by every chunk validator.
+        assert_eq!(report.messages["part"].sent, 3);
+        assert_eq!(report.messages["forward"].sent, 3 + 3 * 2);
+        assert!(report.messages.values().all(|stats| stats.dropped == 0));
+    }
+
+    /// Two more parts are needed than arrive in the first hop, so the witnesses are decoded
+    /// once the forwards of the other chunk validators arrive.
+    #[test]
+    fn seven_validators() {
+        let report = run(include_str!("../scenarios/seven_validators.json"));
+        assert_eq!(report.geometry.total_parts, 7);
+        assert_eq!(report.geometry.data_parts, 4);
+        assert_eq!((report.witnesses, report.reconstructions, report.failures), (1, 6, 0));
+        assert_eq!(report.reconstruction_latency, latency(20.0));
+        assert_eq!(report.messages["part"].sent, 6);
+        assert_eq!(report.messages["forward"].sent, 6 + 6 * 5);
+        assert!(report.messages.values().all(|stats| stats.dropped == 0));
+    }
+
+    /// Nothing sent by the chunk producers arrives, so the chunk validators never learn about
+    /// the witnesses and never send anything.
+    #[test]
+    fn total_loss() {
+        let report = run(include_str!("../scenarios/total_loss.json"));
+        assert_eq!((report.witnesses, report.reconstructions, report.failures), (2, 0, 6));
+        assert_eq!(report.reconstruction_latency, None);
+        assert_eq!(report.messages["part"].sent, 2 * 3);
+        assert_eq!(report.messages["forward"].sent, 2 * 3);
+        assert!(report.messages.values().all(|stats| stats.dropped == stats.sent));
+    }
+}